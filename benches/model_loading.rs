@@ -28,6 +28,8 @@ fn benchmark_model_registry(c: &mut Criterion) {
                 template: Some("chatml".to_string()),
                 ctx_len: Some(black_box(4096)),
                 n_threads: Some(black_box(4)),
+                n_gpu_layers: None,
+                mmproj_path: None,
             };
             registry.register(black_box(entry));
         })
@@ -42,6 +44,8 @@ fn benchmark_model_registry(c: &mut Criterion) {
             template: Some("chatml".to_string()),
             ctx_len: Some(4096),
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
         registry.register(entry);
     }
@@ -52,9 +52,82 @@ fn validate_version() {
     println!("cargo:warning=Building shimmy version {}", version);
 }
 
+/// OEM branding is read with `option_env!` (see src/branding.rs); rebuild when
+/// it changes and refuse a Keygen account without its matching public key
+fn validate_oem_branding() {
+    const OEM_VARS: &[&str] = &[
+        "SHIMMY_OEM_PRODUCT_NAME",
+        "SHIMMY_OEM_APP_NAME",
+        "SHIMMY_OEM_ABOUT",
+        "SHIMMY_OEM_DATA_DIR",
+        "SHIMMY_OEM_KEYGEN_ACCOUNT_ID",
+        "SHIMMY_OEM_KEYGEN_PUBLIC_KEY",
+        "SHIMMY_OEM_RELEASES_URL",
+        "SHIMMY_OEM_TELEMETRY_URL",
+    ];
+    for var in OEM_VARS {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+
+    let account = env::var("SHIMMY_OEM_KEYGEN_ACCOUNT_ID").is_ok();
+    let public_key = env::var("SHIMMY_OEM_KEYGEN_PUBLIC_KEY").is_ok();
+    if account != public_key {
+        panic!(
+            "ERROR: SHIMMY_OEM_KEYGEN_ACCOUNT_ID and SHIMMY_OEM_KEYGEN_PUBLIC_KEY must be set together\n\
+             License responses are verified with the account's own public key."
+        );
+    }
+    if let Ok(key) = env::var("SHIMMY_OEM_KEYGEN_PUBLIC_KEY") {
+        if key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+            panic!("ERROR: SHIMMY_OEM_KEYGEN_PUBLIC_KEY must be a hex-encoded 32-byte Ed25519 key");
+        }
+    }
+}
+
+/// Versions of the libraries behind optional backends, reported by
+/// `shimmy --version --verbose` (see src/capabilities.rs)
+fn export_dependency_versions() {
+    const PACKAGES: &[(&str, &str)] = &[
+        ("shimmy-llama-cpp-2", "SHIMMY_DEP_LLAMA_CPP"),
+        ("image", "SHIMMY_DEP_IMAGE"),
+        ("chromiumoxide", "SHIMMY_DEP_CHROMIUMOXIDE"),
+    ];
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let Ok(lock) = std::fs::read_to_string("Cargo.lock") else {
+        return;
+    };
+    for (package, var) in PACKAGES {
+        let name = format!("name = \"{}\"", package);
+        let mut lines = lock.lines().map(str::trim);
+        if lines.by_ref().any(|line| line == name) {
+            let version = lines
+                .next()
+                .and_then(|line| line.strip_prefix("version = \""))
+                .and_then(|v| v.strip_suffix('"'));
+            if let Some(version) = version {
+                println!("cargo:rustc-env={}={}", var, version);
+            }
+        }
+    }
+}
+
+/// Generate the gRPC service from proto/shimmy.proto, with a bundled protoc
+/// so builds don't need one installed
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    println!("cargo:rerun-if-changed=proto/shimmy.proto");
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("bundled protoc is available");
+    env::set_var("PROTOC", protoc);
+    tonic_build::compile_protos("proto/shimmy.proto").expect("proto/shimmy.proto compiles");
+}
+
 fn main() {
     // Version validation - prevents Issue #63 version mismatch problems
     validate_version();
+    validate_oem_branding();
+    export_dependency_versions();
+    #[cfg(feature = "grpc")]
+    compile_protos();
 
     println!("cargo:rerun-if-changed=libs/");
 
@@ -0,0 +1,441 @@
+//! Runtime reconfiguration through `/api/admin/config`.
+//!
+//! Some settings are worth changing on a running server without a restart
+//! that drops every loaded model. `PUT /api/admin/config` changes them and
+//! `GET /api/admin/config` shows the values in force:
+//!
+//! - `log_level`: a `RUST_LOG`-style filter such as `info` or
+//!   `shimmy=debug,info`, applied to the console and the log file
+//! - `rate_limits`: `global` and `per_client` allowances, as set by
+//!   `SHIMMY_RATE_LIMIT_*`; a scope that is sent replaces its limits
+//!   entirely, and buckets start over full
+//! - `cache_ttls`: `response_secs` for the response cache and
+//!   `idempotency_secs` for `Idempotency-Key` replays
+//! - `queues`: requests allowed to wait for a `chat`, `code` or `vision`
+//!   slot, as set by `SHIMMY_MAX_QUEUE_*`; `null` removes the limit
+//!
+//! A `PUT` names only what it changes, and is checked as a whole before
+//! anything is applied. Both routes need `x-admin-token` to match
+//! `SHIMMY_ADMIN_TOKEN`; with no token set they answer `403 ADMIN_DISABLED`.
+//! Every change is appended to `admin/config_journal.jsonl` in the data
+//! directory with when it was made, by which address, and the settings
+//! before and after. Changes last until the server stops; the environment
+//! and `shimmy.toml` decide what it starts with.
+
+use crate::error::ShimmyError;
+use crate::rate_limit::Limits;
+use crate::AppState;
+use axum::extract::{ConnectInfo, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+pub const ADMIN_TOKEN_ENV: &str = "SHIMMY_ADMIN_TOKEN";
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// Journal entries returned by `GET /api/admin/config`
+const RECENT_CHANGES: usize = 20;
+
+/// Replaces the filter of one log output with a new `RUST_LOG` directive
+pub type LogFilterSetter = Box<dyn Fn(&str) -> anyhow::Result<()> + Send + Sync>;
+
+struct LogLevel {
+    directive: Option<String>,
+    setters: Vec<LogFilterSetter>,
+}
+
+fn log_level() -> &'static Mutex<LogLevel> {
+    static LEVEL: OnceLock<Mutex<LogLevel>> = OnceLock::new();
+    LEVEL.get_or_init(|| {
+        Mutex::new(LogLevel {
+            directive: std::env::var("RUST_LOG").ok().filter(|v| !v.is_empty()),
+            setters: Vec::new(),
+        })
+    })
+}
+
+/// Let `log_level` changes reach the tracing outputs set up at startup
+pub fn register_log_filters(setters: Vec<LogFilterSetter>) {
+    log_level()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .setters = setters;
+}
+
+/// Settings in force, as `GET /api/admin/config` reports them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuntimeConfig {
+    /// `None` while the `RUST_LOG` default applies
+    pub log_level: Option<String>,
+    pub rate_limits: RateLimitConfig,
+    pub cache_ttls: CacheTtls,
+    pub queues: Queues,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub global: Limits,
+    pub per_client: Limits,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CacheTtls {
+    pub response_secs: u64,
+    #[cfg(feature = "vision")]
+    pub idempotency_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Queues {
+    pub chat: Option<usize>,
+    pub code: Option<usize>,
+    pub vision: Option<usize>,
+}
+
+/// Body of `PUT /api/admin/config`; anything left out stays as it is
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limits: Option<RateLimitUpdate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttls: Option<CacheTtlUpdate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queues: Option<QueueUpdate>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub global: Option<Limits>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_client: Option<Limits>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheTtlUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_secs: Option<u64>,
+}
+
+/// `Some(None)` removes a route's queue limit
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueueUpdate {
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub chat: Option<Option<usize>>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub code: Option<Option<usize>>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub vision: Option<Option<usize>>,
+}
+
+/// Tell an explicit `null` apart from a field that was left out
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// One line of the journal
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    at: chrono::DateTime<chrono::Utc>,
+    client: Option<String>,
+    changes: serde_json::Value,
+    before: serde_json::Value,
+    after: serde_json::Value,
+}
+
+/// The settings `state` is running with
+pub fn current(state: &AppState) -> RuntimeConfig {
+    let (global, per_client) = state.rate_limits.limits();
+    RuntimeConfig {
+        log_level: log_level()
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .directive
+            .clone(),
+        rate_limits: RateLimitConfig { global, per_client },
+        cache_ttls: CacheTtls {
+            response_secs: state.response_cache.ttl().as_secs(),
+            #[cfg(feature = "vision")]
+            idempotency_secs: crate::cache::idempotency::IdempotencyCache::global()
+                .ttl()
+                .as_secs(),
+        },
+        queues: Queues {
+            chat: state.route_limits.chat.max_queue(),
+            code: state.route_limits.code.max_queue(),
+            vision: state.route_limits.vision.max_queue(),
+        },
+    }
+}
+
+/// Apply `update` to the running server, refusing it whole if any part is
+/// invalid
+pub fn apply(state: &AppState, update: &ConfigUpdate) -> Result<RuntimeConfig, ShimmyError> {
+    let invalid = |reason: String| ShimmyError::InvalidRequest { reason };
+    if let Some(directive) = &update.log_level {
+        tracing_subscriber::EnvFilter::try_new(directive)
+            .map_err(|e| invalid(format!("log_level {:?} is not valid: {}", directive, e)))?;
+    }
+    #[cfg(not(feature = "vision"))]
+    if update
+        .cache_ttls
+        .as_ref()
+        .is_some_and(|ttls| ttls.idempotency_secs.is_some())
+    {
+        return Err(invalid(
+            "cache_ttls.idempotency_secs needs a build with the `vision` feature".to_string(),
+        ));
+    }
+
+    if let Some(directive) = &update.log_level {
+        let mut level = log_level().lock().unwrap_or_else(|e| e.into_inner());
+        for set in &level.setters {
+            set(directive).map_err(|e| ShimmyError::ConfigError {
+                field: "log_level".to_string(),
+                value: format!("{} ({})", directive, e),
+            })?;
+        }
+        level.directive = Some(directive.clone());
+    }
+    if let Some(limits) = &update.rate_limits {
+        let (global, per_client) = state.rate_limits.limits();
+        state.rate_limits.set(
+            limits.global.map(without_zeros).unwrap_or(global),
+            limits.per_client.map(without_zeros).unwrap_or(per_client),
+        );
+    }
+    if let Some(ttls) = &update.cache_ttls {
+        if let Some(secs) = ttls.response_secs {
+            state.response_cache.set_ttl(Duration::from_secs(secs));
+        }
+        #[cfg(feature = "vision")]
+        if let Some(secs) = ttls.idempotency_secs {
+            crate::cache::idempotency::IdempotencyCache::global()
+                .set_ttl(Duration::from_secs(secs));
+        }
+    }
+    if let Some(queues) = &update.queues {
+        let routes = &state.route_limits;
+        for (limit, max_queue) in [
+            (&routes.chat, queues.chat),
+            (&routes.code, queues.code),
+            (&routes.vision, queues.vision),
+        ] {
+            if let Some(max_queue) = max_queue {
+                limit.set_max_queue(max_queue);
+            }
+        }
+    }
+    Ok(current(state))
+}
+
+/// `0` turns a limit off, as it does in the environment
+fn without_zeros(limits: Limits) -> Limits {
+    Limits {
+        requests_per_minute: limits.requests_per_minute.filter(|&n| n > 0),
+        tokens_per_minute: limits.tokens_per_minute.filter(|&n| n > 0),
+    }
+}
+
+/// Check the request's `x-admin-token` against `SHIMMY_ADMIN_TOKEN`
+pub fn authorize(headers: &HeaderMap) -> Result<(), ShimmyError> {
+    let expected = std::env::var(ADMIN_TOKEN_ENV)
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+        .ok_or(ShimmyError::AdminDisabled)?;
+    let sent = headers
+        .get(ADMIN_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(ShimmyError::AdminUnauthorized)?;
+    // Compare digests so the time taken doesn't reveal a matching prefix
+    if Sha256::digest(sent.trim().as_bytes()) != Sha256::digest(expected.trim().as_bytes()) {
+        return Err(ShimmyError::AdminUnauthorized);
+    }
+    Ok(())
+}
+
+fn journal_path() -> PathBuf {
+    crate::util::paths::data_dir()
+        .join("admin")
+        .join("config_journal.jsonl")
+}
+
+fn append_journal(entry: &JournalEntry) -> anyhow::Result<()> {
+    let path = journal_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?
+        .write_all(&line)?;
+    Ok(())
+}
+
+/// The last `n` journal entries, oldest first
+fn recent_changes(n: usize) -> Vec<serde_json::Value> {
+    let Ok(journal) = std::fs::read_to_string(journal_path()) else {
+        return Vec::new();
+    };
+    let lines: Vec<&str> = journal.lines().filter(|l| !l.trim().is_empty()).collect();
+    lines[lines.len().saturating_sub(n)..]
+        .iter()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// `GET /api/admin/config`
+pub async fn get_config(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ShimmyError> {
+    authorize(&headers)?;
+    Ok(Json(serde_json::json!({
+        "config": current(&state),
+        "recent_changes": recent_changes(RECENT_CHANGES),
+    })))
+}
+
+/// `PUT /api/admin/config`
+pub async fn put_config(
+    State(state): State<Arc<AppState>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(update): Json<ConfigUpdate>,
+) -> Result<Json<serde_json::Value>, ShimmyError> {
+    authorize(&headers)?;
+    let before = current(&state);
+    let after = apply(&state, &update)?;
+    let client = peer.map(|ConnectInfo(addr)| addr.ip().to_string());
+    tracing::info!(
+        "Runtime configuration changed by {}: {}",
+        client.as_deref().unwrap_or("an unknown address"),
+        serde_json::to_string(&update).unwrap_or_default()
+    );
+    let entry = JournalEntry {
+        at: chrono::Utc::now(),
+        client,
+        changes: serde_json::to_value(&update)?,
+        before: serde_json::to_value(&before)?,
+        after: serde_json::to_value(&after)?,
+    };
+    if let Err(e) = append_journal(&entry) {
+        tracing::warn!(
+            "Could not journal the configuration change in {}: {}",
+            journal_path().display(),
+            e
+        );
+    }
+    Ok(Json(serde_json::json!({ "config": after })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_registry::Registry;
+
+    fn state() -> AppState {
+        let engine = Box::new(crate::engine::adapter::InferenceEngineAdapter::new());
+        AppState::new(engine, Registry::default())
+    }
+
+    #[test]
+    fn test_update_applies_only_what_it_names() {
+        let state = state();
+        let update: ConfigUpdate = serde_json::from_value(serde_json::json!({
+            "rate_limits": { "per_client": { "requests_per_minute": 30, "tokens_per_minute": 0 } },
+            "cache_ttls": { "response_secs": 60 },
+            "queues": { "vision": 2, "chat": null }
+        }))
+        .unwrap();
+        state.route_limits.chat.set_max_queue(Some(5));
+        state.route_limits.code.set_max_queue(Some(1));
+
+        let config = apply(&state, &update).unwrap();
+        assert_eq!(config.rate_limits.per_client.requests_per_minute, Some(30));
+        assert_eq!(config.rate_limits.per_client.tokens_per_minute, None);
+        assert_eq!(config.cache_ttls.response_secs, 60);
+        assert_eq!(state.response_cache.ttl(), Duration::from_secs(60));
+        assert_eq!(
+            (config.queues.chat, config.queues.code, config.queues.vision),
+            (None, Some(1), Some(2))
+        );
+        assert_eq!(config, current(&state));
+    }
+
+    #[test]
+    fn test_invalid_update_changes_nothing() {
+        let state = state();
+        let before = current(&state);
+        let update = ConfigUpdate {
+            log_level: Some("shimmy=loud".to_string()),
+            cache_ttls: Some(CacheTtlUpdate {
+                response_secs: Some(1),
+                idempotency_secs: None,
+            }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply(&state, &update),
+            Err(ShimmyError::InvalidRequest { .. })
+        ));
+        assert_eq!(current(&state), before);
+
+        assert!(serde_json::from_str::<ConfigUpdate>(r#"{"log_levl": "debug"}"#).is_err());
+    }
+
+    #[test]
+    fn test_admin_token() {
+        let mut headers = HeaderMap::new();
+        std::env::remove_var(ADMIN_TOKEN_ENV);
+        assert!(matches!(
+            authorize(&headers),
+            Err(ShimmyError::AdminDisabled)
+        ));
+
+        std::env::set_var(ADMIN_TOKEN_ENV, "s3cret");
+        assert!(matches!(
+            authorize(&headers),
+            Err(ShimmyError::AdminUnauthorized)
+        ));
+        headers.insert(ADMIN_TOKEN_HEADER, "wrong".parse().unwrap());
+        assert!(authorize(&headers).is_err());
+        headers.insert(ADMIN_TOKEN_HEADER, "s3cret".parse().unwrap());
+        assert!(authorize(&headers).is_ok());
+        std::env::remove_var(ADMIN_TOKEN_ENV);
+    }
+}
@@ -141,6 +141,9 @@ pub async fn messages(
     let mut options = crate::engine::GenOptions {
         max_tokens: req.max_tokens,
         stream: req.stream.unwrap_or(false),
+        deadline: crate::deadline::current(),
+        api_key: crate::api_keys::current(),
+        rate_limit: crate::rate_limit::current(),
         ..Default::default()
     };
 
@@ -153,6 +156,9 @@ pub async fn messages(
     if let Some(k) = req.top_k {
         options.top_k = k;
     }
+    if let Err(e) = options.validate() {
+        return crate::error::ShimmyError::from(e).into_response();
+    }
 
     // Prepare the prompt using the same logic as OpenAI compatibility
     let (system_prompt, conversation_pairs) =
@@ -174,13 +180,20 @@ pub async fn messages(
     }
 
     // Load the model and generate response
-    let Ok(loaded_model) = state.engine.load(&spec).await else {
-        tracing::error!("Failed to load model '{}'", req.model);
-        return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    let loaded_model = match state.load_model(&spec).await {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            tracing::error!("Failed to load model '{}': {}", req.model, e);
+            return crate::api_errors::model_load_failed_response(&req.model, e);
+        }
     };
 
-    match loaded_model.generate(&prompt, options, None).await {
-        Ok(response) => {
+    match loaded_model
+        .generate_with_finish(&prompt, options, None)
+        .await
+    {
+        Ok(generation) => {
+            let response = generation.text;
             let anthropic_response = AnthropicMessageResponse {
                 id: format!("msg_{}", Uuid::new_v4()),
                 response_type: "message".to_string(),
@@ -190,7 +203,7 @@ pub async fn messages(
                     text: response.clone(),
                 }],
                 model: req.model,
-                stop_reason: "end_turn".to_string(),
+                stop_reason: anthropic_stop_reason(generation.finish_reason).to_string(),
                 stop_sequence: None,
                 usage: AnthropicUsage {
                     input_tokens: estimate_tokens(&prompt),
@@ -207,6 +220,17 @@ pub async fn messages(
     }
 }
 
+/// Anthropic `stop_reason` for an engine finish reason
+fn anthropic_stop_reason(reason: crate::engine::FinishReason) -> &'static str {
+    use crate::engine::FinishReason;
+    match reason {
+        FinishReason::Length | FinishReason::Time => "max_tokens",
+        FinishReason::ToolCalls => "tool_use",
+        FinishReason::ContentFilter => "refusal",
+        FinishReason::Stop | FinishReason::Cancelled | FinishReason::Repetition => "end_turn",
+    }
+}
+
 /// Extract system message and conversation pairs from messages
 /// This mimics the logic from openai_compat.rs but adapted for Anthropic format
 fn extract_system_and_pairs(
@@ -350,4 +374,13 @@ mod tests {
         assert_eq!(estimate_tokens("test"), 1); // 4 chars = 1 token
         assert_eq!(estimate_tokens("hello world"), 3); // 11 chars = 2.75 -> 3 tokens
     }
+
+    #[test]
+    fn test_anthropic_stop_reason_mapping() {
+        use crate::engine::FinishReason;
+        assert_eq!(anthropic_stop_reason(FinishReason::Stop), "end_turn");
+        assert_eq!(anthropic_stop_reason(FinishReason::Length), "max_tokens");
+        assert_eq!(anthropic_stop_reason(FinishReason::ToolCalls), "tool_use");
+        assert_eq!(anthropic_stop_reason(FinishReason::Cancelled), "end_turn");
+    }
 }
@@ -1,7 +1,10 @@
 use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
 use axum::{
     extract::State,
-    response::{sse::Event, IntoResponse, Sse},
+    response::{
+        sse::{Event, KeepAlive},
+        IntoResponse, Response, Sse,
+    },
     Json,
 };
 use futures_util::StreamExt;
@@ -9,8 +12,51 @@ use serde::{Deserialize, Serialize};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use crate::invariant_ppt::shimmy_invariants;
-use crate::{engine::GenOptions, templates::TemplateFamily, AppState};
+use crate::{
+    engine::{GenOptions, InvalidParameter, LoadedModel, TokenDetail},
+    error::ShimmyError,
+    templates::TemplateFamily,
+    AppState,
+};
 use std::sync::Arc;
+use std::time::Duration;
+
+/// Seconds between `: keep-alive` comments on idle SSE streams; `0` disables them
+pub const SSE_KEEPALIVE_ENV: &str = "SHIMMY_SSE_KEEPALIVE_SECS";
+const DEFAULT_SSE_KEEPALIVE_SECS: u64 = 15;
+
+/// Heartbeat interval for SSE responses, from [`SSE_KEEPALIVE_ENV`]
+fn sse_keep_alive_interval(value: Option<&str>) -> Option<Duration> {
+    let secs = value
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_SSE_KEEPALIVE_SECS);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// SSE response that sends `: keep-alive` comments while the stream is idle
+///
+/// Long prefills on CPU can go minutes without a token; the comments keep
+/// proxies (nginx, Cloudflare tunnels) from closing the connection.
+pub fn sse_response<S>(stream: S) -> Response
+where
+    S: futures_util::Stream<Item = Result<Event, std::convert::Infallible>> + Send + 'static,
+{
+    let interval = sse_keep_alive_interval(std::env::var(SSE_KEEPALIVE_ENV).ok().as_deref());
+    sse_response_with(stream, interval)
+}
+
+fn sse_response_with<S>(stream: S, interval: Option<Duration>) -> Response
+where
+    S: futures_util::Stream<Item = Result<Event, std::convert::Infallible>> + Send + 'static,
+{
+    let sse = Sse::new(stream);
+    match interval {
+        Some(interval) => sse
+            .keep_alive(KeepAlive::new().interval(interval).text("keep-alive"))
+            .into_response(),
+        None => sse.into_response(),
+    }
+}
 
 #[derive(Debug, Deserialize)]
 pub struct GenerateRequest {
@@ -28,6 +74,25 @@ pub struct GenerateRequest {
     pub max_tokens: Option<usize>,
     #[serde(default)]
     pub stream: Option<bool>,
+    /// Wall-clock budget for generation; partial output ends with `finish_reason: "time"`
+    #[serde(default)]
+    pub max_time_ms: Option<u64>,
+    /// Report each token's vocabulary id
+    #[serde(default)]
+    pub token_ids: Option<bool>,
+    /// Report each token's log-probability
+    #[serde(default)]
+    pub logprobs: Option<bool>,
+    /// GBNF grammar the output must match
+    #[serde(default)]
+    pub grammar: Option<String>,
+    /// How long to keep the model loaded afterwards: seconds or a duration
+    /// such as "10m"; 0 unloads it, -1 keeps it
+    #[serde(default)]
+    pub keep_alive: Option<crate::model_cache::KeepAlive>,
+    /// Registered LoRA adapter to apply, by name (default: the model's own)
+    #[serde(default)]
+    pub adapter: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -39,6 +104,67 @@ pub struct ChatMessage {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GenerateResponse {
     pub response: String,
+    /// Per-token details, when `token_ids` or `logprobs` was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<TokenDetail>>,
+}
+
+/// Per-token fields a client asked for
+#[derive(Debug, Clone, Copy, Default)]
+struct TokenFields {
+    ids: bool,
+    logprobs: bool,
+}
+
+impl TokenFields {
+    fn any(self) -> bool {
+        self.ids || self.logprobs
+    }
+
+    /// `token` with only the requested fields
+    fn select(self, mut token: TokenDetail) -> TokenDetail {
+        if !self.ids {
+            token.id = None;
+        }
+        if !self.logprobs {
+            token.logprob = None;
+        }
+        token
+    }
+}
+
+/// The file behind a request's `adapter`, refusing names `model` has no
+/// adapter registered under
+pub(crate) fn adapter_path(
+    registry: &crate::model_registry::Registry,
+    model: &str,
+    adapter: Option<&str>,
+) -> Result<Option<std::path::PathBuf>, ShimmyError> {
+    let Some(adapter) = adapter else {
+        return Ok(None);
+    };
+    registry
+        .adapter_path(model, adapter)
+        .map(Some)
+        .ok_or_else(|| ShimmyError::AdapterNotFound {
+            model: model.to_string(),
+            adapter: adapter.to_string(),
+        })
+}
+
+/// Refuse an adapter the model's backend can't apply, rather than answer
+/// without it
+pub(crate) fn check_adapter_support(
+    loaded: &dyn LoadedModel,
+    opts: &GenOptions,
+    model: &str,
+) -> Result<(), ShimmyError> {
+    if opts.adapter.is_some() && !loaded.supports_adapters() {
+        return Err(ShimmyError::UnsupportedOperation {
+            operation: format!("LoRA adapters on model '{}' (llama.cpp only)", model),
+        });
+    }
+    Ok(())
 }
 
 pub async fn generate(
@@ -49,8 +175,44 @@ pub async fn generate(
         tracing::error!("Model '{}' not found in registry", req.model);
         return axum::http::StatusCode::NOT_FOUND.into_response();
     };
-    let engine = &state.engine;
-    let loaded = match engine.load(&spec).await {
+    if req.prompt.is_some() && req.messages.is_some() {
+        return ShimmyError::from(InvalidParameter::new(
+            "messages",
+            "`prompt` and `messages` are mutually exclusive; send one or the other",
+        ))
+        .into_response();
+    }
+
+    let mut opts = GenOptions::default();
+    if let Some(t) = req.temperature {
+        opts.temperature = t;
+    }
+    if let Some(p) = req.top_p {
+        opts.top_p = p;
+    }
+    if let Some(k) = req.top_k {
+        opts.top_k = k;
+    }
+    if let Some(m) = req.max_tokens {
+        opts.max_tokens = m;
+    }
+    if let Some(s) = req.stream {
+        opts.stream = s;
+    }
+    opts.max_time_ms = req.max_time_ms;
+    opts.grammar = req.grammar.clone();
+    opts.deadline = crate::deadline::current();
+    opts.api_key = crate::api_keys::current();
+    opts.rate_limit = crate::rate_limit::current();
+    opts.adapter = match adapter_path(&state.registry, &req.model, req.adapter.as_deref()) {
+        Ok(adapter) => adapter,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = opts.validate() {
+        return ShimmyError::from(e).into_response();
+    }
+
+    let loaded = match state.load_model_keep_alive(&spec, req.keep_alive).await {
         Ok(loaded) => loaded,
         Err(e) => {
             tracing::error!(
@@ -58,9 +220,12 @@ pub async fn generate(
                 req.model,
                 e
             );
-            return axum::http::StatusCode::BAD_GATEWAY.into_response();
+            return crate::api_errors::model_load_failed_response(&req.model, e);
         }
     };
+    if let Err(e) = check_adapter_support(&*loaded, &opts, &req.model) {
+        return e.into_response();
+    }
 
     // Construct prompt
     let prompt = if let Some(ms) = &req.messages {
@@ -78,58 +243,183 @@ pub async fn generate(
         req.prompt.unwrap_or_default()
     };
 
-    let mut opts = GenOptions::default();
-    if let Some(t) = req.temperature {
-        opts.temperature = t;
-    }
-    if let Some(p) = req.top_p {
-        opts.top_p = p;
-    }
-    if let Some(k) = req.top_k {
-        opts.top_k = k;
+    let fields = TokenFields {
+        ids: req.token_ids.unwrap_or(false),
+        logprobs: req.logprobs.unwrap_or(false),
+    };
+    respond(loaded, prompt, opts, &req.model, fields).await
+}
+
+/// Body for `POST /api/generate/raw`
+///
+/// The prompt reaches the model byte-for-byte: no chat template, no system
+/// prompt and no stop tokens beyond the ones given here.
+#[derive(Debug, Deserialize)]
+pub struct RawGenerateRequest {
+    pub model: String,
+    pub prompt: String,
+    /// Prepend the model's BOS token (default: true)
+    #[serde(default)]
+    pub add_bos: Option<bool>,
+    #[serde(default)]
+    pub stop: Vec<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<i32>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    #[serde(default)]
+    pub seed: Option<u32>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub max_time_ms: Option<u64>,
+    /// GBNF grammar the output must match
+    #[serde(default)]
+    pub grammar: Option<String>,
+    /// How long to keep the model loaded afterwards: seconds or a duration
+    /// such as "10m"; 0 unloads it, -1 keeps it
+    #[serde(default)]
+    pub keep_alive: Option<crate::model_cache::KeepAlive>,
+    /// Registered LoRA adapter to apply, by name (default: the model's own)
+    #[serde(default)]
+    pub adapter: Option<String>,
+}
+
+impl RawGenerateRequest {
+    fn gen_options(&self) -> GenOptions {
+        let defaults = GenOptions::default();
+        GenOptions {
+            max_tokens: self.max_tokens.unwrap_or(defaults.max_tokens),
+            temperature: self.temperature.unwrap_or(defaults.temperature),
+            top_p: self.top_p.unwrap_or(defaults.top_p),
+            top_k: self.top_k.unwrap_or(defaults.top_k),
+            seed: self.seed,
+            stream: self.stream.unwrap_or(false),
+            stop_tokens: self.stop.clone(),
+            add_bos: self.add_bos,
+            max_time_ms: self.max_time_ms,
+            grammar: self.grammar.clone(),
+            ..defaults
+        }
     }
-    if let Some(m) = req.max_tokens {
-        opts.max_tokens = m;
+}
+
+/// Completion without template application, for continuation-style prompts
+/// or clients that do their own templating
+pub async fn generate_raw(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<RawGenerateRequest>,
+) -> impl IntoResponse {
+    let Some(spec) = state.registry.to_spec(&req.model) else {
+        tracing::error!("Model '{}' not found in registry", req.model);
+        return axum::http::StatusCode::NOT_FOUND.into_response();
+    };
+    let mut opts = req.gen_options();
+    opts.deadline = crate::deadline::current();
+    opts.api_key = crate::api_keys::current();
+    opts.rate_limit = crate::rate_limit::current();
+    opts.adapter = match adapter_path(&state.registry, &req.model, req.adapter.as_deref()) {
+        Ok(adapter) => adapter,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = opts.validate() {
+        return ShimmyError::from(e).into_response();
     }
-    if let Some(s) = req.stream {
-        opts.stream = s;
+    let loaded = match state.load_model_keep_alive(&spec, req.keep_alive).await {
+        Ok(loaded) => loaded,
+        Err(e) => return crate::api_errors::model_load_failed_response(&req.model, e),
+    };
+    if let Err(e) = check_adapter_support(&*loaded, &opts, &req.model) {
+        return e.into_response();
     }
 
+    respond(loaded, req.prompt, opts, &req.model, TokenFields::default()).await
+}
+
+/// Run a generation as SSE when `opts.stream` is set, else as one JSON body
+async fn respond(
+    loaded: Box<dyn LoadedModel>,
+    prompt: String,
+    opts: GenOptions,
+    model: &str,
+    fields: TokenFields,
+) -> Response {
     if opts.stream {
         // SSE streaming
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<String>();
         let mut opts_clone = opts.clone();
         opts_clone.stream = false; // internal generation collects tokens while we push per token
-        let prompt_clone = prompt.clone();
+        let cancel = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        opts_clone.cancel = Some(cancel.clone());
         tokio::spawn(async move {
             let tx_tokens = tx.clone();
-            let _ = loaded
-                .generate(
-                    &prompt_clone,
-                    opts_clone,
-                    Some(Box::new(move |tok| {
-                        let _ = tx_tokens.send(tok);
-                    })),
-                )
-                .await;
+            let send = move |data: String| {
+                if tx_tokens.send(data).is_err() {
+                    cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+            };
+            if fields.any() {
+                // One JSON object per token instead of bare text
+                let _ = loaded
+                    .generate_detailed(
+                        &prompt,
+                        opts_clone,
+                        Some(Box::new(move |token| {
+                            send(serde_json::to_string(&fields.select(token)).unwrap_or_default())
+                        })),
+                    )
+                    .await;
+            } else {
+                let _ = loaded
+                    .generate(&prompt, opts_clone, Some(Box::new(send)))
+                    .await;
+            }
             let _ = tx.send("[DONE]".into());
         });
         let stream = UnboundedReceiverStream::new(rx)
             .map(|s| Ok::<Event, std::convert::Infallible>(Event::default().data(s)));
-        Sse::new(stream).into_response()
+        sse_response(stream)
     } else {
-        match loaded.generate(&prompt, opts, None).await {
+        let tokens = fields
+            .any()
+            .then(|| Arc::new(std::sync::Mutex::new(Vec::new())));
+        let result = match &tokens {
+            Some(tokens) => {
+                let sink = tokens.clone();
+                loaded
+                    .generate_detailed(
+                        &prompt,
+                        opts,
+                        Some(Box::new(move |token| {
+                            sink.lock()
+                                .unwrap_or_else(|e| e.into_inner())
+                                .push(fields.select(token))
+                        })),
+                    )
+                    .await
+                    .map(|generation| generation.text)
+            }
+            None => loaded.generate(&prompt, opts, None).await,
+        };
+        match result {
             Ok(full) => {
-                tracing::debug!(
-                    "Generation completed successfully for model '{}'",
-                    req.model
-                );
-                Json(GenerateResponse { response: full }).into_response()
+                tracing::debug!("Generation completed successfully for model '{}'", model);
+                let tokens = tokens
+                    .map(|t| std::mem::take(&mut *t.lock().unwrap_or_else(|e| e.into_inner())));
+                Json(GenerateResponse {
+                    response: full,
+                    tokens,
+                })
+                .into_response()
             }
             Err(e) => {
                 tracing::error!(
                     "Generation failed for model '{}': {} (Issue #106 Windows debugging)",
-                    req.model,
+                    model,
                     e
                 );
                 axum::http::StatusCode::BAD_GATEWAY.into_response()
@@ -144,11 +434,21 @@ pub async fn ws_generate(
     State(state): State<Arc<AppState>>,
     ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_ws_generate(state, socket))
+    // The socket is served from another task, outside the request's key and
+    // rate limit scopes
+    let api_key = crate::api_keys::current();
+    let rate_limit = crate::rate_limit::current();
+    ws.on_upgrade(move |socket| handle_ws_generate(state, socket, api_key, rate_limit))
 }
 
-async fn handle_ws_generate(state: Arc<AppState>, mut socket: WebSocket) {
-    // Expect first message with request JSON
+async fn handle_ws_generate(
+    state: Arc<AppState>,
+    mut socket: WebSocket,
+    api_key: Option<Arc<crate::api_keys::ApiKey>>,
+    rate_limit: Option<Arc<crate::rate_limit::Charge>>,
+) {
+    // One generation per connection, admitted like the HTTP request that
+    // opened it; the first message is the request
     let Some(Ok(first)) = socket.recv().await else {
         return;
     };
@@ -157,29 +457,97 @@ async fn handle_ws_generate(state: Arc<AppState>, mut socket: WebSocket) {
         WsMessage::Binary(b) => String::from_utf8_lossy(&b).to_string(),
         _ => return,
     };
-    let req: GenerateRequest = match serde_json::from_str(&req_json) {
-        Ok(r) => r,
+    let prepared = match serde_json::from_str::<GenerateRequest>(&req_json) {
+        Ok(req) => prepare_ws_generate(&state, req, api_key, rate_limit).await,
+        Err(e) => Err(ShimmyError::InvalidRequest {
+            reason: format!("bad request: {}", e),
+        }),
+    };
+    let (loaded, prompt, mut opts) = match prepared {
+        Ok(prepared) => prepared,
         Err(e) => {
-            let _ = socket
-                .send(WsMessage::Text(format!(
-                    "{{\"error\":\"bad request: {e}\"}}"
-                )))
-                .await;
+            let _ = socket.send(ws_frame(ws_error(&e))).await;
             return;
         }
     };
-    let Some(spec) = state.registry.to_spec(&req.model) else {
-        let _ = socket
-            .send(WsMessage::Text("{\"error\":\"model not found\"}".into()))
-            .await;
-        return;
-    };
-    let Ok(loaded) = state.engine.load(&spec).await else {
-        let _ = socket
-            .send(WsMessage::Text("{\"error\":\"load failed\"}".into()))
-            .await;
+
+    let cancel = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    opts.cancel = Some(cancel.clone());
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let generation = tokio::spawn(async move {
+        // The sender goes with the callback, so the channel closes when
+        // generation ends
+        let on_token: Box<dyn FnMut(String) + Send> = Box::new(move |tok| {
+            let _ = tx.send(tok);
+        });
+        loaded
+            .generate_with_finish(&prompt, opts, Some(on_token))
+            .await
+    });
+
+    let stop = || cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+    let mut connected = true;
+    loop {
+        tokio::select! {
+            token = rx.recv() => {
+                let Some(token) = token else { break };
+                if connected
+                    && socket
+                        .send(ws_frame(serde_json::json!({ "token": token })))
+                        .await
+                        .is_err()
+                {
+                    connected = false;
+                    stop();
+                }
+            }
+            message = socket.recv(), if connected => match message {
+                Some(Ok(WsMessage::Text(text))) if is_ws_cancel(&text) => stop(),
+                Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => {
+                    connected = false;
+                    stop();
+                }
+                // Anything else mid-stream is ignored
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    if !connected {
         return;
+    }
+    let last = match generation.await {
+        Ok(Ok(generation)) => serde_json::json!({
+            "done": true,
+            "finish_reason": generation.finish_reason.as_str(),
+        }),
+        Ok(Err(e)) => ws_error(&ShimmyError::GenerationError {
+            reason: e.to_string(),
+        }),
+        Err(e) => ws_error(&ShimmyError::GenerationError {
+            reason: e.to_string(),
+        }),
     };
+    let _ = socket.send(ws_frame(last)).await;
+}
+
+/// Model, prompt and options for a `/ws/generate` request
+async fn prepare_ws_generate(
+    state: &AppState,
+    req: GenerateRequest,
+    api_key: Option<Arc<crate::api_keys::ApiKey>>,
+    rate_limit: Option<Arc<crate::rate_limit::Charge>>,
+) -> Result<(Box<dyn LoadedModel>, String, GenOptions), ShimmyError> {
+    let spec = state
+        .registry
+        .to_spec(&req.model)
+        .ok_or_else(|| ShimmyError::ModelNotFound {
+            name: req.model.clone(),
+        })?;
+    let adapter = adapter_path(&state.registry, &req.model, req.adapter.as_deref())?;
+    let loaded = state
+        .load_model_keep_alive(&spec, req.keep_alive)
+        .await
+        .map_err(|e| ShimmyError::from_load(std::path::Path::new(&req.model), e))?;
 
     // Build prompt (reuse logic)
     let prompt = if let Some(ms) = &req.messages {
@@ -210,36 +578,36 @@ async fn handle_ws_generate(state: Arc<AppState>, mut socket: WebSocket) {
     if let Some(m) = req.max_tokens {
         opts.max_tokens = m;
     }
-    // Force internal non-stream; we push per-token ourselves
-    let mut internal = opts.clone();
-    internal.stream = false;
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
-    tokio::spawn({
-        let prompt = prompt.clone();
-        let tx_done = tx.clone();
-        async move {
-            let tx_tokens = tx.clone();
-            let _ = loaded
-                .generate(
-                    &prompt,
-                    internal,
-                    Some(Box::new(move |tok| {
-                        let _ = tx_tokens.send(tok);
-                    })),
-                )
-                .await;
-            let _ = tx_done.send("[DONE]".into());
-        }
-    });
-    while let Some(piece) = rx.recv().await {
-        if piece == "[DONE]" {
-            break;
-        }
-        if socket.send(WsMessage::Text(piece)).await.is_err() {
-            break;
+    opts.max_time_ms = req.max_time_ms;
+    opts.api_key = api_key;
+    opts.rate_limit = rate_limit;
+    opts.adapter = adapter;
+    // Tokens are pushed to the socket as they come
+    opts.stream = false;
+    opts.validate()?;
+    check_adapter_support(&*loaded, &opts, &req.model)?;
+    Ok((loaded, prompt, opts))
+}
+
+/// Whether a client message is `{"type": "cancel"}`
+fn is_ws_cancel(text: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(text).is_ok_and(|message| message["type"] == "cancel")
+}
+
+fn ws_frame(value: serde_json::Value) -> WsMessage {
+    WsMessage::Text(value.to_string())
+}
+
+/// An error frame: the HTTP error body's fields with the message as `error`
+fn ws_error(e: &ShimmyError) -> serde_json::Value {
+    let (_, body) = e.response_body();
+    let mut frame = body["error"].clone();
+    if let Some(fields) = frame.as_object_mut() {
+        if let Some(message) = fields.remove("message") {
+            fields.insert("error".to_string(), message);
         }
     }
-    let _ = socket.send(WsMessage::Text("{\"done\":true}".into())).await;
+    frame
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -270,6 +638,16 @@ pub async fn list_models(State(state): State<Arc<AppState>>) -> impl IntoRespons
         });
     }
 
+    for entry in state.registry.runtime_models() {
+        models.push(ModelInfo {
+            name: entry.name,
+            size_bytes: crate::engine::gguf::total_size(&entry.base_path),
+            model_type: None,
+            parameter_count: None,
+            source: "registered".to_string(),
+        });
+    }
+
     // Add discovered models
     for (name, discovered) in &state.registry.discovered_models {
         models.push(ModelInfo {
@@ -284,6 +662,210 @@ pub async fn list_models(State(state): State<Arc<AppState>>) -> impl IntoRespons
     Json(ModelListResponse { models })
 }
 
+/// Body for `POST /api/models/register`
+#[derive(Debug, Deserialize)]
+pub struct RegisterModelRequest {
+    /// GGUF file to serve; any shard of a split model
+    pub path: std::path::PathBuf,
+    /// Registry name (default: the file name without extension)
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub lora_path: Option<std::path::PathBuf>,
+    /// Chat template family (default: inferred from the name)
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub ctx_len: Option<usize>,
+    #[serde(default)]
+    pub n_threads: Option<i32>,
+    #[serde(default)]
+    pub n_gpu_layers: Option<u32>,
+    /// Multimodal projector (an `mmproj` GGUF) for vision models
+    #[serde(default)]
+    pub mmproj_path: Option<std::path::PathBuf>,
+}
+
+impl RegisterModelRequest {
+    fn into_entry(
+        self,
+        registry: &crate::model_registry::Registry,
+    ) -> Option<crate::model_registry::ModelEntry> {
+        let name = self.name.or_else(|| {
+            self.path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })?;
+        Some(crate::model_registry::ModelEntry {
+            template: self
+                .template
+                .or_else(|| Some(registry.infer_template(&name))),
+            name,
+            base_path: self.path,
+            lora_path: self.lora_path,
+            ctx_len: self.ctx_len,
+            n_threads: self.n_threads,
+            n_gpu_layers: self.n_gpu_layers,
+            mmproj_path: self.mmproj_path,
+        })
+    }
+}
+
+/// Add a GGUF model to the registry without a restart and save it so it is
+/// registered again on the next start
+pub async fn register_model(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<RegisterModelRequest>,
+) -> Response {
+    use crate::model_registry::{registered_models_path, RegisterError, Registry};
+
+    let Some(entry) = req.into_entry(&state.registry) else {
+        return ShimmyError::InvalidRequest {
+            reason: "`name` is required when `path` has no file name".to_string(),
+        }
+        .into_response();
+    };
+    if entry.name.trim().is_empty() {
+        return ShimmyError::InvalidRequest {
+            reason: "`name` must not be empty".to_string(),
+        }
+        .into_response();
+    }
+    if entry.ctx_len == Some(0) {
+        return ShimmyError::from(InvalidParameter::new("ctx_len", "must be at least 1"))
+            .into_response();
+    }
+
+    let entry = match state.registry.register_runtime(entry) {
+        Ok(entry) => entry,
+        Err(RegisterError::NameTaken(name)) => {
+            return ShimmyError::InvalidRequest {
+                reason: format!(
+                    "A configured or discovered model is already named '{}'; pick another `name`",
+                    name
+                ),
+            }
+            .into_response();
+        }
+        Err(RegisterError::UnknownModel(name)) => {
+            return ShimmyError::ModelNotFound { name }.into_response()
+        }
+        Err(RegisterError::Load(e)) => return ShimmyError::from(e).into_response(),
+    };
+    if let Err(e) = Registry::persist_registered(&registered_models_path(), &entry) {
+        tracing::warn!(
+            "Registered model '{}' for this run only; saving it failed: {}",
+            entry.name,
+            e
+        );
+    }
+    tracing::info!(
+        "Registered model '{}' from {}",
+        entry.name,
+        entry.base_path.display()
+    );
+
+    let estimate = state.registry.memory_estimate(&entry.name);
+    (
+        axum::http::StatusCode::CREATED,
+        Json(serde_json::json!({
+            "name": entry.name,
+            "path": entry.base_path,
+            "template": entry.template,
+            "ctx_len": entry.ctx_len.unwrap_or(4096),
+            "n_gpu_layers": entry.n_gpu_layers,
+            "embedding": state.registry.is_embedding_model(&entry.name),
+            "estimated_runtime_gb": estimate.map(|e| e.estimated_runtime_gb),
+        })),
+    )
+        .into_response()
+}
+
+/// Body for `POST /api/models/:name/adapters`
+#[derive(Debug, Deserialize)]
+pub struct RegisterAdapterRequest {
+    /// Name requests select the adapter by
+    pub name: String,
+    /// GGUF LoRA adapter trained for the model
+    pub path: std::path::PathBuf,
+}
+
+/// Add a LoRA adapter to a model, selectable per request with `adapter`,
+/// and save it so it is registered again on the next start
+pub async fn register_adapter(
+    State(state): State<Arc<AppState>>,
+    Path(model): Path<String>,
+    Json(req): Json<RegisterAdapterRequest>,
+) -> Response {
+    use crate::model_registry::{registered_models_path, RegisterError, Registry};
+
+    if req.name.trim().is_empty() {
+        return ShimmyError::InvalidRequest {
+            reason: "`name` must not be empty".to_string(),
+        }
+        .into_response();
+    }
+    if let Err(e) = state
+        .registry
+        .register_adapter(&model, &req.name, req.path.clone())
+    {
+        return match e {
+            RegisterError::UnknownModel(name) => ShimmyError::ModelNotFound { name },
+            RegisterError::Load(e) => ShimmyError::from(e),
+            e @ RegisterError::NameTaken(_) => ShimmyError::InvalidRequest {
+                reason: e.to_string(),
+            },
+        }
+        .into_response();
+    }
+    if let Err(e) =
+        Registry::persist_adapter(&registered_models_path(), &model, &req.name, &req.path)
+    {
+        tracing::warn!(
+            "Registered adapter '{}' of model '{}' for this run only; saving it failed: {}",
+            req.name,
+            model,
+            e
+        );
+    }
+    tracing::info!(
+        "Registered adapter '{}' of model '{}' from {}",
+        req.name,
+        model,
+        req.path.display()
+    );
+    (
+        axum::http::StatusCode::CREATED,
+        Json(serde_json::json!({
+            "model": model,
+            "name": req.name,
+            "path": req.path,
+        })),
+    )
+        .into_response()
+}
+
+/// The LoRA adapters registered for a model
+pub async fn list_adapters(
+    State(state): State<Arc<AppState>>,
+    Path(model): Path<String>,
+) -> Response {
+    if state.registry.to_spec(&model).is_none() {
+        return ShimmyError::ModelNotFound { name: model }.into_response();
+    }
+    let adapters: Vec<_> = state
+        .registry
+        .adapters(&model)
+        .into_iter()
+        .map(|(name, path)| serde_json::json!({"name": name, "path": path}))
+        .collect();
+    Json(serde_json::json!({
+        "model": model,
+        "adapters": adapters,
+    }))
+    .into_response()
+}
+
 pub async fn discover_models(State(_state): State<Arc<AppState>>) -> impl IntoResponse {
     // Discovery API provides read-only access to discovered models
     // Registry mutation requires request-scoped discovery for thread safety
@@ -340,30 +922,175 @@ pub async fn load_model(
     }))
 }
 
+/// Stop keeping `name` loaded; requests still using it finish first
 pub async fn unload_model(
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     Path(name): Path<String>,
 ) -> impl IntoResponse {
-    // Simple model unloading endpoint - future enhancement
-    // Model unloading: Handled automatically via Rust's Drop trait when response completes
+    let status = if state.model_cache.unload(&name) {
+        "unloaded"
+    } else {
+        "not_loaded"
+    };
     Json(serde_json::json!({
         "message": format!("Model {} unload requested", name),
-        "status": "pending"
+        "status": status
     }))
 }
 
 pub async fn model_status(
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     Path(name): Path<String>,
 ) -> impl IntoResponse {
-    // Model status: Reports operational status with memory and load information
+    let loaded = state.model_cache.contains(&name);
+    let warnings = state
+        .engine
+        .running_models()
+        .into_iter()
+        .rev()
+        .find(|m| m.name == name)
+        .map(|m| m.warnings)
+        .unwrap_or_default();
+    Json(serde_json::json!({
+        "model": name,
+        "status": if loaded { "loaded" } else { "unknown" },
+        "loaded": loaded,
+        "warnings": warnings
+    }))
+}
+
+/// Body for `POST /api/models/:name/apply-template`
+#[derive(Debug, Deserialize)]
+pub struct ApplyTemplateRequest {
+    pub messages: Vec<ChatMessage>,
+}
+
+/// The exact prompt `/v1/chat/completions` would send `name` for `messages`,
+/// without loading the model or generating
+pub async fn apply_template(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(req): Json<ApplyTemplateRequest>,
+) -> Response {
+    let Some(spec) = state.registry.to_spec(&name) else {
+        return ShimmyError::ModelNotFound { name }.into_response();
+    };
+    let fam = crate::openai_compat::template_family(spec.template.as_deref(), &name);
+    let prompt = crate::openai_compat::render_chat(&fam, &req.messages);
     Json(serde_json::json!({
         "model": name,
-        "status": "unknown",
-        "loaded": false
+        "template": fam,
+        "estimated_tokens": crate::truncation::estimate_tokens(&prompt),
+        "stop_tokens": fam.stop_tokens(),
+        "prompt": prompt,
+    }))
+    .into_response()
+}
+
+/// Models currently in memory with backend-reported RAM/VRAM usage
+pub async fn running_models(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let models: Vec<serde_json::Value> = state
+        .engine
+        .running_models()
+        .into_iter()
+        .map(|m| {
+            let estimated_bytes = std::fs::metadata(&m.path).ok().map(|meta| {
+                let estimate = crate::util::memory::estimate_memory_requirements(meta.len());
+                (estimate.estimated_runtime_gb * 1_024_000_000.0) as u64
+            });
+            serde_json::json!({
+                "name": m.name,
+                "model": m.path.display().to_string(),
+                "loaded_at": m.loaded_at.to_rfc3339(),
+                "size": m.memory.map(|u| u.total_bytes()),
+                "size_ram": m.memory.map(|u| u.ram_bytes),
+                "size_vram": m.memory.map(|u| u.vram_bytes),
+                "estimated_size": estimated_bytes,
+                "warnings": m.warnings,
+            })
+        })
+        .collect();
+
+    Json(serde_json::json!({ "models": models }))
+}
+
+/// Power profile and CPU layout that shape inference on this host
+pub async fn system_info() -> impl IntoResponse {
+    let topology = crate::util::cpu::CoreTopology::detect();
+    Json(serde_json::json!({
+        "power": crate::power::PowerManager::global().status(),
+        "cpu": {
+            "logical_cores": std::thread::available_parallelism().map(|n| n.get()).unwrap_or(0),
+            "hybrid": topology.as_ref().is_some_and(|t| t.is_hybrid()),
+            "performance_cores": topology
+                .filter(|t| t.is_hybrid())
+                .map(|t| t.performance_cores),
+        },
+        "capabilities": crate::capabilities::Capabilities::global(),
     }))
 }
 
+/// Query of `GET /api/connect`
+#[derive(Debug, Default, Deserialize)]
+pub struct ConnectQuery {
+    /// Model the snippets use (default: the first chat model)
+    pub model: Option<String>,
+}
+
+/// Client configuration snippets filled in with this server's address and
+/// models
+pub async fn connect_info(
+    State(state): State<Arc<AppState>>,
+    listener: Option<axum::Extension<crate::connect::Listener>>,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<ConnectQuery>,
+) -> Response {
+    let base_url = crate::connect::base_url(&headers, listener.as_ref().map(|l| &l.0));
+    match crate::connect::ConnectInfo::for_registry(
+        &state.registry,
+        &base_url,
+        query.model.as_deref(),
+        !state.api_keys.is_empty(),
+    ) {
+        Ok(info) => Json(info).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PowerModeRequest {
+    pub mode: crate::power::PowerMode,
+}
+
+/// Switch between `auto`, `normal` and `low` power modes at runtime
+pub async fn set_power_mode(Json(req): Json<PowerModeRequest>) -> impl IntoResponse {
+    let power = crate::power::PowerManager::global();
+    power.set_mode(req.mode);
+    Json(serde_json::json!({ "power": power.status() }))
+}
+
+/// Server events as SSE; currently `load_progress` updates for model loads
+pub async fn events() -> Response {
+    let rx = crate::load_progress::LoadEvents::global().subscribe();
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(progress) => {
+                    let event = Event::default()
+                        .event("load_progress")
+                        .json_data(&progress)
+                        .unwrap_or_else(|_| Event::default().comment("unserializable event"));
+                    return Some((Ok(event), rx));
+                }
+                // A slow client misses intermediate updates, not the stream
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    sse_response(stream)
+}
+
 #[allow(dead_code)]
 pub async fn list_tools(State(_state): State<Arc<AppState>>) -> impl IntoResponse {
     Json(serde_json::json!({
@@ -473,6 +1200,12 @@ mod tests {
             top_p: None,
             top_k: None,
             stream: Some(false),
+            max_time_ms: None,
+            token_ids: None,
+            logprobs: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
         };
 
         // Exercise handler code path (will fail gracefully due to no model)
@@ -480,6 +1213,249 @@ mod tests {
         // Test completed successfully
     }
 
+    #[test]
+    fn test_raw_generate_request_options() {
+        let req: RawGenerateRequest = serde_json::from_str(
+            r#"{"model": "m", "prompt": "Once upon", "add_bos": false, "stop": ["\n\n"], "seed": 7, "max_time_ms": 5000}"#,
+        )
+        .unwrap();
+        let opts = req.gen_options();
+        assert_eq!(opts.add_bos, Some(false));
+        assert_eq!(opts.stop_tokens, vec!["\n\n".to_string()]);
+        assert_eq!(opts.seed, Some(7));
+        assert_eq!(opts.max_time_ms, Some(5000));
+        assert!(!opts.stream);
+        assert_eq!(opts.max_tokens, GenOptions::default().max_tokens);
+
+        let missing_prompt = serde_json::from_str::<RawGenerateRequest>(r#"{"model": "m"}"#);
+        assert!(missing_prompt.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generate_raw_unknown_model() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::Registry;
+
+        let engine = Box::new(InferenceEngineAdapter::new());
+        let state = Arc::new(AppState::new(engine, Registry::default()));
+        let req: RawGenerateRequest =
+            serde_json::from_str(r#"{"model": "nope", "prompt": "x"}"#).unwrap();
+
+        let response = generate_raw(State(state), Json(req)).await.into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_sampling_options_rejected_before_load() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry {
+            name: "m".to_string(),
+            base_path: std::path::PathBuf::from("/nonexistent/m.gguf"),
+            lora_path: None,
+            template: None,
+            ctx_len: None,
+            n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
+        });
+        let engine = Box::new(InferenceEngineAdapter::new());
+        let state = Arc::new(AppState::new(engine, registry));
+
+        let req: RawGenerateRequest =
+            serde_json::from_str(r#"{"model": "m", "prompt": "x", "top_p": 0}"#).unwrap();
+        let response = generate_raw(State(state.clone()), Json(req))
+            .await
+            .into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+
+        let req: GenerateRequest = serde_json::from_str(
+            r#"{"model": "m", "prompt": "x", "messages": [{"role": "user", "content": "y"}]}"#,
+        )
+        .unwrap();
+        let response = generate(State(state), Json(req)).await.into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_apply_template_renders_without_loading() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::{ModelEntry, Registry};
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry {
+            name: "m".to_string(),
+            base_path: std::path::PathBuf::from("/nonexistent/m.gguf"),
+            lora_path: None,
+            template: Some("chatml".to_string()),
+            ctx_len: None,
+            n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
+        });
+        let engine = Box::new(InferenceEngineAdapter::new());
+        let state = Arc::new(AppState::new(engine, registry));
+
+        let req: ApplyTemplateRequest = serde_json::from_str(
+            r#"{"messages": [{"role": "system", "content": "Be brief."},
+                             {"role": "user", "content": "Hi"}]}"#,
+        )
+        .unwrap();
+        let response = apply_template(State(state.clone()), Path("m".to_string()), Json(req)).await;
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["template"], "ChatML");
+        let prompt = json["prompt"].as_str().unwrap();
+        assert!(prompt.contains("<|im_start|>system\nBe brief."));
+        assert!(prompt.ends_with("<|im_start|>assistant\n"));
+
+        let req = ApplyTemplateRequest { messages: vec![] };
+        let response = apply_template(State(state), Path("other".to_string()), Json(req)).await;
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+    }
+
+    struct Counting;
+
+    #[async_trait::async_trait]
+    impl LoadedModel for Counting {
+        async fn generate(
+            &self,
+            _prompt: &str,
+            _opts: GenOptions,
+            _on_token: Option<Box<dyn FnMut(String) + Send>>,
+        ) -> anyhow::Result<String> {
+            Ok("ab".to_string())
+        }
+
+        async fn generate_detailed(
+            &self,
+            _prompt: &str,
+            _opts: GenOptions,
+            mut on_token: Option<Box<dyn FnMut(TokenDetail) + Send>>,
+        ) -> anyhow::Result<crate::engine::Generation> {
+            for (id, text) in [(7, "a"), (8, "b")] {
+                if let Some(cb) = on_token.as_mut() {
+                    cb(TokenDetail {
+                        text: text.to_string(),
+                        id: Some(id),
+                        logprob: Some(-0.5),
+                        top_logprobs: Vec::new(),
+                    });
+                }
+            }
+            Ok(crate::engine::Generation {
+                text: "ab".to_string(),
+                finish_reason: crate::engine::FinishReason::Stop,
+                cached_tokens: 0,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_details_in_responses() {
+        let ids_only = TokenFields {
+            ids: true,
+            logprobs: false,
+        };
+        let body = |response: Response| async move {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            String::from_utf8(bytes.to_vec()).unwrap()
+        };
+
+        let plain = respond(
+            Box::new(Counting),
+            "p".into(),
+            GenOptions {
+                stream: false,
+                ..Default::default()
+            },
+            "m",
+            TokenFields::default(),
+        )
+        .await;
+        assert_eq!(body(plain).await, r#"{"response":"ab"}"#);
+
+        let detailed = respond(
+            Box::new(Counting),
+            "p".into(),
+            GenOptions {
+                stream: false,
+                ..Default::default()
+            },
+            "m",
+            ids_only,
+        )
+        .await;
+        let json: serde_json::Value = serde_json::from_str(&body(detailed).await).unwrap();
+        assert_eq!(
+            json["tokens"],
+            serde_json::json!([{"text": "a", "id": 7}, {"text": "b", "id": 8}])
+        );
+
+        let streamed = respond(
+            Box::new(Counting),
+            "p".into(),
+            GenOptions {
+                stream: true,
+                ..Default::default()
+            },
+            "m",
+            TokenFields {
+                ids: true,
+                logprobs: true,
+            },
+        )
+        .await;
+        let text = body(streamed).await;
+        assert!(text.contains(r#"data: {"text":"a","id":7,"logprob":-0.5}"#));
+        assert!(text.contains("data: [DONE]"));
+    }
+
+    #[test]
+    fn test_sse_keep_alive_interval() {
+        assert_eq!(sse_keep_alive_interval(None), Some(Duration::from_secs(15)));
+        assert_eq!(
+            sse_keep_alive_interval(Some("5")),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(sse_keep_alive_interval(Some("0")), None);
+        assert_eq!(
+            sse_keep_alive_interval(Some("soon")),
+            Some(Duration::from_secs(15))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sse_keep_alive_during_silence() {
+        let slow = futures_util::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            Ok::<Event, std::convert::Infallible>(Event::default().data("token"))
+        });
+        let response = sse_response_with(slow, Some(Duration::from_millis(50)));
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(body.starts_with(": keep-alive\n"), "{body}");
+        assert!(body.ends_with("data: token\n\n"), "{body}");
+
+        let quiet = futures_util::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok::<Event, std::convert::Infallible>(Event::default().data("token"))
+        });
+        let bytes = axum::body::to_bytes(sse_response_with(quiet, None).into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&bytes[..], b"data: token\n\n");
+    }
+
     #[tokio::test]
     async fn test_list_models_handler_execution() {
         use crate::engine::adapter::InferenceEngineAdapter;
@@ -519,6 +1495,12 @@ mod tests {
             top_p: Some(0.9),
             top_k: Some(40),
             stream: Some(false),
+            max_time_ms: None,
+            token_ids: None,
+            logprobs: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
         };
 
         assert_eq!(req.model, "test");
@@ -541,6 +1523,7 @@ mod tests {
     fn test_generate_response_structure() {
         let resp = GenerateResponse {
             response: "Generated text".to_string(),
+            tokens: None,
         };
 
         assert_eq!(resp.response, "Generated text");
@@ -666,6 +1649,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = Box::new(InferenceEngineAdapter::new());
@@ -681,6 +1666,12 @@ mod tests {
             top_p: Some(0.9),
             top_k: Some(40),
             stream: Some(true), // Enable streaming (line 54)
+            max_time_ms: None,
+            token_ids: None,
+            logprobs: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
         };
 
         // Exercise streaming path (lines 54-64)
@@ -701,6 +1692,8 @@ mod tests {
             template: Some("llama3".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = Box::new(InferenceEngineAdapter::new());
@@ -725,6 +1718,12 @@ mod tests {
             top_p: None,
             top_k: None,
             stream: Some(false),
+            max_time_ms: None,
+            token_ids: None,
+            logprobs: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
         };
 
         // Exercise messages path with system prompt (lines 35-42)
@@ -818,6 +1817,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = Box::new(InferenceEngineAdapter::new());
@@ -840,6 +1841,25 @@ mod tests {
         assert!(test_signature());
     }
 
+    #[test]
+    fn test_ws_cancel_and_error_frames() {
+        assert!(is_ws_cancel(r#"{"type": "cancel"}"#));
+        assert!(!is_ws_cancel(r#"{"type": "generate"}"#));
+        assert!(!is_ws_cancel("cancel"));
+
+        let frame = ws_error(&ShimmyError::ModelNotFound {
+            name: "nope".to_string(),
+        });
+        assert_eq!(frame["code"], "MODEL_NOT_FOUND");
+        assert_eq!(frame["error"], "Model not found: nope");
+        assert!(frame.get("message").is_none());
+
+        let frame =
+            ws_error(&InvalidParameter::new("top_k", "must be 0 (disabled) or positive").into());
+        assert_eq!(frame["code"], "INVALID_PARAMETER");
+        assert_eq!(frame["param"], "top_k");
+    }
+
     #[test]
     fn test_model_info_structure() {
         let info = ModelInfo {
@@ -899,6 +1919,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         // The registry might have discovered models too
@@ -1018,6 +2040,12 @@ mod tests {
             top_p: Some(0.9),
             top_k: Some(40),
             stream: Some(false),
+            max_time_ms: None,
+            token_ids: None,
+            logprobs: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
         };
 
         let debug_str = format!("{:?}", req);
@@ -1035,6 +2063,7 @@ mod tests {
 
         let gen_resp = GenerateResponse {
             response: "generated text".to_string(),
+            tokens: None,
         };
 
         let debug_str = format!("{:?}", gen_resp);
@@ -1077,6 +2106,7 @@ mod tests {
 
         let gen_response = GenerateResponse {
             response: "Test response".to_string(),
+            tokens: None,
         };
 
         let json = serde_json::to_string(&gen_response).unwrap();
@@ -1133,103 +2163,255 @@ mod tests {
 #[axum::debug_handler]
 pub async fn vision(
     State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
     Json(mut req): Json<crate::vision::VisionRequest>,
 ) -> impl IntoResponse {
-    // Extract license from environment or request
-    if req.license.is_none() {
-        req.license = std::env::var("SHIMMY_LICENSE_KEY").ok();
-    }
+    use crate::cache::idempotency::{IdempotencyCache, Outcome, IDEMPOTENCY_KEY_HEADER};
 
-    // Use default vision model or specified one
-    let env_model = std::env::var("SHIMMY_VISION_MODEL").ok();
-    let model_name = req
-        .model
-        .as_deref()
-        .or(env_model.as_deref())
-        .unwrap_or("minicpm-v")
-        .to_string();
+    if let Some(accept) = headers.get(crate::vision::ACCEPT_SCHEMA_VERSION_HEADER) {
+        match crate::vision::negotiate_schema_version(accept.to_str().unwrap_or_default()) {
+            Ok(version) => req.schema_version = Some(version),
+            Err(e) => return e.into_response(),
+        }
+    }
+    if req.stream.unwrap_or(false) && headers.contains_key(IDEMPOTENCY_KEY_HEADER) {
+        return ShimmyError::InvalidRequest {
+            reason: "Idempotency-Key can't be used with `stream`; a stream can't be replayed"
+                .to_string(),
+        }
+        .into_response();
+    }
+    let claim = match IdempotencyCache::global().begin(&headers, &req, std::time::Instant::now()) {
+        Ok(Outcome::Untracked) => None,
+        Ok(Outcome::Run(claim)) => Some(claim),
+        Ok(Outcome::Replay(response)) => return response,
+        Err(e) => return e.into_response(),
+    };
 
+    req.license = vision_license(req.license);
+    let model_name = vision_model_name(&state, req.model.as_deref(), &req.mode);
     let Some(license_manager) = state.vision_license_manager.as_ref() else {
-        tracing::error!("Vision license manager not initialized");
-        return (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({
-                "error": {
-                    "code": "VISION_LICENSE_MANAGER_MISSING",
-                    "message": "Vision subsystem not initialized",
-                }
-            })),
-        )
-            .into_response();
+        return license_manager_missing();
     };
 
-    fn map_vision_error_status(message: &str) -> axum::http::StatusCode {
-        if message.contains("Either image_base64 or url must be provided") {
-            return axum::http::StatusCode::BAD_REQUEST;
-        }
-        if message.starts_with("Failed to decode base64 image") {
-            return axum::http::StatusCode::BAD_REQUEST;
-        }
-        if message.starts_with("Failed to preprocess image") {
-            return axum::http::StatusCode::UNPROCESSABLE_ENTITY;
-        }
-        if message.contains("Vision model '") && message.contains("not available in Ollama") {
-            return axum::http::StatusCode::UNPROCESSABLE_ENTITY;
-        }
-        if message.contains("Failed to fetch image from URL") {
-            if message.to_lowercase().contains("timed out") {
-                return axum::http::StatusCode::GATEWAY_TIMEOUT;
+    if let Some(callback_url) = req.callback_url.clone() {
+        return vision_job(state.clone(), req, model_name, &callback_url, claim);
+    }
+    if req.stream.unwrap_or(false) {
+        return stream_vision(state.clone(), req, model_name);
+    }
+
+    match crate::vision::process_vision_request(req, &model_name, license_manager, &state).await {
+        Ok(response) => {
+            if let Some(claim) = claim {
+                claim.complete(axum::http::StatusCode::OK, &response);
             }
-            return axum::http::StatusCode::BAD_GATEWAY;
+            Json(response).into_response()
         }
-        if message.contains("Vision inference timed out") {
-            return axum::http::StatusCode::GATEWAY_TIMEOUT;
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `/api/vision` with `callback_url`: answer `202 Accepted` with a job id and
+/// deliver the outcome to the callback once the analysis finishes. With an
+/// `Idempotency-Key`, repeats get the same job id and start no second job.
+#[cfg(feature = "vision")]
+fn vision_job(
+    state: Arc<AppState>,
+    req: crate::vision::VisionRequest,
+    model_name: String,
+    callback_url: &str,
+    claim: Option<crate::cache::idempotency::Claim<'static>>,
+) -> Response {
+    use crate::vision_webhook::{self, JobAccepted, JobStatus, WebhookPayload};
+
+    if req.stream.unwrap_or(false) {
+        return ShimmyError::InvalidRequest {
+            reason: "`stream` and `callback_url` are mutually exclusive; send one or the other"
+                .to_string(),
         }
-        if message.contains("Failed to load vision model")
-            || message.contains("Vision inference failed")
-        {
-            return axum::http::StatusCode::BAD_GATEWAY;
+        .into_response();
+    }
+    let secret = std::env::var(vision_webhook::WEBHOOK_SECRET_ENV).ok();
+    let url = match vision_webhook::validate_callback(callback_url, secret.as_deref()) {
+        Ok(url) => url,
+        Err(e) => return e.into_response(),
+    };
+    let secret = secret.unwrap_or_default();
+
+    let job_id = format!("visionjob-{}", uuid::Uuid::new_v4());
+    let accepted = JobAccepted {
+        job_id: job_id.clone(),
+        status: JobStatus::Accepted,
+    };
+    if let Some(claim) = claim {
+        claim.complete(axum::http::StatusCode::ACCEPTED, &accepted);
+    }
+    let charges = (crate::api_keys::current(), crate::rate_limit::current());
+    tokio::spawn(charged(charges, async move {
+        let Some(license_manager) = state.vision_license_manager.as_ref() else {
+            return;
+        };
+        let result =
+            crate::vision::process_vision_request(req, &model_name, license_manager, &state).await;
+        let payload = WebhookPayload::from_result(job_id, result);
+        vision_webhook::deliver(url, secret, payload).await;
+    }));
+    (axum::http::StatusCode::ACCEPTED, Json(accepted)).into_response()
+}
+
+/// Run a spawned task under the API key and rate-limit charge of the request
+/// that spawned it, so its generations are still counted against them
+#[cfg(feature = "vision")]
+async fn charged<F: std::future::Future>(
+    (key, charge): (
+        Option<Arc<crate::api_keys::ApiKey>>,
+        Option<Arc<crate::rate_limit::Charge>>,
+    ),
+    f: F,
+) -> F::Output {
+    match (key, charge) {
+        (Some(key), Some(charge)) => {
+            crate::api_keys::scope(key, crate::rate_limit::scope(charge, f)).await
         }
+        (Some(key), None) => crate::api_keys::scope(key, f).await,
+        (None, Some(charge)) => crate::rate_limit::scope(charge, f).await,
+        (None, None) => f.await,
+    }
+}
+
+/// License from the request, else the environment, else `shimmy license set`
+/// storage
+#[cfg(feature = "vision")]
+fn vision_license(license: Option<String>) -> Option<String> {
+    license.or_else(|| {
+        std::env::var("SHIMMY_LICENSE_KEY")
+            .ok()
+            .or_else(crate::license_store::cached)
+    })
+}
 
-        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+/// The specified model, else the one configured for the mode, else the default
+#[cfg(feature = "vision")]
+fn vision_model_name(state: &AppState, model: Option<&str>, mode: &str) -> String {
+    let default_model =
+        std::env::var("SHIMMY_VISION_MODEL").unwrap_or_else(|_| "minicpm-v".to_string());
+    match model {
+        Some(model) => model.to_string(),
+        None => crate::vision::select_vision_model(mode, &default_model, state),
     }
+}
 
-    match crate::vision::process_vision_request(req, &model_name, license_manager, &state).await {
-        Ok(response) => Json(response).into_response(),
-        Err(e) => {
-            // Check if it's a license error
-            if let Some(license_err) = e.downcast_ref::<crate::vision_license::VisionLicenseError>()
-            {
-                return (
-                    license_err.to_status_code(),
-                    Json(license_err.to_json_error()),
-                )
-                    .into_response();
+#[cfg(feature = "vision")]
+fn license_manager_missing() -> Response {
+    tracing::error!("Vision license manager not initialized");
+    (
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({
+            "error": {
+                "code": "VISION_LICENSE_MANAGER_MISSING",
+                "message": "Vision subsystem not initialized",
             }
+        })),
+    )
+        .into_response()
+}
 
-            let full_message = e.to_string();
-            let status = map_vision_error_status(&full_message);
-
-            tracing::error!(status = %status, "Vision processing error: {}", full_message);
-            // Expose client error messages (4xx) to help users fix their requests.
-            // Hide server error details (5xx) unless running in dev mode.
-            let message =
-                if status.is_client_error() || std::env::var("SHIMMY_VISION_DEV_MODE").is_ok() {
-                    full_message
-                } else {
-                    "Vision processing error".to_string()
-                };
-
-            (
-                status,
-                Json(serde_json::json!({
-                    "error": {
-                        "code": "VISION_PROCESSING_ERROR",
-                        "message": message,
-                    }
-                })),
-            )
-                .into_response()
+/// `POST /api/vision/batch`: analyze each image like `/api/vision` would,
+/// a few at a time, and answer with every result in request order
+#[cfg(feature = "vision")]
+pub async fn vision_batch(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    Json(mut req): Json<crate::vision_batch::VisionBatchRequest>,
+) -> Response {
+    if let Some(accept) = headers.get(crate::vision::ACCEPT_SCHEMA_VERSION_HEADER) {
+        match crate::vision::negotiate_schema_version(accept.to_str().unwrap_or_default()) {
+            Ok(version) => req.schema_version = Some(version),
+            Err(e) => return e.into_response(),
         }
     }
+    req.license = vision_license(req.license);
+    let model_name = vision_model_name(&state, req.model.as_deref(), &req.mode);
+    let Some(license_manager) = state.vision_license_manager.as_ref() else {
+        return license_manager_missing();
+    };
+
+    match crate::vision_batch::process_batch(req, &model_name, license_manager, &state).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `GET /api/license/status`: the configured vision license, its expiry,
+/// usage against the monthly cap and any renewal warning
+#[cfg(feature = "vision")]
+pub async fn license_status(State(state): State<Arc<AppState>>) -> Response {
+    let Some(license_manager) = state.vision_license_manager.as_ref() else {
+        return ShimmyError::BackendNotAvailable {
+            backend: "vision licensing".to_string(),
+        }
+        .into_response();
+    };
+    let key = std::env::var("SHIMMY_LICENSE_KEY")
+        .ok()
+        .or_else(crate::license_store::cached);
+    Json(license_manager.status(key.as_deref()).await).into_response()
+}
+
+/// Streaming `/api/vision`: `token` events with model output (and for
+/// `video`, a `keyframe` event per analyzed frame), then `done` with the full
+/// response. Once the stream has started the status can't
+/// change, so any failure (license, usage recording, image or inference)
+/// ends it with an `error` event carrying the status and error body the
+/// non-streaming request would have returned.
+#[cfg(feature = "vision")]
+fn stream_vision(
+    state: Arc<AppState>,
+    req: crate::vision::VisionRequest,
+    model_name: String,
+) -> Response {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Event>();
+    let deadline = crate::deadline::current();
+    let charges = (crate::api_keys::current(), crate::rate_limit::current());
+    let task = crate::deadline::scope(deadline, async move {
+        let Some(license_manager) = state.vision_license_manager.as_ref() else {
+            return;
+        };
+        let tx_progress = tx.clone();
+        let on_progress: crate::vision::ProgressSink = Arc::new(move |progress| {
+            let event = match progress {
+                crate::vision::VisionProgress::Token(text) => Event::default()
+                    .event("token")
+                    .json_data(serde_json::json!({ "text": text })),
+                crate::vision::VisionProgress::Keyframe(keyframe) => {
+                    Event::default().event("keyframe").json_data(&keyframe)
+                }
+            };
+            if let Ok(event) = event {
+                let _ = tx_progress.send(event);
+            }
+        });
+        let result = crate::vision::process_vision_request_streaming(
+            req,
+            &model_name,
+            license_manager,
+            &state,
+            Some(on_progress),
+        )
+        .await;
+        let event = match result {
+            Ok(response) => Event::default().event("done").json_data(&response),
+            Err(e) => {
+                let (status, mut body) = e.response_body();
+                body["status"] = status.as_u16().into();
+                Event::default().event("error").json_data(&body)
+            }
+        };
+        if let Ok(event) = event {
+            let _ = tx.send(event);
+        }
+    });
+    tokio::spawn(charged(charges, task));
+    sse_response(UnboundedReceiverStream::new(rx).map(Ok::<Event, std::convert::Infallible>))
 }
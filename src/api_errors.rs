@@ -1,5 +1,10 @@
 // Improved API error handling
-use axum::{http::StatusCode, response::Json};
+use crate::engine::LoadError;
+use crate::error::ShimmyError;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -36,6 +41,24 @@ impl From<ApiError> for (StatusCode, Json<ErrorResponse>) {
     }
 }
 
+/// HTTP status for a categorized model load failure
+pub fn load_error_status(err: &LoadError) -> StatusCode {
+    match err {
+        LoadError::FileNotFound { .. } => StatusCode::NOT_FOUND,
+        LoadError::UnsupportedArchitecture { .. } | LoadError::CorruptGguf { .. } => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        LoadError::OutOfMemory { .. } | LoadError::BackendInitFailed { .. } => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
+
+/// Response for a failed `engine.load`, with a stable code when the failure is categorized
+pub fn model_load_failed_response(model: &str, err: anyhow::Error) -> Response {
+    ShimmyError::from_load(std::path::Path::new(model), err).into_response()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -192,6 +215,34 @@ mod tests {
         assert!(obj.contains_key("error"));
     }
 
+    #[test]
+    fn test_load_error_status_mapping() {
+        let not_found = LoadError::FileNotFound {
+            path: "missing.gguf".into(),
+        };
+        assert_eq!(load_error_status(&not_found), StatusCode::NOT_FOUND);
+
+        let oom = LoadError::OutOfMemory {
+            details: "failed to allocate".to_string(),
+        };
+        assert_eq!(load_error_status(&oom), StatusCode::SERVICE_UNAVAILABLE);
+
+        let corrupt = LoadError::CorruptGguf {
+            path: "bad.gguf".into(),
+            details: "invalid magic".to_string(),
+        };
+        assert_eq!(
+            load_error_status(&corrupt),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[test]
+    fn test_model_load_failed_response_uncategorized() {
+        let response = model_load_failed_response("m", anyhow::anyhow!("boom"));
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
     #[test]
     fn test_status_code_mapping() {
         // Verify all status codes are as expected
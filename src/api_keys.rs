@@ -0,0 +1,357 @@
+//! API keys with per-key rate limits and daily token quotas.
+//!
+//! Sharing one server with a small team needs two things: knowing who is
+//! calling, and keeping one person's batch job from starving everyone else.
+//! `SHIMMY_API_KEYS_FILE` (or `api_keys_file` in `shimmy.toml`) names a JSON
+//! file of keys:
+//!
+//! ```json
+//! {
+//!   "requests_per_minute": 60,
+//!   "keys": [
+//!     { "name": "alice", "key": "sk-alice-…", "tokens_per_day": 200000 },
+//!     { "name": "ci", "key": "sk-ci-…", "requests_per_minute": 600 }
+//!   ]
+//! }
+//! ```
+//!
+//! Top-level limits apply to keys that don't set their own; a limit left out
+//! everywhere is unlimited. With the file set, every route except `/health`
+//! and `/readyz` needs `Authorization: Bearer <key>` or `x-api-key: <key>`
+//! and answers `401 UNAUTHORIZED` without one. A key over its limit gets
+//! `429 RATE_LIMITED` with `Retry-After`:
+//!
+//! - `requests_per_minute` refills continuously, so a key can burst up to the
+//!   full minute's allowance and then gets one request per `60 / n` seconds
+//! - `tokens_per_day` counts prompt and generated tokens of text generation
+//!   per UTC day. A request is refused once the day's quota is used up; the
+//!   request that crosses it still finishes. Counts are kept in memory and
+//!   start over when the server restarts
+
+use crate::error::ShimmyError;
+use anyhow::{bail, Context, Result};
+use axum::http::HeaderMap;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+pub const API_KEYS_FILE_ENV: &str = "SHIMMY_API_KEYS_FILE";
+
+/// Routes that stay open so health probes don't need a key
+const OPEN_ROUTES: &[&str] = &["/health", "/readyz"];
+
+tokio::task_local! {
+    static CURRENT_KEY: Arc<ApiKey>;
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeysFile {
+    requests_per_minute: Option<u32>,
+    tokens_per_day: Option<u64>,
+    keys: Vec<KeyEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyEntry {
+    name: String,
+    key: String,
+    requests_per_minute: Option<u32>,
+    tokens_per_day: Option<u64>,
+}
+
+/// One key's limits and what it has used of them
+#[derive(Debug)]
+pub struct ApiKey {
+    pub name: String,
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_day: Option<u64>,
+    usage: Mutex<Usage>,
+}
+
+#[derive(Debug)]
+struct Usage {
+    /// Requests the key may still make before it has to wait
+    allowance: f64,
+    refilled: Instant,
+    day: NaiveDate,
+    tokens_today: u64,
+}
+
+impl ApiKey {
+    pub fn new(name: &str, requests_per_minute: Option<u32>, tokens_per_day: Option<u64>) -> Self {
+        Self {
+            name: name.to_string(),
+            requests_per_minute,
+            tokens_per_day,
+            usage: Mutex::new(Usage {
+                allowance: requests_per_minute.unwrap_or(0) as f64,
+                refilled: Instant::now(),
+                day: Utc::now().date_naive(),
+                tokens_today: 0,
+            }),
+        }
+    }
+
+    /// Take one request from the key's allowance, failing with
+    /// `RATE_LIMITED` when a limit is used up
+    pub fn admit(&self, now: Instant, utc: DateTime<Utc>) -> Result<(), ShimmyError> {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        usage.roll_over(utc.date_naive());
+
+        if let Some(quota) = self.tokens_per_day {
+            if usage.tokens_today >= quota {
+                let midnight = (usage.day + chrono::Days::new(1))
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap_or_default()
+                    .and_utc();
+                let wait = (midnight - utc).num_seconds().max(1) as u64;
+                return Err(self.limited("tokens/day", wait));
+            }
+        }
+
+        if let Some(rpm) = self.requests_per_minute {
+            let per_second = rpm as f64 / 60.0;
+            let elapsed = now.saturating_duration_since(usage.refilled).as_secs_f64();
+            usage.allowance = (usage.allowance + elapsed * per_second).min(rpm as f64);
+            usage.refilled = now;
+            if usage.allowance < 1.0 {
+                let wait = ((1.0 - usage.allowance) / per_second).ceil().max(1.0) as u64;
+                return Err(self.limited("requests/minute", wait));
+            }
+            usage.allowance -= 1.0;
+        }
+        Ok(())
+    }
+
+    /// Count tokens a request used against today's quota
+    pub fn record_tokens(&self, tokens: u64) {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        usage.roll_over(Utc::now().date_naive());
+        usage.tokens_today = usage.tokens_today.saturating_add(tokens);
+    }
+
+    /// Tokens used so far today
+    pub fn tokens_today(&self) -> u64 {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        usage.roll_over(Utc::now().date_naive());
+        usage.tokens_today
+    }
+
+    fn limited(&self, limit: &str, retry_after_secs: u64) -> ShimmyError {
+        ShimmyError::RateLimited {
+            key: self.name.clone(),
+            limit: limit.to_string(),
+            retry_after_secs,
+        }
+    }
+}
+
+impl Usage {
+    fn roll_over(&mut self, today: NaiveDate) {
+        if today != self.day {
+            self.day = today;
+            self.tokens_today = 0;
+        }
+    }
+}
+
+/// Keys from `SHIMMY_API_KEYS_FILE`; empty leaves the API open
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    /// Looked up by SHA-256 so comparing a guess takes the same time whatever
+    /// it shares with a real key
+    keys: HashMap<[u8; 32], Arc<ApiKey>>,
+}
+
+impl ApiKeys {
+    pub fn parse(json: &str) -> Result<Self> {
+        let file: KeysFile = serde_json::from_str(json)?;
+        if file.keys.is_empty() {
+            bail!("no keys listed");
+        }
+        let mut keys = HashMap::new();
+        for entry in file.keys {
+            let secret = entry.key.trim();
+            if entry.name.trim().is_empty() || secret.is_empty() {
+                bail!("every key needs a name and a key");
+            }
+            let requests_per_minute = entry.requests_per_minute.or(file.requests_per_minute);
+            let tokens_per_day = entry.tokens_per_day.or(file.tokens_per_day);
+            if requests_per_minute == Some(0) || tokens_per_day == Some(0) {
+                bail!("limits for `{}` must be above 0", entry.name);
+            }
+            let key = ApiKey::new(entry.name.trim(), requests_per_minute, tokens_per_day);
+            if keys.insert(digest(secret), Arc::new(key)).is_some() {
+                bail!("`{}` reuses another entry's key", entry.name);
+            }
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("reading {} {}", API_KEYS_FILE_ENV, path.display()))?;
+        Self::parse(&json)
+            .with_context(|| format!("invalid {} {}", API_KEYS_FILE_ENV, path.display()))
+    }
+
+    /// Keys from `SHIMMY_API_KEYS_FILE`, or none when it is unset
+    pub fn from_env() -> Result<Self> {
+        match std::env::var_os(API_KEYS_FILE_ENV).filter(|p| !p.is_empty()) {
+            Some(path) => Self::load(Path::new(&path)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// The key a request presents, if it is one of ours
+    pub fn authenticate(&self, headers: &HeaderMap) -> Option<Arc<ApiKey>> {
+        let presented = bearer_token(headers)?;
+        self.keys.get(&digest(presented)).cloned()
+    }
+}
+
+/// Whether `route` is usable without a key
+pub fn is_open_route(route: &str) -> bool {
+    OPEN_ROUTES.contains(&route)
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let token = match header("authorization") {
+        Some(value) => {
+            let (scheme, token) = value.trim().split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then_some(token)?
+        }
+        None => header("x-api-key")?,
+    };
+    Some(token.trim()).filter(|t| !t.is_empty())
+}
+
+fn digest(secret: &str) -> [u8; 32] {
+    Sha256::digest(secret.as_bytes()).into()
+}
+
+/// Run `f` on behalf of `key`
+pub async fn scope<F: std::future::Future>(key: Arc<ApiKey>, f: F) -> F::Output {
+    CURRENT_KEY.scope(key, f).await
+}
+
+/// The key the current request authenticated with, if any
+pub fn current() -> Option<Arc<ApiKey>> {
+    CURRENT_KEY.try_with(Arc::clone).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const KEYS: &str = r#"{
+        "requests_per_minute": 2,
+        "keys": [
+            { "name": "alice", "key": "sk-alice", "tokens_per_day": 100 },
+            { "name": "ci", "key": "sk-ci", "requests_per_minute": 600 }
+        ]
+    }"#;
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_keys_file_and_authentication() {
+        let keys = ApiKeys::parse(KEYS).unwrap();
+        assert_eq!(keys.len(), 2);
+
+        let alice = keys
+            .authenticate(&headers("authorization", "Bearer sk-alice"))
+            .unwrap();
+        assert_eq!(alice.name, "alice");
+        assert_eq!(alice.requests_per_minute, Some(2));
+        assert_eq!(alice.tokens_per_day, Some(100));
+        let ci = keys.authenticate(&headers("x-api-key", "sk-ci")).unwrap();
+        assert_eq!(ci.requests_per_minute, Some(600));
+        assert_eq!(ci.tokens_per_day, None);
+
+        assert!(keys
+            .authenticate(&headers("authorization", "Bearer sk-bob"))
+            .is_none());
+        assert!(keys
+            .authenticate(&headers("authorization", "Basic sk-alice"))
+            .is_none());
+        assert!(keys.authenticate(&HeaderMap::new()).is_none());
+        assert!(ApiKeys::default().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_keys_files_are_rejected() {
+        for bad in [
+            r#"{"keys": []}"#,
+            r#"{"keys": [{"name": "a", "key": " "}]}"#,
+            r#"{"keys": [{"name": "a", "key": "k", "requests_per_minute": 0}]}"#,
+            r#"{"keys": [{"name": "a", "key": "k"}, {"name": "b", "key": "k"}]}"#,
+            r#"{"keys": [{"name": "a", "key": "k", "rpm": 5}]}"#,
+        ] {
+            assert!(ApiKeys::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_requests_per_minute_refill() {
+        let key = ApiKey::new("alice", Some(2), None);
+        let start = Instant::now();
+        let utc = Utc::now();
+        assert!(key.admit(start, utc).is_ok());
+        assert!(key.admit(start, utc).is_ok());
+        match key.admit(start, utc) {
+            Err(ShimmyError::RateLimited {
+                limit,
+                retry_after_secs,
+                ..
+            }) => {
+                assert_eq!(limit, "requests/minute");
+                assert_eq!(retry_after_secs, 30);
+            }
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+        assert!(key.admit(start + Duration::from_secs(30), utc).is_ok());
+        assert!(key.admit(start + Duration::from_secs(30), utc).is_err());
+    }
+
+    #[test]
+    fn test_tokens_per_day_resets_at_midnight() {
+        let key = ApiKey::new("alice", None, Some(100));
+        let now = Instant::now();
+        let utc = Utc::now();
+        key.record_tokens(60);
+        assert!(key.admit(now, utc).is_ok());
+        key.record_tokens(60);
+        assert_eq!(key.tokens_today(), 120);
+        let Err(ShimmyError::RateLimited {
+            retry_after_secs, ..
+        }) = key.admit(now, utc)
+        else {
+            panic!("expected the daily quota to be used up");
+        };
+        assert!(retry_after_secs <= 24 * 60 * 60);
+
+        let tomorrow = utc + chrono::Duration::days(1);
+        assert!(key.admit(now, tomorrow).is_ok());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::invariant_ppt::shimmy_invariants;
+use crate::model_store::{LayerKind, ModelStore};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -9,6 +10,8 @@ pub struct DiscoveredModel {
     pub name: String,
     pub path: PathBuf,
     pub lora_path: Option<PathBuf>,
+    /// Multimodal projector found next to the model or in its manifest
+    pub mmproj_path: Option<PathBuf>,
     pub size_bytes: u64,
     pub model_type: String,
     pub parameter_count: Option<String>,
@@ -51,6 +54,73 @@ pub struct ModelAutoDiscovery {
     pub search_paths: Vec<PathBuf>,
 }
 
+/// Set to `1` (or pass `--no-app-models`) to skip other apps' model folders
+pub const NO_APP_MODELS_ENV: &str = "SHIMMY_NO_APP_MODELS";
+
+/// Model folders of other desktop apps (LM Studio, GPT4All)
+///
+/// Each app's configured download location is honored alongside its default.
+pub fn app_model_dirs() -> Vec<PathBuf> {
+    let mut dirs_found = Vec::new();
+    let home = dirs::home_dir();
+
+    // LM Studio: ~/.lmstudio (0.3+) or ~/.cache/lm-studio (older releases)
+    if let Some(home) = &home {
+        for app_dir in [
+            home.join(".lmstudio"),
+            home.join(".cache").join("lm-studio"),
+        ] {
+            if let Some(folder) = fs::read_to_string(app_dir.join("settings.json"))
+                .ok()
+                .and_then(|s| lm_studio_downloads_folder(&s))
+            {
+                dirs_found.push(folder);
+            }
+            dirs_found.push(app_dir.join("models"));
+        }
+    }
+
+    // GPT4All keeps Qt ini settings under ~/.config on Unix and %APPDATA% on Windows
+    let ini_paths = home
+        .iter()
+        .map(|h| h.join(".config"))
+        .chain(dirs::config_dir())
+        .map(|d| d.join("nomic.ai").join("GPT4All.ini"));
+    for ini in ini_paths {
+        if let Some(folder) = fs::read_to_string(ini)
+            .ok()
+            .and_then(|s| gpt4all_model_path(&s))
+        {
+            dirs_found.push(folder);
+        }
+    }
+    if let Some(data) = dirs::data_local_dir() {
+        dirs_found.push(data.join("nomic.ai").join("GPT4All"));
+    }
+
+    dirs_found.dedup();
+    dirs_found
+}
+
+/// `downloadsFolder` from LM Studio's settings.json
+fn lm_studio_downloads_folder(settings: &str) -> Option<PathBuf> {
+    let value: serde_json::Value = serde_json::from_str(settings).ok()?;
+    let folder = value.get("downloadsFolder")?.as_str()?;
+    (!folder.is_empty()).then(|| PathBuf::from(folder))
+}
+
+/// `modelPath` from GPT4All.ini
+fn gpt4all_model_path(ini: &str) -> Option<PathBuf> {
+    ini.lines().find_map(|line| {
+        let value = line
+            .trim()
+            .strip_prefix("modelPath=")?
+            .trim()
+            .trim_matches('"');
+        (!value.is_empty()).then(|| PathBuf::from(value))
+    })
+}
+
 impl ModelAutoDiscovery {
     pub fn new() -> Self {
         let mut search_paths = vec![PathBuf::from("./models")];
@@ -79,7 +149,6 @@ impl ModelAutoDiscovery {
         if let Some(home) = std::env::var_os("HOME") {
             search_paths.push(PathBuf::from(home.clone()).join(".cache/huggingface/hub"));
             search_paths.push(PathBuf::from(home.clone()).join(".ollama/models"));
-            search_paths.push(PathBuf::from(home.clone()).join(".lmstudio/models"));
             search_paths.push(PathBuf::from(home.clone()).join("models"));
             search_paths.push(PathBuf::from(home).join(".local/share/shimmy/models"));
         }
@@ -88,7 +157,6 @@ impl ModelAutoDiscovery {
             // Focus on likely GGUF model locations
             search_paths.push(PathBuf::from(user_profile.clone()).join(".cache\\huggingface\\hub"));
             search_paths.push(PathBuf::from(user_profile.clone()).join(".ollama\\models"));
-            search_paths.push(PathBuf::from(user_profile.clone()).join(".lmstudio\\models"));
             search_paths.push(PathBuf::from(user_profile.clone()).join("models"));
             search_paths
                 .push(PathBuf::from(user_profile.clone()).join("AppData\\Local\\shimmy\\models"));
@@ -117,6 +185,11 @@ impl ModelAutoDiscovery {
             }
         }
 
+        // Models already downloaded by LM Studio or GPT4All, unless opted out
+        if std::env::var(NO_APP_MODELS_ENV).map_or(true, |v| v != "1") {
+            search_paths.extend(app_model_dirs());
+        }
+
         Self { search_paths }
     }
 
@@ -147,9 +220,16 @@ impl ModelAutoDiscovery {
             Err(e) => eprintln!("Warning: Failed to discover Ollama models: {}", e),
         }
 
-        // Remove duplicates based on file hash or path
-        discovered.sort_by(|a, b| a.path.cmp(&b.path));
-        discovered.dedup_by(|a, b| a.path == b.path);
+        // Models named in the shared content-addressable store
+        match self.discover_store_models(&ModelStore::open_default()) {
+            Ok(store_models) => discovered.extend(store_models),
+            Err(e) => eprintln!("Warning: Failed to read the model store: {}", e),
+        }
+
+        // Remove duplicates based on file hash or path (store aliases share a
+        // path but keep their own names)
+        discovered.sort_by(|a, b| a.path.cmp(&b.path).then_with(|| a.name.cmp(&b.name)));
+        discovered.dedup_by(|a, b| a.path == b.path && a.name == b.name);
 
         // PPT Invariant: Validate discovery results before returning
         shimmy_invariants::assert_discovery_valid(discovered.len());
@@ -313,7 +393,28 @@ impl ModelAutoDiscovery {
         }
 
         // Create grouped model entries for sharded models
-        for (group_key, files) in shard_groups {
+        for (group_key, mut files) in shard_groups {
+            // Split GGUFs load from their first shard, so register that path
+            // and only when the whole set is on disk
+            if group_key.ends_with(".gguf") {
+                files.sort();
+                let first = &files[0];
+                let shards = match crate::engine::gguf::resolve_split(first) {
+                    Ok(shards) => shards,
+                    Err(e) => {
+                        tracing::warn!("Skipping incomplete split model {}: {}", group_key, e);
+                        continue;
+                    }
+                };
+                if let Ok(mut model) = self.analyze_model_file(&shards[0]) {
+                    model.name = self.generate_model_name(&group_key);
+                    model.size_bytes =
+                        crate::engine::gguf::total_size(&shards[0]).unwrap_or(model.size_bytes);
+                    grouped_models.push(model);
+                }
+                continue;
+            }
+
             if files.len() > 1 {
                 // Calculate total size
                 let total_size: u64 = files
@@ -362,6 +463,7 @@ impl ModelAutoDiscovery {
                     name: model_name,
                     path: descriptive_path,
                     lora_path,
+                    mmproj_path: self.find_projector_for_model(first_file),
                     size_bytes: total_size,
                     model_type: backend_type,
                     parameter_count,
@@ -385,9 +487,10 @@ impl ModelAutoDiscovery {
     fn is_model_file(&self, path: &Path) -> bool {
         if let Some(extension) = path.extension() {
             let ext = extension.to_string_lossy().to_lowercase();
-            // Accept GGUF files (primary format)
+            // Accept GGUF files (primary format), except vision projectors,
+            // which are paired with the model they belong to
             if ext == "gguf" {
-                return true;
+                return !self.is_projector_file(path);
             }
             // Accept SafeTensors files (native Rust support - no Python needed!)
             if ext == "safetensors" {
@@ -463,6 +566,48 @@ impl ModelAutoDiscovery {
         None
     }
 
+    fn is_projector_file(&self, path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gguf"))
+            && path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.to_lowercase().contains("mmproj"))
+    }
+
+    /// The `mmproj` projector next to a vision model: the only one in its
+    /// directory, or the one named after the model when there are several
+    pub fn find_projector_for_model(&self, model_path: &Path) -> Option<PathBuf> {
+        let model_dir = model_path.parent()?;
+        let model_stem = model_path.file_stem()?.to_str()?.to_lowercase();
+
+        let mut projectors: Vec<PathBuf> = fs::read_dir(model_dir)
+            .ok()?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| self.is_projector_file(path))
+            .collect();
+        if projectors.len() <= 1 {
+            return projectors.pop();
+        }
+        projectors.into_iter().find(|path| {
+            let stem = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("")
+                .to_lowercase();
+            // `mmproj-Qwen2-VL-7B-Instruct-f16` belongs to
+            // `Qwen2-VL-7B-Instruct-Q4_K_M`
+            let base = stem
+                .replace("mmproj", "")
+                .trim_matches(|c: char| c == '-' || c == '_' || c == '.')
+                .trim_end_matches("-f16")
+                .trim_end_matches("-f32")
+                .to_string();
+            !base.is_empty() && model_stem.starts_with(&base)
+        })
+    }
+
     fn analyze_model_file(&self, path: &Path) -> Result<DiscoveredModel> {
         let metadata = fs::metadata(path)?;
         let filename = path
@@ -491,6 +636,7 @@ impl ModelAutoDiscovery {
             name,
             path: path.to_path_buf(),
             lora_path,
+            mmproj_path: self.find_projector_for_model(path),
             size_bytes: metadata.len(),
             model_type: backend_type,
             parameter_count,
@@ -549,15 +695,45 @@ impl ModelAutoDiscovery {
     }
 
     fn generate_model_name(&self, filename: &str) -> String {
-        // Remove file extension
-        let name = if let Some(pos) = filename.rfind('.') {
-            &filename[..pos]
-        } else {
-            filename
-        };
+        crate::model_names::from_file_name(filename)
+    }
 
-        // Replace common separators with dashes
-        name.replace("_", "-").replace(" ", "-").to_lowercase()
+    /// One model per store manifest that has a model layer on disk
+    fn discover_store_models(&self, store: &ModelStore) -> Result<Vec<DiscoveredModel>> {
+        let mut models = Vec::new();
+        for manifest in store.manifests()? {
+            let Some(layer) = manifest.layer(LayerKind::Model) else {
+                continue;
+            };
+            let path = store.blob_path(layer);
+            if !path.exists() {
+                continue;
+            }
+            let (model_type, parameter_count, quantization) = self.parse_filename(&layer.file_name);
+            // Same PPT invariant as `analyze_model_file`: GGUF always uses Llama
+            let model_type = if path.extension().and_then(|s| s.to_str()) == Some("gguf") {
+                "Llama".to_string()
+            } else {
+                model_type
+            };
+            models.push(DiscoveredModel {
+                name: manifest.name.clone(),
+                lora_path: manifest
+                    .layer(LayerKind::Lora)
+                    .map(|l| store.blob_path(l))
+                    .filter(|p| p.exists()),
+                mmproj_path: manifest
+                    .layer(LayerKind::Projector)
+                    .map(|l| store.blob_path(l))
+                    .filter(|p| p.exists()),
+                path,
+                size_bytes: layer.size,
+                model_type,
+                parameter_count,
+                quantization,
+            });
+        }
+        Ok(models)
     }
 
     fn discover_ollama_models(&self) -> Result<Vec<DiscoveredModel>> {
@@ -681,6 +857,8 @@ impl ModelAutoDiscovery {
                                             name: display_name,
                                             path: blob_path,
                                             lora_path: None,
+                                            mmproj_path: self
+                                                .find_ollama_projector(&manifest, blobs_dir),
                                             size_bytes: layer.size as u64,
                                             model_type: "Ollama".to_string(),
                                             parameter_count: None,
@@ -780,6 +958,21 @@ impl ModelAutoDiscovery {
         Ok(models)
     }
 
+    /// The projector blob an Ollama vision model's manifest lists
+    fn find_ollama_projector(
+        &self,
+        manifest: &OllamaManifest,
+        blobs_dir: &Path,
+    ) -> Option<PathBuf> {
+        manifest
+            .layers
+            .iter()
+            .filter(|layer| layer.media_type == "application/vnd.ollama.image.projector")
+            .filter_map(|layer| layer.digest.strip_prefix("sha256:"))
+            .map(|hash| blobs_dir.join(format!("sha256-{}", hash)))
+            .find(|path| path.exists())
+    }
+
     fn is_gguf_blob(&self, path: &Path) -> Result<bool> {
         let mut file = std::fs::File::open(path)?;
         let mut buffer = [0u8; 4];
@@ -805,6 +998,7 @@ mod tests {
             name: "test".to_string(),
             path: PathBuf::from("/test"),
             lora_path: None,
+            mmproj_path: None,
             size_bytes: 1024,
             model_type: "Llama".to_string(),
             parameter_count: Some("7B".to_string()),
@@ -828,4 +1022,104 @@ mod tests {
         assert_eq!(params, Some("7B".to_string()));
         assert_eq!(quant, Some("Q4_K_M".to_string()));
     }
+
+    #[test]
+    fn test_store_aliases_are_discovered_by_name() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = ModelStore::new(temp.path().join("store"));
+        let source = temp.path().join("phi-3b-q4_k_m.gguf");
+        std::fs::write(&source, b"GGUF").unwrap();
+        let layer = store.import_file(&source, LayerKind::Model).unwrap();
+        store.write_manifest("phi", vec![layer]).unwrap();
+        store.alias("phi", "assistant").unwrap();
+
+        let discovery = ModelAutoDiscovery::new();
+        let models = discovery.discover_store_models(&store).unwrap();
+        let names: Vec<_> = models.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["assistant", "phi"]);
+        assert_eq!(models[0].path, models[1].path);
+        assert_eq!(models[0].model_type, "Llama");
+        assert_eq!(models[0].quantization.as_deref(), Some("Q4_K_M"));
+    }
+
+    #[test]
+    fn test_split_gguf_registers_first_shard() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("models");
+        std::fs::create_dir(&dir).unwrap();
+        for i in [2, 1, 3] {
+            let name = format!("Llama-3.1-70B-Q4_K_M-0000{i}-of-00003.gguf");
+            std::fs::write(dir.join(name), vec![0u8; 100]).unwrap();
+        }
+        // Only one of two shards downloaded
+        std::fs::write(dir.join("qwen-00001-of-00002.gguf"), b"partial").unwrap();
+
+        let discovery = ModelAutoDiscovery::new();
+        let models = discovery.scan_directory(&dir).unwrap();
+        assert_eq!(models.len(), 1);
+        let model = &models[0];
+        assert_eq!(model.name, "llama-3.1-70b-q4-k-m");
+        assert_eq!(
+            model.path,
+            dir.join("Llama-3.1-70B-Q4_K_M-00001-of-00003.gguf")
+        );
+        assert_eq!(model.size_bytes, 300);
+        assert_eq!(model.model_type, "Llama");
+    }
+
+    #[test]
+    fn test_projectors_pair_with_their_models() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("models");
+        std::fs::create_dir(&dir).unwrap();
+        for name in [
+            "Qwen2-VL-7B-Instruct-Q4_K_M.gguf",
+            "mmproj-Qwen2-VL-7B-Instruct-f16.gguf",
+            "llava-v1.6-Q4_K_M.gguf",
+            "mmproj-llava-v1.6-f16.gguf",
+        ] {
+            std::fs::write(dir.join(name), b"GGUF").unwrap();
+        }
+
+        let discovery = ModelAutoDiscovery::new();
+        let mut models = discovery.scan_directory(&dir).unwrap();
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        let paired: Vec<_> = models
+            .iter()
+            .map(|m| (m.name.as_str(), m.mmproj_path.clone()))
+            .collect();
+        assert_eq!(
+            paired,
+            [
+                (
+                    "llava-v1.6-q4-k-m",
+                    Some(dir.join("mmproj-llava-v1.6-f16.gguf"))
+                ),
+                (
+                    "qwen2-vl-7b-instruct-q4-k-m",
+                    Some(dir.join("mmproj-Qwen2-VL-7B-Instruct-f16.gguf"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_app_settings_parsing() {
+        assert_eq!(
+            lm_studio_downloads_folder(r#"{"downloadsFolder": "/data/lmstudio", "theme": "dark"}"#),
+            Some(PathBuf::from("/data/lmstudio"))
+        );
+        assert_eq!(
+            lm_studio_downloads_folder(r#"{"downloadsFolder": ""}"#),
+            None
+        );
+        assert_eq!(lm_studio_downloads_folder("not json"), None);
+
+        let ini = "[General]\nfontSize=Small\nmodelPath=/mnt/models/gpt4all/\n";
+        assert_eq!(
+            gpt4all_model_path(ini),
+            Some(PathBuf::from("/mnt/models/gpt4all/"))
+        );
+        assert_eq!(gpt4all_model_path("[General]\nmodelPath=\n"), None);
+    }
 }
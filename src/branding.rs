@@ -0,0 +1,66 @@
+//! Build-time branding for OEM builds.
+//!
+//! Products that embed shimmy can ship a branded binary without maintaining a
+//! fork by setting these variables when compiling, e.g.
+//! `SHIMMY_OEM_PRODUCT_NAME=Acme SHIMMY_OEM_APP_NAME=acme cargo build --release`.
+//! Anything left unset keeps the stock value.
+//!
+//! - `SHIMMY_OEM_PRODUCT_NAME`: name in banners and `--help` (`Shimmy`)
+//! - `SHIMMY_OEM_APP_NAME`: command name, and the directory used under the
+//!   platform config and data directories (`shimmy`)
+//! - `SHIMMY_OEM_DATA_DIR`: default data directory; `SHIMMY_DATA_DIR` (or
+//!   `data_dir` in `shimmy.toml`) still takes precedence at runtime
+//! - `SHIMMY_OEM_KEYGEN_ACCOUNT_ID` / `SHIMMY_OEM_KEYGEN_PUBLIC_KEY`: the
+//!   Keygen account vision licenses are validated against (set both, or
+//!   neither; the build fails otherwise)
+//! - `SHIMMY_OEM_RELEASES_URL`: release feed checked by `upgrade`
+//! - `SHIMMY_OEM_TELEMETRY_URL`: default upload endpoint for usage telemetry
+//!
+//! These are compile-time only on purpose: a runtime override of the license
+//! account would let anyone point validation at their own Keygen account.
+
+/// `value` if the variable was set at build time, otherwise `default`
+pub const fn or_default(value: Option<&'static str>, default: &'static str) -> &'static str {
+    match value {
+        Some(value) => value,
+        None => default,
+    }
+}
+
+/// Product name shown to users
+pub const PRODUCT_NAME: &str = or_default(option_env!("SHIMMY_OEM_PRODUCT_NAME"), "Shimmy");
+
+/// Command name and per-user directory name
+pub const APP_NAME: &str = or_default(option_env!("SHIMMY_OEM_APP_NAME"), "shimmy");
+
+/// One-line description for `--help`
+pub const ABOUT: &str = or_default(
+    option_env!("SHIMMY_OEM_ABOUT"),
+    "Shimmy: single-binary GGUF + LoRA server",
+);
+
+/// Default data directory baked into the build, if any
+pub const DEFAULT_DATA_DIR: Option<&str> = option_env!("SHIMMY_OEM_DATA_DIR");
+
+/// Release feed for `upgrade`
+pub const RELEASES_URL: &str = or_default(
+    option_env!("SHIMMY_OEM_RELEASES_URL"),
+    "https://api.github.com/repos/Michael-A-Kuykendall/shimmy/releases",
+);
+
+/// Default telemetry upload endpoint (`SHIMMY_TELEMETRY_URL` overrides it)
+pub const TELEMETRY_URL: &str = or_default(
+    option_env!("SHIMMY_OEM_TELEMETRY_URL"),
+    "https://metrics.shimmy-ai.dev/v1/usage",
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_or_default_prefers_build_value() {
+        assert_eq!(or_default(Some("Acme"), "Shimmy"), "Acme");
+        assert_eq!(or_default(None, "Shimmy"), "Shimmy");
+    }
+}
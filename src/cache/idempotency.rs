@@ -0,0 +1,292 @@
+//! `Idempotency-Key` support for vision requests.
+//!
+//! A client that times out and retries `/api/vision` would otherwise run the
+//! model again and count against the license's monthly cap twice. A request
+//! sent with `Idempotency-Key: <key>` runs once; repeats of it within
+//! `SHIMMY_IDEMPOTENCY_TTL_SECS` (default 86400) get the original response
+//! back, marked with `Idempotent-Replayed: true`, without touching the model
+//! or the license.
+//!
+//! - the same key with a different body is refused with `422
+//!   IDEMPOTENCY_KEY_REUSED`
+//! - a repeat that arrives while the first request is still running gets
+//!   `409 IDEMPOTENCY_IN_PROGRESS`
+//! - failed requests aren't remembered, so retrying them runs them again
+//!
+//! Keys are scoped to the API key that sent them, so tenants can't read each
+//! other's results by guessing keys.
+
+use crate::error::ShimmyError;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+pub const IDEMPOTENCY_TTL_ENV: &str = "SHIMMY_IDEMPOTENCY_TTL_SECS";
+
+const DEFAULT_TTL_SECS: u64 = 86_400;
+const MAX_ENTRIES: usize = 4096;
+const MAX_KEY_LEN: usize = 255;
+
+#[derive(Debug)]
+enum State {
+    Running,
+    Done {
+        status: StatusCode,
+        body: serde_json::Value,
+    },
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// SHA-256 of the request body
+    fingerprint: [u8; 32],
+    state: State,
+    created: Instant,
+}
+
+#[derive(Debug)]
+pub struct IdempotencyCache {
+    /// In milliseconds, so it can change at runtime
+    ttl_ms: AtomicU64,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+/// What to do with a request
+pub enum Outcome<'a> {
+    /// No `Idempotency-Key`: run it as usual
+    Untracked,
+    /// First sight of the key: run it and [`Claim::complete`] with the result
+    Run(Claim<'a>),
+    /// Seen before: send this instead of running it
+    Replay(Response),
+}
+
+/// A running request's hold on its key; dropped without completing (the
+/// request failed), it frees the key for a retry
+pub struct Claim<'a> {
+    cache: &'a IdempotencyCache,
+    key: String,
+    done: bool,
+}
+
+impl IdempotencyCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl_ms: AtomicU64::new(ttl.as_millis() as u64),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn from_env() -> Self {
+        let secs = std::env::var(IDEMPOTENCY_TTL_ENV)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_TTL_SECS);
+        Self::new(Duration::from_secs(secs))
+    }
+
+    pub fn global() -> &'static IdempotencyCache {
+        static GLOBAL: OnceLock<IdempotencyCache> = OnceLock::new();
+        GLOBAL.get_or_init(IdempotencyCache::from_env)
+    }
+
+    /// How long completed responses are replayed
+    pub fn ttl(&self) -> Duration {
+        Duration::from_millis(self.ttl_ms.load(Ordering::Relaxed))
+    }
+
+    /// Change how long completed responses are replayed; `0` stops tracking
+    /// new keys
+    pub fn set_ttl(&self, ttl: Duration) {
+        self.ttl_ms.store(ttl.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Look up the request's `Idempotency-Key`, claiming it when it's new
+    pub fn begin(
+        &self,
+        headers: &HeaderMap,
+        body: &impl Serialize,
+        now: Instant,
+    ) -> Result<Outcome<'_>, ShimmyError> {
+        let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+            return Ok(Outcome::Untracked);
+        };
+        let key = value
+            .to_str()
+            .ok()
+            .map(str::trim)
+            .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+            .ok_or_else(|| ShimmyError::InvalidRequest {
+                reason: format!(
+                    "Idempotency-Key must be 1 to {} printable ASCII characters",
+                    MAX_KEY_LEN
+                ),
+            })?;
+        let ttl = self.ttl();
+        if ttl.is_zero() {
+            return Ok(Outcome::Untracked);
+        }
+        let scope = crate::api_keys::current()
+            .map(|api_key| api_key.name.clone())
+            .unwrap_or_default();
+        let scoped = format!("{}\0{}", scope, key);
+        let fingerprint: [u8; 32] =
+            Sha256::digest(serde_json::to_vec(body).unwrap_or_default()).into();
+
+        let mut entries = self.lock();
+        entries.retain(|_, entry| now.saturating_duration_since(entry.created) < ttl);
+        if let Some(entry) = entries.get(&scoped) {
+            if entry.fingerprint != fingerprint {
+                return Err(ShimmyError::IdempotencyKeyReused {
+                    key: key.to_string(),
+                });
+            }
+            return match &entry.state {
+                State::Running => Err(ShimmyError::IdempotencyInProgress {
+                    key: key.to_string(),
+                }),
+                State::Done { status, body } => {
+                    let mut response = (*status, Json(body.clone())).into_response();
+                    response
+                        .headers_mut()
+                        .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+                    Ok(Outcome::Replay(response))
+                }
+            };
+        }
+        if entries.len() >= MAX_ENTRIES {
+            let oldest = entries
+                .iter()
+                .filter(|(_, entry)| matches!(entry.state, State::Done { .. }))
+                .min_by_key(|(_, entry)| entry.created)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            scoped.clone(),
+            Entry {
+                fingerprint,
+                state: State::Running,
+                created: now,
+            },
+        );
+        Ok(Outcome::Run(Claim {
+            cache: self,
+            key: scoped,
+            done: false,
+        }))
+    }
+}
+
+impl Claim<'_> {
+    /// Remember the response to replay to repeats of the request
+    pub fn complete(mut self, status: StatusCode, body: &impl Serialize) {
+        let Ok(body) = serde_json::to_value(body) else {
+            return;
+        };
+        if let Some(entry) = self.cache.lock().get_mut(&self.key) {
+            entry.state = State::Done { status, body };
+            self.done = true;
+        }
+    }
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut entries = self.cache.lock();
+        if matches!(
+            entries.get(&self.key),
+            Some(Entry {
+                state: State::Running,
+                ..
+            })
+        ) {
+            entries.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, key.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_repeats_replay_the_first_response() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60));
+        let now = Instant::now();
+        let body = serde_json::json!({"image_base64": "aGk=", "mode": "ocr"});
+        assert!(matches!(
+            cache.begin(&HeaderMap::new(), &body, now),
+            Ok(Outcome::Untracked)
+        ));
+
+        let Ok(Outcome::Run(claim)) = cache.begin(&headers("k1"), &body, now) else {
+            panic!("first request should run");
+        };
+        assert!(matches!(
+            cache.begin(&headers("k1"), &body, now),
+            Err(ShimmyError::IdempotencyInProgress { .. })
+        ));
+        claim.complete(StatusCode::OK, &serde_json::json!({"text_blocks": []}));
+
+        let Ok(Outcome::Replay(response)) = cache.begin(&headers("k1"), &body, now) else {
+            panic!("repeat should replay");
+        };
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[REPLAYED_HEADER], "true");
+
+        let other = serde_json::json!({"image_base64": "aGk=", "mode": "full"});
+        assert!(matches!(
+            cache.begin(&headers("k1"), &other, now),
+            Err(ShimmyError::IdempotencyKeyReused { .. })
+        ));
+
+        // Expired keys run again
+        let later = now + Duration::from_secs(61);
+        assert!(matches!(
+            cache.begin(&headers("k1"), &other, later),
+            Ok(Outcome::Run(_))
+        ));
+    }
+
+    #[test]
+    fn test_failed_requests_free_the_key() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60));
+        let now = Instant::now();
+        let body = serde_json::json!({"url": "https://example.com"});
+        let Ok(Outcome::Run(claim)) = cache.begin(&headers("retry-me"), &body, now) else {
+            panic!("first request should run");
+        };
+        drop(claim);
+        assert!(matches!(
+            cache.begin(&headers("retry-me"), &body, now),
+            Ok(Outcome::Run(_))
+        ));
+
+        assert!(cache.begin(&headers(" "), &body, now).is_err());
+        assert!(cache.begin(&headers(&"k".repeat(256)), &body, now).is_err());
+    }
+}
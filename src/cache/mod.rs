@@ -1,5 +1,9 @@
 // Response caching for identical inference requests
 
+#[cfg(feature = "vision")]
+pub mod idempotency;
 pub mod response_cache;
+#[cfg(feature = "vision")]
+pub mod url_cache;
 
 pub use response_cache::ResponseCache;
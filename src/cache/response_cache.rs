@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use tokio::sync::RwLock;
@@ -108,6 +109,8 @@ impl CachedResponse {
 pub struct ResponseCache {
     cache: Arc<RwLock<HashMap<CacheKey, CachedResponse>>>,
     config: ResponseCacheConfig,
+    /// `config.default_ttl` in milliseconds, changeable at runtime
+    ttl_ms: Arc<AtomicU64>,
     stats: Arc<RwLock<CacheStats>>,
 }
 
@@ -145,11 +148,22 @@ impl ResponseCache {
     pub fn with_config(config: ResponseCacheConfig) -> Self {
         Self {
             cache: Arc::new(RwLock::new(HashMap::new())),
+            ttl_ms: Arc::new(AtomicU64::new(config.default_ttl.as_millis() as u64)),
             config,
             stats: Arc::new(RwLock::new(CacheStats::default())),
         }
     }
 
+    /// How long entries stay valid
+    pub fn ttl(&self) -> Duration {
+        Duration::from_millis(self.ttl_ms.load(Ordering::Relaxed))
+    }
+
+    /// Change how long entries stay valid, including ones already cached
+    pub fn set_ttl(&self, ttl: Duration) {
+        self.ttl_ms.store(ttl.as_millis() as u64, Ordering::Relaxed);
+    }
+
     /// Get cached response if available and not expired
     pub async fn get(&self, key: &CacheKey) -> Option<String> {
         if !self.config.enabled {
@@ -160,7 +174,7 @@ impl ResponseCache {
         let mut stats = self.stats.write().await;
 
         if let Some(entry) = cache.get_mut(key) {
-            if entry.is_expired(self.config.default_ttl) {
+            if entry.is_expired(self.ttl()) {
                 // Remove expired entry
                 cache.remove(key);
                 stats.misses += 1;
@@ -240,7 +254,8 @@ impl ResponseCache {
         let initial_count = cache.len();
         let initial_size = stats.total_size_bytes;
 
-        cache.retain(|_, entry| !entry.is_expired(self.config.default_ttl));
+        let ttl = self.ttl();
+        cache.retain(|_, entry| !entry.is_expired(ttl));
 
         // Recalculate size
         stats.total_size_bytes = cache.values().map(|e| e.size_bytes).sum();
@@ -300,6 +315,7 @@ impl Clone for ResponseCache {
         Self {
             cache: self.cache.clone(),
             config: self.config.clone(),
+            ttl_ms: self.ttl_ms.clone(),
             stats: self.stats.clone(),
         }
     }
@@ -0,0 +1,280 @@
+//! Cache of images fetched for vision URL requests, and of their analyses.
+//!
+//! Repeated analyses of the same remote image revalidate it with
+//! `If-None-Match` / `If-Modified-Since` instead of downloading it again; a
+//! `304 Not Modified` (or a body with the same SHA-256) keeps the cached
+//! image and any analyses already made of it, so the model isn't run twice
+//! on identical input. Changed content replaces the entry and drops its
+//! analyses.
+//!
+//! - `SHIMMY_VISION_URL_CACHE=0`: disable
+//! - `SHIMMY_VISION_URL_CACHE_ENTRIES`: images kept (default 64)
+//! - `SHIMMY_VISION_URL_CACHE_MB`: total image bytes kept (default 256)
+//!
+//! Entries are keyed by URL and, for authenticated fetches, a hash of the
+//! `url_headers` sent, so one caller's private image is never served to
+//! another.
+
+use crate::vision::VisionResponse;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+pub const URL_CACHE_ENV: &str = "SHIMMY_VISION_URL_CACHE";
+pub const URL_CACHE_ENTRIES_ENV: &str = "SHIMMY_VISION_URL_CACHE_ENTRIES";
+pub const URL_CACHE_MB_ENV: &str = "SHIMMY_VISION_URL_CACHE_MB";
+
+const DEFAULT_MAX_ENTRIES: usize = 64;
+const DEFAULT_MAX_MB: usize = 256;
+
+/// A cached image and the validators to revalidate it with
+#[derive(Debug, Clone)]
+pub struct CachedImage {
+    pub bytes: Arc<Vec<u8>>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Hex SHA-256 of `bytes`
+    pub content_hash: String,
+}
+
+#[derive(Debug)]
+struct Entry {
+    image: CachedImage,
+    /// Analyses of this content, by request options
+    results: HashMap<String, VisionResponse>,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    bytes: usize,
+    clock: u64,
+}
+
+#[derive(Debug)]
+pub struct UrlCache {
+    enabled: bool,
+    max_entries: usize,
+    max_bytes: usize,
+    inner: Mutex<Inner>,
+}
+
+impl UrlCache {
+    pub fn new(enabled: bool, max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            enabled: enabled && max_entries > 0 && max_bytes > 0,
+            max_entries,
+            max_bytes,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    fn from_env() -> Self {
+        let number = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        Self::new(
+            std::env::var(URL_CACHE_ENV)
+                .map(|v| v.trim() != "0")
+                .unwrap_or(true),
+            number(URL_CACHE_ENTRIES_ENV, DEFAULT_MAX_ENTRIES),
+            number(URL_CACHE_MB_ENV, DEFAULT_MAX_MB) * 1024 * 1024,
+        )
+    }
+
+    pub fn global() -> &'static UrlCache {
+        static GLOBAL: OnceLock<UrlCache> = OnceLock::new();
+        GLOBAL.get_or_init(UrlCache::from_env)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The cached image for `key`, to revalidate or reuse
+    pub fn image(&self, key: &str) -> Option<CachedImage> {
+        if !self.enabled {
+            return None;
+        }
+        let mut inner = self.lock();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let entry = inner.entries.get_mut(key)?;
+        entry.last_used = clock;
+        Some(entry.image.clone())
+    }
+
+    /// Store a freshly downloaded image; analyses survive if the content is
+    /// unchanged. Returns the image as cached (or as given, when disabled).
+    pub fn store_image(
+        &self,
+        key: &str,
+        bytes: Vec<u8>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> CachedImage {
+        let image = CachedImage {
+            content_hash: hex::encode(Sha256::digest(&bytes)),
+            bytes: Arc::new(bytes),
+            etag,
+            last_modified,
+        };
+        if !self.enabled || image.bytes.len() > self.max_bytes {
+            return image;
+        }
+
+        let mut inner = self.lock();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let results = match inner.entries.remove(key) {
+            Some(old) => {
+                inner.bytes -= old.image.bytes.len();
+                if old.image.content_hash == image.content_hash {
+                    old.results
+                } else {
+                    HashMap::new()
+                }
+            }
+            None => HashMap::new(),
+        };
+        inner.bytes += image.bytes.len();
+        inner.entries.insert(
+            key.to_string(),
+            Entry {
+                image: image.clone(),
+                results,
+                last_used: clock,
+            },
+        );
+
+        while inner.entries.len() > self.max_entries || inner.bytes > self.max_bytes {
+            let Some(oldest) = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            if let Some(evicted) = inner.entries.remove(&oldest) {
+                inner.bytes -= evicted.image.bytes.len();
+            }
+        }
+        image
+    }
+
+    /// A previous analysis of the image under `key` with the same options
+    pub fn result(&self, key: &str, content_hash: &str, options: &str) -> Option<VisionResponse> {
+        if !self.enabled {
+            return None;
+        }
+        let inner = self.lock();
+        let entry = inner.entries.get(key)?;
+        if entry.image.content_hash != content_hash {
+            return None;
+        }
+        entry.results.get(options).cloned()
+    }
+
+    /// Remember an analysis, unless the cached content changed meanwhile
+    pub fn store_result(
+        &self,
+        key: &str,
+        content_hash: &str,
+        options: &str,
+        response: &VisionResponse,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let mut inner = self.lock();
+        if let Some(entry) = inner.entries.get_mut(key) {
+            if entry.image.content_hash == content_hash {
+                entry.results.insert(options.to_string(), response.clone());
+            }
+        }
+    }
+}
+
+/// Cache key for a URL fetched with optional extra headers
+pub fn url_key(url: &str, headers: Option<&reqwest::header::HeaderMap>) -> String {
+    let Some(headers) = headers.filter(|h| !h.is_empty()) else {
+        return url.to_string();
+    };
+    let mut pairs: Vec<(&str, &[u8])> = headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_bytes()))
+        .collect();
+    pairs.sort();
+    let mut hasher = Sha256::new();
+    for (name, value) in pairs {
+        hasher.update(name.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(value);
+        hasher.update(b"\0");
+    }
+    format!("{}#{}", url, hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revalidated_content_keeps_results() {
+        let cache = UrlCache::new(true, 2, 1024);
+        assert!(cache.image("u").is_none());
+
+        let image = cache.store_image("u", vec![1, 2, 3], Some("\"v1\"".into()), None);
+        assert_eq!(cache.image("u").unwrap().etag.as_deref(), Some("\"v1\""));
+
+        let response: VisionResponse = serde_json::from_value(serde_json::json!({
+            "image_path": null, "url": "u", "mode": "ocr", "text_blocks": [],
+            "layout": {"theme": null, "regions": [], "key_ui_elements": []},
+            "visual": {"background": null, "accent_colors": [], "contrast": null, "description": null},
+            "interaction": {"description": null}, "dom_map": null,
+            "meta": {"model": "m", "backend": "llama.cpp", "duration_ms": 1, "parse_warnings": null},
+            "raw_model_output": null
+        }))
+        .unwrap();
+        cache.store_result("u", &image.content_hash, "ocr", &response);
+        assert!(cache.result("u", &image.content_hash, "ocr").is_some());
+        assert!(cache.result("u", &image.content_hash, "full").is_none());
+
+        // Same bytes again (no validators on the server): analyses survive
+        cache.store_image("u", vec![1, 2, 3], None, None);
+        assert!(cache.result("u", &image.content_hash, "ocr").is_some());
+
+        // New content drops them
+        let changed = cache.store_image("u", vec![4, 5, 6], None, None);
+        assert!(cache.result("u", &changed.content_hash, "ocr").is_none());
+    }
+
+    #[test]
+    fn test_eviction_and_keys() {
+        let cache = UrlCache::new(true, 2, 8);
+        cache.store_image("a", vec![0; 4], None, None);
+        cache.store_image("b", vec![0; 4], None, None);
+        cache.image("a");
+        cache.store_image("c", vec![0; 4], None, None);
+        assert!(cache.image("a").is_some());
+        assert!(cache.image("b").is_none());
+        // Larger than the whole cache: returned but not kept
+        cache.store_image("d", vec![0; 9], None, None);
+        assert!(cache.image("d").is_none());
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(
+            url_key("https://x/img.png", Some(&headers)),
+            "https://x/img.png"
+        );
+        headers.insert("authorization", "Bearer a".parse().unwrap());
+        let a = url_key("https://x/img.png", Some(&headers));
+        headers.insert("authorization", "Bearer b".parse().unwrap());
+        assert_ne!(a, url_key("https://x/img.png", Some(&headers)));
+    }
+}
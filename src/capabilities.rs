@@ -0,0 +1,276 @@
+//! What this binary was built with, and what of it works on this machine.
+//!
+//! A feature compiled in isn't always usable: a CUDA build on a machine
+//! without the NVIDIA driver falls back to the CPU, and web capture needs a
+//! Chrome or Chromium install. The report lists every backend and optional
+//! feature shimmy knows about with whether it was compiled in, the version of
+//! the library behind it, and whether its runtime was found. It is detected
+//! once, printed at `serve` startup (compiled-in entries only), in full by
+//! `shimmy --version --verbose`, and returned by `GET /api/system`.
+
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// One backend or optional feature
+#[derive(Debug, Clone, Serialize)]
+pub struct Capability {
+    pub name: &'static str,
+    pub compiled: bool,
+    /// Version of the library providing it, when the build recorded one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<&'static str>,
+    /// Compiled in and its runtime was found
+    pub usable: bool,
+    /// Why a compiled-in entry isn't usable, or what it relies on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub version: &'static str,
+    pub target: String,
+    pub entries: Vec<Capability>,
+}
+
+impl Capability {
+    fn new(name: &'static str, compiled: bool, version: Option<&'static str>) -> Self {
+        Self {
+            name,
+            compiled,
+            version: version.filter(|_| compiled),
+            usable: compiled,
+            detail: None,
+        }
+    }
+
+    /// Mark a compiled-in entry unusable unless `probe` finds its runtime
+    fn probe(mut self, probe: impl FnOnce() -> bool, missing: &str) -> Self {
+        if self.compiled && !probe() {
+            self.usable = false;
+            self.detail = Some(missing.to_string());
+        }
+        self
+    }
+
+    fn detail(mut self, detail: &str) -> Self {
+        if self.compiled {
+            self.detail = Some(detail.to_string());
+        }
+        self
+    }
+}
+
+impl Capabilities {
+    /// Detected once per process
+    pub fn global() -> &'static Self {
+        static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+        CAPABILITIES.get_or_init(Self::detect)
+    }
+
+    pub fn detect() -> Self {
+        let llama = cfg!(feature = "llama");
+        let llama_version = option_env!("SHIMMY_DEP_LLAMA_CPP");
+        let apple_silicon = cfg!(target_os = "macos") && std::env::consts::ARCH == "aarch64";
+        let entries = vec![
+            Capability::new("llama", llama, llama_version).detail("llama.cpp on the CPU"),
+            Capability::new("cuda", cfg!(feature = "llama-cuda"), llama_version).probe(
+                || runs("nvidia-smi", &[]),
+                "nvidia-smi not found or no NVIDIA driver; llama.cpp falls back to the CPU",
+            ),
+            Capability::new("vulkan", cfg!(feature = "llama-vulkan"), llama_version).probe(
+                || runs("vulkaninfo", &["--summary"]),
+                "vulkaninfo not found or no Vulkan device",
+            ),
+            Capability::new("opencl", cfg!(feature = "llama-opencl"), llama_version).probe(
+                || runs("clinfo", &[]),
+                "clinfo not found or no OpenCL platform",
+            ),
+            Capability::new("metal", llama && cfg!(target_os = "macos"), llama_version),
+            Capability::new("mlx", cfg!(feature = "mlx"), None).probe(
+                || apple_silicon && runs("python3", &["-c", "import mlx.core"]),
+                "needs Apple Silicon and the `mlx` Python package",
+            ),
+            Capability::new("huggingface", cfg!(feature = "huggingface"), None)
+                .probe(|| runs("python3", &["--version"]), "python3 not found"),
+            Capability::new(
+                "vision",
+                cfg!(feature = "vision"),
+                option_env!("SHIMMY_DEP_IMAGE"),
+            )
+            .detail(if cfg!(feature = "vision-codecs") {
+                "PNG, JPEG, WebP, GIF, BMP and TIFF input"
+            } else {
+                "PNG and JPEG input"
+            }),
+            Capability::new(
+                "vision-web",
+                cfg!(feature = "vision-web"),
+                option_env!("SHIMMY_DEP_CHROMIUMOXIDE"),
+            )
+            .probe(browser_installed, "no Chrome or Chromium found"),
+            Capability::new("vision-tesseract", cfg!(feature = "vision-tesseract"), None)
+                .probe(|| runs("tesseract", &["--version"]), "tesseract not found"),
+            Capability::new("vision-video", cfg!(feature = "vision-video"), None)
+                .probe(|| runs("ffmpeg", &["-version"]), "ffmpeg not found"),
+            Capability::new("audio", false, None),
+        ];
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            target: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+            entries,
+        }
+    }
+
+    /// Compiled-in entries on one line, flagging the ones that can't run here
+    pub fn summary(&self) -> String {
+        let compiled: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|c| c.compiled)
+            .map(|c| {
+                let name = match c.version {
+                    Some(version) => format!("{} {}", c.name, version),
+                    None => c.name.to_string(),
+                };
+                if c.usable {
+                    name
+                } else {
+                    format!("{} (unusable)", name)
+                }
+            })
+            .collect();
+        if compiled.is_empty() {
+            "none".to_string()
+        } else {
+            compiled.join(", ")
+        }
+    }
+
+    /// A table of every entry, for `--version --verbose`
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "{} {} ({})\n\n",
+            crate::branding::APP_NAME,
+            self.version,
+            self.target
+        );
+        for c in &self.entries {
+            let status = match (c.compiled, c.usable) {
+                (false, _) => "not compiled",
+                (true, true) => "usable",
+                (true, false) => "unusable",
+            };
+            let mut line = format!(
+                "  {:<12} {:<13} {:<9}",
+                c.name,
+                status,
+                c.version.unwrap_or("")
+            );
+            if let Some(detail) = &c.detail {
+                line.push_str(&format!(" {}", detail));
+            }
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Whether the command line asks for `--version --verbose`
+pub fn verbose_version_requested(args: &[String]) -> bool {
+    args.iter().any(|a| a == "--version" || a == "-V") && args.iter().any(|a| a == "--verbose")
+}
+
+fn runs(program: &str, args: &[&str]) -> bool {
+    std::process::Command::new(program)
+        .args(args)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// A Chrome or Chromium executable on `PATH` or in the usual install location
+fn browser_installed() -> bool {
+    const NAMES: &[&str] = &[
+        "google-chrome",
+        "google-chrome-stable",
+        "chromium",
+        "chromium-browser",
+        "chrome",
+        "chrome.exe",
+    ];
+    const INSTALLS: &[&str] = &[
+        "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+        "/Applications/Chromium.app/Contents/MacOS/Chromium",
+        r"C:\Program Files\Google\Chrome\Application\chrome.exe",
+        r"C:\Program Files (x86)\Google\Chrome\Application\chrome.exe",
+    ];
+    let on_path = std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|dir| NAMES.iter().any(|name| dir.join(name).is_file()))
+    });
+    on_path
+        || INSTALLS
+            .iter()
+            .any(|path| std::path::Path::new(path).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_covers_every_backend() {
+        let report = Capabilities::detect();
+        for name in ["llama", "cuda", "vulkan", "metal", "vision", "audio"] {
+            assert!(report.entries.iter().any(|c| c.name == name), "{}", name);
+        }
+        for c in &report.entries {
+            assert!(
+                c.compiled || !c.usable,
+                "{} usable without being compiled",
+                c.name
+            );
+            assert!(c.compiled || c.version.is_none());
+        }
+        let llama = report.entries.iter().find(|c| c.name == "llama").unwrap();
+        assert_eq!(llama.compiled, cfg!(feature = "llama"));
+        assert!(report
+            .render()
+            .lines()
+            .any(|line| line.split_whitespace().eq(["audio", "not", "compiled"])));
+    }
+
+    #[test]
+    fn test_unusable_entries_say_why() {
+        let missing = Capability::new("cuda", true, Some("0.1.123")).probe(|| false, "no driver");
+        assert!(!missing.usable);
+        assert_eq!(missing.detail.as_deref(), Some("no driver"));
+
+        let absent = Capability::new("cuda", false, Some("0.1.123")).probe(|| false, "no driver");
+        assert_eq!(
+            (absent.usable, absent.version, absent.detail),
+            (false, None, None)
+        );
+
+        let report = Capabilities {
+            version: "1.0.0",
+            target: "x86_64-linux".to_string(),
+            entries: vec![missing, Capability::new("llama", true, Some("0.1.123"))],
+        };
+        assert_eq!(report.summary(), "cuda 0.1.123 (unusable), llama 0.1.123");
+    }
+
+    #[test]
+    fn test_verbose_version_flag() {
+        let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert!(verbose_version_requested(&args(&[
+            "--version",
+            "--verbose"
+        ])));
+        assert!(verbose_version_requested(&args(&["--verbose", "-V"])));
+        assert!(!verbose_version_requested(&args(&["--version"])));
+        assert!(!verbose_version_requested(&args(&["serve", "--verbose"])));
+    }
+}
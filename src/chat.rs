@@ -0,0 +1,367 @@
+//! Interactive terminal chat (`shimmy chat`) against the local engine.
+//!
+//! Keeps the conversation history for the session, streams tokens as they are
+//! generated, and supports a few slash commands:
+//! `/model <name>`, `/system [prompt]`, `/save <file>`, `/clear`, `/help`, `/quit`.
+//! Multi-line input: wrap it in `"""` or end lines with `\`.
+
+use crate::api::ChatMessage;
+use crate::engine::{GenOptions, InferenceEngine, LoadedModel};
+use crate::model_registry::Registry;
+use crate::templates::TemplateFamily;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+const HELP: &str = "\
+Commands:
+  /model <name>     switch model (keeps history)
+  /system [prompt]  set or clear the system prompt
+  /save <file>      write the conversation as JSON
+  /clear            forget the conversation history
+  /help             show this help
+  /quit             exit (Ctrl-D also works)
+Multi-line input: wrap in \"\"\" or end a line with \\";
+
+/// Slash command entered at the prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplCommand {
+    Model(String),
+    System(Option<String>),
+    Save(PathBuf),
+    Clear,
+    Help,
+    Quit,
+}
+
+/// One complete unit of user input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplInput {
+    Command(ReplCommand),
+    Message(String),
+    Invalid(String),
+}
+
+/// Classify a complete input as a slash command or a chat message
+pub fn parse_input(input: &str) -> ReplInput {
+    let trimmed = input.trim();
+    let Some(rest) = trimmed.strip_prefix('/') else {
+        return ReplInput::Message(trimmed.to_string());
+    };
+
+    let (name, arg) = match rest.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (rest, ""),
+    };
+
+    let command = match name {
+        "model" if arg.is_empty() => return ReplInput::Invalid("usage: /model <name>".into()),
+        "model" => ReplCommand::Model(arg.to_string()),
+        "system" if arg.is_empty() => ReplCommand::System(None),
+        "system" => ReplCommand::System(Some(arg.to_string())),
+        "save" if arg.is_empty() => return ReplInput::Invalid("usage: /save <file>".into()),
+        "save" => ReplCommand::Save(PathBuf::from(arg)),
+        "clear" => ReplCommand::Clear,
+        "help" | "?" => ReplCommand::Help,
+        "quit" | "exit" | "q" => ReplCommand::Quit,
+        other => return ReplInput::Invalid(format!("unknown command /{other} (try /help)")),
+    };
+    ReplInput::Command(command)
+}
+
+/// Accumulates raw lines until a complete (possibly multi-line) input is ready
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    lines: Vec<String>,
+    in_block: bool,
+}
+
+impl LineBuffer {
+    /// Feed one line; returns the complete input once it is finished
+    pub fn push(&mut self, line: &str) -> Option<String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if line.trim() == "\"\"\"" {
+            if self.in_block {
+                self.in_block = false;
+                return Some(self.take());
+            }
+            self.in_block = true;
+            return None;
+        }
+        if self.in_block {
+            self.lines.push(line.to_string());
+            return None;
+        }
+        if let Some(continued) = line.strip_suffix('\\') {
+            self.lines.push(continued.to_string());
+            return None;
+        }
+
+        self.lines.push(line.to_string());
+        Some(self.take())
+    }
+
+    /// Whether a multi-line input is in progress
+    pub fn is_continuing(&self) -> bool {
+        self.in_block || !self.lines.is_empty()
+    }
+
+    fn take(&mut self) -> String {
+        std::mem::take(&mut self.lines).join("\n")
+    }
+}
+
+/// Conversation state for one REPL session
+#[derive(Debug, Clone)]
+pub struct ChatSession {
+    pub model: String,
+    pub system: Option<String>,
+    pub history: Vec<ChatMessage>,
+}
+
+impl ChatSession {
+    pub fn new(model: String, system: Option<String>) -> Self {
+        Self {
+            model,
+            system,
+            history: Vec::new(),
+        }
+    }
+
+    /// Render the prompt for the next turn with `input` as the new user message
+    pub fn render_prompt(&self, family: &TemplateFamily, input: &str) -> String {
+        let pairs: Vec<(String, String)> = self
+            .history
+            .iter()
+            .map(|m| (m.role.clone(), m.content.clone()))
+            .collect();
+        family.render(self.system.as_deref(), &pairs, Some(input))
+    }
+
+    /// Write the conversation as JSON (system prompt first, if any)
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut messages = Vec::with_capacity(self.history.len() + 1);
+        if let Some(system) = &self.system {
+            messages.push(ChatMessage {
+                role: "system".to_string(),
+                content: system.clone(),
+            });
+        }
+        messages.extend(self.history.iter().cloned());
+
+        let transcript = serde_json::json!({
+            "model": self.model,
+            "messages": messages,
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&transcript)?)?;
+        Ok(())
+    }
+}
+
+/// Pick the prompt template the same way the OpenAI-compatible route does
+pub(crate) fn template_family(template: Option<&str>, model: &str) -> TemplateFamily {
+    match template {
+        Some("chatml") => TemplateFamily::ChatML,
+        Some("llama3") | Some("llama-3") => TemplateFamily::Llama3,
+        _ => {
+            let model = model.to_lowercase();
+            if model.contains("qwen") || model.contains("chatglm") {
+                TemplateFamily::ChatML
+            } else if model.contains("llama") {
+                TemplateFamily::Llama3
+            } else {
+                TemplateFamily::OpenChat
+            }
+        }
+    }
+}
+
+struct ActiveModel {
+    loaded: Box<dyn LoadedModel>,
+    family: TemplateFamily,
+}
+
+async fn load_model(
+    engine: &dyn InferenceEngine,
+    registry: &Registry,
+    name: &str,
+) -> anyhow::Result<ActiveModel> {
+    let Some(spec) = registry.to_spec(name) else {
+        anyhow::bail!("no model {name}");
+    };
+    let family = template_family(spec.template.as_deref(), name);
+    let loaded = engine.load(&spec).await?;
+    Ok(ActiveModel { loaded, family })
+}
+
+/// Run the interactive chat loop on stdin/stdout
+pub async fn run(
+    engine: &dyn InferenceEngine,
+    registry: &Registry,
+    model: String,
+    system: Option<String>,
+    max_tokens: usize,
+) -> anyhow::Result<()> {
+    let mut active = load_model(engine, registry, &model).await?;
+    let mut session = ChatSession::new(model, system);
+    let mut buffer = LineBuffer::default();
+    let stdin = std::io::stdin();
+
+    println!("💬 Chatting with {} — /help for commands", session.model);
+    loop {
+        print!(
+            "{}",
+            if buffer.is_continuing() {
+                "... "
+            } else {
+                ">>> "
+            }
+        );
+        std::io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            println!();
+            break;
+        }
+        let Some(input) = buffer.push(&line) else {
+            continue;
+        };
+
+        match parse_input(&input) {
+            ReplInput::Message(text) if text.is_empty() => {}
+            ReplInput::Message(text) => {
+                let prompt = session.render_prompt(&active.family, &text);
+                let opts = GenOptions {
+                    max_tokens,
+                    stream: true,
+                    stop_tokens: active.family.stop_tokens(),
+                    ..Default::default()
+                };
+                let on_token: Box<dyn FnMut(String) + Send> = Box::new(|token| {
+                    print!("{}", token);
+                    let _ = std::io::stdout().flush();
+                });
+
+                match active.loaded.generate(&prompt, opts, Some(on_token)).await {
+                    Ok(reply) => {
+                        println!();
+                        session.history.push(ChatMessage {
+                            role: "user".to_string(),
+                            content: text,
+                        });
+                        session.history.push(ChatMessage {
+                            role: "assistant".to_string(),
+                            content: reply.trim().to_string(),
+                        });
+                    }
+                    Err(e) => eprintln!("\n❌ generation failed: {e}"),
+                }
+            }
+            ReplInput::Command(ReplCommand::Model(name)) => {
+                match load_model(engine, registry, &name).await {
+                    Ok(next) => {
+                        active = next;
+                        session.model = name;
+                        println!("✅ switched to {}", session.model);
+                    }
+                    Err(e) => eprintln!("❌ {e}"),
+                }
+            }
+            ReplInput::Command(ReplCommand::System(prompt)) => {
+                match &prompt {
+                    Some(_) => println!("✅ system prompt set"),
+                    None => println!("✅ system prompt cleared"),
+                }
+                session.system = prompt;
+            }
+            ReplInput::Command(ReplCommand::Save(path)) => match session.save(&path) {
+                Ok(()) => println!(
+                    "✅ saved {} messages to {}",
+                    session.history.len(),
+                    path.display()
+                ),
+                Err(e) => eprintln!("❌ save failed: {e}"),
+            },
+            ReplInput::Command(ReplCommand::Clear) => {
+                session.history.clear();
+                println!("✅ history cleared");
+            }
+            ReplInput::Command(ReplCommand::Help) => println!("{HELP}"),
+            ReplInput::Command(ReplCommand::Quit) => break,
+            ReplInput::Invalid(msg) => eprintln!("❌ {msg}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            parse_input("/model phi3"),
+            ReplInput::Command(ReplCommand::Model("phi3".into()))
+        );
+        assert_eq!(
+            parse_input("/system be brief"),
+            ReplInput::Command(ReplCommand::System(Some("be brief".into())))
+        );
+        assert_eq!(
+            parse_input("/system"),
+            ReplInput::Command(ReplCommand::System(None))
+        );
+        assert_eq!(
+            parse_input("/save chat.json"),
+            ReplInput::Command(ReplCommand::Save(PathBuf::from("chat.json")))
+        );
+        assert!(matches!(parse_input("/model"), ReplInput::Invalid(_)));
+        assert!(matches!(parse_input("/bogus"), ReplInput::Invalid(_)));
+        assert_eq!(parse_input("  hello  "), ReplInput::Message("hello".into()));
+    }
+
+    #[test]
+    fn test_line_buffer_multiline() {
+        let mut buf = LineBuffer::default();
+        assert_eq!(buf.push("single\n"), Some("single".to_string()));
+
+        assert_eq!(buf.push("first \\\n"), None);
+        assert!(buf.is_continuing());
+        assert_eq!(buf.push("second\n"), Some("first \nsecond".to_string()));
+
+        assert_eq!(buf.push("\"\"\"\n"), None);
+        assert_eq!(buf.push("a\n"), None);
+        assert_eq!(buf.push("b\\\n"), None);
+        assert_eq!(buf.push("\"\"\"\n"), Some("a\nb\\".to_string()));
+        assert!(!buf.is_continuing());
+    }
+
+    #[test]
+    fn test_session_prompt_and_save() {
+        let mut session = ChatSession::new("qwen".into(), Some("be brief".into()));
+        session.history.push(ChatMessage {
+            role: "user".into(),
+            content: "hi".into(),
+        });
+        session.history.push(ChatMessage {
+            role: "assistant".into(),
+            content: "hello".into(),
+        });
+
+        let prompt = session.render_prompt(&TemplateFamily::ChatML, "again");
+        assert!(prompt.starts_with("<|im_start|>system\nbe brief"));
+        assert!(prompt.contains("<|im_start|>assistant\nhello<|im_end|>"));
+        assert!(prompt.ends_with("<|im_start|>assistant\n"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chat.json");
+        session.save(&path).unwrap();
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["model"], "qwen");
+        assert_eq!(saved["messages"].as_array().unwrap().len(), 3);
+        assert_eq!(saved["messages"][0]["role"], "system");
+    }
+}
@@ -1,10 +1,10 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[command(
-    name = "shimmy",
+    name = crate::branding::APP_NAME,
     version,
-    about = "Shimmy: single-binary GGUF + LoRA server"
+    about = crate::branding::ABOUT
 )]
 pub struct Cli {
     #[command(subcommand)]
@@ -18,6 +18,10 @@ pub struct Cli {
     )]
     pub model_dirs: Option<String>,
 
+    /// Skip model folders of other apps (LM Studio, GPT4All) during discovery
+    #[arg(long, global = true)]
+    pub no_app_models: bool,
+
     /// GPU backend to use for llama.cpp inference
     #[arg(
         long,
@@ -33,6 +37,16 @@ pub struct Cli {
     /// Offload first N MoE layers' expert tensors to CPU
     #[arg(long, global = true, value_name = "N", conflicts_with = "cpu_moe")]
     pub n_cpu_moe: Option<usize>,
+
+    /// Export tracing spans over OTLP/gRPC to this collector (`otel` feature),
+    /// e.g. http://localhost:4317; defaults to SHIMMY_OTLP_ENDPOINT
+    #[arg(long, global = true, value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Make backend calls randomly slow, fail or stop early, for resilience
+    /// testing; defaults to SHIMMY_FAULT_INJECT (see `engine::fault`)
+    #[arg(long, global = true, hide = true, value_name = "SPEC")]
+    pub fault_inject: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -44,6 +58,30 @@ pub enum Command {
         /// Direct path to a specific model file (bypasses auto-discovery)
         #[arg(long)]
         model_path: Option<String>,
+        /// Refuse requests that change server state (model load/unload, vector
+        /// collections, document ingest, power mode); inference stays available
+        #[arg(long)]
+        read_only: bool,
+        /// Restrict the server with Landlock (Linux, `sandbox` feature): no
+        /// writes outside the data directories and no outbound TCP except the
+        /// ports in SHIMMY_SANDBOX_ALLOW_CONNECT
+        #[arg(long)]
+        sandbox: bool,
+        /// PEM certificate chain to serve HTTPS with; reloaded when it changes
+        #[arg(long, requires = "tls_key")]
+        tls_cert: Option<String>,
+        /// PEM private key for --tls-cert
+        #[arg(long, requires = "tls_cert")]
+        tls_key: Option<String>,
+        /// Keep models loaded for this long after their last request: minutes,
+        /// or a duration like "90s" or "2h"; -1 keeps them (default 0: unload
+        /// after each request)
+        #[arg(long, value_name = "DURATION", allow_hyphen_values = true)]
+        model_ttl: Option<String>,
+        /// Also serve the gRPC API on this address, e.g. 127.0.0.1:50051
+        /// (`grpc` feature); defaults to SHIMMY_GRPC_BIND
+        #[arg(long, value_name = "ADDR")]
+        grpc_bind: Option<String>,
     },
     /// List registered and auto-discovered models
     List {
@@ -73,6 +111,32 @@ pub enum Command {
         #[arg(long, default_value_t = 64)]
         max_tokens: usize,
     },
+    /// One-shot generation for scripts: prompt from -p and/or piped stdin
+    Run {
+        name: String,
+        #[arg(short, long)]
+        prompt: Option<String>,
+        /// System prompt (ignored with --raw)
+        #[arg(long)]
+        system: Option<String>,
+        /// Send the prompt as-is instead of applying the model's chat template
+        #[arg(long)]
+        raw: bool,
+        #[command(flatten)]
+        sampling: SamplingArgs,
+        /// Print a JSON object with the response and usage stats
+        #[arg(long)]
+        json: bool,
+    },
+    /// Interactive terminal chat with a local model
+    Chat {
+        name: String,
+        /// System prompt for the session (change later with /system)
+        #[arg(long)]
+        system: Option<String>,
+        #[arg(long, default_value_t = 512)]
+        max_tokens: usize,
+    },
     /// Show GPU backend information and capabilities
     GpuInfo,
     /// Initialize integration templates for deployment platforms
@@ -87,6 +151,171 @@ pub enum Command {
         #[arg(short, long)]
         name: Option<String>,
     },
+    /// Detect hardware, download a starter model, and write shimmy.toml
+    Setup {
+        /// Accept all prompts (download without asking)
+        #[arg(short, long)]
+        yes: bool,
+        /// Only recommend models; don't download anything
+        #[arg(long)]
+        no_download: bool,
+        /// Write the config here instead of the default location
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Update this binary to the latest GitHub release
+    Upgrade {
+        /// Release channel to follow
+        #[arg(long, default_value = "stable", value_parser = ["stable", "prerelease"])]
+        channel: String,
+        /// Only report whether a newer release exists
+        #[arg(long)]
+        check: bool,
+        /// Replace the binary even if a package manager installed it
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show local usage stats and manage anonymous, opt-in telemetry
+    Stats {
+        /// Print the stats file as JSON
+        #[arg(long)]
+        json: bool,
+        /// Opt in to counting requests and backends (stored locally only)
+        #[arg(long, conflicts_with = "disable")]
+        enable: bool,
+        /// With --enable, also send the aggregate counts once a day
+        #[arg(long, requires = "enable")]
+        upload: bool,
+        /// Stop counting (the stats file is kept)
+        #[arg(long)]
+        disable: bool,
+    },
+    /// Upgrade on-disk state (license cache, usage stats, config) to the current format
+    Migrate {
+        /// Show pending migrations without changing any files
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Bundle version, hardware, redacted config and recent errors for a bug report
+    Report {
+        /// Write the archive here instead of ./shimmy-report-<timestamp>.zip
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// Print client configuration snippets (curl, Python, JavaScript, Continue)
+    ConnectInfo {
+        /// Server URL (default: where `serve --bind auto` listens)
+        #[arg(long)]
+        url: Option<String>,
+        /// Model the snippets use (default: the first chat model)
+        #[arg(long)]
+        model: Option<String>,
+        /// Print the `GET /api/connect` JSON instead
+        #[arg(long)]
+        json: bool,
+    },
+    /// Manage the shared, content-addressed model store
+    Store {
+        #[command(subcommand)]
+        action: StoreAction,
+    },
+    /// Manage the stored Shimmy Vision license key
+    #[cfg(feature = "vision")]
+    License {
+        #[command(subcommand)]
+        action: LicenseAction,
+    },
+    /// Serve one model to a parent `serve` over stdin/stdout (SHIMMY_ISOLATE_INFERENCE)
+    #[cfg(feature = "llama")]
+    #[command(hide = true)]
+    Worker,
+}
+
+/// Sampling flags mirroring `GenOptions`
+#[derive(Args, Debug, Clone)]
+pub struct SamplingArgs {
+    #[arg(long, default_value_t = 256)]
+    pub max_tokens: usize,
+    #[arg(long, default_value_t = 0.7)]
+    pub temperature: f32,
+    #[arg(long, default_value_t = 0.9)]
+    pub top_p: f32,
+    #[arg(long, default_value_t = 40)]
+    pub top_k: i32,
+    #[arg(long, default_value_t = 1.1)]
+    pub repeat_penalty: f32,
+    #[arg(long)]
+    pub seed: Option<u32>,
+    /// Stop sequence (repeatable)
+    #[arg(long = "stop", value_name = "TEXT")]
+    pub stop: Vec<String>,
+}
+
+impl SamplingArgs {
+    pub fn gen_options(&self) -> crate::engine::GenOptions {
+        crate::engine::GenOptions {
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            repeat_penalty: self.repeat_penalty,
+            seed: self.seed,
+            stream: true,
+            stop_tokens: self.stop.clone(),
+            add_bos: None,
+            repetition: None,
+            max_time_ms: None,
+            grammar: None,
+            top_logprobs: 0,
+            cancel: None,
+            deadline: None,
+            api_key: None,
+            rate_limit: None,
+            adapter: None,
+        }
+    }
+}
+
+#[cfg(feature = "vision")]
+#[derive(Subcommand, Debug)]
+pub enum LicenseAction {
+    /// Store a license key (OS keychain where available)
+    Set { key: String },
+    /// Show whether a license key is stored
+    Show,
+    /// Remove the stored license key
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StoreAction {
+    /// List stored models and the blobs they point at
+    List,
+    /// Download a model file into the store under NAME
+    Pull {
+        name: String,
+        url: String,
+        /// Expected SHA-256; skips the download when the blob is already stored
+        #[arg(long)]
+        sha256: Option<String>,
+    },
+    /// Add a local model file to the store under NAME
+    Import {
+        name: String,
+        path: String,
+        /// LoRA adapter to store alongside the model
+        #[arg(long)]
+        lora: Option<String>,
+        /// Vision projector (mmproj GGUF) to store alongside the model
+        #[arg(long)]
+        mmproj: Option<String>,
+    },
+    /// Register another name for a stored model without copying it
+    Alias { name: String, alias: String },
+    /// Remove a name (blobs are freed by `prune`)
+    Rm { name: String },
+    /// Delete blobs that no name refers to
+    Prune,
 }
 
 #[cfg(test)]
@@ -103,6 +332,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_serve_read_only_flag() {
+        let cli = Cli::try_parse_from(["shimmy", "serve", "--read-only"]).unwrap();
+        match cli.cmd {
+            Command::Serve { read_only, .. } => assert!(read_only),
+            _ => panic!("Expected Serve command"),
+        }
+        let cli = Cli::try_parse_from(["shimmy", "serve"]).unwrap();
+        assert!(matches!(
+            cli.cmd,
+            Command::Serve {
+                read_only: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_cli_serve_tls_flags_go_together() {
+        let cli = Cli::try_parse_from([
+            "shimmy",
+            "serve",
+            "--tls-cert",
+            "cert.pem",
+            "--tls-key",
+            "key.pem",
+        ])
+        .unwrap();
+        match cli.cmd {
+            Command::Serve {
+                tls_cert, tls_key, ..
+            } => {
+                assert_eq!(tls_cert.as_deref(), Some("cert.pem"));
+                assert_eq!(tls_key.as_deref(), Some("key.pem"));
+            }
+            _ => panic!("Expected Serve command"),
+        }
+        assert!(Cli::try_parse_from(["shimmy", "serve", "--tls-cert", "cert.pem"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_serve_model_ttl() {
+        let cli = Cli::try_parse_from(["shimmy", "serve", "--model-ttl", "-1"]).unwrap();
+        match cli.cmd {
+            Command::Serve { model_ttl, .. } => assert_eq!(model_ttl.as_deref(), Some("-1")),
+            _ => panic!("Expected Serve command"),
+        }
+    }
+
     #[test]
     fn test_cli_serve_command_manual_bind() {
         let cli = Cli::try_parse_from(["shimmy", "serve", "--bind", "127.0.0.1:8080"]).unwrap();
@@ -117,6 +395,12 @@ mod tests {
         let command = Command::Serve {
             bind: "auto".to_string(),
             model_path: None,
+            read_only: false,
+            sandbox: false,
+            tls_cert: None,
+            tls_key: None,
+            model_ttl: None,
+            grpc_bind: None,
         };
 
         // Test that we can access the bind field
@@ -133,6 +417,12 @@ mod tests {
         let command = Command::Serve {
             bind: "192.168.1.100:9000".to_string(),
             model_path: None,
+            read_only: false,
+            sandbox: false,
+            tls_cert: None,
+            tls_key: None,
+            model_ttl: None,
+            grpc_bind: None,
         };
 
         match command {
@@ -158,6 +448,15 @@ mod tests {
         matches!(cli.cmd, Command::List { short: true });
     }
 
+    #[test]
+    fn test_cli_no_app_models_flag() {
+        let cli = Cli::try_parse_from(["shimmy", "list", "--no-app-models"]).unwrap();
+        assert!(cli.no_app_models);
+
+        let cli = Cli::try_parse_from(["shimmy", "list"]).unwrap();
+        assert!(!cli.no_app_models);
+    }
+
     #[test]
     fn test_cli_generate_command() {
         let cli = Cli::try_parse_from([
@@ -212,6 +511,133 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_run_command_sampling_flags() {
+        let cli = Cli::try_parse_from([
+            "shimmy",
+            "run",
+            "phi3",
+            "-p",
+            "hi",
+            "--temperature",
+            "0.2",
+            "--stop",
+            "\n\n",
+            "--stop",
+            "END",
+            "--json",
+        ])
+        .unwrap();
+        match cli.cmd {
+            Command::Run {
+                name,
+                prompt,
+                sampling,
+                json,
+                raw,
+                ..
+            } => {
+                assert_eq!(name, "phi3");
+                assert_eq!(prompt.as_deref(), Some("hi"));
+                assert!(json);
+                assert!(!raw);
+                let opts = sampling.gen_options();
+                assert_eq!(opts.temperature, 0.2);
+                assert_eq!(opts.max_tokens, 256);
+                assert_eq!(opts.stop_tokens, vec!["\n\n", "END"]);
+            }
+            _ => panic!("Expected Run command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_migrate_dry_run() {
+        let cli = Cli::try_parse_from(["shimmy", "migrate", "--dry-run"]).unwrap();
+        assert!(matches!(cli.cmd, Command::Migrate { dry_run: true }));
+    }
+
+    #[test]
+    fn test_cli_setup_command() {
+        let cli = Cli::try_parse_from(["shimmy", "setup", "--yes", "--no-download"]).unwrap();
+        match cli.cmd {
+            Command::Setup {
+                yes,
+                no_download,
+                output,
+            } => {
+                assert!(yes && no_download);
+                assert!(output.is_none());
+            }
+            _ => panic!("Expected Setup command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_upgrade_channel() {
+        let cli = Cli::try_parse_from(["shimmy", "upgrade", "--channel", "prerelease"]).unwrap();
+        assert!(matches!(
+            cli.cmd,
+            Command::Upgrade { ref channel, check: false, force: false } if channel == "prerelease"
+        ));
+        assert!(Cli::try_parse_from(["shimmy", "upgrade", "--channel", "nightly"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_stats_flags() {
+        let cli = Cli::try_parse_from(["shimmy", "stats", "--enable", "--upload"]).unwrap();
+        assert!(matches!(
+            cli.cmd,
+            Command::Stats {
+                enable: true,
+                upload: true,
+                disable: false,
+                ..
+            }
+        ));
+        assert!(Cli::try_parse_from(["shimmy", "stats", "--upload"]).is_err());
+        assert!(Cli::try_parse_from(["shimmy", "stats", "--enable", "--disable"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_chat_command() {
+        let cli = Cli::try_parse_from(["shimmy", "chat", "phi3", "--system", "be brief"]).unwrap();
+        match cli.cmd {
+            Command::Chat {
+                name,
+                system,
+                max_tokens,
+            } => {
+                assert_eq!(name, "phi3");
+                assert_eq!(system.as_deref(), Some("be brief"));
+                assert_eq!(max_tokens, 512);
+            }
+            _ => panic!("Expected Chat command"),
+        }
+    }
+
+    #[cfg(feature = "vision")]
+    #[test]
+    fn test_cli_license_set_command() {
+        let cli = Cli::try_parse_from(["shimmy", "license", "set", "KEY-123"]).unwrap();
+        match cli.cmd {
+            Command::License {
+                action: LicenseAction::Set { key },
+            } => assert_eq!(key, "KEY-123"),
+            _ => panic!("Expected License Set command"),
+        }
+    }
+
+    #[test]
+    fn test_cli_store_alias_command() {
+        let cli = Cli::try_parse_from(["shimmy", "store", "alias", "qwen", "default"]).unwrap();
+        match cli.cmd {
+            Command::Store {
+                action: StoreAction::Alias { name, alias },
+            } => assert_eq!((name.as_str(), alias.as_str()), ("qwen", "default")),
+            _ => panic!("Expected Store Alias command"),
+        }
+    }
+
     #[test]
     fn test_cli_bench_command_default_tokens() {
         let cli = Cli::try_parse_from(["shimmy", "bench", "test-model"]).unwrap();
@@ -0,0 +1,87 @@
+//! Low-latency code completion for editors.
+//!
+//! Editors ask for a completion on nearly every keystroke and drop most of
+//! them, so one that waits behind a long chat job is worthless by the time
+//! it arrives. `POST /v1/code/completions` takes the same body as
+//! `/v1/completions` (`prompt`, and `suffix` for fill-in-the-middle) with
+//! defaults tuned for latency:
+//!
+//! - its own queue, `SHIMMY_MAX_CONCURRENT_CODE` / `SHIMMY_MAX_QUEUE_CODE`,
+//!   apart from chat's
+//! - 64 tokens unless `max_tokens` says otherwise
+//! - greedy decoding unless `temperature` is set, which the llama backend
+//!   runs without the rest of its sampler chain
+//! - the model stays loaded for the server's `--model-ttl` unless
+//!   `keep_alive` says otherwise; while it is, the KV cache still holds the
+//!   previous keystroke's prompt and only the newly typed text is evaluated
+//!
+//! Each completion is timed from when the request arrived until its last
+//! token, against a target of `SHIMMY_CODE_SLO_MS` (default 500). `/metrics`
+//! reports the p95 of the last 1000 completions and how many missed it.
+
+use crate::engine::GenOptions;
+use crate::openai_compat::CompletionRequest;
+use crate::prometheus::{Received, ServerMetrics};
+use crate::AppState;
+use axum::extract::State;
+use axum::{Extension, Json};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const SLO_ENV: &str = "SHIMMY_CODE_SLO_MS";
+
+const DEFAULT_SLO: Duration = Duration::from_millis(500);
+
+/// Tokens generated when the request doesn't say
+pub const DEFAULT_MAX_TOKENS: usize = 64;
+
+/// The latency target for one completion
+pub fn slo() -> Duration {
+    std::env::var(SLO_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|&ms| ms > 0)
+        .map_or(DEFAULT_SLO, Duration::from_millis)
+}
+
+/// Generation defaults for editor completions: short and greedy
+pub fn options() -> GenOptions {
+    GenOptions {
+        max_tokens: DEFAULT_MAX_TOKENS,
+        temperature: 0.0,
+        repeat_penalty: 1.0,
+        ..GenOptions::default()
+    }
+}
+
+pub async fn completions(
+    State(state): State<Arc<AppState>>,
+    received: Option<Extension<Received>>,
+    Json(req): Json<CompletionRequest>,
+) -> axum::response::Response {
+    let started = received.map_or_else(Instant::now, |Extension(Received(at))| at);
+    let slo = slo();
+    let on_done = Box::new(move || {
+        ServerMetrics::global().record_code_completion(started.elapsed(), slo);
+    });
+    crate::openai_compat::complete(state, req, options(), Some(on_done)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_are_short_and_greedy() {
+        let opts = options();
+        assert_eq!(opts.max_tokens, DEFAULT_MAX_TOKENS);
+        assert!(opts.is_greedy());
+        assert!(opts.validate().is_ok());
+        assert!(!GenOptions::default().is_greedy());
+        let sampled = GenOptions {
+            temperature: 0.2,
+            ..options()
+        };
+        assert!(!sampled.is_greedy());
+    }
+}
@@ -0,0 +1,314 @@
+//! Ready-to-paste client configuration for this server.
+//!
+//! `GET /api/connect` and `shimmy connect-info` render the same snippets
+//! (curl, the Python and JavaScript OpenAI clients, a Continue config) filled
+//! in with the address clients reach the server at and one of its models.
+//! Behind a reverse proxy the address comes from `X-Forwarded-Proto` and
+//! `X-Forwarded-Host`, else from the `Host` header the client sent.
+//!
+//! When `SHIMMY_API_KEYS_FILE` is set the snippets read the key from the
+//! `SHIMMY_API_KEY` environment variable instead of hard-coding one.
+
+use axum::http::HeaderMap;
+use serde::Serialize;
+use std::net::SocketAddr;
+
+/// Environment variable the snippets read the API key from
+pub const API_KEY_VAR: &str = "SHIMMY_API_KEY";
+
+/// Where `serve --bind auto` listens when the default port is free
+pub const DEFAULT_ADDR: &str = "127.0.0.1:11435";
+
+/// Placeholder for the model when the server has none
+const MODEL_PLACEHOLDER: &str = "MODEL_NAME";
+
+/// The address the server listens on, for requests without a `Host` header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Listener {
+    pub addr: SocketAddr,
+    pub tls: bool,
+}
+
+impl Listener {
+    pub fn base_url(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        // Clients can't connect to 0.0.0.0; on this host loopback reaches it
+        let mut addr = self.addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(if addr.is_ipv4() {
+                std::net::Ipv4Addr::LOCALHOST.into()
+            } else {
+                std::net::Ipv6Addr::LOCALHOST.into()
+            });
+        }
+        format!("{}://{}", scheme, addr)
+    }
+}
+
+/// The URL of a server started with `--bind auto`, for `shimmy connect-info`
+pub fn default_base_url() -> String {
+    let addr = std::env::var("SHIMMY_BIND_ADDRESS")
+        .ok()
+        .and_then(|addr| addr.parse().ok())
+        .unwrap_or_else(|| DEFAULT_ADDR.parse().expect("valid default address"));
+    Listener { addr, tls: false }.base_url()
+}
+
+/// The URL a client reached the server at, without a trailing slash
+pub fn base_url(headers: &HeaderMap, listener: Option<&Listener>) -> String {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            // Proxies append to a list; the first entry is the client's view
+            .and_then(|v| v.split(',').next())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+    let fallback =
+        || listener.map_or_else(|| format!("http://{}", DEFAULT_ADDR), Listener::base_url);
+    let Some(host) = header("x-forwarded-host").or_else(|| header("host")) else {
+        return fallback();
+    };
+    let scheme = header("x-forwarded-proto")
+        .or_else(|| listener.map(|l| if l.tls { "https" } else { "http" }))
+        .unwrap_or("http");
+    format!("{}://{}", scheme, host)
+}
+
+/// One client configuration
+#[derive(Debug, Clone, Serialize)]
+pub struct Snippet {
+    pub id: &'static str,
+    pub title: &'static str,
+    /// Syntax for highlighting
+    pub language: &'static str,
+    pub code: String,
+}
+
+/// Body of `GET /api/connect`
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectInfo {
+    pub base_url: String,
+    /// Base URL for OpenAI-compatible clients
+    pub openai_base_url: String,
+    /// The model the snippets use
+    pub model: Option<String>,
+    pub models: Vec<String>,
+    pub api_key_required: bool,
+    pub snippets: Vec<Snippet>,
+}
+
+impl ConnectInfo {
+    pub fn new(
+        base_url: &str,
+        models: Vec<String>,
+        model: Option<String>,
+        api_key_required: bool,
+    ) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        let openai_base_url = format!("{}/v1", base_url);
+        let name = model.as_deref().unwrap_or(MODEL_PLACEHOLDER);
+        let snippets = vec![
+            curl(&openai_base_url, name, api_key_required),
+            python(&openai_base_url, name, api_key_required),
+            javascript(&openai_base_url, name, api_key_required),
+            continue_config(&openai_base_url, name, api_key_required),
+        ];
+        Self {
+            base_url,
+            openai_base_url,
+            model,
+            models,
+            api_key_required,
+            snippets,
+        }
+    }
+
+    /// Snippets for the models in `registry`, using `model` or else the first
+    /// chat model
+    pub fn for_registry(
+        registry: &crate::model_registry::Registry,
+        base_url: &str,
+        model: Option<&str>,
+        api_key_required: bool,
+    ) -> Result<Self, crate::error::ShimmyError> {
+        let models = registry.list_all_available();
+        let model = match model {
+            Some(name) => Some(registry.resolve_name(name).ok_or_else(|| {
+                crate::error::ShimmyError::ModelNotFound {
+                    name: name.to_string(),
+                }
+            })?),
+            None => models
+                .iter()
+                .find(|name| !registry.is_embedding_model(name))
+                .cloned(),
+        };
+        Ok(Self::new(base_url, models, model, api_key_required))
+    }
+
+    /// The snippets as terminal text, each under its title
+    pub fn render(&self) -> String {
+        let mut out = format!("{} at {}\n", crate::branding::PRODUCT_NAME, self.base_url);
+        if self.model.is_none() {
+            out.push_str(&format!(
+                "No models available yet; replace {} once one is.\n",
+                MODEL_PLACEHOLDER
+            ));
+        }
+        if self.api_key_required {
+            out.push_str(&format!("API keys are required: export {}.\n", API_KEY_VAR));
+        }
+        for snippet in &self.snippets {
+            out.push_str(&format!("\n# {}\n{}\n", snippet.title, snippet.code));
+        }
+        out
+    }
+}
+
+/// `s` as a double-quoted string literal, valid in JSON, Python and JavaScript
+fn quoted(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| format!("\"{}\"", s))
+}
+
+fn curl(base: &str, model: &str, auth: bool) -> Snippet {
+    let auth_header = if auth {
+        format!("  -H \"Authorization: Bearer ${}\" \\\n", API_KEY_VAR)
+    } else {
+        String::new()
+    };
+    Snippet {
+        id: "curl",
+        title: "curl",
+        language: "bash",
+        code: format!(
+            "curl {base}/chat/completions \\\n  -H \"Content-Type: application/json\" \\\n{auth_header}  -d '{{\"model\": {model}, \"messages\": [{{\"role\": \"user\", \"content\": \"Hello!\"}}]}}'",
+            base = base,
+            auth_header = auth_header,
+            model = quoted(model),
+        ),
+    }
+}
+
+fn python(base: &str, model: &str, auth: bool) -> Snippet {
+    let (import, api_key) = if auth {
+        (
+            "import os\n",
+            format!("os.environ[{}]", quoted(API_KEY_VAR)),
+        )
+    } else {
+        ("", quoted(crate::branding::APP_NAME))
+    };
+    Snippet {
+        id: "python",
+        title: "Python (openai)",
+        language: "python",
+        code: format!(
+            "{import}from openai import OpenAI\n\nclient = OpenAI(base_url={base}, api_key={api_key})\nresponse = client.chat.completions.create(\n    model={model},\n    messages=[{{\"role\": \"user\", \"content\": \"Hello!\"}}],\n)\nprint(response.choices[0].message.content)",
+            import = import,
+            base = quoted(base),
+            api_key = api_key,
+            model = quoted(model),
+        ),
+    }
+}
+
+fn javascript(base: &str, model: &str, auth: bool) -> Snippet {
+    let api_key = if auth {
+        format!("process.env.{}", API_KEY_VAR)
+    } else {
+        quoted(crate::branding::APP_NAME)
+    };
+    Snippet {
+        id: "javascript",
+        title: "JavaScript (openai)",
+        language: "javascript",
+        code: format!(
+            "import OpenAI from \"openai\";\n\nconst client = new OpenAI({{ baseURL: {base}, apiKey: {api_key} }});\nconst response = await client.chat.completions.create({{\n  model: {model},\n  messages: [{{ role: \"user\", content: \"Hello!\" }}],\n}});\nconsole.log(response.choices[0].message.content);",
+            base = quoted(base),
+            api_key = api_key,
+            model = quoted(model),
+        ),
+    }
+}
+
+fn continue_config(base: &str, model: &str, auth: bool) -> Snippet {
+    let api_key = if auth {
+        format!("${{{{ secrets.{} }}}}", API_KEY_VAR)
+    } else {
+        crate::branding::APP_NAME.to_string()
+    };
+    Snippet {
+        id: "continue",
+        title: "Continue (config.yaml)",
+        language: "yaml",
+        code: format!(
+            "name: {product}\nversion: 1.0.0\nschema: v1\nmodels:\n  - name: {product} {model_name}\n    provider: openai\n    model: {model}\n    apiBase: {base}\n    apiKey: {api_key}\n    roles:\n      - chat\n      - edit\n      - apply",
+            product = crate::branding::PRODUCT_NAME,
+            model_name = model,
+            model = quoted(model),
+            base = base,
+            api_key = api_key,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_url_prefers_forwarded_headers() {
+        let listener = Listener {
+            addr: "0.0.0.0:8080".parse().unwrap(),
+            tls: true,
+        };
+        let mut headers = HeaderMap::new();
+        assert_eq!(base_url(&headers, None), "http://127.0.0.1:11435");
+        assert_eq!(
+            base_url(&headers, Some(&listener)),
+            "https://127.0.0.1:8080"
+        );
+
+        headers.insert("host", "gpu-box:8080".parse().unwrap());
+        assert_eq!(base_url(&headers, Some(&listener)), "https://gpu-box:8080");
+        assert_eq!(base_url(&headers, None), "http://gpu-box:8080");
+
+        headers.insert("x-forwarded-host", "llm.example.com".parse().unwrap());
+        headers.insert("x-forwarded-proto", "https, http".parse().unwrap());
+        assert_eq!(base_url(&headers, None), "https://llm.example.com");
+    }
+
+    #[test]
+    fn test_snippets_use_address_model_and_key() {
+        let info = ConnectInfo::new(
+            "http://gpu-box:11435/",
+            vec!["phi-3-mini".to_string()],
+            Some("phi-3-mini".to_string()),
+            false,
+        );
+        assert_eq!(info.openai_base_url, "http://gpu-box:11435/v1");
+        let ids: Vec<_> = info.snippets.iter().map(|s| s.id).collect();
+        assert_eq!(ids, ["curl", "python", "javascript", "continue"]);
+        for snippet in &info.snippets {
+            assert!(
+                snippet.code.contains("http://gpu-box:11435/v1"),
+                "{}",
+                snippet.id
+            );
+            assert!(snippet.code.contains("phi-3-mini"), "{}", snippet.id);
+            assert!(!snippet.code.contains(API_KEY_VAR), "{}", snippet.id);
+        }
+        assert!(info.snippets[0]
+            .code
+            .contains(r#"-d '{"model": "phi-3-mini", "messages""#));
+
+        let keyed = ConnectInfo::new("http://gpu-box:11435", Vec::new(), None, true);
+        for snippet in &keyed.snippets {
+            assert!(snippet.code.contains(API_KEY_VAR), "{}", snippet.id);
+            assert!(snippet.code.contains(MODEL_PLACEHOLDER), "{}", snippet.id);
+        }
+        assert!(keyed.render().contains("export SHIMMY_API_KEY"));
+    }
+}
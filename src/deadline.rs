@@ -0,0 +1,175 @@
+//! Per-request deadlines from the `x-request-deadline-ms` header.
+//!
+//! A client that gives up after a fixed time can say so, and the server stops
+//! spending work on the request once the time is up:
+//!
+//! - a request whose deadline has passed, or passes while it waits for a
+//!   concurrency slot, is refused with `504 DEADLINE_EXCEEDED`; so is one with
+//!   less than `SHIMMY_DEADLINE_MIN_MS` (default 250) left when it is admitted
+//! - generation stops at the deadline and returns the output so far with
+//!   `finish_reason: "time"`
+//! - vision requests share the budget between the license check, image fetch,
+//!   preprocessing, safety screening and inference
+//!
+//! The header holds either the milliseconds the client is willing to wait,
+//! counted from when the request arrives, or an absolute Unix time in
+//! milliseconds (any value from `10^12` up, i.e. after September 2001).
+
+use crate::error::ShimmyError;
+use axum::http::HeaderMap;
+use std::future::Future;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DEADLINE_HEADER: &str = "x-request-deadline-ms";
+pub const MIN_BUDGET_ENV: &str = "SHIMMY_DEADLINE_MIN_MS";
+
+const DEFAULT_MIN_BUDGET_MS: u64 = 250;
+
+/// Header values from here up are Unix times rather than budgets
+const ABSOLUTE_FROM_MS: u64 = 1_000_000_000_000;
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// The deadline a request's headers ask for, relative to `now`
+pub fn from_headers(headers: &HeaderMap, now: Instant) -> Result<Option<Instant>, ShimmyError> {
+    let Some(value) = headers.get(DEADLINE_HEADER) else {
+        return Ok(None);
+    };
+    let ms: u64 = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| ShimmyError::InvalidRequest {
+            reason: format!("{} must be a whole number of milliseconds", DEADLINE_HEADER),
+        })?;
+    if ms < ABSOLUTE_FROM_MS {
+        return Ok(Some(now + Duration::from_millis(ms)));
+    }
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let left = Duration::from_millis(ms).saturating_sub(since_epoch);
+    Ok(Some(now + left))
+}
+
+/// The least budget worth admitting a request with
+pub fn min_budget() -> Duration {
+    let ms = std::env::var(MIN_BUDGET_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_MIN_BUDGET_MS);
+    Duration::from_millis(ms)
+}
+
+/// Run `f` with `deadline` as the current request's deadline
+pub async fn scope<F: Future>(deadline: Option<Instant>, f: F) -> F::Output {
+    match deadline {
+        Some(deadline) => DEADLINE.scope(deadline, f).await,
+        None => f.await,
+    }
+}
+
+/// The current request's deadline, if it sent one
+pub fn current() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Time left before the current request's deadline
+pub fn remaining() -> Option<Duration> {
+    current().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Refuse a request with less than [`min_budget`] left before its deadline
+pub fn admit() -> Result<(), ShimmyError> {
+    match remaining() {
+        Some(left) if left < min_budget() => Err(exceeded("admission")),
+        _ => Ok(()),
+    }
+}
+
+/// Fail if the current request's deadline passed before `stage`
+#[cfg_attr(not(feature = "vision"), allow(dead_code))]
+pub fn check(stage: &str) -> Result<(), ShimmyError> {
+    match remaining() {
+        Some(left) if left.is_zero() => Err(exceeded(stage)),
+        _ => Ok(()),
+    }
+}
+
+/// Run `f`, giving up with `DEADLINE_EXCEEDED` when the current request's
+/// deadline passes first
+pub async fn within<F: Future>(stage: &str, f: F) -> Result<F::Output, ShimmyError> {
+    match current() {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), f)
+            .await
+            .map_err(|_| exceeded(stage)),
+        None => Ok(f.await),
+    }
+}
+
+fn exceeded(stage: &str) -> ShimmyError {
+    ShimmyError::DeadlineExceeded {
+        stage: stage.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(DEADLINE_HEADER, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_budget_and_absolute_deadlines() {
+        let now = Instant::now();
+        assert_eq!(from_headers(&HeaderMap::new(), now).unwrap(), None);
+        assert_eq!(
+            from_headers(&headers("1500"), now).unwrap(),
+            Some(now + Duration::from_millis(1500))
+        );
+
+        let in_a_minute = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 60_000;
+        let deadline = from_headers(&headers(&in_a_minute.to_string()), now)
+            .unwrap()
+            .unwrap();
+        let left = deadline - now;
+        assert!(left > Duration::from_secs(55) && left <= Duration::from_secs(60));
+
+        // An absolute time in the past leaves no budget
+        assert_eq!(
+            from_headers(&headers("1000000000000"), now).unwrap(),
+            Some(now)
+        );
+        assert!(from_headers(&headers("soon"), now).is_err());
+        assert!(from_headers(&headers("-5"), now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scope_shares_the_deadline() {
+        assert_eq!(current(), None);
+        assert!(check("anything").is_ok());
+
+        let deadline = Instant::now() + Duration::from_millis(20);
+        scope(Some(deadline), async {
+            assert_eq!(current(), Some(deadline));
+            assert!(within("quick", async {}).await.is_ok());
+            let slow = within("slow", tokio::time::sleep(Duration::from_secs(5))).await;
+            assert!(matches!(
+                slow,
+                Err(ShimmyError::DeadlineExceeded { ref stage }) if stage == "slow"
+            ));
+            assert!(check("late").is_err());
+        })
+        .await;
+    }
+}
@@ -0,0 +1,552 @@
+//! OpenAI-compatible `/v1/embeddings`.
+//!
+//! Accepts a single string or thousands of them. Inputs can optionally be
+//! split into overlapping token chunks first (for indexing long documents);
+//! the backend then packs sequences into micro-batches that fit its context
+//! window. Results always come back in input order, chunks in document order.
+//!
+//! With the `vision` feature, `input` may instead hold images (objects with
+//! one of `image_base64` or `url`), embedded by a vision model's CLIP/SigLIP
+//! projector for image similarity search.
+
+use crate::engine::{InvalidParameter, LoadedModel};
+use crate::error::ShimmyError;
+use crate::AppState;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Upper bound on strings per request, after chunking
+pub const MAX_INPUTS: usize = 16_384;
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    Single(String),
+    Multiple(Vec<String>),
+    #[cfg(feature = "vision")]
+    Image(crate::vision::ImageInput),
+    #[cfg(feature = "vision")]
+    Images(Vec<crate::vision::ImageInput>),
+}
+
+impl EmbeddingInput {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            EmbeddingInput::Single(s) => vec![s],
+            EmbeddingInput::Multiple(v) => v,
+            // Taken by `image_embeddings` before text is handled
+            #[cfg(feature = "vision")]
+            EmbeddingInput::Image(_) | EmbeddingInput::Images(_) => Vec::new(),
+        }
+    }
+
+    #[cfg(feature = "vision")]
+    fn images(&self) -> Option<&[crate::vision::ImageInput]> {
+        match self {
+            EmbeddingInput::Image(image) => Some(std::slice::from_ref(image)),
+            EmbeddingInput::Images(images) => Some(images),
+            _ => None,
+        }
+    }
+}
+
+/// Split each input into windows of `tokens` tokens sharing `overlap` tokens
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Chunking {
+    pub tokens: usize,
+    #[serde(default)]
+    pub overlap: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingRequest {
+    pub model: String,
+    pub input: EmbeddingInput,
+    #[serde(default)]
+    pub chunking: Option<Chunking>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingData {
+    pub object: String,
+    /// Position of the source string in `input`
+    pub index: usize,
+    /// Position of the chunk within its input, when chunking
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<usize>,
+    /// Text of the chunk, when chunking
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingUsage {
+    pub prompt_tokens: usize,
+    pub total_tokens: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingResponse {
+    pub object: String,
+    pub data: Vec<EmbeddingData>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
+}
+
+impl Chunking {
+    pub fn validate(&self) -> Result<(), InvalidParameter> {
+        if self.tokens == 0 {
+            return Err(InvalidParameter::new(
+                "chunking",
+                "`tokens` must be at least 1",
+            ));
+        }
+        if self.overlap >= self.tokens {
+            return Err(InvalidParameter::new(
+                "chunking",
+                format!(
+                    "`overlap` ({}) must be smaller than `tokens` ({})",
+                    self.overlap, self.tokens
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Join token pieces into windows of `size` pieces, each sharing `overlap`
+/// pieces with the previous one
+///
+/// Input shorter than one window comes back whole; no window is emitted that
+/// would only repeat the previous one's overlap.
+pub fn chunk_pieces(pieces: &[String], size: usize, overlap: usize) -> Vec<String> {
+    if pieces.len() <= size {
+        return vec![pieces.concat()];
+    }
+    let stride = size - overlap;
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + size).min(pieces.len());
+        chunks.push(pieces[start..end].concat());
+        if end == pieces.len() {
+            break;
+        }
+        start += stride;
+    }
+    chunks
+}
+
+/// Whitespace-delimited pieces for backends without a tokenizer
+fn word_pieces(text: &str) -> Vec<String> {
+    text.split_inclusive(char::is_whitespace)
+        .map(str::to_string)
+        .collect()
+}
+
+/// Group sequences, in order, into batches of at most `token_budget` tokens
+/// and `max_seqs` sequences
+///
+/// A sequence longer than the budget gets a batch of its own; the backend
+/// reports it if it really doesn't fit.
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+pub fn plan_batches(lengths: &[usize], token_budget: usize, max_seqs: usize) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    let mut current = Vec::new();
+    let mut tokens = 0;
+    for (i, &len) in lengths.iter().enumerate() {
+        if !current.is_empty() && (tokens + len > token_budget || current.len() == max_seqs) {
+            batches.push(std::mem::take(&mut current));
+            tokens = 0;
+        }
+        current.push(i);
+        tokens += len;
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
+
+/// Scale to unit length, as OpenAI embeddings are
+pub fn normalize(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// `text` split into overlapping chunks by the model's tokenizer, or by words
+/// when the backend has none
+pub fn chunk_text(loaded: &dyn LoadedModel, text: &str, chunking: Chunking) -> Vec<String> {
+    let pieces = loaded
+        .token_pieces(text)
+        .unwrap_or_else(|| word_pieces(text));
+    chunk_pieces(&pieces, chunking.tokens, chunking.overlap)
+}
+
+/// Look up and load an embedding model
+pub async fn load_model(
+    state: &AppState,
+    model: &str,
+) -> Result<Box<dyn LoadedModel>, ShimmyError> {
+    if let Some(rejection) = state.registry.rejection(model) {
+        return Err(ShimmyError::Load(rejection));
+    }
+    let Some(spec) = state.registry.to_spec(model) else {
+        return Err(ShimmyError::ModelNotFound {
+            name: model.to_string(),
+        });
+    };
+    state
+        .load_model(&spec)
+        .await
+        .map_err(|e| ShimmyError::from_load(std::path::Path::new(model), e))
+}
+
+/// Embed `texts` with an already loaded model, normalized like the endpoint's output
+pub async fn embed_loaded(
+    loaded: &dyn LoadedModel,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, ShimmyError> {
+    let mut vectors = loaded
+        .embed(texts)
+        .await
+        .map_err(|e| ShimmyError::GenerationError {
+            reason: e.to_string(),
+        })?;
+    vectors.iter_mut().for_each(|v| normalize(v));
+    Ok(vectors)
+}
+
+/// Load `model` and embed `texts`
+pub async fn embed_texts(
+    state: &AppState,
+    model: &str,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, ShimmyError> {
+    let loaded = load_model(state, model).await?;
+    embed_loaded(loaded.as_ref(), texts).await
+}
+
+pub async fn embeddings(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<EmbeddingRequest>,
+) -> Response {
+    if let Some(rejection) = state.registry.rejection(&req.model) {
+        return ShimmyError::Load(rejection).into_response();
+    }
+    let Some(spec) = state.registry.to_spec(&req.model) else {
+        return ShimmyError::ModelNotFound { name: req.model }.into_response();
+    };
+
+    #[cfg(feature = "vision")]
+    if let Some(images) = req.input.images() {
+        if req.chunking.is_some() {
+            return ShimmyError::from(InvalidParameter::new(
+                "chunking",
+                "only applies to text input",
+            ))
+            .into_response();
+        }
+        return image_embeddings(&state, &spec, &req.model, images).await;
+    }
+
+    let inputs = req.input.into_vec();
+    if inputs.is_empty() {
+        return ShimmyError::from(InvalidParameter::new("input", "must not be empty"))
+            .into_response();
+    }
+    if let Some(chunking) = &req.chunking {
+        if let Err(e) = chunking.validate() {
+            return ShimmyError::from(e).into_response();
+        }
+    }
+
+    let loaded = match state.load_model(&spec).await {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            tracing::error!("Failed to load model '{}': {:?}", req.model, e);
+            return crate::api_errors::model_load_failed_response(&req.model, e);
+        }
+    };
+
+    // (input index, chunk index, text) in output order
+    let mut items: Vec<(usize, Option<usize>, String)> = Vec::new();
+    for (index, text) in inputs.into_iter().enumerate() {
+        match req.chunking {
+            Some(chunking) => {
+                for (chunk, piece) in chunk_text(loaded.as_ref(), &text, chunking)
+                    .into_iter()
+                    .enumerate()
+                {
+                    items.push((index, Some(chunk), piece));
+                }
+            }
+            None => items.push((index, None, text)),
+        }
+    }
+    if items.len() > MAX_INPUTS {
+        return ShimmyError::from(InvalidParameter::new(
+            "input",
+            format!(
+                "{} strings after chunking; at most {} per request",
+                items.len(),
+                MAX_INPUTS
+            ),
+        ))
+        .into_response();
+    }
+
+    let texts: Vec<String> = items.iter().map(|(_, _, text)| text.clone()).collect();
+    let prompt_tokens = texts
+        .iter()
+        .map(|t| {
+            loaded
+                .count_tokens(t)
+                .unwrap_or_else(|| crate::truncation::estimate_tokens(t))
+        })
+        .sum();
+    let vectors = match loaded.embed(&texts).await {
+        Ok(vectors) => vectors,
+        Err(e) => {
+            return ShimmyError::GenerationError {
+                reason: e.to_string(),
+            }
+            .into_response()
+        }
+    };
+
+    let chunked = req.chunking.is_some();
+    let data = items
+        .into_iter()
+        .zip(vectors)
+        .map(|((index, chunk, text), mut embedding)| {
+            normalize(&mut embedding);
+            EmbeddingData {
+                object: "embedding".to_string(),
+                index,
+                chunk,
+                text: chunked.then_some(text),
+                embedding,
+            }
+        })
+        .collect();
+
+    Json(EmbeddingResponse {
+        object: "list".to_string(),
+        data,
+        model: req.model,
+        usage: EmbeddingUsage {
+            prompt_tokens,
+            total_tokens: prompt_tokens,
+        },
+    })
+    .into_response()
+}
+
+/// Embed images with a vision model: each is preprocessed as for
+/// `/api/vision` and encoded by the model's projector, skipping generation
+#[cfg(feature = "vision")]
+async fn image_embeddings(
+    state: &AppState,
+    spec: &crate::engine::ModelSpec,
+    model: &str,
+    images: &[crate::vision::ImageInput],
+) -> Response {
+    if images.len() > MAX_INPUTS {
+        return ShimmyError::from(InvalidParameter::new(
+            "input",
+            format!(
+                "{} images; at most {} per request",
+                images.len(),
+                MAX_INPUTS
+            ),
+        ))
+        .into_response();
+    }
+    let Some(license_manager) = state.vision_license_manager.as_ref() else {
+        return ShimmyError::BackendNotAvailable {
+            backend: "vision licensing".to_string(),
+        }
+        .into_response();
+    };
+    let license = std::env::var("SHIMMY_LICENSE_KEY")
+        .ok()
+        .or_else(crate::license_store::cached);
+
+    let loaded = match state.load_model(spec).await {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            tracing::error!("Failed to load model '{}': {:?}", model, e);
+            return crate::api_errors::model_load_failed_response(model, e);
+        }
+    };
+    let vectors = match crate::vision::embed_images(
+        images,
+        loaded.as_ref(),
+        license.as_deref(),
+        license_manager,
+    )
+    .await
+    {
+        Ok(vectors) => vectors,
+        Err(e) => return e.into_response(),
+    };
+
+    let data = vectors
+        .into_iter()
+        .enumerate()
+        .map(|(index, embedding)| EmbeddingData {
+            object: "embedding".to_string(),
+            index,
+            chunk: None,
+            text: None,
+            embedding,
+        })
+        .collect();
+    // Image tokens aren't counted against any budget
+    Json(EmbeddingResponse {
+        object: "list".to_string(),
+        data,
+        model: model.to_string(),
+        usage: EmbeddingUsage {
+            prompt_tokens: 0,
+            total_tokens: 0,
+        },
+    })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pieces(text: &str) -> Vec<String> {
+        text.chars().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn test_chunk_pieces_with_overlap() {
+        assert_eq!(
+            chunk_pieces(&pieces("abcdefg"), 3, 1),
+            vec!["abc", "cde", "efg"]
+        );
+        assert_eq!(
+            chunk_pieces(&pieces("abcdefgh"), 3, 1),
+            vec!["abc", "cde", "efg", "gh"]
+        );
+        assert_eq!(chunk_pieces(&pieces("abcdef"), 3, 0), vec!["abc", "def"]);
+        assert_eq!(chunk_pieces(&pieces("ab"), 3, 1), vec!["ab"]);
+        assert_eq!(chunk_pieces(&[], 3, 1), vec![""]);
+    }
+
+    #[test]
+    fn test_plan_batches_preserves_order() {
+        let batches = plan_batches(&[3, 3, 3, 10, 1, 1], 6, 8);
+        assert_eq!(batches, vec![vec![0, 1], vec![2], vec![3], vec![4, 5]]);
+        let capped = plan_batches(&[1; 5], 100, 2);
+        assert_eq!(capped, vec![vec![0, 1], vec![2, 3], vec![4]]);
+        assert!(plan_batches(&[], 10, 2).is_empty());
+    }
+
+    #[test]
+    fn test_request_parsing_and_chunking_validation() {
+        let req: EmbeddingRequest = serde_json::from_str(
+            r#"{"model": "m", "input": ["a", "b"], "chunking": {"tokens": 256, "overlap": 32}}"#,
+        )
+        .unwrap();
+        assert_eq!(req.input.into_vec().len(), 2);
+        assert!(req.chunking.unwrap().validate().is_ok());
+
+        let single: EmbeddingRequest =
+            serde_json::from_str(r#"{"model": "m", "input": "hello"}"#).unwrap();
+        assert_eq!(single.input.into_vec(), vec!["hello".to_string()]);
+
+        let bad = Chunking {
+            tokens: 4,
+            overlap: 4,
+        };
+        assert_eq!(bad.validate().unwrap_err().param, "chunking");
+    }
+
+    #[tokio::test]
+    async fn test_bad_requests_rejected_before_load() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::{ModelEntry, Registry};
+        use axum::http::StatusCode;
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry {
+            name: "m".to_string(),
+            base_path: std::path::PathBuf::from("/nonexistent/m.gguf"),
+            lora_path: None,
+            template: None,
+            ctx_len: None,
+            n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
+        });
+        let engine = Box::new(InferenceEngineAdapter::new());
+        let state = Arc::new(AppState::new(engine, registry));
+
+        let cases = [
+            (r#"{"model": "nope", "input": "x"}"#, StatusCode::NOT_FOUND),
+            (r#"{"model": "m", "input": []}"#, StatusCode::BAD_REQUEST),
+            (
+                r#"{"model": "m", "input": "x", "chunking": {"tokens": 0}}"#,
+                StatusCode::BAD_REQUEST,
+            ),
+        ];
+        for (body, status) in cases {
+            let req: EmbeddingRequest = serde_json::from_str(body).unwrap();
+            let response = embeddings(State(state.clone()), Json(req)).await;
+            assert_eq!(response.status(), status, "{body}");
+        }
+    }
+
+    #[cfg(feature = "vision")]
+    #[test]
+    fn test_image_inputs_parse_apart_from_text() {
+        let req: EmbeddingRequest = serde_json::from_str(
+            r#"{"model": "m", "input": [{"url": "https://example.com/a.png"}, {"image_base64": "AAAA"}]}"#,
+        )
+        .unwrap();
+        let images = req.input.images().unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[1].image_base64.as_deref(), Some("AAAA"));
+
+        let single: EmbeddingRequest = serde_json::from_str(
+            r#"{"model": "m", "input": {"url": "https://example.com/a.png"}}"#,
+        )
+        .unwrap();
+        assert_eq!(single.input.images().unwrap().len(), 1);
+
+        for text in [r#""hello""#, r#"["a", "b"]"#, "[]"] {
+            let req: EmbeddingRequest =
+                serde_json::from_str(&format!(r#"{{"model": "m", "input": {}}}"#, text)).unwrap();
+            assert!(req.input.images().is_none(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_normalize_and_word_pieces() {
+        let mut v = vec![3.0, 4.0];
+        normalize(&mut v);
+        assert_eq!(v, vec![0.6, 0.8]);
+        let mut zero = vec![0.0, 0.0];
+        normalize(&mut zero);
+        assert_eq!(zero, vec![0.0, 0.0]);
+
+        assert_eq!(
+            word_pieces("one two  three"),
+            vec!["one ", "two ", " ", "three"]
+        );
+    }
+}
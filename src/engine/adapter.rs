@@ -1,7 +1,14 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::Instrument;
 
-use super::{GenOptions, InferenceEngine, LoadedModel, ModelSpec};
+use super::{
+    GenOptions, Generation, InferenceEngine, LoadedModel, ModelMemoryUsage, ModelSpec,
+    RunningModel, TokenDetail,
+};
 
 #[cfg(feature = "huggingface")]
 use super::{UniversalEngine, UniversalModel, UniversalModelSpec};
@@ -12,10 +19,16 @@ pub struct InferenceEngineAdapter {
     huggingface_engine: super::huggingface::HuggingFaceEngine,
     #[cfg(feature = "llama")]
     llama_engine: super::llama::LlamaEngine,
+    /// Loads llama models in child processes instead, when isolation is on
+    #[cfg(feature = "llama")]
+    worker_engine: Option<super::worker::WorkerEngine>,
     #[cfg(feature = "mlx")]
     mlx_engine: super::mlx::MLXEngine,
     safetensors_engine: super::safetensors_native::SafeTensorsEngine,
     // Note: loaded_models removed as caching is not currently implemented
+    /// Models handed out by `load` that haven't been dropped yet, for `/api/ps`
+    running: Arc<Mutex<HashMap<u64, RunningModel>>>,
+    next_load_id: AtomicU64,
 }
 
 impl Default for InferenceEngineAdapter {
@@ -31,9 +44,13 @@ impl InferenceEngineAdapter {
             huggingface_engine: super::huggingface::HuggingFaceEngine::new(),
             #[cfg(feature = "llama")]
             llama_engine: super::llama::LlamaEngine::new(),
+            #[cfg(feature = "llama")]
+            worker_engine: None,
             #[cfg(feature = "mlx")]
             mlx_engine: super::mlx::MLXEngine::new(),
             safetensors_engine: super::safetensors_native::SafeTensorsEngine::new(),
+            running: Arc::new(Mutex::new(HashMap::new())),
+            next_load_id: AtomicU64::new(0),
         }
     }
 
@@ -44,9 +61,13 @@ impl InferenceEngineAdapter {
             huggingface_engine: super::huggingface::HuggingFaceEngine::new(),
             #[cfg(feature = "llama")]
             llama_engine: super::llama::LlamaEngine::new_with_backend(_gpu_backend),
+            #[cfg(feature = "llama")]
+            worker_engine: None,
             #[cfg(feature = "mlx")]
             mlx_engine: super::mlx::MLXEngine::new(),
             safetensors_engine: super::safetensors_native::SafeTensorsEngine::new(),
+            running: Arc::new(Mutex::new(HashMap::new())),
+            next_load_id: AtomicU64::new(0),
         }
     }
 
@@ -57,6 +78,13 @@ impl InferenceEngineAdapter {
         self
     }
 
+    /// Load llama models in crash-isolated `worker` processes started with `args`
+    #[cfg(feature = "llama")]
+    pub fn with_worker_isolation(mut self, args: Vec<String>) -> Self {
+        self.worker_engine = Some(super::worker::WorkerEngine::new(args));
+        self
+    }
+
     /// Auto-detect best backend for model
     fn select_backend(&self, spec: &ModelSpec) -> BackendChoice {
         // Check file extension and path patterns to determine optimal backend
@@ -193,12 +221,44 @@ enum BackendChoice {
     SafeTensors,
 }
 
+impl BackendChoice {
+    fn label(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "llama")]
+            BackendChoice::Llama => "llama",
+            #[cfg(feature = "huggingface")]
+            BackendChoice::HuggingFace => "huggingface",
+            #[cfg(feature = "mlx")]
+            BackendChoice::MLX => "mlx",
+            BackendChoice::SafeTensors => "safetensors",
+        }
+    }
+}
+
 #[async_trait]
 impl InferenceEngine for InferenceEngineAdapter {
     async fn load(&self, spec: &ModelSpec) -> Result<Box<dyn LoadedModel>> {
+        // Local model files must exist; HuggingFace model IDs are resolved by the backend
+        let is_model_file = matches!(
+            spec.base_path.extension().and_then(|s| s.to_str()),
+            Some("gguf" | "safetensors" | "npz" | "mlx")
+        );
+        if is_model_file && !spec.base_path.exists() {
+            return Err(super::LoadError::FileNotFound {
+                path: spec.base_path.clone(),
+            }
+            .into());
+        }
+
+        // Split GGUFs need every shard; only the first carries the architecture
+        let shards = super::gguf::resolve_split(&spec.base_path)?;
+
+        // Fail fast on architectures llama.cpp would abort on
+        super::gguf::check_architecture(&shards[0])?;
+
         // Select backend and load model directly (no caching for now to avoid complexity)
         let backend = self.select_backend(spec);
-        match backend {
+        let result = match backend {
             BackendChoice::SafeTensors => {
                 // Use native SafeTensors engine - NO Python dependency!
                 self.safetensors_engine.load(spec).await
@@ -209,7 +269,10 @@ impl InferenceEngine for InferenceEngineAdapter {
                 self.mlx_engine.load(spec).await
             }
             #[cfg(feature = "llama")]
-            BackendChoice::Llama => self.llama_engine.load(spec).await,
+            BackendChoice::Llama => match &self.worker_engine {
+                Some(worker_engine) => worker_engine.load(spec).await,
+                None => self.llama_engine.load(spec).await,
+            },
             #[cfg(feature = "huggingface")]
             BackendChoice::HuggingFace => {
                 // Convert to UniversalModelSpec for huggingface backend (for HF model IDs)
@@ -225,12 +288,214 @@ impl InferenceEngine for InferenceEngineAdapter {
                     device: "cpu".to_string(),
                     n_threads: spec.n_threads,
                 };
-                let universal_model = self.huggingface_engine.load(&universal_spec).await?;
-                Ok(Box::new(UniversalModelWrapper {
-                    model: universal_model,
-                }))
+                self.huggingface_engine
+                    .load(&universal_spec)
+                    .await
+                    .map(|model| Box::new(UniversalModelWrapper { model }) as Box<dyn LoadedModel>)
             }
+        };
+        let model = result.map_err(|e| super::LoadError::categorize(&spec.base_path, e))?;
+        crate::telemetry::Telemetry::global().record_backend(backend.label());
+        Ok(self.track(spec, model))
+    }
+
+    fn running_models(&self) -> Vec<RunningModel> {
+        let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        let mut models: Vec<RunningModel> = running.values().cloned().collect();
+        models.sort_by_key(|m| m.loaded_at);
+        models
+    }
+}
+
+impl InferenceEngineAdapter {
+    /// Record a freshly loaded model and its measured memory until it is dropped
+    fn track(&self, spec: &ModelSpec, model: Box<dyn LoadedModel>) -> Box<dyn LoadedModel> {
+        let id = self.next_load_id.fetch_add(1, Ordering::Relaxed);
+        let entry = RunningModel {
+            name: spec.name.clone(),
+            path: spec.base_path.clone(),
+            loaded_at: chrono::Utc::now(),
+            memory: model.memory_usage(),
+            warnings: model.token_warnings(),
+        };
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, entry);
+
+        Box::new(TrackedModel {
+            inner: model,
+            name: spec.name.clone(),
+            id,
+            running: Arc::clone(&self.running),
+        })
+    }
+}
+
+/// What a generation's tokens are charged to
+type Charges = (
+    Option<Arc<crate::api_keys::ApiKey>>,
+    Option<Arc<crate::rate_limit::Charge>>,
+);
+
+/// Loaded model that unregisters itself from the running list on drop
+struct TrackedModel {
+    inner: Box<dyn LoadedModel>,
+    name: String,
+    id: u64,
+    running: Arc<Mutex<HashMap<u64, RunningModel>>>,
+}
+
+impl TrackedModel {
+    fn span(&self) -> tracing::Span {
+        tracing::info_span!(
+            "llm.generate",
+            llm.model = %self.name,
+            llm.output_tokens = tracing::field::Empty,
+        )
+    }
+
+    /// Count a finished generation for `/metrics`, its span, and the API key
+    /// and rate limits that asked for it
+    fn meter(
+        &self,
+        span: &tracing::Span,
+        prompt: &str,
+        text: &str,
+        started: std::time::Instant,
+        (api_key, rate_limit): Charges,
+    ) {
+        let count = |text: &str| {
+            self.inner
+                .count_tokens(text)
+                .unwrap_or_else(|| crate::truncation::estimate_tokens(text))
+        };
+        let tokens = count(text);
+        span.record("llm.output_tokens", tokens);
+        crate::prometheus::ServerMetrics::global().record_generation(tokens, started.elapsed());
+        if api_key.is_none() && rate_limit.is_none() {
+            return;
+        }
+        let used = (count(prompt) + tokens) as u64;
+        if let Some(key) = api_key {
+            key.record_tokens(used);
         }
+        if let Some(charge) = rate_limit {
+            charge.record_tokens(used);
+        }
+    }
+}
+
+#[async_trait]
+impl LoadedModel for TrackedModel {
+    async fn generate(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<String> {
+        let span = self.span();
+        let started = std::time::Instant::now();
+        let charges = (opts.api_key.clone(), opts.rate_limit.clone());
+        let text = self
+            .inner
+            .generate(prompt, opts, on_token)
+            .instrument(span.clone())
+            .await?;
+        self.meter(&span, prompt, &text, started, charges);
+        Ok(text)
+    }
+
+    async fn generate_with_finish(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<Generation> {
+        let span = self.span();
+        let started = std::time::Instant::now();
+        let charges = (opts.api_key.clone(), opts.rate_limit.clone());
+        let generation = self
+            .inner
+            .generate_with_finish(prompt, opts, on_token)
+            .instrument(span.clone())
+            .await?;
+        self.meter(&span, prompt, &generation.text, started, charges);
+        Ok(generation)
+    }
+
+    async fn generate_detailed(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(TokenDetail) + Send>>,
+    ) -> Result<Generation> {
+        let span = self.span();
+        let started = std::time::Instant::now();
+        let charges = (opts.api_key.clone(), opts.rate_limit.clone());
+        let generation = self
+            .inner
+            .generate_detailed(prompt, opts, on_token)
+            .instrument(span.clone())
+            .await?;
+        self.meter(&span, prompt, &generation.text, started, charges);
+        Ok(generation)
+    }
+
+    async fn generate_vision(
+        &self,
+        images: &[&[u8]],
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<String> {
+        let span = self.span();
+        let started = std::time::Instant::now();
+        let charges = (opts.api_key.clone(), opts.rate_limit.clone());
+        let text = self
+            .inner
+            .generate_vision(images, prompt, opts, on_token)
+            .instrument(span.clone())
+            .await?;
+        self.meter(&span, prompt, &text, started, charges);
+        Ok(text)
+    }
+
+    fn memory_usage(&self) -> Option<ModelMemoryUsage> {
+        self.inner.memory_usage()
+    }
+
+    fn token_warnings(&self) -> Vec<super::special_tokens::TokenWarning> {
+        self.inner.token_warnings()
+    }
+
+    fn count_tokens(&self, text: &str) -> Option<usize> {
+        self.inner.count_tokens(text)
+    }
+
+    fn token_pieces(&self, text: &str) -> Option<Vec<String>> {
+        self.inner.token_pieces(text)
+    }
+
+    fn supports_adapters(&self) -> bool {
+        self.inner.supports_adapters()
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.inner.embed(inputs).await
+    }
+
+    async fn embed_images(&self, images: &[&[u8]]) -> Result<Vec<Vec<f32>>> {
+        self.inner.embed_images(images).await
+    }
+}
+
+impl Drop for TrackedModel {
+    fn drop(&mut self) {
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
     }
 }
 
@@ -269,6 +534,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         }
     }
 
@@ -360,4 +627,80 @@ mod tests {
             assert_eq!(backend3, BackendChoice::MLX);
         }
     }
+
+    #[tokio::test]
+    async fn test_load_missing_file_is_categorized() {
+        let adapter = InferenceEngineAdapter::new();
+        let spec = create_test_spec("missing", "/nonexistent/dir/missing.safetensors");
+
+        let err = adapter.load(&spec).await.err().expect("load should fail");
+        let load_err = err
+            .downcast_ref::<crate::engine::LoadError>()
+            .expect("error should be a LoadError");
+        assert_eq!(load_err.code(), "MODEL_FILE_NOT_FOUND");
+    }
+
+    struct FixedMemoryModel;
+
+    #[async_trait]
+    impl LoadedModel for FixedMemoryModel {
+        async fn generate(
+            &self,
+            _prompt: &str,
+            _opts: GenOptions,
+            _on_token: Option<Box<dyn FnMut(String) + Send>>,
+        ) -> Result<String> {
+            Ok(String::new())
+        }
+
+        async fn generate_vision(
+            &self,
+            _images: &[&[u8]],
+            _prompt: &str,
+            _opts: GenOptions,
+            _on_token: Option<Box<dyn FnMut(String) + Send>>,
+        ) -> Result<String> {
+            Ok("a cat on a mat".to_string())
+        }
+
+        fn memory_usage(&self) -> Option<ModelMemoryUsage> {
+            Some(ModelMemoryUsage {
+                ram_bytes: 100,
+                vram_bytes: 300,
+            })
+        }
+    }
+
+    #[test]
+    fn test_running_models_tracks_until_drop() {
+        let adapter = InferenceEngineAdapter::new();
+        let spec = create_test_spec("tracked", "tracked.gguf");
+
+        let model = adapter.track(&spec, Box::new(FixedMemoryModel));
+        let running = adapter.running_models();
+        assert_eq!(running.len(), 1);
+        assert_eq!(running[0].name, "tracked");
+        assert_eq!(running[0].memory.unwrap().total_bytes(), 400);
+
+        drop(model);
+        assert!(adapter.running_models().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_vision_generation_is_charged_to_the_api_key() {
+        let adapter = InferenceEngineAdapter::new();
+        let spec = create_test_spec("tracked", "tracked.gguf");
+        let model = adapter.track(&spec, Box::new(FixedMemoryModel));
+        let key = Arc::new(crate::api_keys::ApiKey::new("vision", None, Some(1_000)));
+
+        let opts = GenOptions {
+            api_key: Some(Arc::clone(&key)),
+            ..Default::default()
+        };
+        model
+            .generate_vision(&[], "describe the image", opts, None)
+            .await
+            .unwrap();
+        assert!(key.tokens_today() > 0);
+    }
 }
@@ -0,0 +1,490 @@
+//! Fault injection for resilience testing.
+//!
+//! Timeouts, retries and error mapping are only exercised when a backend
+//! misbehaves, which real models rarely do on demand. The hidden
+//! `--fault-inject <SPEC>` flag (or `SHIMMY_FAULT_INJECT`) wraps the engine
+//! so that backend calls misbehave at random:
+//!
+//! - `delay=P[:MS]`: with probability P, wait up to MS milliseconds (default
+//!   1000) before the call, to trip timeouts and deadlines
+//! - `error=P`: fail generation and embedding calls with an uncategorized
+//!   backend error
+//! - `truncate=P[:N]`: end generation after at most N tokens (default 8), as
+//!   if it hit `max_tokens`; streams stop at the same point
+//! - `load_error=P`: fail model loads with an out-of-memory or backend
+//!   initialization error, categorized like real ones
+//! - `seed=N`: make the sequence of faults reproducible
+//!
+//! For example `--fault-inject "delay=0.2:3000,error=0.05,seed=7"`. Never
+//! enable this in production.
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::{
+    FinishReason, GenOptions, Generation, InferenceEngine, LoadError, LoadedModel,
+    ModelMemoryUsage, ModelSpec, RunningModel, TokenDetail,
+};
+
+pub const FAULT_INJECT_ENV: &str = "SHIMMY_FAULT_INJECT";
+
+/// How often each kind of fault happens
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultConfig {
+    pub delay: f64,
+    pub max_delay: Duration,
+    pub error: f64,
+    pub truncate: f64,
+    pub max_tokens_kept: usize,
+    pub load_error: f64,
+    pub seed: Option<u64>,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        Self {
+            delay: 0.0,
+            max_delay: Duration::from_millis(1000),
+            error: 0.0,
+            truncate: 0.0,
+            max_tokens_kept: 8,
+            load_error: 0.0,
+            seed: None,
+        }
+    }
+}
+
+impl FaultConfig {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut config = Self::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .with_context(|| format!("expected `kind=probability`, got `{}`", part))?;
+            let (value, arg) = match value.split_once(':') {
+                Some((value, arg)) => (value, Some(arg)),
+                None => (value, None),
+            };
+            if key == "seed" {
+                config.seed = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("invalid seed `{}`", value))?,
+                );
+                continue;
+            }
+            let probability: f64 = value
+                .parse()
+                .ok()
+                .filter(|p| (0.0..=1.0).contains(p))
+                .with_context(|| format!("`{}` needs a probability between 0 and 1", key))?;
+            let param = |name: &str| -> Result<Option<u64>> {
+                arg.map(|a| a.parse::<u64>())
+                    .transpose()
+                    .with_context(|| format!("invalid {} for `{}`", name, key))
+            };
+            match key {
+                "delay" => {
+                    config.delay = probability;
+                    if let Some(ms) = param("milliseconds")? {
+                        config.max_delay = Duration::from_millis(ms);
+                    }
+                }
+                "error" => config.error = probability,
+                "truncate" => {
+                    config.truncate = probability;
+                    if let Some(n) = param("token count")? {
+                        config.max_tokens_kept = n as usize;
+                    }
+                }
+                "load_error" => config.load_error = probability,
+                other => bail!(
+                    "unknown fault `{}` (expected delay, error, truncate, load_error or seed)",
+                    other
+                ),
+            }
+        }
+        Ok(config)
+    }
+
+    /// The faults `flag` asks for, else those in `SHIMMY_FAULT_INJECT`
+    pub fn resolve(flag: Option<&str>) -> Result<Option<Self>> {
+        let spec = match flag {
+            Some(spec) => spec.to_string(),
+            None => match std::env::var(FAULT_INJECT_ENV) {
+                Ok(spec) if !spec.trim().is_empty() => spec,
+                _ => return Ok(None),
+            },
+        };
+        Self::parse(&spec)
+            .map(Some)
+            .with_context(|| format!("invalid fault injection spec `{}`", spec))
+    }
+}
+
+/// Wrap `engine` when fault injection is configured, returning the faults
+/// in effect so the caller can warn about them
+pub fn wrap(
+    engine: Box<dyn InferenceEngine>,
+    flag: Option<&str>,
+) -> Result<(Box<dyn InferenceEngine>, Option<FaultConfig>)> {
+    match FaultConfig::resolve(flag)? {
+        Some(config) => Ok((
+            Box::new(FaultyEngine::new(engine, config.clone())),
+            Some(config),
+        )),
+        None => Ok((engine, None)),
+    }
+}
+
+/// Shared dice for an engine and the models it loaded
+#[derive(Debug)]
+struct Faults {
+    config: FaultConfig,
+    rng: Mutex<StdRng>,
+}
+
+impl Faults {
+    fn roll(&self, probability: f64) -> bool {
+        probability > 0.0 && self.rng().gen_bool(probability)
+    }
+
+    fn rng(&self) -> std::sync::MutexGuard<'_, StdRng> {
+        self.rng.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Maybe wait before `call`
+    async fn delay(&self, call: &str) {
+        if self.roll(self.config.delay) {
+            let max = self.config.max_delay.as_millis() as u64;
+            let wait = Duration::from_millis(self.rng().gen_range(0..=max));
+            tracing::debug!("Fault injection: delaying {} by {:?}", call, wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Maybe wait, then maybe fail: what generation and embedding calls go
+    /// through
+    async fn before(&self, call: &str) -> Result<()> {
+        self.delay(call).await;
+        if self.roll(self.config.error) {
+            return Err(anyhow!("Injected fault: {} failed", call));
+        }
+        Ok(())
+    }
+
+    /// How many tokens to let through, when this call is truncated
+    fn truncation(&self) -> Option<usize> {
+        self.roll(self.config.truncate)
+            .then(|| self.rng().gen_range(0..=self.config.max_tokens_kept))
+    }
+}
+
+/// An engine whose loads and models fail as [`FaultConfig`] says
+pub struct FaultyEngine {
+    inner: Box<dyn InferenceEngine>,
+    faults: Arc<Faults>,
+}
+
+impl FaultyEngine {
+    pub fn new(inner: Box<dyn InferenceEngine>, config: FaultConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            inner,
+            faults: Arc::new(Faults {
+                config,
+                rng: Mutex::new(rng),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl InferenceEngine for FaultyEngine {
+    async fn load(&self, spec: &ModelSpec) -> Result<Box<dyn LoadedModel>> {
+        self.faults.delay("model load").await;
+        if self.faults.roll(self.faults.config.load_error) {
+            let details = "injected fault".to_string();
+            let err = if self.faults.rng().gen_bool(0.5) {
+                LoadError::OutOfMemory { details }
+            } else {
+                LoadError::BackendInitFailed { details }
+            };
+            return Err(err.into());
+        }
+        let inner = self.inner.load(spec).await?;
+        Ok(Box::new(FaultyModel {
+            inner,
+            faults: self.faults.clone(),
+        }))
+    }
+
+    fn running_models(&self) -> Vec<RunningModel> {
+        self.inner.running_models()
+    }
+}
+
+type TokenSink<T> = Box<dyn FnMut(T) + Send>;
+
+struct FaultyModel {
+    inner: Box<dyn LoadedModel>,
+    faults: Arc<Faults>,
+}
+
+/// A token callback passing on the first `keep` tokens to `on_token` and
+/// recording their text, which becomes the truncated output
+fn truncating<T: Send + 'static>(
+    keep: usize,
+    mut on_token: Option<TokenSink<T>>,
+    text: fn(&T) -> &str,
+) -> (TokenSink<T>, Arc<Mutex<String>>) {
+    let kept = Arc::new(Mutex::new(String::new()));
+    let out = kept.clone();
+    let mut seen = 0;
+    let callback = Box::new(move |token: T| {
+        if seen < keep {
+            seen += 1;
+            out.lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push_str(text(&token));
+            if let Some(on_token) = on_token.as_mut() {
+                on_token(token);
+            }
+        }
+    });
+    (callback, kept)
+}
+
+fn kept_text(kept: &Mutex<String>) -> String {
+    std::mem::take(&mut *kept.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+fn truncated(kept: &Mutex<String>, generation: Generation) -> Generation {
+    Generation {
+        text: kept_text(kept),
+        finish_reason: FinishReason::Length,
+        ..generation
+    }
+}
+
+#[async_trait]
+impl LoadedModel for FaultyModel {
+    async fn generate(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<String> {
+        self.faults.before("generation").await?;
+        let Some(keep) = self.faults.truncation() else {
+            return self.inner.generate(prompt, opts, on_token).await;
+        };
+        let (callback, kept) = truncating(keep, on_token, String::as_str);
+        self.inner.generate(prompt, opts, Some(callback)).await?;
+        Ok(kept_text(&kept))
+    }
+
+    async fn generate_with_finish(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<Generation> {
+        self.faults.before("generation").await?;
+        let Some(keep) = self.faults.truncation() else {
+            return self
+                .inner
+                .generate_with_finish(prompt, opts, on_token)
+                .await;
+        };
+        let (callback, kept) = truncating(keep, on_token, String::as_str);
+        let generation = self
+            .inner
+            .generate_with_finish(prompt, opts, Some(callback))
+            .await?;
+        Ok(truncated(&kept, generation))
+    }
+
+    async fn generate_detailed(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(TokenDetail) + Send>>,
+    ) -> Result<Generation> {
+        self.faults.before("generation").await?;
+        let Some(keep) = self.faults.truncation() else {
+            return self.inner.generate_detailed(prompt, opts, on_token).await;
+        };
+        let (callback, kept) = truncating(keep, on_token, |t: &TokenDetail| t.text.as_str());
+        let generation = self
+            .inner
+            .generate_detailed(prompt, opts, Some(callback))
+            .await?;
+        Ok(truncated(&kept, generation))
+    }
+
+    async fn generate_vision(
+        &self,
+        images: &[&[u8]],
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<String> {
+        self.faults.before("vision generation").await?;
+        let Some(keep) = self.faults.truncation() else {
+            return self
+                .inner
+                .generate_vision(images, prompt, opts, on_token)
+                .await;
+        };
+        let (callback, kept) = truncating(keep, on_token, String::as_str);
+        self.inner
+            .generate_vision(images, prompt, opts, Some(callback))
+            .await?;
+        Ok(kept_text(&kept))
+    }
+
+    fn memory_usage(&self) -> Option<ModelMemoryUsage> {
+        self.inner.memory_usage()
+    }
+
+    fn token_warnings(&self) -> Vec<super::special_tokens::TokenWarning> {
+        self.inner.token_warnings()
+    }
+
+    fn count_tokens(&self, text: &str) -> Option<usize> {
+        self.inner.count_tokens(text)
+    }
+
+    fn token_pieces(&self, text: &str) -> Option<Vec<String>> {
+        self.inner.token_pieces(text)
+    }
+
+    fn supports_adapters(&self) -> bool {
+        self.inner.supports_adapters()
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.faults.before("embedding").await?;
+        self.inner.embed(inputs).await
+    }
+
+    async fn embed_images(&self, images: &[&[u8]]) -> Result<Vec<Vec<f32>>> {
+        self.faults.before("image embedding").await?;
+        self.inner.embed_images(images).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Streams one token per word of the prompt
+    struct Words;
+
+    #[async_trait]
+    impl LoadedModel for Words {
+        async fn generate(
+            &self,
+            prompt: &str,
+            _opts: GenOptions,
+            mut on_token: Option<Box<dyn FnMut(String) + Send>>,
+        ) -> Result<String> {
+            for word in prompt.split_inclusive(' ') {
+                if let Some(on_token) = on_token.as_mut() {
+                    on_token(word.to_string());
+                }
+            }
+            Ok(prompt.to_string())
+        }
+    }
+
+    struct WordsEngine;
+
+    #[async_trait]
+    impl InferenceEngine for WordsEngine {
+        async fn load(&self, _spec: &ModelSpec) -> Result<Box<dyn LoadedModel>> {
+            Ok(Box::new(Words))
+        }
+    }
+
+    fn spec() -> ModelSpec {
+        ModelSpec {
+            name: "words".to_string(),
+            base_path: "words.gguf".into(),
+            lora_path: None,
+            template: None,
+            ctx_len: 512,
+            n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
+        }
+    }
+
+    async fn load(spec_text: &str) -> Result<Box<dyn LoadedModel>> {
+        let config = FaultConfig::parse(spec_text).unwrap();
+        FaultyEngine::new(Box::new(WordsEngine), config)
+            .load(&spec())
+            .await
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        let config = FaultConfig::parse("delay=0.25:3000, error=0.1,truncate=1:2,seed=7").unwrap();
+        assert_eq!(config.delay, 0.25);
+        assert_eq!(config.max_delay, Duration::from_millis(3000));
+        assert_eq!(config.error, 0.1);
+        assert_eq!((config.truncate, config.max_tokens_kept), (1.0, 2));
+        assert_eq!(config.seed, Some(7));
+        assert_eq!(FaultConfig::parse("").unwrap(), FaultConfig::default());
+
+        assert!(FaultConfig::parse("error=1.5").is_err());
+        assert!(FaultConfig::parse("explode=0.5").is_err());
+        assert!(FaultConfig::parse("delay").is_err());
+        assert!(FaultConfig::parse("delay=0.5:soon").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_injected_failures() {
+        let err = load("load_error=1").await.err().unwrap();
+        assert!(err.downcast_ref::<LoadError>().is_some());
+
+        let model = load("error=1").await.unwrap();
+        let err = model.generate("a b", GenOptions::default(), None).await;
+        assert!(err.unwrap_err().to_string().contains("Injected fault"));
+        assert!(model.embed(&["a".to_string()]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_truncation_cuts_output_and_stream() {
+        let model = load("truncate=1:2,seed=1").await.unwrap();
+        let streamed = Arc::new(Mutex::new(String::new()));
+        let sink = streamed.clone();
+        let on_token: Box<dyn FnMut(String) + Send> =
+            Box::new(move |t| sink.lock().unwrap().push_str(&t));
+        let generation = model
+            .generate_with_finish("one two three four", GenOptions::default(), Some(on_token))
+            .await
+            .unwrap();
+        assert_eq!(generation.finish_reason, FinishReason::Length);
+        assert!(["", "one ", "one two "].contains(&generation.text.as_str()));
+        assert_eq!(*streamed.lock().unwrap(), generation.text);
+    }
+
+    #[tokio::test]
+    async fn test_no_faults_passes_through() {
+        let model = load("delay=1:0,error=0,truncate=0").await.unwrap();
+        let text = model
+            .generate("one two", GenOptions::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(text, "one two");
+    }
+}
@@ -0,0 +1,510 @@
+//! GGUF header inspection and the architecture capability matrix.
+//!
+//! llama.cpp aborts with an opaque assert when handed an architecture it
+//! doesn't know, so the registry reads `general.architecture` up front and
+//! rejects models no compiled backend can run. The same header tells
+//! embedding models apart from chat models.
+//!
+//! Large quants ship split as `name-00001-of-0000N.gguf`; llama.cpp loads the
+//! set from the first shard, so the helpers here resolve and validate the
+//! siblings before anything is registered or loaded.
+
+use super::LoadError;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Architectures accepted by the bundled llama.cpp
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+#[rustfmt::skip]
+pub const LLAMA_ARCHITECTURES: &[&str] = &[
+    "arcee", "arctic", "arwkv7", "baichuan", "bailingmoe", "bert", "bitnet", "bloom", "chameleon",
+    "chatglm", "codeshell", "cohere2", "command-r", "dbrx", "deci", "deepseek", "deepseek2",
+    "dots1", "ernie4_5", "ernie4_5-moe", "exaone", "exaone4", "falcon", "falcon-h1", "gemma",
+    "gemma2", "gemma3", "gemma3n", "glm4", "glm4moe", "gpt-oss", "gpt2", "gptj", "gptneox",
+    "granite", "granitehybrid", "granitemoe", "grok", "hunyuan-dense", "hunyuan-moe", "internlm2",
+    "jais", "jina-bert-v2", "lfm2", "llama", "llama4", "mamba", "mamba2", "minicpm", "minicpm3",
+    "mpt", "nemotron", "nomic-bert", "nomic-bert-moe", "olmo", "olmo2", "olmoe", "openelm", "orion",
+    "phi2", "phi3", "phimoe", "plamo", "plamo2", "plm", "qwen", "qwen2", "qwen2moe", "qwen2vl",
+    "qwen3", "qwen3moe", "refact", "rwkv6", "rwkv6qwen2", "rwkv7", "smollm3", "stablelm",
+    "starcoder", "starcoder2", "t5", "t5encoder", "xverse",
+];
+
+/// Encoder architectures that only produce embeddings
+pub const EMBEDDING_ARCHITECTURES: &[&str] = &[
+    "bert",
+    "jina-bert-v2",
+    "nomic-bert",
+    "nomic-bert-moe",
+    "t5encoder",
+];
+
+/// Backends compiled into this binary that load GGUF, with the architectures each accepts
+pub fn gguf_backends() -> Vec<(&'static str, &'static [&'static str])> {
+    #[allow(unused_mut)]
+    let mut backends: Vec<(&'static str, &'static [&'static str])> = Vec::new();
+    #[cfg(feature = "llama")]
+    backends.push(("llama.cpp", LLAMA_ARCHITECTURES));
+    backends
+}
+
+const GGUF_TYPE_UINT8: u32 = 0;
+const GGUF_TYPE_INT8: u32 = 1;
+const GGUF_TYPE_UINT16: u32 = 2;
+const GGUF_TYPE_INT16: u32 = 3;
+const GGUF_TYPE_UINT32: u32 = 4;
+const GGUF_TYPE_INT32: u32 = 5;
+const GGUF_TYPE_FLOAT32: u32 = 6;
+const GGUF_TYPE_BOOL: u32 = 7;
+const GGUF_TYPE_STRING: u32 = 8;
+const GGUF_TYPE_ARRAY: u32 = 9;
+const GGUF_TYPE_UINT64: u32 = 10;
+const GGUF_TYPE_INT64: u32 = 11;
+const GGUF_TYPE_FLOAT64: u32 = 12;
+
+/// Longest key or architecture string we'll read; anything longer is corrupt
+const MAX_SHORT_STRING: u64 = 1024;
+
+fn read_u32(r: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(r: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn invalid(msg: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.into())
+}
+
+fn read_short_string(r: &mut impl Read) -> std::io::Result<String> {
+    let len = read_u64(r)?;
+    if len > MAX_SHORT_STRING {
+        return Err(invalid(format!(
+            "string length {len} exceeds {MAX_SHORT_STRING}"
+        )));
+    }
+    let mut buf = vec![0u8; len as usize];
+    r.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| invalid(e.to_string()))
+}
+
+fn scalar_size(value_type: u32) -> Option<u64> {
+    match value_type {
+        GGUF_TYPE_UINT8 | GGUF_TYPE_INT8 | GGUF_TYPE_BOOL => Some(1),
+        GGUF_TYPE_UINT16 | GGUF_TYPE_INT16 => Some(2),
+        GGUF_TYPE_UINT32 | GGUF_TYPE_INT32 | GGUF_TYPE_FLOAT32 => Some(4),
+        GGUF_TYPE_UINT64 | GGUF_TYPE_INT64 | GGUF_TYPE_FLOAT64 => Some(8),
+        _ => None,
+    }
+}
+
+fn skip_value<R: Read + Seek>(r: &mut R, value_type: u32) -> std::io::Result<()> {
+    if let Some(size) = scalar_size(value_type) {
+        r.seek(SeekFrom::Current(size as i64))?;
+        return Ok(());
+    }
+    match value_type {
+        GGUF_TYPE_STRING => {
+            let len = read_u64(r)?;
+            r.seek(SeekFrom::Current(len as i64))?;
+        }
+        GGUF_TYPE_ARRAY => {
+            let elem_type = read_u32(r)?;
+            let count = read_u64(r)?;
+            skip_elements(r, elem_type, count)?;
+        }
+        other => return Err(invalid(format!("unknown GGUF value type {other}"))),
+    }
+    Ok(())
+}
+
+fn skip_elements<R: Read + Seek>(r: &mut R, elem_type: u32, count: u64) -> std::io::Result<()> {
+    match scalar_size(elem_type) {
+        Some(size) => {
+            r.seek(SeekFrom::Current((size * count) as i64))?;
+        }
+        None => {
+            for _ in 0..count {
+                skip_value(r, elem_type)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Open a GGUF (v2+) header, positioned at its first key; returns the key count
+fn open_header(path: &Path) -> std::io::Result<Option<(BufReader<std::fs::File>, u64)>> {
+    let mut r = BufReader::new(std::fs::File::open(path)?);
+
+    let mut magic = [0u8; 4];
+    if r.read_exact(&mut magic).is_err() || &magic != b"GGUF" {
+        return Ok(None);
+    }
+    // v1 used 32-bit counts and predates the architecture key
+    if read_u32(&mut r)? < 2 {
+        return Ok(None);
+    }
+    let _tensor_count = read_u64(&mut r)?;
+    let kv_count = read_u64(&mut r)?;
+    Ok(Some((r, kv_count)))
+}
+
+/// Read `general.architecture` from a GGUF header
+///
+/// Returns `Ok(None)` for files that aren't GGUF (v2+) or don't declare one.
+pub fn read_architecture(path: &Path) -> std::io::Result<Option<String>> {
+    let Some((mut r, kv_count)) = open_header(path)? else {
+        return Ok(None);
+    };
+
+    for _ in 0..kv_count {
+        let key = read_short_string(&mut r)?;
+        let value_type = read_u32(&mut r)?;
+        if key == "general.architecture" && value_type == GGUF_TYPE_STRING {
+            return read_short_string(&mut r).map(Some);
+        }
+        skip_value(&mut r, value_type)?;
+    }
+    Ok(None)
+}
+
+/// Special-token metadata from a GGUF's `tokenizer.ggml.*` keys
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenizerMetadata {
+    /// The vocabulary, indexed by token id
+    pub tokens: Vec<String>,
+    pub bos_token_id: Option<u32>,
+    pub eos_token_id: Option<u32>,
+    /// End-of-turn token, declared by some chat models besides EOS
+    pub eot_token_id: Option<u32>,
+}
+
+/// Read the vocabulary and special token ids from a GGUF header
+///
+/// Returns `Ok(None)` for files that aren't GGUF (v2+).
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+pub fn read_tokenizer(path: &Path) -> std::io::Result<Option<TokenizerMetadata>> {
+    let Some((mut r, kv_count)) = open_header(path)? else {
+        return Ok(None);
+    };
+
+    let mut meta = TokenizerMetadata::default();
+    for _ in 0..kv_count {
+        let key = read_short_string(&mut r)?;
+        let value_type = read_u32(&mut r)?;
+        let id = match key.as_str() {
+            "tokenizer.ggml.bos_token_id" => &mut meta.bos_token_id,
+            "tokenizer.ggml.eos_token_id" => &mut meta.eos_token_id,
+            "tokenizer.ggml.eot_token_id" => &mut meta.eot_token_id,
+            "tokenizer.ggml.tokens" if value_type == GGUF_TYPE_ARRAY => {
+                let elem_type = read_u32(&mut r)?;
+                let count = read_u64(&mut r)?;
+                if elem_type != GGUF_TYPE_STRING {
+                    skip_elements(&mut r, elem_type, count)?;
+                    continue;
+                }
+                for _ in 0..count {
+                    meta.tokens.push(read_short_string(&mut r)?);
+                }
+                continue;
+            }
+            _ => {
+                skip_value(&mut r, value_type)?;
+                continue;
+            }
+        };
+        match value_type {
+            // Some converters write -1 for "none"
+            GGUF_TYPE_UINT32 | GGUF_TYPE_INT32 => {
+                let value = read_u32(&mut r)?;
+                *id = Some(value).filter(|&v| value_type == GGUF_TYPE_UINT32 || (v as i32) >= 0);
+            }
+            _ => skip_value(&mut r, value_type)?,
+        }
+    }
+    Ok(Some(meta))
+}
+
+/// Whether a GGUF is an embedding model: an encoder-only architecture, or a
+/// decoder converted for embeddings, which declares `<arch>.pooling_type`
+/// (e.g. gte-Qwen2)
+pub fn is_embedding_model(path: &Path) -> std::io::Result<bool> {
+    let Some((mut r, kv_count)) = open_header(path)? else {
+        return Ok(false);
+    };
+
+    for _ in 0..kv_count {
+        let key = read_short_string(&mut r)?;
+        let value_type = read_u32(&mut r)?;
+        if key.ends_with(".pooling_type") {
+            return Ok(true);
+        }
+        if key == "general.architecture" && value_type == GGUF_TYPE_STRING {
+            if EMBEDDING_ARCHITECTURES.contains(&read_short_string(&mut r)?.as_str()) {
+                return Ok(true);
+            }
+            continue;
+        }
+        skip_value(&mut r, value_type)?;
+    }
+    Ok(false)
+}
+
+/// Reject a GGUF whose architecture no compiled backend supports
+///
+/// Non-GGUF files, unreadable headers and builds without a GGUF backend pass;
+/// the backend reports those at load time.
+pub fn check_architecture(path: &Path) -> Result<Option<String>, LoadError> {
+    let arch = match read_architecture(path) {
+        Ok(Some(arch)) => arch,
+        Ok(None) => return Ok(None),
+        Err(e) => {
+            tracing::debug!("Could not read GGUF header of {}: {}", path.display(), e);
+            return Ok(None);
+        }
+    };
+
+    let backends = gguf_backends();
+    if backends.is_empty()
+        || backends
+            .iter()
+            .any(|(_, archs)| archs.contains(&arch.as_str()))
+    {
+        return Ok(Some(arch));
+    }
+
+    let supported = backends
+        .iter()
+        .map(|(backend, archs)| format!("{backend}: {}", archs.join(", ")))
+        .collect::<Vec<_>>()
+        .join("; ");
+    Err(LoadError::UnsupportedArchitecture {
+        details: format!(
+            "{} declares architecture '{}', which this build cannot run (supported by {})",
+            path.display(),
+            arch,
+            supported
+        ),
+    })
+}
+
+/// Split GGUF file name parts: `(prefix, shard index, shard count)`
+///
+/// Matches llama.cpp's `%s-%05d-of-%05d.gguf` naming; indexes are 1-based.
+pub fn parse_split_name(file_name: &str) -> Option<(&str, u32, u32)> {
+    let stem = file_name.strip_suffix(".gguf")?;
+    let (head, count) = stem.rsplit_once("-of-")?;
+    let (prefix, index) = head.rsplit_once('-')?;
+    let digits = |s: &str| s.len() == 5 && s.bytes().all(|b| b.is_ascii_digit());
+    if prefix.is_empty() || !digits(index) || !digits(count) {
+        return None;
+    }
+    let (index, count) = (index.parse().ok()?, count.parse().ok()?);
+    if index == 0 || index > count {
+        return None;
+    }
+    Some((prefix, index, count))
+}
+
+/// Every shard of the split set `path` belongs to, first shard first
+///
+/// Returns `None` when `path` isn't a split GGUF name; shards may not exist.
+pub fn split_paths(path: &Path) -> Option<Vec<PathBuf>> {
+    let file_name = path.file_name()?.to_str()?;
+    let (prefix, _, count) = parse_split_name(file_name)?;
+    Some(
+        (1..=count)
+            .map(|i| path.with_file_name(format!("{prefix}-{i:05}-of-{count:05}.gguf")))
+            .collect(),
+    )
+}
+
+/// Resolve a model path to the files the backend will read
+///
+/// Split sets resolve to all shards (first shard first) and fail with the
+/// first missing sibling; anything else resolves to itself.
+pub fn resolve_split(path: &Path) -> Result<Vec<PathBuf>, LoadError> {
+    let Some(shards) = split_paths(path) else {
+        return Ok(vec![path.to_path_buf()]);
+    };
+    if let Some(missing) = shards.iter().find(|p| !p.exists()) {
+        return Err(LoadError::FileNotFound {
+            path: missing.clone(),
+        });
+    }
+    Ok(shards)
+}
+
+/// Combined on-disk size of a model, summing shards of a split set
+pub fn total_size(path: &Path) -> Option<u64> {
+    let shards = split_paths(path).unwrap_or_else(|| vec![path.to_path_buf()]);
+    shards
+        .iter()
+        .map(|p| std::fs::metadata(p).ok().map(|m| m.len()))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn push_string(buf: &mut Vec<u8>, s: &str) {
+        buf.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    /// Minimal GGUF v3 header with a couple of keys before the architecture
+    fn gguf_with_arch(arch: &str) -> Vec<u8> {
+        let mut buf = b"GGUF".to_vec();
+        buf.extend_from_slice(&3u32.to_le_bytes());
+        buf.extend_from_slice(&0u64.to_le_bytes()); // tensors
+        buf.extend_from_slice(&3u64.to_le_bytes()); // kv pairs
+
+        push_string(&mut buf, "general.file_type");
+        buf.extend_from_slice(&GGUF_TYPE_UINT32.to_le_bytes());
+        buf.extend_from_slice(&15u32.to_le_bytes());
+
+        push_string(&mut buf, "tokenizer.ggml.tokens");
+        buf.extend_from_slice(&GGUF_TYPE_ARRAY.to_le_bytes());
+        buf.extend_from_slice(&GGUF_TYPE_STRING.to_le_bytes());
+        buf.extend_from_slice(&2u64.to_le_bytes());
+        push_string(&mut buf, "<s>");
+        push_string(&mut buf, "</s>");
+
+        push_string(&mut buf, "general.architecture");
+        buf.extend_from_slice(&GGUF_TYPE_STRING.to_le_bytes());
+        push_string(&mut buf, arch);
+        buf
+    }
+
+    fn write_temp(bytes: &[u8]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_read_architecture_skips_preceding_keys() {
+        let file = write_temp(&gguf_with_arch("qwen2"));
+        assert_eq!(
+            read_architecture(file.path()).unwrap().as_deref(),
+            Some("qwen2")
+        );
+    }
+
+    #[test]
+    fn test_embedding_model_detection() {
+        let chat = write_temp(&gguf_with_arch("qwen2"));
+        assert!(!is_embedding_model(chat.path()).unwrap());
+        let bert = write_temp(&gguf_with_arch("nomic-bert"));
+        assert!(is_embedding_model(bert.path()).unwrap());
+
+        // A decoder converted for embeddings declares a pooling type
+        let mut gte = gguf_with_arch("qwen2");
+        gte[16..24].copy_from_slice(&4u64.to_le_bytes());
+        push_string(&mut gte, "qwen2.pooling_type");
+        gte.extend_from_slice(&GGUF_TYPE_UINT32.to_le_bytes());
+        gte.extend_from_slice(&2u32.to_le_bytes());
+        assert!(is_embedding_model(write_temp(&gte).path()).unwrap());
+
+        assert!(!is_embedding_model(write_temp(b"not a model").path()).unwrap());
+    }
+
+    #[test]
+    fn test_read_tokenizer_metadata() {
+        let mut buf = gguf_with_arch("llama");
+        buf[16..24].copy_from_slice(&5u64.to_le_bytes());
+        push_string(&mut buf, "tokenizer.ggml.bos_token_id");
+        buf.extend_from_slice(&GGUF_TYPE_UINT32.to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        push_string(&mut buf, "tokenizer.ggml.eot_token_id");
+        buf.extend_from_slice(&GGUF_TYPE_INT32.to_le_bytes());
+        buf.extend_from_slice(&(-1i32).to_le_bytes());
+
+        let meta = read_tokenizer(write_temp(&buf).path()).unwrap().unwrap();
+        assert_eq!(meta.tokens, ["<s>", "</s>"]);
+        assert_eq!(meta.bos_token_id, Some(0));
+        assert_eq!(meta.eos_token_id, None);
+        assert_eq!(meta.eot_token_id, None);
+        assert_eq!(
+            read_tokenizer(write_temp(b"not a model").path()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_non_gguf_has_no_architecture() {
+        let file = write_temp(b"not a model");
+        assert_eq!(read_architecture(file.path()).unwrap(), None);
+        assert!(check_architecture(file.path()).unwrap().is_none());
+    }
+
+    #[cfg(feature = "llama")]
+    #[test]
+    fn test_unknown_architecture_is_rejected_with_supported_list() {
+        let file = write_temp(&gguf_with_arch("clip"));
+        let err = check_architecture(file.path()).unwrap_err();
+        assert_eq!(err.code(), "UNSUPPORTED_ARCHITECTURE");
+        let msg = err.to_string();
+        assert!(msg.contains("'clip'"));
+        assert!(msg.contains("llama.cpp: ") && msg.contains("qwen2"));
+
+        let ok = write_temp(&gguf_with_arch("llama"));
+        assert_eq!(
+            check_architecture(ok.path()).unwrap().as_deref(),
+            Some("llama")
+        );
+    }
+
+    #[test]
+    fn test_parse_split_name() {
+        assert_eq!(
+            parse_split_name("Llama-3.1-70B-Q4_K_M-00002-of-00005.gguf"),
+            Some(("Llama-3.1-70B-Q4_K_M", 2, 5))
+        );
+        assert_eq!(
+            parse_split_name("model-00001-of-00001.gguf"),
+            Some(("model", 1, 1))
+        );
+        assert_eq!(parse_split_name("model.gguf"), None);
+        assert_eq!(parse_split_name("model-00001-of-00004.safetensors"), None);
+        assert_eq!(parse_split_name("model-1-of-4.gguf"), None);
+        assert_eq!(parse_split_name("model-00005-of-00004.gguf"), None);
+        assert_eq!(parse_split_name("-00001-of-00002.gguf"), None);
+    }
+
+    #[test]
+    fn test_resolve_split_requires_every_shard() {
+        let dir = tempfile::tempdir().unwrap();
+        let second = dir.path().join("big-00002-of-00003.gguf");
+        for i in 1..=2 {
+            std::fs::write(
+                dir.path().join(format!("big-0000{i}-of-00003.gguf")),
+                b"shard",
+            )
+            .unwrap();
+        }
+
+        match resolve_split(&second) {
+            Err(LoadError::FileNotFound { path }) => {
+                assert!(path.ends_with("big-00003-of-00003.gguf"))
+            }
+            other => panic!("expected missing shard, got {other:?}"),
+        }
+
+        std::fs::write(dir.path().join("big-00003-of-00003.gguf"), b"shard").unwrap();
+        let shards = resolve_split(&second).unwrap();
+        assert_eq!(shards.len(), 3);
+        assert!(shards[0].ends_with("big-00001-of-00003.gguf"));
+        assert_eq!(total_size(&second), Some(15));
+
+        let single = dir.path().join("single.gguf");
+        assert_eq!(resolve_split(&single).unwrap(), vec![single]);
+    }
+}
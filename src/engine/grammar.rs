@@ -0,0 +1,87 @@
+//! GBNF grammars for constrained generation.
+//!
+//! A grammar in [`GenOptions::grammar`](super::GenOptions::grammar) restricts
+//! sampling to tokens that keep the output inside the language it describes,
+//! so callers that need machine-readable output (JSON mode, vision) get text
+//! that parses instead of prose around it. Only the llama.cpp backend enforces
+//! grammars; the others ignore the field.
+
+use super::InvalidParameter;
+
+/// Any single JSON object, whitespace allowed between tokens
+pub const JSON_OBJECT: &str = r#"root   ::= object
+value  ::= object | array | string | number | ("true" | "false" | "null") ws
+
+object ::=
+  "{" ws (
+            string ":" ws value
+    ("," ws string ":" ws value)*
+  )? "}" ws
+
+array  ::=
+  "[" ws (
+            value
+    ("," ws value)*
+  )? "]" ws
+
+string ::=
+  "\"" (
+    [^"\\\x7F\x00-\x1F] |
+    "\\" (["\\bfnrt] | "u" [0-9a-fA-F]{4})
+  )* "\"" ws
+
+number ::= ("-"? ([0-9] | [1-9] [0-9]{0,15})) ("." [0-9]+)? ([eE] [-+]? [0-9] [1-9]{0,15})? ws
+
+ws ::= | " " | "\n" [ \t]{0,20}
+"#;
+
+/// Name of the start rule every grammar must define
+pub const ROOT_RULE: &str = "root";
+
+/// Cheap structural check before a grammar reaches the backend
+///
+/// llama.cpp only logs parse failures, so catch the common mistakes (empty
+/// text, no `root` rule) here and answer with a 400 instead.
+pub fn validate(grammar: &str) -> Result<(), InvalidParameter> {
+    if grammar.trim().is_empty() {
+        return Err(InvalidParameter::new("grammar", "must not be empty"));
+    }
+    let defines_root = grammar.lines().any(|line| {
+        line.split_once("::=")
+            .is_some_and(|(name, _)| name.trim() == ROOT_RULE)
+    });
+    if !defines_root {
+        return Err(InvalidParameter::new(
+            "grammar",
+            format!("must define a `{}` rule", ROOT_RULE),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_grammar_is_valid() {
+        assert!(validate(JSON_OBJECT).is_ok());
+    }
+
+    #[test]
+    fn rejects_empty_grammar() {
+        let err = validate("  \n").unwrap_err();
+        assert_eq!(err.param, "grammar");
+    }
+
+    #[test]
+    fn rejects_grammar_without_root() {
+        let err = validate("answer ::= \"yes\" | \"no\"").unwrap_err();
+        assert!(err.reason.contains("root"));
+    }
+
+    #[test]
+    fn accepts_root_with_surrounding_whitespace() {
+        assert!(validate("  root ::= \"yes\" | \"no\"\n").is_ok());
+    }
+}
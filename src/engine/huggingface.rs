@@ -374,6 +374,16 @@ mod tests {
             seed: Some(42),
             stream: false,
             stop_tokens: Vec::new(),
+            add_bos: None,
+            repetition: None,
+            max_time_ms: None,
+            grammar: None,
+            top_logprobs: 0,
+            cancel: None,
+            deadline: None,
+            api_key: None,
+            rate_limit: None,
+            adapter: None,
         };
 
         assert_eq!(opts.max_tokens, 100);
@@ -0,0 +1,488 @@
+//! JSON Schema to GBNF, for `response_format: {"type": "json_schema"}`.
+//!
+//! The schema is compiled to a grammar so that sampling can only produce
+//! JSON that validates against it. Most of what structured-output schemas use
+//! is covered: `type` (including lists of types), `properties` and `required`,
+//! `additionalProperties` as a schema for map values, `items` with
+//! `minItems`/`maxItems`, `minLength`/`maxLength`, `enum`, `const`, `anyOf`,
+//! `oneOf`, local `$ref`s into `$defs`/`definitions` (recursive ones too) and
+//! the `date`, `time`, `date-time` and `uuid` formats.
+//!
+//! Keywords a grammar can't enforce, such as `pattern` or `minimum`, are
+//! refused rather than silently ignored, since the output would then no longer
+//! be guaranteed to validate. Objects with `properties` are generated with
+//! exactly those properties, in alphabetical order.
+
+use super::InvalidParameter;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Rules every compiled schema can refer to, as in [`super::grammar::JSON_OBJECT`]
+const PRIMITIVES: &str = r#"value ::= object | array | string | number | boolean | null
+object ::= "{" ws ( string ":" ws value ( "," ws string ":" ws value )* )? "}" ws
+array ::= "[" ws ( value ( "," ws value )* )? "]" ws
+char ::= [^"\\\x7F\x00-\x1F] | "\\" (["\\bfnrt] | "u" [0-9a-fA-F]{4})
+string ::= "\"" char* "\"" ws
+number ::= "-"? ([0-9] | [1-9] [0-9]{0,15}) ("." [0-9]+)? ([eE] [-+]? [0-9] [1-9]{0,15})? ws
+integer ::= "-"? ([0-9] | [1-9] [0-9]{0,15}) ws
+boolean ::= ("true" | "false") ws
+null ::= "null" ws
+ws ::= | " " | "\n" [ \t]{0,20}
+"#;
+
+/// Keywords that constrain values in ways a grammar doesn't express
+const UNSUPPORTED: &[&str] = &[
+    "pattern",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "multipleOf",
+    "allOf",
+    "not",
+    "if",
+    "then",
+    "else",
+    "patternProperties",
+    "propertyNames",
+    "minProperties",
+    "maxProperties",
+    "dependentRequired",
+    "dependentSchemas",
+    "prefixItems",
+    "contains",
+    "uniqueItems",
+];
+
+/// String formats with a grammar of their own
+const FORMATS: &[(&str, &str)] = &[
+    (
+        "date",
+        r#"[0-9]{4} "-" ("0" [1-9] | "1" [0-2]) "-" ("0" [1-9] | [12] [0-9] | "3" [01])"#,
+    ),
+    (
+        "time",
+        r#"([01] [0-9] | "2" [0-3]) ":" [0-5] [0-9] ":" [0-5] [0-9] ("." [0-9]{1,6})? ("Z" | [+-] ([01] [0-9] | "2" [0-3]) ":" [0-5] [0-9])"#,
+    ),
+    ("date-time", r#"format-date "T" format-time"#),
+    (
+        "uuid",
+        r#"[0-9a-fA-F]{8} "-" [0-9a-fA-F]{4} "-" [0-9a-fA-F]{4} "-" [0-9a-fA-F]{4} "-" [0-9a-fA-F]{12}"#,
+    ),
+];
+
+/// GBNF grammar whose language is the JSON documents `schema` accepts
+pub fn to_grammar(schema: &Value) -> Result<String, InvalidParameter> {
+    let mut compiler = Compiler {
+        root: schema,
+        rules: Vec::new(),
+        names: HashSet::new(),
+        refs: HashMap::new(),
+    };
+    compiler.rule(super::grammar::ROOT_RULE, schema)?;
+    let mut grammar = String::new();
+    for (name, body) in &compiler.rules {
+        grammar.push_str(&format!("{} ::= {}\n", name, body));
+    }
+    grammar.push_str(PRIMITIVES);
+    Ok(grammar)
+}
+
+fn unsupported(reason: impl std::fmt::Display) -> InvalidParameter {
+    InvalidParameter::new(
+        "response_format",
+        format!("unsupported JSON schema: {}", reason),
+    )
+}
+
+/// `text` as a GBNF string literal
+fn literal(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A JSON value matched exactly, e.g. for `const` and `enum`
+fn json_literal(value: &Value) -> String {
+    format!("{} ws", literal(&value.to_string()))
+}
+
+/// `{min,max}` repetition of `item`, as GBNF spells it
+fn repeat(item: &str, min: u64, max: Option<u64>) -> String {
+    match (min, max) {
+        (0, Some(0)) => String::new(),
+        (0, None) => format!("{}*", item),
+        (1, None) => format!("{}+", item),
+        (min, None) => format!("{}{{{},}}", item, min),
+        (min, Some(max)) if min == max => format!("{}{{{}}}", item, min),
+        (min, Some(max)) => format!("{}{{{},{}}}", item, min, max),
+    }
+}
+
+struct Compiler<'a> {
+    root: &'a Value,
+    /// Rules in the order they were named
+    rules: Vec<(String, String)>,
+    names: HashSet<String>,
+    /// Rule compiled for each `$ref` target
+    refs: HashMap<String, String>,
+}
+
+impl Compiler<'_> {
+    /// An unused rule name built from `base`
+    fn fresh(&mut self, base: &str) -> String {
+        let base: String = base
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let taken = |name: &str| {
+            PRIMITIVES.lines().any(|line| {
+                line.split_once(" ::=")
+                    .is_some_and(|(rule, _)| rule == name)
+            })
+        };
+        let mut name = base.clone();
+        let mut n = 1;
+        while self.names.contains(&name) || taken(&name) {
+            n += 1;
+            name = format!("{}{}", base, n);
+        }
+        self.names.insert(name.clone());
+        name
+    }
+
+    /// Define `name` as matching `schema`
+    fn rule(&mut self, name: &str, schema: &Value) -> Result<(), InvalidParameter> {
+        self.names.insert(name.to_string());
+        let index = self.rules.len();
+        self.rules.push((name.to_string(), String::new()));
+        self.rules[index].1 = self.expr(name, schema)?;
+        Ok(())
+    }
+
+    /// Define a fresh rule for `schema` and return its name
+    fn named(&mut self, base: &str, schema: &Value) -> Result<String, InvalidParameter> {
+        let name = self.fresh(base);
+        self.rule(&name, schema)?;
+        Ok(name)
+    }
+
+    /// Rule for a built-in string format, defined on first use
+    fn format(&mut self, format: &str) -> Result<String, InvalidParameter> {
+        let name = format!("format-{}", format);
+        if !self.names.contains(&name) {
+            let Some((_, body)) = FORMATS.iter().find(|(f, _)| *f == format) else {
+                return Err(unsupported(format!("`format: {}`", format)));
+            };
+            if format == "date-time" {
+                self.format("date")?;
+                self.format("time")?;
+            }
+            self.names.insert(name.clone());
+            self.rules.push((name.clone(), body.to_string()));
+        }
+        Ok(name)
+    }
+
+    /// GBNF expression matching `schema`; `name` prefixes rules made for its parts
+    fn expr(&mut self, name: &str, schema: &Value) -> Result<String, InvalidParameter> {
+        let schema = match schema {
+            Value::Bool(true) => return Ok("value".to_string()),
+            Value::Bool(false) => return Err(unsupported("`false` matches no value")),
+            Value::Object(schema) => schema,
+            _ => return Err(unsupported("a schema must be an object or a boolean")),
+        };
+        if let Some(keyword) = UNSUPPORTED.iter().find(|k| schema.contains_key(**k)) {
+            return Err(unsupported(format!("`{}` can't be enforced", keyword)));
+        }
+
+        if let Some(reference) = schema.get("$ref") {
+            return self.reference(reference);
+        }
+        if let Some(value) = schema.get("const") {
+            return Ok(json_literal(value));
+        }
+        if let Some(values) = schema.get("enum") {
+            let Some(values) = values.as_array().filter(|v| !v.is_empty()) else {
+                return Err(unsupported("`enum` must be a non-empty array"));
+            };
+            let choices: Vec<String> = values.iter().map(json_literal).collect();
+            return Ok(format!("({})", choices.join(" | ")));
+        }
+        for keyword in ["anyOf", "oneOf"] {
+            if let Some(schemas) = schema.get(keyword) {
+                let Some(schemas) = schemas.as_array().filter(|s| !s.is_empty()) else {
+                    return Err(unsupported(format!(
+                        "`{}` must be a non-empty array",
+                        keyword
+                    )));
+                };
+                let mut choices = Vec::new();
+                for (i, option) in schemas.iter().enumerate() {
+                    choices.push(self.named(&format!("{}-{}", name, i), option)?);
+                }
+                return Ok(format!("({})", choices.join(" | ")));
+            }
+        }
+
+        match schema.get("type") {
+            Some(Value::String(kind)) => self.typed(name, schema, kind),
+            Some(Value::Array(kinds)) => {
+                let mut choices = Vec::new();
+                for kind in kinds {
+                    let Some(kind) = kind.as_str() else {
+                        return Err(unsupported("`type` entries must be strings"));
+                    };
+                    choices.push(self.typed(name, schema, kind)?);
+                }
+                Ok(format!("({})", choices.join(" | ")))
+            }
+            Some(_) => Err(unsupported("`type` must be a string or an array")),
+            None if schema.contains_key("properties")
+                || schema.contains_key("additionalProperties") =>
+            {
+                self.typed(name, schema, "object")
+            }
+            None if schema.contains_key("items") => self.typed(name, schema, "array"),
+            None => Ok("value".to_string()),
+        }
+    }
+
+    /// A `$ref` into the schema's own definitions, compiled once
+    fn reference(&mut self, reference: &Value) -> Result<String, InvalidParameter> {
+        let pointer = reference
+            .as_str()
+            .and_then(|r| r.strip_prefix('#'))
+            .ok_or_else(|| unsupported("only local `$ref`s (starting with `#`) are supported"))?;
+        if let Some(name) = self.refs.get(pointer) {
+            return Ok(name.clone());
+        }
+        let target = self
+            .root
+            .pointer(pointer)
+            .ok_or_else(|| unsupported(format!("`$ref` {:?} points nowhere", pointer)))?;
+        // Named before it is compiled so recursive references resolve
+        let name = self.fresh(&format!("ref{}", pointer));
+        self.refs.insert(pointer.to_string(), name.clone());
+        self.rule(&name, target)?;
+        Ok(name)
+    }
+
+    fn typed(
+        &mut self,
+        name: &str,
+        schema: &Map<String, Value>,
+        kind: &str,
+    ) -> Result<String, InvalidParameter> {
+        let bound = |key: &str| schema.get(key).and_then(Value::as_u64);
+        match kind {
+            "string" => {
+                if let Some(format) = schema.get("format").and_then(Value::as_str) {
+                    let format = self.format(format)?;
+                    return Ok(format!(r#""\"" {} "\"" ws"#, format));
+                }
+                match (bound("minLength"), bound("maxLength")) {
+                    (None, None) => Ok("string".to_string()),
+                    (min, max) => Ok(format!(
+                        r#""\"" {} "\"" ws"#,
+                        repeat("char", min.unwrap_or(0), max)
+                    )),
+                }
+            }
+            "number" | "integer" | "boolean" | "null" => Ok(kind.to_string()),
+            "array" => {
+                let item = match schema.get("items") {
+                    Some(items) => self.named(&format!("{}-item", name), items)?,
+                    None => "value".to_string(),
+                };
+                let (min, max) = (bound("minItems").unwrap_or(0), bound("maxItems"));
+                if max.is_some_and(|max| max < min) {
+                    return Err(unsupported("`maxItems` is less than `minItems`"));
+                }
+                let rest = format!(r#"("," ws {})"#, item);
+                let list = match (min, max) {
+                    (_, Some(0)) => String::new(),
+                    (0, max) => format!("({} {})?", item, repeat(&rest, 0, max.map(|m| m - 1))),
+                    (min, max) => {
+                        format!("{} {}", item, repeat(&rest, min - 1, max.map(|m| m - 1)))
+                    }
+                };
+                Ok(format!(r#""[" ws {} "]" ws"#, list))
+            }
+            "object" => self.object(name, schema),
+            other => Err(unsupported(format!("unknown type `{}`", other))),
+        }
+    }
+
+    fn object(
+        &mut self,
+        name: &str,
+        schema: &Map<String, Value>,
+    ) -> Result<String, InvalidParameter> {
+        let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+            // A map: any keys, values matching `additionalProperties`
+            return match schema.get("additionalProperties") {
+                None | Some(Value::Bool(true)) => Ok("object".to_string()),
+                Some(Value::Bool(false)) => Ok(r#""{" ws "}" ws"#.to_string()),
+                Some(values) => {
+                    let value = self.named(&format!("{}-value", name), values)?;
+                    Ok(format!(
+                        r#""{{" ws (string ":" ws {v} ("," ws string ":" ws {v})*)? "}}" ws"#,
+                        v = value
+                    ))
+                }
+            };
+        };
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|r| r.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        if let Some(missing) = required.iter().find(|r| !properties.contains_key(**r)) {
+            return Err(unsupported(format!(
+                "required property `{}` is not in `properties`",
+                missing
+            )));
+        }
+
+        let (mut present, mut optional) = (Vec::new(), Vec::new());
+        for (key, property) in properties {
+            let value = self.named(&format!("{}-{}", name, key), property)?;
+            let pair = format!(
+                r#"{} ws ":" ws {}"#,
+                literal(&Value::String(key.clone()).to_string()),
+                value
+            );
+            if required.contains(&key.as_str()) {
+                present.push(pair);
+            } else {
+                optional.push(pair);
+            }
+        }
+        let more = |pairs: &[String]| -> String {
+            pairs
+                .iter()
+                .map(|pair| format!(r#" ("," ws {})?"#, pair))
+                .collect()
+        };
+        let body = if !present.is_empty() {
+            let pairs = present.join(r#" "," ws "#);
+            format!("{}{}", pairs, more(&optional))
+        } else if !optional.is_empty() {
+            // The first property present has no comma before it
+            let starts: Vec<String> = (0..optional.len())
+                .map(|i| format!("{}{}", optional[i], more(&optional[i + 1..])))
+                .collect();
+            format!("({})?", starts.join(" | "))
+        } else {
+            String::new()
+        };
+        Ok(format!(r#""{{" ws {} "}}" ws"#, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule<'a>(grammar: &'a str, name: &str) -> &'a str {
+        grammar
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{} ::= ", name)))
+            .unwrap_or_else(|| panic!("no rule {} in\n{}", name, grammar))
+    }
+
+    #[test]
+    fn test_object_with_required_and_optional_properties() {
+        let grammar = to_grammar(&json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "maxLength": 20},
+                "age": {"type": "integer"},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}, "maxItems": 3}
+            },
+            "required": ["name"]
+        }))
+        .unwrap();
+        assert!(super::super::grammar::validate(&grammar).is_ok());
+        assert_eq!(
+            rule(&grammar, "root"),
+            r#""{" ws "\"name\"" ws ":" ws root-name ("," ws "\"age\"" ws ":" ws root-age)? ("," ws "\"tags\"" ws ":" ws root-tags)? "}" ws"#
+        );
+        assert_eq!(rule(&grammar, "root-age"), "integer");
+        assert_eq!(rule(&grammar, "root-name"), r#""\"" char{0,20} "\"" ws"#);
+        assert_eq!(
+            rule(&grammar, "root-tags"),
+            r#""[" ws (root-tags-item ("," ws root-tags-item){0,2})? "]" ws"#
+        );
+        assert_eq!(
+            rule(&grammar, "root-tags-item"),
+            r#"("\"a\"" ws | "\"b\"" ws)"#
+        );
+    }
+
+    #[test]
+    fn test_all_optional_properties_and_refs() {
+        let grammar = to_grammar(&json!({
+            "$defs": {"node": {
+                "type": "object",
+                "properties": {"next": {"anyOf": [{"$ref": "#/$defs/node"}, {"type": "null"}]}}
+            }},
+            "$ref": "#/$defs/node"
+        }))
+        .unwrap();
+        assert_eq!(rule(&grammar, "root"), "ref--defs-node");
+        assert_eq!(
+            rule(&grammar, "ref--defs-node"),
+            r#""{" ws ("\"next\"" ws ":" ws ref--defs-node-next)? "}" ws"#
+        );
+        assert_eq!(
+            rule(&grammar, "ref--defs-node-next"),
+            "(ref--defs-node-next-0 | ref--defs-node-next-1)"
+        );
+        assert_eq!(rule(&grammar, "ref--defs-node-next-0"), "ref--defs-node");
+        assert_eq!(rule(&grammar, "ref--defs-node-next-1"), "null");
+    }
+
+    #[test]
+    fn test_formats_and_unsupported_keywords() {
+        let grammar = to_grammar(&json!({"type": "string", "format": "date-time"})).unwrap();
+        assert_eq!(rule(&grammar, "root"), r#""\"" format-date-time "\"" ws"#);
+        assert!(grammar.contains("format-date ::= "));
+        assert!(grammar.contains("format-time ::= "));
+
+        for schema in [
+            json!({"type": "string", "pattern": "^a+$"}),
+            json!({"type": "integer", "minimum": 0}),
+            json!({"type": "string", "format": "email"}),
+            json!({"$ref": "https://example.com/schema.json"}),
+            json!({"type": "object", "properties": {}, "required": ["id"]}),
+            json!(false),
+        ] {
+            let err = to_grammar(&schema).unwrap_err();
+            assert_eq!(err.param, "response_format");
+            assert!(err.reason.starts_with("unsupported JSON schema"));
+        }
+    }
+
+    #[test]
+    fn test_empty_schema_matches_any_value() {
+        assert_eq!(rule(&to_grammar(&json!({})).unwrap(), "root"), "value");
+        let map = to_grammar(&json!({"additionalProperties": {"type": "number"}})).unwrap();
+        assert_eq!(
+            rule(&map, "root"),
+            r#""{" ws (string ":" ws root-value ("," ws string ":" ws root-value)*)? "}" ws"#
+        );
+    }
+}
@@ -8,6 +8,16 @@ use super::{GenOptions, InferenceEngine, LoadedModel, ModelSpec};
 /// Matches Ollama's approach: use physical cores with intelligent limits
 #[allow(dead_code)]
 fn get_optimal_thread_count() -> i32 {
+    // Hybrid CPUs: one thread per physical performance core we're pinned to
+    if let Some(threads) = crate::util::cpu::inference_threads() {
+        tracing::info!(
+            "Threading: using {} threads on the pinned cores ({})",
+            threads,
+            crate::util::cpu::AFFINITY_ENV
+        );
+        return threads as i32;
+    }
+
     let total_cores = std::thread::available_parallelism()
         .map(|n| n.get() as i32)
         .unwrap_or(4);
@@ -351,16 +361,43 @@ impl InferenceEngine for LlamaEngine {
             use shimmy_llama_cpp_2 as llama;
             use std::num::NonZeroU32;
 
+            // Split GGUFs load from the first shard; llama.cpp finds the rest by
+            // name, so every sibling has to be present and valid up front
+            let shards = super::gguf::resolve_split(&spec.base_path)?;
+            if shards.len() > 1 {
+                info!("Loading split GGUF with {} shards", shards.len());
+            }
+
+            // Fail fast with a categorized error before touching the backend
+            for shard in &shards {
+                super::check_gguf_magic(shard)?;
+            }
+            let model_path = &shards[0];
+
+            let mut progress = crate::load_progress::LoadTracker::start(
+                &spec.name,
+                super::gguf::total_size(&spec.base_path).unwrap_or(0),
+            );
+            if let Err(e) = progress.read_weights(&shards) {
+                // The backend reads the file itself and reports a real error
+                tracing::debug!("Read-ahead of {} failed: {}", model_path.display(), e);
+            }
+
             // Use global singleton backend (fixes Issue #128: BackendAlreadyInitialized)
-            let be = get_or_init_backend()?;
+            let be = get_or_init_backend().map_err(|e| super::LoadError::BackendInitFailed {
+                details: e.to_string(),
+            })?;
 
             // Configure GPU acceleration based on backend
-            let n_gpu_layers = self.gpu_backend.gpu_layers();
+            let n_gpu_layers = spec
+                .n_gpu_layers
+                .unwrap_or_else(|| self.gpu_backend.gpu_layers());
             info!(
                 "Loading model with {} GPU layers ({:?} backend)",
                 n_gpu_layers, self.gpu_backend
             );
 
+            progress.offloading(n_gpu_layers);
             let mut model_params =
                 llama::model::params::LlamaModelParams::default().with_n_gpu_layers(n_gpu_layers);
 
@@ -375,49 +412,60 @@ impl InferenceEngine for LlamaEngine {
             }
 
             // Attempt to load the model with better error handling
-            let model = match llama::model::LlamaModel::load_from_file(
-                be,
-                &spec.base_path,
-                &model_params,
-            ) {
-                Ok(model) => model,
-                Err(e) => {
-                    // Check if this looks like a memory allocation failure
-                    let error_msg = format!("{}", e);
-                    if error_msg.contains("failed to allocate")
-                        || error_msg.contains("CPU_REPACK buffer")
-                    {
-                        let file_size = std::fs::metadata(&spec.base_path)
-                            .map(|m| m.len())
-                            .unwrap_or(0);
-                        let size_gb = file_size as f64 / 1_024_000_000.0;
-
-                        return Err(anyhow!(
-                            "Memory allocation failed for model {} ({:.1}GB). \n\
-                            💡 Possible solutions:\n\
-                            • Use a smaller model (7B instead of 14B parameters)\n\
-                            • Add more system RAM (model needs ~{}GB)\n\
-                            • Enable model quantization (Q4_K_M, Q5_K_M)\n\
-                            • MoE CPU offloading is temporarily disabled (Issue #108)\n\
-                            Original error: {}",
-                            spec.base_path.display(),
-                            size_gb,
-                            (size_gb * 1.5) as u32, // Rough estimate of RAM needed
-                            e
-                        ));
+            let model =
+                match llama::model::LlamaModel::load_from_file(be, model_path, &model_params) {
+                    Ok(model) => model,
+                    Err(e) => {
+                        // Check if this looks like a memory allocation failure
+                        let error_msg = format!("{}", e);
+                        if error_msg.contains("failed to allocate")
+                            || error_msg.contains("CPU_REPACK buffer")
+                        {
+                            let file_size = super::gguf::total_size(&spec.base_path).unwrap_or(0);
+                            let size_gb = file_size as f64 / 1_024_000_000.0;
+
+                            return Err(super::LoadError::OutOfMemory {
+                                details: format!(
+                                    "model {} ({:.1}GB). \n\
+                                💡 Possible solutions:\n\
+                                • Use a smaller model (7B instead of 14B parameters)\n\
+                                • Add more system RAM (model needs ~{}GB)\n\
+                                • Enable model quantization (Q4_K_M, Q5_K_M)\n\
+                                • MoE CPU offloading is temporarily disabled (Issue #108)\n\
+                                Original error: {}",
+                                    spec.base_path.display(),
+                                    size_gb,
+                                    (size_gb * 1.5) as u32, // Rough estimate of RAM needed
+                                    e
+                                ),
+                            }
+                            .into());
+                        }
+
+                        // Categorize other errors where the message allows it
+                        return Err(super::LoadError::categorize(&spec.base_path, e.into()));
                     }
-
-                    // Re-throw other errors as-is
-                    return Err(e.into());
-                }
-            };
+                };
+            let token_warnings = check_special_tokens(&model, model_path, spec);
+            // Low-power mode trades speed for draw on laptops running on battery
+            let power = crate::power::PowerManager::global().profile();
+            let n_threads =
+                power.cap_threads(spec.n_threads.unwrap_or_else(get_optimal_thread_count));
+            let n_batch = power.cap_batch(Self::calculate_adaptive_batch_size(spec.ctx_len));
+            if power == crate::power::PowerProfile::LowPower {
+                info!(
+                    "Low-power profile: {} threads, batch {}",
+                    n_threads, n_batch
+                );
+            }
             let ctx_params = llama::context::params::LlamaContextParams::default()
                 .with_n_ctx(NonZeroU32::new(spec.ctx_len as u32))
-                .with_n_batch(Self::calculate_adaptive_batch_size(spec.ctx_len))
+                .with_n_batch(n_batch)
                 .with_n_ubatch(512)
-                .with_n_threads(spec.n_threads.unwrap_or_else(get_optimal_thread_count))
-                .with_n_threads_batch(spec.n_threads.unwrap_or_else(get_optimal_thread_count));
+                .with_n_threads(n_threads)
+                .with_n_threads_batch(n_threads);
             let ctx_tmp = model.new_context(be, ctx_params)?;
+            let mut adapters = LoraAdapters::default();
             if let Some(ref lora) = spec.lora_path {
                 // Check if it's a SafeTensors file and convert if needed
                 let lora_path = if lora.extension().and_then(|s| s.to_str()) == Some("safetensors")
@@ -431,19 +479,36 @@ impl InferenceEngine for LlamaEngine {
                     lora.clone()
                 };
 
-                let mut adapter = model.lora_adapter_init(&lora_path)?;
-                ctx_tmp
-                    .lora_adapter_set(&mut adapter, 1.0)
-                    .map_err(|e| anyhow!("lora set: {e:?}"))?;
-                info!(adapter=%lora_path.display(), "LoRA adapter attached");
+                adapters.default = Some(lora_path.clone());
+                adapters.select(&model, &ctx_tmp, Some(&lora_path))?;
+            }
+            #[cfg(feature = "vision")]
+            let projector = spec
+                .mmproj_path
+                .as_deref()
+                .map(|path| load_projector(&model, path, spec, n_threads, n_gpu_layers > 0))
+                .transpose()?;
+            #[cfg(not(feature = "vision"))]
+            if let Some(path) = &spec.mmproj_path {
+                tracing::warn!(
+                    "Ignoring projector {}: built without vision support (--features vision)",
+                    path.display()
+                );
             }
             // Store both model and context together to maintain proper lifetimes
             // The context lifetime is tied to &model; storing both in the same struct ensures safety
             let ctx: llama::context::LlamaContext<'static> =
                 unsafe { std::mem::transmute(ctx_tmp) };
+            progress.ready();
             Ok(Box::new(LlamaLoaded {
                 model,
                 ctx: Mutex::new(ctx),
+                #[cfg(feature = "vision")]
+                projector,
+                kv_prefix: Mutex::default(),
+                adapters: Mutex::new(adapters),
+                n_gpu_layers,
+                token_warnings,
             }))
         }
         #[cfg(not(feature = "llama"))]
@@ -458,6 +523,160 @@ impl InferenceEngine for LlamaEngine {
 struct LlamaLoaded {
     model: shimmy_llama_cpp_2::model::LlamaModel,
     ctx: Mutex<shimmy_llama_cpp_2::context::LlamaContext<'static>>,
+    /// Image encoder of a vision model; only used while `ctx` is locked
+    #[cfg(feature = "vision")]
+    projector: Option<Projector>,
+    /// What `ctx`'s KV cache holds; only touched while `ctx` is locked
+    kv_prefix: Mutex<super::prefix_cache::KvPrefix>,
+    /// LoRA adapters set on `ctx`; only touched while `ctx` is locked
+    adapters: Mutex<LoraAdapters>,
+    n_gpu_layers: u32,
+    token_warnings: Vec<super::special_tokens::TokenWarning>,
+}
+
+/// The multimodal projector of a vision model, loaded through llama.cpp's
+/// mtmd library, and the chat template its prompts are wrapped in
+#[cfg(all(feature = "llama", feature = "vision"))]
+struct Projector {
+    ctx: shimmy_llama_cpp_2::mtmd::MtmdContext,
+    template: crate::templates::TemplateFamily,
+}
+
+#[cfg(all(feature = "llama", feature = "vision"))]
+fn load_projector(
+    model: &shimmy_llama_cpp_2::model::LlamaModel,
+    path: &std::path::Path,
+    spec: &ModelSpec,
+    n_threads: i32,
+    use_gpu: bool,
+) -> Result<Projector> {
+    use shimmy_llama_cpp_2::mtmd::{MtmdContext, MtmdContextParams};
+
+    super::check_gguf_magic(path)?;
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Projector path is not UTF-8: {}", path.display()))?;
+    let params = MtmdContextParams {
+        use_gpu,
+        n_threads,
+        ..Default::default()
+    };
+    let ctx = MtmdContext::init_from_file(path_str, model, &params).map_err(|e| {
+        super::LoadError::BackendInitFailed {
+            details: format!("projector {}: {}", path.display(), e),
+        }
+    })?;
+    if !ctx.support_vision() {
+        anyhow::bail!("{} is not a vision projector", path.display());
+    }
+    info!("Vision projector loaded: {}", path.display());
+    Ok(Projector {
+        ctx,
+        template: crate::openai_compat::template_family(spec.template.as_deref(), &spec.name),
+    })
+}
+
+/// `prompt` with a media marker for each image it doesn't already place
+#[cfg(all(feature = "llama", feature = "vision"))]
+fn with_media_markers(prompt: &str, images: usize, marker: &str) -> String {
+    let missing = images.saturating_sub(prompt.matches(marker).count());
+    let mut out = format!("{}\n", marker).repeat(missing);
+    out.push_str(prompt);
+    out
+}
+
+/// Check the special tokens llama.cpp loaded against the GGUF's tokenizer
+/// metadata and the model's chat template, logging each mismatch
+#[cfg(feature = "llama")]
+fn check_special_tokens(
+    model: &shimmy_llama_cpp_2::model::LlamaModel,
+    path: &std::path::Path,
+    spec: &ModelSpec,
+) -> Vec<super::special_tokens::TokenWarning> {
+    use shimmy_llama_cpp_2::token::LlamaToken;
+
+    // Embedding models never generate, so their end tokens don't matter
+    if super::gguf::is_embedding_model(path).unwrap_or(false) {
+        return Vec::new();
+    }
+    let meta = match super::gguf::read_tokenizer(path) {
+        Ok(Some(meta)) => meta,
+        Ok(None) => return Vec::new(),
+        Err(e) => {
+            tracing::debug!(
+                "Reading tokenizer metadata of {} failed: {}",
+                path.display(),
+                e
+            );
+            return Vec::new();
+        }
+    };
+    let to_id = |token: LlamaToken| u32::try_from(token.0).ok();
+    let is_eog = |id: u32| model.is_eog_token(LlamaToken(id as i32));
+    let runtime = super::special_tokens::RuntimeTokens {
+        bos: to_id(model.token_bos()),
+        eos: to_id(model.token_eos()),
+        is_eog: &is_eog,
+    };
+    let family = crate::openai_compat::template_family(spec.template.as_deref(), &spec.name);
+    let warnings = super::special_tokens::check(&meta, &family, Some(&runtime));
+    for warning in &warnings {
+        tracing::warn!(
+            model = %spec.name,
+            code = %warning.code,
+            "Special token check: {}",
+            warning.message
+        );
+    }
+    warnings
+}
+
+/// LoRA adapters of one loaded model, each read from disk on first use and
+/// kept so switching back to it doesn't reload it
+#[cfg(feature = "llama")]
+#[derive(Default)]
+struct LoraAdapters {
+    /// The spec's `lora_path`, used when a request names no adapter
+    default: Option<std::path::PathBuf>,
+    /// The adapter the context runs with
+    active: Option<std::path::PathBuf>,
+    loaded:
+        std::collections::HashMap<std::path::PathBuf, shimmy_llama_cpp_2::model::LlamaLoraAdapter>,
+}
+
+#[cfg(feature = "llama")]
+impl LoraAdapters {
+    /// Run `ctx` with the adapter at `path`, or with none
+    fn select(
+        &mut self,
+        model: &shimmy_llama_cpp_2::model::LlamaModel,
+        ctx: &shimmy_llama_cpp_2::context::LlamaContext<'_>,
+        path: Option<&std::path::Path>,
+    ) -> Result<()> {
+        if self.active.as_deref() == path {
+            return Ok(());
+        }
+        if let Some(active) = self.active.take() {
+            if let Some(adapter) = self.loaded.get_mut(&active) {
+                ctx.lora_adapter_remove(adapter)
+                    .map_err(|e| anyhow::anyhow!("lora remove: {e:?}"))?;
+            }
+        }
+        let Some(path) = path else {
+            return Ok(());
+        };
+        if !self.loaded.contains_key(path) {
+            let adapter = model.lora_adapter_init(path)?;
+            self.loaded.insert(path.to_path_buf(), adapter);
+        }
+        if let Some(adapter) = self.loaded.get_mut(path) {
+            ctx.lora_adapter_set(adapter, 1.0)
+                .map_err(|e| anyhow::anyhow!("lora set: {e:?}"))?;
+        }
+        self.active = Some(path.to_path_buf());
+        info!(adapter=%path.display(), "LoRA adapter attached");
+        Ok(())
+    }
 }
 
 #[cfg(feature = "llama")]
@@ -471,49 +690,517 @@ unsafe impl Sync for LlamaLoaded {}
 #[cfg(feature = "llama")]
 #[async_trait]
 impl LoadedModel for LlamaLoaded {
+    fn count_tokens(&self, text: &str) -> Option<usize> {
+        use shimmy_llama_cpp_2::model::AddBos;
+        self.model
+            .str_to_token(text, AddBos::Always)
+            .ok()
+            .map(|t| t.len())
+    }
+
+    fn token_pieces(&self, text: &str) -> Option<Vec<String>> {
+        use shimmy_llama_cpp_2::model::{AddBos, Special};
+        let tokens = self.model.str_to_token(text, AddBos::Never).ok()?;
+        tokens
+            .into_iter()
+            .map(|t| self.model.token_to_str(t, Special::Plaintext).ok())
+            .collect()
+    }
+
+    fn supports_adapters(&self) -> bool {
+        true
+    }
+
+    fn token_warnings(&self) -> Vec<super::special_tokens::TokenWarning> {
+        self.token_warnings.clone()
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        use shimmy_llama_cpp_2::{
+            context::params::LlamaContextParams, llama_batch::LlamaBatch, model::AddBos,
+        };
+        use std::num::NonZeroU32;
+        // Sequences decoded together; llama.cpp keeps per-sequence state for each
+        const MAX_SEQS: usize = 64;
+
+        let n_ctx = self
+            .ctx
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock context: {}", e))?
+            .n_ctx();
+        let tokenized = inputs
+            .iter()
+            .map(|text| self.model.str_to_token(text, AddBos::Always))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some((i, tokens)) = tokenized
+            .iter()
+            .enumerate()
+            .find(|(_, t)| t.len() > n_ctx as usize)
+        {
+            anyhow::bail!(
+                "Input {} is {} tokens but the context holds {}; enable chunking to split it",
+                i,
+                tokens.len(),
+                n_ctx
+            );
+        }
+
+        // A separate context: embeddings need their own output buffers and
+        // whole sequences in one micro-batch
+        let mut params = LlamaContextParams::default()
+            .with_n_ctx(NonZeroU32::new(n_ctx))
+            .with_n_batch(n_ctx)
+            .with_n_ubatch(n_ctx)
+            .with_n_seq_max(MAX_SEQS as u32)
+            .with_embeddings(true);
+        if let Some(threads) = crate::power::PowerManager::global().profile().max_threads() {
+            params = params.with_n_threads(threads).with_n_threads_batch(threads);
+        }
+        let mut ctx = self.model.new_context(get_or_init_backend()?, params)?;
+
+        let lengths: Vec<usize> = tokenized.iter().map(|t| t.len()).collect();
+        let mut out = vec![Vec::new(); inputs.len()];
+        for group in crate::embeddings::plan_batches(&lengths, n_ctx as usize, MAX_SEQS) {
+            ctx.clear_kv_cache();
+            let mut batch = LlamaBatch::new(n_ctx as usize, group.len() as i32);
+            for (seq, &i) in group.iter().enumerate() {
+                batch.add_sequence(&tokenized[i], seq as i32, false)?;
+            }
+            ctx.decode(&mut batch)?;
+            for (seq, &i) in group.iter().enumerate() {
+                out[i] = ctx.embeddings_seq_ith(seq as i32)?.to_vec();
+            }
+        }
+        Ok(out)
+    }
+
+    fn memory_usage(&self) -> Option<super::ModelMemoryUsage> {
+        // Weights plus context state (KV cache, logits); offloaded layers live in VRAM
+        let weights = self.model.size();
+        let context = self.ctx.lock().ok()?.get_state_size() as u64;
+        let total = weights + context;
+        let n_layer = self.model.n_layer().max(1) as u64;
+        let offloaded = (self.n_gpu_layers as u64).min(n_layer);
+        let vram_bytes = total * offloaded / n_layer;
+        Some(super::ModelMemoryUsage {
+            ram_bytes: total - vram_bytes,
+            vram_bytes,
+        })
+    }
+
     async fn generate(
         &self,
         prompt: &str,
         opts: GenOptions,
-        mut on_token: Option<Box<dyn FnMut(String) + Send>>,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
     ) -> Result<String> {
+        Ok(self
+            .generate_with_finish(prompt, opts, on_token)
+            .await?
+            .text)
+    }
+
+    async fn generate_with_finish(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<super::Generation> {
+        let on_detail = on_token.map(|mut cb| {
+            Box::new(move |token: super::TokenDetail| cb(token.text))
+                as Box<dyn FnMut(super::TokenDetail) + Send>
+        });
+        self.generate_tokens(prompt, opts, on_detail, false)
+    }
+
+    async fn generate_detailed(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(super::TokenDetail) + Send>>,
+    ) -> Result<super::Generation> {
+        self.generate_tokens(prompt, opts, on_token, true)
+    }
+
+    #[cfg(feature = "vision")]
+    async fn generate_vision(
+        &self,
+        images: &[&[u8]],
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<String> {
+        let on_detail = on_token.map(|mut cb| {
+            Box::new(move |token: super::TokenDetail| cb(token.text))
+                as Box<dyn FnMut(super::TokenDetail) + Send>
+        });
+        Ok(self
+            .generate_with_images(images, prompt, opts, on_detail)?
+            .text)
+    }
+
+    #[cfg(feature = "vision")]
+    async fn embed_images(&self, images: &[&[u8]]) -> Result<Vec<Vec<f32>>> {
         use shimmy_llama_cpp_2::{
-            llama_batch::LlamaBatch,
-            model::{AddBos, Special},
-            sampling::LlamaSampler,
+            context::params::{LlamaContextParams, LlamaPoolingType},
+            mtmd::{mtmd_default_marker, MtmdBitmap, MtmdInputText},
+        };
+        use std::num::NonZeroU32;
+
+        let Some(projector) = self.projector.as_ref() else {
+            anyhow::bail!(
+                "Model was loaded without a vision projector; register it with an mmproj_path"
+            );
         };
+        let n_ctx = self
+            .ctx
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock context: {}", e))?
+            .n_ctx();
+
+        // A separate context, as for text embeddings, that mean-pools the
+        // hidden states of an image's tokens into one vector. Each image is
+        // decoded in one batch so the pooling sees all of it.
+        let mut params = LlamaContextParams::default()
+            .with_n_ctx(NonZeroU32::new(n_ctx))
+            .with_n_batch(n_ctx)
+            .with_n_ubatch(n_ctx)
+            .with_embeddings(true)
+            .with_pooling_type(LlamaPoolingType::Mean);
+        if let Some(threads) = crate::power::PowerManager::global().profile().max_threads() {
+            params = params.with_n_threads(threads).with_n_threads_batch(threads);
+        }
+        let mut ctx = self.model.new_context(get_or_init_backend()?, params)?;
+
+        let mut out = Vec::with_capacity(images.len());
+        for (i, image) in images.iter().enumerate() {
+            let bitmap = MtmdBitmap::from_buffer(&projector.ctx, image)
+                .map_err(|e| anyhow::anyhow!("Failed to decode image {}: {}", i, e))?;
+            // Only the marker: the vector describes the image, not a prompt
+            let chunks = projector
+                .ctx
+                .tokenize(
+                    MtmdInputText {
+                        text: mtmd_default_marker().to_string(),
+                        add_special: false,
+                        parse_special: true,
+                    },
+                    &[&bitmap],
+                )
+                .map_err(|e| anyhow::anyhow!("Failed to tokenize image {}: {}", i, e))?;
+            if chunks.total_tokens() > n_ctx as usize {
+                anyhow::bail!(
+                    "Image {} is {} tokens but the context holds {}",
+                    i,
+                    chunks.total_tokens(),
+                    n_ctx
+                );
+            }
+            ctx.clear_kv_cache();
+            chunks
+                .eval_chunks(&projector.ctx, &ctx, 0, 0, n_ctx as i32, false)
+                .map_err(|e| anyhow::anyhow!("Failed to encode image {}: {}", i, e))?;
+            out.push(ctx.embeddings_seq_ith(0)?.to_vec());
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(feature = "llama")]
+impl LlamaLoaded {
+    /// Text generation behind both generate methods; `logprobs` adds each
+    /// token's log-probability, and the `top_logprobs` most likely
+    /// alternatives, to what the callback receives
+    fn generate_tokens(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(super::TokenDetail) + Send>>,
+        logprobs: bool,
+    ) -> Result<super::Generation> {
+        use shimmy_llama_cpp_2::{llama_batch::LlamaBatch, model::AddBos};
+        let deadline = opts.stop_at();
         let mut ctx = self
             .ctx
             .lock()
             .map_err(|e| anyhow::anyhow!("Failed to lock context: {}", e))?;
-        let tokens = self.model.str_to_token(prompt, AddBos::Always)?;
+        let add_bos = if opts.add_bos.unwrap_or(true) {
+            AddBos::Always
+        } else {
+            AddBos::Never
+        };
+        let tokens = self.model.str_to_token(prompt, add_bos)?;
+
+        // Keep the part of the KV cache the previous request shares with this
+        // prompt and drop the rest
+        let mut kv_prefix = self
+            .kv_prefix
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock prefix cache: {}", e))?;
+        {
+            let mut adapters = self
+                .adapters
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock adapters: {}", e))?;
+            let adapter = opts.adapter.clone().or_else(|| adapters.default.clone());
+            if adapters.active != adapter {
+                // The cached prefix was computed with the other adapter
+                kv_prefix.clear();
+                adapters.select(&self.model, &ctx, adapter.as_deref())?;
+            }
+        }
+        let ids: Vec<i32> = tokens.iter().map(|t| t.0).collect();
+        let mut cached_tokens = if super::prefix_cache::enabled() {
+            kv_prefix.reusable(&ids)
+        } else {
+            0
+        };
+        if cached_tokens > 0
+            && !ctx
+                .clear_kv_cache_seq(Some(0), Some(cached_tokens as u32), None)
+                .unwrap_or(false)
+        {
+            // Some architectures (recurrent, SWA) can't drop a suffix
+            cached_tokens = 0;
+        }
+        if cached_tokens == 0 {
+            ctx.clear_kv_cache();
+        }
+        // Until decoding succeeds the cache contents are unknown
+        kv_prefix.clear();
+        if cached_tokens > 0 {
+            tracing::debug!(
+                "Reusing {} of {} prompt tokens from the KV cache",
+                cached_tokens,
+                tokens.len()
+            );
+        }
 
         // Create batch with explicit logits configuration
-        let mut batch = LlamaBatch::new(tokens.len(), 1);
-        for (i, &token) in tokens.iter().enumerate() {
+        let prompt_eval = tracing::info_span!(
+            "llm.prompt_eval",
+            llm.prompt_tokens = tokens.len(),
+            llm.cached_tokens = cached_tokens,
+        )
+        .entered();
+        let pending = &tokens[cached_tokens..];
+        let mut batch = LlamaBatch::new(pending.len(), 1);
+        for (i, &token) in pending.iter().enumerate() {
             // Only request logits for the last token in the initial batch
-            let logits = i == tokens.len() - 1;
-            batch.add(token, i as i32, &[0], logits)?;
+            let logits = i == pending.len() - 1;
+            batch.add(token, (cached_tokens + i) as i32, &[0], logits)?;
         }
         ctx.decode(&mut batch)?;
+        drop(prompt_eval);
+        // Batch position holding the logits for the next token
+        let logits_index = pending.len() as i32 - 1;
+
+        let n_past = tokens.len() as i32;
+        let (out, finish_reason, all_tokens) = self.sample_tokens(
+            &mut ctx,
+            tokens,
+            n_past,
+            logits_index,
+            deadline,
+            &opts,
+            on_token,
+            logprobs,
+        )?;
+
+        // The cache holds every token decoded so far
+        kv_prefix.set(&all_tokens.iter().map(|t| t.0).collect::<Vec<_>>());
+
+        Ok(super::Generation {
+            text: out,
+            finish_reason,
+            cached_tokens,
+        })
+    }
 
-        let mut sampler = LlamaSampler::chain_simple([
-            LlamaSampler::temp(opts.temperature),
-            LlamaSampler::top_p(opts.top_p, 1),
-            LlamaSampler::top_k(opts.top_k),
-            // API changed order: (repeat_last_n, freq_penalty, presence_penalty, penalty)
-            LlamaSampler::penalties(64, 0.0, 0.0, opts.repeat_penalty),
-            LlamaSampler::greedy(),
-        ])
-        .with_tokens(tokens.iter().copied());
+    /// Generate from images and a prompt: the projector encodes the images
+    /// into the context, then sampling goes on as for text
+    #[cfg(feature = "vision")]
+    fn generate_with_images(
+        &self,
+        images: &[&[u8]],
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(super::TokenDetail) + Send>>,
+    ) -> Result<super::Generation> {
+        use shimmy_llama_cpp_2::mtmd::{mtmd_default_marker, MtmdBitmap, MtmdInputText};
 
-        let mut out = String::new();
-        let mut all_tokens = tokens;
+        let Some(projector) = self.projector.as_ref() else {
+            anyhow::bail!(
+                "Model was loaded without a vision projector; register it with an mmproj_path"
+            );
+        };
+        let deadline = opts.stop_at();
+        let mut ctx = self
+            .ctx
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock context: {}", e))?;
+        {
+            let mut adapters = self
+                .adapters
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock adapters: {}", e))?;
+            let adapter = opts.adapter.clone().or_else(|| adapters.default.clone());
+            adapters.select(&self.model, &ctx, adapter.as_deref())?;
+        }
+        // Image embeddings can't be matched against a text prefix, so nothing
+        // in the cache is reusable before or after
+        self.kv_prefix
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock prefix cache: {}", e))?
+            .clear();
+        ctx.clear_kv_cache();
+
+        let bitmaps = images
+            .iter()
+            .map(|image| MtmdBitmap::from_buffer(&projector.ctx, image))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("Failed to decode image: {}", e))?;
+        let bitmap_refs: Vec<&MtmdBitmap> = bitmaps.iter().collect();
+        let text = projector.template.render(
+            None,
+            &[],
+            Some(&with_media_markers(
+                prompt,
+                images.len(),
+                mtmd_default_marker(),
+            )),
+        );
+        let chunks = projector
+            .ctx
+            .tokenize(
+                MtmdInputText {
+                    text,
+                    add_special: opts.add_bos.unwrap_or(true),
+                    parse_special: true,
+                },
+                &bitmap_refs,
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to tokenize vision prompt: {}", e))?;
+
+        let prompt_eval = tracing::info_span!(
+            "llm.prompt_eval",
+            llm.prompt_tokens = chunks.total_tokens(),
+            llm.images = images.len(),
+        )
+        .entered();
+        let n_batch = ctx.n_batch() as i32;
+        let n_past = chunks
+            .eval_chunks(&projector.ctx, &ctx, 0, 0, n_batch, true)
+            .map_err(|e| anyhow::anyhow!("Failed to evaluate images: {}", e))?;
+        drop(prompt_eval);
+
+        // mtmd decodes the prompt itself, so the logits of its last position
+        // are only reachable through the sampler; no log-probabilities here
+        let (text, finish_reason, _) = self.sample_tokens(
+            &mut ctx,
+            Vec::new(),
+            n_past,
+            -1,
+            deadline,
+            &opts,
+            on_token,
+            false,
+        )?;
+        Ok(super::Generation {
+            text,
+            finish_reason,
+            cached_tokens: 0,
+        })
+    }
+
+    /// Sample after a decoded prompt until an end token, a stop sequence or a
+    /// limit. `history` holds the prompt's tokens for the repetition penalty,
+    /// `n_past` is the position of the next token and `logits_index` where its
+    /// logits are in the last batch. Returns the text, why it stopped and
+    /// `history` with the sampled tokens appended.
+    fn sample_tokens(
+        &self,
+        ctx: &mut shimmy_llama_cpp_2::context::LlamaContext<'static>,
+        history: Vec<shimmy_llama_cpp_2::token::LlamaToken>,
+        mut n_past: i32,
+        mut logits_index: i32,
+        deadline: Option<std::time::Instant>,
+        opts: &GenOptions,
+        mut on_token: Option<Box<dyn FnMut(super::TokenDetail) + Send>>,
+        logprobs: bool,
+    ) -> Result<(
+        String,
+        super::FinishReason,
+        Vec<shimmy_llama_cpp_2::token::LlamaToken>,
+    )> {
+        use super::FinishReason;
+        use shimmy_llama_cpp_2::{
+            llama_batch::LlamaBatch, model::Special, sampling::LlamaSampler, token::LlamaToken,
+        };
 
-        for _ in 0..opts.max_tokens {
+        let mut sampler = if opts.is_greedy() {
+            // Nothing to reshape the distribution, so skip the softmax and
+            // sorting the rest of the chain would do for every token
+            LlamaSampler::greedy()
+        } else {
+            LlamaSampler::chain_simple([
+                LlamaSampler::temp(opts.temperature),
+                LlamaSampler::top_p(opts.top_p, 1),
+                LlamaSampler::top_k(opts.top_k),
+                // API changed order: (repeat_last_n, freq_penalty, presence_penalty, penalty)
+                LlamaSampler::penalties(64, 0.0, 0.0, opts.repeat_penalty),
+                LlamaSampler::greedy(),
+            ])
+            .with_tokens(history.iter().copied())
+        };
+        // The grammar goes in front so the rest of the chain only sees tokens it
+        // allows; it is added after `with_tokens` because the prompt is not part
+        // of the grammar's language
+        if let Some(grammar) = opts.grammar.as_deref() {
+            let grammar = LlamaSampler::grammar(&self.model, grammar, super::grammar::ROOT_RULE)
+                .map_err(|e| anyhow::anyhow!("Invalid grammar: {}", e))?;
+            sampler = LlamaSampler::chain_simple([grammar, sampler]);
+        }
+
+        let mut out = String::new();
+        let mut all_tokens = history;
+        let mut finish_reason = FinishReason::Length;
+        let mut repetition = opts
+            .repetition
+            .or_else(super::RepetitionConfig::from_env)
+            .map(super::repetition::RepetitionDetector::new);
+
+        let _decode = tracing::info_span!("llm.decode").entered();
+        for generated in 1..=opts.max_tokens {
+            if opts.is_cancelled() {
+                finish_reason = FinishReason::Cancelled;
+                break;
+            }
+            if deadline.is_some_and(|d| std::time::Instant::now() >= d) {
+                finish_reason = FinishReason::Time;
+                break;
+            }
             // Sample from the last (and only) position with logits
             let token = sampler.sample(&ctx, -1);
+            let (logprob, top_logprobs) = if logprobs {
+                let logits = ctx.get_logits_ith(logits_index);
+                let top = super::top_logprobs(logits, opts.top_logprobs)
+                    .into_iter()
+                    .filter_map(|(id, logprob)| {
+                        let id = id as i32;
+                        let text = self
+                            .model
+                            .token_to_str(LlamaToken(id), Special::Plaintext)
+                            .ok()?;
+                        Some(super::TopLogprob { text, id, logprob })
+                    })
+                    .collect();
+                (super::logprob_at(logits, token.0 as usize), top)
+            } else {
+                (None, Vec::new())
+            };
             if self.model.is_eog_token(token) {
+                finish_reason = FinishReason::Stop;
                 break;
             }
             // Use Plaintext to avoid re-tokenizing control tokens into special forms
@@ -541,21 +1228,38 @@ impl LoadedModel for LlamaLoaded {
                         break;
                     }
                 }
+                finish_reason = FinishReason::Stop;
                 break;
             }
 
             // Handle UTF-8 aware token streaming (Issue #139 fix)
             if let Some(cb) = on_token.as_mut() {
-                cb(piece.clone());
+                cb(super::TokenDetail {
+                    text: piece.clone(),
+                    id: Some(token.0),
+                    logprob,
+                    top_logprobs,
+                });
+            }
+
+            if repetition.as_mut().is_some_and(|r| r.push(token.0)) {
+                tracing::warn!(
+                    "Aborting generation after {} tokens: output is repeating",
+                    generated
+                );
+                finish_reason = FinishReason::Repetition;
+                break;
             }
 
             let mut step = LlamaBatch::new(1, 1);
-            step.add(token, all_tokens.len() as i32, &[0], true)?;
+            step.add(token, n_past, &[0], true)?;
             ctx.decode(&mut step)?;
+            n_past += 1;
+            logits_index = 0;
             all_tokens.push(token);
         }
 
-        Ok(out)
+        Ok((out, finish_reason, all_tokens))
     }
 }
 
@@ -646,6 +1350,8 @@ mod tests {
             template: Some("chatml".to_string()),
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         // let result = engine.load(&spec).await; // Commented to avoid test file dependencies
@@ -661,10 +1367,27 @@ mod tests {
             template: Some("chatml".to_string()),
             ctx_len: 4096,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         assert_eq!(spec.name, "valid");
         assert_eq!(spec.ctx_len, 4096);
         assert!(spec.template.is_some());
     }
+
+    #[cfg(all(feature = "llama", feature = "vision"))]
+    #[test]
+    fn test_media_markers_added_for_unplaced_images() {
+        let marker = "<__media__>";
+        assert_eq!(
+            with_media_markers("Describe", 2, marker),
+            "<__media__>\n<__media__>\nDescribe"
+        );
+        assert_eq!(
+            with_media_markers("Compare <__media__> with", 2, marker),
+            "<__media__>\nCompare <__media__> with"
+        );
+        assert_eq!(with_media_markers("Describe", 0, marker), "Describe");
+    }
 }
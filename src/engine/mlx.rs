@@ -281,6 +281,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         assert!(MLXEngine::is_mlx_compatible(&mlx_spec));
@@ -293,6 +295,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         assert!(MLXEngine::is_mlx_compatible(&llama_spec));
@@ -309,6 +313,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         let result = MLXModel::new(&spec).await;
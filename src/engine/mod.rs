@@ -1,7 +1,12 @@
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub use repetition::RepetitionConfig;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenOptions {
@@ -14,6 +19,39 @@ pub struct GenOptions {
     pub stream: bool,
     #[serde(default)]
     pub stop_tokens: Vec<String>,
+    /// Prepend the BOS token to the prompt; `None` keeps the backend default
+    #[serde(default)]
+    pub add_bos: Option<bool>,
+    /// Abort runaway loops; `None` falls back to `SHIMMY_REPETITION_ABORT`
+    #[serde(default)]
+    pub repetition: Option<RepetitionConfig>,
+    /// Wall-clock limit for generation, counted from when the request reaches the model
+    #[serde(default)]
+    pub max_time_ms: Option<u64>,
+    /// GBNF grammar the output must match; see [`grammar`]
+    #[serde(default)]
+    pub grammar: Option<String>,
+    /// Most likely alternatives to report with each token's logprob, up to
+    /// [`MAX_TOP_LOGPROBS`]; only [`LoadedModel::generate_detailed`] reports them
+    #[serde(default)]
+    pub top_logprobs: usize,
+    /// Set to stop generating early, e.g. when the client disconnects
+    #[serde(skip)]
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// The request's `x-request-deadline-ms`; see [`crate::deadline`]
+    #[serde(skip)]
+    pub deadline: Option<Instant>,
+    /// The request's API key, charged for the tokens used; see [`crate::api_keys`]
+    #[serde(skip)]
+    pub api_key: Option<Arc<crate::api_keys::ApiKey>>,
+    /// Rate limit buckets charged for the tokens used; see [`crate::rate_limit`]
+    #[serde(skip)]
+    pub rate_limit: Option<Arc<crate::rate_limit::Charge>>,
+    /// LoRA adapter file to apply in place of the model's own; resolved from
+    /// the request's `adapter` name by [`crate::model_registry::Registry::adapter_path`]
+    /// and passed on to inference workers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adapter: Option<PathBuf>,
 }
 
 impl Default for GenOptions {
@@ -27,8 +65,345 @@ impl Default for GenOptions {
             seed: None,
             stream: true,
             stop_tokens: Vec::new(),
+            add_bos: None,
+            repetition: None,
+            max_time_ms: None,
+            grammar: None,
+            top_logprobs: 0,
+            cancel: None,
+            deadline: None,
+            api_key: None,
+            rate_limit: None,
+            adapter: None,
+        }
+    }
+}
+
+impl GenOptions {
+    /// Whether the caller asked generation to stop
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    /// Whether sampling always takes the most likely token, so backends can
+    /// skip the rest of the sampler chain
+    pub fn is_greedy(&self) -> bool {
+        self.temperature <= 0.0 && self.repeat_penalty == 1.0
+    }
+
+    /// When generation has to stop: `max_time_ms` from now or the request
+    /// deadline, whichever comes first
+    pub fn stop_at(&self) -> Option<Instant> {
+        let max_time = self
+            .max_time_ms
+            .map(|ms| Instant::now() + Duration::from_millis(ms));
+        match (max_time, self.deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Fold the deadline into `max_time_ms` for a process boundary, where an
+    /// `Instant` means nothing
+    pub fn deadline_as_max_time(mut self) -> Self {
+        if let Some(deadline) = self.deadline.take() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let left_ms = (left.as_millis() as u64).max(1);
+            self.max_time_ms = Some(self.max_time_ms.map_or(left_ms, |ms| ms.min(left_ms)));
+        }
+        self
+    }
+}
+
+/// Why generation ended, with OpenAI `finish_reason` names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// End-of-generation token or a stop sequence
+    Stop,
+    /// Hit `max_tokens`
+    Length,
+    /// The model emitted tool calls
+    ToolCalls,
+    /// Output withheld by a content filter
+    ContentFilter,
+    /// The client went away mid-generation
+    Cancelled,
+    /// Aborted because the output was looping
+    Repetition,
+    /// Hit `max_time_ms` or the request deadline
+    Time,
+}
+
+impl FinishReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::ToolCalls => "tool_calls",
+            FinishReason::ContentFilter => "content_filter",
+            FinishReason::Cancelled => "cancelled",
+            FinishReason::Repetition => "repetition",
+            FinishReason::Time => "time",
+        }
+    }
+}
+
+/// Generated text and why generation ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generation {
+    pub text: String,
+    pub finish_reason: FinishReason,
+    /// Prompt tokens reused from the KV cache instead of evaluated again
+    pub cached_tokens: usize,
+}
+
+/// A generated token with whatever the backend knows about it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenDetail {
+    pub text: String,
+    /// Vocabulary id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    /// Natural log of the token's probability under the model, before sampling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprob: Option<f32>,
+    /// The most likely tokens at this position, most likely first, when
+    /// [`GenOptions::top_logprobs`] asked for them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+/// A candidate token at one position of the output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopLogprob {
+    pub text: String,
+    pub id: i32,
+    pub logprob: f32,
+}
+
+/// Upper bound on [`GenOptions::top_logprobs`], as in the OpenAI API
+pub const MAX_TOP_LOGPROBS: usize = 20;
+
+/// Log of the softmax denominator, computed so large logits can't overflow
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+fn log_sum_exp(logits: &[f32]) -> f32 {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let sum: f32 = logits.iter().map(|l| (l - max).exp()).sum();
+    max + sum.ln()
+}
+
+/// Log-probability of `logits[index]` after a softmax over all of `logits`
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+pub fn logprob_at(logits: &[f32], index: usize) -> Option<f32> {
+    let logit = *logits.get(index)?;
+    Some(logit - log_sum_exp(logits))
+}
+
+/// Indices and log-probabilities of the `n` largest `logits`, most likely first
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+pub fn top_logprobs(logits: &[f32], n: usize) -> Vec<(usize, f32)> {
+    if n == 0 || logits.is_empty() {
+        return Vec::new();
+    }
+    let by_logit = |a: &(usize, f32), b: &(usize, f32)| b.1.total_cmp(&a.1);
+    let mut ranked: Vec<(usize, f32)> = logits.iter().copied().enumerate().collect();
+    if n < ranked.len() {
+        ranked.select_nth_unstable_by(n - 1, by_logit);
+        ranked.truncate(n);
+    }
+    ranked.sort_by(by_logit);
+    let norm = log_sum_exp(logits);
+    ranked
+        .into_iter()
+        .map(|(index, logit)| (index, logit - norm))
+        .collect()
+}
+
+/// A request option outside the range the backends handle sensibly
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Invalid `{param}`: {reason}")]
+pub struct InvalidParameter {
+    pub param: &'static str,
+    pub reason: String,
+}
+
+impl InvalidParameter {
+    pub fn new(param: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            param,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl GenOptions {
+    /// Reject sampling options that would produce degenerate output
+    pub fn validate(&self) -> std::result::Result<(), InvalidParameter> {
+        if !(0.0..=2.0).contains(&self.temperature) {
+            return Err(InvalidParameter::new(
+                "temperature",
+                format!("must be between 0 and 2, got {}", self.temperature),
+            ));
         }
+        if !(self.top_p > 0.0 && self.top_p <= 1.0) {
+            return Err(InvalidParameter::new(
+                "top_p",
+                format!("must be greater than 0 and at most 1, got {}", self.top_p),
+            ));
+        }
+        if self.top_k < 0 {
+            return Err(InvalidParameter::new(
+                "top_k",
+                format!("must be 0 (disabled) or positive, got {}", self.top_k),
+            ));
+        }
+        if self.max_tokens == 0 {
+            return Err(InvalidParameter::new("max_tokens", "must be at least 1"));
+        }
+        if !(self.repeat_penalty.is_finite() && self.repeat_penalty > 0.0) {
+            return Err(InvalidParameter::new(
+                "repeat_penalty",
+                format!("must be greater than 0, got {}", self.repeat_penalty),
+            ));
+        }
+        if self.max_time_ms == Some(0) {
+            return Err(InvalidParameter::new("max_time_ms", "must be at least 1"));
+        }
+        if self.top_logprobs > MAX_TOP_LOGPROBS {
+            return Err(InvalidParameter::new(
+                "top_logprobs",
+                format!(
+                    "must be at most {}, got {}",
+                    MAX_TOP_LOGPROBS, self.top_logprobs
+                ),
+            ));
+        }
+        if let Some(repetition) = &self.repetition {
+            repetition.validate()?;
+        }
+        if let Some(grammar) = &self.grammar {
+            grammar::validate(grammar)?;
+        }
+        if self.stop_tokens.iter().any(|s| s.is_empty()) {
+            return Err(InvalidParameter::new(
+                "stop",
+                "stop sequences must not be empty strings",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Categorized model load failure.
+///
+/// Engines return these wrapped in `anyhow::Error`; callers recover the
+/// category with `err.downcast_ref::<LoadError>()`.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum LoadError {
+    #[error("Model file not found: {}", path.display())]
+    FileNotFound { path: PathBuf },
+
+    #[error("Unsupported model architecture: {details}")]
+    UnsupportedArchitecture { details: String },
+
+    #[error("Out of memory while loading model: {details}")]
+    OutOfMemory { details: String },
+
+    #[error("Inference backend failed to initialize: {details}")]
+    BackendInitFailed { details: String },
+
+    #[error("Corrupt or invalid GGUF file {}: {details}", path.display())]
+    CorruptGguf { path: PathBuf, details: String },
+}
+
+impl LoadError {
+    /// Stable machine-readable code for API clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            LoadError::FileNotFound { .. } => "MODEL_FILE_NOT_FOUND",
+            LoadError::UnsupportedArchitecture { .. } => "UNSUPPORTED_ARCHITECTURE",
+            LoadError::OutOfMemory { .. } => "OUT_OF_MEMORY",
+            LoadError::BackendInitFailed { .. } => "BACKEND_INIT_FAILED",
+            LoadError::CorruptGguf { .. } => "CORRUPT_GGUF",
+        }
+    }
+
+    /// Best-effort categorization of an untyped backend error message
+    pub fn from_message(path: &Path, message: &str) -> Option<Self> {
+        let lower = message.to_lowercase();
+        let details = message.to_string();
+        if lower.contains("failed to allocate")
+            || lower.contains("out of memory")
+            || lower.contains("cpu_repack buffer")
+            || lower.contains("memory allocation failed")
+        {
+            Some(LoadError::OutOfMemory { details })
+        } else if lower.contains("unknown model architecture")
+            || lower.contains("unsupported architecture")
+            || lower.contains("unknown architecture")
+        {
+            Some(LoadError::UnsupportedArchitecture { details })
+        } else if lower.contains("initialize llama backend") || lower.contains("backend init") {
+            Some(LoadError::BackendInitFailed { details })
+        } else if lower.contains("invalid magic")
+            || lower.contains("gguf_init")
+            || lower.contains("corrupt")
+            || lower.contains("unexpected end of file")
+        {
+            Some(LoadError::CorruptGguf {
+                path: path.to_path_buf(),
+                details,
+            })
+        } else if lower.contains("no such file") || lower.contains("not found") {
+            Some(LoadError::FileNotFound {
+                path: path.to_path_buf(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Attach a category to a load error, leaving it untouched if unknown
+    pub fn categorize(path: &Path, err: anyhow::Error) -> anyhow::Error {
+        if err.downcast_ref::<LoadError>().is_some() {
+            return err;
+        }
+        match Self::from_message(path, &format!("{:#}", err)) {
+            Some(load_err) => load_err.into(),
+            None => err,
+        }
+    }
+}
+
+/// Check that a GGUF file starts with the `GGUF` magic bytes
+pub fn check_gguf_magic(path: &Path) -> std::result::Result<(), LoadError> {
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => LoadError::FileNotFound {
+            path: path.to_path_buf(),
+        },
+        _ => LoadError::CorruptGguf {
+            path: path.to_path_buf(),
+            details: e.to_string(),
+        },
+    })?;
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic)
+        .map_err(|e| LoadError::CorruptGguf {
+            path: path.to_path_buf(),
+            details: e.to_string(),
+        })?;
+    if &magic != b"GGUF" {
+        return Err(LoadError::CorruptGguf {
+            path: path.to_path_buf(),
+            details: format!("invalid magic {:?} (expected \"GGUF\")", magic),
+        });
     }
+    Ok(())
 }
 
 // Universal backend support - true shim architecture
@@ -67,7 +442,7 @@ pub struct UniversalModelSpec {
 }
 
 // Legacy ModelSpec for backward compatibility
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelSpec {
     pub name: String,
     pub base_path: PathBuf,
@@ -75,6 +450,12 @@ pub struct ModelSpec {
     pub template: Option<String>,
     pub ctx_len: usize,
     pub n_threads: Option<i32>,
+    /// Layers to offload to the GPU; `None` uses the backend's default
+    #[serde(default)]
+    pub n_gpu_layers: Option<u32>,
+    /// Multimodal projector (an `mmproj` GGUF) that lets the model read images
+    #[serde(default)]
+    pub mmproj_path: Option<PathBuf>,
 }
 
 #[cfg(feature = "huggingface")]
@@ -112,10 +493,41 @@ pub trait UniversalModel: Send + Sync {
     ) -> Result<String>;
 }
 
+/// Memory held by a loaded model, as reported by its backend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelMemoryUsage {
+    pub ram_bytes: u64,
+    pub vram_bytes: u64,
+}
+
+impl ModelMemoryUsage {
+    pub fn total_bytes(&self) -> u64 {
+        self.ram_bytes + self.vram_bytes
+    }
+}
+
+/// A model currently held in memory by an engine
+#[derive(Debug, Clone, Serialize)]
+pub struct RunningModel {
+    pub name: String,
+    pub path: PathBuf,
+    pub loaded_at: chrono::DateTime<chrono::Utc>,
+    /// `None` when the backend can't introspect its allocations
+    pub memory: Option<ModelMemoryUsage>,
+    /// Special-token problems found at load time
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<special_tokens::TokenWarning>,
+}
+
 // Legacy trait for backward compatibility
 #[async_trait]
 pub trait InferenceEngine: Send + Sync {
     async fn load(&self, spec: &ModelSpec) -> Result<Box<dyn LoadedModel>>;
+
+    /// Models currently loaded through this engine
+    fn running_models(&self) -> Vec<RunningModel> {
+        Vec::new()
+    }
 }
 
 #[async_trait]
@@ -127,9 +539,34 @@ pub trait LoadedModel: Send + Sync {
         on_token: Option<Box<dyn FnMut(String) + Send>>,
     ) -> Result<String>;
 
+    /// Like [`generate`](Self::generate), also reporting why generation ended
+    ///
+    /// Backends that can't tell report `Stop`, or `Cancelled` when the
+    /// caller's cancel flag was raised.
+    async fn generate_with_finish(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<Generation> {
+        let cancel = opts.cancel.clone();
+        let text = self.generate(prompt, opts, on_token).await?;
+        let finish_reason = if cancel.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+            FinishReason::Cancelled
+        } else {
+            FinishReason::Stop
+        };
+        Ok(Generation {
+            text,
+            finish_reason,
+            cached_tokens: 0,
+        })
+    }
+
+    /// Generate from `prompt` and one or more images, in the order given
     async fn generate_vision(
         &self,
-        _image_data: &[u8],
+        _images: &[&[u8]],
         _prompt: &str,
         _opts: GenOptions,
         _on_token: Option<Box<dyn FnMut(String) + Send>>,
@@ -137,6 +574,63 @@ pub trait LoadedModel: Send + Sync {
         // Default implementation returns error - vision models should override
         Err(anyhow!("Vision not supported by this model"))
     }
+
+    /// Like [`generate_with_finish`](Self::generate_with_finish), handing the
+    /// callback each token's id and log-probability where the backend knows them
+    async fn generate_detailed(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(TokenDetail) + Send>>,
+    ) -> Result<Generation> {
+        let on_text = on_token.map(|mut cb| {
+            Box::new(move |text| {
+                cb(TokenDetail {
+                    text,
+                    id: None,
+                    logprob: None,
+                    top_logprobs: Vec::new(),
+                })
+            }) as Box<dyn FnMut(String) + Send>
+        });
+        self.generate_with_finish(prompt, opts, on_text).await
+    }
+
+    /// Actual memory used by this model after load, if the backend can report it
+    fn memory_usage(&self) -> Option<ModelMemoryUsage> {
+        None
+    }
+
+    /// Special-token mismatches found when the model was loaded
+    fn token_warnings(&self) -> Vec<special_tokens::TokenWarning> {
+        Vec::new()
+    }
+
+    /// Number of tokens `text` encodes to, if the backend exposes its tokenizer
+    fn count_tokens(&self, _text: &str) -> Option<usize> {
+        None
+    }
+
+    /// `text` split into the strings of its tokens, if the backend exposes its tokenizer
+    fn token_pieces(&self, _text: &str) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Whether generation applies [`GenOptions::adapter`]
+    fn supports_adapters(&self) -> bool {
+        false
+    }
+
+    /// One embedding vector per input, in input order
+    async fn embed(&self, _inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        Err(anyhow!("This backend does not support embeddings"))
+    }
+
+    /// One embedding vector per image, in input order, from the model's
+    /// vision encoder without generating any text
+    async fn embed_images(&self, _images: &[&[u8]]) -> Result<Vec<Vec<f32>>> {
+        Err(anyhow!("This model does not support image embeddings"))
+    }
 }
 
 pub mod llama;
@@ -151,4 +645,233 @@ pub mod universal;
 pub mod mlx;
 
 pub mod adapter;
+pub mod fault;
+pub mod gguf;
+pub mod grammar;
+pub mod json_schema;
+pub mod prefix_cache;
+pub mod repetition;
 pub mod safetensors_native;
+pub mod special_tokens;
+
+#[cfg(feature = "llama")]
+pub mod worker;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    #[async_trait]
+    impl LoadedModel for Echo {
+        async fn generate(
+            &self,
+            prompt: &str,
+            _opts: GenOptions,
+            _on_token: Option<Box<dyn FnMut(String) + Send>>,
+        ) -> Result<String> {
+            Ok(prompt.to_string())
+        }
+    }
+
+    #[test]
+    fn test_deadline_shortens_max_time() {
+        let soon = Instant::now() + Duration::from_millis(500);
+        let opts = GenOptions {
+            max_time_ms: Some(60_000),
+            deadline: Some(soon),
+            ..Default::default()
+        };
+        assert_eq!(opts.stop_at(), Some(soon));
+        let folded = opts.deadline_as_max_time();
+        assert!(folded.deadline.is_none());
+        assert!(folded.max_time_ms.is_some_and(|ms| (1..=500).contains(&ms)));
+
+        let opts = GenOptions {
+            max_time_ms: Some(100),
+            deadline: Some(Instant::now() + Duration::from_secs(60)),
+            ..Default::default()
+        };
+        assert_eq!(opts.deadline_as_max_time().max_time_ms, Some(100));
+        assert_eq!(GenOptions::default().stop_at(), None);
+    }
+
+    #[tokio::test]
+    async fn test_default_finish_reason() {
+        let generation = Echo
+            .generate_with_finish("hi", GenOptions::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(generation.text, "hi");
+        assert_eq!(generation.finish_reason, FinishReason::Stop);
+
+        let opts = GenOptions {
+            cancel: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+        assert!(opts.is_cancelled());
+        let generation = Echo.generate_with_finish("hi", opts, None).await.unwrap();
+        assert_eq!(generation.finish_reason, FinishReason::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_default_generate_detailed() {
+        struct Streaming;
+        #[async_trait]
+        impl LoadedModel for Streaming {
+            async fn generate(
+                &self,
+                prompt: &str,
+                _opts: GenOptions,
+                mut on_token: Option<Box<dyn FnMut(String) + Send>>,
+            ) -> Result<String> {
+                if let Some(cb) = on_token.as_mut() {
+                    cb(prompt.to_string());
+                }
+                Ok(prompt.to_string())
+            }
+        }
+        let tokens = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = tokens.clone();
+        let generation = Streaming
+            .generate_detailed(
+                "hi",
+                GenOptions::default(),
+                Some(Box::new(move |t| sink.lock().unwrap().push(t))),
+            )
+            .await
+            .unwrap();
+        assert_eq!(generation.text, "hi");
+        let tokens = tokens.lock().unwrap();
+        assert_eq!(
+            *tokens,
+            vec![TokenDetail {
+                text: "hi".to_string(),
+                id: None,
+                logprob: None,
+                top_logprobs: Vec::new(),
+            }]
+        );
+        assert_eq!(
+            serde_json::to_string(&tokens[0]).unwrap(),
+            r#"{"text":"hi"}"#
+        );
+    }
+
+    #[test]
+    fn test_logprob_at() {
+        let uniform = logprob_at(&[1.0, 1.0, 1.0, 1.0], 2).unwrap();
+        assert!((uniform - 0.25f32.ln()).abs() < 1e-6);
+        // Large logits must not overflow
+        let peaked = logprob_at(&[1000.0, 0.0], 0).unwrap();
+        assert!(peaked.abs() < 1e-6);
+        assert_eq!(logprob_at(&[0.0], 1), None);
+    }
+
+    #[test]
+    fn test_top_logprobs() {
+        let logits = [0.0, 3.0, 1.0, 3.0, 2.0];
+        let top = top_logprobs(&logits, 3);
+        let indices: Vec<usize> = top.iter().map(|&(i, _)| i).collect();
+        assert!(indices == [1, 3, 4] || indices == [3, 1, 4]);
+        for (index, logprob) in top {
+            assert!((logprob - logprob_at(&logits, index).unwrap()).abs() < 1e-6);
+        }
+        assert_eq!(top_logprobs(&logits, 10).len(), logits.len());
+        assert!(top_logprobs(&logits, 0).is_empty());
+    }
+
+    #[test]
+    fn test_finish_reason_names() {
+        for reason in [
+            FinishReason::Stop,
+            FinishReason::Length,
+            FinishReason::ToolCalls,
+            FinishReason::ContentFilter,
+            FinishReason::Cancelled,
+            FinishReason::Repetition,
+            FinishReason::Time,
+        ] {
+            assert_eq!(
+                serde_json::to_value(reason).unwrap(),
+                serde_json::json!(reason.as_str())
+            );
+        }
+        assert_eq!(FinishReason::ToolCalls.as_str(), "tool_calls");
+    }
+
+    #[test]
+    fn test_gen_options_validation() {
+        assert!(GenOptions::default().validate().is_ok());
+
+        let with = |tweak: fn(&mut GenOptions)| {
+            let mut opts = GenOptions::default();
+            tweak(&mut opts);
+            opts
+        };
+        let cases = [
+            (with(|o| o.temperature = 2.5), "temperature"),
+            (with(|o| o.temperature = f32::NAN), "temperature"),
+            (with(|o| o.top_p = 0.0), "top_p"),
+            (with(|o| o.top_p = 1.5), "top_p"),
+            (with(|o| o.top_k = -1), "top_k"),
+            (with(|o| o.max_tokens = 0), "max_tokens"),
+            (with(|o| o.repeat_penalty = 0.0), "repeat_penalty"),
+            (with(|o| o.stop_tokens = vec![String::new()]), "stop"),
+            (with(|o| o.max_time_ms = Some(0)), "max_time_ms"),
+            (
+                with(|o| o.repetition = Some(RepetitionConfig::new(1.5))),
+                "repetition",
+            ),
+            (with(|o| o.grammar = Some(String::new())), "grammar"),
+            (with(|o| o.top_logprobs = 21), "top_logprobs"),
+        ];
+        for (opts, param) in cases {
+            let err = opts.validate().unwrap_err();
+            assert_eq!(err.param, param);
+            assert!(err.to_string().starts_with(&format!("Invalid `{param}`")));
+        }
+
+        let edges = GenOptions {
+            temperature: 0.0,
+            top_p: 1.0,
+            top_k: 0,
+            max_tokens: 1,
+            ..Default::default()
+        };
+        assert!(edges.validate().is_ok());
+    }
+
+    #[test]
+    fn test_load_error_from_message() {
+        let path = Path::new("model.gguf");
+        let oom = LoadError::from_message(path, "ggml: failed to allocate CPU_REPACK buffer");
+        assert_eq!(oom.map(|e| e.code()), Some("OUT_OF_MEMORY"));
+
+        let arch = LoadError::from_message(path, "unknown model architecture: 'foo'");
+        assert_eq!(arch.map(|e| e.code()), Some("UNSUPPORTED_ARCHITECTURE"));
+
+        assert!(LoadError::from_message(path, "something odd happened").is_none());
+    }
+
+    #[test]
+    fn test_check_gguf_magic() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let bad = dir.path().join("bad.gguf");
+        std::fs::write(&bad, b"NOPE-not-a-gguf").unwrap();
+        let err = check_gguf_magic(&bad).unwrap_err();
+        assert_eq!(err.code(), "CORRUPT_GGUF");
+
+        let good = dir.path().join("good.gguf");
+        std::fs::write(&good, b"GGUF\x03\x00\x00\x00").unwrap();
+        assert!(check_gguf_magic(&good).is_ok());
+
+        let missing = dir.path().join("missing.gguf");
+        assert_eq!(
+            check_gguf_magic(&missing).unwrap_err().code(),
+            "MODEL_FILE_NOT_FOUND"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Prompt-prefix reuse across requests.
+//!
+//! Agent loops and chat UIs resend the same system prompt and history on
+//! every turn. A loaded llama model keeps its KV cache between requests, so
+//! the tokens it already evaluated for the previous request can be kept as
+//! long as the new prompt starts with them: only the rest of the prompt is
+//! decoded, and the reused count is reported as `cached_tokens`.
+//!
+//! Matching is done on fixed-size blocks of token ids, each identified by a
+//! hash of everything up to and including it, so finding the shared prefix
+//! compares one hash per block instead of every token. Set
+//! `SHIMMY_PREFIX_CACHE=0` to evaluate every prompt from scratch.
+#![cfg_attr(not(feature = "llama"), allow(dead_code))]
+
+use std::hash::{Hash, Hasher};
+
+pub const PREFIX_CACHE_ENV: &str = "SHIMMY_PREFIX_CACHE";
+
+/// Tokens per hashed block
+const BLOCK: usize = 64;
+
+/// Whether prompt-prefix reuse is enabled (default: on)
+pub fn enabled() -> bool {
+    std::env::var(PREFIX_CACHE_ENV)
+        .map(|v| !matches!(v.trim(), "0" | "false" | "off"))
+        .unwrap_or(true)
+}
+
+/// The token sequence held in a context's KV cache
+#[derive(Debug, Default, Clone)]
+pub struct KvPrefix {
+    tokens: Vec<i32>,
+    /// `hashes[i]` covers `tokens[..(i + 1) * BLOCK]`
+    hashes: Vec<u64>,
+}
+
+impl KvPrefix {
+    /// Leading tokens of `prompt` that are already in the cache
+    ///
+    /// Leaves at least one prompt token to evaluate, since sampling needs the
+    /// logits of the last prompt position.
+    pub fn reusable(&self, prompt: &[i32]) -> usize {
+        let limit = prompt.len().saturating_sub(1).min(self.tokens.len());
+
+        // Whole blocks first, by hash, then the remainder token by token
+        let prompt_hashes = block_hashes(&prompt[..limit]);
+        let blocks = self
+            .hashes
+            .iter()
+            .zip(&prompt_hashes)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let start = blocks * BLOCK;
+        start
+            + self.tokens[start..limit]
+                .iter()
+                .zip(&prompt[start..limit])
+                .take_while(|(a, b)| a == b)
+                .count()
+    }
+
+    /// Record that the cache now holds `tokens`
+    pub fn set(&mut self, tokens: &[i32]) {
+        self.tokens = tokens.to_vec();
+        self.hashes = block_hashes(tokens);
+    }
+
+    /// Forget the cached tokens, e.g. when the cache was cleared or a decode
+    /// failed partway
+    pub fn clear(&mut self) {
+        self.tokens.clear();
+        self.hashes.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+/// Chained hash of every complete block in `tokens`
+fn block_hashes(tokens: &[i32]) -> Vec<u64> {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    tokens
+        .chunks_exact(BLOCK)
+        .map(|block| {
+            block.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(n: usize) -> Vec<i32> {
+        (0..n as i32).collect()
+    }
+
+    #[test]
+    fn test_reuses_shared_prefix() {
+        let mut kv = KvPrefix::default();
+        assert_eq!(kv.reusable(&tokens(10)), 0);
+
+        let system = tokens(200);
+        kv.set(&system);
+        let mut next = system.clone();
+        next.extend([900, 901, 902]);
+        assert_eq!(kv.reusable(&next), 200);
+
+        // Diverging inside the second block keeps the first block and the
+        // matching part of the second
+        let mut diverged = system.clone();
+        diverged[100] = -1;
+        assert_eq!(kv.reusable(&diverged), 100);
+    }
+
+    #[test]
+    fn test_leaves_one_token_to_evaluate() {
+        let mut kv = KvPrefix::default();
+        kv.set(&tokens(130));
+        assert_eq!(kv.reusable(&tokens(130)), 129);
+        assert_eq!(kv.reusable(&tokens(50)), 49);
+        assert_eq!(kv.reusable(&tokens(1)), 0);
+        assert_eq!(kv.reusable(&[]), 0);
+    }
+
+    #[test]
+    fn test_clear_forgets_tokens() {
+        let mut kv = KvPrefix::default();
+        kv.set(&tokens(70));
+        assert_eq!(kv.len(), 70);
+        kv.clear();
+        assert!(kv.is_empty());
+        assert_eq!(kv.reusable(&tokens(70)), 0);
+    }
+}
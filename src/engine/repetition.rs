@@ -0,0 +1,197 @@
+//! Runaway-repetition detection.
+//!
+//! Small models sometimes fall into a loop and emit the same phrase until
+//! `max_tokens`, which on CPU can burn minutes. The detector watches a sliding
+//! window of generated tokens and flags the generation once too many of the
+//! window's n-grams are repeats, so the backend can stop with
+//! `finish_reason: "repetition"`.
+//!
+//! Off by default. `SHIMMY_REPETITION_ABORT` sets the threshold (0-1, share of
+//! repeated n-grams in the window); `SHIMMY_REPETITION_WINDOW` (default 64)
+//! and `SHIMMY_REPETITION_NGRAM` (default 4) tune what is counted.
+
+use super::InvalidParameter;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+
+pub const REPETITION_ABORT_ENV: &str = "SHIMMY_REPETITION_ABORT";
+pub const REPETITION_WINDOW_ENV: &str = "SHIMMY_REPETITION_WINDOW";
+pub const REPETITION_NGRAM_ENV: &str = "SHIMMY_REPETITION_NGRAM";
+
+const DEFAULT_WINDOW: usize = 64;
+const DEFAULT_NGRAM: usize = 4;
+
+/// When to call a generation a runaway loop
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RepetitionConfig {
+    /// Share of repeated n-grams in the window that aborts generation
+    pub threshold: f32,
+    /// Tokens considered, most recent first
+    #[serde(default = "default_window")]
+    pub window: usize,
+    /// Length of the token sequences compared
+    #[serde(default = "default_ngram")]
+    pub ngram: usize,
+}
+
+fn default_window() -> usize {
+    DEFAULT_WINDOW
+}
+
+fn default_ngram() -> usize {
+    DEFAULT_NGRAM
+}
+
+impl RepetitionConfig {
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            window: DEFAULT_WINDOW,
+            ngram: DEFAULT_NGRAM,
+        }
+    }
+
+    /// Server-wide setting; `None` when unset or 0
+    pub fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok();
+        Self::parse(
+            var(REPETITION_ABORT_ENV).as_deref(),
+            var(REPETITION_WINDOW_ENV).as_deref(),
+            var(REPETITION_NGRAM_ENV).as_deref(),
+        )
+    }
+
+    fn parse(threshold: Option<&str>, window: Option<&str>, ngram: Option<&str>) -> Option<Self> {
+        let threshold = threshold?.trim().parse::<f32>().ok()?;
+        if threshold <= 0.0 {
+            return None;
+        }
+        let config = Self {
+            threshold,
+            window: window
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_WINDOW),
+            ngram: ngram
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_NGRAM),
+        };
+        match config.validate() {
+            Ok(()) => Some(config),
+            Err(e) => {
+                tracing::warn!("Ignoring repetition detection settings: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), InvalidParameter> {
+        if !(self.threshold > 0.0 && self.threshold <= 1.0) {
+            return Err(InvalidParameter::new(
+                "repetition",
+                format!(
+                    "threshold must be greater than 0 and at most 1, got {}",
+                    self.threshold
+                ),
+            ));
+        }
+        if self.ngram == 0 || self.window <= self.ngram {
+            return Err(InvalidParameter::new(
+                "repetition",
+                format!(
+                    "window ({}) must be longer than the n-gram size ({}), which must be at least 1",
+                    self.window, self.ngram
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Sliding-window n-gram repetition check over generated tokens
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+pub struct RepetitionDetector<T> {
+    config: RepetitionConfig,
+    recent: VecDeque<T>,
+}
+
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+impl<T: Copy + Eq + Hash> RepetitionDetector<T> {
+    pub fn new(config: RepetitionConfig) -> Self {
+        Self {
+            config,
+            recent: VecDeque::with_capacity(config.window),
+        }
+    }
+
+    /// Share of the window's n-grams that already appeared earlier in it
+    pub fn repeated_ratio(&self) -> f32 {
+        let n = self.config.ngram;
+        if self.recent.len() < n {
+            return 0.0;
+        }
+        let tokens: Vec<T> = self.recent.iter().copied().collect();
+        let total = tokens.len() - n + 1;
+        let distinct: HashSet<&[T]> = tokens.windows(n).collect();
+        (total - distinct.len()) as f32 / total as f32
+    }
+
+    /// Record a generated token; `true` once the output counts as a loop
+    ///
+    /// Only a full window is judged, so short answers are never cut off.
+    pub fn push(&mut self, token: T) -> bool {
+        if self.recent.len() == self.config.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(token);
+        self.recent.len() == self.config.window && self.repeated_ratio() >= self.config.threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(threshold: f32, window: usize, ngram: usize) -> RepetitionConfig {
+        RepetitionConfig {
+            threshold,
+            window,
+            ngram,
+        }
+    }
+
+    #[test]
+    fn test_loop_is_detected() {
+        let mut detector = RepetitionDetector::new(config(0.5, 16, 3));
+        let looped = [1, 2, 3, 4].iter().cycle().take(64);
+        let hit = looped.enumerate().find(|&(_, &t)| detector.push(t));
+        // The first full window already has 10 of 14 trigrams repeated
+        assert_eq!(hit.map(|(i, _)| i), Some(15));
+    }
+
+    #[test]
+    fn test_varied_output_is_not_flagged() {
+        let mut detector = RepetitionDetector::new(config(0.5, 16, 3));
+        assert!((0..200).all(|t| !detector.push(t)));
+        assert_eq!(detector.repeated_ratio(), 0.0);
+    }
+
+    #[test]
+    fn test_parse_from_env_values() {
+        assert_eq!(RepetitionConfig::parse(None, None, None), None);
+        assert_eq!(RepetitionConfig::parse(Some("0"), None, None), None);
+        assert_eq!(
+            RepetitionConfig::parse(Some("0.6"), None, None),
+            Some(RepetitionConfig::new(0.6))
+        );
+        assert_eq!(
+            RepetitionConfig::parse(Some("0.6"), Some("32"), Some("2")),
+            Some(config(0.6, 32, 2))
+        );
+        assert_eq!(RepetitionConfig::parse(Some("1.5"), None, None), None);
+        assert_eq!(
+            RepetitionConfig::parse(Some("0.6"), Some("4"), Some("4")),
+            None
+        );
+    }
+}
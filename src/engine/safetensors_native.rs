@@ -674,6 +674,8 @@ mod tests {
             template: Some("chatml".to_string()),
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         let result = engine.load(&spec).await;
@@ -0,0 +1,205 @@
+//! Load-time sanity checks of a model's special tokens.
+//!
+//! A model that doesn't know its end-of-sequence token, or whose chat
+//! template closes turns with a token that doesn't end generation, runs on
+//! until `max_tokens` and users can't tell why. When a GGUF loads, the BOS
+//! and EOS ids the backend uses are checked against the file's tokenizer
+//! metadata and against the end-of-turn token the selected template writes.
+//! Each mismatch is logged as a warning and listed under `warnings` in
+//! `/api/ps` and `/api/models/:name/status`; loading goes ahead regardless.
+
+use super::gguf::TokenizerMetadata;
+use crate::templates::TemplateFamily;
+use serde::Serialize;
+
+/// One special-token problem found at load time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TokenWarning {
+    /// Stable identifier, e.g. `eos_mismatch`
+    pub code: String,
+    pub message: String,
+}
+
+/// Special tokens as the backend loaded them
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+pub struct RuntimeTokens<'a> {
+    pub bos: Option<u32>,
+    pub eos: Option<u32>,
+    /// Whether the backend stops generating at a token
+    pub is_eog: &'a dyn Fn(u32) -> bool,
+}
+
+/// The token `family` closes each turn with, which has to end generation
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+fn end_of_turn(family: &TemplateFamily) -> Option<&'static str> {
+    match family {
+        TemplateFamily::ChatML => Some("<|im_end|>"),
+        TemplateFamily::Llama3 => Some("<|eot_id|>"),
+        TemplateFamily::OpenChat => None,
+    }
+}
+
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+fn describe(meta: &TokenizerMetadata, id: u32) -> String {
+    match meta.tokens.get(id as usize) {
+        Some(piece) => format!("{} ({:?})", id, piece),
+        None => id.to_string(),
+    }
+}
+
+/// Check `meta` against the tokens the backend loaded, where known, and the
+/// template the model will be prompted with
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+pub fn check(
+    meta: &TokenizerMetadata,
+    family: &TemplateFamily,
+    runtime: Option<&RuntimeTokens>,
+) -> Vec<TokenWarning> {
+    let mut warnings = Vec::new();
+    let mut warn = |code: String, message: String| warnings.push(TokenWarning { code, message });
+    let vocab = meta.tokens.len();
+
+    if meta.eos_token_id.is_none() {
+        warn(
+            "eos_missing".into(),
+            "the GGUF declares no EOS token (tokenizer.ggml.eos_token_id), so generation may \
+             only end at a stop sequence or max_tokens"
+                .into(),
+        );
+    }
+    let loaded = [
+        ("bos", meta.bos_token_id, runtime.and_then(|r| r.bos)),
+        ("eos", meta.eos_token_id, runtime.and_then(|r| r.eos)),
+    ];
+    for (kind, declared, used) in loaded {
+        if let Some(id) = declared.filter(|&id| vocab > 0 && id as usize >= vocab) {
+            warn(
+                format!("{}_out_of_range", kind),
+                format!(
+                    "{} token id {} is outside the {}-token vocabulary",
+                    kind.to_uppercase(),
+                    id,
+                    vocab
+                ),
+            );
+        }
+        if runtime.is_some() && used != declared {
+            let show = |id: Option<u32>| id.map_or("none".to_string(), |id| describe(meta, id));
+            warn(
+                format!("{}_mismatch", kind),
+                format!(
+                    "the backend uses {} token {} but the GGUF declares {}",
+                    kind.to_uppercase(),
+                    show(used),
+                    show(declared)
+                ),
+            );
+        }
+    }
+
+    if let (Some(marker), false) = (end_of_turn(family), meta.tokens.is_empty()) {
+        match meta.tokens.iter().position(|piece| piece == marker) {
+            None => warn(
+                "end_of_turn_unknown".into(),
+                format!(
+                    "the {:?} template ends turns with {} but the vocabulary has no such token; \
+                     the model was likely trained with a different template",
+                    family, marker
+                ),
+            ),
+            Some(id) => {
+                let id = id as u32;
+                let ends = match runtime {
+                    Some(runtime) => (runtime.is_eog)(id),
+                    None => [meta.eos_token_id, meta.eot_token_id].contains(&Some(id)),
+                };
+                if !ends {
+                    warn(
+                        "end_of_turn_not_eog".into(),
+                        format!(
+                            "{} doesn't end generation, so replies can run past the end of the turn",
+                            describe(meta, id)
+                        ),
+                    );
+                }
+            }
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chatml_vocab(eos: Option<u32>) -> TokenizerMetadata {
+        TokenizerMetadata {
+            tokens: [
+                "<s>",
+                "hello",
+                "<|im_start|>",
+                "<|im_end|>",
+                "<|endoftext|>",
+            ]
+            .map(String::from)
+            .to_vec(),
+            bos_token_id: None,
+            eos_token_id: eos,
+            eot_token_id: None,
+        }
+    }
+
+    fn codes(warnings: &[TokenWarning]) -> Vec<&str> {
+        warnings.iter().map(|w| w.code.as_str()).collect()
+    }
+
+    #[test]
+    fn test_consistent_tokens_pass() {
+        let meta = chatml_vocab(Some(3));
+        assert!(check(&meta, &TemplateFamily::ChatML, None).is_empty());
+
+        let is_eog = |id: u32| id == 3 || id == 4;
+        let runtime = RuntimeTokens {
+            bos: None,
+            eos: Some(3),
+            is_eog: &is_eog,
+        };
+        assert!(check(&meta, &TemplateFamily::ChatML, Some(&runtime)).is_empty());
+        // OpenChat has no end-of-turn token to check
+        assert!(check(&meta, &TemplateFamily::OpenChat, None).is_empty());
+    }
+
+    #[test]
+    fn test_mismatches_are_reported() {
+        // EOS is <|endoftext|>, so <|im_end|> never stops a ChatML reply
+        let meta = chatml_vocab(Some(4));
+        let warnings = check(&meta, &TemplateFamily::ChatML, None);
+        assert_eq!(codes(&warnings), ["end_of_turn_not_eog"]);
+        assert!(warnings[0].message.contains("\"<|im_end|>\""));
+
+        let is_eog = |id: u32| id == 4;
+        let runtime = RuntimeTokens {
+            bos: Some(0),
+            eos: Some(4),
+            is_eog: &is_eog,
+        };
+        let meta = TokenizerMetadata {
+            bos_token_id: Some(9),
+            ..chatml_vocab(None)
+        };
+        assert_eq!(
+            codes(&check(&meta, &TemplateFamily::ChatML, Some(&runtime))),
+            [
+                "eos_missing",
+                "bos_out_of_range",
+                "bos_mismatch",
+                "eos_mismatch",
+                "end_of_turn_not_eog"
+            ]
+        );
+
+        // A vocabulary without Llama 3's tokens prompted with its template
+        let warnings = check(&chatml_vocab(Some(3)), &TemplateFamily::Llama3, None);
+        assert_eq!(codes(&warnings), ["end_of_turn_unknown"]);
+    }
+}
@@ -79,6 +79,8 @@ impl TryFrom<UniversalModelSpec> for super::ModelSpec {
                 template: spec.template,
                 ctx_len: spec.ctx_len,
                 n_threads: spec.n_threads,
+                n_gpu_layers: None,
+                mmproj_path: None,
             }),
             _ => Err(anyhow!(
                 "Cannot convert non-GGUF backend to legacy ModelSpec"
@@ -367,6 +369,16 @@ mod tests {
             seed: Some(42),
             stream: true,
             stop_tokens: Vec::new(),
+            add_bos: None,
+            repetition: None,
+            max_time_ms: None,
+            grammar: None,
+            top_logprobs: 0,
+            cancel: None,
+            deadline: None,
+            api_key: None,
+            rate_limit: None,
+            adapter: None,
         };
 
         let result = adapter.generate("Hello world", opts, None).await;
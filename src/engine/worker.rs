@@ -0,0 +1,710 @@
+//! Crash isolation for llama.cpp inference.
+//!
+//! A segfault or abort inside llama.cpp normally takes the whole server down.
+//! With `SHIMMY_ISOLATE_INFERENCE=1` each llama model is loaded in its own
+//! `shimmy worker` child process instead, driven over the child's stdin and
+//! stdout with one JSON message per line. If the worker dies, the requests it
+//! was serving fail with an inference error (HTTP 500), the server keeps
+//! running, and the model is reloaded in a fresh worker in the background.
+//!
+//! The worker gets the parent's GPU backend and MoE flags. Its tokenizer isn't
+//! forwarded, so token counts fall back to estimates in this mode. Anything
+//! else the worker prints to stdout (its own log lines) is relayed to the
+//! server log; stderr is shared with the server.
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use super::{
+    FinishReason, GenOptions, Generation, InferenceEngine, LoadedModel, ModelMemoryUsage,
+    ModelSpec, TokenDetail,
+};
+
+pub const ISOLATE_ENV: &str = "SHIMMY_ISOLATE_INFERENCE";
+
+/// Error for requests that were in flight when their worker died
+pub const WORKER_CRASHED: &str = "inference worker crashed; the model is being reloaded";
+
+/// Id the load handshake is answered on; requests count up from 1
+const LOAD_ID: u64 = 0;
+
+/// How often a waiting request checks its cancel flag
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+/// Whether llama models should be loaded in worker processes
+pub fn isolation_requested() -> bool {
+    std::env::var(ISOLATE_ENV).is_ok_and(|v| v == "1")
+}
+
+/// Server to worker
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    Load {
+        spec: ModelSpec,
+    },
+    Generate {
+        id: u64,
+        prompt: String,
+        opts: GenOptions,
+        /// Send `token` events; without it only `done` comes back
+        stream: bool,
+    },
+    Vision {
+        id: u64,
+        images_hex: Vec<String>,
+        prompt: String,
+        opts: GenOptions,
+        stream: bool,
+    },
+    Embed {
+        id: u64,
+        inputs: Vec<String>,
+    },
+    EmbedImages {
+        id: u64,
+        images_hex: Vec<String>,
+    },
+    Cancel {
+        id: u64,
+    },
+}
+
+/// Worker to server
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Loaded {
+        memory: Option<ModelMemoryUsage>,
+    },
+    Token {
+        id: u64,
+        token: TokenDetail,
+    },
+    Done {
+        id: u64,
+        text: String,
+        finish_reason: FinishReason,
+        #[serde(default)]
+        cached_tokens: usize,
+    },
+    Embeddings {
+        id: u64,
+        vectors: Vec<Vec<f32>>,
+    },
+    Error {
+        id: u64,
+        message: String,
+    },
+}
+
+impl Event {
+    fn id(&self) -> u64 {
+        match self {
+            Event::Loaded { .. } => LOAD_ID,
+            Event::Token { id, .. }
+            | Event::Done { id, .. }
+            | Event::Embeddings { id, .. }
+            | Event::Error { id, .. } => *id,
+        }
+    }
+
+    /// Whether this event ends its request
+    fn is_final(&self) -> bool {
+        !matches!(self, Event::Token { .. })
+    }
+}
+
+type Pending = Arc<Mutex<HashMap<u64, UnboundedSender<Event>>>>;
+
+/// Loads llama models in supervised `shimmy worker` processes
+pub struct WorkerEngine {
+    /// Global flags passed to the worker ahead of the `worker` subcommand
+    args: Vec<String>,
+}
+
+impl WorkerEngine {
+    pub fn new(args: Vec<String>) -> Self {
+        Self { args }
+    }
+}
+
+#[async_trait]
+impl InferenceEngine for WorkerEngine {
+    async fn load(&self, spec: &ModelSpec) -> Result<Box<dyn LoadedModel>> {
+        let supervisor = Arc::new(Supervisor {
+            spec: spec.clone(),
+            args: self.args.clone(),
+            worker: tokio::sync::Mutex::new(None),
+            memory: Mutex::new(None),
+            next_id: AtomicU64::new(LOAD_ID + 1),
+        });
+        supervisor.worker().await?;
+        Ok(Box::new(WorkerModel { supervisor }))
+    }
+}
+
+/// One running worker process with its model loaded
+struct Worker {
+    child: Mutex<Child>,
+    stdin: tokio::sync::Mutex<ChildStdin>,
+    pending: Pending,
+    alive: Arc<AtomicBool>,
+}
+
+impl Worker {
+    async fn spawn(
+        spec: &ModelSpec,
+        args: &[String],
+        supervisor: Weak<Supervisor>,
+    ) -> Result<(Self, Option<ModelMemoryUsage>)> {
+        let exe = std::env::current_exe().context("locating the shimmy binary")?;
+        let mut child = tokio::process::Command::new(exe)
+            .args(args)
+            .arg("worker")
+            // Its log lines reach the log files through the server, and its
+            // work is covered by the server's `llm.generate` spans
+            .env(crate::log_files::LOG_DIR_ENV, "")
+            .env(crate::otel::OTLP_ENDPOINT_ENV, "")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("starting inference worker")?;
+        let stdin = child.stdin.take().context("worker stdin")?;
+        let stdout = child.stdout.take().context("worker stdout")?;
+
+        let pending: Pending = Arc::default();
+        let (load_tx, mut load_rx) = unbounded_channel();
+        lock(&pending).insert(LOAD_ID, load_tx);
+        let alive = Arc::new(AtomicBool::new(true));
+        tokio::spawn(read_events(
+            stdout,
+            Arc::clone(&pending),
+            Arc::clone(&alive),
+            supervisor,
+        ));
+
+        let worker = Self {
+            child: Mutex::new(child),
+            stdin: tokio::sync::Mutex::new(stdin),
+            pending,
+            alive,
+        };
+        worker.send(&Request::Load { spec: spec.clone() }).await?;
+        match load_rx.recv().await {
+            Some(Event::Loaded { memory }) => Ok((worker, memory)),
+            Some(Event::Error { message, .. }) => Err(super::LoadError::categorize(
+                &spec.base_path,
+                anyhow!(message),
+            )),
+            _ => bail!("inference worker exited while loading {}", spec.name),
+        }
+    }
+
+    async fn send(&self, request: &Request) -> Result<()> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        let mut stdin = self.stdin.lock().await;
+        match stdin.write_all(&line).await {
+            Ok(()) => stdin.flush().await.map_err(|_| anyhow!(WORKER_CRASHED)),
+            Err(_) => bail!(WORKER_CRASHED),
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Route worker events to their requests; on exit, fail them and restart
+async fn read_events(
+    stdout: ChildStdout,
+    pending: Pending,
+    alive: Arc<AtomicBool>,
+    supervisor: Weak<Supervisor>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(event) = serde_json::from_str::<Event>(&line) else {
+            tracing::info!(target: "shimmy::worker", "{}", line);
+            continue;
+        };
+        let mut routes = lock(&pending);
+        let id = event.id();
+        let tx = if event.is_final() {
+            routes.remove(&id)
+        } else {
+            routes.get(&id).cloned()
+        };
+        if let Some(tx) = tx {
+            let _ = tx.send(event);
+        }
+    }
+
+    alive.store(false, Ordering::Relaxed);
+    // Dropping the senders fails every request still waiting on this worker
+    lock(&pending).clear();
+    // Gone when the model was unloaded, which is what killed the worker
+    if let Some(supervisor) = supervisor.upgrade() {
+        tokio::spawn(restart(supervisor));
+    }
+}
+
+/// Boxed so that this future's type doesn't end up containing itself
+fn restart(supervisor: Arc<Supervisor>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    Box::pin(async move {
+        if let Err(e) = supervisor.worker().await {
+            tracing::warn!(
+                "Could not restart inference worker for {}: {:#}",
+                supervisor.spec.name,
+                e
+            );
+        }
+    })
+}
+
+/// Keeps one model's worker running, restarting it after a crash
+struct Supervisor {
+    spec: ModelSpec,
+    args: Vec<String>,
+    worker: tokio::sync::Mutex<Option<Arc<Worker>>>,
+    memory: Mutex<Option<ModelMemoryUsage>>,
+    next_id: AtomicU64,
+}
+
+impl Supervisor {
+    /// The live worker, starting a new one if the last has died
+    async fn worker(self: &Arc<Self>) -> Result<Arc<Worker>> {
+        let mut slot = self.worker.lock().await;
+        if let Some(worker) = slot.take() {
+            if worker.alive.load(Ordering::Relaxed) {
+                *slot = Some(Arc::clone(&worker));
+                return Ok(worker);
+            }
+            let status = lock(&worker.child).try_wait().ok().flatten();
+            tracing::warn!(
+                "Inference worker for {} exited ({}); restarting",
+                self.spec.name,
+                status.map_or_else(|| "unknown status".to_string(), |s| s.to_string())
+            );
+        }
+        let (worker, memory) = Worker::spawn(&self.spec, &self.args, Arc::downgrade(self)).await?;
+        *lock(&self.memory) = memory;
+        let worker = Arc::new(worker);
+        *slot = Some(Arc::clone(&worker));
+        Ok(worker)
+    }
+
+    /// Send a request, passing its tokens to `on_token`, and return its final event
+    async fn call(
+        self: &Arc<Self>,
+        request: impl FnOnce(u64) -> Request,
+        cancel: Option<Arc<AtomicBool>>,
+        mut on_token: impl FnMut(TokenDetail),
+    ) -> Result<Event> {
+        let worker = self.worker().await?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, mut rx) = unbounded_channel();
+        lock(&worker.pending).insert(id, tx);
+        let _pending = PendingGuard {
+            pending: Arc::clone(&worker.pending),
+            id,
+        };
+        worker.send(&request(id)).await?;
+
+        let mut cancel = cancel;
+        loop {
+            let event = match &cancel {
+                Some(flag) => tokio::select! {
+                    event = rx.recv() => event,
+                    _ = tokio::time::sleep(CANCEL_POLL) => {
+                        if flag.load(Ordering::Relaxed) {
+                            cancel = None;
+                            worker.send(&Request::Cancel { id }).await?;
+                        }
+                        continue;
+                    }
+                },
+                None => rx.recv().await,
+            };
+            match event {
+                Some(Event::Token { token, .. }) => on_token(token),
+                Some(Event::Error { message, .. }) => bail!(message),
+                Some(event) => return Ok(event),
+                None => bail!(WORKER_CRASHED),
+            }
+        }
+    }
+}
+
+/// Stops routing events to a request once its caller stops waiting
+struct PendingGuard {
+    pending: Pending,
+    id: u64,
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        lock(&self.pending).remove(&self.id);
+    }
+}
+
+/// Server-side handle to a model loaded in a worker
+struct WorkerModel {
+    supervisor: Arc<Supervisor>,
+}
+
+impl WorkerModel {
+    async fn generation(
+        &self,
+        request: impl FnOnce(u64) -> Request,
+        cancel: Option<Arc<AtomicBool>>,
+        on_token: Option<Box<dyn FnMut(TokenDetail) + Send>>,
+    ) -> Result<Generation> {
+        let mut on_token = on_token;
+        let event = self
+            .supervisor
+            .call(request, cancel, |token| {
+                if let Some(cb) = on_token.as_mut() {
+                    cb(token)
+                }
+            })
+            .await?;
+        match event {
+            Event::Done {
+                text,
+                finish_reason,
+                cached_tokens,
+                ..
+            } => Ok(Generation {
+                text,
+                finish_reason,
+                cached_tokens,
+            }),
+            other => bail!("unexpected reply from inference worker: {:?}", other),
+        }
+    }
+}
+
+fn text_callback(
+    on_token: Option<Box<dyn FnMut(String) + Send>>,
+) -> Option<Box<dyn FnMut(TokenDetail) + Send>> {
+    on_token.map(|mut cb| {
+        Box::new(move |token: TokenDetail| cb(token.text)) as Box<dyn FnMut(TokenDetail) + Send>
+    })
+}
+
+#[async_trait]
+impl LoadedModel for WorkerModel {
+    async fn generate(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<String> {
+        Ok(self
+            .generate_with_finish(prompt, opts, on_token)
+            .await?
+            .text)
+    }
+
+    async fn generate_with_finish(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<Generation> {
+        self.generate_detailed(prompt, opts, text_callback(on_token))
+            .await
+    }
+
+    async fn generate_detailed(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(TokenDetail) + Send>>,
+    ) -> Result<Generation> {
+        let opts = opts.deadline_as_max_time();
+        let cancel = opts.cancel.clone();
+        let stream = on_token.is_some();
+        let request = |id| Request::Generate {
+            id,
+            prompt: prompt.to_string(),
+            opts,
+            stream,
+        };
+        self.generation(request, cancel, on_token).await
+    }
+
+    async fn generate_vision(
+        &self,
+        images: &[&[u8]],
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<String> {
+        let opts = opts.deadline_as_max_time();
+        let cancel = opts.cancel.clone();
+        let stream = on_token.is_some();
+        let request = |id| Request::Vision {
+            id,
+            images_hex: images.iter().map(hex::encode).collect(),
+            prompt: prompt.to_string(),
+            opts,
+            stream,
+        };
+        Ok(self
+            .generation(request, cancel, text_callback(on_token))
+            .await?
+            .text)
+    }
+
+    fn memory_usage(&self) -> Option<ModelMemoryUsage> {
+        *lock(&self.supervisor.memory)
+    }
+
+    fn supports_adapters(&self) -> bool {
+        // The worker loads the model with llama.cpp
+        cfg!(feature = "llama")
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = |id| Request::Embed {
+            id,
+            inputs: inputs.to_vec(),
+        };
+        match self.supervisor.call(request, None, |_| {}).await? {
+            Event::Embeddings { vectors, .. } => Ok(vectors),
+            other => bail!("unexpected reply from inference worker: {:?}", other),
+        }
+    }
+
+    async fn embed_images(&self, images: &[&[u8]]) -> Result<Vec<Vec<f32>>> {
+        let request = |id| Request::EmbedImages {
+            id,
+            images_hex: images.iter().map(hex::encode).collect(),
+        };
+        match self.supervisor.call(request, None, |_| {}).await? {
+            Event::Embeddings { vectors, .. } => Ok(vectors),
+            other => bail!("unexpected reply from inference worker: {:?}", other),
+        }
+    }
+}
+
+/// Body of `shimmy worker`: serve one model to the parent over stdin/stdout
+pub async fn run_worker(engine: &dyn InferenceEngine) -> Result<()> {
+    let (out, mut out_rx) = unbounded_channel::<Event>();
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(event) = out_rx.recv().await {
+            let mut line = serde_json::to_vec(&event)?;
+            line.push(b'\n');
+            stdout.write_all(&line).await?;
+            stdout.flush().await?;
+        }
+        Ok::<_, anyhow::Error>(())
+    });
+
+    let mut model: Option<Arc<dyn LoadedModel>> = None;
+    let cancels: Arc<Mutex<HashMap<u64, Arc<AtomicBool>>>> = Arc::default();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    // EOF means the server is gone or unloaded the model
+    while let Some(line) = lines.next_line().await? {
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                tracing::warn!("Ignoring malformed worker request: {}", e);
+                continue;
+            }
+        };
+        let (id, mut opts) = match request {
+            Request::Load { spec } => {
+                let _ = out.send(match engine.load(&spec).await {
+                    Ok(loaded) => {
+                        let memory = loaded.memory_usage();
+                        model = Some(Arc::from(loaded));
+                        Event::Loaded { memory }
+                    }
+                    Err(e) => Event::Error {
+                        id: LOAD_ID,
+                        message: format!("{:#}", e),
+                    },
+                });
+                continue;
+            }
+            Request::Cancel { id } => {
+                if let Some(flag) = lock(&cancels).get(&id) {
+                    flag.store(true, Ordering::Relaxed);
+                }
+                continue;
+            }
+            Request::Generate { id, ref opts, .. } | Request::Vision { id, ref opts, .. } => {
+                (id, Some(opts.clone()))
+            }
+            Request::Embed { id, .. } | Request::EmbedImages { id, .. } => (id, None),
+        };
+        let Some(model) = model.clone() else {
+            let _ = out.send(Event::Error {
+                id,
+                message: "no model loaded".to_string(),
+            });
+            continue;
+        };
+        if let Some(opts) = opts.as_mut() {
+            let flag = Arc::new(AtomicBool::new(false));
+            opts.cancel = Some(Arc::clone(&flag));
+            lock(&cancels).insert(id, flag);
+        }
+
+        let out = out.clone();
+        let cancels = Arc::clone(&cancels);
+        tokio::spawn(async move {
+            let tokens = out.clone();
+            let on_token = move |token: TokenDetail| {
+                let _ = tokens.send(Event::Token { id, token });
+            };
+            let result = match request {
+                Request::Generate { prompt, stream, .. } => {
+                    let on_token =
+                        stream.then(|| Box::new(on_token) as Box<dyn FnMut(TokenDetail) + Send>);
+                    model
+                        .generate_detailed(&prompt, opts.unwrap_or_default(), on_token)
+                        .await
+                        .map(|g| Event::Done {
+                            id,
+                            text: g.text,
+                            finish_reason: g.finish_reason,
+                            cached_tokens: g.cached_tokens,
+                        })
+                }
+                Request::Vision {
+                    images_hex,
+                    prompt,
+                    stream,
+                    ..
+                } => match images_hex
+                    .iter()
+                    .map(hex::decode)
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(images) => {
+                        let images: Vec<&[u8]> = images.iter().map(Vec::as_slice).collect();
+                        let opts = opts.unwrap_or_default();
+                        let cancel = opts.cancel.clone();
+                        let on_token = stream.then(|| {
+                            let mut on_token = on_token;
+                            Box::new(move |text| {
+                                on_token(TokenDetail {
+                                    text,
+                                    id: None,
+                                    logprob: None,
+                                    top_logprobs: Vec::new(),
+                                })
+                            }) as Box<dyn FnMut(String) + Send>
+                        });
+                        model
+                            .generate_vision(&images, &prompt, opts, on_token)
+                            .await
+                            .map(|text| Event::Done {
+                                id,
+                                text,
+                                finish_reason: if cancel
+                                    .is_some_and(|flag| flag.load(Ordering::Relaxed))
+                                {
+                                    FinishReason::Cancelled
+                                } else {
+                                    FinishReason::Stop
+                                },
+                                cached_tokens: 0,
+                            })
+                    }
+                    Err(e) => Err(anyhow!("invalid image data: {}", e)),
+                },
+                Request::Embed { inputs, .. } => model
+                    .embed(&inputs)
+                    .await
+                    .map(|vectors| Event::Embeddings { id, vectors }),
+                Request::EmbedImages { images_hex, .. } => match images_hex
+                    .iter()
+                    .map(hex::decode)
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(images) => {
+                        let images: Vec<&[u8]> = images.iter().map(Vec::as_slice).collect();
+                        model
+                            .embed_images(&images)
+                            .await
+                            .map(|vectors| Event::Embeddings { id, vectors })
+                    }
+                    Err(e) => Err(anyhow!("invalid image data: {}", e)),
+                },
+                Request::Load { .. } | Request::Cancel { .. } => unreachable!(),
+            };
+            lock(&cancels).remove(&id);
+            let _ = out.send(result.unwrap_or_else(|e| Event::Error {
+                id,
+                message: format!("{:#}", e),
+            }));
+        });
+    }
+
+    drop(out);
+    writer.await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_protocol_round_trip() {
+        let request = Request::Load {
+            spec: ModelSpec {
+                name: "phi3".to_string(),
+                base_path: PathBuf::from("/models/phi3.gguf"),
+                lora_path: None,
+                template: Some("chatml".to_string()),
+                ctx_len: 4096,
+                n_threads: None,
+                n_gpu_layers: None,
+                mmproj_path: None,
+            },
+        };
+        let line = serde_json::to_string(&request).unwrap();
+        assert!(line.starts_with(r#"{"op":"load""#), "{}", line);
+        match serde_json::from_str(&line).unwrap() {
+            Request::Load { spec } => {
+                assert_eq!(spec.base_path, PathBuf::from("/models/phi3.gguf"))
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let event: Event =
+            serde_json::from_str(r#"{"event":"done","id":7,"text":"hi","finish_reason":"length"}"#)
+                .unwrap();
+        assert_eq!(event.id(), 7);
+        assert!(event.is_final());
+        let token: Event =
+            serde_json::from_str(r#"{"event":"token","id":7,"token":{"text":"h"}}"#).unwrap();
+        assert!(!token.is_final());
+        assert_eq!(Event::Loaded { memory: None }.id(), LOAD_ID);
+    }
+
+    #[test]
+    fn test_log_lines_are_not_events() {
+        assert!(serde_json::from_str::<Event>("INFO shimmy: loading model").is_err());
+        assert!(serde_json::from_str::<Event>(r#"{"level":"info"}"#).is_err());
+    }
+}
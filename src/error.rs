@@ -1,3 +1,7 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -6,6 +10,9 @@ pub enum ShimmyError {
     #[error("Model not found: {name}")]
     ModelNotFound { name: String },
 
+    #[error("Model '{model}' has no adapter named '{adapter}'")]
+    AdapterNotFound { model: String, adapter: String },
+
     #[error("Model loading failed: {path}")]
     ModelLoadError {
         path: PathBuf,
@@ -51,6 +58,47 @@ pub enum ShimmyError {
     #[error("Unsupported operation: {operation}")]
     UnsupportedOperation { operation: String },
 
+    #[error("{operation} is disabled: this server is running in read-only mode")]
+    ReadOnly { operation: String },
+
+    #[error("Requests from {addr} are not allowed on this route")]
+    AccessDenied { addr: String },
+
+    #[error("Too many {route} requests are waiting; try again shortly")]
+    ServerBusy { route: String },
+
+    #[error("Request deadline exceeded during {stage}")]
+    DeadlineExceeded { stage: String },
+
+    #[error("A valid API key is required (Authorization: Bearer <key> or x-api-key)")]
+    Unauthorized,
+
+    #[error("A valid admin token is required (x-admin-token)")]
+    AdminUnauthorized,
+
+    #[error("The admin API is disabled; set SHIMMY_ADMIN_TOKEN to enable it")]
+    AdminDisabled,
+
+    #[error("API key `{key}` is over its {limit} limit; retry in {retry_after_secs}s")]
+    RateLimited {
+        key: String,
+        limit: String,
+        retry_after_secs: u64,
+    },
+
+    #[error("Rate limit for {scope} reached ({limit}); retry in {retry_after_secs}s")]
+    ServerRateLimited {
+        scope: String,
+        limit: String,
+        retry_after_secs: u64,
+    },
+
+    #[error("Idempotency-Key `{key}` was already used for a different request")]
+    IdempotencyKeyReused { key: String },
+
+    #[error("A request with Idempotency-Key `{key}` is still running; retry shortly")]
+    IdempotencyInProgress { key: String },
+
     #[error("Tool execution failed: {error}")]
     ToolExecutionFailed { error: String },
 
@@ -110,18 +158,295 @@ pub enum ShimmyError {
 
     #[error("Model verification failed: {details}")]
     ModelVerificationFailed { details: String },
+
+    // Categorized model load failures
+    #[error(transparent)]
+    Load(#[from] crate::engine::LoadError),
+
+    // Request validation
+    #[error("{reason}")]
+    InvalidRequest { reason: String },
+
+    #[error(transparent)]
+    InvalidParameter(#[from] crate::engine::InvalidParameter),
+
+    #[error(transparent)]
+    VectorStore(#[from] crate::vector_store::VectorStoreError),
+
+    // Vision errors
+    #[cfg(feature = "vision")]
+    #[error(transparent)]
+    VisionLicense(#[from] crate::vision_license::VisionLicenseError),
+
+    #[error("Failed to fetch image from URL: {reason}")]
+    ImageFetchFailed { reason: String, timed_out: bool },
+
+    #[error("Failed to preprocess image: {reason}")]
+    ImagePreprocessFailed { reason: String },
+
+    #[error("{reason}")]
+    VisionModelUnavailable { reason: String },
+
+    #[error("Image blocked by the safety check: {reason}")]
+    ImageBlocked { reason: String },
+
+    #[error("Model download failed: {reason}")]
+    ModelDownloadFailed { reason: String },
+
+    #[error("Vision inference failed: {reason}")]
+    InferenceFailed { reason: String },
+
+    #[error("Vision inference timed out after {timeout_ms} ms")]
+    InferenceTimeout { timeout_ms: u64 },
+
+    #[error("Usage recording failed: {reason}")]
+    UsageRecordingFailed { reason: String },
+
+    #[error("State store error: {reason}")]
+    StateStoreFailed { reason: String },
+
+    #[error("Schema version {requested} is not supported; supported versions: {supported}")]
+    UnsupportedSchemaVersion {
+        requested: String,
+        supported: String,
+    },
+
+    #[error(
+        "Insufficient memory for request: needs {required_mb} MB, {available_mb} MB available"
+    )]
+    InsufficientMemory { required_mb: u64, available_mb: u64 },
 }
 
+#[cfg_attr(not(feature = "vision"), allow(dead_code))]
 pub type Result<T> = std::result::Result<T, ShimmyError>;
 
 impl From<anyhow::Error> for ShimmyError {
     fn from(err: anyhow::Error) -> Self {
-        ShimmyError::GenerationError {
-            reason: err.to_string(),
+        match err.downcast::<crate::engine::LoadError>() {
+            Ok(load_err) => ShimmyError::Load(load_err),
+            Err(err) => ShimmyError::GenerationError {
+                reason: err.to_string(),
+            },
         }
     }
 }
 
+impl ShimmyError {
+    /// Wrap an `engine.load` failure, keeping its category when it has one
+    pub fn from_load(path: &std::path::Path, err: anyhow::Error) -> Self {
+        match err.downcast::<crate::engine::LoadError>() {
+            Ok(load_err) => ShimmyError::Load(load_err),
+            Err(source) => ShimmyError::ModelLoadError {
+                path: path.to_path_buf(),
+                source,
+            },
+        }
+    }
+
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            ShimmyError::ModelNotFound { .. } => "MODEL_NOT_FOUND",
+            ShimmyError::AdapterNotFound { .. } => "ADAPTER_NOT_FOUND",
+            ShimmyError::ModelLoadError { .. } => "MODEL_LOAD_FAILED",
+            ShimmyError::Load(load_err) => load_err.code(),
+            ShimmyError::GenerationError { .. } => "GENERATION_FAILED",
+            ShimmyError::ConfigError { .. } => "INVALID_CONFIGURATION",
+            ShimmyError::BackendNotAvailable { .. } | ShimmyError::UnsupportedBackend { .. } => {
+                "BACKEND_NOT_AVAILABLE"
+            }
+            ShimmyError::TemplateError { .. } => "TEMPLATE_ERROR",
+            ShimmyError::InvalidRequest { .. }
+            | ShimmyError::MissingParameter { .. }
+            | ShimmyError::InvalidPath { .. } => "INVALID_REQUEST",
+            ShimmyError::InvalidParameter(_) => "INVALID_PARAMETER",
+            ShimmyError::VectorStore(store_err) => store_err.code(),
+            ShimmyError::FileNotFound { .. } => "FILE_NOT_FOUND",
+            ShimmyError::ToolNotFound { .. } => "TOOL_NOT_FOUND",
+            ShimmyError::WorkflowStepNotFound { .. }
+            | ShimmyError::WorkflowVariableNotFound { .. }
+            | ShimmyError::WorkflowCircularDependency { .. } => "WORKFLOW_ERROR",
+            ShimmyError::UnsupportedOperation { .. } | ShimmyError::NotImplemented { .. } => {
+                "NOT_IMPLEMENTED"
+            }
+            ShimmyError::ReadOnly { .. } => "READ_ONLY",
+            ShimmyError::AccessDenied { .. } => "ACCESS_DENIED",
+            ShimmyError::ServerBusy { .. } => "SERVER_BUSY",
+            ShimmyError::DeadlineExceeded { .. } => "DEADLINE_EXCEEDED",
+            ShimmyError::Unauthorized | ShimmyError::AdminUnauthorized => "UNAUTHORIZED",
+            ShimmyError::AdminDisabled => "ADMIN_DISABLED",
+            ShimmyError::RateLimited { .. } | ShimmyError::ServerRateLimited { .. } => {
+                "RATE_LIMITED"
+            }
+            ShimmyError::IdempotencyKeyReused { .. } => "IDEMPOTENCY_KEY_REUSED",
+            ShimmyError::IdempotencyInProgress { .. } => "IDEMPOTENCY_IN_PROGRESS",
+            ShimmyError::SafeTensorsConversionNeeded { .. }
+            | ShimmyError::MlxIncompatible { .. } => "UNSUPPORTED_MODEL_FORMAT",
+            ShimmyError::MlxNotAvailable { .. } | ShimmyError::PythonDependenciesMissing { .. } => {
+                "BACKEND_NOT_AVAILABLE"
+            }
+            #[cfg(feature = "vision")]
+            ShimmyError::VisionLicense(license_err) => license_err.code(),
+            ShimmyError::ImageFetchFailed { .. } => "IMAGE_FETCH_FAILED",
+            ShimmyError::ImagePreprocessFailed { .. } => "IMAGE_PREPROCESS_FAILED",
+            ShimmyError::VisionModelUnavailable { .. } => "VISION_MODEL_UNAVAILABLE",
+            ShimmyError::ImageBlocked { .. } => "IMAGE_BLOCKED",
+            ShimmyError::ModelDownloadFailed { .. } => "MODEL_DOWNLOAD_FAILED",
+            ShimmyError::InferenceFailed { .. } => "INFERENCE_FAILED",
+            ShimmyError::InferenceTimeout { .. } => "INFERENCE_TIMEOUT",
+            ShimmyError::InsufficientMemory { .. } => "INSUFFICIENT_MEMORY",
+            ShimmyError::UnsupportedSchemaVersion { .. } => "UNSUPPORTED_SCHEMA_VERSION",
+            ShimmyError::ToolExecutionFailed { .. }
+            | ShimmyError::ScriptExecutionFailed { .. }
+            | ShimmyError::ProcessFailed { .. }
+            | ShimmyError::PortAllocationFailed { .. }
+            | ShimmyError::DiscoveryFailed { .. }
+            | ShimmyError::ModelVerificationFailed { .. }
+            | ShimmyError::UsageRecordingFailed { .. }
+            | ShimmyError::StateStoreFailed { .. }
+            | ShimmyError::AsyncError(_)
+            | ShimmyError::IoError(_)
+            | ShimmyError::SerdeError(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    /// HTTP status for this error
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ShimmyError::ModelNotFound { .. }
+            | ShimmyError::AdapterNotFound { .. }
+            | ShimmyError::FileNotFound { .. }
+            | ShimmyError::ToolNotFound { .. } => StatusCode::NOT_FOUND,
+            ShimmyError::ReadOnly { .. }
+            | ShimmyError::AccessDenied { .. }
+            | ShimmyError::AdminDisabled => StatusCode::FORBIDDEN,
+            ShimmyError::Unauthorized | ShimmyError::AdminUnauthorized => StatusCode::UNAUTHORIZED,
+            ShimmyError::RateLimited { .. } | ShimmyError::ServerRateLimited { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            ShimmyError::IdempotencyInProgress { .. } => StatusCode::CONFLICT,
+            ShimmyError::UnsupportedSchemaVersion { .. } => StatusCode::NOT_ACCEPTABLE,
+            ShimmyError::Load(load_err) => crate::api_errors::load_error_status(load_err),
+            ShimmyError::VectorStore(store_err) => store_err.status_code(),
+            ShimmyError::InvalidRequest { .. }
+            | ShimmyError::InvalidParameter(_)
+            | ShimmyError::MissingParameter { .. }
+            | ShimmyError::InvalidPath { .. }
+            | ShimmyError::ConfigError { .. } => StatusCode::BAD_REQUEST,
+            ShimmyError::ImagePreprocessFailed { .. }
+            | ShimmyError::VisionModelUnavailable { .. }
+            | ShimmyError::ImageBlocked { .. }
+            | ShimmyError::IdempotencyKeyReused { .. }
+            | ShimmyError::SafeTensorsConversionNeeded { .. }
+            | ShimmyError::MlxIncompatible { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ShimmyError::ImageFetchFailed {
+                timed_out: true, ..
+            }
+            | ShimmyError::InferenceTimeout { .. }
+            | ShimmyError::DeadlineExceeded { .. } => StatusCode::GATEWAY_TIMEOUT,
+            ShimmyError::ImageFetchFailed { .. }
+            | ShimmyError::ModelLoadError { .. }
+            | ShimmyError::ModelDownloadFailed { .. }
+            | ShimmyError::InferenceFailed { .. }
+            | ShimmyError::GenerationError { .. } => StatusCode::BAD_GATEWAY,
+            ShimmyError::BackendNotAvailable { .. }
+            | ShimmyError::UnsupportedBackend { .. }
+            | ShimmyError::MlxNotAvailable { .. }
+            | ShimmyError::PythonDependenciesMissing { .. }
+            | ShimmyError::InsufficientMemory { .. }
+            | ShimmyError::ServerBusy { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ShimmyError::UnsupportedOperation { .. } | ShimmyError::NotImplemented { .. } => {
+                StatusCode::NOT_IMPLEMENTED
+            }
+            #[cfg(feature = "vision")]
+            ShimmyError::VisionLicense(license_err) => license_err.to_status_code(),
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn is_vision_error(&self) -> bool {
+        matches!(
+            self,
+            ShimmyError::ImageFetchFailed { .. }
+                | ShimmyError::ImagePreprocessFailed { .. }
+                | ShimmyError::VisionModelUnavailable { .. }
+                | ShimmyError::ModelDownloadFailed { .. }
+                | ShimmyError::InferenceFailed { .. }
+                | ShimmyError::InferenceTimeout { .. }
+                | ShimmyError::UsageRecordingFailed { .. }
+        )
+    }
+}
+
+/// Single HTTP mapping layer: `{"error": {"code", "message"}}`.
+///
+/// Client errors (4xx) expose the full message so users can fix their request;
+/// server error details are hidden unless `SHIMMY_DEV_MODE` is set.
+impl IntoResponse for ShimmyError {
+    fn into_response(self) -> Response {
+        let (status, body) = self.response_body();
+        let mut response = (status, Json(body)).into_response();
+        let headers = response.headers_mut();
+        match self {
+            ShimmyError::Unauthorized => {
+                headers.insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            }
+            ShimmyError::RateLimited {
+                retry_after_secs, ..
+            }
+            | ShimmyError::ServerRateLimited {
+                retry_after_secs, ..
+            } => {
+                headers.insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+            }
+            ShimmyError::IdempotencyInProgress { .. } => {
+                headers.insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+            }
+            _ => {}
+        }
+        response
+    }
+}
+
+impl ShimmyError {
+    /// Status and JSON body of the HTTP error response, also sent as the
+    /// terminal `error` event when a failure happens after a stream started
+    pub fn response_body(&self) -> (StatusCode, serde_json::Value) {
+        #[cfg(feature = "vision")]
+        if let ShimmyError::VisionLicense(license_err) = self {
+            return (license_err.to_status_code(), license_err.to_json_error());
+        }
+
+        let status = self.status_code();
+        let full_message = self.to_string();
+        if status.is_server_error() {
+            tracing::error!(status = %status, code = self.code(), "{}", full_message);
+        }
+
+        let dev_mode = std::env::var("SHIMMY_DEV_MODE").is_ok()
+            || std::env::var("SHIMMY_VISION_DEV_MODE").is_ok();
+        let message = if status.is_client_error() || dev_mode {
+            full_message
+        } else if self.is_vision_error() {
+            "Vision processing error".to_string()
+        } else {
+            "Internal server error".to_string()
+        };
+
+        let mut body = serde_json::json!({
+            "error": {
+                "code": self.code(),
+                "message": message,
+            }
+        });
+        if let ShimmyError::InvalidParameter(invalid) = self {
+            body["error"]["param"] = invalid.param.into();
+        }
+
+        (status, body)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -475,6 +800,7 @@ mod tests {
         for error in errors {
             match error {
                 ShimmyError::ModelNotFound { .. } => {}
+                ShimmyError::AdapterNotFound { .. } => {}
                 ShimmyError::ModelLoadError { .. } => {}
                 ShimmyError::GenerationError { .. } => {}
                 ShimmyError::ConfigError { .. } => {}
@@ -487,6 +813,17 @@ mod tests {
                 ShimmyError::WorkflowVariableNotFound { .. } => {}
                 ShimmyError::WorkflowCircularDependency { .. } => {}
                 ShimmyError::UnsupportedOperation { .. } => {}
+                ShimmyError::ReadOnly { .. } => {}
+                ShimmyError::AccessDenied { .. } => {}
+                ShimmyError::ServerBusy { .. } => {}
+                ShimmyError::DeadlineExceeded { .. } => {}
+                ShimmyError::Unauthorized => {}
+                ShimmyError::AdminUnauthorized => {}
+                ShimmyError::AdminDisabled => {}
+                ShimmyError::RateLimited { .. } => {}
+                ShimmyError::ServerRateLimited { .. } => {}
+                ShimmyError::IdempotencyKeyReused { .. } => {}
+                ShimmyError::IdempotencyInProgress { .. } => {}
                 ShimmyError::ToolExecutionFailed { .. } => {}
                 ShimmyError::InvalidPath { .. } => {}
                 ShimmyError::FileNotFound { .. } => {}
@@ -503,7 +840,238 @@ mod tests {
                 ShimmyError::UnsupportedBackend { .. } => {}
                 ShimmyError::PythonDependenciesMissing { .. } => {}
                 ShimmyError::ModelVerificationFailed { .. } => {}
+                ShimmyError::Load(_) => {}
+                ShimmyError::InvalidRequest { .. } => {}
+                ShimmyError::InvalidParameter(_) => {}
+                ShimmyError::VectorStore(_) => {}
+                #[cfg(feature = "vision")]
+                ShimmyError::VisionLicense(_) => {}
+                ShimmyError::ImageFetchFailed { .. } => {}
+                ShimmyError::ImagePreprocessFailed { .. } => {}
+                ShimmyError::VisionModelUnavailable { .. } => {}
+                ShimmyError::ImageBlocked { .. } => {}
+                ShimmyError::ModelDownloadFailed { .. } => {}
+                ShimmyError::InferenceFailed { .. } => {}
+                ShimmyError::InferenceTimeout { .. } => {}
+                ShimmyError::UsageRecordingFailed { .. } => {}
+                ShimmyError::StateStoreFailed { .. } => {}
+                ShimmyError::InsufficientMemory { .. } => {}
+                ShimmyError::UnsupportedSchemaVersion { .. } => {}
             }
         }
     }
+
+    #[test]
+    fn test_anyhow_load_error_keeps_category() {
+        let err: anyhow::Error = crate::engine::LoadError::OutOfMemory {
+            details: "failed to allocate".to_string(),
+        }
+        .into();
+        let shimmy_err = ShimmyError::from(err);
+        assert_eq!(shimmy_err.code(), "OUT_OF_MEMORY");
+        assert_eq!(shimmy_err.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_http_mapping_status_and_code() {
+        let cases = vec![
+            (
+                ShimmyError::InvalidRequest {
+                    reason: "bad".to_string(),
+                },
+                StatusCode::BAD_REQUEST,
+                "INVALID_REQUEST",
+            ),
+            (
+                ShimmyError::InvalidParameter(crate::engine::InvalidParameter::new(
+                    "top_p",
+                    "must be greater than 0 and at most 1, got 0",
+                )),
+                StatusCode::BAD_REQUEST,
+                "INVALID_PARAMETER",
+            ),
+            (
+                ShimmyError::ImagePreprocessFailed {
+                    reason: "bad image".to_string(),
+                },
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "IMAGE_PREPROCESS_FAILED",
+            ),
+            (
+                ShimmyError::ImageBlocked {
+                    reason: "flagged as unsafe by moondream".to_string(),
+                },
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "IMAGE_BLOCKED",
+            ),
+            (
+                ShimmyError::ImageFetchFailed {
+                    reason: "timed out".to_string(),
+                    timed_out: true,
+                },
+                StatusCode::GATEWAY_TIMEOUT,
+                "IMAGE_FETCH_FAILED",
+            ),
+            (
+                ShimmyError::InferenceTimeout { timeout_ms: 10 },
+                StatusCode::GATEWAY_TIMEOUT,
+                "INFERENCE_TIMEOUT",
+            ),
+            (
+                ShimmyError::UnsupportedSchemaVersion {
+                    requested: "3".to_string(),
+                    supported: "1".to_string(),
+                },
+                StatusCode::NOT_ACCEPTABLE,
+                "UNSUPPORTED_SCHEMA_VERSION",
+            ),
+            (
+                ShimmyError::ModelNotFound {
+                    name: "m".to_string(),
+                },
+                StatusCode::NOT_FOUND,
+                "MODEL_NOT_FOUND",
+            ),
+            (
+                ShimmyError::ReadOnly {
+                    operation: "POST /api/vectors".to_string(),
+                },
+                StatusCode::FORBIDDEN,
+                "READ_ONLY",
+            ),
+            (
+                ShimmyError::AccessDenied {
+                    addr: "192.168.1.20".to_string(),
+                },
+                StatusCode::FORBIDDEN,
+                "ACCESS_DENIED",
+            ),
+            (
+                ShimmyError::ServerBusy {
+                    route: "chat".to_string(),
+                },
+                StatusCode::SERVICE_UNAVAILABLE,
+                "SERVER_BUSY",
+            ),
+            (
+                ShimmyError::DeadlineExceeded {
+                    stage: "queueing".to_string(),
+                },
+                StatusCode::GATEWAY_TIMEOUT,
+                "DEADLINE_EXCEEDED",
+            ),
+            (
+                ShimmyError::Unauthorized,
+                StatusCode::UNAUTHORIZED,
+                "UNAUTHORIZED",
+            ),
+            (
+                ShimmyError::AdminUnauthorized,
+                StatusCode::UNAUTHORIZED,
+                "UNAUTHORIZED",
+            ),
+            (
+                ShimmyError::AdminDisabled,
+                StatusCode::FORBIDDEN,
+                "ADMIN_DISABLED",
+            ),
+            (
+                ShimmyError::RateLimited {
+                    key: "alice".to_string(),
+                    limit: "requests/minute".to_string(),
+                    retry_after_secs: 30,
+                },
+                StatusCode::TOO_MANY_REQUESTS,
+                "RATE_LIMITED",
+            ),
+            (
+                ShimmyError::ServerRateLimited {
+                    scope: "client 10.0.0.2".to_string(),
+                    limit: "tokens/minute".to_string(),
+                    retry_after_secs: 12,
+                },
+                StatusCode::TOO_MANY_REQUESTS,
+                "RATE_LIMITED",
+            ),
+            (
+                ShimmyError::AdapterNotFound {
+                    model: "llama3".to_string(),
+                    adapter: "pirate".to_string(),
+                },
+                StatusCode::NOT_FOUND,
+                "ADAPTER_NOT_FOUND",
+            ),
+            (
+                ShimmyError::IdempotencyKeyReused {
+                    key: "upload-42".to_string(),
+                },
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "IDEMPOTENCY_KEY_REUSED",
+            ),
+            (
+                ShimmyError::IdempotencyInProgress {
+                    key: "upload-42".to_string(),
+                },
+                StatusCode::CONFLICT,
+                "IDEMPOTENCY_IN_PROGRESS",
+            ),
+            (
+                ShimmyError::InsufficientMemory {
+                    required_mb: 4096,
+                    available_mb: 1024,
+                },
+                StatusCode::SERVICE_UNAVAILABLE,
+                "INSUFFICIENT_MEMORY",
+            ),
+        ];
+
+        for (error, status, code) in cases {
+            assert_eq!(error.status_code(), status, "{}", error);
+            assert_eq!(error.code(), code);
+            assert_eq!(error.into_response().status(), status);
+        }
+    }
+
+    #[test]
+    fn test_rate_limited_response_has_retry_after() {
+        let response = ShimmyError::RateLimited {
+            key: "alice".to_string(),
+            limit: "tokens/day".to_string(),
+            retry_after_secs: 3600,
+        }
+        .into_response();
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3600");
+
+        let response = ShimmyError::ServerRateLimited {
+            scope: "all clients".to_string(),
+            limit: "requests/minute".to_string(),
+            retry_after_secs: 2,
+        }
+        .into_response();
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+
+        let response = ShimmyError::Unauthorized.into_response();
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_parameter_body_names_param() {
+        let err = ShimmyError::from(crate::engine::InvalidParameter::new(
+            "temperature",
+            "must be between 0 and 2, got 5",
+        ));
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"]["code"], "INVALID_PARAMETER");
+        assert_eq!(body["error"]["param"], "temperature");
+        assert_eq!(
+            body["error"]["message"],
+            "Invalid `temperature`: must be between 0 and 2, got 5"
+        );
+    }
 }
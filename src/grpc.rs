@@ -0,0 +1,445 @@
+//! gRPC inference service (`grpc` feature).
+//!
+//! Internal services that prefer protobuf over JSON and SSE can reach the
+//! same models over gRPC. `serve --grpc-bind 127.0.0.1:50051` (or
+//! `SHIMMY_GRPC_BIND`) serves `shimmy.v1.Inference`, defined in
+//! `proto/shimmy.proto`, on its own port next to the HTTP server: streaming
+//! generation, embeddings, and listing, loading and unloading models.
+//!
+//! The HTTP server's guards apply to gRPC calls as well: `SHIMMY_IP_ACL`
+//! (matched against the method path, e.g. `/shimmy.v1.Inference/Generate`),
+//! API keys sent as `authorization: Bearer <key>` metadata,
+//! `SHIMMY_RATE_LIMIT_*`, the chat concurrency limit for `Generate`, and
+//! `--read-only` for loading and unloading. A failed call's status message
+//! starts with shimmy's error code, e.g. `MODEL_NOT_FOUND: ...`.
+//!
+//! Builds without the feature warn at startup when an address is set and
+//! carry on with HTTP only.
+
+use std::net::SocketAddr;
+
+pub const GRPC_BIND_ENV: &str = "SHIMMY_GRPC_BIND";
+
+/// The address to serve gRPC on, if any; the flag wins over the environment
+pub fn bind_address(flag: Option<&str>) -> anyhow::Result<Option<SocketAddr>> {
+    let value = flag
+        .map(str::to_string)
+        .or_else(|| std::env::var(GRPC_BIND_ENV).ok())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+    match value {
+        Some(v) => v
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("invalid gRPC address {:?}: {}", v, e)),
+        None => Ok(None),
+    }
+}
+
+/// Serve gRPC on `addr` in the background, or say why it isn't
+pub fn spawn(addr: Option<SocketAddr>, state: &std::sync::Arc<crate::AppState>) {
+    let Some(addr) = addr else {
+        return;
+    };
+    #[cfg(feature = "grpc")]
+    {
+        println!("📡 Serving gRPC on {}", addr);
+        let state = std::sync::Arc::clone(state);
+        tokio::spawn(async move {
+            if let Err(e) = serve(addr, state).await {
+                tracing::error!("gRPC server on {} stopped: {:#}", addr, e);
+            }
+        });
+    }
+    #[cfg(not(feature = "grpc"))]
+    {
+        let _ = state;
+        eprintln!(
+            "⚠️  gRPC on {} needs a build with the `grpc` feature; serving HTTP only",
+            addr
+        );
+    }
+}
+
+#[cfg(feature = "grpc")]
+pub mod proto {
+    tonic::include_proto!("shimmy.v1");
+}
+
+#[cfg(feature = "grpc")]
+pub use service::serve;
+
+#[cfg(feature = "grpc")]
+mod service {
+    use super::proto::{self, inference_server::Inference, inference_server::InferenceServer};
+    use crate::engine::{GenOptions, InvalidParameter, ModelSpec};
+    use crate::error::ShimmyError;
+    use crate::model_cache::KeepAlive;
+    use crate::AppState;
+    use std::net::SocketAddr;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tonic::{Code, Request, Response, Status};
+
+    type GenerateStream =
+        Pin<Box<dyn tokio_stream::Stream<Item = Result<proto::GenerateResponse, Status>> + Send>>;
+
+    /// Serve `shimmy.v1.Inference` on `addr` until the process exits
+    pub async fn serve(addr: SocketAddr, state: Arc<AppState>) -> anyhow::Result<()> {
+        tonic::transport::Server::builder()
+            .add_service(InferenceServer::new(InferenceService { state }))
+            .serve(addr)
+            .await?;
+        Ok(())
+    }
+
+    /// A shimmy error as a gRPC status, its code leading the message
+    pub(super) fn status(e: ShimmyError) -> Status {
+        use axum::http::StatusCode;
+        let code = match e.status_code() {
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+            StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+            StatusCode::FORBIDDEN => Code::PermissionDenied,
+            StatusCode::NOT_FOUND => Code::NotFound,
+            StatusCode::CONFLICT => Code::Aborted,
+            StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+            StatusCode::NOT_IMPLEMENTED => Code::Unimplemented,
+            StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+            StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+            _ => Code::Internal,
+        };
+        Status::new(code, format!("{}: {}", e.code(), e))
+    }
+
+    fn keep_alive(value: Option<&str>) -> Result<Option<KeepAlive>, Status> {
+        value
+            .map(|v| {
+                KeepAlive::parse(v, Duration::from_secs(1))
+                    .map_err(|e| status(InvalidParameter::new("keep_alive", e.to_string()).into()))
+            })
+            .transpose()
+    }
+
+    /// What an admitted call is charged to
+    struct Caller {
+        api_key: Option<Arc<crate::api_keys::ApiKey>>,
+        charge: Option<Arc<crate::rate_limit::Charge>>,
+    }
+
+    struct InferenceService {
+        state: Arc<AppState>,
+    }
+
+    impl InferenceService {
+        /// The HTTP server's access checks, in the same order
+        fn admit<T>(&self, req: &Request<T>, method: &str, admin: bool) -> Result<Caller, Status> {
+            let state = &self.state;
+            let peer = req.remote_addr().map(|addr| addr.ip());
+            if !state.ip_acl.is_empty() {
+                let path = format!("/shimmy.v1.Inference/{}", method);
+                if !peer.is_some_and(|ip| state.ip_acl.allows(ip, &path, admin)) {
+                    return Err(status(ShimmyError::AccessDenied {
+                        addr: peer
+                            .map_or_else(|| "an unknown address".to_string(), |ip| ip.to_string()),
+                    }));
+                }
+            }
+            let api_key = if state.api_keys.is_empty() {
+                None
+            } else {
+                let headers = req.metadata().clone().into_headers();
+                let key = state
+                    .api_keys
+                    .authenticate(&headers)
+                    .ok_or_else(|| status(ShimmyError::Unauthorized))?;
+                key.admit(Instant::now(), chrono::Utc::now())
+                    .map_err(status)?;
+                Some(key)
+            };
+            let charge = if state.rate_limits.is_empty() {
+                None
+            } else {
+                let charge = state
+                    .rate_limits
+                    .admit(peer, Instant::now())
+                    .map_err(status)?;
+                Some(Arc::new(charge))
+            };
+            if admin && state.read_only {
+                return Err(status(ShimmyError::ReadOnly {
+                    operation: format!("gRPC {}", method),
+                }));
+            }
+            Ok(Caller { api_key, charge })
+        }
+
+        fn spec(&self, model: &str) -> Result<ModelSpec, Status> {
+            if let Some(rejection) = self.state.registry.rejection(model) {
+                return Err(status(ShimmyError::Load(rejection)));
+            }
+            self.state.registry.to_spec(model).ok_or_else(|| {
+                status(ShimmyError::ModelNotFound {
+                    name: model.to_string(),
+                })
+            })
+        }
+    }
+
+    #[tonic::async_trait]
+    impl Inference for InferenceService {
+        type GenerateStream = GenerateStream;
+
+        async fn generate(
+            &self,
+            request: Request<proto::GenerateRequest>,
+        ) -> Result<Response<GenerateStream>, Status> {
+            let caller = self.admit(&request, "Generate", false)?;
+            let req = request.into_inner();
+            let state = &self.state;
+            let spec = self.spec(&req.model)?;
+            if state.registry.is_embedding_model(&req.model) {
+                return Err(status(ShimmyError::InvalidRequest {
+                    reason: format!("Model '{}' is an embedding model; use Embed", req.model),
+                }));
+            }
+            let keep_alive = keep_alive(req.keep_alive.as_deref())?;
+
+            let fam = crate::openai_compat::template_family(spec.template.as_deref(), &req.model);
+            let mut opts = GenOptions::default();
+            if let Some(n) = req.max_tokens {
+                opts.max_tokens = n as usize;
+            }
+            if let Some(t) = req.temperature {
+                opts.temperature = t;
+            }
+            if let Some(p) = req.top_p {
+                opts.top_p = p;
+            }
+            if let Some(k) = req.top_k {
+                opts.top_k = k.min(i32::MAX as u32) as i32;
+            }
+            opts.seed = req.seed;
+            if !req.raw {
+                opts.stop_tokens = fam.stop_tokens();
+            }
+            opts.stop_tokens.extend(req.stop);
+            opts.api_key = caller.api_key;
+            opts.rate_limit = caller.charge;
+            opts.adapter =
+                crate::api::adapter_path(&state.registry, &req.model, req.adapter.as_deref())
+                    .map_err(status)?;
+            opts.validate().map_err(|e| status(e.into()))?;
+
+            let slot = state.route_limits.chat.enter().await.map_err(status)?;
+            let loaded = state
+                .load_model_keep_alive(&spec, keep_alive)
+                .await
+                .map_err(|e| status(ShimmyError::from_load(std::path::Path::new(&req.model), e)))?;
+            crate::api::check_adapter_support(&*loaded, &opts, &req.model).map_err(status)?;
+            let prompt = if req.raw {
+                req.prompt
+            } else {
+                fam.render(req.system.as_deref(), &[], Some(&req.prompt))
+            };
+
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            // A failed send means the client went away; stop generating for it
+            let cancel = Arc::new(AtomicBool::new(false));
+            opts.cancel = Some(cancel.clone());
+            tokio::spawn(async move {
+                let _slot = slot;
+                let tx_tokens = tx.clone();
+                let on_token: Box<dyn FnMut(String) + Send> = Box::new(move |text| {
+                    let token = proto::GenerateResponse {
+                        text,
+                        ..Default::default()
+                    };
+                    if tx_tokens.send(Ok(token)).is_err() {
+                        cancel.store(true, Ordering::Relaxed);
+                    }
+                });
+                let last = match loaded
+                    .generate_with_finish(&prompt, opts, Some(on_token))
+                    .await
+                {
+                    Ok(generation) => {
+                        let count = |text: &str| {
+                            loaded
+                                .count_tokens(text)
+                                .unwrap_or_else(|| crate::truncation::estimate_tokens(text))
+                                as u32
+                        };
+                        Ok(proto::GenerateResponse {
+                            text: String::new(),
+                            done: true,
+                            finish_reason: generation.finish_reason.as_str().to_string(),
+                            prompt_tokens: count(&prompt),
+                            completion_tokens: count(&generation.text),
+                        })
+                    }
+                    Err(e) => Err(status(ShimmyError::GenerationError {
+                        reason: e.to_string(),
+                    })),
+                };
+                let _ = tx.send(last);
+            });
+            Ok(Response::new(Box::pin(UnboundedReceiverStream::new(rx))))
+        }
+
+        async fn embed(
+            &self,
+            request: Request<proto::EmbedRequest>,
+        ) -> Result<Response<proto::EmbedResponse>, Status> {
+            self.admit(&request, "Embed", false)?;
+            let req = request.into_inner();
+            if req.input.is_empty() {
+                return Err(status(
+                    InvalidParameter::new("input", "must not be empty").into(),
+                ));
+            }
+            if req.input.len() > crate::embeddings::MAX_INPUTS {
+                return Err(status(
+                    InvalidParameter::new(
+                        "input",
+                        format!(
+                            "at most {} strings per request",
+                            crate::embeddings::MAX_INPUTS
+                        ),
+                    )
+                    .into(),
+                ));
+            }
+            let spec = self.spec(&req.model)?;
+            let loaded =
+                self.state.load_model(&spec).await.map_err(|e| {
+                    status(ShimmyError::from_load(std::path::Path::new(&req.model), e))
+                })?;
+            let prompt_tokens: usize = req
+                .input
+                .iter()
+                .map(|t| {
+                    loaded
+                        .count_tokens(t)
+                        .unwrap_or_else(|| crate::truncation::estimate_tokens(t))
+                })
+                .sum();
+            let vectors = loaded.embed(&req.input).await.map_err(|e| {
+                status(ShimmyError::GenerationError {
+                    reason: e.to_string(),
+                })
+            })?;
+            let embeddings = vectors
+                .into_iter()
+                .map(|mut values| {
+                    crate::embeddings::normalize(&mut values);
+                    proto::Embedding { values }
+                })
+                .collect();
+            Ok(Response::new(proto::EmbedResponse {
+                embeddings,
+                prompt_tokens: prompt_tokens as u32,
+            }))
+        }
+
+        async fn list_models(
+            &self,
+            request: Request<proto::ListModelsRequest>,
+        ) -> Result<Response<proto::ListModelsResponse>, Status> {
+            self.admit(&request, "ListModels", false)?;
+            let state = &self.state;
+            let models = state
+                .registry
+                .list_all_available()
+                .into_iter()
+                .map(|name| proto::ModelInfo {
+                    loaded: state.model_cache.contains(&name),
+                    estimated_runtime_gb: state
+                        .registry
+                        .memory_estimate(&name)
+                        .map(|e| e.estimated_runtime_gb),
+                    name,
+                })
+                .collect();
+            Ok(Response::new(proto::ListModelsResponse { models }))
+        }
+
+        async fn load_model(
+            &self,
+            request: Request<proto::LoadModelRequest>,
+        ) -> Result<Response<proto::ModelStatus>, Status> {
+            self.admit(&request, "LoadModel", true)?;
+            let req = request.into_inner();
+            let spec = self.spec(&req.model)?;
+            let keep_alive = keep_alive(req.keep_alive.as_deref())?;
+            let status_text = if keep_alive.is_some_and(KeepAlive::unloads_immediately) {
+                self.state.model_cache.unload(&req.model);
+                "unloaded"
+            } else {
+                self.state
+                    .load_model_keep_alive(&spec, keep_alive)
+                    .await
+                    .map_err(|e| {
+                        status(ShimmyError::from_load(std::path::Path::new(&req.model), e))
+                    })?;
+                "loaded"
+            };
+            Ok(Response::new(proto::ModelStatus {
+                model: req.model,
+                status: status_text.to_string(),
+            }))
+        }
+
+        async fn unload_model(
+            &self,
+            request: Request<proto::UnloadModelRequest>,
+        ) -> Result<Response<proto::ModelStatus>, Status> {
+            self.admit(&request, "UnloadModel", true)?;
+            let req = request.into_inner();
+            let status_text = if self.state.model_cache.unload(&req.model) {
+                "unloaded"
+            } else {
+                "not_loaded"
+            };
+            Ok(Response::new(proto::ModelStatus {
+                model: req.model,
+                status: status_text.to_string(),
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_address_flag_and_validation() {
+        assert_eq!(
+            bind_address(Some("127.0.0.1:50051")).unwrap(),
+            Some("127.0.0.1:50051".parse().unwrap())
+        );
+        // An empty flag turns gRPC off even when the environment sets it
+        assert_eq!(bind_address(Some(" ")).unwrap(), None);
+        assert!(bind_address(Some("localhost")).is_err());
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_errors_map_to_grpc_codes() {
+        use crate::error::ShimmyError;
+        use tonic::Code;
+
+        let not_found = service::status(ShimmyError::ModelNotFound {
+            name: "phi3".to_string(),
+        });
+        assert_eq!(not_found.code(), Code::NotFound);
+        assert!(not_found.message().starts_with("MODEL_NOT_FOUND: "));
+        assert_eq!(
+            service::status(ShimmyError::Unauthorized).code(),
+            Code::Unauthenticated
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Per-route IP allow/deny lists.
+//!
+//! Plenty of setups bind to `0.0.0.0` on a home network with nothing in front,
+//! which hands every route to every device on the LAN. `SHIMMY_IP_ACL` (or
+//! `ip_acl` in `shimmy.toml`) restricts routes by client address:
+//!
+//! ```text
+//! SHIMMY_IP_ACL="admin allow loopback; * allow lan; /v1/* deny 192.168.1.50"
+//! ```
+//!
+//! Rules are separated by `;` and read `<routes> <allow|deny> <addresses>`:
+//!
+//! - routes: `*` (everything), `admin` (the routes `serve --read-only`
+//!   refuses), a path prefix ending in `*` such as `/v1/*`, or an exact path
+//! - addresses: comma-separated IPs or CIDR ranges, plus `any`, `loopback`
+//!   and `lan` (private, link-local and loopback addresses)
+//!
+//! Every rule whose routes match a request applies to it: the client address
+//! must be listed by each matching `allow` rule and by no matching `deny`
+//! rule, so the example keeps admin routes to loopback while the rest of the
+//! API is open to the LAN. Routes no rule mentions stay open. The address
+//! checked is the TCP peer, so behind a reverse proxy every request comes from
+//! the proxy.
+
+use anyhow::{bail, Result};
+use std::net::IpAddr;
+
+pub const IP_ACL_ENV: &str = "SHIMMY_IP_ACL";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Routes {
+    All,
+    Admin,
+    Prefix(String),
+    Exact(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Addresses {
+    Any,
+    Loopback,
+    Lan,
+    Net { addr: IpAddr, prefix: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    routes: Routes,
+    action: Action,
+    addresses: Vec<Addresses>,
+}
+
+/// Parsed `SHIMMY_IP_ACL`; empty means every route is open to everyone
+#[derive(Debug, Clone, Default)]
+pub struct IpAcl {
+    rules: Vec<Rule>,
+}
+
+impl Routes {
+    fn parse(text: &str) -> Self {
+        match text {
+            "*" => Routes::All,
+            "admin" => Routes::Admin,
+            _ => match text.strip_suffix('*') {
+                Some(prefix) => Routes::Prefix(prefix.to_string()),
+                None => Routes::Exact(text.to_string()),
+            },
+        }
+    }
+
+    fn matches(&self, path: &str, admin: bool) -> bool {
+        match self {
+            Routes::All => true,
+            Routes::Admin => admin,
+            Routes::Prefix(prefix) => path.starts_with(prefix.as_str()),
+            Routes::Exact(exact) => path == exact,
+        }
+    }
+}
+
+impl Addresses {
+    fn parse(text: &str) -> Result<Self> {
+        let net = match text {
+            "any" => return Ok(Addresses::Any),
+            "loopback" => return Ok(Addresses::Loopback),
+            "lan" => return Ok(Addresses::Lan),
+            _ => text,
+        };
+        let (addr, prefix) = match net.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (net, None),
+        };
+        let Ok(addr) = addr.parse::<IpAddr>() else {
+            bail!(
+                "`{}` is not an IP address, CIDR range, any, loopback or lan",
+                text
+            );
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            None => max,
+            Some(p) => match p.parse::<u8>() {
+                Ok(p) if p <= max => p,
+                _ => bail!("`{}` has an invalid prefix length", text),
+            },
+        };
+        Ok(Addresses::Net {
+            addr: addr.to_canonical(),
+            prefix,
+        })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match self {
+            Addresses::Any => true,
+            Addresses::Loopback => ip.is_loopback(),
+            Addresses::Lan => is_lan(ip),
+            Addresses::Net { addr, prefix } => match (addr, ip) {
+                (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                    same_prefix(net.to_bits().into(), ip.to_bits().into(), 32, *prefix)
+                }
+                (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                    same_prefix(net.to_bits(), ip.to_bits(), 128, *prefix)
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+fn same_prefix(a: u128, b: u128, bits: u8, prefix: u8) -> bool {
+    let shift = bits - prefix;
+    shift >= bits || (a >> shift) == (b >> shift)
+}
+
+/// Loopback, private (RFC 1918 / unique local) and link-local addresses
+fn is_lan(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_loopback() || v4.is_private() || v4.is_link_local(),
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            v6.is_loopback() || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+impl IpAcl {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        for rule in spec.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+            let mut parts = rule.split_whitespace();
+            let (Some(routes), Some(action), Some(addresses), None) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                bail!(
+                    "invalid {} rule `{}`: expected `<routes> <allow|deny> <addresses>`",
+                    IP_ACL_ENV,
+                    rule
+                );
+            };
+            let action = match action {
+                "allow" => Action::Allow,
+                "deny" => Action::Deny,
+                other => bail!(
+                    "invalid {} rule `{}`: `{}` is not allow or deny",
+                    IP_ACL_ENV,
+                    rule,
+                    other
+                ),
+            };
+            let addresses = addresses
+                .split(',')
+                .filter(|a| !a.is_empty())
+                .map(Addresses::parse)
+                .collect::<Result<Vec<_>>>()
+                .map_err(|e| anyhow::anyhow!("invalid {} rule `{}`: {}", IP_ACL_ENV, rule, e))?;
+            if addresses.is_empty() {
+                bail!(
+                    "invalid {} rule `{}`: no addresses listed",
+                    IP_ACL_ENV,
+                    rule
+                );
+            }
+            rules.push(Rule {
+                routes: Routes::parse(routes),
+                action,
+                addresses,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// Rules from `SHIMMY_IP_ACL`, or none when it is unset
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(IP_ACL_ENV) {
+            Ok(spec) => Self::parse(&spec),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Whether `ip` may call `path`; `admin` marks a state-changing route
+    pub fn allows(&self, ip: IpAddr, path: &str, admin: bool) -> bool {
+        let ip = ip.to_canonical();
+        self.rules
+            .iter()
+            .filter(|rule| rule.routes.matches(path, admin))
+            .all(|rule| {
+                let listed = rule.addresses.iter().any(|a| a.contains(ip));
+                listed == (rule.action == Action::Allow)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_admin_loopback_inference_lan() {
+        let acl = IpAcl::parse("admin allow loopback; * allow lan").unwrap();
+        assert_eq!(acl.len(), 2);
+
+        assert!(acl.allows(ip("127.0.0.1"), "/api/models/m/load", true));
+        assert!(!acl.allows(ip("192.168.1.20"), "/api/models/m/load", true));
+        assert!(acl.allows(ip("192.168.1.20"), "/v1/chat/completions", false));
+        assert!(acl.allows(ip("::ffff:10.0.0.3"), "/api/generate", false));
+        assert!(acl.allows(ip("fd00::1"), "/api/generate", false));
+        assert!(!acl.allows(ip("203.0.113.9"), "/api/generate", false));
+    }
+
+    #[test]
+    fn test_deny_wins_and_unlisted_routes_stay_open() {
+        let acl =
+            IpAcl::parse("/v1/* allow 10.0.0.0/8; /v1/* deny 10.0.0.5; /diag deny any").unwrap();
+
+        assert!(acl.allows(ip("10.1.2.3"), "/v1/models", false));
+        assert!(!acl.allows(ip("10.0.0.5"), "/v1/models", false));
+        assert!(!acl.allows(ip("172.16.0.1"), "/v1/models", false));
+        assert!(!acl.allows(ip("127.0.0.1"), "/diag", false));
+        assert!(acl.allows(ip("203.0.113.9"), "/api/generate", false));
+        assert!(IpAcl::default().allows(ip("203.0.113.9"), "/diag", true));
+    }
+
+    #[test]
+    fn test_cidr_matching() {
+        let net = Addresses::parse("192.168.0.0/16").unwrap();
+        assert!(net.contains(ip("192.168.44.1")));
+        assert!(!net.contains(ip("192.169.0.1")));
+        assert!(!net.contains(ip("::1")));
+        assert!(Addresses::parse("0.0.0.0/0")
+            .unwrap()
+            .contains(ip("8.8.8.8")));
+        let v6 = Addresses::parse("2001:db8::/32").unwrap();
+        assert!(v6.contains(ip("2001:db8:1::7")));
+        assert!(!v6.contains(ip("2001:db9::7")));
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        assert!(IpAcl::parse("").unwrap().is_empty());
+        for bad in [
+            "admin loopback",
+            "admin permit loopback",
+            "* allow 10.0.0.0/33",
+            "* allow nowhere",
+            "* allow lan extra",
+            "* allow ,",
+        ] {
+            let err = IpAcl::parse(bad).unwrap_err().to_string();
+            assert!(err.contains(IP_ACL_ENV), "{}", err);
+        }
+    }
+}
@@ -1,34 +1,94 @@
 // Suppress function pointer comparison warnings from auto-generated bindings
 #![allow(unpredictable_function_pointer_comparisons)]
 
+pub mod admin_config;
 pub mod anthropic_compat;
 pub mod api;
 pub mod api_errors;
+pub mod api_keys;
 pub mod auto_discovery;
+pub mod branding;
 pub mod cache;
+pub mod capabilities;
+pub mod chat;
 pub mod cli;
+pub mod code_completion;
+pub mod connect;
+pub mod deadline;
 pub mod discovery;
+pub mod embeddings;
 pub mod engine;
 pub mod error;
+pub mod grpc;
+pub mod ip_acl;
+#[cfg(feature = "vision")]
+pub mod license_store;
+pub mod load_progress;
+pub mod load_queue;
+pub mod log_files;
 pub mod main_integration;
 pub mod metrics;
+pub mod migrations;
+pub mod model_cache;
 pub mod model_manager;
+pub mod model_names;
 pub mod model_registry;
+pub mod model_schedule;
+pub mod model_store;
 pub mod observability;
+pub mod ollama_compat;
+pub mod oneshot;
 pub mod openai_compat;
+pub mod otel;
 pub mod port_manager;
+pub mod power;
+pub mod prometheus;
+pub mod rag;
+pub mod rate_limit;
+pub mod recovery;
+pub mod report;
+pub mod route_limits;
 pub mod rustchain_compat;
 pub mod safetensors_adapter;
+pub mod sandbox;
 pub mod server;
+pub mod setup;
+pub mod state_store;
+pub mod telemetry;
 pub mod templates;
+pub mod tls;
+pub mod tool_calling;
 pub mod tools;
+pub mod truncation;
+pub mod upgrade;
+#[cfg(feature = "vision")]
+pub mod usage_export;
+pub mod vector_store;
 #[cfg(feature = "vision")]
 pub mod vision;
 #[cfg(feature = "vision")]
+pub mod vision_batch;
+#[cfg(feature = "vision")]
 pub mod vision_license;
+#[cfg(feature = "vision")]
+pub mod vision_modes;
+#[cfg(feature = "vision")]
+pub mod vision_quality;
+#[cfg(feature = "vision")]
+pub mod vision_safety;
+#[cfg(feature = "vision-tesseract")]
+pub mod vision_tesseract;
+#[cfg(feature = "vision-video")]
+pub mod vision_video;
+#[cfg(feature = "vision")]
+pub mod vision_webhook;
 pub mod util {
+    pub mod cpu;
     pub mod diag;
+    pub mod http;
     pub mod memory;
+    pub mod paths;
+    pub mod zip;
 }
 pub mod invariant_ppt;
 pub mod workflow;
@@ -46,6 +106,19 @@ pub struct AppState {
     pub registry: model_registry::Registry,
     pub observability: observability::ObservabilityManager,
     pub response_cache: cache::ResponseCache,
+    pub load_queue: load_queue::LoadQueue,
+    pub route_limits: route_limits::RouteLimits,
+    pub vector_store: vector_store::VectorStore,
+    /// Set by `serve --read-only`: mutating routes answer 403
+    pub read_only: bool,
+    /// Per-route client address rules from `SHIMMY_IP_ACL`
+    pub ip_acl: ip_acl::IpAcl,
+    /// Keys and their limits from `SHIMMY_API_KEYS_FILE`; empty leaves the API open
+    pub api_keys: api_keys::ApiKeys,
+    /// Server-wide and per-client limits from `SHIMMY_RATE_LIMIT_*`
+    pub rate_limits: rate_limit::RateLimits,
+    /// Models kept loaded between requests, per `--model-ttl` and `keep_alive`
+    pub model_cache: model_cache::ModelCache,
     #[cfg(feature = "vision")]
     pub vision_license_manager: Option<crate::vision_license::VisionLicenseManager>,
 }
@@ -60,8 +133,41 @@ impl AppState {
             registry,
             observability: observability::ObservabilityManager::new(),
             response_cache: cache::ResponseCache::new(),
+            load_queue: load_queue::LoadQueue::from_env(),
+            route_limits: route_limits::RouteLimits::from_env(),
+            vector_store: vector_store::VectorStore::new(util::paths::data_dir().join("vectors")),
+            read_only: false,
+            ip_acl: ip_acl::IpAcl::default(),
+            api_keys: api_keys::ApiKeys::default(),
+            rate_limits: rate_limit::RateLimits::from_env(),
+            model_cache: model_cache::ModelCache::default(),
             #[cfg(feature = "vision")]
             vision_license_manager: Some(crate::vision_license::VisionLicenseManager::new()),
         }
     }
+
+    /// Load a model through the shared load queue, waiting for a slot if needed,
+    /// unless an earlier request left it loaded
+    pub async fn load_model(
+        &self,
+        spec: &engine::ModelSpec,
+    ) -> anyhow::Result<Box<dyn engine::LoadedModel>> {
+        self.load_model_keep_alive(spec, None).await
+    }
+
+    /// [`load_model`](Self::load_model) with the request's `keep_alive`
+    pub async fn load_model_keep_alive(
+        &self,
+        spec: &engine::ModelSpec,
+        keep_alive: Option<model_cache::KeepAlive>,
+    ) -> anyhow::Result<Box<dyn engine::LoadedModel>> {
+        self.model_cache
+            .get_or_load(
+                &spec.name,
+                model_cache::estimate_bytes(spec),
+                keep_alive,
+                || self.load_queue.load(self.engine.as_ref(), spec),
+            )
+            .await
+    }
 }
@@ -0,0 +1,274 @@
+//! Local storage for the Shimmy Vision license key.
+//!
+//! `shimmy license set <key>` stores the key in the OS keychain when one is
+//! reachable (macOS `security`, Linux Secret Service via `secret-tool`) and
+//! falls back to a user-only file in the config directory otherwise. The
+//! vision routes read it automatically when a request omits `license`; the
+//! server reads it once at startup, so a key stored later takes effect on
+//! the next restart.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+const KEYCHAIN_SERVICE: &str = "shimmy-vision";
+const KEYCHAIN_ACCOUNT: &str = "license";
+
+/// Where a stored license key lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LicenseStoreBackend {
+    Keychain,
+    File,
+}
+
+impl std::fmt::Display for LicenseStoreBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LicenseStoreBackend::Keychain => write!(f, "OS keychain"),
+            LicenseStoreBackend::File => write!(f, "{}", license_file_path().display()),
+        }
+    }
+}
+
+/// Path of the file fallback
+pub fn license_file_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(crate::branding::APP_NAME)
+        .join("license.key")
+}
+
+/// Store a license key, preferring the OS keychain
+pub fn store(key: &str) -> anyhow::Result<LicenseStoreBackend> {
+    let key = key.trim();
+    if key.is_empty() {
+        anyhow::bail!("license key is empty");
+    }
+
+    if keychain_store(key) {
+        // Remove any stale plaintext copy so the keychain is the single source
+        let _ = std::fs::remove_file(license_file_path());
+        return Ok(LicenseStoreBackend::Keychain);
+    }
+
+    file_store(key)?;
+    Ok(LicenseStoreBackend::File)
+}
+
+/// Load the stored license key, if any
+pub fn load() -> Option<String> {
+    keychain_load()
+        .or_else(|| std::fs::read_to_string(license_file_path()).ok())
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+}
+
+/// The stored license key, read once per process. Reading the keychain
+/// spawns a subprocess, so request handlers use this rather than [`load`].
+pub fn cached() -> Option<String> {
+    static KEY: OnceLock<Option<String>> = OnceLock::new();
+    KEY.get_or_init(load).clone()
+}
+
+/// Remove the stored license key from every backend
+pub fn clear() -> anyhow::Result<()> {
+    keychain_clear();
+    match std::fs::remove_file(license_file_path()) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Mask a key for display (keeps the last 4 characters)
+pub fn mask(key: &str) -> String {
+    let tail: String = key
+        .chars()
+        .rev()
+        .take(4)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    format!("****{}", tail)
+}
+
+fn file_store(key: &str) -> anyhow::Result<()> {
+    let path = license_file_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&path)?;
+    file.write_all(key.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_store(key: &str) -> bool {
+    // With `-w` last, security prompts for the password and reads it from
+    // stdin, so it never appears in argv
+    let Ok(mut child) = Command::new("security")
+        .args([
+            "add-generic-password",
+            "-U",
+            "-s",
+            KEYCHAIN_SERVICE,
+            "-a",
+            KEYCHAIN_ACCOUNT,
+            "-w",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The prompt asks for the password twice
+        if writeln!(stdin, "{key}\n{key}").is_err() {
+            return false;
+        }
+    }
+    child.wait().map(|s| s.success()).unwrap_or(false)
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_load() -> Option<String> {
+    Command::new("security")
+        .args([
+            "find-generic-password",
+            "-s",
+            KEYCHAIN_SERVICE,
+            "-a",
+            KEYCHAIN_ACCOUNT,
+            "-w",
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_clear() {
+    let _ = Command::new("security")
+        .args([
+            "delete-generic-password",
+            "-s",
+            KEYCHAIN_SERVICE,
+            "-a",
+            KEYCHAIN_ACCOUNT,
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(target_os = "linux")]
+fn keychain_store(key: &str) -> bool {
+    // secret-tool reads the secret from stdin so it never appears in argv
+    let Ok(mut child) = Command::new("secret-tool")
+        .args([
+            "store",
+            "--label=Shimmy Vision license",
+            "service",
+            KEYCHAIN_SERVICE,
+            "account",
+            KEYCHAIN_ACCOUNT,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        if stdin.write_all(key.as_bytes()).is_err() {
+            return false;
+        }
+    }
+    child.wait().map(|s| s.success()).unwrap_or(false)
+}
+
+#[cfg(target_os = "linux")]
+fn keychain_load() -> Option<String> {
+    Command::new("secret-tool")
+        .args([
+            "lookup",
+            "service",
+            KEYCHAIN_SERVICE,
+            "account",
+            KEYCHAIN_ACCOUNT,
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn keychain_clear() {
+    let _ = Command::new("secret-tool")
+        .args([
+            "clear",
+            "service",
+            KEYCHAIN_SERVICE,
+            "account",
+            KEYCHAIN_ACCOUNT,
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+// Windows Credential Manager has no CLI that can read secrets back, so other
+// platforms use the file fallback only.
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn keychain_store(_key: &str) -> bool {
+    false
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn keychain_load() -> Option<String> {
+    None
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn keychain_clear() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_keeps_last_four() {
+        assert_eq!(mask("ABCD-1234-WXYZ"), "****WXYZ");
+        assert_eq!(mask("ab"), "****ab");
+    }
+
+    #[test]
+    fn test_store_rejects_empty_key() {
+        assert!(store("   ").is_err());
+    }
+
+    #[test]
+    fn test_license_file_path_in_shimmy_dir() {
+        let path = license_file_path();
+        assert!(path.ends_with("shimmy/license.key"));
+    }
+}
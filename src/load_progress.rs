@@ -0,0 +1,338 @@
+//! Model load progress for the terminal and the `/api/events` stream.
+//!
+//! Big GGUFs can take minutes to come off disk. While a model loads, the
+//! weights are read through the page cache in chunks (so the backend's mmap
+//! then finds them resident) and each step is published here: `shimmy serve`
+//! draws it as a progress bar on stderr, and `/api/events` forwards it to
+//! HTTP clients as `load_progress` events.
+
+use serde::Serialize;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before slow ones start skipping
+const EVENT_CAPACITY: usize = 256;
+/// Read size when pulling weights through the page cache
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+const READ_CHUNK: usize = 8 * 1024 * 1024;
+
+/// Where a model load is at
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadStage {
+    /// Waiting for a free load slot
+    Queued,
+    /// Reading weights from disk
+    Reading,
+    /// Building the model in the backend, uploading layers to the GPU
+    Offloading,
+    Ready,
+    Failed,
+}
+
+/// One progress update for a model load
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LoadProgress {
+    pub model: String,
+    pub stage: LoadStage,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    /// Layers being offloaded to the GPU, once known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_layers: Option<u32>,
+    /// Loads waiting ahead of this one, while queued
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queued_ahead: Option<usize>,
+    pub elapsed_ms: u64,
+}
+
+impl LoadProgress {
+    pub fn queued(model: &str, ahead: usize) -> Self {
+        LoadProgress {
+            model: model.to_string(),
+            stage: LoadStage::Queued,
+            bytes_done: 0,
+            bytes_total: 0,
+            gpu_layers: None,
+            queued_ahead: Some(ahead),
+            elapsed_ms: 0,
+        }
+    }
+
+    /// Share of the weights read so far, 0-100
+    pub fn percent(&self) -> u8 {
+        if self.bytes_total == 0 {
+            return 100;
+        }
+        (self.bytes_done.min(self.bytes_total) * 100 / self.bytes_total) as u8
+    }
+
+    /// Single-line terminal rendering, e.g. `[#####-----] 50% 2.0/4.0 GB`
+    pub fn render_line(&self) -> String {
+        const WIDTH: usize = 24;
+        let gb = |b: u64| b as f64 / 1_073_741_824.0;
+        let secs = self.elapsed_ms as f64 / 1000.0;
+        match self.stage {
+            LoadStage::Queued => format!(
+                "⏳ Waiting to load {}: another load is running ({} queued ahead)",
+                self.model,
+                self.queued_ahead.unwrap_or(0)
+            ),
+            LoadStage::Reading => {
+                let filled = self.percent() as usize * WIDTH / 100;
+                format!(
+                    "📥 Loading {} [{}{}] {:>3}% {:.1}/{:.1} GB ({:.0}s)",
+                    self.model,
+                    "#".repeat(filled),
+                    "-".repeat(WIDTH - filled),
+                    self.percent(),
+                    gb(self.bytes_done),
+                    gb(self.bytes_total),
+                    secs
+                )
+            }
+            LoadStage::Offloading => match self.gpu_layers {
+                Some(n) if n > 0 => format!(
+                    "🧠 Loading {}: uploading {} layers to GPU ({:.0}s)",
+                    self.model, n, secs
+                ),
+                _ => format!("🧠 Loading {}: building model ({:.0}s)", self.model, secs),
+            },
+            LoadStage::Ready => format!("✅ Loaded {} in {:.1}s", self.model, secs),
+            LoadStage::Failed => format!("❌ Failed to load {} after {:.1}s", self.model, secs),
+        }
+    }
+}
+
+/// Broadcast hub for load progress
+pub struct LoadEvents {
+    tx: broadcast::Sender<LoadProgress>,
+}
+
+impl LoadEvents {
+    pub fn global() -> &'static LoadEvents {
+        static GLOBAL: OnceLock<LoadEvents> = OnceLock::new();
+        GLOBAL.get_or_init(|| LoadEvents {
+            tx: broadcast::channel(EVENT_CAPACITY).0,
+        })
+    }
+
+    /// Send an update to every subscriber; a no-op when nobody listens
+    pub fn publish(&self, progress: LoadProgress) {
+        let _ = self.tx.send(progress);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LoadProgress> {
+        self.tx.subscribe()
+    }
+}
+
+/// Publishes the progress of one model load
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+pub struct LoadTracker {
+    model: String,
+    bytes_total: u64,
+    gpu_layers: Option<u32>,
+    started: Instant,
+    ready: bool,
+}
+
+#[cfg_attr(not(feature = "llama"), allow(dead_code))]
+impl LoadTracker {
+    pub fn start(model: &str, bytes_total: u64) -> Self {
+        let tracker = LoadTracker {
+            model: model.to_string(),
+            bytes_total,
+            gpu_layers: None,
+            started: Instant::now(),
+            ready: false,
+        };
+        tracker.publish(LoadStage::Reading, 0);
+        tracker
+    }
+
+    fn publish(&self, stage: LoadStage, bytes_done: u64) {
+        LoadEvents::global().publish(LoadProgress {
+            model: self.model.clone(),
+            stage,
+            bytes_done,
+            bytes_total: self.bytes_total,
+            gpu_layers: self.gpu_layers,
+            queued_ahead: None,
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+        });
+    }
+
+    /// Read `files` front to back, publishing every whole percent
+    ///
+    /// Skipped when the weights don't fit in available memory: they would
+    /// only evict each other and the backend would read them twice.
+    pub fn read_weights(&self, files: &[PathBuf]) -> std::io::Result<()> {
+        if crate::util::memory::sample_memory()
+            .is_some_and(|m| self.bytes_total > m.available_bytes)
+        {
+            tracing::debug!(
+                "Skipping read-ahead for {}: larger than available memory",
+                self.model
+            );
+            return Ok(());
+        }
+        let mut buf = vec![0u8; READ_CHUNK];
+        let mut done = 0u64;
+        let mut last_percent = 0;
+        for path in files {
+            let mut file = std::fs::File::open(path)?;
+            loop {
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                done += n as u64;
+                let percent = (done.min(self.bytes_total) * 100)
+                    .checked_div(self.bytes_total)
+                    .unwrap_or(100);
+                if percent > last_percent {
+                    last_percent = percent;
+                    self.publish(LoadStage::Reading, done);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Weights are read; the backend is now building the model
+    pub fn offloading(&mut self, gpu_layers: u32) {
+        self.gpu_layers = Some(gpu_layers);
+        self.publish(LoadStage::Offloading, self.bytes_total);
+    }
+
+    /// The model is loaded; dropping the tracker without this reports failure
+    pub fn ready(mut self) {
+        self.ready = true;
+        self.publish(LoadStage::Ready, self.bytes_total);
+    }
+}
+
+impl Drop for LoadTracker {
+    fn drop(&mut self) {
+        if !self.ready {
+            self.publish(LoadStage::Failed, 0);
+        }
+    }
+}
+
+/// Draw load progress on stderr while the server runs
+///
+/// Does nothing when stderr isn't a terminal, so logs piped to files stay
+/// free of carriage-return redraws.
+pub fn spawn_terminal_reporter() {
+    if !std::io::IsTerminal::is_terminal(&std::io::stderr()) {
+        return;
+    }
+    let mut rx = LoadEvents::global().subscribe();
+    tokio::spawn(async move {
+        use std::io::Write;
+        loop {
+            let progress = match rx.recv().await {
+                Ok(progress) => progress,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let mut stderr = std::io::stderr();
+            let line = progress.render_line();
+            match progress.stage {
+                LoadStage::Ready | LoadStage::Failed => {
+                    let _ = writeln!(stderr, "\r\x1b[2K{}", line);
+                }
+                _ => {
+                    let _ = write!(stderr, "\r\x1b[2K{}", line);
+                    let _ = stderr.flush();
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(stage: LoadStage, bytes_done: u64) -> LoadProgress {
+        LoadProgress {
+            model: "llama".to_string(),
+            stage,
+            bytes_done,
+            bytes_total: 4 * 1_073_741_824,
+            gpu_layers: None,
+            queued_ahead: None,
+            elapsed_ms: 3000,
+        }
+    }
+
+    #[test]
+    fn test_render_line() {
+        let half = progress(LoadStage::Reading, 2 * 1_073_741_824);
+        assert_eq!(half.percent(), 50);
+        assert_eq!(
+            half.render_line(),
+            "📥 Loading llama [############------------]  50% 2.0/4.0 GB (3s)"
+        );
+
+        let mut offloading = progress(LoadStage::Offloading, 0);
+        offloading.gpu_layers = Some(33);
+        assert!(offloading
+            .render_line()
+            .contains("uploading 33 layers to GPU"));
+        assert!(progress(LoadStage::Ready, 0)
+            .render_line()
+            .starts_with("✅ Loaded llama"));
+        assert_eq!(
+            LoadProgress::queued("llama", 2).render_line(),
+            "⏳ Waiting to load llama: another load is running (2 queued ahead)"
+        );
+    }
+
+    #[test]
+    fn test_progress_serialization() {
+        let json = serde_json::to_value(progress(LoadStage::Offloading, 0)).unwrap();
+        assert_eq!(json["stage"], "offloading");
+        assert!(json.get("gpu_layers").is_none());
+        assert!(json.get("queued_ahead").is_none());
+        let queued = serde_json::to_value(LoadProgress::queued("llama", 0)).unwrap();
+        assert_eq!(queued["stage"], "queued");
+        assert_eq!(queued["queued_ahead"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_tracker_publishes_read_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("weights.gguf");
+        std::fs::write(&path, vec![0u8; READ_CHUNK * 2 + 10]).unwrap();
+        let total = std::fs::metadata(&path).unwrap().len();
+
+        let mut rx = LoadEvents::global().subscribe();
+        let mut tracker = LoadTracker::start("tracker-test", total);
+        tracker.read_weights(&[path]).unwrap();
+        tracker.offloading(0);
+        tracker.ready();
+        drop(LoadTracker::start("tracker-test-failed", total));
+
+        let mut stages = Vec::new();
+        let mut failed = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            match event.model.as_str() {
+                "tracker-test" => stages.push((event.stage, event.bytes_done)),
+                "tracker-test-failed" => failed.push(event.stage),
+                _ => {}
+            }
+        }
+        assert_eq!(failed, vec![LoadStage::Reading, LoadStage::Failed]);
+        assert_eq!(stages.first(), Some(&(LoadStage::Reading, 0)));
+        assert_eq!(stages.last(), Some(&(LoadStage::Ready, total)));
+        assert!(stages.contains(&(LoadStage::Offloading, total)));
+    }
+}
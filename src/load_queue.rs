@@ -0,0 +1,156 @@
+//! Bound on concurrent model loads.
+//!
+//! Requests load their model on arrival, so a burst of requests for different
+//! large models would otherwise pull several multi-GB files into memory at
+//! once. Loads made through [`LoadQueue`] take one of a fixed number of slots;
+//! the rest wait their turn and say so with a `queued` load event, which the
+//! terminal progress bar and `/api/events` show like any other load stage.
+//!
+//! `SHIMMY_MAX_CONCURRENT_LOADS` sets the number of slots (default 1).
+
+use crate::engine::{InferenceEngine, LoadedModel, ModelSpec};
+use crate::load_progress::{LoadEvents, LoadProgress};
+use anyhow::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::Instrument;
+
+pub const MAX_CONCURRENT_LOADS_ENV: &str = "SHIMMY_MAX_CONCURRENT_LOADS";
+const DEFAULT_MAX_CONCURRENT_LOADS: usize = 1;
+
+/// Hands out load slots in arrival order
+pub struct LoadQueue {
+    slots: Semaphore,
+    limit: usize,
+    waiting: AtomicUsize,
+}
+
+/// Counts a caller as waiting until it gets a slot or gives up
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl LoadQueue {
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            slots: Semaphore::new(limit),
+            limit,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let limit = std::env::var(MAX_CONCURRENT_LOADS_ENV)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|&n: &usize| n > 0)
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_LOADS);
+        Self::new(limit)
+    }
+
+    /// Wait for a slot, announcing the wait when there isn't one free
+    pub async fn acquire(&self, model: &str) -> SemaphorePermit<'_> {
+        if let Ok(permit) = self.slots.try_acquire() {
+            return permit;
+        }
+        let ahead = self.waiting.fetch_add(1, Ordering::SeqCst);
+        let _waiting = Waiting(&self.waiting);
+        tracing::info!(
+            "Queueing load of {}: {} load(s) running, {} waiting ahead",
+            model,
+            self.limit,
+            ahead
+        );
+        LoadEvents::global().publish(LoadProgress::queued(model, ahead));
+        self.slots
+            .acquire()
+            .await
+            .expect("load queue semaphore is never closed")
+    }
+
+    /// Loads currently waiting for a slot
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+
+    /// `engine.load(spec)` once a slot is free; the slot is held only while loading
+    pub async fn load(
+        &self,
+        engine: &dyn InferenceEngine,
+        spec: &ModelSpec,
+    ) -> Result<Box<dyn LoadedModel>> {
+        async {
+            let _slot = self
+                .acquire(&spec.name)
+                .instrument(tracing::info_span!("queue.wait", queue = "load"))
+                .await;
+            engine.load(spec).await
+        }
+        .instrument(tracing::info_span!("model.load", model = %spec.name))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_progress::LoadStage;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_limit_is_at_least_one() {
+        assert_eq!(LoadQueue::new(0).limit, 1);
+        assert_eq!(LoadQueue::new(3).limit, 3);
+    }
+
+    #[tokio::test]
+    async fn test_surplus_loads_queue_and_announce_it() {
+        let queue = Arc::new(LoadQueue::new(1));
+        let mut events = LoadEvents::global().subscribe();
+
+        let first = queue.acquire("queue-test-a").await;
+        let waiter = {
+            let queue = queue.clone();
+            tokio::spawn(async move {
+                let _slot = queue.acquire("queue-test-b").await;
+            })
+        };
+        while queue.waiting.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(!waiter.is_finished());
+
+        drop(first);
+        waiter.await.unwrap();
+        assert_eq!(queue.waiting.load(Ordering::SeqCst), 0);
+
+        let mut queued = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if matches!(event.model.as_str(), "queue-test-a" | "queue-test-b") {
+                queued.push((event.model, event.stage, event.queued_ahead));
+            }
+        }
+        assert_eq!(
+            queued,
+            vec![("queue-test-b".to_string(), LoadStage::Queued, Some(0))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_wait_leaves_the_queue() {
+        let queue = LoadQueue::new(1);
+        let _slot = queue.acquire("queue-test-c").await;
+        let timed_out = tokio::time::timeout(
+            std::time::Duration::from_millis(10),
+            queue.acquire("queue-test-d"),
+        )
+        .await;
+        assert!(timed_out.is_err());
+        assert_eq!(queue.waiting.load(Ordering::SeqCst), 0);
+    }
+}
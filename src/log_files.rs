@@ -0,0 +1,375 @@
+//! Rolling log files.
+//!
+//! By default shimmy only logs to stderr. With `SHIMMY_LOG_DIR` (or `log_dir`
+//! in `shimmy.toml`) set, application logs also go to `<dir>/shimmy.log` and
+//! one line per HTTP request goes to `<dir>/access.log`. Each file is rotated
+//! when the hour or day changes (`SHIMMY_LOG_ROTATE`: `hourly`, `daily` or
+//! `never`) or when it would grow past `SHIMMY_LOG_MAX_SIZE_MB`, and only the
+//! newest `SHIMMY_LOG_KEEP` rotated files are kept. Rotated files are named
+//! `<stem>.<YYYYMMDD-HHMMSS.mmm>.log`, so they sort by age.
+
+use chrono::{DateTime, Local};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+pub const LOG_DIR_ENV: &str = "SHIMMY_LOG_DIR";
+pub const LOG_ROTATE_ENV: &str = "SHIMMY_LOG_ROTATE";
+pub const LOG_MAX_SIZE_ENV: &str = "SHIMMY_LOG_MAX_SIZE_MB";
+pub const LOG_KEEP_ENV: &str = "SHIMMY_LOG_KEEP";
+pub const ACCESS_LOG_ENV: &str = "SHIMMY_ACCESS_LOG";
+
+const DEFAULT_MAX_SIZE_MB: u64 = 100;
+const DEFAULT_KEEP: usize = 7;
+
+/// When a log file is started afresh regardless of its size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl Rotation {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "hourly" => Some(Self::Hourly),
+            "daily" => Some(Self::Daily),
+            "never" | "off" | "none" => Some(Self::Never),
+            _ => None,
+        }
+    }
+
+    /// Files whose writes fall in different periods are rotated apart
+    fn period(self, at: DateTime<Local>) -> String {
+        match self {
+            Self::Hourly => at.format("%Y%m%d%H").to_string(),
+            Self::Daily => at.format("%Y%m%d").to_string(),
+            Self::Never => String::new(),
+        }
+    }
+}
+
+/// Rotation and retention shared by every log file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogPolicy {
+    pub dir: PathBuf,
+    pub rotation: Rotation,
+    /// Size that triggers rotation; `None` for no limit
+    pub max_bytes: Option<u64>,
+    /// Rotated files kept per log
+    pub keep: usize,
+    pub access_log: bool,
+}
+
+impl LogPolicy {
+    /// The configured policy, or `None` when file logging is off
+    pub fn from_env() -> Option<Self> {
+        let dir = std::env::var(LOG_DIR_ENV).ok()?;
+        if dir.trim().is_empty() {
+            return None;
+        }
+        let var = |name: &str| std::env::var(name).ok();
+        let rotation = match var(LOG_ROTATE_ENV) {
+            Some(value) => Rotation::parse(&value).unwrap_or_else(|| {
+                eprintln!(
+                    "⚠️  Ignoring {}={:?}: expected hourly, daily or never",
+                    LOG_ROTATE_ENV, value
+                );
+                Rotation::Daily
+            }),
+            None => Rotation::Daily,
+        };
+        let max_mb = var(LOG_MAX_SIZE_ENV)
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_MAX_SIZE_MB);
+        Some(Self {
+            dir: PathBuf::from(dir.trim()),
+            rotation,
+            max_bytes: (max_mb > 0).then_some(max_mb * 1024 * 1024),
+            keep: var(LOG_KEEP_ENV)
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_KEEP),
+            access_log: var(ACCESS_LOG_ENV)
+                .map(|v| !matches!(v.trim(), "0" | "false" | "off"))
+                .unwrap_or(true),
+        })
+    }
+}
+
+struct OpenFile {
+    file: std::fs::File,
+    len: u64,
+    period: String,
+}
+
+/// An append-only log file that rotates itself
+pub struct RollingFile {
+    path: PathBuf,
+    stem: String,
+    policy: LogPolicy,
+    file: Mutex<Option<OpenFile>>,
+}
+
+impl RollingFile {
+    /// `<policy.dir>/<stem>.log`; the file is opened at the first write
+    pub fn new(policy: &LogPolicy, stem: &str) -> Self {
+        Self {
+            path: policy.dir.join(format!("{}.log", stem)),
+            stem: stem.to_string(),
+            policy: policy.clone(),
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one record, rotating first when it is due
+    pub fn append(&self, record: &[u8]) -> io::Result<()> {
+        let now = Local::now();
+        let period = self.policy.rotation.period(now);
+        let mut guard = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if guard.is_none() {
+            *guard = Some(self.open()?);
+        }
+        let due = guard.as_ref().is_some_and(|open| {
+            open.len > 0
+                && (open.period != period
+                    || self
+                        .policy
+                        .max_bytes
+                        .is_some_and(|max| open.len + record.len() as u64 > max))
+        });
+        if due {
+            *guard = None;
+            self.rotate(now)?;
+            *guard = Some(self.open()?);
+        }
+        let open = guard.as_mut().expect("log file opened above");
+        open.file.write_all(record)?;
+        open.len += record.len() as u64;
+        Ok(())
+    }
+
+    fn open(&self) -> io::Result<OpenFile> {
+        std::fs::create_dir_all(&self.policy.dir)?;
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let meta = file.metadata()?;
+        // A file left by an earlier run belongs to the period it was last written in
+        let written = meta
+            .modified()
+            .map(DateTime::<Local>::from)
+            .unwrap_or_else(|_| Local::now());
+        Ok(OpenFile {
+            file,
+            len: meta.len(),
+            period: self.policy.rotation.period(written),
+        })
+    }
+
+    fn rotate(&self, now: DateTime<Local>) -> io::Result<()> {
+        let rotated = self.policy.dir.join(format!(
+            "{}.{}.log",
+            self.stem,
+            now.format("%Y%m%d-%H%M%S%.3f")
+        ));
+        std::fs::rename(&self.path, rotated)?;
+        self.prune()
+    }
+
+    /// Delete rotated files beyond the newest `keep`
+    fn prune(&self) -> io::Result<()> {
+        let prefix = format!("{}.", self.stem);
+        let current = format!("{}.log", self.stem);
+        let mut rotated: Vec<PathBuf> = std::fs::read_dir(&self.policy.dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        name.starts_with(&prefix) && name.ends_with(".log") && name != current
+                    })
+            })
+            .collect();
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.policy.keep);
+        for path in &rotated[..excess] {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for RollingFile {
+    type Writer = &'a RollingFile;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+/// The fmt layer writes each event in one call, so records never straddle a
+/// rotation
+impl Write for &RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.append(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+static ACCESS_LOG: OnceLock<Option<RollingFile>> = OnceLock::new();
+
+/// Set up file logging from the environment
+///
+/// Returns the application log for the tracing subscriber and keeps the
+/// access log for [`access_log`]. Call once, after `shimmy.toml` has been
+/// applied.
+pub fn init() -> Option<RollingFile> {
+    let policy = LogPolicy::from_env();
+    let _ = ACCESS_LOG.set(
+        policy
+            .as_ref()
+            .filter(|p| p.access_log)
+            .map(|p| RollingFile::new(p, "access")),
+    );
+    policy.map(|p| RollingFile::new(&p, "shimmy"))
+}
+
+/// The access log, when file logging is on
+pub fn access_log() -> Option<&'static RollingFile> {
+    ACCESS_LOG.get().and_then(Option::as_ref)
+}
+
+/// Access log line in Common Log Format, plus the time taken to answer
+///
+/// The query string is left out since it may carry credentials.
+pub fn access_line(
+    peer: Option<IpAddr>,
+    at: DateTime<Local>,
+    request_line: (&str, &str, &str),
+    status: u16,
+    elapsed: Duration,
+) -> String {
+    let (method, path, version) = request_line;
+    format!(
+        "{} - - [{}] \"{} {} {}\" {} {}ms\n",
+        peer.map_or_else(|| "-".to_string(), |ip| ip.to_string()),
+        at.format("%d/%b/%Y:%H:%M:%S %z"),
+        method,
+        path,
+        version,
+        status,
+        elapsed.as_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(dir: &Path, max_bytes: Option<u64>, keep: usize) -> LogPolicy {
+        LogPolicy {
+            dir: dir.to_path_buf(),
+            rotation: Rotation::Never,
+            max_bytes,
+            keep,
+            access_log: true,
+        }
+    }
+
+    fn rotated(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name != "shimmy.log")
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_rotates_at_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = RollingFile::new(&policy(dir.path(), Some(10), 5), "shimmy");
+        log.append(b"123456\n").unwrap();
+        assert!(rotated(dir.path()).is_empty());
+        log.append(b"abcdef\n").unwrap();
+        assert_eq!(rotated(dir.path()).len(), 1);
+        assert_eq!(std::fs::read_to_string(log.path()).unwrap(), "abcdef\n");
+    }
+
+    #[test]
+    fn test_oversized_record_goes_to_an_empty_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = RollingFile::new(&policy(dir.path(), Some(4), 5), "shimmy");
+        log.append(b"longer than the limit\n").unwrap();
+        assert!(rotated(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_keeps_newest_rotated_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = RollingFile::new(&policy(dir.path(), Some(1), 2), "shimmy");
+        for i in 0..5 {
+            log.append(format!("{}\n", i).as_bytes()).unwrap();
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        let kept = rotated(dir.path());
+        assert_eq!(kept.len(), 2);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(&kept[1])).unwrap(),
+            "3\n"
+        );
+        assert_eq!(std::fs::read_to_string(log.path()).unwrap(), "4\n");
+    }
+
+    #[test]
+    fn test_rotation_periods() {
+        assert_eq!(Rotation::parse("Daily"), Some(Rotation::Daily));
+        assert_eq!(Rotation::parse("never"), Some(Rotation::Never));
+        assert_eq!(Rotation::parse("weekly"), None);
+
+        let at = DateTime::parse_from_rfc3339("2026-10-16T12:30:00+00:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let later = at + chrono::Duration::hours(1);
+        assert_ne!(Rotation::Hourly.period(at), Rotation::Hourly.period(later));
+        assert_eq!(Rotation::Never.period(at), Rotation::Never.period(later));
+    }
+
+    #[test]
+    fn test_access_line() {
+        let at = DateTime::parse_from_rfc3339("2026-10-16T12:30:00+00:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let line = access_line(
+            Some("127.0.0.1".parse().unwrap()),
+            at,
+            ("POST", "/v1/chat/completions", "HTTP/1.1"),
+            200,
+            Duration::from_millis(1234),
+        );
+        assert!(line.starts_with("127.0.0.1 - - ["));
+        assert!(line.ends_with("] \"POST /v1/chat/completions HTTP/1.1\" 200 1234ms\n"));
+        assert!(access_line(
+            None,
+            at,
+            ("GET", "/health", "HTTP/1.1"),
+            200,
+            Duration::ZERO
+        )
+        .starts_with("- - - ["));
+    }
+}
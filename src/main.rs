@@ -1,28 +1,90 @@
 // Suppress function pointer comparison warnings from auto-generated bindings
 #![allow(unpredictable_function_pointer_comparisons)]
 
+mod admin_config;
 mod anthropic_compat;
 mod api;
 mod api_errors;
+mod api_keys;
 mod auto_discovery;
+mod branding;
 mod cache;
+mod capabilities;
+mod chat;
 mod cli;
+mod code_completion;
+mod connect;
+mod deadline;
+mod embeddings;
 mod engine;
+mod error;
+mod grpc;
 mod invariant_ppt;
+mod ip_acl;
+#[cfg(feature = "vision")]
+mod license_store;
+mod load_progress;
+mod load_queue;
+mod log_files;
 mod main_integration;
+mod migrations;
+mod model_cache;
+mod model_names;
 mod model_registry;
+mod model_schedule;
+mod model_store;
 mod observability;
+mod ollama_compat;
+mod oneshot;
 mod openai_compat;
+mod otel;
 mod port_manager;
+mod power;
+mod prometheus;
+mod rag;
+mod rate_limit;
+mod recovery;
+mod report;
+mod route_limits;
+mod sandbox;
 mod server;
+mod setup;
+#[cfg(feature = "vision")]
+mod state_store;
+mod telemetry;
 mod templates;
+mod tls;
+mod tool_calling;
+mod truncation;
+mod upgrade;
+#[cfg(feature = "vision")]
+mod usage_export;
+mod vector_store;
 #[cfg(feature = "vision")]
 mod vision;
 #[cfg(feature = "vision")]
+mod vision_batch;
+#[cfg(feature = "vision")]
 mod vision_license;
+#[cfg(feature = "vision")]
+mod vision_modes;
+#[cfg(feature = "vision")]
+mod vision_quality;
+#[cfg(feature = "vision")]
+mod vision_safety;
+#[cfg(feature = "vision-tesseract")]
+mod vision_tesseract;
+#[cfg(feature = "vision-video")]
+mod vision_video;
+#[cfg(feature = "vision")]
+mod vision_webhook;
 mod util {
+    pub mod cpu;
     pub mod diag;
+    pub mod http;
     pub mod memory;
+    pub mod paths;
+    pub mod zip;
 }
 
 use clap::Parser;
@@ -36,6 +98,19 @@ pub struct AppState {
     pub registry: Registry,
     pub observability: observability::ObservabilityManager,
     pub response_cache: cache::ResponseCache,
+    pub load_queue: load_queue::LoadQueue,
+    pub route_limits: route_limits::RouteLimits,
+    pub vector_store: vector_store::VectorStore,
+    /// Set by `serve --read-only`: mutating routes answer 403
+    pub read_only: bool,
+    /// Per-route client address rules from `SHIMMY_IP_ACL`
+    pub ip_acl: ip_acl::IpAcl,
+    /// Keys and their limits from `SHIMMY_API_KEYS_FILE`; empty leaves the API open
+    pub api_keys: api_keys::ApiKeys,
+    /// Server-wide and per-client limits from `SHIMMY_RATE_LIMIT_*`
+    pub rate_limits: rate_limit::RateLimits,
+    /// Models kept loaded between requests, per `--model-ttl` and `keep_alive`
+    pub model_cache: model_cache::ModelCache,
     #[cfg(feature = "vision")]
     pub vision_license_manager: Option<crate::vision_license::VisionLicenseManager>,
 }
@@ -48,6 +123,14 @@ impl AppState {
             registry,
             observability: observability::ObservabilityManager::new(),
             response_cache: cache::ResponseCache::new(),
+            load_queue: load_queue::LoadQueue::from_env(),
+            route_limits: route_limits::RouteLimits::from_env(),
+            vector_store: vector_store::VectorStore::new(util::paths::data_dir().join("vectors")),
+            read_only: false,
+            ip_acl: ip_acl::IpAcl::default(),
+            api_keys: api_keys::ApiKeys::default(),
+            rate_limits: rate_limit::RateLimits::from_env(),
+            model_cache: model_cache::ModelCache::default(),
             #[cfg(feature = "vision")]
             vision_license_manager: None,
         };
@@ -55,10 +138,37 @@ impl AppState {
         #[cfg(feature = "vision")]
         {
             state.vision_license_manager = Some(crate::vision_license::VisionLicenseManager::new());
+            // Read the stored key now rather than on a request's worker thread
+            let _ = license_store::cached();
         }
 
         state
     }
+
+    /// Load a model through the shared load queue, waiting for a slot if needed,
+    /// unless an earlier request left it loaded
+    pub async fn load_model(
+        &self,
+        spec: &engine::ModelSpec,
+    ) -> anyhow::Result<Box<dyn engine::LoadedModel>> {
+        self.load_model_keep_alive(spec, None).await
+    }
+
+    /// [`load_model`](Self::load_model) with the request's `keep_alive`
+    pub async fn load_model_keep_alive(
+        &self,
+        spec: &engine::ModelSpec,
+        keep_alive: Option<model_cache::KeepAlive>,
+    ) -> anyhow::Result<Box<dyn engine::LoadedModel>> {
+        self.model_cache
+            .get_or_load(
+                &spec.name,
+                model_cache::estimate_bytes(spec),
+                keep_alive,
+                || self.load_queue.load(self.engine.as_ref(), spec),
+            )
+            .await
+    }
 }
 
 /// Runtime version validation - prevents Issue #63 broken binary distribution
@@ -109,7 +219,7 @@ fn print_startup_diagnostics(
     #[cfg_attr(not(feature = "llama"), allow(unused_variables))] n_cpu_moe: Option<usize>,
     model_count: usize,
 ) {
-    println!("🎯 Shimmy v{}", version);
+    println!("🎯 {} v{}", branding::PRODUCT_NAME, version);
 
     // GPU backend info
     #[cfg(feature = "llama")]
@@ -140,6 +250,10 @@ fn print_startup_diagnostics(
     {
         println!("🔧 Backend: Stub mode (no llama feature)");
     }
+    println!(
+        "🧩 Compiled in: {}",
+        capabilities::Capabilities::global().summary()
+    );
 
     // MoE configuration - NOW WORKING (Issue #108 fix)
     #[cfg(feature = "llama")]
@@ -158,8 +272,75 @@ fn print_startup_diagnostics(
     println!("📦 Models: {} available", model_count);
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+/// Global flags a worker process needs to load models the same way
+#[cfg(feature = "llama")]
+fn worker_args(cli: &cli::Cli) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(backend) = &cli.gpu_backend {
+        args.extend(["--gpu-backend".to_string(), backend.clone()]);
+    }
+    if cli.cpu_moe {
+        args.push("--cpu-moe".to_string());
+    }
+    if let Some(n) = cli.n_cpu_moe {
+        args.extend(["--n-cpu-moe".to_string(), n.to_string()]);
+    }
+    args
+}
+
+fn main() -> anyhow::Result<()> {
+    // clap answers a plain `--version` itself
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if capabilities::verbose_version_requested(&args) {
+        print!("{}", capabilities::Capabilities::global().render());
+        return Ok(());
+    }
+
+    // Settings from `shimmy setup`; explicit environment variables win
+    setup::apply_config_file();
+
+    // Threads inherit CPU affinity, so pin before the runtime and llama.cpp
+    // start theirs
+    let pinned = util::cpu::apply_on_startup();
+    if let Err(e) = &pinned {
+        eprintln!("⚠️  {} not applied: {:#}", util::cpu::AFFINITY_ENV, e);
+    }
+
+    // The sandbox covers only threads started after it, so it goes on before
+    // the runtime spawns its workers
+    if let Ok(cli::Cli {
+        cmd: cli::Command::Serve { sandbox, .. },
+        ..
+    }) = cli::Cli::try_parse()
+    {
+        if let Ok(Some(cpus)) = &pinned {
+            println!("🧵 Pinned to CPUs {}", util::cpu::format_cpu_list(cpus));
+        }
+        if sandbox::requested(sandbox) {
+            let applied = sandbox::SandboxPolicy::from_env()
+                .and_then(|policy| Ok((sandbox::apply(&policy)?, policy)));
+            match applied {
+                Ok((status, policy)) => println!(
+                    "🧱 Sandbox: {} ({} writable paths, outbound ports: {:?})",
+                    status,
+                    policy.writable.len(),
+                    policy.connect_ports
+                ),
+                Err(e) => {
+                    eprintln!("❌ {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run())
+}
+
+async fn run() -> anyhow::Result<()> {
     // Version validation - prevents Issue #63 distribution of broken binaries
     validate_runtime_version();
 
@@ -170,21 +351,95 @@ async fn main() -> anyhow::Result<()> {
             .map(|t| !t.is_empty() && t != "dumb")
             .unwrap_or(false);
 
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_ansi(use_ansi)
-        .init();
+    let cli = cli::Cli::parse();
+
+    // Console output follows RUST_LOG, as does the log file when `SHIMMY_LOG_DIR`
+    // is set; warnings and errors are also kept for `shimmy report`. Spans go to
+    // an OTLP collector when one is configured
+    let otlp_endpoint = otel::endpoint(cli.otlp_endpoint.as_deref());
+    #[cfg(not(feature = "otel"))]
+    if otlp_endpoint.is_some() {
+        eprintln!("⚠️  OTLP export needs a build with the `otel` feature; spans are not exported");
+    }
+    #[cfg(feature = "otel")]
+    let (otel_layer, _otel_guard) = match otlp_endpoint.as_deref().map(otel::layer) {
+        Some(Ok((layer, guard))) => (Some(layer), Some(guard)),
+        Some(Err(e)) => {
+            eprintln!("⚠️  OTLP export disabled: {}", e);
+            (None, None)
+        }
+        None => (None, None),
+    };
+    #[cfg(not(feature = "otel"))]
+    let otel_layer: Option<tracing_subscriber::layer::Identity> = None;
+    {
+        use tracing_subscriber::{
+            filter::LevelFilter, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter,
+            Layer,
+        };
+        // Both filters can be replaced through `PUT /api/admin/config`
+        let (console_filter, console_handle) = reload::Layer::new(EnvFilter::from_default_env());
+        let mut log_filters: Vec<admin_config::LogFilterSetter> =
+            vec![Box::new(move |directive: &str| {
+                console_handle.reload(EnvFilter::try_new(directive)?)?;
+                Ok(())
+            })];
+        let log_file = log_files::init().map(|file| {
+            let (file_filter, file_handle) = reload::Layer::new(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+            );
+            log_filters.push(Box::new(move |directive: &str| {
+                file_handle.reload(EnvFilter::try_new(directive)?)?;
+                Ok(())
+            }));
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(file)
+                .with_filter(file_filter)
+        });
+        tracing_subscriber::registry()
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(use_ansi)
+                    .with_filter(console_filter),
+            )
+            .with(log_file)
+            .with(otel_layer)
+            .with(report::RecentLogLayer::in_data_dir().with_filter(LevelFilter::WARN))
+            .init();
+        admin_config::register_log_filters(log_filters);
+    }
 
     // Platform capability notice
     #[cfg(all(target_arch = "aarch64", target_os = "macos", not(feature = "llama")))]
     info!("llama.cpp temporarily disabled on macOS ARM64 due to upstream i8mm build incompatibility; using SafeTensors backend");
 
-    let cli = cli::Cli::parse();
+    // One model for a parent `serve`; the parent owns all on-disk state
+    #[cfg(feature = "llama")]
+    if let cli::Command::Worker = cli.cmd {
+        let mut adapter =
+            engine::adapter::InferenceEngineAdapter::new_with_backend(cli.gpu_backend.as_deref());
+        if cli.cpu_moe || cli.n_cpu_moe.is_some() {
+            adapter = adapter.with_moe_config(cli.cpu_moe, cli.n_cpu_moe);
+        }
+        return engine::worker::run_worker(&adapter).await;
+    }
+
+    // Repair anything a crash or power cut left half-written before reading state
+    recovery::recover_on_startup();
+
+    // Bring on-disk state up to the current format; `shimmy migrate` handles it explicitly
+    if !matches!(cli.cmd, cli::Command::Migrate { .. }) {
+        migrations::migrate_on_startup();
+    }
 
     // Add custom model directories from command line to environment
     if let Some(model_dirs) = &cli.model_dirs {
         std::env::set_var("SHIMMY_MODEL_PATHS", model_dirs);
     }
+    if cli.no_app_models {
+        std::env::set_var(auto_discovery::NO_APP_MODELS_ENV, "1");
+    }
 
     // Initialize registry with auto-discovery
     let mut reg = Registry::with_discovery();
@@ -199,7 +454,11 @@ async fn main() -> anyhow::Result<()> {
         template: Some("chatml".into()),
         ctx_len: Some(4096),
         n_threads: None,
+        n_gpu_layers: None,
+        mmproj_path: None,
     });
+    // Models added through `POST /api/models/register` on earlier runs
+    reg.load_registered(&model_registry::registered_models_path());
 
     // Create engine with MoE configuration if needed
     let engine: Box<dyn engine::InferenceEngine> = {
@@ -214,6 +473,11 @@ async fn main() -> anyhow::Result<()> {
                 adapter = adapter.with_moe_config(cli.cpu_moe, cli.n_cpu_moe);
             }
 
+            // Run llama models in supervised child processes
+            if engine::worker::isolation_requested() {
+                adapter = adapter.with_worker_isolation(worker_args(&cli));
+            }
+
             Box::new(adapter)
         }
         #[cfg(not(feature = "llama"))]
@@ -224,6 +488,17 @@ async fn main() -> anyhow::Result<()> {
             Box::new(adapter)
         }
     };
+    let (engine, faults) =
+        engine::fault::wrap(engine, cli.fault_inject.as_deref()).unwrap_or_else(|e| {
+            eprintln!("❌ {:#}", e);
+            std::process::exit(1);
+        });
+    if let Some(faults) = faults {
+        eprintln!(
+            "⚠️  Fault injection enabled, backend calls will misbehave: {:?}",
+            faults
+        );
+    }
 
     // Handle model-path registration for serve command
     if let cli::Command::Serve {
@@ -247,6 +522,8 @@ async fn main() -> anyhow::Result<()> {
                 template: None,
                 ctx_len: None,
                 n_threads: None,
+                n_gpu_layers: None,
+                mmproj_path: None,
             });
 
             println!("🎯 Direct model loaded: {} -> {}", model_name, path);
@@ -256,11 +533,44 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    let state = AppState::new(engine, reg);
+    let mut state = AppState::new(engine, reg);
+    if let cli::Command::Serve {
+        read_only,
+        ref model_ttl,
+        ..
+    } = cli.cmd
+    {
+        state.read_only = read_only;
+        let keep_alive =
+            model_cache::KeepAlive::resolve(model_ttl.as_deref()).unwrap_or_else(|e| {
+                eprintln!("❌ --model-ttl: {:#}", e);
+                std::process::exit(1);
+            });
+        state.model_cache = model_cache::ModelCache::new(keep_alive, model_cache::memory_budget());
+        state.ip_acl = ip_acl::IpAcl::from_env().unwrap_or_else(|e| {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        });
+        state.api_keys = api_keys::ApiKeys::from_env().unwrap_or_else(|e| {
+            eprintln!("❌ {:#}", e);
+            std::process::exit(1);
+        });
+        #[cfg(feature = "vision")]
+        if let Err(e) = vision_modes::init() {
+            eprintln!("❌ {:#}", e);
+            std::process::exit(1);
+        }
+    }
     let state = Arc::new(state);
 
     match cli.cmd {
-        cli::Command::Serve { ref bind, .. } => {
+        cli::Command::Serve {
+            ref bind,
+            ref tls_cert,
+            ref tls_key,
+            ref grpc_bind,
+            ..
+        } => {
             // Use smart bind address resolution instead of direct parsing
             let addr = port_manager::GLOBAL_PORT_ALLOCATOR
                 .resolve_bind_address(bind)
@@ -284,7 +594,62 @@ async fn main() -> anyhow::Result<()> {
                 cli.n_cpu_moe,
                 0, // Will update after model discovery
             );
+            let tls = tls::TlsFiles::resolve(tls_cert.as_deref(), tls_key.as_deref())
+                .unwrap_or_else(|e| {
+                    eprintln!("❌ {}", e);
+                    std::process::exit(1);
+                });
+            let grpc_addr = grpc::bind_address(grpc_bind.as_deref()).unwrap_or_else(|e| {
+                eprintln!("❌ --grpc-bind: {:#}", e);
+                std::process::exit(1);
+            });
+            let schedule = model_schedule::Schedule::from_env().unwrap_or_else(|e| {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            });
+
             println!("🚀 Starting server on {}", addr);
+            if let Some(files) = &tls {
+                println!("🔐 Serving HTTPS with {}", files.cert.display());
+            }
+            if state.read_only {
+                println!("🔒 Read-only mode: model, vector store and ingest changes are disabled");
+            }
+            if !state.ip_acl.is_empty() {
+                println!(
+                    "🛡️  IP access rules: {} (from {})",
+                    state.ip_acl.len(),
+                    ip_acl::IP_ACL_ENV
+                );
+            }
+            if !state.api_keys.is_empty() {
+                println!(
+                    "🔑 API keys required: {} key(s) (from {})",
+                    state.api_keys.len(),
+                    api_keys::API_KEYS_FILE_ENV
+                );
+            }
+            if !state.rate_limits.is_empty() {
+                println!("🚦 Rate limits: {}", state.rate_limits.summary());
+            }
+            if !schedule.is_empty() {
+                println!(
+                    "⏰ Model schedule: {} rule(s) (from {})",
+                    schedule.len(),
+                    model_schedule::MODEL_SCHEDULE_ENV
+                );
+            }
+            if !state.model_cache.default_keep_alive().unloads_immediately() {
+                let budget = match state.model_cache.budget() {
+                    Some(bytes) => format!("{:.1} GB", bytes as f64 / 1_073_741_824.0),
+                    None => "no limit".to_string(),
+                };
+                println!(
+                    "♻️  Idle models: {}, kept up to {}",
+                    state.model_cache.default_keep_alive(),
+                    budget
+                );
+            }
 
             // Auto-register discovered models if we only have the default
             let manual_count = state.registry.list().len();
@@ -303,6 +668,11 @@ async fn main() -> anyhow::Result<()> {
                             adapter = adapter.with_moe_config(cli.cpu_moe, cli.n_cpu_moe);
                         }
 
+                        // Run llama models in supervised child processes
+                        if engine::worker::isolation_requested() {
+                            adapter = adapter.with_worker_isolation(worker_args(&cli));
+                        }
+
                         Box::new(adapter)
                     }
                     #[cfg(not(feature = "llama"))]
@@ -313,8 +683,21 @@ async fn main() -> anyhow::Result<()> {
                         Box::new(adapter)
                     }
                 };
+                // Same faults as the first engine; the spec was checked there
+                let enhanced_engine =
+                    match engine::fault::wrap(enhanced_engine, cli.fault_inject.as_deref()) {
+                        Ok((engine, _)) => engine,
+                        Err(e) => {
+                            eprintln!("❌ {:#}", e);
+                            std::process::exit(1);
+                        }
+                    };
 
                 let mut enhanced_state = AppState::new(enhanced_engine, state.registry.clone());
+                enhanced_state.read_only = state.read_only;
+                enhanced_state.ip_acl = state.ip_acl.clone();
+                enhanced_state.api_keys = state.api_keys.clone();
+                enhanced_state.model_cache = state.model_cache.clone();
                 enhanced_state.registry.auto_register_discovered();
                 let enhanced_state = Arc::new(enhanced_state);
 
@@ -332,10 +715,13 @@ async fn main() -> anyhow::Result<()> {
                 println!("✅ Ready to serve requests");
                 println!("   • POST /api/generate (streaming + non-streaming)");
                 println!("   • GET  /health (health check + metrics)");
+                println!("   • GET  /readyz (readiness, degraded under memory pressure)");
                 println!("   • GET  /v1/models (OpenAI-compatible)");
 
                 info!(%addr, models=%available_models.len(), "shimmy serving with {} available models", available_models.len());
-                return server::run(addr, enhanced_state).await;
+                grpc::spawn(grpc_addr, &enhanced_state);
+                model_schedule::spawn(schedule, &enhanced_state);
+                return server::run(addr, enhanced_state, tls).await;
             }
 
             // Use existing state if manually configured
@@ -353,10 +739,13 @@ async fn main() -> anyhow::Result<()> {
             println!("✅ Ready to serve requests");
             println!("   • POST /api/generate (streaming + non-streaming)");
             println!("   • GET  /health (health check + metrics)");
+            println!("   • GET  /readyz (readiness, degraded under memory pressure)");
             println!("   • GET  /v1/models (OpenAI-compatible)");
 
             info!(%addr, models=%available_models.len(), "shimmy serving with {} available models", available_models.len());
-            server::run(addr, state).await?;
+            grpc::spawn(grpc_addr, &state);
+            model_schedule::spawn(schedule, &state);
+            server::run(addr, state, tls).await?;
         }
         cli::Command::List { short } => {
             if short {
@@ -425,7 +814,8 @@ async fn main() -> anyhow::Result<()> {
                 discovered.retain(|name, _| {
                     let name_lower = name.to_lowercase();
                     // Filter out known non-LLM model types
-                    !name_lower.contains("clip")
+                    !registry.is_embedding_model(name)
+                        && !name_lower.contains("clip")
                         && !name_lower.contains("text-to-image")
                         && !name_lower.contains("vision")
                         && !name_lower.contains("image")
@@ -468,6 +858,17 @@ async fn main() -> anyhow::Result<()> {
                     }
                 }
             }
+
+            let rejected = registry.rejected_models();
+            if !rejected.is_empty() {
+                println!(
+                    "\n⚠️  Skipped {} models this build can't run:",
+                    rejected.len()
+                );
+                for (name, reason) in rejected {
+                    println!("  {}: {}", name, reason);
+                }
+            }
         }
         cli::Command::Probe { name } => {
             let Some(spec) = state.registry.to_spec(&name) else {
@@ -476,7 +877,12 @@ async fn main() -> anyhow::Result<()> {
             match state.engine.load(&spec).await {
                 Ok(_) => println!("ok: loaded {name}"),
                 Err(e) => {
-                    eprintln!("probe failed: {e}");
+                    match e.downcast_ref::<engine::LoadError>() {
+                        Some(load_err) => {
+                            eprintln!("probe failed [{}]: {load_err}", load_err.code())
+                        }
+                        None => eprintln!("probe failed: {e}"),
+                    }
                     std::process::exit(2);
                 }
             }
@@ -524,6 +930,109 @@ async fn main() -> anyhow::Result<()> {
                 .await?;
             println!("{}", out);
         }
+        cli::Command::Run {
+            name,
+            prompt,
+            system,
+            raw,
+            sampling,
+            json,
+        } => {
+            let opts = oneshot::RunOptions {
+                system,
+                raw,
+                json,
+                gen: sampling.gen_options(),
+            };
+            oneshot::run(state.engine.as_ref(), &state.registry, &name, prompt, opts).await?;
+        }
+        cli::Command::Report { output } => {
+            let output = output
+                .map(PathBuf::from)
+                .unwrap_or_else(report::default_output);
+            let summary = report::write_report(&output)?;
+            println!("{}", summary);
+            println!("📦 Wrote {}", output.display());
+            println!(
+                "   Attach it to your issue at {} (config secrets are redacted; review before sharing)",
+                report::ISSUES_URL
+            );
+        }
+        cli::Command::ConnectInfo { url, model, json } => {
+            let api_key_required = api_keys::ApiKeys::from_env()
+                .map(|keys| !keys.is_empty())
+                .unwrap_or(false);
+            let info = connect::ConnectInfo::for_registry(
+                &state.registry,
+                &url.unwrap_or_else(connect::default_base_url),
+                model.as_deref(),
+                api_key_required,
+            )?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&info)?);
+            } else {
+                print!("{}", info.render());
+            }
+        }
+        cli::Command::Migrate { dry_run } => {
+            let steps = migrations::migrate(
+                &migrations::state_files(),
+                &migrations::manifest_path(),
+                dry_run,
+            )?;
+            if steps.is_empty() {
+                println!("✅ State is up to date");
+            }
+            for step in &steps {
+                println!(
+                    "{} {} ({}) v{} -> v{}: {}",
+                    if dry_run { "would migrate" } else { "migrated" },
+                    step.file,
+                    step.path.display(),
+                    step.from,
+                    step.to,
+                    step.description
+                );
+            }
+        }
+        cli::Command::Setup {
+            yes,
+            no_download,
+            output,
+        } => {
+            setup::run(setup::SetupOptions {
+                yes,
+                no_download,
+                output: output.map(PathBuf::from),
+            })
+            .await?;
+        }
+        cli::Command::Upgrade {
+            channel,
+            check,
+            force,
+        } => {
+            upgrade::run(upgrade::UpgradeOptions {
+                channel: channel.parse()?,
+                check,
+                force,
+            })
+            .await?;
+        }
+        cli::Command::Chat {
+            name,
+            system,
+            max_tokens,
+        } => {
+            chat::run(
+                state.engine.as_ref(),
+                &state.registry,
+                name,
+                system,
+                max_tokens,
+            )
+            .await?;
+        }
         cli::Command::GpuInfo => {
             println!("🖥️  GPU Backend Information");
             println!();
@@ -628,6 +1137,139 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
         }
+        cli::Command::Store { action } => {
+            let store = model_store::ModelStore::open_default();
+            match action {
+                cli::StoreAction::List => {
+                    let manifests = store.manifests()?;
+                    if manifests.is_empty() {
+                        println!("No models in {}", store.root().display());
+                    }
+                    for manifest in manifests {
+                        let digest = manifest
+                            .layer(model_store::LayerKind::Model)
+                            .map(|l| l.digest.trim_start_matches("sha256:"))
+                            .unwrap_or("-");
+                        println!(
+                            "{:<32} {:>8.2} GB  {}",
+                            manifest.name,
+                            manifest.size() as f64 / 1e9,
+                            &digest[..digest.len().min(12)]
+                        );
+                    }
+                }
+                cli::StoreAction::Pull { name, url, sha256 } => {
+                    println!("⬇️  Downloading {} into {}", url, store.root().display());
+                    let layer = store
+                        .download(
+                            &url,
+                            model_store::LayerKind::Model,
+                            sha256.as_deref(),
+                            &mut |_, _| {},
+                        )
+                        .await?;
+                    store.write_manifest(&name, vec![layer])?;
+                    println!("✅ Stored {}", name);
+                }
+                cli::StoreAction::Import {
+                    name,
+                    path,
+                    lora,
+                    mmproj,
+                } => {
+                    let mut layers = vec![store
+                        .import_file(std::path::Path::new(&path), model_store::LayerKind::Model)?];
+                    if let Some(lora) = lora {
+                        layers.push(store.import_file(
+                            std::path::Path::new(&lora),
+                            model_store::LayerKind::Lora,
+                        )?);
+                    }
+                    if let Some(mmproj) = mmproj {
+                        layers.push(store.import_file(
+                            std::path::Path::new(&mmproj),
+                            model_store::LayerKind::Projector,
+                        )?);
+                    }
+                    store.write_manifest(&name, layers)?;
+                    println!("✅ Stored {}", name);
+                }
+                cli::StoreAction::Alias { name, alias } => {
+                    store.alias(&name, &alias)?;
+                    println!("✅ {} now also refers to {}", alias, name);
+                }
+                cli::StoreAction::Rm { name } => {
+                    if store.remove(&name)? {
+                        println!(
+                            "✅ Removed {} (run `{} store prune` to free space)",
+                            name,
+                            branding::APP_NAME
+                        );
+                    } else {
+                        println!("❌ No model named {}", name);
+                        std::process::exit(1);
+                    }
+                }
+                cli::StoreAction::Prune => {
+                    let report = store.prune()?;
+                    println!(
+                        "✅ Removed {} unreferenced blobs ({:.2} GB)",
+                        report.removed,
+                        report.freed_bytes as f64 / 1e9
+                    );
+                }
+            }
+        }
+        #[cfg(feature = "vision")]
+        cli::Command::License { action } => match action {
+            cli::LicenseAction::Set { key } => match license_store::store(&key) {
+                Ok(backend) => println!("✅ License key stored in {}", backend),
+                Err(e) => {
+                    eprintln!("❌ Failed to store license key: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            cli::LicenseAction::Show => match license_store::load() {
+                Some(key) => println!("🔑 License key: {}", license_store::mask(&key)),
+                None => println!(
+                    "❌ No license key stored. Run: {} license set <key>",
+                    branding::APP_NAME
+                ),
+            },
+            cli::LicenseAction::Clear => {
+                license_store::clear()?;
+                println!("✅ Stored license key removed");
+            }
+        },
+        cli::Command::Stats {
+            json,
+            enable,
+            upload,
+            disable,
+        } => {
+            if enable || disable {
+                let mode = match (disable, upload) {
+                    (true, _) => telemetry::TelemetryMode::Off,
+                    (false, true) => telemetry::TelemetryMode::Upload,
+                    (false, false) => telemetry::TelemetryMode::Local,
+                };
+                let path = telemetry::set_mode(mode)?;
+                println!(
+                    "✅ Telemetry set to {} in {}",
+                    mode.as_str(),
+                    path.display()
+                );
+            } else {
+                telemetry::print_stats(json)?;
+            }
+        }
+        #[cfg(feature = "llama")]
+        cli::Command::Worker => unreachable!("handled before startup"),
+    }
+
+    // Keep counts from short-lived commands (e.g. `shimmy run`)
+    if let Err(e) = telemetry::Telemetry::global().flush() {
+        tracing::debug!("Telemetry flush failed: {}", e);
     }
     Ok(())
 }
@@ -686,6 +1328,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(4096),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         // Test engine creation (line 42)
@@ -765,6 +1409,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let manual_models = registry.list();
@@ -817,6 +1463,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = MockEngine;
@@ -854,6 +1502,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = MockEngine;
@@ -904,6 +1554,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = MockEngine;
@@ -1028,6 +1680,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(4096),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let models = reg.list();
@@ -1056,6 +1710,8 @@ mod tests {
             template: None,
             ctx_len: None,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let after_count = registry.list().len();
@@ -1108,6 +1764,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(4096),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine: Box<dyn engine::InferenceEngine> =
@@ -1176,6 +1834,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
         let _engine = MockEngine;
         let state = Arc::new(AppState::new(
@@ -1362,6 +2022,8 @@ mod tests {
             template: None,
             ctx_len: None,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         // Test maximal entry
@@ -1372,6 +2034,8 @@ mod tests {
             template: Some("llama3".to_string()),
             ctx_len: Some(8192),
             n_threads: Some(8),
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let models = registry.list();
@@ -1402,6 +2066,8 @@ mod tests {
             template: None,
             ctx_len: 1024,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         let loaded = engine.load(&minimal_spec).await.unwrap();
@@ -1598,6 +2264,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = MockEngine;
@@ -1633,6 +2301,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = MockEngine;
@@ -1742,6 +2412,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         // Create an engine that might fail
@@ -52,6 +52,8 @@ mod tests {
             template: Some("chatml".to_string()),
             ctx_len: Some(4096),
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         registry.register(test_entry);
@@ -92,6 +94,8 @@ mod tests {
             template: None,
             ctx_len: None,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         registry1_mut.register(test_entry);
@@ -173,6 +177,8 @@ mod tests {
             template: Some("llama3".to_string()),
             ctx_len: Some(8192),
             n_threads: Some(8),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         registry_mut.register(production_model);
@@ -215,6 +221,8 @@ mod tests {
             template: Some("chatml".to_string()),
             ctx_len: Some(2048),
             n_threads: Some(2),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         registry.register(test_model);
@@ -278,7 +278,7 @@ impl TelemetryCollector {
         let data = self.build_telemetry_data(config, metrics);
 
         // Send data with timeout
-        let client = reqwest::Client::builder()
+        let client = crate::util::http::client_builder()
             .timeout(std::time::Duration::from_secs(5))
             .build()?;
 
@@ -514,7 +514,8 @@ impl TelemetryCollector {
 
     fn detect_hardware_tier() -> String {
         let has_nvidia = Self::detect_gpu();
-        let total_memory = sys_info::mem_info().map_or(0, |m| m.total);
+        // KiB, as the thresholds below expect
+        let total_memory = crate::util::memory::get_total_memory() / 1024;
 
         if has_nvidia && total_memory > 32_000_000 {
             // 32GB+ with GPU
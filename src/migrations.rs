@@ -0,0 +1,301 @@
+//! Versioned on-disk state and startup migrations.
+//!
+//! Every persisted file Shimmy owns is listed in [`state_files`] with its
+//! current format version. The version each file was last written at is kept
+//! in `<data_dir>/state_versions.json`, so the files themselves keep their
+//! existing shape. On startup (and via `shimmy migrate`) any file behind its
+//! current version is run through its migration chain; the previous contents
+//! are kept as `<file>.v<N>.bak` before the upgraded file is written.
+//!
+//! To change a format: bump `current` for the file and append a [`Migration`]
+//! whose `from` is the old version.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the version manifest inside the data directory
+pub const MANIFEST_FILE: &str = "state_versions.json";
+
+/// One upgrade step for a state file
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: fn(Value) -> anyhow::Result<Value>,
+}
+
+/// A persisted file and the migrations that bring it to `current`
+#[derive(Debug, Clone)]
+pub struct StateFile {
+    pub name: &'static str,
+    pub path: PathBuf,
+    pub current: u32,
+    pub migrations: &'static [Migration],
+}
+
+/// Recorded format version per state file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub versions: BTreeMap<String, u32>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        crate::recovery::write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+}
+
+/// A pending upgrade of one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedStep {
+    pub file: &'static str,
+    pub path: PathBuf,
+    pub from: u32,
+    pub to: u32,
+    pub description: &'static str,
+}
+
+/// v0 -> v1: files written before versioning existed. The v1 format is the
+/// pre-versioning format, so this only checks the file is valid JSON.
+fn adopt_unversioned(value: Value) -> anyhow::Result<Value> {
+    Ok(value)
+}
+
+const ADOPT_V1: &[Migration] = &[Migration {
+    from: 0,
+    description: "adopt unversioned file as v1",
+    apply: adopt_unversioned,
+}];
+
+/// All versioned state files. Discovered models are found again on every
+/// start; only models registered through the API are saved.
+pub fn state_files() -> Vec<StateFile> {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(crate::branding::APP_NAME);
+    let vision_dir = crate::util::paths::data_dir().join("vision");
+
+    let file = |name, path| StateFile {
+        name,
+        path,
+        current: 1,
+        migrations: ADOPT_V1,
+    };
+    vec![
+        file("license_cache", vision_dir.join("license_cache.json")),
+        file("usage_stats", vision_dir.join("usage_stats.json")),
+        file(
+            "telemetry_stats",
+            crate::util::paths::data_dir()
+                .join("telemetry")
+                .join("stats.json"),
+        ),
+        file("vision_config", config_dir.join("vision.json")),
+        file(
+            "registered_models",
+            crate::model_registry::registered_models_path(),
+        ),
+    ]
+}
+
+/// Default manifest location
+pub fn manifest_path() -> PathBuf {
+    crate::util::paths::data_dir().join(MANIFEST_FILE)
+}
+
+/// Work out which migrations are pending
+pub fn plan(files: &[StateFile], manifest: &Manifest) -> anyhow::Result<Vec<PlannedStep>> {
+    let mut steps = Vec::new();
+    for file in files {
+        if !file.path.exists() {
+            continue;
+        }
+        let recorded = manifest.versions.get(file.name).copied().unwrap_or(0);
+        if recorded > file.current {
+            anyhow::bail!(
+                "{} ({}) is format v{}, newer than this shimmy supports (v{}); upgrade shimmy",
+                file.name,
+                file.path.display(),
+                recorded,
+                file.current
+            );
+        }
+
+        let mut version = recorded;
+        while version < file.current {
+            let Some(migration) = file.migrations.iter().find(|m| m.from == version) else {
+                anyhow::bail!("no migration for {} from v{}", file.name, version);
+            };
+            steps.push(PlannedStep {
+                file: file.name,
+                path: file.path.clone(),
+                from: version,
+                to: version + 1,
+                description: migration.description,
+            });
+            version += 1;
+        }
+    }
+    Ok(steps)
+}
+
+/// Apply pending migrations and record new versions. With `dry_run` nothing is
+/// written; the returned steps are what would run.
+pub fn migrate(
+    files: &[StateFile],
+    manifest_path: &Path,
+    dry_run: bool,
+) -> anyhow::Result<Vec<PlannedStep>> {
+    let mut manifest = Manifest::load(manifest_path)?;
+    let steps = plan(files, &manifest)?;
+    if dry_run {
+        return Ok(steps);
+    }
+
+    for file in files {
+        let file_steps: Vec<&PlannedStep> = steps.iter().filter(|s| s.file == file.name).collect();
+        if let Some(first) = file_steps.first() {
+            let original = std::fs::read_to_string(&file.path)?;
+            let mut value: Value = serde_json::from_str(&original)
+                .map_err(|e| anyhow::anyhow!("{} is not valid JSON: {}", file.path.display(), e))?;
+            for step in &file_steps {
+                let migration = file
+                    .migrations
+                    .iter()
+                    .find(|m| m.from == step.from)
+                    .expect("planned step has a migration");
+                value = (migration.apply)(value)?;
+            }
+
+            let backup = file
+                .path
+                .with_extension(format!("json.v{}.bak", first.from));
+            std::fs::write(&backup, &original)?;
+            crate::recovery::write_atomic(
+                &file.path,
+                serde_json::to_string_pretty(&value)?.as_bytes(),
+            )?;
+        }
+        manifest
+            .versions
+            .insert(file.name.to_string(), file.current);
+    }
+
+    manifest.save(manifest_path)?;
+    Ok(steps)
+}
+
+/// Run pending migrations at startup; failures are logged, not fatal
+pub fn migrate_on_startup() {
+    match migrate(&state_files(), &manifest_path(), false) {
+        Ok(steps) => {
+            for step in steps {
+                tracing::info!(
+                    "Migrated {} v{} -> v{}: {}",
+                    step.file,
+                    step.from,
+                    step.to,
+                    step.description
+                );
+            }
+        }
+        Err(e) => tracing::warn!("State migration failed: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_flag(mut value: Value) -> anyhow::Result<Value> {
+        value["migrated"] = Value::Bool(true);
+        Ok(value)
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            from: 0,
+            description: "adopt",
+            apply: adopt_unversioned,
+        },
+        Migration {
+            from: 1,
+            description: "add flag",
+            apply: add_flag,
+        },
+    ];
+
+    fn test_file(dir: &Path) -> StateFile {
+        StateFile {
+            name: "usage_stats",
+            path: dir.join("usage_stats.json"),
+            current: 2,
+            migrations: TEST_MIGRATIONS,
+        }
+    }
+
+    #[test]
+    fn test_dry_run_plans_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![test_file(dir.path())];
+        std::fs::write(&files[0].path, r#"{"requests_today": 3}"#).unwrap();
+        let manifest = dir.path().join(MANIFEST_FILE);
+
+        let steps = migrate(&files, &manifest, true).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!((steps[0].from, steps[1].to), (0, 2));
+        assert!(!manifest.exists());
+        assert!(!std::fs::read_to_string(&files[0].path)
+            .unwrap()
+            .contains("migrated"));
+    }
+
+    #[test]
+    fn test_migrate_applies_chain_backs_up_and_records_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![test_file(dir.path())];
+        std::fs::write(&files[0].path, r#"{"requests_today": 3}"#).unwrap();
+        let manifest = dir.path().join(MANIFEST_FILE);
+
+        migrate(&files, &manifest, false).unwrap();
+
+        let upgraded: Value =
+            serde_json::from_str(&std::fs::read_to_string(&files[0].path).unwrap()).unwrap();
+        assert_eq!(upgraded["migrated"], true);
+        assert_eq!(upgraded["requests_today"], 3);
+        assert!(dir.path().join("usage_stats.json.v0.bak").exists());
+        assert_eq!(
+            Manifest::load(&manifest)
+                .unwrap()
+                .versions
+                .get("usage_stats"),
+            Some(&2)
+        );
+
+        // Second run is a no-op
+        assert!(migrate(&files, &manifest, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![test_file(dir.path())];
+        std::fs::write(&files[0].path, "{}").unwrap();
+
+        let mut manifest = Manifest::default();
+        manifest.versions.insert("usage_stats".into(), 9);
+        let err = plan(&files, &manifest).unwrap_err();
+        assert!(err.to_string().contains("newer than this shimmy supports"));
+    }
+}
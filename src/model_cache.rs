@@ -0,0 +1,623 @@
+//! Models kept loaded between requests.
+//!
+//! By default a model is loaded when a request arrives and freed when the
+//! request ends. With a keep-alive it stays in memory afterwards, so the next
+//! request for it skips the load, and is unloaded once it has gone unused for
+//! that long. The server-wide keep-alive comes from `serve --model-ttl`
+//! (`SHIMMY_MODEL_TTL`, `model_ttl` in `shimmy.toml`); a request can override
+//! it for its model with `keep_alive`, as Ollama clients do.
+//!
+//! Requests for a kept model share one copy; the backends serialize access to
+//! their context, so concurrent requests take turns instead of each loading
+//! their own.
+//!
+//! Several models can be kept at once, up to a memory budget
+//! (`SHIMMY_MODEL_MEMORY_MB`, by default the machine's RAM plus VRAM). A load
+//! that would go over it first unloads the least recently used idle models;
+//! a model that still doesn't fit is served without being kept.
+
+use crate::engine::{
+    gguf, GenOptions, Generation, LoadedModel, ModelMemoryUsage, ModelSpec, TokenDetail,
+};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const MODEL_TTL_ENV: &str = "SHIMMY_MODEL_TTL";
+pub const MODEL_MEMORY_ENV: &str = "SHIMMY_MODEL_MEMORY_MB";
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a model stays loaded after its last request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "KeepAliveValue", into = "f64")]
+pub enum KeepAlive {
+    /// Unload after this much idle time; zero unloads when the request ends
+    Idle(Duration),
+    /// Never unload on its own
+    Forever,
+}
+
+/// `keep_alive` as clients send it: seconds, or a duration string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeepAliveValue {
+    Seconds(f64),
+    Text(String),
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        KeepAlive::Idle(Duration::ZERO)
+    }
+}
+
+impl KeepAlive {
+    /// Parse `"30s"`, `"10m"`, `"2h"` or a bare number of `bare_unit`s;
+    /// any negative value means forever
+    pub fn parse(text: &str, bare_unit: Duration) -> Result<Self> {
+        let text = text.trim();
+        let split = text
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let unit = match unit {
+            "" => bare_unit,
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_secs(60),
+            "h" => Duration::from_secs(3600),
+            _ => bail!("unknown unit in keep-alive {:?}; use s, m or h", text),
+        };
+        match number.trim().parse::<f64>() {
+            Ok(n) => Self::scaled(n, unit),
+            Err(_) => bail!("invalid keep-alive {:?}; expected e.g. \"10m\" or -1", text),
+        }
+    }
+
+    fn scaled(n: f64, unit: Duration) -> Result<Self> {
+        if n < 0.0 {
+            return Ok(KeepAlive::Forever);
+        }
+        match Duration::try_from_secs_f64(unit.as_secs_f64() * n) {
+            Ok(ttl) => Ok(KeepAlive::Idle(ttl)),
+            Err(_) => bail!("keep-alive is too long; use -1 to keep a model loaded"),
+        }
+    }
+
+    /// The flag, else `SHIMMY_MODEL_TTL`; bare numbers are minutes
+    pub fn resolve(flag: Option<&str>) -> Result<Self> {
+        let value = flag
+            .map(str::to_string)
+            .or_else(|| std::env::var(MODEL_TTL_ENV).ok())
+            .filter(|v| !v.trim().is_empty());
+        match value {
+            Some(v) => Self::parse(&v, Duration::from_secs(60)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Whether a model is freed as soon as its request ends
+    pub fn unloads_immediately(self) -> bool {
+        self == KeepAlive::Idle(Duration::ZERO)
+    }
+
+    fn expired(self, idle: Duration) -> bool {
+        match self {
+            KeepAlive::Idle(ttl) => idle >= ttl,
+            KeepAlive::Forever => false,
+        }
+    }
+}
+
+impl TryFrom<KeepAliveValue> for KeepAlive {
+    type Error = String;
+
+    fn try_from(value: KeepAliveValue) -> std::result::Result<Self, Self::Error> {
+        let parsed = match value {
+            KeepAliveValue::Seconds(n) => Self::scaled(n, Duration::from_secs(1)),
+            KeepAliveValue::Text(text) => Self::parse(&text, Duration::from_secs(1)),
+        };
+        parsed.map_err(|e| e.to_string())
+    }
+}
+
+impl From<KeepAlive> for f64 {
+    fn from(keep_alive: KeepAlive) -> f64 {
+        match keep_alive {
+            KeepAlive::Idle(ttl) => ttl.as_secs_f64(),
+            KeepAlive::Forever => -1.0,
+        }
+    }
+}
+
+impl std::fmt::Display for KeepAlive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeepAlive::Idle(ttl) if ttl.is_zero() => write!(f, "unload after each request"),
+            KeepAlive::Idle(ttl) => write!(f, "unload after {}s idle", ttl.as_secs()),
+            KeepAlive::Forever => write!(f, "keep loaded"),
+        }
+    }
+}
+
+/// Bytes a model is expected to take once loaded: the runtime estimate for
+/// its files (base plus LoRA), or 0 when they can't be read
+pub fn estimate_bytes(spec: &ModelSpec) -> u64 {
+    let file_bytes = gguf::total_size(&spec.base_path).unwrap_or(0)
+        + spec
+            .lora_path
+            .as_deref()
+            .and_then(gguf::total_size)
+            .unwrap_or(0);
+    let estimate = crate::util::memory::estimate_memory_requirements(file_bytes);
+    (estimate.estimated_runtime_gb * 1_024_000_000.0) as u64
+}
+
+/// `SHIMMY_MODEL_MEMORY_MB` (`0` for no budget), else the machine's RAM plus
+/// VRAM when they can be read
+pub fn memory_budget() -> Option<u64> {
+    if let Some(mb) = std::env::var(MODEL_MEMORY_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+    {
+        return (mb > 0).then_some(mb.saturating_mul(1024 * 1024));
+    }
+    let ram = crate::util::memory::sample_memory()?.total_bytes;
+    Some(ram + crate::util::memory::detect_vram_bytes().unwrap_or(0))
+}
+
+struct Entry {
+    model: Arc<dyn LoadedModel>,
+    keep_alive: KeepAlive,
+    last_used: Instant,
+    /// Reported by the backend, else estimated from the files
+    bytes: u64,
+}
+
+type Entries = Arc<Mutex<HashMap<String, Entry>>>;
+
+/// Loaded models by registry name, with their keep-alive
+#[derive(Clone, Default)]
+pub struct ModelCache {
+    default_keep_alive: KeepAlive,
+    /// Bytes kept models may take together; `None` for no limit
+    budget: Option<u64>,
+    entries: Entries,
+    /// Held while loading a model to keep, so a burst of requests for it
+    /// loads it once
+    loading: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl ModelCache {
+    pub fn new(default_keep_alive: KeepAlive, budget: Option<u64>) -> Self {
+        Self {
+            default_keep_alive,
+            budget,
+            ..Self::default()
+        }
+    }
+
+    pub fn default_keep_alive(&self) -> KeepAlive {
+        self.default_keep_alive
+    }
+
+    pub fn budget(&self) -> Option<u64> {
+        self.budget
+    }
+
+    /// The kept copy of `name`, else the result of `load`, kept for
+    /// `keep_alive` (the server default when `None`); `estimate` is the
+    /// model's expected size, see [`estimate_bytes`]
+    pub async fn get_or_load<F, Fut>(
+        &self,
+        name: &str,
+        estimate: u64,
+        keep_alive: Option<KeepAlive>,
+        load: F,
+    ) -> Result<Box<dyn LoadedModel>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Box<dyn LoadedModel>>>,
+    {
+        let keep_alive = keep_alive.unwrap_or(self.default_keep_alive);
+        if let Some(model) = self.checkout(name, keep_alive) {
+            return Ok(model);
+        }
+        if keep_alive.unloads_immediately() {
+            self.make_room(name, estimate);
+            return load().await;
+        }
+        let _loading = self.loading.lock().await;
+        if let Some(model) = self.checkout(name, keep_alive) {
+            return Ok(model);
+        }
+        let fits = self.make_room(name, estimate);
+        let model = load().await?;
+        if !fits {
+            tracing::warn!(
+                "Not keeping {} loaded: it doesn't fit the {} budget",
+                name,
+                MODEL_MEMORY_ENV
+            );
+            return Ok(model);
+        }
+        let bytes = model
+            .memory_usage()
+            .map(|usage| usage.total_bytes())
+            .filter(|&bytes| bytes > 0)
+            .unwrap_or(estimate);
+        let model: Arc<dyn LoadedModel> = Arc::from(model);
+        lock(&self.entries).insert(
+            name.to_string(),
+            Entry {
+                model: Arc::clone(&model),
+                keep_alive,
+                last_used: Instant::now(),
+                bytes,
+            },
+        );
+        tracing::info!("Keeping {} loaded ({})", name, keep_alive);
+        Ok(self.lease(name, model))
+    }
+
+    /// Unload least recently used idle models until `estimate` more bytes
+    /// fit the budget; whether they do
+    fn make_room(&self, name: &str, estimate: u64) -> bool {
+        let Some(budget) = self.budget else {
+            return true;
+        };
+        if estimate > budget {
+            return false;
+        }
+        let mut evicted = Vec::new();
+        let fits = {
+            let mut entries = lock(&self.entries);
+            loop {
+                let resident: u64 = entries.values().map(|entry| entry.bytes).sum();
+                if resident.saturating_add(estimate) <= budget {
+                    break true;
+                }
+                let idle = entries
+                    .iter()
+                    .filter(|(_, entry)| Arc::strong_count(&entry.model) == 1)
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(name, _)| name.clone());
+                let Some(lru) = idle else {
+                    break false;
+                };
+                evicted.extend(entries.remove(&lru).map(|entry| (lru, entry)));
+            }
+        };
+        // Models are freed here, outside the lock
+        for (lru, _) in evicted {
+            tracing::info!("Unloaded {} to make room for {}", lru, name);
+        }
+        fits
+    }
+
+    /// Bytes taken by kept models
+    pub fn resident_bytes(&self) -> u64 {
+        lock(&self.entries).values().map(|entry| entry.bytes).sum()
+    }
+
+    /// Hand out the kept copy of `name`, taking on the request's keep-alive
+    fn checkout(&self, name: &str, keep_alive: KeepAlive) -> Option<Box<dyn LoadedModel>> {
+        let model = {
+            let mut entries = lock(&self.entries);
+            let entry = entries.get_mut(name)?;
+            entry.keep_alive = keep_alive;
+            entry.last_used = Instant::now();
+            Arc::clone(&entry.model)
+        };
+        Some(self.lease(name, model))
+    }
+
+    fn lease(&self, name: &str, model: Arc<dyn LoadedModel>) -> Box<dyn LoadedModel> {
+        Box::new(Lease {
+            model: Some(model),
+            name: name.to_string(),
+            entries: Arc::clone(&self.entries),
+        })
+    }
+
+    /// Whether `name` is loaded and kept
+    pub fn contains(&self, name: &str) -> bool {
+        lock(&self.entries).contains_key(name)
+    }
+
+    /// Stop keeping `name`; it is freed once requests using it finish
+    pub fn unload(&self, name: &str) -> bool {
+        let removed = lock(&self.entries).remove(name);
+        removed.is_some()
+    }
+
+    /// Unload models idle past their keep-alive, returning their names
+    pub fn sweep(&self, now: Instant) -> Vec<String> {
+        let expired: Vec<(String, Entry)> = {
+            let mut entries = lock(&self.entries);
+            let names: Vec<String> = entries
+                .iter()
+                .filter(|(_, entry)| {
+                    Arc::strong_count(&entry.model) == 1
+                        && entry
+                            .keep_alive
+                            .expired(now.saturating_duration_since(entry.last_used))
+                })
+                .map(|(name, _)| name.clone())
+                .collect();
+            names
+                .into_iter()
+                .filter_map(|name| entries.remove(&name).map(|entry| (name, entry)))
+                .collect()
+        };
+        // Models are freed here, outside the lock
+        expired.into_iter().map(|(name, _)| name).collect()
+    }
+
+    /// Check for idle models every few seconds for the life of the server
+    pub fn spawn_reaper(&self) {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                tick.tick().await;
+                for name in cache.sweep(Instant::now()) {
+                    tracing::info!("Unloaded idle model {}", name);
+                }
+            }
+        });
+    }
+}
+
+fn lock(entries: &Entries) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+    entries.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// One request's use of a kept model; returning it restarts the idle clock
+struct Lease {
+    model: Option<Arc<dyn LoadedModel>>,
+    name: String,
+    entries: Entries,
+}
+
+impl Lease {
+    fn model(&self) -> &dyn LoadedModel {
+        self.model.as_deref().expect("lease is live until dropped")
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let Some(model) = self.model.take() else {
+            return;
+        };
+        let mut entries = lock(&self.entries);
+        let Some(entry) = entries.get_mut(&self.name) else {
+            return;
+        };
+        if !Arc::ptr_eq(&entry.model, &model) {
+            return;
+        }
+        entry.last_used = Instant::now();
+        drop(model);
+        // The last request asked for no keep-alive: free it now rather than
+        // at the next sweep
+        if entry.keep_alive.unloads_immediately() && Arc::strong_count(&entry.model) == 1 {
+            let entry = entries.remove(&self.name);
+            drop(entries);
+            drop(entry);
+        }
+    }
+}
+
+#[async_trait]
+impl LoadedModel for Lease {
+    async fn generate(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<String> {
+        self.model().generate(prompt, opts, on_token).await
+    }
+
+    async fn generate_with_finish(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<Generation> {
+        self.model()
+            .generate_with_finish(prompt, opts, on_token)
+            .await
+    }
+
+    async fn generate_detailed(
+        &self,
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(TokenDetail) + Send>>,
+    ) -> Result<Generation> {
+        self.model().generate_detailed(prompt, opts, on_token).await
+    }
+
+    async fn generate_vision(
+        &self,
+        images: &[&[u8]],
+        prompt: &str,
+        opts: GenOptions,
+        on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> Result<String> {
+        self.model()
+            .generate_vision(images, prompt, opts, on_token)
+            .await
+    }
+
+    fn memory_usage(&self) -> Option<ModelMemoryUsage> {
+        self.model().memory_usage()
+    }
+
+    fn token_warnings(&self) -> Vec<crate::engine::special_tokens::TokenWarning> {
+        self.model().token_warnings()
+    }
+
+    fn count_tokens(&self, text: &str) -> Option<usize> {
+        self.model().count_tokens(text)
+    }
+
+    fn token_pieces(&self, text: &str) -> Option<Vec<String>> {
+        self.model().token_pieces(text)
+    }
+
+    fn supports_adapters(&self) -> bool {
+        self.model().supports_adapters()
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.model().embed(inputs).await
+    }
+
+    async fn embed_images(&self, images: &[&[u8]]) -> Result<Vec<Vec<f32>>> {
+        self.model().embed_images(images).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Echo;
+
+    #[async_trait]
+    impl LoadedModel for Echo {
+        async fn generate(
+            &self,
+            prompt: &str,
+            _opts: GenOptions,
+            _on_token: Option<Box<dyn FnMut(String) + Send>>,
+        ) -> Result<String> {
+            Ok(prompt.to_string())
+        }
+    }
+
+    async fn load(cache: &ModelCache, keep_alive: Option<KeepAlive>, loads: &AtomicUsize) {
+        let model = cache
+            .get_or_load("echo", 0, keep_alive, || async {
+                loads.fetch_add(1, Ordering::SeqCst);
+                Ok(Box::new(Echo) as Box<dyn LoadedModel>)
+            })
+            .await
+            .unwrap();
+        let text = model.generate("hi", GenOptions::default(), None).await;
+        assert_eq!(text.unwrap(), "hi");
+    }
+
+    #[test]
+    fn test_keep_alive_parsing() {
+        let minute = Duration::from_secs(60);
+        let parse = |text: &str| KeepAlive::parse(text, minute).unwrap();
+        assert_eq!(parse("5"), KeepAlive::Idle(Duration::from_secs(300)));
+        assert_eq!(parse("90s"), KeepAlive::Idle(Duration::from_secs(90)));
+        assert_eq!(parse("1.5h"), KeepAlive::Idle(Duration::from_secs(5400)));
+        assert_eq!(parse("0"), KeepAlive::default());
+        assert_eq!(parse("-1"), KeepAlive::Forever);
+        assert!(KeepAlive::parse("5d", minute).is_err());
+        assert!(KeepAlive::parse("soon", minute).is_err());
+
+        // JSON numbers are seconds, as with Ollama
+        let json = |value: serde_json::Value| serde_json::from_value::<KeepAlive>(value);
+        assert_eq!(
+            json(serde_json::json!(30)).unwrap(),
+            KeepAlive::Idle(Duration::from_secs(30))
+        );
+        assert_eq!(
+            json(serde_json::json!("10m")).unwrap(),
+            KeepAlive::Idle(Duration::from_secs(600))
+        );
+        assert_eq!(json(serde_json::json!(-1)).unwrap(), KeepAlive::Forever);
+        assert!(json(serde_json::json!("later")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_kept_models_are_shared_until_idle() {
+        let loads = AtomicUsize::new(0);
+        let cache = ModelCache::new(KeepAlive::Idle(Duration::from_secs(60)), None);
+        load(&cache, None, &loads).await;
+        load(&cache, None, &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(cache.contains("echo"));
+
+        assert!(cache.sweep(Instant::now()).is_empty());
+        let later = Instant::now() + Duration::from_secs(61);
+        assert_eq!(cache.sweep(later), vec!["echo".to_string()]);
+        assert!(!cache.contains("echo"));
+
+        load(&cache, Some(KeepAlive::Forever), &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert!(cache.sweep(later + Duration::from_secs(86_400)).is_empty());
+        assert!(cache.unload("echo"));
+        assert!(!cache.unload("echo"));
+    }
+
+    #[tokio::test]
+    async fn test_models_in_use_are_not_unloaded() {
+        let cache = ModelCache::new(KeepAlive::Idle(Duration::from_secs(1)), None);
+        let model = cache
+            .get_or_load("echo", 0, None, || async {
+                Ok(Box::new(Echo) as Box<dyn LoadedModel>)
+            })
+            .await
+            .unwrap();
+        let later = Instant::now() + Duration::from_secs(5);
+        assert!(cache.sweep(later).is_empty());
+        drop(model);
+        assert_eq!(cache.sweep(later + Duration::from_secs(5)).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_zero_keep_alive_unloads_after_the_request() {
+        let loads = AtomicUsize::new(0);
+        let cache = ModelCache::default();
+        load(&cache, None, &loads).await;
+        load(&cache, None, &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert!(!cache.contains("echo"));
+
+        // A request with `keep_alive: 0` frees a kept model once it's done
+        load(&cache, Some(KeepAlive::Forever), &loads).await;
+        assert!(cache.contains("echo"));
+        load(&cache, Some(KeepAlive::default()), &loads).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+        assert!(!cache.contains("echo"));
+    }
+
+    #[tokio::test]
+    async fn test_budget_evicts_least_recently_used() {
+        let cache = ModelCache::new(KeepAlive::Forever, Some(100));
+        let echo = || async { Ok(Box::new(Echo) as Box<dyn LoadedModel>) };
+        drop(cache.get_or_load("a", 40, None, echo).await.unwrap());
+        drop(cache.get_or_load("b", 40, None, echo).await.unwrap());
+        drop(cache.get_or_load("a", 40, None, echo).await.unwrap());
+        assert_eq!(cache.resident_bytes(), 80);
+
+        // "b" went unused longest
+        drop(cache.get_or_load("c", 40, None, echo).await.unwrap());
+        assert!(cache.contains("a") && cache.contains("c") && !cache.contains("b"));
+
+        // Models in use stay; a model that can't fit next to them isn't kept
+        let a = cache.get_or_load("a", 40, None, echo).await.unwrap();
+        let c = cache.get_or_load("c", 40, None, echo).await.unwrap();
+        drop(cache.get_or_load("d", 40, None, echo).await.unwrap());
+        assert!(!cache.contains("d"));
+        drop((a, c));
+
+        drop(cache.get_or_load("huge", 500, None, echo).await.unwrap());
+        assert!(!cache.contains("huge"));
+        assert_eq!(cache.resident_bytes(), 80);
+    }
+}
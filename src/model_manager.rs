@@ -365,6 +365,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         }
     }
 
@@ -410,6 +412,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         let result = manager.load_model("test-model".to_string(), spec).await;
@@ -464,6 +468,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         manager
@@ -0,0 +1,252 @@
+//! Model names as users type them, mapped onto registry names.
+//!
+//! The registry knows models under the names they were registered or
+//! discovered with: `phi-3-mini-q4-k-m` for a GGUF file,
+//! `registry.ollama.ai/library/llama3/8b` for an Ollama blob, whatever name
+//! was given to `POST /api/models/register`. Clients send those and more:
+//!
+//! - Ollama style: `llama3`, `llama3:8b`, `llama3:latest`,
+//!   `registry.ollama.ai/library/llama3:8b`, `ollama://llama3`
+//! - Hugging Face style: `bartowski/Qwen2.5-7B-Instruct-GGUF:Q4_K_M`, with or
+//!   without `hf.co/`, `huggingface.co/` or `https://`
+//! - file paths: `/models/Phi-3-mini-Q4_K_M.gguf`, `./phi3.gguf`
+//!
+//! [`candidates`] lists the registry names an input may stand for, most
+//! literal first, and [`resolve`] picks the first one that exists. Every
+//! route goes through `Registry::resolve_name`, so a name that works on one
+//! API works on all of them.
+
+use std::path::{Path, PathBuf};
+
+/// Prefixes that only say where a name came from
+const SCHEMES: &[&str] = &["ollama://", "hf://", "https://", "http://"];
+const HOSTS: &[&str] = &["registry.ollama.ai/", "huggingface.co/", "hf.co/"];
+
+/// Ollama's registry, as discovered names spell it
+const OLLAMA_REGISTRY: &str = "registry.ollama.ai";
+
+/// File extensions of model weights
+const MODEL_EXTENSIONS: &[&str] = &["gguf", "safetensors", "bin"];
+
+/// Lowercase with `_` and spaces turned into `-`, the way discovered files
+/// are named
+pub fn slug(name: &str) -> String {
+    name.replace(['_', ' '], "-").to_lowercase()
+}
+
+/// The name auto-discovery gives a model file
+pub fn from_file_name(file_name: &str) -> String {
+    let stem = match file_name.rfind('.') {
+        Some(pos) => &file_name[..pos],
+        None => file_name,
+    };
+    slug(stem)
+}
+
+/// The input as a file path, when it names a model file
+pub fn as_path(input: &str) -> Option<PathBuf> {
+    let input = input.trim();
+    let path = Path::new(input);
+    let has_extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| MODEL_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()));
+    let looks_like_path = input.starts_with(['/', '.', '~']) || input.contains('\\');
+    (has_extension || looks_like_path).then(|| path.to_path_buf())
+}
+
+/// The input without scheme, host and `:latest` tag, e.g.
+/// `registry.ollama.ai/library/minicpm-v:latest` becomes `minicpm-v`
+pub fn normalize(input: &str) -> String {
+    let (name, tag) = split_tag(strip_prefixes(input.trim()));
+    match tag {
+        Some(tag) if !tag.eq_ignore_ascii_case("latest") => format!("{}:{}", name, tag),
+        _ => name.to_string(),
+    }
+}
+
+/// Registry names the input may refer to, most literal first
+pub fn candidates(input: &str) -> Vec<String> {
+    let input = input.trim();
+    let mut out = vec![input.to_string()];
+    if as_path(input).is_some() {
+        // Either separator, so Windows paths work from any client
+        if let Some(file_name) = input.rsplit(['/', '\\']).next() {
+            out.push(from_file_name(file_name));
+        }
+        return dedup(out);
+    }
+
+    let stripped = strip_prefixes(input);
+    let (name, tag) = split_tag(stripped);
+    let tag = tag.filter(|t| !t.eq_ignore_ascii_case("latest"));
+    out.push(stripped.to_string());
+    out.push(normalize(input));
+    out.push(name.to_string());
+
+    // Ollama blobs are discovered as <registry>/<namespace>/<model>/<tag>
+    let namespaced = if name.contains('/') {
+        name.to_string()
+    } else {
+        format!("library/{}", name)
+    };
+    out.push(format!(
+        "{}/{}/{}",
+        OLLAMA_REGISTRY,
+        namespaced,
+        tag.unwrap_or("latest")
+    ));
+
+    // Hugging Face repos hold files named after the repo and quantization
+    if let Some((_, repo)) = name.split_once('/') {
+        let repo = repo.trim_end_matches("-GGUF").trim_end_matches("-gguf");
+        if let Some(tag) = tag {
+            out.push(slug(&format!("{}-{}", repo, tag)));
+        }
+        out.push(slug(repo));
+    }
+    dedup(out)
+}
+
+/// The first candidate for `input` among `known` names, matching exactly,
+/// or else ignoring case when that is unambiguous
+pub fn resolve<'a>(input: &str, known: &[&'a str]) -> Option<&'a str> {
+    let candidates = candidates(input);
+    candidates
+        .iter()
+        .find_map(|c| known.iter().find(|k| **k == c.as_str()))
+        .or_else(|| {
+            candidates.iter().find_map(|c| {
+                let mut matches = known.iter().filter(|k| k.eq_ignore_ascii_case(c));
+                match (matches.next(), matches.next()) {
+                    (Some(only), None) => Some(only),
+                    _ => None,
+                }
+            })
+        })
+        .copied()
+}
+
+fn strip_prefixes(mut s: &str) -> &str {
+    for scheme in SCHEMES {
+        if let Some(rest) = s.strip_prefix(scheme) {
+            s = rest;
+            break;
+        }
+    }
+    for host in HOSTS {
+        if let Some(rest) = s.strip_prefix(host) {
+            s = rest;
+            break;
+        }
+    }
+    s.strip_prefix("library/").unwrap_or(s).trim_matches('/')
+}
+
+/// `name:tag` or the older `name/latest`; a colon before the last `/` is not
+/// a tag
+fn split_tag(s: &str) -> (&str, Option<&str>) {
+    if let Some(name) = s.strip_suffix("/latest") {
+        return (name, Some("latest"));
+    }
+    let last = s.rfind('/').map_or(0, |i| i + 1);
+    match s[last..].rsplit_once(':') {
+        Some((name, tag)) if !tag.is_empty() => (&s[..last + name.len()], Some(tag)),
+        _ => (s, None),
+    }
+}
+
+fn dedup(list: Vec<String>) -> Vec<String> {
+    let mut out: Vec<String> = Vec::with_capacity(list.len());
+    for item in list {
+        if !item.is_empty() && !out.contains(&item) {
+            out.push(item);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN: &[&str] = &[
+        "phi-3-mini-q4-k-m",
+        "qwen2.5-7b-instruct-q4-k-m",
+        "registry.ollama.ai/library/llama3/8b",
+        "registry.ollama.ai/library/llama3/latest",
+        "registry.ollama.ai/acme/coder/latest",
+        "My-Model",
+        "minicpm-v",
+    ];
+
+    #[test]
+    fn test_resolves_forms_users_type() {
+        let cases = [
+            ("phi-3-mini-q4-k-m", "phi-3-mini-q4-k-m"),
+            ("  phi-3-mini-q4-k-m ", "phi-3-mini-q4-k-m"),
+            ("PHI-3-MINI-Q4-K-M", "phi-3-mini-q4-k-m"),
+            ("my-model", "My-Model"),
+            ("llama3", "registry.ollama.ai/library/llama3/latest"),
+            ("llama3:latest", "registry.ollama.ai/library/llama3/latest"),
+            ("llama3:8b", "registry.ollama.ai/library/llama3/8b"),
+            (
+                "registry.ollama.ai/library/llama3:8b",
+                "registry.ollama.ai/library/llama3/8b",
+            ),
+            (
+                "ollama://llama3",
+                "registry.ollama.ai/library/llama3/latest",
+            ),
+            ("acme/coder", "registry.ollama.ai/acme/coder/latest"),
+            (
+                "bartowski/Qwen2.5-7B-Instruct-GGUF:Q4_K_M",
+                "qwen2.5-7b-instruct-q4-k-m",
+            ),
+            (
+                "hf.co/bartowski/Qwen2.5-7B-Instruct-GGUF:Q4_K_M",
+                "qwen2.5-7b-instruct-q4-k-m",
+            ),
+            (
+                "https://huggingface.co/bartowski/Qwen2.5-7B-Instruct-GGUF:Q4_K_M",
+                "qwen2.5-7b-instruct-q4-k-m",
+            ),
+            ("/models/Phi-3-mini-Q4_K_M.gguf", "phi-3-mini-q4-k-m"),
+            (r"C:\models\phi-3-mini-q4_k_m.gguf", "phi-3-mini-q4-k-m"),
+            ("registry.ollama.ai/library/minicpm-v:latest", "minicpm-v"),
+            ("minicpm-v:latest", "minicpm-v"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(resolve(input, KNOWN), Some(expected), "{}", input);
+        }
+        assert_eq!(resolve("llama3:70b", KNOWN), None);
+        assert_eq!(resolve("mistral", KNOWN), None);
+    }
+
+    #[test]
+    fn test_case_insensitive_match_must_be_unique() {
+        let known = ["Model", "model-a", "MODEL-A"];
+        assert_eq!(resolve("model", &known), Some("Model"));
+        assert_eq!(resolve("Model-a", &known), None);
+    }
+
+    #[test]
+    fn test_normalize_and_file_names() {
+        assert_eq!(
+            normalize("registry.ollama.ai/library/minicpm-v:latest"),
+            "minicpm-v"
+        );
+        assert_eq!(
+            normalize("registry.ollama.ai/library/minicpm-v/latest"),
+            "minicpm-v"
+        );
+        assert_eq!(normalize("hf.co/org/repo:Q8_0"), "org/repo:Q8_0");
+        assert_eq!(normalize("llama3:8b"), "llama3:8b");
+        assert_eq!(
+            from_file_name("Phi 3_mini.Q4_K_M.gguf"),
+            "phi-3-mini.q4-k-m"
+        );
+        assert!(as_path("llama3:8b").is_none());
+        assert!(as_path("./weights/model").is_some());
+    }
+}
@@ -1,7 +1,30 @@
-use super::engine::ModelSpec;
+use super::engine::{check_gguf_magic, gguf, LoadError, ModelSpec};
 use crate::auto_discovery::{DiscoveredModel, ModelAutoDiscovery};
+use crate::util::memory::{estimate_memory_requirements, MemoryEstimate};
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Models added through `POST /api/models/register`, kept across restarts
+pub const REGISTERED_MODELS_FILE: &str = "registered_models.json";
+
+/// Default location of [`REGISTERED_MODELS_FILE`]
+pub fn registered_models_path() -> PathBuf {
+    crate::util::paths::data_dir().join(REGISTERED_MODELS_FILE)
+}
+
+/// On-disk form of the models registered at runtime
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RegisteredModels {
+    models: Vec<ModelEntry>,
+    /// LoRA adapters by base model, then adapter name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    adapters: BTreeMap<String, BTreeMap<String, PathBuf>>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelEntry {
@@ -11,12 +34,84 @@ pub struct ModelEntry {
     pub template: Option<String>,
     pub ctx_len: Option<usize>,
     pub n_threads: Option<i32>,
+    /// Layers to offload to the GPU; `None` uses the backend's default
+    #[serde(default)]
+    pub n_gpu_layers: Option<u32>,
+    /// Multimodal projector (an `mmproj` GGUF) for vision models
+    #[serde(default)]
+    pub mmproj_path: Option<PathBuf>,
 }
 
 #[derive(Default, Clone)]
 pub struct Registry {
     inner: HashMap<String, ModelEntry>,
     pub discovered_models: HashMap<String, DiscoveredModel>,
+    /// Runtime memory estimates keyed by model name, computed when a model is
+    /// registered or discovered
+    memory_estimates: HashMap<String, MemoryEstimate>,
+    /// Models whose GGUF header marks them as embedding models
+    embedding_models: HashSet<String>,
+    /// Models refused at registration, with the reason
+    rejected: HashMap<String, LoadError>,
+    /// Models registered while serving; shared between clones so every
+    /// handle sees them
+    runtime: Arc<RwLock<RuntimeModels>>,
+}
+
+#[derive(Default)]
+struct RuntimeModels {
+    entries: HashMap<String, ModelEntry>,
+    memory_estimates: HashMap<String, MemoryEstimate>,
+    embedding_models: HashSet<String>,
+    /// Named LoRA adapters by base model, selectable per request
+    adapters: HashMap<String, BTreeMap<String, PathBuf>>,
+}
+
+/// Why [`Registry::register_runtime`] refused a model
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum RegisterError {
+    #[error("A configured or discovered model is already named '{0}'")]
+    NameTaken(String),
+
+    #[error("No model named '{0}'")]
+    UnknownModel(String),
+
+    #[error(transparent)]
+    Load(#[from] LoadError),
+}
+
+/// A registration that passed [`check_entry`]
+struct CheckedEntry {
+    entry: ModelEntry,
+    memory_estimate: Option<MemoryEstimate>,
+    embedding: bool,
+}
+
+/// Validate a model file for registration
+///
+/// Any shard of a split GGUF resolves to the set's first shard, as long as
+/// every sibling is present.
+fn check_entry(mut e: ModelEntry) -> Result<CheckedEntry, LoadError> {
+    let shards = gguf::resolve_split(&e.base_path)
+        .and_then(|shards| gguf::check_architecture(&shards[0]).map(|_| shards))?;
+    // Projectors are `clip` GGUFs, which only the vision path can load
+    if let Some(mmproj) = &e.mmproj_path {
+        check_gguf_magic(mmproj)?;
+    }
+    e.base_path = shards[0].clone();
+    Ok(CheckedEntry {
+        memory_estimate: gguf::total_size(&e.base_path).map(estimate_memory_requirements),
+        embedding: gguf::is_embedding_model(&e.base_path).unwrap_or(false),
+        entry: e,
+    })
+}
+
+fn read_registered(path: &Path) -> anyhow::Result<RegisteredModels> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(serde_json::from_slice(&data)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RegisteredModels::default()),
+        Err(e) => Err(e.into()),
+    }
 }
 
 // Alias for backward compatibility and mission expectations
@@ -27,6 +122,10 @@ impl Registry {
         Self {
             inner: HashMap::new(),
             discovered_models: HashMap::new(),
+            memory_estimates: HashMap::new(),
+            embedding_models: HashSet::new(),
+            rejected: HashMap::new(),
+            runtime: Arc::default(),
         }
     }
 
@@ -39,8 +138,24 @@ impl Registry {
     pub fn refresh_discovered_models(&mut self) {
         let discovery = ModelAutoDiscovery::new();
         if let Ok(models) = discovery.discover_models() {
+            for name in self.discovered_models.keys() {
+                if !self.inner.contains_key(name) {
+                    self.memory_estimates.remove(name);
+                    self.embedding_models.remove(name);
+                }
+            }
             self.discovered_models.clear();
             for model in models {
+                if let Err(e) = gguf::check_architecture(&model.path) {
+                    tracing::warn!("Skipping model '{}': {}", model.name, e);
+                    self.rejected.insert(model.name.clone(), e);
+                    continue;
+                }
+                self.memory_estimates.insert(
+                    model.name.clone(),
+                    estimate_memory_requirements(model.size_bytes),
+                );
+                self.mark_embedding(&model.name, &model.path);
                 self.discovered_models.insert(model.name.clone(), model);
             }
         }
@@ -57,6 +172,8 @@ impl Registry {
                     template: Some(self.infer_template(name)),
                     ctx_len: Some(4096),
                     n_threads: None,
+                    n_gpu_layers: None,
+                    mmproj_path: discovered.mmproj_path.clone(),
                 };
                 self.inner.insert(name.clone(), entry);
             }
@@ -75,8 +192,206 @@ impl Registry {
         }
     }
 
+    /// Register a model, rejecting GGUF files no compiled backend can run
+    ///
+    /// Any shard of a split GGUF registers the set under its first shard, as
+    /// long as every sibling is present.
+    pub fn try_register(&mut self, e: ModelEntry) -> Result<(), LoadError> {
+        let name = e.name.clone();
+        let checked = match check_entry(e) {
+            Ok(checked) => checked,
+            Err(err) => {
+                self.rejected.insert(name, err.clone());
+                return Err(err);
+            }
+        };
+        self.rejected.remove(&name);
+        if let Some(estimate) = checked.memory_estimate {
+            self.memory_estimates.insert(name.clone(), estimate);
+        }
+        if checked.embedding {
+            self.embedding_models.insert(name.clone());
+        } else {
+            self.embedding_models.remove(&name);
+        }
+        self.inner.insert(name, checked.entry);
+        Ok(())
+    }
+
+    /// Register a model while serving, through a shared handle
+    ///
+    /// Names of configured or discovered models are taken; registering a
+    /// name again replaces the earlier runtime registration. Returns the
+    /// entry as stored, with split GGUFs resolved to their first shard.
+    pub fn register_runtime(&self, e: ModelEntry) -> Result<ModelEntry, RegisterError> {
+        if self.inner.contains_key(&e.name) || self.discovered_models.contains_key(&e.name) {
+            return Err(RegisterError::NameTaken(e.name));
+        }
+        // Configured models may point at files that appear later; a model
+        // added over the API has to be loadable now
+        for shard in gguf::resolve_split(&e.base_path)? {
+            check_gguf_magic(&shard)?;
+        }
+        let checked = check_entry(e)?;
+        let name = checked.entry.name.clone();
+        let mut runtime = self.runtime.write();
+        match checked.memory_estimate {
+            Some(estimate) => runtime.memory_estimates.insert(name.clone(), estimate),
+            None => runtime.memory_estimates.remove(&name),
+        };
+        if checked.embedding {
+            runtime.embedding_models.insert(name.clone());
+        } else {
+            runtime.embedding_models.remove(&name);
+        }
+        runtime.entries.insert(name, checked.entry.clone());
+        Ok(checked.entry)
+    }
+
+    /// Models registered at runtime, by name
+    pub fn runtime_models(&self) -> Vec<ModelEntry> {
+        let mut models: Vec<_> = self.runtime.read().entries.values().cloned().collect();
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        models
+    }
+
+    /// Re-register the models saved in `path` by earlier runtime registrations
+    ///
+    /// Entries whose file has gone or can no longer be loaded are skipped
+    /// with a warning and stay in the file.
+    pub fn load_registered(&self, path: &Path) {
+        let Ok(data) = std::fs::read(path) else {
+            return;
+        };
+        let saved: RegisteredModels = match serde_json::from_slice(&data) {
+            Ok(saved) => saved,
+            Err(e) => {
+                tracing::warn!("Ignoring {}: {}", path.display(), e);
+                return;
+            }
+        };
+        for entry in saved.models {
+            let name = entry.name.clone();
+            if let Err(err) = self.register_runtime(entry) {
+                tracing::warn!("Not restoring registered model '{}': {}", name, err);
+            }
+        }
+        for (model, adapters) in saved.adapters {
+            for (name, adapter) in adapters {
+                if let Err(err) = self.register_adapter(&model, &name, adapter) {
+                    tracing::warn!(
+                        "Not restoring adapter '{}' of model '{}': {}",
+                        name,
+                        model,
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    /// Save `entry` to the registered-models file at `path`, replacing any
+    /// saved entry with the same name
+    pub fn persist_registered(path: &Path, entry: &ModelEntry) -> anyhow::Result<()> {
+        let mut saved = read_registered(path)?;
+        saved.models.retain(|m| m.name != entry.name);
+        saved.models.push(entry.clone());
+        crate::recovery::write_atomic(path, &serde_json::to_vec_pretty(&saved)?)
+    }
+
+    /// Register a LoRA adapter for `model` under `name`, replacing any
+    /// adapter already registered with that name
+    pub fn register_adapter(
+        &self,
+        model: &str,
+        name: &str,
+        path: PathBuf,
+    ) -> Result<(), RegisterError> {
+        if self.to_spec(model).is_none() {
+            return Err(RegisterError::UnknownModel(model.to_string()));
+        }
+        check_gguf_magic(&path)?;
+        self.runtime
+            .write()
+            .adapters
+            .entry(model.to_string())
+            .or_default()
+            .insert(name.to_string(), path);
+        Ok(())
+    }
+
+    /// The file of `model`'s adapter called `name`
+    pub fn adapter_path(&self, model: &str, name: &str) -> Option<PathBuf> {
+        self.runtime.read().adapters.get(model)?.get(name).cloned()
+    }
+
+    /// Adapters registered for `model`, by name
+    pub fn adapters(&self, model: &str) -> BTreeMap<String, PathBuf> {
+        self.runtime
+            .read()
+            .adapters
+            .get(model)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Save an adapter registration to the registered-models file at `path`
+    pub fn persist_adapter(
+        path: &Path,
+        model: &str,
+        name: &str,
+        adapter: &Path,
+    ) -> anyhow::Result<()> {
+        let mut saved = read_registered(path)?;
+        saved
+            .adapters
+            .entry(model.to_string())
+            .or_default()
+            .insert(name.to_string(), adapter.to_path_buf());
+        crate::recovery::write_atomic(path, &serde_json::to_vec_pretty(&saved)?)
+    }
+
+    /// Like [`Registry::try_register`], logging a rejection instead of returning it
     pub fn register(&mut self, e: ModelEntry) {
-        self.inner.insert(e.name.clone(), e);
+        let name = e.name.clone();
+        if let Err(err) = self.try_register(e) {
+            tracing::warn!("Not registering model '{}': {}", name, err);
+        }
+    }
+
+    /// Why a model was refused at registration, as a categorized load error
+    pub fn rejection(&self, name: &str) -> Option<LoadError> {
+        self.rejected.get(self.canonical(name).as_ref()).cloned()
+    }
+
+    /// All models refused at registration or discovery, by name
+    pub fn rejected_models(&self) -> &HashMap<String, LoadError> {
+        &self.rejected
+    }
+
+    fn mark_embedding(&mut self, name: &str, path: &Path) {
+        if gguf::is_embedding_model(path).unwrap_or(false) {
+            self.embedding_models.insert(name.to_string());
+        } else {
+            self.embedding_models.remove(name);
+        }
+    }
+
+    /// Whether a model only produces embeddings (serve it on `/v1/embeddings`)
+    pub fn is_embedding_model(&self, name: &str) -> bool {
+        let name = self.canonical(name);
+        self.embedding_models.contains(name.as_ref())
+            || self.runtime.read().embedding_models.contains(name.as_ref())
+    }
+
+    /// Estimated runtime memory for a model, if its file size is known
+    pub fn memory_estimate(&self, name: &str) -> Option<MemoryEstimate> {
+        let name = self.canonical(name);
+        let name = name.as_ref();
+        self.memory_estimates
+            .get(name)
+            .or(self.runtime.read().memory_estimates.get(name))
+            .cloned()
     }
     pub fn get(&self, name: &str) -> Option<&ModelEntry> {
         // First check manually registered models, then auto-discovered
@@ -88,15 +403,68 @@ impl Registry {
     pub fn list_all_available(&self) -> Vec<String> {
         let mut available = Vec::new();
         available.extend(self.inner.keys().cloned());
+        available.extend(self.runtime.read().entries.keys().cloned());
         available.extend(self.discovered_models.keys().cloned());
         available.sort();
         available.dedup();
         available
     }
 
+    /// The registered, discovered or rejected model a name refers to, in any
+    /// of the forms [`crate::model_names`] accepts, including the path of
+    /// its file
+    pub fn resolve_name(&self, input: &str) -> Option<String> {
+        let runtime = self.runtime.read();
+        let known: Vec<&str> = self
+            .inner
+            .keys()
+            .chain(runtime.entries.keys())
+            .chain(self.discovered_models.keys())
+            .chain(self.rejected.keys())
+            .map(String::as_str)
+            .collect();
+        if let Some(name) = crate::model_names::resolve(input, &known) {
+            return Some(name.to_string());
+        }
+
+        let path = crate::model_names::as_path(input)?;
+        let same = |other: &Path| {
+            other == path
+                || matches!(
+                    (other.canonicalize(), path.canonicalize()),
+                    (Ok(a), Ok(b)) if a == b
+                )
+        };
+        self.inner
+            .values()
+            .chain(runtime.entries.values())
+            .find(|e| same(&e.base_path))
+            .map(|e| e.name.clone())
+            .or_else(|| {
+                self.discovered_models
+                    .values()
+                    .find(|d| same(&d.path))
+                    .map(|d| d.name.clone())
+            })
+    }
+
+    /// The registry name for `name`, or `name` itself when nothing matches
+    fn canonical<'a>(&self, name: &'a str) -> std::borrow::Cow<'a, str> {
+        if self.inner.contains_key(name) || self.discovered_models.contains_key(name) {
+            return name.into();
+        }
+        match self.resolve_name(name) {
+            Some(resolved) => resolved.into(),
+            None => name.into(),
+        }
+    }
+
     pub fn to_spec(&self, name: &str) -> Option<ModelSpec> {
-        // Try manually registered first
-        if let Some(e) = self.inner.get(name) {
+        let name = self.canonical(name);
+        let name = name.as_ref();
+        // Try manually registered first, then models registered at runtime
+        let runtime = self.runtime.read();
+        if let Some(e) = self.inner.get(name).or(runtime.entries.get(name)) {
             return Some(ModelSpec {
                 name: e.name.clone(),
                 base_path: e.base_path.clone(),
@@ -104,6 +472,8 @@ impl Registry {
                 template: e.template.clone(),
                 ctx_len: e.ctx_len.unwrap_or(4096),
                 n_threads: e.n_threads,
+                n_gpu_layers: e.n_gpu_layers,
+                mmproj_path: e.mmproj_path.clone(),
             });
         }
 
@@ -116,6 +486,8 @@ impl Registry {
                 template: Some(self.infer_template(&discovered.name)),
                 ctx_len: 4096,
                 n_threads: None,
+                n_gpu_layers: None,
+                mmproj_path: discovered.mmproj_path.clone(),
             });
         }
 
@@ -150,6 +522,8 @@ mod tests {
             template: Some("chatml".to_string()),
             ctx_len: Some(4096),
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         registry.register(entry.clone());
@@ -157,6 +531,46 @@ mod tests {
         assert!(registry.get("test-model").is_some());
     }
 
+    #[test]
+    fn test_lookups_accept_typed_name_forms() {
+        let mut registry = Registry::new();
+        registry.register(ModelEntry {
+            name: "phi-3-mini".to_string(),
+            base_path: PathBuf::from("/models/Phi-3-mini.gguf"),
+            lora_path: None,
+            template: None,
+            ctx_len: None,
+            n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
+        });
+        let ollama = "registry.ollama.ai/library/llama3/8b";
+        registry.discovered_models.insert(
+            ollama.to_string(),
+            DiscoveredModel {
+                name: ollama.to_string(),
+                path: PathBuf::from("/ollama/blobs/sha256-abc"),
+                lora_path: None,
+                mmproj_path: None,
+                size_bytes: 0,
+                model_type: "Ollama".to_string(),
+                parameter_count: None,
+                quantization: None,
+            },
+        );
+
+        let name = |input: &str| registry.to_spec(input).map(|spec| spec.name);
+        assert_eq!(name("llama3:8b").as_deref(), Some(ollama));
+        assert_eq!(name("/ollama/blobs/sha256-abc").as_deref(), Some(ollama));
+        assert_eq!(name("PHI-3-MINI").as_deref(), Some("phi-3-mini"));
+        assert_eq!(
+            name("/models/Phi-3-mini.gguf").as_deref(),
+            Some("phi-3-mini")
+        );
+        assert_eq!(registry.resolve_name("llama3:70b"), None);
+        assert!(registry.to_spec("mistral").is_none());
+    }
+
     #[test]
     fn test_list_models() {
         let mut registry = Registry::new();
@@ -167,6 +581,8 @@ mod tests {
             template: None,
             ctx_len: None,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         registry.register(entry);
@@ -174,4 +590,252 @@ mod tests {
         assert_eq!(models.len(), 1);
         assert_eq!(models[0].name, "test");
     }
+
+    #[test]
+    fn test_register_records_memory_estimate() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_path = dir.path().join("tiny.gguf");
+        std::fs::write(&model_path, vec![0u8; 4096]).unwrap();
+
+        let mut registry = Registry::new();
+        for (name, path) in [
+            ("tiny", model_path),
+            ("missing", PathBuf::from("/nope.gguf")),
+        ] {
+            registry.register(ModelEntry {
+                name: name.to_string(),
+                base_path: path,
+                lora_path: None,
+                template: None,
+                ctx_len: None,
+                n_threads: None,
+                n_gpu_layers: None,
+                mmproj_path: None,
+            });
+        }
+
+        let estimate = registry.memory_estimate("tiny").unwrap();
+        assert!(estimate.estimated_runtime_gb > 0.0);
+        assert!(registry.memory_estimate("missing").is_none());
+    }
+
+    #[test]
+    fn test_register_flags_embedding_models() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut registry = Registry::new();
+        for (name, arch) in [("nomic-embed", "nomic-bert"), ("chat", "qwen2")] {
+            let path = dir.path().join(format!("{name}.gguf"));
+            let mut header = b"GGUF".to_vec();
+            header.extend_from_slice(&3u32.to_le_bytes());
+            header.extend_from_slice(&0u64.to_le_bytes());
+            header.extend_from_slice(&1u64.to_le_bytes());
+            header.extend_from_slice(&20u64.to_le_bytes());
+            header.extend_from_slice(b"general.architecture");
+            header.extend_from_slice(&8u32.to_le_bytes()); // string value
+            header.extend_from_slice(&(arch.len() as u64).to_le_bytes());
+            header.extend_from_slice(arch.as_bytes());
+            std::fs::write(&path, header).unwrap();
+            registry.register(ModelEntry {
+                name: name.to_string(),
+                base_path: path,
+                lora_path: None,
+                template: None,
+                ctx_len: None,
+                n_threads: None,
+                n_gpu_layers: None,
+                mmproj_path: None,
+            });
+        }
+
+        assert!(registry.is_embedding_model("nomic-embed"));
+        assert!(!registry.is_embedding_model("chat"));
+        assert!(!registry.is_embedding_model("missing"));
+    }
+
+    #[cfg(feature = "llama")]
+    #[test]
+    fn test_try_register_rejects_unsupported_architecture() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_path = dir.path().join("mmproj.gguf");
+        let mut header = b"GGUF".to_vec();
+        header.extend_from_slice(&3u32.to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes());
+        header.extend_from_slice(&1u64.to_le_bytes());
+        for (i, s) in ["general.architecture", "clip"].iter().enumerate() {
+            header.extend_from_slice(&(s.len() as u64).to_le_bytes());
+            header.extend_from_slice(s.as_bytes());
+            if i == 0 {
+                header.extend_from_slice(&8u32.to_le_bytes()); // string value
+            }
+        }
+        std::fs::write(&model_path, header).unwrap();
+
+        let mut registry = Registry::new();
+        let err = registry
+            .try_register(ModelEntry {
+                name: "mmproj".to_string(),
+                base_path: model_path,
+                lora_path: None,
+                template: None,
+                ctx_len: None,
+                n_threads: None,
+                n_gpu_layers: None,
+                mmproj_path: None,
+            })
+            .unwrap_err();
+        assert_eq!(err.code(), "UNSUPPORTED_ARCHITECTURE");
+        assert!(registry.get("mmproj").is_none());
+        assert!(registry.to_spec("mmproj").is_none());
+        assert!(registry.rejection("mmproj").is_some());
+    }
+
+    #[test]
+    fn test_try_register_split_gguf() {
+        let dir = tempfile::tempdir().unwrap();
+        let shard = |i: u32| dir.path().join(format!("big-0000{i}-of-00003.gguf"));
+        for i in 1..=2 {
+            std::fs::write(shard(i), vec![0u8; 1024]).unwrap();
+        }
+        let entry = |path: PathBuf| ModelEntry {
+            name: "big".to_string(),
+            base_path: path,
+            lora_path: None,
+            template: None,
+            ctx_len: None,
+            n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
+        };
+
+        let mut registry = Registry::new();
+        let err = registry.try_register(entry(shard(2))).unwrap_err();
+        assert_eq!(err.code(), "MODEL_FILE_NOT_FOUND");
+        assert!(err.to_string().contains("big-00003-of-00003.gguf"));
+        assert!(registry.get("big").is_none());
+        assert_eq!(
+            registry.rejection("big").map(|e| e.code()),
+            Some("MODEL_FILE_NOT_FOUND")
+        );
+
+        std::fs::write(shard(3), vec![0u8; 1024]).unwrap();
+        registry.try_register(entry(shard(2))).unwrap();
+        assert_eq!(registry.to_spec("big").unwrap().base_path, shard(1));
+        assert!(registry.rejection("big").is_none());
+        assert!(registry.memory_estimate("big").is_some());
+    }
+
+    #[test]
+    fn test_register_runtime_is_shared_and_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_path = dir.path().join("added.gguf");
+        let mut data = b"GGUF".to_vec();
+        data.resize(4096, 0);
+        std::fs::write(&model_path, data).unwrap();
+        let entry = |name: &str| ModelEntry {
+            name: name.to_string(),
+            base_path: model_path.clone(),
+            lora_path: None,
+            template: Some("chatml".to_string()),
+            ctx_len: Some(8192),
+            n_threads: None,
+            n_gpu_layers: Some(12),
+            mmproj_path: None,
+        };
+
+        let mut registry = Registry::new();
+        registry.register(entry("configured"));
+        let handle = registry.clone();
+        let added = handle.register_runtime(entry("added")).unwrap();
+
+        let spec = registry.to_spec("added").unwrap();
+        assert_eq!(spec.ctx_len, 8192);
+        assert_eq!(spec.n_gpu_layers, Some(12));
+        assert!(registry.memory_estimate("added").is_some());
+        assert!(registry.list_all_available().contains(&"added".to_string()));
+        assert_eq!(registry.list().len(), 1);
+        assert!(matches!(
+            registry.register_runtime(entry("configured")),
+            Err(RegisterError::NameTaken(_))
+        ));
+        let missing = ModelEntry {
+            base_path: dir.path().join("missing.gguf"),
+            ..entry("missing")
+        };
+        assert!(matches!(
+            registry.register_runtime(missing),
+            Err(RegisterError::Load(LoadError::FileNotFound { .. }))
+        ));
+        let corrupt = dir.path().join("corrupt.gguf");
+        std::fs::write(&corrupt, vec![0u8; 4096]).unwrap();
+        assert!(matches!(
+            registry.register_runtime(ModelEntry {
+                base_path: corrupt,
+                ..entry("corrupt")
+            }),
+            Err(RegisterError::Load(LoadError::CorruptGguf { .. }))
+        ));
+
+        let store = dir.path().join(REGISTERED_MODELS_FILE);
+        Registry::persist_registered(&store, &added).unwrap();
+        Registry::persist_registered(&store, &added).unwrap();
+        let restored = Registry::new();
+        restored.load_registered(&store);
+        assert_eq!(restored.runtime_models().len(), 1);
+        assert_eq!(restored.to_spec("added").unwrap().n_gpu_layers, Some(12));
+    }
+
+    #[test]
+    fn test_adapters_are_registered_per_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let gguf = |name: &str| {
+            let path = dir.path().join(name);
+            let mut data = b"GGUF".to_vec();
+            data.resize(4096, 0);
+            std::fs::write(&path, data).unwrap();
+            path
+        };
+        let registry = Registry::new();
+        registry
+            .register_runtime(ModelEntry {
+                name: "base".to_string(),
+                base_path: gguf("base.gguf"),
+                lora_path: None,
+                template: Some("chatml".to_string()),
+                ctx_len: None,
+                n_threads: None,
+                n_gpu_layers: None,
+                mmproj_path: None,
+            })
+            .unwrap();
+
+        let pirate = gguf("pirate.gguf");
+        registry
+            .register_adapter("base", "pirate", pirate.clone())
+            .unwrap();
+        assert_eq!(
+            registry.adapter_path("base", "pirate"),
+            Some(pirate.clone())
+        );
+        assert_eq!(registry.adapter_path("base", "poet"), None);
+        assert_eq!(registry.adapters("base").len(), 1);
+        assert!(matches!(
+            registry.register_adapter("missing", "pirate", pirate.clone()),
+            Err(RegisterError::UnknownModel(_))
+        ));
+        let corrupt = dir.path().join("corrupt.gguf");
+        std::fs::write(&corrupt, b"not a gguf").unwrap();
+        assert!(matches!(
+            registry.register_adapter("base", "corrupt", corrupt),
+            Err(RegisterError::Load(LoadError::CorruptGguf { .. }))
+        ));
+
+        let store = dir.path().join(REGISTERED_MODELS_FILE);
+        for entry in registry.runtime_models() {
+            Registry::persist_registered(&store, &entry).unwrap();
+        }
+        Registry::persist_adapter(&store, "base", "pirate", &pirate).unwrap();
+        let restored = Registry::new();
+        restored.load_registered(&store);
+        assert_eq!(restored.adapter_path("base", "pirate"), Some(pirate));
+    }
 }
@@ -0,0 +1,387 @@
+//! Scheduled model loading and unloading.
+//!
+//! An office deployment wants its big model hot during work hours and its
+//! memory back overnight. `SHIMMY_MODEL_SCHEDULE` (or `model_schedule` in
+//! `shimmy.toml`) lists cron-style rules that a background task runs in
+//! local time:
+//!
+//! ```text
+//! SHIMMY_MODEL_SCHEDULE="0 8 * * mon-fri load llama3-70b; 0 19 * * mon-fri unload llama3-70b"
+//! ```
+//!
+//! Rules are separated by `;` and read `<minute> <hour> <day> <month>
+//! <weekday> <load|unload> <model>`. Each time field is `*`, a number, a
+//! range `a-b`, a step `*/n` or `a-b/n`, or a comma-separated list of those;
+//! weekdays are `0`-`7` (both `0` and `7` are Sunday) or `sun`-`sat`. As in
+//! cron, when both day and weekday are restricted a rule fires on either.
+//!
+//! A scheduled load keeps the model loaded until it is unloaded, by a rule
+//! or through the API; the memory budget may still evict it for another
+//! model. At startup the most recent rule for each model in the past week is
+//! applied, so a server restarted at noon on a weekday loads the model the
+//! 8am rule would have.
+
+use crate::model_cache::KeepAlive;
+use crate::AppState;
+use anyhow::{bail, Result};
+use chrono::{Datelike, Duration as TimeDelta, NaiveDateTime, Timelike};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const MODEL_SCHEDULE_ENV: &str = "SHIMMY_MODEL_SCHEDULE";
+
+/// How far back startup looks for the rule that decides a model's state
+const CATCH_UP_MINUTES: i64 = 7 * 24 * 60;
+
+/// Most minutes run at once after the task falls behind, e.g. during a
+/// long load or after the machine slept
+const MAX_BACKLOG_MINUTES: i64 = 60;
+
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Values one time field allows, as a bit per value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    bits: u64,
+    /// Written as `*`, which matters for the day/weekday rule
+    any: bool,
+}
+
+impl Field {
+    fn parse(text: &str, min: u32, max: u32, names: &[&str]) -> Result<Self> {
+        let value = |v: &str| -> Result<u32> {
+            let n = match names.iter().position(|name| v.eq_ignore_ascii_case(name)) {
+                Some(i) => i as u32 + min,
+                None => match v.parse::<u32>() {
+                    Ok(n) => n,
+                    Err(_) => bail!("`{}` is not a number", v),
+                },
+            };
+            if !(min..=max).contains(&n) {
+                bail!("{} is outside {}-{}", n, min, max);
+            }
+            Ok(n)
+        };
+        let mut bits = 0u64;
+        for part in text.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => match step.parse::<u32>() {
+                    Ok(step) if step > 0 => (range, step),
+                    _ => bail!("`{}` has an invalid step", part),
+                },
+                None => (part, 1),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((a, b)) => (value(a)?, value(b)?),
+                    None if step > 1 => (value(range)?, max),
+                    None => {
+                        let n = value(range)?;
+                        (n, n)
+                    }
+                },
+            };
+            if start > end {
+                bail!("`{}` is an empty range", range);
+            }
+            for n in (start..=end).step_by(step as usize) {
+                bits |= 1 << n;
+            }
+        }
+        Ok(Self {
+            bits,
+            any: text == "*",
+        })
+    }
+
+    fn has(&self, n: u32) -> bool {
+        self.bits & (1 << n) != 0
+    }
+}
+
+/// When a rule fires
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cron {
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+impl Cron {
+    fn matches(&self, at: NaiveDateTime) -> bool {
+        let day = self.day.has(at.day());
+        let weekday = self.weekday.has(at.weekday().num_days_from_sunday())
+            || (at.weekday().num_days_from_sunday() == 0 && self.weekday.has(7));
+        let date = match (self.day.any, self.weekday.any) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        date && self.minute.has(at.minute())
+            && self.hour.has(at.hour())
+            && self.month.has(at.month())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Load,
+    Unload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    when: Cron,
+    action: Action,
+    model: String,
+}
+
+/// Parsed `SHIMMY_MODEL_SCHEDULE`; empty schedules nothing
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    rules: Vec<Rule>,
+}
+
+impl Schedule {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        for rule in spec.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+            let parts: Vec<&str> = rule.split_whitespace().collect();
+            let [minute, hour, day, month, weekday, action, model] = parts[..] else {
+                bail!(
+                    "invalid {} rule `{}`: expected `<minute> <hour> <day> <month> <weekday> \
+                     <load|unload> <model>`",
+                    MODEL_SCHEDULE_ENV,
+                    rule
+                );
+            };
+            let invalid = |field: &str, e: anyhow::Error| {
+                anyhow::anyhow!(
+                    "invalid {} rule `{}`: {}: {}",
+                    MODEL_SCHEDULE_ENV,
+                    rule,
+                    field,
+                    e
+                )
+            };
+            let when = Cron {
+                minute: Field::parse(minute, 0, 59, &[]).map_err(|e| invalid("minute", e))?,
+                hour: Field::parse(hour, 0, 23, &[]).map_err(|e| invalid("hour", e))?,
+                day: Field::parse(day, 1, 31, &[]).map_err(|e| invalid("day", e))?,
+                month: Field::parse(month, 1, 12, &[]).map_err(|e| invalid("month", e))?,
+                weekday: Field::parse(weekday, 0, 7, &WEEKDAYS)
+                    .map_err(|e| invalid("weekday", e))?,
+            };
+            let action = match action {
+                "load" => Action::Load,
+                "unload" => Action::Unload,
+                other => bail!(
+                    "invalid {} rule `{}`: `{}` is not load or unload",
+                    MODEL_SCHEDULE_ENV,
+                    rule,
+                    other
+                ),
+            };
+            rules.push(Rule {
+                when,
+                action,
+                model: model.to_string(),
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// Rules from `SHIMMY_MODEL_SCHEDULE`, or none when it is unset
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(MODEL_SCHEDULE_ENV) {
+            Ok(spec) => Self::parse(&spec),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Actions due at the minute `at`, in the order the rules are listed
+    fn due(&self, at: NaiveDateTime) -> impl Iterator<Item = (Action, &str)> {
+        self.rules
+            .iter()
+            .filter(move |rule| rule.when.matches(at))
+            .map(|rule| (rule.action, rule.model.as_str()))
+    }
+
+    /// The action each model's most recent rule before `now` took, looking
+    /// back a week; a later-listed rule wins within the same minute
+    fn latest(&self, now: NaiveDateTime) -> Vec<(Action, &str)> {
+        let mut models: Vec<&str> = self.rules.iter().map(|r| r.model.as_str()).collect();
+        models.sort_unstable();
+        models.dedup();
+        models
+            .into_iter()
+            .filter_map(|model| {
+                (0..CATCH_UP_MINUTES).find_map(|back| {
+                    let at = now - TimeDelta::minutes(back);
+                    self.rules
+                        .iter()
+                        .rev()
+                        .find(|rule| rule.model == model && rule.when.matches(at))
+                        .map(|rule| (rule.action, model))
+                })
+            })
+            .collect()
+    }
+}
+
+fn this_minute() -> NaiveDateTime {
+    let now = chrono::Local::now().naive_local();
+    now.with_second(0)
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap_or(now)
+}
+
+async fn apply(state: &AppState, action: Action, model: &str) {
+    match action {
+        Action::Load => {
+            let Some(spec) = state.registry.to_spec(model) else {
+                tracing::warn!("Scheduled load of '{}': no such model", model);
+                return;
+            };
+            match state
+                .load_model_keep_alive(&spec, Some(KeepAlive::Forever))
+                .await
+            {
+                Ok(_) => tracing::info!("Scheduled load of '{}' done", model),
+                Err(e) => tracing::warn!("Scheduled load of '{}' failed: {:#}", model, e),
+            }
+        }
+        Action::Unload => {
+            if state.model_cache.unload(model) {
+                tracing::info!("Scheduled unload of '{}' done", model);
+            }
+        }
+    }
+}
+
+/// Run `schedule` in the background for as long as the server runs
+pub fn spawn(schedule: Schedule, state: &Arc<AppState>) {
+    if schedule.is_empty() {
+        return;
+    }
+    for rule in &schedule.rules {
+        if state.registry.to_spec(&rule.model).is_none() {
+            tracing::warn!(
+                "{} names '{}', which is not an available model",
+                MODEL_SCHEDULE_ENV,
+                rule.model
+            );
+        }
+    }
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        let mut last = this_minute();
+        for (action, model) in schedule.latest(last) {
+            if action == Action::Load {
+                apply(&state, action, model).await;
+            }
+        }
+        loop {
+            let now = chrono::Local::now().naive_local();
+            let into_minute = Duration::new(now.second() as u64, now.nanosecond());
+            tokio::time::sleep(Duration::from_secs(60).saturating_sub(into_minute)).await;
+
+            // Every minute since the last pass, so a slow load doesn't skip
+            // the rules behind it
+            let now = this_minute();
+            let first =
+                (last + TimeDelta::minutes(1)).max(now - TimeDelta::minutes(MAX_BACKLOG_MINUTES));
+            let mut at = first;
+            while at <= now {
+                for (action, model) in schedule.due(at) {
+                    apply(&state, action, model).await;
+                }
+                at += TimeDelta::minutes(1);
+            }
+            last = last.max(now);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // March 2026: the 2nd is a Monday
+        NaiveDate::from_ymd_opt(2026, 3, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_office_hours() {
+        let schedule =
+            Schedule::parse("0 8 * * mon-fri load big; 0 19 * * 1-5 unload big").unwrap();
+        assert_eq!(schedule.len(), 2);
+
+        let due = |t| schedule.due(t).collect::<Vec<_>>();
+        assert_eq!(due(at(2, 8, 0)), [(Action::Load, "big")]);
+        assert_eq!(due(at(6, 19, 0)), [(Action::Unload, "big")]);
+        assert!(due(at(2, 8, 1)).is_empty());
+        // Saturday
+        assert!(due(at(7, 8, 0)).is_empty());
+
+        // Restarted at noon on Tuesday: the 8am load applies
+        assert_eq!(schedule.latest(at(3, 12, 0)), [(Action::Load, "big")]);
+        // On Sunday the last rule was Friday's unload
+        assert_eq!(schedule.latest(at(8, 12, 0)), [(Action::Unload, "big")]);
+    }
+
+    #[test]
+    fn test_fields() {
+        let field = Field::parse("*/15", 0, 59, &[]).unwrap();
+        assert!(field.has(0) && field.has(45) && !field.has(50));
+        let field = Field::parse("1-10/3,20", 0, 59, &[]).unwrap();
+        assert_eq!(
+            (0..60).filter(|&n| field.has(n)).collect::<Vec<_>>(),
+            [1, 4, 7, 10, 20]
+        );
+        let field = Field::parse("5/20", 0, 59, &[]).unwrap();
+        assert_eq!(
+            (0..60).filter(|&n| field.has(n)).collect::<Vec<_>>(),
+            [5, 25, 45]
+        );
+
+        // Sunday is 0 or 7, and day and weekday together match either
+        let sundays = Schedule::parse("30 6 * * 7 load m").unwrap();
+        assert_eq!(sundays.due(at(8, 6, 30)).count(), 1);
+        let either = Schedule::parse("0 0 1 * sat load m").unwrap();
+        assert_eq!(either.due(at(1, 0, 0)).count(), 1);
+        assert_eq!(either.due(at(7, 0, 0)).count(), 1);
+        assert_eq!(either.due(at(2, 0, 0)).count(), 0);
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        assert!(Schedule::parse("").unwrap().is_empty());
+        for bad in [
+            "0 8 * * load big",
+            "0 8 * * mon-fri warm big",
+            "60 8 * * * load big",
+            "0 8 * * fri-mon load big",
+            "*/0 8 * * * load big",
+            "0 8 * * someday load big",
+        ] {
+            let err = Schedule::parse(bad).unwrap_err().to_string();
+            assert!(err.contains(MODEL_SCHEDULE_ENV), "{}", err);
+        }
+    }
+}
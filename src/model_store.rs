@@ -0,0 +1,459 @@
+//! Content-addressable model store.
+//!
+//! Downloaded weights are kept once per content hash and named through small
+//! manifests, the way Ollama lays out its blobs:
+//!
+//! ```text
+//! <store>/blobs/sha256-<hex>.gguf
+//! <store>/manifests/<name>.json   {"name", "layers": [{"digest", "size", ...}]}
+//! ```
+//!
+//! Registering the same file under several names writes another manifest, not
+//! another copy, and every shimmy instance or profile pointed at the same store
+//! (`SHIMMY_MODEL_STORE`, default `<local_data_dir>/store`) shares the blobs.
+//! Blobs keep the extension of the file they came from so backend selection by
+//! extension keeps working. Writes go through a temp file and a rename, so
+//! instances can download into the store concurrently.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+pub const MODEL_STORE_ENV: &str = "SHIMMY_MODEL_STORE";
+
+/// Current manifest format
+const MANIFEST_VERSION: u32 = 1;
+
+/// Blobs younger than this are never pruned: another instance may be about to
+/// write the manifest that references it
+const PRUNE_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// What a layer holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayerKind {
+    Model,
+    Lora,
+    Projector,
+}
+
+/// One blob referenced by a manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layer {
+    pub kind: LayerKind,
+    /// `sha256:<hex>`
+    pub digest: String,
+    pub size: u64,
+    /// Name of the file the blob was created from
+    pub file_name: String,
+}
+
+/// A name pointing at a set of blobs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub schema_version: u32,
+    pub name: String,
+    pub layers: Vec<Layer>,
+}
+
+impl Manifest {
+    pub fn layer(&self, kind: LayerKind) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.kind == kind)
+    }
+
+    /// Total size of the referenced blobs
+    pub fn size(&self) -> u64 {
+        self.layers.iter().map(|l| l.size).sum()
+    }
+}
+
+/// Result of [`ModelStore::prune`]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PruneReport {
+    pub removed: usize,
+    pub freed_bytes: u64,
+}
+
+pub struct ModelStore {
+    root: PathBuf,
+}
+
+impl ModelStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// `SHIMMY_MODEL_STORE`, or `store` under the local data directory
+    pub fn open_default() -> Self {
+        let root = std::env::var(MODEL_STORE_ENV)
+            .ok()
+            .filter(|d| !d.trim().is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| crate::util::paths::local_data_dir().join("store"));
+        Self::new(root)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn blobs_dir(&self) -> PathBuf {
+        self.root.join("blobs")
+    }
+
+    fn manifests_dir(&self) -> PathBuf {
+        self.root.join("manifests")
+    }
+
+    fn tmp_dir(&self) -> PathBuf {
+        self.root.join("tmp")
+    }
+
+    fn manifest_path(&self, name: &str) -> PathBuf {
+        self.manifests_dir().join(format!("{}.json", name))
+    }
+
+    /// Where the blob for `layer` lives
+    pub fn blob_path(&self, layer: &Layer) -> PathBuf {
+        let hex = layer.digest.trim_start_matches("sha256:");
+        let file = match Path::new(&layer.file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+        {
+            Some(ext) => format!("sha256-{}.{}", hex, ext),
+            None => format!("sha256-{}", hex),
+        };
+        self.blobs_dir().join(file)
+    }
+
+    /// A fresh temp file path inside the store (same filesystem as the blobs)
+    fn temp_path(&self) -> Result<PathBuf> {
+        let dir = self.tmp_dir();
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir.join(format!("{:016x}.partial", rand::random::<u64>())))
+    }
+
+    /// Move a fully written temp file into place as the blob for `layer`
+    fn commit_blob(&self, tmp: &Path, layer: &Layer) -> Result<PathBuf> {
+        let dest = self.blob_path(layer);
+        std::fs::create_dir_all(self.blobs_dir())?;
+        if dest.exists() {
+            // Same content already stored (possibly by another instance)
+            let _ = std::fs::remove_file(tmp);
+        } else {
+            std::fs::rename(tmp, &dest)?;
+        }
+        Ok(dest)
+    }
+
+    /// Add a local file to the store, hard-linking it when possible
+    pub fn import_file(&self, path: &Path, kind: LayerKind) -> Result<Layer> {
+        let mut file =
+            std::fs::File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 8 * 1024 * 1024];
+        let mut size = 0u64;
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            size += n as u64;
+        }
+        let layer = Layer {
+            kind,
+            digest: format!("sha256:{:x}", hasher.finalize()),
+            size,
+            file_name: file_name_of(path),
+        };
+        if self.blob_path(&layer).exists() {
+            return Ok(layer);
+        }
+
+        let tmp = self.temp_path()?;
+        if std::fs::hard_link(path, &tmp).is_err() {
+            std::fs::copy(path, &tmp)?;
+        }
+        self.commit_blob(&tmp, &layer)?;
+        Ok(layer)
+    }
+
+    /// Download `url` into the store, skipping the download when a blob with
+    /// `expected_sha256` is already present. `progress` gets bytes written and
+    /// the total size when the server reports it.
+    pub async fn download(
+        &self,
+        url: &str,
+        kind: LayerKind,
+        expected_sha256: Option<&str>,
+        progress: &mut (dyn FnMut(u64, Option<u64>) + Send),
+    ) -> Result<Layer> {
+        let file_name = url
+            .split(['?', '#'])
+            .next()
+            .and_then(|u| u.rsplit('/').next())
+            .filter(|n| !n.is_empty())
+            .unwrap_or("model")
+            .to_string();
+
+        if let Some(expected) = expected_sha256 {
+            let candidate = Layer {
+                kind,
+                digest: format!("sha256:{}", expected.to_lowercase()),
+                size: 0,
+                file_name: file_name.clone(),
+            };
+            let path = self.blob_path(&candidate);
+            if let Ok(meta) = std::fs::metadata(&path) {
+                return Ok(Layer {
+                    size: meta.len(),
+                    ..candidate
+                });
+            }
+        }
+
+        let mut response = crate::util::http::client_builder()
+            .build()?
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?;
+        let total = response.content_length();
+        let tmp = self.temp_path()?;
+        let mut file = std::fs::File::create(&tmp)?;
+        let mut hasher = Sha256::new();
+        let mut written = 0u64;
+        let result: Result<()> = async {
+            while let Some(chunk) = response.chunk().await? {
+                hasher.update(&chunk);
+                file.write_all(&chunk)?;
+                written += chunk.len() as u64;
+                progress(written, total);
+            }
+            file.flush()?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(e);
+        }
+
+        let actual = format!("{:x}", hasher.finalize());
+        if let Some(expected) = expected_sha256 {
+            if !actual.eq_ignore_ascii_case(expected) {
+                let _ = std::fs::remove_file(&tmp);
+                bail!(
+                    "SHA256 mismatch for {}: expected {}, got {}",
+                    url,
+                    expected,
+                    actual
+                );
+            }
+        }
+        let layer = Layer {
+            kind,
+            digest: format!("sha256:{}", actual),
+            size: written,
+            file_name,
+        };
+        self.commit_blob(&tmp, &layer)?;
+        Ok(layer)
+    }
+
+    /// Point `name` at `layers`, replacing any previous manifest of that name
+    pub fn write_manifest(&self, name: &str, layers: Vec<Layer>) -> Result<Manifest> {
+        validate_name(name)?;
+        for layer in &layers {
+            if !self.blob_path(layer).exists() {
+                bail!("blob {} is not in the store", layer.digest);
+            }
+        }
+        let manifest = Manifest {
+            schema_version: MANIFEST_VERSION,
+            name: name.to_string(),
+            layers,
+        };
+        std::fs::create_dir_all(self.manifests_dir())?;
+        let tmp = self.temp_path()?;
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&manifest)?)?;
+        std::fs::rename(&tmp, self.manifest_path(name))?;
+        Ok(manifest)
+    }
+
+    pub fn manifest(&self, name: &str) -> Result<Option<Manifest>> {
+        validate_name(name)?;
+        match std::fs::read_to_string(self.manifest_path(name)) {
+            Ok(text) => Ok(Some(serde_json::from_str(&text).with_context(|| {
+                format!("invalid manifest {}", self.manifest_path(name).display())
+            })?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// All readable manifests, sorted by name
+    pub fn manifests(&self) -> Result<Vec<Manifest>> {
+        let entries = match std::fs::read_dir(self.manifests_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut manifests: Vec<Manifest> = entries
+            .flatten()
+            .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+            .filter_map(|e| std::fs::read_to_string(e.path()).ok())
+            .filter_map(|text| serde_json::from_str(&text).ok())
+            .collect();
+        manifests.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(manifests)
+    }
+
+    /// Register `alias` for the blobs of `name` without copying anything
+    pub fn alias(&self, name: &str, alias: &str) -> Result<Manifest> {
+        let Some(manifest) = self.manifest(name)? else {
+            bail!("no model named `{}` in the store", name);
+        };
+        self.write_manifest(alias, manifest.layers)
+    }
+
+    /// Delete a manifest; its blobs stay until [`prune`](Self::prune)
+    pub fn remove(&self, name: &str) -> Result<bool> {
+        validate_name(name)?;
+        match std::fs::remove_file(self.manifest_path(name)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Delete blobs and leftover temp files no manifest references
+    pub fn prune(&self) -> Result<PruneReport> {
+        let referenced: Vec<PathBuf> = self
+            .manifests()?
+            .iter()
+            .flat_map(|m| m.layers.iter().map(|l| self.blob_path(l)))
+            .collect();
+        let mut report = PruneReport::default();
+        for dir in [self.blobs_dir(), self.tmp_dir()] {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Ok(meta) = entry.metadata() else {
+                    continue;
+                };
+                let recent = meta
+                    .modified()
+                    .ok()
+                    .and_then(|m| SystemTime::now().duration_since(m).ok())
+                    .is_none_or(|age| age < PRUNE_GRACE);
+                if !meta.is_file() || recent || referenced.contains(&path) {
+                    continue;
+                }
+                if std::fs::remove_file(&path).is_ok() {
+                    report.removed += 1;
+                    report.freed_bytes += meta.len();
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn file_name_of(path: &Path) -> String {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("model")
+        .to_string()
+}
+
+/// Names become file names, so keep them portable
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !valid {
+        bail!(
+            "invalid model name `{}`: use letters, digits, `.`, `_` and `-`",
+            name
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aliases_share_one_blob() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ModelStore::new(dir.path().join("store"));
+        let source = dir.path().join("tiny-q4.gguf");
+        std::fs::write(&source, b"GGUF weights").unwrap();
+
+        let layer = store.import_file(&source, LayerKind::Model).unwrap();
+        assert_eq!(layer.size, 12);
+        assert!(layer.digest.starts_with("sha256:"));
+        store.write_manifest("tiny", vec![layer.clone()]).unwrap();
+        store.alias("tiny", "tiny-latest").unwrap();
+
+        // Importing the same bytes again reuses the blob
+        let again = store.import_file(&source, LayerKind::Model).unwrap();
+        assert_eq!(again, layer);
+        let blobs: Vec<_> = std::fs::read_dir(store.blobs_dir()).unwrap().collect();
+        assert_eq!(blobs.len(), 1);
+
+        let names: Vec<_> = store
+            .manifests()
+            .unwrap()
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+        assert_eq!(names, ["tiny", "tiny-latest"]);
+        let path = store.blob_path(&layer);
+        assert!(path.to_string_lossy().ends_with(".gguf"));
+        assert_eq!(std::fs::read(path).unwrap(), b"GGUF weights");
+    }
+
+    #[test]
+    fn test_prune_keeps_referenced_and_recent_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ModelStore::new(dir.path());
+        let source = dir.path().join("a.gguf");
+        std::fs::write(&source, b"a").unwrap();
+        let layer = store.import_file(&source, LayerKind::Model).unwrap();
+        store.write_manifest("a", vec![layer]).unwrap();
+
+        assert!(store.remove("a").unwrap());
+        assert!(!store.remove("a").unwrap());
+        // Unreferenced but just written: another instance may still claim it
+        assert_eq!(store.prune().unwrap(), PruneReport::default());
+    }
+
+    #[test]
+    fn test_names_and_missing_blobs_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ModelStore::new(dir.path());
+        for bad in ["", "../x", "org/model", ".hidden", "a:b"] {
+            assert!(store.write_manifest(bad, Vec::new()).is_err(), "{}", bad);
+        }
+        let missing = Layer {
+            kind: LayerKind::Model,
+            digest: "sha256:00".to_string(),
+            size: 1,
+            file_name: "m.gguf".to_string(),
+        };
+        assert!(store.write_manifest("m", vec![missing]).is_err());
+        assert!(store.manifest("m").unwrap().is_none());
+        assert!(store.alias("m", "n").is_err());
+    }
+}
@@ -0,0 +1,952 @@
+//! Ollama REST API compatibility layer.
+//!
+//! Tools hard-coded to Ollama (Open WebUI, Continue, Raycast) call
+//! `/api/chat`, `/api/generate` and `/api/tags` and read streamed replies as
+//! newline-delimited JSON, one object per line. This module speaks that
+//! dialect on top of the same engine as the OpenAI routes:
+//!
+//! - `POST /api/chat`: chat with Ollama's messages, `options` and `tools`
+//! - `POST /api/generate`: shared with shimmy's own generate route; see
+//!   [`generate_any`] for how a request's dialect is picked
+//! - `GET /api/tags`, `POST /api/show` and `GET /api/version`: the model list
+//!   and details clients probe before chatting
+//!
+//! As with Ollama, replies stream unless the request sends `"stream": false`,
+//! and `keep_alive` says how long the model stays loaded afterwards (seconds
+//! or a duration such as `"5m"`; 0 unloads it, a negative value keeps it). A
+//! request without a prompt or messages only loads the model, or unloads it
+//! with `keep_alive: 0`. Errors are `{"error": "..."}` with shimmy's status
+//! codes, and model names may carry Ollama's `:latest` tag.
+//!
+//! Reference: https://github.com/ollama/ollama/blob/main/docs/api.md
+
+use crate::api::ChatMessage;
+use crate::engine::{FinishReason, GenOptions, InvalidParameter, LoadedModel, ModelSpec};
+use crate::error::ShimmyError;
+use crate::model_cache::KeepAlive;
+use crate::tool_calling::{self, FunctionCall, Tool, ToolPlan};
+use crate::AppState;
+use axum::body::Body;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Serve every `/api/generate` request in Ollama's dialect
+pub const OLLAMA_GENERATE_ENV: &str = "SHIMMY_OLLAMA_GENERATE";
+
+/// `/api/generate` fields only Ollama's version of the route has
+const OLLAMA_ONLY_FIELDS: &[&str] = &[
+    "options", "raw", "format", "images", "context", "suffix", "template",
+];
+
+/// `options` of an Ollama request; ones shimmy has no use for (`num_ctx`,
+/// `mirostat`, ...) are ignored
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OllamaOptions {
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<i32>,
+    #[serde(default)]
+    pub repeat_penalty: Option<f32>,
+    /// Tokens to generate; -1 and -2 leave shimmy's default
+    #[serde(default)]
+    pub num_predict: Option<i64>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+    #[serde(default)]
+    pub stop: Option<Vec<String>>,
+}
+
+impl OllamaOptions {
+    fn apply(&self, opts: &mut GenOptions) {
+        if let Some(t) = self.temperature {
+            opts.temperature = t;
+        }
+        if let Some(p) = self.top_p {
+            opts.top_p = p;
+        }
+        if let Some(k) = self.top_k {
+            opts.top_k = k;
+        }
+        if let Some(penalty) = self.repeat_penalty {
+            opts.repeat_penalty = penalty;
+        }
+        if let Some(n) = self.num_predict.filter(|&n| n > 0) {
+            opts.max_tokens = n as usize;
+        }
+        opts.seed = self.seed.and_then(|seed| u32::try_from(seed).ok());
+        opts.stop_tokens.extend(self.stop.iter().flatten().cloned());
+    }
+}
+
+/// Body of Ollama's `POST /api/generate`; `context` and `template` are
+/// accepted and ignored
+#[derive(Debug, Deserialize)]
+pub struct OllamaGenerateRequest {
+    pub model: String,
+    #[serde(default)]
+    pub prompt: String,
+    #[serde(default)]
+    pub system: Option<String>,
+    /// Code after the cursor, for fill-in-the-middle completion
+    #[serde(default)]
+    pub suffix: Option<String>,
+    /// Send the prompt without the chat template
+    #[serde(default)]
+    pub raw: bool,
+    /// `"json"` or a JSON schema; both constrain the reply to a JSON object
+    #[serde(default)]
+    pub format: Option<Value>,
+    #[serde(default)]
+    pub images: Vec<String>,
+    #[serde(default)]
+    pub options: OllamaOptions,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub keep_alive: Option<KeepAlive>,
+}
+
+/// Body of Ollama's `POST /api/chat`
+#[derive(Debug, Deserialize)]
+pub struct OllamaChatRequest {
+    pub model: String,
+    #[serde(default)]
+    pub messages: Vec<OllamaMessage>,
+    #[serde(default)]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(default)]
+    pub format: Option<Value>,
+    #[serde(default)]
+    pub options: OllamaOptions,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub keep_alive: Option<KeepAlive>,
+}
+
+/// A chat message, in requests and in `/api/chat` replies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OllamaToolCall {
+    pub function: OllamaFunctionCall,
+}
+
+/// Unlike OpenAI's, Ollama's arguments are a JSON object, not a string
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OllamaFunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+impl From<tool_calling::ToolCall> for OllamaToolCall {
+    fn from(call: tool_calling::ToolCall) -> Self {
+        Self {
+            function: OllamaFunctionCall {
+                arguments: serde_json::from_str(&call.function.arguments)
+                    .unwrap_or_else(|_| Value::Object(Default::default())),
+                name: call.function.name,
+            },
+        }
+    }
+}
+
+/// One line of a reply; the last has `done: true`, the reason and timings
+#[derive(Debug, Serialize)]
+pub struct OllamaResponse {
+    pub model: String,
+    pub created_at: String,
+    /// `/api/generate` output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    /// `/api/chat` output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<OllamaMessage>,
+    pub done: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub done_reason: Option<String>,
+    #[serde(flatten)]
+    pub stats: Option<Stats>,
+}
+
+/// Token counts and durations in nanoseconds, as Ollama reports them
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub total_duration: u64,
+    pub load_duration: u64,
+    /// Prompt tokens evaluated, not counting ones reused from the KV cache
+    pub prompt_eval_count: usize,
+    /// Time to the first generated token
+    pub prompt_eval_duration: u64,
+    pub eval_count: usize,
+    pub eval_duration: u64,
+}
+
+/// Which route a reply is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Generate,
+    Chat,
+}
+
+impl OllamaResponse {
+    fn new(kind: Kind, model: &str, content: String, tool_calls: Vec<OllamaToolCall>) -> Self {
+        let (response, message) = match kind {
+            Kind::Generate => (Some(content), None),
+            Kind::Chat => (
+                None,
+                Some(OllamaMessage {
+                    role: "assistant".to_string(),
+                    content,
+                    images: Vec::new(),
+                    tool_calls,
+                }),
+            ),
+        };
+        Self {
+            model: model.to_string(),
+            created_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+            response,
+            message,
+            done: false,
+            done_reason: None,
+            stats: None,
+        }
+    }
+
+    fn done(mut self, reason: &str, stats: Option<Stats>) -> Self {
+        self.done = true;
+        self.done_reason = Some(reason.to_string());
+        self.stats = stats;
+        self
+    }
+}
+
+/// Ollama's `done_reason` for an engine finish reason
+fn done_reason(reason: FinishReason) -> &'static str {
+    match reason {
+        FinishReason::Length | FinishReason::Time => "length",
+        FinishReason::Stop
+        | FinishReason::ToolCalls
+        | FinishReason::ContentFilter
+        | FinishReason::Cancelled
+        | FinishReason::Repetition => "stop",
+    }
+}
+
+/// An error as Ollama sends it, `{"error": "..."}`, with shimmy's status
+fn error_response(e: ShimmyError) -> Response {
+    let (status, body) = e.response_body();
+    let message = body["error"]["message"].clone();
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+/// Whether a `/api/generate` body is meant for Ollama's version of the route
+fn is_ollama_generate(body: &Value, forced: bool) -> bool {
+    forced
+        || body
+            .as_object()
+            .is_some_and(|fields| OLLAMA_ONLY_FIELDS.iter().any(|f| fields.contains_key(*f)))
+}
+
+fn ollama_generate_forced() -> bool {
+    std::env::var(OLLAMA_GENERATE_ENV)
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// `POST /api/generate` in either dialect
+///
+/// A body with a field only Ollama's route has (`options`, `raw`, `format`,
+/// `images`, `context`, `suffix` or `template`) gets Ollama's behaviour, as
+/// does every body when `SHIMMY_OLLAMA_GENERATE` is set; anything else is
+/// shimmy's own generate request.
+pub async fn generate_any(State(state): State<Arc<AppState>>, Json(body): Json<Value>) -> Response {
+    if is_ollama_generate(&body, ollama_generate_forced()) {
+        match serde_json::from_value::<OllamaGenerateRequest>(body) {
+            Ok(req) => generate(state, req).await,
+            Err(e) => error_response(ShimmyError::InvalidRequest {
+                reason: e.to_string(),
+            }),
+        }
+    } else {
+        match serde_json::from_value::<crate::api::GenerateRequest>(body) {
+            Ok(req) => crate::api::generate(State(state), Json(req))
+                .await
+                .into_response(),
+            Err(e) => ShimmyError::InvalidRequest {
+                reason: e.to_string(),
+            }
+            .into_response(),
+        }
+    }
+}
+
+/// Ollama's `POST /api/generate`: complete `prompt`, templated as a user
+/// turn unless `raw` is set
+pub async fn generate(state: Arc<AppState>, req: OllamaGenerateRequest) -> Response {
+    let started = Instant::now();
+    let (name, spec) = match resolve(&state, &req.model) {
+        Ok(found) => found,
+        Err(e) => return error_response(e),
+    };
+    if !req.images.is_empty() {
+        return error_response(
+            InvalidParameter::new(
+                "images",
+                "image input isn't supported here; use /api/vision",
+            )
+            .into(),
+        );
+    }
+    let fam = crate::openai_compat::template_family(spec.template.as_deref(), &name);
+    let fim = match req.suffix {
+        Some(_) => match crate::templates::FimFormat::detect(spec.template.as_deref(), &name) {
+            Some(fim) => Some(fim),
+            None => {
+                return error_response(
+                    InvalidParameter::new(
+                        "suffix",
+                        format!("model '{}' does not support insert", name),
+                    )
+                    .into(),
+                )
+            }
+        },
+        None => None,
+    };
+    let mut opts = gen_options(req.stream, &req.options);
+    match fim {
+        Some(fim) => opts.stop_tokens.extend(fim.stop_tokens()),
+        None if !req.raw => opts.stop_tokens.extend(fam.stop_tokens()),
+        None => {}
+    }
+    opts.grammar = match format_grammar(req.format.as_ref()) {
+        Ok(grammar) => grammar,
+        Err(e) => return error_response(e.into()),
+    };
+    if let Err(e) = opts.validate() {
+        return error_response(e.into());
+    }
+    if req.prompt.is_empty() {
+        return load_only(&state, Kind::Generate, &name, &spec, req.keep_alive).await;
+    }
+
+    let loaded = match state.load_model_keep_alive(&spec, req.keep_alive).await {
+        Ok(loaded) => loaded,
+        Err(e) => return error_response(ShimmyError::from_load(std::path::Path::new(&name), e)),
+    };
+    let prompt = if let (Some(fim), Some(suffix)) = (fim, &req.suffix) {
+        fim.render(&req.prompt, suffix)
+    } else if req.raw {
+        req.prompt
+    } else {
+        fam.render(req.system.as_deref(), &[], Some(&req.prompt))
+    };
+    Reply {
+        kind: Kind::Generate,
+        model: name,
+        loaded,
+        prompt,
+        opts,
+        tools: None,
+        started,
+        load_duration: started.elapsed(),
+    }
+    .respond()
+    .await
+}
+
+/// Ollama's `POST /api/chat`
+pub async fn chat(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<OllamaChatRequest>,
+) -> Response {
+    let started = Instant::now();
+    let (name, spec) = match resolve(&state, &req.model) {
+        Ok(found) => found,
+        Err(e) => return error_response(e),
+    };
+    if req.messages.iter().any(|m| !m.images.is_empty()) {
+        return error_response(
+            InvalidParameter::new(
+                "images",
+                "image input isn't supported here; use /api/vision",
+            )
+            .into(),
+        );
+    }
+    let fam = crate::openai_compat::template_family(spec.template.as_deref(), &name);
+    let mut opts = gen_options(req.stream, &req.options);
+    opts.stop_tokens.extend(fam.stop_tokens());
+    opts.grammar = match format_grammar(req.format.as_ref()) {
+        Ok(grammar) => grammar,
+        Err(e) => return error_response(e.into()),
+    };
+    if let Err(e) = opts.validate() {
+        return error_response(e.into());
+    }
+    let tools = match ToolPlan::new(req.tools.as_deref().unwrap_or_default(), None) {
+        Ok(plan) => plan,
+        Err(reason) => return error_response(ShimmyError::InvalidRequest { reason }),
+    };
+    if req.messages.is_empty() {
+        return load_only(&state, Kind::Chat, &name, &spec, req.keep_alive).await;
+    }
+
+    let mut messages = chat_messages(req.messages);
+    if let Some(plan) = &tools {
+        messages = crate::openai_compat::with_system_prompt(&messages, plan.system_prompt());
+    }
+    let loaded = match state.load_model_keep_alive(&spec, req.keep_alive).await {
+        Ok(loaded) => loaded,
+        Err(e) => return error_response(ShimmyError::from_load(std::path::Path::new(&name), e)),
+    };
+    let load_duration = started.elapsed();
+    // Drop the oldest turns if the conversation overflows the context window
+    let (prompt, _) = crate::truncation::fit_messages(
+        &messages,
+        spec.ctx_len.saturating_sub(opts.max_tokens),
+        |messages| crate::openai_compat::render_chat(&fam, messages),
+        |text| {
+            loaded
+                .count_tokens(text)
+                .unwrap_or_else(|| crate::truncation::estimate_tokens(text))
+        },
+    );
+    Reply {
+        kind: Kind::Chat,
+        model: name,
+        loaded,
+        prompt,
+        opts,
+        tools,
+        started,
+        load_duration,
+    }
+    .respond()
+    .await
+}
+
+/// The registry name and spec for a model name in any form
+/// [`crate::model_names`] accepts, such as `llama3:latest`
+fn resolve(state: &AppState, model: &str) -> Result<(String, ModelSpec), ShimmyError> {
+    let name = state
+        .registry
+        .resolve_name(model)
+        .unwrap_or_else(|| model.to_string());
+    let name = name.as_str();
+    if let Some(rejection) = state.registry.rejection(name) {
+        return Err(ShimmyError::Load(rejection));
+    }
+    let spec = state
+        .registry
+        .to_spec(name)
+        .ok_or_else(|| ShimmyError::ModelNotFound {
+            name: model.to_string(),
+        })?;
+    if state.registry.is_embedding_model(name) {
+        return Err(ShimmyError::InvalidRequest {
+            reason: format!("Model '{}' is an embedding model; use /v1/embeddings", name),
+        });
+    }
+    Ok((name.to_string(), spec))
+}
+
+/// Options for a request; Ollama streams unless told not to
+fn gen_options(stream: Option<bool>, options: &OllamaOptions) -> GenOptions {
+    let mut opts = GenOptions {
+        stream: stream.unwrap_or(true),
+        deadline: crate::deadline::current(),
+        api_key: crate::api_keys::current(),
+        rate_limit: crate::rate_limit::current(),
+        ..Default::default()
+    };
+    options.apply(&mut opts);
+    opts
+}
+
+/// Grammar for a request's `format`: `"json"` and JSON schemas both get
+/// the built-in JSON object grammar
+fn format_grammar(format: Option<&Value>) -> Result<Option<String>, InvalidParameter> {
+    match format {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) if s.is_empty() => Ok(None),
+        Some(Value::String(s)) if s == "json" => {
+            Ok(Some(crate::engine::grammar::JSON_OBJECT.to_string()))
+        }
+        Some(Value::Object(_)) => Ok(Some(crate::engine::grammar::JSON_OBJECT.to_string())),
+        Some(_) => Err(InvalidParameter::new(
+            "format",
+            "expected \"json\" or a JSON schema object",
+        )),
+    }
+}
+
+/// Messages as the chat template takes them, rendering `tool_calls` and
+/// `tool` results as the text the model was prompted with
+fn chat_messages(messages: Vec<OllamaMessage>) -> Vec<ChatMessage> {
+    messages
+        .into_iter()
+        .map(|m| {
+            if m.role == "tool" {
+                return ChatMessage {
+                    role: "user".to_string(),
+                    content: tool_calling::render_response(&m.content),
+                };
+            }
+            let mut content = m.content;
+            for call in m.tool_calls {
+                if !content.is_empty() {
+                    content.push('\n');
+                }
+                content.push_str(&tool_calling::render_call(&FunctionCall {
+                    name: call.function.name,
+                    arguments: call.function.arguments.to_string(),
+                }));
+            }
+            ChatMessage {
+                role: m.role,
+                content,
+            }
+        })
+        .collect()
+}
+
+/// Answer a request with nothing to generate: load the model, or unload it
+/// when `keep_alive` is 0
+async fn load_only(
+    state: &AppState,
+    kind: Kind,
+    name: &str,
+    spec: &ModelSpec,
+    keep_alive: Option<KeepAlive>,
+) -> Response {
+    let reason = if keep_alive.is_some_and(KeepAlive::unloads_immediately) {
+        state.model_cache.unload(name);
+        "unload"
+    } else {
+        if let Err(e) = state.load_model_keep_alive(spec, keep_alive).await {
+            return error_response(ShimmyError::from_load(std::path::Path::new(name), e));
+        }
+        "load"
+    };
+    Json(OllamaResponse::new(kind, name, String::new(), Vec::new()).done(reason, None))
+        .into_response()
+}
+
+/// A generation to run and send back in Ollama's format
+struct Reply {
+    kind: Kind,
+    model: String,
+    loaded: Box<dyn LoadedModel>,
+    prompt: String,
+    opts: GenOptions,
+    /// With tools the reply is held back until it can be told apart from a
+    /// function call
+    tools: Option<ToolPlan>,
+    started: Instant,
+    load_duration: Duration,
+}
+
+/// What a finished generation sends back
+struct Outcome {
+    content: String,
+    tool_calls: Vec<OllamaToolCall>,
+    done_reason: &'static str,
+    stats: Stats,
+}
+
+impl Reply {
+    async fn respond(self) -> Response {
+        if self.opts.stream {
+            return self.stream();
+        }
+        let (kind, model) = (self.kind, self.model.clone());
+        match self.generate(None).await {
+            Ok(outcome) => Json(
+                OllamaResponse::new(kind, &model, outcome.content, outcome.tool_calls)
+                    .done(outcome.done_reason, Some(outcome.stats)),
+            )
+            .into_response(),
+            Err(e) => {
+                tracing::error!("Generation failed for '{}': {}", model, e);
+                error_response(ShimmyError::GenerationError {
+                    reason: e.to_string(),
+                })
+            }
+        }
+    }
+
+    /// Stream the reply as NDJSON, ending with the `done` object
+    fn stream(mut self) -> Response {
+        use tokio_stream::wrappers::UnboundedReceiverStream;
+        use tokio_stream::StreamExt;
+
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        // A failed send means the client hung up; stop generating for it
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.opts.cancel = Some(cancel.clone());
+        self.opts.stream = false;
+        let (kind, model) = (self.kind, self.model.clone());
+        let held = self.tools.is_some();
+
+        tokio::spawn(async move {
+            let tx_tokens = tx.clone();
+            let model_for_tokens = model.clone();
+            let on_token: Box<dyn FnMut(String) + Send> = Box::new(move |token| {
+                let gone = if held {
+                    tx_tokens.is_closed()
+                } else {
+                    let chunk = OllamaResponse::new(kind, &model_for_tokens, token, Vec::new());
+                    tx_tokens.send(to_line(&chunk)).is_err()
+                };
+                if gone {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            });
+            match self.generate(Some(on_token)).await {
+                Ok(outcome) => {
+                    if held && (!outcome.content.is_empty() || !outcome.tool_calls.is_empty()) {
+                        let chunk =
+                            OllamaResponse::new(kind, &model, outcome.content, outcome.tool_calls);
+                        let _ = tx.send(to_line(&chunk));
+                    }
+                    let done = OllamaResponse::new(kind, &model, String::new(), Vec::new())
+                        .done(outcome.done_reason, Some(outcome.stats));
+                    let _ = tx.send(to_line(&done));
+                }
+                Err(e) => {
+                    tracing::error!("Streaming generation failed for '{}': {}", model, e);
+                    let _ = tx.send(serde_json::json!({ "error": e.to_string() }).to_string());
+                }
+            }
+        });
+
+        let lines = UnboundedReceiverStream::new(rx)
+            .map(|line| Ok::<_, std::convert::Infallible>(format!("{}\n", line)));
+        (
+            [(header::CONTENT_TYPE, "application/x-ndjson")],
+            Body::from_stream(lines),
+        )
+            .into_response()
+    }
+
+    async fn generate(
+        self,
+        mut on_token: Option<Box<dyn FnMut(String) + Send>>,
+    ) -> anyhow::Result<Outcome> {
+        let first_token = Arc::new(OnceLock::new());
+        let first = Arc::clone(&first_token);
+        let callback: Box<dyn FnMut(String) + Send> = Box::new(move |token| {
+            let _ = first.set(Instant::now());
+            if let Some(on_token) = on_token.as_mut() {
+                on_token(token);
+            }
+        });
+        let began = Instant::now();
+        let generation = self
+            .loaded
+            .generate_with_finish(&self.prompt, self.opts, Some(callback))
+            .await?;
+        let ended = Instant::now();
+        let first = first_token.get().copied().unwrap_or(ended);
+
+        let count = |text: &str| {
+            self.loaded
+                .count_tokens(text)
+                .unwrap_or_else(|| crate::truncation::estimate_tokens(text))
+        };
+        let stats = Stats {
+            total_duration: nanos(ended.saturating_duration_since(self.started)),
+            load_duration: nanos(self.load_duration),
+            prompt_eval_count: count(&self.prompt).saturating_sub(generation.cached_tokens),
+            prompt_eval_duration: nanos(first.saturating_duration_since(began)),
+            eval_count: count(&generation.text),
+            eval_duration: nanos(ended.saturating_duration_since(first)),
+        };
+        let (content, calls) = match &self.tools {
+            Some(plan) => plan.extract(&generation.text),
+            None => (generation.text, Vec::new()),
+        };
+        Ok(Outcome {
+            content,
+            tool_calls: calls.into_iter().map(OllamaToolCall::from).collect(),
+            done_reason: done_reason(generation.finish_reason),
+            stats,
+        })
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+fn to_line(response: &OllamaResponse) -> String {
+    serde_json::to_string(response).unwrap_or_else(|e| {
+        tracing::error!("Failed to serialize Ollama response: {}", e);
+        "{}".to_string()
+    })
+}
+
+/// A model in `/api/tags`
+#[derive(Debug, Serialize)]
+pub struct OllamaModel {
+    pub name: String,
+    pub model: String,
+    pub modified_at: String,
+    pub size: u64,
+    /// Identifies the file by path, size and modification time; unlike
+    /// Ollama's it isn't a hash of the contents
+    pub digest: String,
+    pub details: OllamaModelDetails,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OllamaModelDetails {
+    pub format: String,
+    pub family: String,
+    pub parameter_size: String,
+    pub quantization_level: String,
+}
+
+/// `GET /api/tags`: every available model
+pub async fn tags(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let models: Vec<OllamaModel> = state
+        .registry
+        .list_all_available()
+        .into_iter()
+        .filter(|name| !state.registry.is_embedding_model(name))
+        .filter_map(|name| {
+            let spec = state.registry.to_spec(&name)?;
+            Some(describe(&state, spec))
+        })
+        .collect();
+    Json(serde_json::json!({ "models": models }))
+}
+
+fn describe(state: &AppState, spec: ModelSpec) -> OllamaModel {
+    use sha2::{Digest, Sha256};
+
+    let meta = std::fs::metadata(&spec.base_path).ok();
+    let size = meta.as_ref().map(|m| m.len()).unwrap_or(0);
+    let modified = meta
+        .and_then(|m| m.modified().ok())
+        .map(chrono::DateTime::<chrono::Utc>::from)
+        .unwrap_or_default();
+    let digest = Sha256::digest(format!(
+        "{}\0{}\0{}",
+        spec.base_path.display(),
+        size,
+        modified.timestamp_nanos_opt().unwrap_or_default()
+    ));
+    let discovered = state.registry.discovered_models.get(&spec.name);
+    OllamaModel {
+        model: spec.name.clone(),
+        name: spec.name,
+        modified_at: modified.to_rfc3339(),
+        size,
+        digest: hex::encode(digest),
+        details: OllamaModelDetails {
+            format: spec
+                .base_path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+            family: discovered
+                .map(|m| m.model_type.to_lowercase())
+                .filter(|family| family != "unknown")
+                .unwrap_or_default(),
+            parameter_size: discovered
+                .and_then(|m| m.parameter_count.clone())
+                .unwrap_or_default(),
+            quantization_level: discovered
+                .and_then(|m| m.quantization.clone())
+                .unwrap_or_default(),
+        },
+    }
+}
+
+/// Body of `POST /api/show`; older clients send `name`
+#[derive(Debug, Deserialize)]
+pub struct ShowRequest {
+    #[serde(alias = "name")]
+    pub model: String,
+}
+
+/// `POST /api/show`: a model's details and context length
+pub async fn show(State(state): State<Arc<AppState>>, Json(req): Json<ShowRequest>) -> Response {
+    let (_, spec) = match resolve(&state, &req.model) {
+        Ok(found) => found,
+        Err(e) => return error_response(e),
+    };
+    let ctx_len = spec.ctx_len;
+    let template = spec.template.clone().unwrap_or_default();
+    let model = describe(&state, spec);
+    let architecture = if model.details.family.is_empty() {
+        "llama".to_string()
+    } else {
+        model.details.family.clone()
+    };
+    Json(serde_json::json!({
+        "modelfile": "",
+        "parameters": "",
+        "template": template,
+        "details": model.details,
+        "model_info": {
+            "general.architecture": architecture,
+            format!("{}.context_length", architecture): ctx_len,
+        },
+        "capabilities": ["completion", "tools"],
+        "modified_at": model.modified_at,
+    }))
+    .into_response()
+}
+
+/// `GET /api/version`
+pub async fn version() -> impl IntoResponse {
+    Json(serde_json::json!({ "version": env!("CARGO_PKG_VERSION") }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_dialect_detection() {
+        let native = serde_json::json!({"model": "m", "prompt": "hi", "max_tokens": 8});
+        assert!(!is_ollama_generate(&native, false));
+        assert!(is_ollama_generate(&native, true));
+        let ollama =
+            serde_json::json!({"model": "m", "prompt": "hi", "options": {"num_predict": 8}});
+        assert!(is_ollama_generate(&ollama, false));
+        assert!(is_ollama_generate(
+            &serde_json::json!({"model": "m", "raw": true}),
+            false
+        ));
+    }
+
+    #[test]
+    fn test_options_and_format() {
+        let req: OllamaGenerateRequest = serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "prompt": "hi",
+            "options": {"temperature": 0.2, "num_predict": 64, "seed": 7, "stop": ["\n\n"], "num_ctx": 4096},
+            "keep_alive": "10m"
+        }))
+        .unwrap();
+        let opts = gen_options(req.stream, &req.options);
+        assert!(opts.stream);
+        assert_eq!(opts.temperature, 0.2);
+        assert_eq!(opts.max_tokens, 64);
+        assert_eq!(opts.seed, Some(7));
+        assert_eq!(opts.stop_tokens, vec!["\n\n".to_string()]);
+        assert_eq!(
+            req.keep_alive,
+            Some(KeepAlive::Idle(Duration::from_secs(600)))
+        );
+
+        let unlimited = OllamaOptions {
+            num_predict: Some(-1),
+            ..Default::default()
+        };
+        assert_eq!(
+            gen_options(Some(false), &unlimited).max_tokens,
+            GenOptions::default().max_tokens
+        );
+
+        let json = Some(crate::engine::grammar::JSON_OBJECT.to_string());
+        assert_eq!(format_grammar(None).unwrap(), None);
+        assert_eq!(format_grammar(Some(&"json".into())).unwrap(), json);
+        assert_eq!(
+            format_grammar(Some(&serde_json::json!({"type": "object"}))).unwrap(),
+            json
+        );
+        assert_eq!(
+            format_grammar(Some(&"yaml".into())).unwrap_err().param,
+            "format"
+        );
+    }
+
+    #[test]
+    fn test_chat_messages_and_replies() {
+        let req: OllamaChatRequest = serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "messages": [
+                {"role": "user", "content": "Weather in Paris?"},
+                {"role": "assistant", "content": "", "tool_calls": [
+                    {"function": {"name": "get_weather", "arguments": {"city": "Paris"}}}
+                ]},
+                {"role": "tool", "content": "18C"}
+            ]
+        }))
+        .unwrap();
+        let messages = chat_messages(req.messages);
+        assert_eq!(
+            messages[1].content,
+            "<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"city\":\"Paris\"}}\n</tool_call>"
+        );
+        assert_eq!(messages[2].role, "user");
+
+        let call = OllamaToolCall::from(tool_calling::ToolCall {
+            id: "call_1".to_string(),
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: r#"{"city":"Paris"}"#.to_string(),
+            },
+        });
+        let chunk = serde_json::to_value(OllamaResponse::new(
+            Kind::Chat,
+            "m",
+            String::new(),
+            vec![call],
+        ))
+        .unwrap();
+        assert_eq!(chunk["done"], false);
+        assert_eq!(chunk["message"]["role"], "assistant");
+        assert_eq!(
+            chunk["message"]["tool_calls"][0]["function"]["arguments"]["city"],
+            "Paris"
+        );
+        assert!(chunk.get("response").is_none() && chunk.get("eval_count").is_none());
+
+        let stats = Stats {
+            total_duration: 5,
+            load_duration: 1,
+            prompt_eval_count: 12,
+            prompt_eval_duration: 2,
+            eval_count: 3,
+            eval_duration: 2,
+        };
+        let done = serde_json::to_value(
+            OllamaResponse::new(Kind::Generate, "m", String::new(), Vec::new())
+                .done(done_reason(FinishReason::Length), Some(stats)),
+        )
+        .unwrap();
+        assert_eq!(done["response"], "");
+        assert_eq!(done["done"], true);
+        assert_eq!(done["done_reason"], "length");
+        assert_eq!(done["eval_count"], 3);
+        assert!(done.get("message").is_none());
+    }
+}
@@ -0,0 +1,163 @@
+//! One-shot generation (`shimmy run`) for shell scripts.
+//!
+//! The prompt comes from `-p`, piped stdin, or both (stdin is appended after the
+//! flag text, so `cat notes.md | shimmy run phi3 -p "Summarize:"` works). Text
+//! mode streams tokens to stdout; `--json` prints a single object with usage stats.
+
+use crate::engine::{GenOptions, InferenceEngine};
+use crate::model_registry::Registry;
+use serde::Serialize;
+use std::io::{IsTerminal, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Options for a single `shimmy run` invocation
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub system: Option<String>,
+    pub raw: bool,
+    pub json: bool,
+    pub gen: GenOptions,
+}
+
+/// `--json` output
+#[derive(Debug, Serialize)]
+pub struct RunOutput {
+    pub model: String,
+    pub response: String,
+    pub usage: RunUsage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunUsage {
+    pub prompt_chars: usize,
+    /// Token pieces streamed by the backend
+    pub completion_tokens: usize,
+    pub load_ms: u64,
+    pub generate_ms: u64,
+    pub tokens_per_second: f64,
+}
+
+/// Combine the `-p` text with piped stdin
+pub fn resolve_prompt(flag: Option<String>, piped: Option<String>) -> anyhow::Result<String> {
+    match (flag, piped) {
+        (Some(flag), Some(piped)) => Ok(format!("{}\n\n{}", flag, piped)),
+        (Some(prompt), None) | (None, Some(prompt)) => Ok(prompt),
+        (None, None) => anyhow::bail!("no prompt: pass -p \"...\" or pipe text on stdin"),
+    }
+}
+
+/// Read stdin when it is piped; `None` for an interactive terminal or empty input
+fn read_piped_stdin() -> std::io::Result<Option<String>> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Ok(None);
+    }
+    let mut input = String::new();
+    stdin.lock().read_to_string(&mut input)?;
+    let input = input.trim_end().to_string();
+    Ok((!input.is_empty()).then_some(input))
+}
+
+/// Load `model`, generate once, and print the result
+pub async fn run(
+    engine: &dyn InferenceEngine,
+    registry: &Registry,
+    model: &str,
+    prompt: Option<String>,
+    opts: RunOptions,
+) -> anyhow::Result<()> {
+    let input = resolve_prompt(prompt, read_piped_stdin()?)?;
+    let Some(spec) = registry.to_spec(model) else {
+        anyhow::bail!("no model {model}");
+    };
+
+    let mut gen = opts.gen;
+    let prompt = if opts.raw {
+        input.clone()
+    } else {
+        let family = crate::chat::template_family(spec.template.as_deref(), model);
+        gen.stop_tokens.extend(family.stop_tokens());
+        family.render(opts.system.as_deref(), &[], Some(&input))
+    };
+
+    let load_start = Instant::now();
+    let loaded = engine.load(&spec).await?;
+    let load_ms = load_start.elapsed().as_millis() as u64;
+
+    let tokens = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&tokens);
+    let stream_to_stdout = !opts.json;
+    let on_token: Box<dyn FnMut(String) + Send> = Box::new(move |piece| {
+        counter.fetch_add(1, Ordering::Relaxed);
+        if stream_to_stdout {
+            print!("{}", piece);
+            let _ = std::io::stdout().flush();
+        }
+    });
+
+    let gen_start = Instant::now();
+    let response = loaded.generate(&prompt, gen, Some(on_token)).await?;
+    let generate_secs = gen_start.elapsed().as_secs_f64();
+
+    if opts.json {
+        let completion_tokens = tokens.load(Ordering::Relaxed);
+        let output = RunOutput {
+            model: model.to_string(),
+            response,
+            usage: RunUsage {
+                prompt_chars: input.chars().count(),
+                completion_tokens,
+                load_ms,
+                generate_ms: (generate_secs * 1000.0) as u64,
+                tokens_per_second: if generate_secs > 0.0 {
+                    completion_tokens as f64 / generate_secs
+                } else {
+                    0.0
+                },
+            },
+        };
+        println!("{}", serde_json::to_string(&output)?);
+    } else if tokens.load(Ordering::Relaxed) == 0 {
+        // Backend didn't stream; print the full response instead
+        println!("{}", response);
+    } else if !response.ends_with('\n') {
+        println!();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_prompt_sources() {
+        assert_eq!(resolve_prompt(Some("hi".into()), None).unwrap(), "hi");
+        assert_eq!(resolve_prompt(None, Some("piped".into())).unwrap(), "piped");
+        assert_eq!(
+            resolve_prompt(Some("Summarize:".into()), Some("text".into())).unwrap(),
+            "Summarize:\n\ntext"
+        );
+        assert!(resolve_prompt(None, None).is_err());
+    }
+
+    #[test]
+    fn test_run_output_json_shape() {
+        let output = RunOutput {
+            model: "phi3".into(),
+            response: "ok".into(),
+            usage: RunUsage {
+                prompt_chars: 2,
+                completion_tokens: 1,
+                load_ms: 10,
+                generate_ms: 20,
+                tokens_per_second: 50.0,
+            },
+        };
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["response"], "ok");
+        assert_eq!(json["usage"]["completion_tokens"], 1);
+    }
+}
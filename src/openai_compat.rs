@@ -1,6 +1,8 @@
 #![allow(dead_code)]
 
-use crate::{api::ChatMessage, AppState};
+use crate::engine::TokenDetail;
+use crate::tool_calling::{self, Tool, ToolCall, ToolCallDelta, ToolChoice, ToolPlan};
+use crate::{api::ChatMessage, truncation::Truncation, AppState};
 use axum::{extract::State, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -8,9 +10,70 @@ use std::sync::Arc;
 #[derive(Debug, Deserialize)]
 pub struct ChatCompletionRequest {
     pub model: String,
+    #[serde(deserialize_with = "deserialize_messages")]
     pub messages: Vec<ChatMessage>,
     #[serde(default)]
     pub stream: Option<bool>,
+    /// `{"include_usage": true}` adds a last chunk with the token usage
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub stop: Option<StopTokens>,
+    /// Wall-clock budget for generation; partial output ends with `finish_reason: "time"`
+    #[serde(default)]
+    pub max_time_ms: Option<u64>,
+    /// Functions the model may call; calls come back as `tool_calls`
+    #[serde(default)]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(default)]
+    pub tool_choice: Option<ToolChoice>,
+    /// `{"type": "json_object"}` constrains the reply to a JSON object
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+    /// GBNF grammar the reply must match; takes the place of `response_format`
+    #[serde(default)]
+    pub grammar: Option<String>,
+    /// How long to keep the model loaded afterwards: seconds or a duration
+    /// such as "10m"; 0 unloads it, -1 keeps it
+    #[serde(default)]
+    pub keep_alive: Option<crate::model_cache::KeepAlive>,
+    /// Registered LoRA adapter to apply, by name (default: the model's own)
+    #[serde(default)]
+    pub adapter: Option<String>,
+    /// Report the log-probability of each generated token
+    #[serde(default)]
+    pub logprobs: Option<bool>,
+    /// With `logprobs`, also report this many of the most likely tokens at
+    /// each position (0-20)
+    #[serde(default)]
+    pub top_logprobs: Option<usize>,
+}
+
+/// `stream_options` of a streaming chat completion request
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+/// Body of the legacy `POST /v1/completions`
+#[derive(Debug, Deserialize)]
+pub struct CompletionRequest {
+    pub model: String,
+    /// Text to continue; with `suffix`, the code before the cursor
+    pub prompt: String,
+    /// Code after the cursor: the model fills in what goes between `prompt`
+    /// and `suffix`, using its fill-in-the-middle tokens
+    #[serde(default)]
+    pub suffix: Option<String>,
+    #[serde(default)]
+    pub stream: Option<bool>,
     #[serde(default)]
     pub temperature: Option<f32>,
     #[serde(default)]
@@ -19,6 +82,56 @@ pub struct ChatCompletionRequest {
     pub top_p: Option<f32>,
     #[serde(default)]
     pub stop: Option<StopTokens>,
+    #[serde(default)]
+    pub max_time_ms: Option<u64>,
+    #[serde(default)]
+    pub keep_alive: Option<crate::model_cache::KeepAlive>,
+    #[serde(default)]
+    pub adapter: Option<String>,
+}
+
+/// A request message as OpenAI clients send it, including function-calling
+/// turns
+#[derive(Debug, Deserialize)]
+struct RequestMessage {
+    role: String,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<ToolCall>>,
+}
+
+/// Read messages into plain [`ChatMessage`]s, rendering assistant
+/// `tool_calls` and `tool` results as text the model can follow
+fn deserialize_messages<'de, D>(deserializer: D) -> Result<Vec<ChatMessage>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let messages = Vec::<RequestMessage>::deserialize(deserializer)?;
+    Ok(messages
+        .into_iter()
+        .map(|m| {
+            let mut content = m.content.unwrap_or_default();
+            match m.role.as_str() {
+                "tool" => ChatMessage {
+                    role: "user".to_string(),
+                    content: tool_calling::render_response(&content),
+                },
+                _ => {
+                    for call in m.tool_calls.unwrap_or_default() {
+                        if !content.is_empty() {
+                            content.push('\n');
+                        }
+                        content.push_str(&tool_calling::render_call(&call.function));
+                    }
+                    ChatMessage {
+                        role: m.role,
+                        content,
+                    }
+                }
+            }
+        })
+        .collect())
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,6 +150,59 @@ impl StopTokens {
     }
 }
 
+/// `response_format` of a chat completion request
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    JsonObject,
+    /// Replies validate against `json_schema.schema`
+    JsonSchema {
+        json_schema: JsonSchemaFormat,
+    },
+}
+
+/// `json_schema` of a `{"type": "json_schema"}` response format
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct JsonSchemaFormat {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Missing means any JSON value
+    #[serde(default)]
+    pub schema: Option<serde_json::Value>,
+    /// Accepted for compatibility; the schema is always enforced
+    #[serde(default)]
+    pub strict: Option<bool>,
+}
+
+/// Grammar for the request's `grammar` or `response_format`, which are
+/// mutually exclusive
+fn resolve_grammar(
+    grammar: Option<String>,
+    format: Option<&ResponseFormat>,
+) -> Result<Option<String>, crate::engine::InvalidParameter> {
+    match (grammar, format) {
+        (Some(_), Some(ResponseFormat::JsonObject | ResponseFormat::JsonSchema { .. })) => {
+            Err(crate::engine::InvalidParameter::new(
+                "grammar",
+                "`grammar` and a JSON `response_format` are mutually exclusive; send one or the other",
+            ))
+        }
+        (Some(grammar), _) => Ok(Some(grammar)),
+        (None, Some(ResponseFormat::JsonObject)) => {
+            Ok(Some(crate::engine::grammar::JSON_OBJECT.to_string()))
+        }
+        (None, Some(ResponseFormat::JsonSchema { json_schema })) => {
+            let any = serde_json::Value::Bool(true);
+            crate::engine::json_schema::to_grammar(json_schema.schema.as_ref().unwrap_or(&any))
+                .map(Some)
+        }
+        (None, _) => Ok(None),
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct ChatCompletionResponse {
     pub id: String,
@@ -45,20 +211,100 @@ pub struct ChatCompletionResponse {
     pub model: String,
     pub choices: Vec<Choice>,
     pub usage: Usage,
+    /// Messages dropped to fit the context window, when any were
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncation: Option<Truncation>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct Choice {
     pub index: usize,
-    pub message: ChatMessage,
+    pub message: ResponseMessage,
+    /// Null unless the request set `logprobs`
+    pub logprobs: Option<ChoiceLogprobs>,
     pub finish_reason: Option<String>,
 }
 
+/// Log-probabilities of the tokens of a reply, or of one streamed chunk
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChoiceLogprobs {
+    pub content: Vec<TokenLogprob>,
+    /// Always null, as in [`ResponseMessage`]
+    #[serde(default)]
+    pub refusal: Option<Vec<TokenLogprob>>,
+}
+
+impl ChoiceLogprobs {
+    fn new(content: Vec<TokenLogprob>) -> Self {
+        Self {
+            content,
+            refusal: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    /// Null when the backend doesn't report log-probabilities; only the
+    /// llama.cpp backend does
+    pub logprob: Option<f32>,
+    /// UTF-8 bytes of `token`
+    pub bytes: Vec<u8>,
+    /// Most likely tokens at this position, as many as `top_logprobs` asked for
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f32,
+    pub bytes: Vec<u8>,
+}
+
+impl From<crate::engine::TokenDetail> for TokenLogprob {
+    fn from(detail: crate::engine::TokenDetail) -> Self {
+        Self {
+            bytes: detail.text.as_bytes().to_vec(),
+            logprob: detail.logprob,
+            top_logprobs: detail
+                .top_logprobs
+                .into_iter()
+                .map(|top| TopLogprob {
+                    bytes: top.text.as_bytes().to_vec(),
+                    token: top.text,
+                    logprob: top.logprob,
+                })
+                .collect(),
+            token: detail.text,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResponseMessage {
+    pub role: String,
+    /// Empty when the reply is only function calls
+    pub content: String,
+    /// Always null: a local model's refusal is ordinary content
+    pub refusal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Usage {
     pub prompt_tokens: usize,
     pub completion_tokens: usize,
     pub total_tokens: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PromptTokensDetails {
+    /// Prompt tokens reused from the model's KV cache
+    pub cached_tokens: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -68,12 +314,22 @@ pub struct ChatCompletionChunk {
     pub created: u64,
     pub model: String,
     pub choices: Vec<ChunkChoice>,
+    /// Sent on the first chunk when messages were dropped to fit the context
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncation: Option<Truncation>,
+    /// Sent on a last chunk with no choices when the request asked for it
+    /// with `stream_options.include_usage`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChunkChoice {
     pub index: usize,
     pub delta: Delta,
+    /// The chunk's token when the request set `logprobs`, otherwise null
+    #[serde(default)]
+    pub logprobs: Option<ChoiceLogprobs>,
     pub finish_reason: Option<String>,
 }
 
@@ -81,6 +337,33 @@ pub struct ChunkChoice {
 pub struct Delta {
     pub content: Option<String>,
     pub role: Option<String>,
+    /// Always null, as in [`ResponseMessage`]
+    #[serde(default)]
+    pub refusal: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+/// A `/v1/completions` response, or one chunk of a streamed one
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<CompletionChoice>,
+    /// Only on non-streaming responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompletionChoice {
+    pub text: String,
+    pub index: usize,
+    /// Always null; log-probabilities aren't reported here
+    pub logprobs: Option<serde_json::Value>,
+    pub finish_reason: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -95,6 +378,12 @@ pub struct ListModel {
     pub object: String,
     pub created: u64,
     pub owned_by: String,
+    /// Estimated runtime memory from the model file size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_runtime_gb: Option<f64>,
+    /// Whether the model fits in this machine's memory: fits, tight or insufficient
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_fit: Option<crate::util::memory::MemoryStatus>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -112,18 +401,34 @@ pub struct Model {
 }
 
 pub async fn models(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    use crate::util::memory::{sample_memory, MemoryStatus};
+
+    // No fit verdict when the build can't read system memory
+    let memory = sample_memory();
+
     let models = state
         .registry
         .list_all_available()
         .into_iter()
-        .map(|name| ListModel {
-            id: name,
-            object: "model".to_string(),
-            created: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs(),
-            owned_by: "shimmy".to_string(),
+        .map(|name| {
+            let estimate = state.registry.memory_estimate(&name);
+            ListModel {
+                estimated_runtime_gb: estimate.as_ref().map(|e| e.estimated_runtime_gb),
+                memory_fit: estimate.zip(memory.as_ref()).map(|(e, m)| {
+                    MemoryStatus::classify(
+                        e.estimated_runtime_gb,
+                        m.total_bytes as f64 / 1_024_000_000.0,
+                        m.available_bytes as f64 / 1_024_000_000.0,
+                    )
+                }),
+                id: name,
+                object: "model".to_string(),
+                created: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                owned_by: "shimmy".to_string(),
+            }
         })
         .collect();
 
@@ -140,6 +445,9 @@ pub async fn chat_completions(
     use axum::http::StatusCode;
 
     // Load and validate model
+    if let Some(rejection) = state.registry.rejection(&req.model) {
+        return crate::error::ShimmyError::Load(rejection).into_response();
+    }
     let Some(spec) = state.registry.to_spec(&req.model) else {
         tracing::warn!("Model '{}' not found in registry", req.model);
         let available_models = state.registry.list_all_available();
@@ -153,59 +461,19 @@ pub async fn chat_completions(
         });
         return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
     };
-    tracing::debug!("Found model spec for '{}': {:?}", req.model, spec);
-    let engine = &state.engine;
-    let loaded = match engine.load(&spec).await {
-        Ok(loaded) => loaded,
-        Err(e) => {
-            tracing::error!("Failed to load model '{}': {:?}", req.model, e);
-            return StatusCode::BAD_GATEWAY.into_response();
-        }
-    };
-
-    // Construct prompt from messages
-    let fam = match spec.template.as_deref() {
-        Some("chatml") => crate::templates::TemplateFamily::ChatML,
-        Some("llama3") | Some("llama-3") => crate::templates::TemplateFamily::Llama3,
-        _ => {
-            // Auto-detect template based on model name
-            if req.model.to_lowercase().contains("qwen")
-                || req.model.to_lowercase().contains("chatglm")
-            {
-                crate::templates::TemplateFamily::ChatML
-            } else if req.model.to_lowercase().contains("llama") {
-                crate::templates::TemplateFamily::Llama3
-            } else {
-                crate::templates::TemplateFamily::OpenChat
-            }
+    if state.registry.is_embedding_model(&req.model) {
+        return crate::error::ShimmyError::InvalidRequest {
+            reason: format!(
+                "Model '{}' is an embedding model; use /v1/embeddings",
+                req.model
+            ),
         }
-    };
-    let pairs = req
-        .messages
-        .iter()
-        .map(|m| (m.role.clone(), m.content.clone()))
-        .collect::<Vec<_>>();
-
-    // For chat completions, we need to trigger assistant response
-    // Extract the last user message to use as input parameter
-    let last_user_message = req
-        .messages
-        .iter()
-        .rfind(|m| m.role == "user")
-        .map(|m| m.content.as_str());
-
-    // Build conversation history without the last user message
-    let history: Vec<_> = if last_user_message.is_some() {
-        req.messages
-            .iter()
-            .take(req.messages.len().saturating_sub(1))
-            .map(|m| (m.role.clone(), m.content.clone()))
-            .collect()
-    } else {
-        pairs.clone()
-    };
+        .into_response();
+    }
+    tracing::debug!("Found model spec for '{}': {:?}", req.model, spec);
 
-    let prompt = fam.render(None, &history, last_user_message);
+    // Pick the chat template
+    let fam = template_family(spec.template.as_deref(), &req.model);
 
     // Set generation options
     let mut opts = crate::engine::GenOptions::default();
@@ -229,16 +497,89 @@ pub async fn chat_completions(
         stop_tokens.extend(user_stop.into_vec());
     }
     opts.stop_tokens = stop_tokens;
+    opts.max_time_ms = req.max_time_ms;
+    opts.deadline = crate::deadline::current();
+    opts.api_key = crate::api_keys::current();
+    opts.rate_limit = crate::rate_limit::current();
+    opts.adapter =
+        match crate::api::adapter_path(&state.registry, &req.model, req.adapter.as_deref()) {
+            Ok(adapter) => adapter,
+            Err(e) => return e.into_response(),
+        };
+    opts.grammar = match resolve_grammar(req.grammar.clone(), req.response_format.as_ref()) {
+        Ok(grammar) => grammar,
+        Err(e) => return crate::error::ShimmyError::from(e).into_response(),
+    };
+    let logprobs = req.logprobs.unwrap_or(false);
+    if req.top_logprobs.is_some() && !logprobs {
+        return crate::error::ShimmyError::from(crate::engine::InvalidParameter::new(
+            "top_logprobs",
+            "`top_logprobs` is only allowed when `logprobs` is true",
+        ))
+        .into_response();
+    }
+    opts.top_logprobs = req.top_logprobs.unwrap_or(0);
+
+    if let Err(e) = opts.validate() {
+        return crate::error::ShimmyError::from(e).into_response();
+    }
+    if req.stream_options.is_some() && !opts.stream {
+        return crate::error::ShimmyError::from(crate::engine::InvalidParameter::new(
+            "stream_options",
+            "`stream_options` is only allowed when `stream` is true",
+        ))
+        .into_response();
+    }
+    let include_usage = req.stream_options.as_ref().is_some_and(|o| o.include_usage);
+    let tool_plan = match ToolPlan::new(
+        req.tools.as_deref().unwrap_or_default(),
+        req.tool_choice.as_ref(),
+    ) {
+        Ok(plan) => plan,
+        Err(reason) => {
+            return crate::error::ShimmyError::InvalidRequest { reason }.into_response();
+        }
+    };
+    let messages = match &tool_plan {
+        Some(plan) => with_system_prompt(&req.messages, plan.system_prompt()),
+        None => req.messages.clone(),
+    };
+
+    let loaded = match state.load_model_keep_alive(&spec, req.keep_alive).await {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            tracing::error!("Failed to load model '{}': {:?}", req.model, e);
+            return crate::api_errors::model_load_failed_response(&req.model, e);
+        }
+    };
+    if let Err(e) = crate::api::check_adapter_support(&*loaded, &opts, &req.model) {
+        return e.into_response();
+    }
+
+    // Drop the oldest turns if the conversation overflows the context window
+    let (prompt, truncation) = crate::truncation::fit_messages(
+        &messages,
+        spec.ctx_len.saturating_sub(opts.max_tokens),
+        |messages| render_chat(&fam, messages),
+        |text| {
+            loaded
+                .count_tokens(text)
+                .unwrap_or_else(|| crate::truncation::estimate_tokens(text))
+        },
+    );
 
     if opts.stream {
         // Handle streaming response with proper OpenAI format
-        use axum::response::sse::{Event, Sse};
+        use axum::response::sse::Event;
         use tokio_stream::wrappers::UnboundedReceiverStream;
         use tokio_stream::StreamExt;
 
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<String>();
         let mut opts_clone = opts.clone();
         opts_clone.stream = false;
+        // A failed send means the client hung up; stop generating for it
+        let cancel = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        opts_clone.cancel = Some(cancel.clone());
         let prompt_clone = prompt.clone();
         let model_clone = req.model.clone();
         let timestamp = std::time::SystemTime::now()
@@ -265,77 +606,237 @@ pub async fn chat_completions(
                     delta: Delta {
                         role: Some("assistant".to_string()),
                         content: None,
+                        tool_calls: None,
+                        refusal: None,
                     },
+                    logprobs: None,
                     finish_reason: None,
                 }],
+                truncation,
+                usage: None,
             };
             let _ = tx_tokens.send(serde_json::to_string(&initial_chunk).unwrap_or_else(|e| {
                 tracing::error!("Failed to serialize initial chunk: {}", e);
                 "{}".to_string()
             }));
 
-            // Generate and stream tokens
-            let _ = loaded
-                .generate(
-                    &prompt_clone,
-                    opts_clone,
-                    Some(Box::new(move |tok| {
-                        let chunk = ChatCompletionChunk {
-                            id: id_for_tokens.clone(),
-                            object: "chat.completion.chunk".to_string(),
-                            created: timestamp,
-                            model: model_for_tokens.clone(),
-                            choices: vec![ChunkChoice {
-                                index: 0,
-                                delta: Delta {
-                                    role: None,
-                                    content: Some(tok),
-                                },
-                                finish_reason: None,
-                            }],
-                        };
-                        let _ = tx_tokens.send(serde_json::to_string(&chunk).unwrap_or_else(|e| {
-                            tracing::error!("Failed to serialize chunk: {}", e);
-                            "{}".to_string()
-                        }));
-                    })),
-                )
-                .await;
+            // Generate and stream tokens; with tools the reply is held back
+            // until it can be told apart from a function call, and goes out
+            // without logprobs
+            let on_token: Box<dyn FnMut(TokenDetail) + Send> = if tool_plan.is_some() {
+                Box::new(move |_| {
+                    if tx_tokens.is_closed() {
+                        cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+                    }
+                })
+            } else {
+                Box::new(move |token: TokenDetail| {
+                    let tok = token.text.clone();
+                    let chunk = ChatCompletionChunk {
+                        id: id_for_tokens.clone(),
+                        object: "chat.completion.chunk".to_string(),
+                        created: timestamp,
+                        model: model_for_tokens.clone(),
+                        choices: vec![ChunkChoice {
+                            index: 0,
+                            delta: Delta {
+                                role: None,
+                                content: Some(tok),
+                                tool_calls: None,
+                                refusal: None,
+                            },
+                            logprobs: logprobs
+                                .then(|| ChoiceLogprobs::new(vec![TokenLogprob::from(token)])),
+                            finish_reason: None,
+                        }],
+                        truncation: None,
+                        usage: None,
+                    };
+                    let sent = tx_tokens.send(serde_json::to_string(&chunk).unwrap_or_else(|e| {
+                        tracing::error!("Failed to serialize chunk: {}", e);
+                        "{}".to_string()
+                    }));
+                    if sent.is_err() {
+                        cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+                    }
+                })
+            };
+            let result = if logprobs {
+                loaded
+                    .generate_detailed(&prompt_clone, opts_clone, Some(on_token))
+                    .await
+            } else {
+                let mut on_token = on_token;
+                let on_text = move |text| {
+                    on_token(TokenDetail {
+                        text,
+                        ..Default::default()
+                    })
+                };
+                loaded
+                    .generate_with_finish(&prompt_clone, opts_clone, Some(Box::new(on_text)))
+                    .await
+            };
+            let (text, mut finish_reason, cached_tokens) = match result {
+                Ok(generation) => (
+                    generation.text,
+                    generation.finish_reason,
+                    generation.cached_tokens,
+                ),
+                Err(e) => {
+                    tracing::error!("Streaming generation failed: {}", e);
+                    (String::new(), crate::engine::FinishReason::Stop, 0)
+                }
+            };
+            let usage = include_usage.then(|| {
+                let count = |text: &str| {
+                    loaded
+                        .count_tokens(text)
+                        .unwrap_or_else(|| crate::truncation::estimate_tokens(text))
+                };
+                let prompt_tokens = count(&prompt_clone);
+                let completion_tokens = count(&text);
+                Usage {
+                    prompt_tokens,
+                    completion_tokens,
+                    total_tokens: prompt_tokens + completion_tokens,
+                    prompt_tokens_details: Some(PromptTokensDetails { cached_tokens }),
+                }
+            });
+            if let Some(plan) = &tool_plan {
+                let (content, tool_calls) = plan.extract(&text);
+                if !tool_calls.is_empty() {
+                    finish_reason = crate::engine::FinishReason::ToolCalls;
+                }
+                // Content first, then each call the way OpenAI streams it:
+                // its name, then its arguments in fragments
+                let mut deltas = Vec::new();
+                if !content.is_empty() {
+                    deltas.push(Delta {
+                        role: None,
+                        content: Some(content),
+                        refusal: None,
+                        tool_calls: None,
+                    });
+                }
+                for (index, call) in tool_calls.into_iter().enumerate() {
+                    deltas.extend(call.into_deltas(index).into_iter().map(|call| Delta {
+                        role: None,
+                        content: None,
+                        refusal: None,
+                        tool_calls: Some(vec![call]),
+                    }));
+                }
+                for delta in deltas {
+                    let chunk = ChatCompletionChunk {
+                        id: id_for_final.clone(),
+                        object: "chat.completion.chunk".to_string(),
+                        created: timestamp,
+                        model: model_for_final.clone(),
+                        choices: vec![ChunkChoice {
+                            index: 0,
+                            delta,
+                            logprobs: None,
+                            finish_reason: None,
+                        }],
+                        truncation: None,
+                        usage: None,
+                    };
+                    let _ = tx.send(serde_json::to_string(&chunk).unwrap_or_else(|e| {
+                        tracing::error!("Failed to serialize chunk: {}", e);
+                        "{}".to_string()
+                    }));
+                }
+            }
+            if finish_reason == crate::engine::FinishReason::Cancelled {
+                tracing::info!(
+                    "Client disconnected; stopped generating for '{}'",
+                    model_for_final
+                );
+            }
 
             // Send final chunk
             let final_chunk = ChatCompletionChunk {
-                id: id_for_final,
+                id: id_for_final.clone(),
                 object: "chat.completion.chunk".to_string(),
                 created: timestamp,
-                model: model_for_final,
+                model: model_for_final.clone(),
                 choices: vec![ChunkChoice {
                     index: 0,
                     delta: Delta {
                         role: None,
                         content: None,
+                        tool_calls: None,
+                        refusal: None,
                     },
-                    finish_reason: Some("stop".to_string()),
+                    logprobs: None,
+                    finish_reason: Some(finish_reason.as_str().to_string()),
                 }],
+                truncation: None,
+                usage: None,
             };
             let _ = tx.send(serde_json::to_string(&final_chunk).unwrap_or_else(|e| {
                 tracing::error!("Failed to serialize final chunk: {}", e);
                 "{}".to_string()
             }));
+            if let Some(usage) = usage {
+                let usage_chunk = ChatCompletionChunk {
+                    id: id_for_final,
+                    object: "chat.completion.chunk".to_string(),
+                    created: timestamp,
+                    model: model_for_final,
+                    choices: Vec::new(),
+                    truncation: None,
+                    usage: Some(usage),
+                };
+                let _ = tx.send(serde_json::to_string(&usage_chunk).unwrap_or_else(|e| {
+                    tracing::error!("Failed to serialize usage chunk: {}", e);
+                    "{}".to_string()
+                }));
+            }
             let _ = tx.send("[DONE]".to_string());
         });
 
         let stream = UnboundedReceiverStream::new(rx)
             .map(|s| Ok::<Event, std::convert::Infallible>(Event::default().data(s)));
-        Sse::new(stream).into_response()
+        crate::api::sse_response(stream)
     } else {
         // Handle non-streaming response
-        match loaded.generate(&prompt, opts, None).await {
-            Ok(content) => {
+        let tokens = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let result = if logprobs {
+            let sink = Arc::clone(&tokens);
+            let on_token = move |token: TokenDetail| {
+                sink.lock().unwrap().push(TokenLogprob::from(token));
+            };
+            loaded
+                .generate_detailed(&prompt, opts, Some(Box::new(on_token)))
+                .await
+        } else {
+            loaded.generate_with_finish(&prompt, opts, None).await
+        };
+        match result {
+            Ok(generation) => {
+                let (content, tool_calls) = match &tool_plan {
+                    Some(plan) => plan.extract(&generation.text),
+                    None => (generation.text, Vec::new()),
+                };
+                let finish_reason = if tool_calls.is_empty() {
+                    generation.finish_reason
+                } else {
+                    crate::engine::FinishReason::ToolCalls
+                };
                 tracing::debug!(
                     "Generated response for model '{}': {} chars",
                     req.model,
                     content.len()
                 );
+                let count = |text: &str| {
+                    loaded
+                        .count_tokens(text)
+                        .unwrap_or_else(|| crate::truncation::estimate_tokens(text))
+                };
+                let prompt_tokens = count(&prompt);
+                let completion_tokens = count(&content);
                 let response = ChatCompletionResponse {
                     id: format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
                     object: "chat.completion".to_string(),
@@ -346,17 +847,26 @@ pub async fn chat_completions(
                     model: req.model,
                     choices: vec![Choice {
                         index: 0,
-                        message: ChatMessage {
+                        message: ResponseMessage {
                             role: "assistant".to_string(),
                             content,
+                            tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                            refusal: None,
                         },
-                        finish_reason: Some("stop".to_string()),
+                        logprobs: logprobs.then(|| {
+                            ChoiceLogprobs::new(std::mem::take(&mut *tokens.lock().unwrap()))
+                        }),
+                        finish_reason: Some(finish_reason.as_str().to_string()),
                     }],
                     usage: Usage {
-                        prompt_tokens: 0, // Token counting not needed for local inference
-                        completion_tokens: 0,
-                        total_tokens: 0,
+                        prompt_tokens,
+                        completion_tokens,
+                        total_tokens: prompt_tokens + completion_tokens,
+                        prompt_tokens_details: Some(PromptTokensDetails {
+                            cached_tokens: generation.cached_tokens,
+                        }),
                     },
+                    truncation,
                 };
                 Json(response).into_response()
             }
@@ -372,6 +882,256 @@ pub async fn chat_completions(
     }
 }
 
+/// Legacy text completion: `prompt` is sent as is, without a chat template,
+/// or with `suffix` wrapped in the model's fill-in-the-middle tokens
+pub async fn completions(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CompletionRequest>,
+) -> axum::response::Response {
+    complete(state, req, crate::engine::GenOptions::default(), None).await
+}
+
+/// Run a text completion, with the request's options overriding `opts`;
+/// `on_done` runs once generation has ended, however it ended
+pub(crate) async fn complete(
+    state: Arc<AppState>,
+    req: CompletionRequest,
+    mut opts: crate::engine::GenOptions,
+    on_done: Option<Box<dyn FnOnce() + Send>>,
+) -> axum::response::Response {
+    use crate::error::ShimmyError;
+
+    if let Some(rejection) = state.registry.rejection(&req.model) {
+        return ShimmyError::Load(rejection).into_response();
+    }
+    let Some(spec) = state.registry.to_spec(&req.model) else {
+        return ShimmyError::ModelNotFound { name: req.model }.into_response();
+    };
+    if state.registry.is_embedding_model(&req.model) {
+        return ShimmyError::InvalidRequest {
+            reason: format!(
+                "Model '{}' is an embedding model; use /v1/embeddings",
+                req.model
+            ),
+        }
+        .into_response();
+    }
+
+    let (prompt, mut stop_tokens) = match &req.suffix {
+        Some(suffix) => {
+            let Some(fim) =
+                crate::templates::FimFormat::detect(spec.template.as_deref(), &req.model)
+            else {
+                return ShimmyError::from(crate::engine::InvalidParameter::new(
+                    "suffix",
+                    format!(
+                        "Model '{}' has no known fill-in-the-middle format; `suffix` works \
+                         with codellama, deepseek-coder and starcoder models",
+                        req.model
+                    ),
+                ))
+                .into_response();
+            };
+            (fim.render(&req.prompt, suffix), fim.stop_tokens())
+        }
+        None => (req.prompt.clone(), Vec::new()),
+    };
+    if let Some(user_stop) = req.stop {
+        stop_tokens.extend(user_stop.into_vec());
+    }
+
+    if let Some(t) = req.temperature {
+        opts.temperature = t;
+    }
+    if let Some(p) = req.top_p {
+        opts.top_p = p;
+    }
+    if let Some(m) = req.max_tokens {
+        opts.max_tokens = m;
+    }
+    opts.stop_tokens = stop_tokens;
+    opts.max_time_ms = req.max_time_ms;
+    opts.deadline = crate::deadline::current();
+    opts.api_key = crate::api_keys::current();
+    opts.rate_limit = crate::rate_limit::current();
+    opts.adapter =
+        match crate::api::adapter_path(&state.registry, &req.model, req.adapter.as_deref()) {
+            Ok(adapter) => adapter,
+            Err(e) => return e.into_response(),
+        };
+    if let Err(e) = opts.validate() {
+        return ShimmyError::from(e).into_response();
+    }
+
+    let loaded = match state.load_model_keep_alive(&spec, req.keep_alive).await {
+        Ok(loaded) => loaded,
+        Err(e) => return crate::api_errors::model_load_failed_response(&req.model, e),
+    };
+    if let Err(e) = crate::api::check_adapter_support(&*loaded, &opts, &req.model) {
+        return e.into_response();
+    }
+
+    let id = format!("cmpl-{}", uuid::Uuid::new_v4().simple());
+    let created = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let model = req.model;
+    let response = move |text: String, finish_reason: Option<String>, usage: Option<Usage>| {
+        CompletionResponse {
+            id: id.clone(),
+            object: "text_completion".to_string(),
+            created,
+            model: model.clone(),
+            choices: vec![CompletionChoice {
+                text,
+                index: 0,
+                logprobs: None,
+                finish_reason,
+            }],
+            usage,
+        }
+    };
+
+    if !req.stream.unwrap_or(false) {
+        let result = loaded.generate_with_finish(&prompt, opts, None).await;
+        if let Some(on_done) = on_done {
+            on_done();
+        }
+        return match result {
+            Ok(generation) => {
+                let count = |text: &str| {
+                    loaded
+                        .count_tokens(text)
+                        .unwrap_or_else(|| crate::truncation::estimate_tokens(text))
+                };
+                let prompt_tokens = count(&prompt);
+                let completion_tokens = count(&generation.text);
+                let usage = Usage {
+                    prompt_tokens,
+                    completion_tokens,
+                    total_tokens: prompt_tokens + completion_tokens,
+                    prompt_tokens_details: Some(PromptTokensDetails {
+                        cached_tokens: generation.cached_tokens,
+                    }),
+                };
+                let finish_reason = generation.finish_reason.as_str().to_string();
+                Json(response(generation.text, Some(finish_reason), Some(usage))).into_response()
+            }
+            Err(e) => ShimmyError::GenerationError {
+                reason: e.to_string(),
+            }
+            .into_response(),
+        };
+    }
+
+    use axum::response::sse::Event;
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tokio_stream::StreamExt;
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    // A failed send means the client hung up; stop generating for it
+    let cancel = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    opts.cancel = Some(cancel.clone());
+    tokio::spawn(async move {
+        let tx_tokens = tx.clone();
+        let chunk = response.clone();
+        let on_token: Box<dyn FnMut(String) + Send> = Box::new(move |tok| {
+            let json = serde_json::to_string(&chunk(tok, None, None)).unwrap_or_default();
+            if tx_tokens.send(json).is_err() {
+                cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+        });
+        let finish_reason = match loaded
+            .generate_with_finish(&prompt, opts, Some(on_token))
+            .await
+        {
+            Ok(generation) => generation.finish_reason,
+            Err(e) => {
+                tracing::error!("Streaming completion failed: {}", e);
+                crate::engine::FinishReason::Stop
+            }
+        };
+        if let Some(on_done) = on_done {
+            on_done();
+        }
+        let last = response(
+            String::new(),
+            Some(finish_reason.as_str().to_string()),
+            None,
+        );
+        let _ = tx.send(serde_json::to_string(&last).unwrap_or_default());
+        let _ = tx.send("[DONE]".to_string());
+    });
+
+    let stream = UnboundedReceiverStream::new(rx)
+        .map(|s| Ok::<Event, std::convert::Infallible>(Event::default().data(s)));
+    crate::api::sse_response(stream)
+}
+
+/// Add `prompt` to the system message, inserting one if there is none
+pub(crate) fn with_system_prompt(messages: &[ChatMessage], prompt: String) -> Vec<ChatMessage> {
+    let mut messages = messages.to_vec();
+    match messages.first_mut() {
+        Some(first) if first.role == "system" => {
+            first.content = format!("{}\n\n{}", first.content, prompt);
+        }
+        _ => messages.insert(
+            0,
+            ChatMessage {
+                role: "system".to_string(),
+                content: prompt,
+            },
+        ),
+    }
+    messages
+}
+
+/// Chat template for a model: the registry's `template`, else a guess from
+/// the model name
+pub fn template_family(template: Option<&str>, model: &str) -> crate::templates::TemplateFamily {
+    match template {
+        Some("chatml") => crate::templates::TemplateFamily::ChatML,
+        Some("llama3") | Some("llama-3") => crate::templates::TemplateFamily::Llama3,
+        _ => {
+            // Auto-detect template based on model name
+            if model.to_lowercase().contains("qwen") || model.to_lowercase().contains("chatglm") {
+                crate::templates::TemplateFamily::ChatML
+            } else if model.to_lowercase().contains("llama") {
+                crate::templates::TemplateFamily::Llama3
+            } else {
+                crate::templates::TemplateFamily::OpenChat
+            }
+        }
+    }
+}
+
+/// Render a conversation, leaving the last user message as the open turn
+///
+/// This is the prompt `/v1/chat/completions` sends the model.
+pub fn render_chat(fam: &crate::templates::TemplateFamily, messages: &[ChatMessage]) -> String {
+    // For chat completions, we need to trigger assistant response
+    // Extract the last user message to use as input parameter
+    let last_user_message = messages
+        .iter()
+        .rfind(|m| m.role == "user")
+        .map(|m| m.content.as_str());
+
+    // Build conversation history without the last user message
+    let take = if last_user_message.is_some() {
+        messages.len().saturating_sub(1)
+    } else {
+        messages.len()
+    };
+    let history: Vec<_> = messages
+        .iter()
+        .take(take)
+        .map(|m| (m.role.clone(), m.content.clone()))
+        .collect();
+
+    fam.render(None, &history, last_user_message)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -394,7 +1154,17 @@ mod tests {
             max_tokens: None,
             top_p: None,
             stream: Some(false),
+            stream_options: None,
             stop: None,
+            max_time_ms: None,
+            tools: None,
+            tool_choice: None,
+            response_format: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
+            logprobs: None,
+            top_logprobs: None,
         };
 
         // Exercise handler code path (will gracefully fail due to no model)
@@ -422,17 +1192,22 @@ mod tests {
             model: "test-model".to_string(),
             choices: vec![Choice {
                 index: 0,
-                message: ChatMessage {
+                message: ResponseMessage {
                     role: "assistant".to_string(),
                     content: "Hello world".to_string(),
+                    tool_calls: None,
+                    refusal: None,
                 },
+                logprobs: None,
                 finish_reason: Some("stop".to_string()),
             }],
             usage: Usage {
                 prompt_tokens: 10,
                 completion_tokens: 5,
                 total_tokens: 15,
+                prompt_tokens_details: None,
             },
+            truncation: None,
         };
 
         assert_eq!(response.id, "test-id");
@@ -447,7 +1222,10 @@ mod tests {
             delta: Delta {
                 role: Some("assistant".to_string()),
                 content: Some("token".to_string()),
+                tool_calls: None,
+                refusal: None,
             },
+            logprobs: None,
             finish_reason: None,
         };
 
@@ -468,10 +1246,20 @@ mod tests {
                 content: "Hello".to_string(),
             }],
             stream: Some(false),
+            stream_options: None,
             temperature: None,
             max_tokens: None,
             top_p: None,
             stop: None,
+            max_time_ms: None,
+            tools: None,
+            tool_choice: None,
+            response_format: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
+            logprobs: None,
+            top_logprobs: None,
         };
 
         let _response = chat_completions(State(state), Json(request)).await;
@@ -494,6 +1282,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = Box::new(InferenceEngineAdapter::new());
@@ -510,6 +1300,16 @@ mod tests {
             max_tokens: Some(100),
             top_p: Some(0.9),
             stop: None,
+            max_time_ms: None,
+            tools: None,
+            tool_choice: None,
+            response_format: None,
+            grammar: None,
+            keep_alive: None,
+            stream_options: None,
+            adapter: None,
+            logprobs: None,
+            top_logprobs: None,
         };
 
         // Exercise streaming path (lines 132-213)
@@ -530,6 +1330,8 @@ mod tests {
             template: Some("llama3".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = Box::new(InferenceEngineAdapter::new());
@@ -552,6 +1354,16 @@ mod tests {
             max_tokens: Some(50),
             top_p: Some(0.8),
             stop: None,
+            max_time_ms: None,
+            tools: None,
+            tool_choice: None,
+            response_format: None,
+            grammar: None,
+            keep_alive: None,
+            stream_options: None,
+            adapter: None,
+            logprobs: None,
+            top_logprobs: None,
         };
 
         // Exercise non-streaming path (lines 214-244)
@@ -572,6 +1384,8 @@ mod tests {
             template: Some("chatml".to_string()),
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         let fam = match spec_chatml.template.as_deref() {
@@ -589,6 +1403,8 @@ mod tests {
             template: Some("llama3".to_string()),
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         let fam = match spec_llama3.template.as_deref() {
@@ -606,6 +1422,8 @@ mod tests {
             template: Some("unknown".to_string()),
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         let fam = match spec_default.template.as_deref() {
@@ -650,6 +1468,57 @@ mod tests {
         assert!(opts.stream);
     }
 
+    #[test]
+    fn test_truncation_serialized_only_when_present() {
+        let mut response = ChatCompletionResponse {
+            id: "test-id".to_string(),
+            object: "chat.completion".to_string(),
+            created: 1234567890,
+            model: "test-model".to_string(),
+            choices: vec![],
+            usage: Usage {
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+                prompt_tokens_details: None,
+            },
+            truncation: None,
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert!(json.get("truncation").is_none());
+
+        response.truncation = Some(Truncation {
+            dropped_messages: vec![1, 2],
+            dropped_tokens: 40,
+            prompt_tokens: 90,
+            budget_tokens: 100,
+        });
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            json["truncation"]["dropped_messages"],
+            serde_json::json!([1, 2])
+        );
+        assert_eq!(json["truncation"]["dropped_tokens"], 40);
+    }
+
+    #[test]
+    fn test_render_chat_leaves_last_user_turn_open() {
+        let messages = vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: "Be brief.".to_string(),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: "Hi".to_string(),
+            },
+        ];
+        let prompt = render_chat(&crate::templates::TemplateFamily::ChatML, &messages);
+        assert!(prompt.starts_with("<|im_start|>system\nBe brief.<|im_end|>"));
+        assert!(prompt.contains("<|im_start|>user\nHi<|im_end|>"));
+        assert!(prompt.ends_with("<|im_start|>assistant\n"));
+    }
+
     #[test]
     fn test_chat_completion_chunk_serialization() {
         let chunk = ChatCompletionChunk {
@@ -662,9 +1531,14 @@ mod tests {
                 delta: Delta {
                     role: Some("assistant".to_string()),
                     content: Some("Hello".to_string()),
+                    tool_calls: None,
+                    refusal: None,
                 },
+                logprobs: None,
                 finish_reason: None,
             }],
+            truncation: None,
+            usage: None,
         };
 
         let json = serde_json::to_string(&chunk).unwrap();
@@ -682,6 +1556,8 @@ mod tests {
         let delta = Delta {
             role: Some("assistant".to_string()),
             content: None,
+            tool_calls: None,
+            refusal: None,
         };
 
         assert_eq!(delta.role.as_ref().unwrap(), "assistant");
@@ -693,6 +1569,8 @@ mod tests {
         let delta = Delta {
             role: None,
             content: Some("token".to_string()),
+            tool_calls: None,
+            refusal: None,
         };
 
         assert!(delta.role.is_none());
@@ -705,6 +1583,7 @@ mod tests {
             prompt_tokens: 10,
             completion_tokens: 20,
             total_tokens: 30,
+            prompt_tokens_details: None,
         };
 
         assert_eq!(usage.prompt_tokens, 10);
@@ -726,12 +1605,16 @@ mod tests {
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    estimated_runtime_gb: None,
+                    memory_fit: None,
                 },
                 ListModel {
                     id: "model2".to_string(),
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    estimated_runtime_gb: None,
+                    memory_fit: None,
                 },
             ],
         };
@@ -780,14 +1663,150 @@ mod tests {
         assert_eq!(request.top_p, Some(0.9));
     }
 
+    #[test]
+    fn test_chat_completion_request_with_tools() {
+        let json_str = r#"{
+            "model": "test-model",
+            "messages": [
+                {"role": "user", "content": "Weather in Paris?"},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function",
+                     "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "18C"}
+            ],
+            "tools": [{"type": "function", "function": {"name": "get_weather"}}],
+            "tool_choice": "auto"
+        }"#;
+
+        let request: ChatCompletionRequest = serde_json::from_str(json_str).unwrap();
+        assert_eq!(request.tools.as_ref().unwrap().len(), 1);
+        assert!(matches!(request.tool_choice, Some(ToolChoice::Mode(ref m)) if m == "auto"));
+        assert_eq!(
+            request.messages[1].content,
+            "<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"city\":\"Paris\"}}\n</tool_call>"
+        );
+        assert_eq!(request.messages[2].role, "user");
+        assert_eq!(
+            request.messages[2].content,
+            "<tool_response>\n18C\n</tool_response>"
+        );
+
+        let messages = with_system_prompt(&request.messages, "Use tools.".to_string());
+        assert_eq!(messages[0].role, "system");
+        assert_eq!(messages.len(), 4);
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            kind: "function".to_string(),
+            function: tool_calling::FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: "{}".to_string(),
+            },
+        };
+        let choice = Choice {
+            index: 0,
+            message: ResponseMessage {
+                role: "assistant".to_string(),
+                content: String::new(),
+                tool_calls: Some(vec![call]),
+                refusal: None,
+            },
+            logprobs: None,
+            finish_reason: Some("tool_calls".to_string()),
+        };
+        let json = serde_json::to_value(&choice).unwrap();
+        assert_eq!(
+            json["message"]["tool_calls"][0]["function"]["name"],
+            "get_weather"
+        );
+        assert_eq!(json["message"]["tool_calls"][0]["type"], "function");
+        assert!(json["message"]["tool_calls"][0].get("index").is_none());
+    }
+
+    #[test]
+    fn test_token_logprobs_format() {
+        let token = TokenLogprob::from(TokenDetail {
+            text: "é".to_string(),
+            id: Some(5),
+            logprob: Some(-0.25),
+            top_logprobs: vec![crate::engine::TopLogprob {
+                text: "e".to_string(),
+                id: 6,
+                logprob: -1.5,
+            }],
+        });
+        let json = serde_json::to_value(ChoiceLogprobs::new(vec![token])).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "content": [{
+                    "token": "é",
+                    "logprob": -0.25,
+                    "bytes": [195, 169],
+                    "top_logprobs": [{"token": "e", "logprob": -1.5, "bytes": [101]}]
+                }],
+                "refusal": null
+            })
+        );
+
+        let request: ChatCompletionRequest = serde_json::from_str(
+            r#"{"model": "m", "messages": [], "logprobs": true, "top_logprobs": 3}"#,
+        )
+        .unwrap();
+        assert_eq!(request.logprobs, Some(true));
+        assert_eq!(request.top_logprobs, Some(3));
+    }
+
+    #[test]
+    fn test_response_format_selects_grammar() {
+        let request: ChatCompletionRequest = serde_json::from_str(
+            r#"{"model": "m", "messages": [], "response_format": {"type": "json_object"}}"#,
+        )
+        .unwrap();
+        assert_eq!(request.response_format, Some(ResponseFormat::JsonObject));
+        assert_eq!(
+            resolve_grammar(None, request.response_format.as_ref()).unwrap(),
+            Some(crate::engine::grammar::JSON_OBJECT.to_string())
+        );
+
+        assert_eq!(
+            resolve_grammar(None, Some(&ResponseFormat::Text)).unwrap(),
+            None
+        );
+        let custom = "root ::= \"yes\" | \"no\"".to_string();
+        assert_eq!(
+            resolve_grammar(Some(custom.clone()), None).unwrap(),
+            Some(custom.clone())
+        );
+        let err = resolve_grammar(Some(custom), Some(&ResponseFormat::JsonObject)).unwrap_err();
+        assert_eq!(err.param, "grammar");
+
+        let request: ChatCompletionRequest = serde_json::from_str(
+            r#"{"model": "m", "messages": [], "response_format": {"type": "json_schema",
+                "json_schema": {"name": "answer", "strict": true, "schema": {
+                    "type": "object", "properties": {"ok": {"type": "boolean"}},
+                    "required": ["ok"]}}}}"#,
+        )
+        .unwrap();
+        let grammar = resolve_grammar(None, request.response_format.as_ref())
+            .unwrap()
+            .unwrap();
+        assert!(grammar.starts_with("root ::= \"{\" ws \"\\\"ok\\\"\" ws"));
+        assert!(crate::engine::grammar::validate(&grammar).is_ok());
+    }
+
     #[test]
     fn test_finish_reason_values() {
         let choice = Choice {
             index: 0,
-            message: ChatMessage {
+            message: ResponseMessage {
                 role: "assistant".to_string(),
                 content: "Response".to_string(),
+                tool_calls: None,
+                refusal: None,
             },
+            logprobs: None,
             finish_reason: Some("stop".to_string()),
         };
 
@@ -798,7 +1817,10 @@ mod tests {
             delta: Delta {
                 role: None,
                 content: None,
+                tool_calls: None,
+                refusal: None,
             },
+            logprobs: None,
             finish_reason: Some("length".to_string()),
         };
 
@@ -843,6 +1865,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
         registry.register(ModelEntry {
             name: "another-model".to_string(),
@@ -851,6 +1875,8 @@ mod tests {
             template: Some("llama3".into()),
             ctx_len: Some(4096),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = Box::new(InferenceEngineAdapter::new());
@@ -878,6 +1904,8 @@ mod tests {
             template: Some("chatml".into()),
             ctx_len: Some(4096),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         registry.register(ModelEntry {
@@ -887,6 +1915,8 @@ mod tests {
             template: Some("llama3".into()),
             ctx_len: Some(8192),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         });
 
         let engine = Box::new(InferenceEngineAdapter::new());
@@ -910,10 +1940,20 @@ mod tests {
                 },
             ],
             stream: Some(false),
+            stream_options: None,
             temperature: Some(0.7),
             max_tokens: Some(100),
             top_p: Some(0.9),
             stop: None,
+            max_time_ms: None,
+            tools: None,
+            tool_choice: None,
+            response_format: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
+            logprobs: None,
+            top_logprobs: None,
         };
 
         // Skip actual model loading in tests - models don't exist
@@ -927,10 +1967,20 @@ mod tests {
                 content: "Count to 3".to_string(),
             }],
             stream: Some(true),
+            stream_options: None,
             temperature: Some(0.5),
             max_tokens: Some(50),
             top_p: None,
             stop: None,
+            max_time_ms: None,
+            tools: None,
+            tool_choice: None,
+            response_format: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
+            logprobs: None,
+            top_logprobs: None,
         };
 
         // Skip actual model loading in tests - models don't exist
@@ -988,10 +2038,20 @@ mod tests {
                 content: "This should fail".to_string(),
             }],
             stream: Some(false),
+            stream_options: None,
             temperature: None,
             max_tokens: None,
             top_p: None,
             stop: None,
+            max_time_ms: None,
+            tools: None,
+            tool_choice: None,
+            response_format: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
+            logprobs: None,
+            top_logprobs: None,
         };
 
         let _response = chat_completions(State(state), Json(invalid_request)).await;
@@ -1013,17 +2073,22 @@ mod tests {
             model: "test-model".to_string(),
             choices: vec![Choice {
                 index: 0,
-                message: ChatMessage {
+                message: ResponseMessage {
                     role: "assistant".to_string(),
                     content: "Hello world".to_string(),
+                    tool_calls: None,
+                    refusal: None,
                 },
+                logprobs: None,
                 finish_reason: Some("stop".to_string()),
             }],
             usage: Usage {
                 prompt_tokens: 10,
                 completion_tokens: 5,
                 total_tokens: 15,
+                prompt_tokens_details: None,
             },
+            truncation: None,
         };
 
         // Serialize to JSON to verify structure
@@ -1046,12 +2111,16 @@ mod tests {
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    estimated_runtime_gb: None,
+                    memory_fit: None,
                 },
                 ListModel {
                     id: "test-model-2".to_string(),
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    estimated_runtime_gb: None,
+                    memory_fit: None,
                 },
             ],
         };
@@ -1075,9 +2144,14 @@ mod tests {
                 delta: Delta {
                     role: Some("assistant".to_string()),
                     content: Some("Hello".to_string()),
+                    tool_calls: None,
+                    refusal: None,
                 },
+                logprobs: None,
                 finish_reason: None,
             }],
+            truncation: None,
+            usage: None,
         };
 
         let json = serde_json::to_value(&chunk).unwrap();
@@ -1090,6 +2164,67 @@ mod tests {
         assert_eq!(choice["index"], 0);
         assert_eq!(choice["delta"]["role"], "assistant");
         assert_eq!(choice["delta"]["content"], "Hello");
+        assert!(choice["delta"]["refusal"].is_null());
         assert!(choice["finish_reason"].is_null());
+        assert!(json.get("usage").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_completions_suffix_needs_fim_model() {
+        use crate::model_registry::ModelEntry;
+
+        let mut registry = Registry::default();
+        registry.register(ModelEntry {
+            name: "phi3-mini".to_string(),
+            base_path: "./phi3-mini.gguf".into(),
+            lora_path: None,
+            template: Some("chatml".into()),
+            ctx_len: Some(2048),
+            n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
+        });
+        let engine = Box::new(InferenceEngineAdapter::new());
+        let state = Arc::new(AppState::new(engine, registry));
+
+        let request: CompletionRequest = serde_json::from_str(
+            r#"{"model": "phi3-mini", "prompt": "def f():", "suffix": "    return 1"}"#,
+        )
+        .unwrap();
+        let response = completions(State(state), Json(request)).await;
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "INVALID_PARAMETER");
+    }
+
+    #[test]
+    fn test_stream_options_usage_chunk() {
+        let request: ChatCompletionRequest = serde_json::from_str(
+            r#"{"model": "m", "messages": [], "stream": true, "stream_options": {"include_usage": true}}"#,
+        )
+        .unwrap();
+        assert!(request.stream_options.unwrap().include_usage);
+
+        let chunk = ChatCompletionChunk {
+            id: "chatcmpl-usage".to_string(),
+            object: "chat.completion.chunk".to_string(),
+            created: 1234567890,
+            model: "test-model".to_string(),
+            choices: Vec::new(),
+            truncation: None,
+            usage: Some(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 3,
+                total_tokens: 15,
+                prompt_tokens_details: Some(PromptTokensDetails { cached_tokens: 8 }),
+            }),
+        };
+        let json = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(json["choices"], serde_json::json!([]));
+        assert_eq!(json["usage"]["total_tokens"], 15);
+        assert_eq!(json["usage"]["prompt_tokens_details"]["cached_tokens"], 8);
     }
 }
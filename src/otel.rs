@@ -0,0 +1,104 @@
+//! OpenTelemetry export of tracing spans.
+//!
+//! With `--otlp-endpoint <url>` (or `SHIMMY_OTLP_ENDPOINT`, falling back to
+//! the standard `OTEL_EXPORTER_OTLP_ENDPOINT`) spans are sent over OTLP/gRPC to
+//! a collector such as Jaeger (`http://localhost:4317`). Every HTTP request
+//! gets an `http.request` span with children for the time spent waiting for a
+//! slot (`queue.wait`), model loads (`model.load`), generation
+//! (`llm.generate`, split into `llm.prompt_eval` and `llm.decode` on the
+//! llama backend) and vision preprocessing (`vision.preprocess`).
+//!
+//! Export needs a build with the `otel` feature; other builds say so at
+//! startup and carry on without it.
+
+pub const OTLP_ENDPOINT_ENV: &str = "SHIMMY_OTLP_ENDPOINT";
+const STANDARD_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// The collector to export to, if any
+///
+/// The flag wins over the environment. An empty `SHIMMY_OTLP_ENDPOINT` turns
+/// export off even when the standard variable is set.
+pub fn endpoint(flag: Option<&str>) -> Option<String> {
+    let non_empty = |v: String| {
+        let v = v.trim().to_string();
+        (!v.is_empty()).then_some(v)
+    };
+    if let Some(flag) = flag {
+        return non_empty(flag.to_string());
+    }
+    match std::env::var(OTLP_ENDPOINT_ENV) {
+        Ok(value) => non_empty(value),
+        Err(_) => std::env::var(STANDARD_ENDPOINT_ENV)
+            .ok()
+            .and_then(non_empty),
+    }
+}
+
+/// Flushes buffered spans when dropped at shutdown
+#[cfg(feature = "otel")]
+pub struct OtelGuard(opentelemetry_sdk::trace::TracerProvider);
+
+#[cfg(feature = "otel")]
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.shutdown() {
+            eprintln!("⚠️  Could not flush OpenTelemetry spans: {}", e);
+        }
+    }
+}
+
+/// A tracing layer exporting spans to `endpoint`; needs a Tokio runtime
+#[cfg(feature = "otel")]
+pub fn layer<S>(
+    endpoint: &str,
+) -> anyhow::Result<(
+    tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>,
+    OtelGuard,
+)>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(opentelemetry_sdk::Resource::new([
+            opentelemetry::KeyValue::new("service.name", crate::branding::APP_NAME),
+            opentelemetry::KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ]))
+        .build();
+    let tracer = provider.tracer(crate::branding::APP_NAME);
+    Ok((
+        tracing_opentelemetry::layer().with_tracer(tracer),
+        OtelGuard(provider),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[serial_test::serial]
+    fn test_endpoint_resolution() {
+        std::env::remove_var(OTLP_ENDPOINT_ENV);
+        std::env::set_var(STANDARD_ENDPOINT_ENV, "http://collector:4317");
+        assert_eq!(endpoint(None).as_deref(), Some("http://collector:4317"));
+        assert_eq!(
+            endpoint(Some("http://jaeger:4317")).as_deref(),
+            Some("http://jaeger:4317")
+        );
+
+        std::env::set_var(OTLP_ENDPOINT_ENV, "");
+        assert_eq!(endpoint(None), None);
+
+        std::env::remove_var(OTLP_ENDPOINT_ENV);
+        std::env::remove_var(STANDARD_ENDPOINT_ENV);
+        assert_eq!(endpoint(None), None);
+    }
+}
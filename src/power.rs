@@ -0,0 +1,291 @@
+//! Low-power mode for laptops.
+//!
+//! Inference keeps every core it is given busy at full clock, which on a
+//! laptop means a draining battery and loud fans. The low-power profile trades
+//! speed for draw: llama.cpp contexts created while it is active use at most
+//! [`LOW_POWER_MAX_THREADS`] threads and prompt batches of at most
+//! [`LOW_POWER_MAX_BATCH`] tokens.
+//!
+//! `SHIMMY_POWER_MODE` (or `power_mode` in `shimmy.toml`) sets the mode at
+//! startup and `POST /api/system/power` changes it at runtime:
+//!
+//! - `auto` (default): low power while the machine runs on battery
+//! - `low`: always low power
+//! - `normal`: never
+//!
+//! A generation context is sized when its model loads, so a change reaches
+//! models loaded afterwards; embeddings get a fresh context per request and
+//! follow it right away. Battery state comes from `/sys/class/power_supply`
+//! on Linux and `pmset` on macOS; elsewhere `auto` behaves like `normal`.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const POWER_MODE_ENV: &str = "SHIMMY_POWER_MODE";
+
+/// Thread cap for inference in the low-power profile
+pub const LOW_POWER_MAX_THREADS: i32 = 4;
+
+/// Prompt batch cap (tokens) in the low-power profile
+pub const LOW_POWER_MAX_BATCH: u32 = 512;
+
+/// How long a battery reading is trusted
+const BATTERY_RECHECK: Duration = Duration::from_secs(30);
+
+/// Requested power behaviour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerMode {
+    Auto,
+    Normal,
+    Low,
+}
+
+impl PowerMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "auto" => Some(PowerMode::Auto),
+            "normal" => Some(PowerMode::Normal),
+            "low" => Some(PowerMode::Low),
+            _ => None,
+        }
+    }
+
+    fn from_env() -> Self {
+        let value = std::env::var(POWER_MODE_ENV).unwrap_or_default();
+        Self::parse(&value).unwrap_or_else(|| {
+            tracing::warn!(
+                "Ignoring {}={:?} (expected auto, normal or low)",
+                POWER_MODE_ENV,
+                value
+            );
+            PowerMode::Auto
+        })
+    }
+}
+
+/// Settings actually in effect
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerProfile {
+    Normal,
+    LowPower,
+}
+
+impl PowerProfile {
+    pub fn for_mode(mode: PowerMode, on_battery: Option<bool>) -> Self {
+        match mode {
+            PowerMode::Low => PowerProfile::LowPower,
+            PowerMode::Auto if on_battery == Some(true) => PowerProfile::LowPower,
+            PowerMode::Auto | PowerMode::Normal => PowerProfile::Normal,
+        }
+    }
+
+    /// Thread cap under this profile, if any
+    pub fn max_threads(self) -> Option<i32> {
+        (self == PowerProfile::LowPower).then_some(LOW_POWER_MAX_THREADS)
+    }
+
+    /// Batch cap under this profile, if any
+    pub fn max_batch(self) -> Option<u32> {
+        (self == PowerProfile::LowPower).then_some(LOW_POWER_MAX_BATCH)
+    }
+
+    #[cfg_attr(not(feature = "llama"), allow(dead_code))]
+    pub fn cap_threads(self, threads: i32) -> i32 {
+        self.max_threads().map_or(threads, |max| threads.min(max))
+    }
+
+    #[cfg_attr(not(feature = "llama"), allow(dead_code))]
+    pub fn cap_batch(self, batch: u32) -> u32 {
+        self.max_batch().map_or(batch, |max| batch.min(max))
+    }
+}
+
+/// What `/api/system` reports about power
+#[derive(Debug, Clone, Serialize)]
+pub struct PowerStatus {
+    pub mode: PowerMode,
+    pub profile: PowerProfile,
+    /// `None` when there is no battery or it can't be read
+    pub on_battery: Option<bool>,
+    pub max_threads: Option<i32>,
+    pub max_batch: Option<u32>,
+}
+
+/// Process-wide power mode and cached battery state
+#[derive(Debug)]
+pub struct PowerManager {
+    /// Set through the API; `None` until then, meaning the environment decides
+    mode: Mutex<Option<PowerMode>>,
+    battery: Mutex<Option<(Instant, Option<bool>)>>,
+}
+
+impl PowerManager {
+    const fn new() -> Self {
+        Self {
+            mode: Mutex::new(None),
+            battery: Mutex::new(None),
+        }
+    }
+
+    pub fn global() -> &'static PowerManager {
+        static GLOBAL: PowerManager = PowerManager::new();
+        &GLOBAL
+    }
+
+    pub fn mode(&self) -> PowerMode {
+        let mode = *self.mode.lock().unwrap_or_else(|e| e.into_inner());
+        mode.unwrap_or_else(PowerMode::from_env)
+    }
+
+    pub fn set_mode(&self, mode: PowerMode) {
+        *self.mode.lock().unwrap_or_else(|e| e.into_inner()) = Some(mode);
+        tracing::info!("Power mode set to {:?}", mode);
+    }
+
+    /// Whether the machine runs on battery, re-read every [`BATTERY_RECHECK`]
+    pub fn on_battery(&self) -> Option<bool> {
+        let mut cached = self.battery.lock().unwrap_or_else(|e| e.into_inner());
+        match *cached {
+            Some((at, on_battery)) if at.elapsed() < BATTERY_RECHECK => on_battery,
+            _ => {
+                let on_battery = detect_on_battery();
+                *cached = Some((Instant::now(), on_battery));
+                on_battery
+            }
+        }
+    }
+
+    #[cfg_attr(not(feature = "llama"), allow(dead_code))]
+    pub fn profile(&self) -> PowerProfile {
+        match self.mode() {
+            PowerMode::Auto => PowerProfile::for_mode(PowerMode::Auto, self.on_battery()),
+            mode => PowerProfile::for_mode(mode, None),
+        }
+    }
+
+    pub fn status(&self) -> PowerStatus {
+        let mode = self.mode();
+        let on_battery = self.on_battery();
+        let profile = PowerProfile::for_mode(mode, on_battery);
+        PowerStatus {
+            mode,
+            profile,
+            on_battery,
+            max_threads: profile.max_threads(),
+            max_batch: profile.max_batch(),
+        }
+    }
+}
+
+/// Whether this machine is running on battery right now
+pub fn detect_on_battery() -> Option<bool> {
+    #[cfg(target_os = "linux")]
+    {
+        battery_from_sysfs(Path::new("/sys/class/power_supply"))
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let output = std::process::Command::new("pmset")
+            .args(["-g", "batt"])
+            .output()
+            .ok()?;
+        battery_from_pmset(&String::from_utf8_lossy(&output.stdout))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        None
+    }
+}
+
+/// Read `/sys/class/power_supply`: on battery if any battery is discharging
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn battery_from_sysfs(dir: &Path) -> Option<bool> {
+    let read = |path: &Path| {
+        std::fs::read_to_string(path)
+            .ok()
+            .map(|s| s.trim().to_string())
+    };
+    let mut has_battery = false;
+    for supply in std::fs::read_dir(dir).ok()?.flatten().map(|e| e.path()) {
+        if read(&supply.join("type")).as_deref() != Some("Battery") {
+            continue;
+        }
+        has_battery = true;
+        if read(&supply.join("status")).as_deref() == Some("Discharging") {
+            return Some(true);
+        }
+    }
+    has_battery.then_some(false)
+}
+
+/// Read `pmset -g batt` output, whose first line names the power source
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn battery_from_pmset(output: &str) -> Option<bool> {
+    let first = output.lines().next()?;
+    if first.contains("'Battery Power'") {
+        Some(true)
+    } else if first.contains("'AC Power'") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_caps() {
+        assert_eq!(
+            PowerProfile::for_mode(PowerMode::Auto, Some(true)),
+            PowerProfile::LowPower
+        );
+        assert_eq!(
+            PowerProfile::for_mode(PowerMode::Auto, None),
+            PowerProfile::Normal
+        );
+        assert_eq!(
+            PowerProfile::for_mode(PowerMode::Normal, Some(true)),
+            PowerProfile::Normal
+        );
+
+        let low = PowerProfile::LowPower;
+        assert_eq!(low.cap_threads(12), LOW_POWER_MAX_THREADS);
+        assert_eq!(low.cap_threads(2), 2);
+        assert_eq!(low.cap_batch(2048), LOW_POWER_MAX_BATCH);
+        assert_eq!(PowerProfile::Normal.cap_batch(2048), 2048);
+        assert_eq!(PowerMode::parse("LOW"), Some(PowerMode::Low));
+        assert_eq!(PowerMode::parse("eco"), None);
+    }
+
+    #[test]
+    fn test_battery_detection() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(battery_from_sysfs(dir.path()), None);
+
+        let ac = dir.path().join("AC");
+        std::fs::create_dir(&ac).unwrap();
+        std::fs::write(ac.join("type"), "Mains\n").unwrap();
+        let bat = dir.path().join("BAT0");
+        std::fs::create_dir(&bat).unwrap();
+        std::fs::write(bat.join("type"), "Battery\n").unwrap();
+        std::fs::write(bat.join("status"), "Charging\n").unwrap();
+        assert_eq!(battery_from_sysfs(dir.path()), Some(false));
+        std::fs::write(bat.join("status"), "Discharging\n").unwrap();
+        assert_eq!(battery_from_sysfs(dir.path()), Some(true));
+
+        assert_eq!(
+            battery_from_pmset("Now drawing from 'Battery Power'\n -InternalBattery-0 (id=1)"),
+            Some(true)
+        );
+        assert_eq!(
+            battery_from_pmset("Now drawing from 'AC Power'"),
+            Some(false)
+        );
+    }
+}
@@ -456,6 +456,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         preloader.register_model("test-model".to_string(), spec).await;
@@ -477,6 +479,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         preloader.register_model("cache-test".to_string(), spec).await;
@@ -513,6 +517,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         preloader.register_model("usage-test".to_string(), spec).await;
@@ -548,6 +554,8 @@ mod tests {
                 template: None,
                 ctx_len: 2048,
                 n_threads: Some(4),
+                n_gpu_layers: None,
+                mmproj_path: None,
             };
             preloader.register_model(format!("model-{}", i), spec).await;
         }
@@ -582,6 +590,8 @@ mod tests {
                 template: None,
                 ctx_len: 2048,
                 n_threads: Some(4),
+                n_gpu_layers: None,
+                mmproj_path: None,
             };
             preloader.register_model(format!("candidate-{}", i), spec).await;
         }
@@ -618,6 +628,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         preloader.register_model("clear-test".to_string(), spec).await;
@@ -645,6 +657,8 @@ mod tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         preloader.register_model("concurrent-test".to_string(), spec).await;
@@ -0,0 +1,487 @@
+//! Prometheus metrics for `GET /metrics`.
+//!
+//! Scrapers ask for the text exposition format (`Accept: text/plain` or
+//! OpenMetrics) and get request counts and latency histograms per route
+//! pattern, generated tokens and generation time, queue depths, loaded models
+//! and memory, and the p95 latency of editor code completions against their
+//! SLO. Other clients keep getting the JSON summary.
+//!
+//! Routes are labelled by pattern (`/api/models/:name/load`), never by the
+//! requested path, so label cardinality stays bounded.
+
+use axum::http::{header, HeaderMap};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the request latency buckets, in seconds
+const LATENCY_BUCKETS: [f64; 14] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0,
+];
+
+/// Recent code completions the p95 latency is taken over
+const CODE_LATENCY_WINDOW: usize = 1000;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&le| seconds <= le) {
+            self.buckets[i] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// When the server received a request, for latencies that include the wait
+/// for a slot
+#[derive(Debug, Clone, Copy)]
+pub struct Received(pub Instant);
+
+/// Latency of recent code completions against their SLO
+#[derive(Debug, Default)]
+struct CodeLatency {
+    /// The last `CODE_LATENCY_WINDOW` latencies, oldest first
+    recent: VecDeque<Duration>,
+    completions: u64,
+    over_slo: u64,
+}
+
+impl CodeLatency {
+    /// Nearest-rank 95th percentile of the recent latencies
+    fn p95(&self) -> Option<Duration> {
+        let mut sorted: Vec<Duration> = self.recent.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (sorted.len() * 95).div_ceil(100);
+        sorted.get(rank.checked_sub(1)?).copied()
+    }
+}
+
+/// Code completion latency for the JSON summary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeLatencyStats {
+    pub completions: u64,
+    pub over_slo: u64,
+    pub p95: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct Requests {
+    /// (method, route, status) -> count
+    counts: BTreeMap<(String, String, u16), u64>,
+    /// (method, route) -> latency
+    latency: BTreeMap<(String, String), Histogram>,
+}
+
+/// Counters collected while the server runs
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    requests: Mutex<Requests>,
+    generations: AtomicU64,
+    generated_tokens: AtomicU64,
+    generation_micros: AtomicU64,
+    /// Rate of the most recent generation, as `f64` bits
+    last_tokens_per_second: AtomicU64,
+    code_latency: Mutex<CodeLatency>,
+}
+
+/// Readings taken when `/metrics` is scraped
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    /// Requests waiting per queue (`chat`, `vision`, `load`)
+    pub queue_depth: Vec<(&'static str, usize)>,
+    pub loaded_models: usize,
+    /// Memory the loaded models report using
+    pub loaded_model_bytes: u64,
+    pub memory: Option<crate::util::memory::MemorySample>,
+    /// Memory held back for admitted vision jobs
+    pub reserved_bytes: u64,
+    /// Latency target for code completions
+    pub code_slo: Option<Duration>,
+}
+
+impl ServerMetrics {
+    pub fn global() -> &'static ServerMetrics {
+        static METRICS: OnceLock<ServerMetrics> = OnceLock::new();
+        METRICS.get_or_init(ServerMetrics::default)
+    }
+
+    pub fn record_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        *requests
+            .counts
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        requests
+            .latency
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// One finished text generation of `tokens` output tokens
+    pub fn record_generation(&self, tokens: usize, elapsed: Duration) {
+        self.generations.fetch_add(1, Ordering::Relaxed);
+        self.generated_tokens
+            .fetch_add(tokens as u64, Ordering::Relaxed);
+        self.generation_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        if tokens > 0 && !elapsed.is_zero() {
+            let rate = tokens as f64 / elapsed.as_secs_f64();
+            self.last_tokens_per_second
+                .store(rate.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// One finished code completion, `elapsed` from request to last token
+    pub fn record_code_completion(&self, elapsed: Duration, slo: Duration) {
+        let mut code = self.code_latency.lock().unwrap_or_else(|e| e.into_inner());
+        if code.recent.len() == CODE_LATENCY_WINDOW {
+            code.recent.pop_front();
+        }
+        code.recent.push_back(elapsed);
+        code.completions += 1;
+        if elapsed > slo {
+            code.over_slo += 1;
+        }
+    }
+
+    pub fn code_latency(&self) -> CodeLatencyStats {
+        let code = self.code_latency.lock().unwrap_or_else(|e| e.into_inner());
+        CodeLatencyStats {
+            completions: code.completions,
+            over_slo: code.over_slo,
+            p95: code.p95(),
+        }
+    }
+
+    /// Everything in the Prometheus text format
+    pub fn render(&self, snapshot: &Snapshot) -> String {
+        let mut out = String::new();
+        {
+            let requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+            family(
+                &mut out,
+                "shimmy_http_requests_total",
+                "counter",
+                "HTTP requests by route pattern and status",
+            );
+            for ((method, route, status), count) in &requests.counts {
+                let _ = writeln!(
+                    out,
+                    "shimmy_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                    escape(method),
+                    escape(route),
+                    status,
+                    count
+                );
+            }
+            family(
+                &mut out,
+                "shimmy_http_request_duration_seconds",
+                "histogram",
+                "Time until the response head was ready, by route pattern",
+            );
+            for ((method, route), histogram) in &requests.latency {
+                let labels = format!("method=\"{}\",route=\"{}\"", escape(method), escape(route));
+                let mut cumulative = 0;
+                for (le, n) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                    cumulative += n;
+                    let _ = writeln!(
+                        out,
+                        "shimmy_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                        labels, le, cumulative
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "shimmy_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                    labels, histogram.count
+                );
+                let _ = writeln!(
+                    out,
+                    "shimmy_http_request_duration_seconds_sum{{{}}} {}",
+                    labels, histogram.sum
+                );
+                let _ = writeln!(
+                    out,
+                    "shimmy_http_request_duration_seconds_count{{{}}} {}",
+                    labels, histogram.count
+                );
+            }
+        }
+
+        sample(
+            &mut out,
+            "shimmy_generations_total",
+            "counter",
+            "Finished text generations",
+            self.generations.load(Ordering::Relaxed),
+        );
+        sample(
+            &mut out,
+            "shimmy_generated_tokens_total",
+            "counter",
+            "Output tokens generated",
+            self.generated_tokens.load(Ordering::Relaxed),
+        );
+        sample(
+            &mut out,
+            "shimmy_generation_seconds_total",
+            "counter",
+            "Time spent generating, including prompt evaluation",
+            self.generation_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+        );
+        sample(
+            &mut out,
+            "shimmy_tokens_per_second",
+            "gauge",
+            "Output tokens per second of the most recent generation",
+            f64::from_bits(self.last_tokens_per_second.load(Ordering::Relaxed)),
+        );
+
+        let code = self.code_latency();
+        sample(
+            &mut out,
+            "shimmy_code_completions_total",
+            "counter",
+            "Finished code completions",
+            code.completions,
+        );
+        sample(
+            &mut out,
+            "shimmy_code_completions_over_slo_total",
+            "counter",
+            "Code completions slower than their latency target",
+            code.over_slo,
+        );
+        if let Some(p95) = code.p95 {
+            sample(
+                &mut out,
+                "shimmy_code_completion_p95_seconds",
+                "gauge",
+                "95th percentile latency of the last 1000 code completions",
+                p95.as_secs_f64(),
+            );
+        }
+        if let Some(slo) = snapshot.code_slo {
+            sample(
+                &mut out,
+                "shimmy_code_completion_slo_seconds",
+                "gauge",
+                "Latency target for code completions",
+                slo.as_secs_f64(),
+            );
+        }
+
+        family(
+            &mut out,
+            "shimmy_queue_depth",
+            "gauge",
+            "Requests waiting for a slot",
+        );
+        for (queue, depth) in &snapshot.queue_depth {
+            let _ = writeln!(out, "shimmy_queue_depth{{queue=\"{}\"}} {}", queue, depth);
+        }
+        sample(
+            &mut out,
+            "shimmy_loaded_models",
+            "gauge",
+            "Models currently loaded",
+            snapshot.loaded_models,
+        );
+        sample(
+            &mut out,
+            "shimmy_loaded_model_bytes",
+            "gauge",
+            "Memory the loaded models report using",
+            snapshot.loaded_model_bytes,
+        );
+        sample(
+            &mut out,
+            "shimmy_memory_reserved_bytes",
+            "gauge",
+            "Memory held back for running vision jobs",
+            snapshot.reserved_bytes,
+        );
+        if let Some(memory) = snapshot.memory {
+            sample(
+                &mut out,
+                "shimmy_memory_total_bytes",
+                "gauge",
+                "Total system memory",
+                memory.total_bytes,
+            );
+            sample(
+                &mut out,
+                "shimmy_memory_available_bytes",
+                "gauge",
+                "Available system memory",
+                memory.available_bytes,
+            );
+            sample(
+                &mut out,
+                "shimmy_swap_used_bytes",
+                "gauge",
+                "Swap in use",
+                memory.swap_used_bytes,
+            );
+        }
+        out
+    }
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    family(out, name, kind, help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Label value escaping from the exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Whether the client asked for the text format rather than JSON
+pub fn wants_text(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|accept| accept.contains("text/plain") || accept.contains("openmetrics"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let metrics = ServerMetrics::default();
+        for ms in [3, 40, 40, 700, 200_000] {
+            metrics.record_request(
+                "POST",
+                "/v1/chat/completions",
+                200,
+                Duration::from_millis(ms),
+            );
+        }
+        metrics.record_request("POST", "/v1/chat/completions", 503, Duration::ZERO);
+        let text = metrics.render(&Snapshot::default());
+        let labels = r#"method="POST",route="/v1/chat/completions""#;
+        assert!(text.contains(&format!(
+            "shimmy_http_requests_total{{{},status=\"200\"}} 5",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "shimmy_http_requests_total{{{},status=\"503\"}} 1",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "shimmy_http_request_duration_seconds_bucket{{{},le=\"0.005\"}} 2",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "shimmy_http_request_duration_seconds_bucket{{{},le=\"0.05\"}} 4",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "shimmy_http_request_duration_seconds_bucket{{{},le=\"120\"}} 5",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "shimmy_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 6",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "shimmy_http_request_duration_seconds_count{{{}}} 6",
+            labels
+        )));
+    }
+
+    #[test]
+    fn test_generation_and_gauges() {
+        let metrics = ServerMetrics::default();
+        metrics.record_generation(50, Duration::from_secs(2));
+        metrics.record_generation(30, Duration::from_secs(1));
+        let text = metrics.render(&Snapshot {
+            queue_depth: vec![("chat", 3), ("load", 0)],
+            loaded_models: 2,
+            ..Snapshot::default()
+        });
+        assert!(text.contains("shimmy_generations_total 2\n"));
+        assert!(text.contains("shimmy_generated_tokens_total 80\n"));
+        assert!(text.contains("shimmy_generation_seconds_total 3\n"));
+        assert!(text.contains("shimmy_tokens_per_second 30\n"));
+        assert!(text.contains("shimmy_queue_depth{queue=\"chat\"} 3\n"));
+        assert!(text.contains("shimmy_loaded_models 2\n"));
+        assert!(text.contains("# TYPE shimmy_tokens_per_second gauge\n"));
+        assert!(!text.contains("shimmy_memory_total_bytes"));
+    }
+
+    #[test]
+    fn test_code_completion_p95_and_slo() {
+        let metrics = ServerMetrics::default();
+        let slo = Duration::from_millis(300);
+        assert_eq!(metrics.code_latency().p95, None);
+        for ms in 1..=100 {
+            metrics.record_code_completion(Duration::from_millis(ms * 5), slo);
+        }
+        let stats = metrics.code_latency();
+        assert_eq!(stats.completions, 100);
+        assert_eq!(stats.over_slo, 40);
+        assert_eq!(stats.p95, Some(Duration::from_millis(475)));
+
+        let text = metrics.render(&Snapshot {
+            code_slo: Some(slo),
+            ..Snapshot::default()
+        });
+        assert!(text.contains("shimmy_code_completions_over_slo_total 40\n"));
+        assert!(text.contains("shimmy_code_completion_p95_seconds 0.475\n"));
+        assert!(text.contains("shimmy_code_completion_slo_seconds 0.3\n"));
+
+        // Only the most recent window counts towards the percentile
+        for _ in 0..CODE_LATENCY_WINDOW {
+            metrics.record_code_completion(Duration::from_millis(20), slo);
+        }
+        assert_eq!(metrics.code_latency().p95, Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn test_wants_text() {
+        let mut headers = HeaderMap::new();
+        assert!(!wants_text(&headers));
+        headers.insert(header::ACCEPT, "*/*".parse().unwrap());
+        assert!(!wants_text(&headers));
+        headers.insert(
+            header::ACCEPT,
+            "application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5"
+                .parse()
+                .unwrap(),
+        );
+        assert!(wants_text(&headers));
+    }
+
+    #[test]
+    fn test_label_escaping() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
@@ -0,0 +1,573 @@
+//! Retrieval-augmented generation over the built-in vector store.
+//!
+//! `POST /api/rag/query` embeds the question, pulls the closest chunks from a
+//! collection, and asks the model to answer from those numbered sources with
+//! `[n]` citations. The retrieved chunks are returned alongside the answer:
+//! as the first `sources` event when streaming, or a `sources` field otherwise.
+//!
+//! `POST /api/rag/ingest` fills a collection: documents are split into
+//! token-sized chunks, embedded, and stored with their source name so answers
+//! can cite where a chunk came from.
+
+use crate::embeddings::Chunking;
+use crate::engine::{GenOptions, InvalidParameter};
+use crate::error::ShimmyError;
+use crate::templates::TemplateFamily;
+use crate::vector_store::{VectorMatch, VectorRecord};
+use crate::AppState;
+use axum::{
+    extract::State,
+    response::{sse::Event, IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub const DEFAULT_TOP_K: usize = 4;
+/// Chunking used by ingestion when the request doesn't set one
+pub const DEFAULT_CHUNKING: Chunking = Chunking {
+    tokens: 256,
+    overlap: 32,
+};
+/// Chunks embedded per model call during ingestion
+const INGEST_BATCH: usize = 64;
+
+const DEFAULT_INSTRUCTIONS: &str = "Answer the question using only the numbered sources. \
+Cite the sources you rely on as [1], [2] and so on. \
+If the sources don't contain the answer, say so.";
+
+#[derive(Debug, Deserialize)]
+pub struct RagQueryRequest {
+    /// Vector collection to search
+    pub collection: String,
+    /// Model that writes the answer
+    pub model: String,
+    /// Model that embeds the query; must match the one used to fill the collection
+    pub embedding_model: String,
+    pub query: String,
+    #[serde(default)]
+    pub top_k: Option<usize>,
+    /// Replaces the default answer-from-sources instructions
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub max_tokens: Option<usize>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// Stream the answer as SSE (default: true)
+    #[serde(default)]
+    pub stream: Option<bool>,
+}
+
+/// A retrieved chunk and the number it is cited by
+#[derive(Debug, Clone, Serialize)]
+pub struct Source {
+    pub n: usize,
+    #[serde(flatten)]
+    pub chunk: VectorMatch,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RagQueryResponse {
+    pub response: String,
+    pub finish_reason: String,
+    pub sources: Vec<Source>,
+}
+
+/// The user turn: numbered sources followed by the question
+pub fn build_user_message(query: &str, sources: &[Source]) -> String {
+    let mut message = String::from("Sources:\n");
+    for source in sources {
+        let text = source.chunk.text.as_deref().unwrap_or("(no text)");
+        message.push_str(&format!("[{}] {}\n\n", source.n, text.trim()));
+    }
+    message.push_str(&format!("Question: {}", query));
+    message
+}
+
+pub async fn query(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<RagQueryRequest>,
+) -> Response {
+    if req.query.trim().is_empty() {
+        return ShimmyError::from(InvalidParameter::new("query", "must not be empty"))
+            .into_response();
+    }
+    let top_k = req.top_k.unwrap_or(DEFAULT_TOP_K);
+    if top_k == 0 {
+        return ShimmyError::from(InvalidParameter::new("top_k", "must be at least 1"))
+            .into_response();
+    }
+    if let Some(rejection) = state.registry.rejection(&req.model) {
+        return ShimmyError::Load(rejection).into_response();
+    }
+    let Some(spec) = state.registry.to_spec(&req.model) else {
+        return ShimmyError::ModelNotFound { name: req.model }.into_response();
+    };
+
+    let fam = match spec.template.as_deref() {
+        Some("chatml") => TemplateFamily::ChatML,
+        Some("llama3") | Some("llama-3") => TemplateFamily::Llama3,
+        _ => TemplateFamily::OpenChat,
+    };
+    let mut opts = GenOptions {
+        stream: req.stream.unwrap_or(true),
+        stop_tokens: fam.stop_tokens(),
+        api_key: crate::api_keys::current(),
+        rate_limit: crate::rate_limit::current(),
+        ..Default::default()
+    };
+    if let Some(m) = req.max_tokens {
+        opts.max_tokens = m;
+    }
+    if let Some(t) = req.temperature {
+        opts.temperature = t;
+    }
+    if let Err(e) = opts.validate() {
+        return ShimmyError::from(e).into_response();
+    }
+
+    // Retrieve
+    let query_vector = match crate::embeddings::embed_texts(
+        &state,
+        &req.embedding_model,
+        std::slice::from_ref(&req.query),
+    )
+    .await
+    {
+        Ok(mut vectors) => vectors.pop().unwrap_or_default(),
+        Err(e) => return e.into_response(),
+    };
+    let sources: Vec<Source> = match state
+        .vector_store
+        .query(&req.collection, &query_vector, top_k)
+    {
+        Ok(chunks) => chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| Source { n: i + 1, chunk })
+            .collect(),
+        Err(e) => return ShimmyError::from(e).into_response(),
+    };
+
+    // Generate
+    let system = req.system.as_deref().unwrap_or(DEFAULT_INSTRUCTIONS);
+    let user = build_user_message(&req.query, &sources);
+    let prompt = fam.render(Some(system), &[], Some(&user));
+    let loaded = match state.load_model(&spec).await {
+        Ok(loaded) => loaded,
+        Err(e) => return crate::api_errors::model_load_failed_response(&req.model, e),
+    };
+
+    if opts.stream {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Event>();
+        let _ = tx.send(
+            Event::default()
+                .event("sources")
+                .json_data(&sources)
+                .unwrap_or_else(|_| Event::default().comment("unserializable sources")),
+        );
+        let mut opts_clone = opts.clone();
+        opts_clone.stream = false;
+        let cancel = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        opts_clone.cancel = Some(cancel.clone());
+        tokio::spawn(async move {
+            let tx_tokens = tx.clone();
+            let result = loaded
+                .generate_with_finish(
+                    &prompt,
+                    opts_clone,
+                    Some(Box::new(move |tok| {
+                        if tx_tokens.send(Event::default().data(tok)).is_err() {
+                            cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+                        }
+                    })),
+                )
+                .await;
+            if let Err(e) = result {
+                tracing::error!("RAG generation failed: {}", e);
+            }
+            let _ = tx.send(Event::default().data("[DONE]"));
+        });
+        let stream = futures_util::StreamExt::map(
+            tokio_stream::wrappers::UnboundedReceiverStream::new(rx),
+            Ok::<Event, std::convert::Infallible>,
+        );
+        crate::api::sse_response(stream)
+    } else {
+        match loaded.generate_with_finish(&prompt, opts, None).await {
+            Ok(generation) => Json(RagQueryResponse {
+                response: generation.text,
+                finish_reason: generation.finish_reason.as_str().to_string(),
+                sources,
+            })
+            .into_response(),
+            Err(e) => ShimmyError::GenerationError {
+                reason: e.to_string(),
+            }
+            .into_response(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IngestDocument {
+    /// Source name, e.g. a file name; chunk ids are `{name}#{n}`
+    pub name: String,
+    pub content: String,
+    /// `text/plain` or `text/markdown`; guessed from `name` when absent
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Extra fields stored with every chunk of the document
+    #[serde(default)]
+    pub metadata: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IngestRequest {
+    pub collection: String,
+    pub embedding_model: String,
+    pub documents: Vec<IngestDocument>,
+    /// Create the collection if it doesn't exist (default: true)
+    #[serde(default)]
+    pub create: Option<bool>,
+    #[serde(default)]
+    pub chunking: Option<Chunking>,
+    /// Report progress as SSE `progress` events (default: false)
+    #[serde(default)]
+    pub stream: Option<bool>,
+}
+
+/// Ingestion progress, sent after each embedded batch
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IngestProgress {
+    /// Document the last batch came from
+    pub document: String,
+    pub documents_done: usize,
+    pub documents_total: usize,
+    pub chunks_done: usize,
+    pub chunks_total: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngestResponse {
+    pub collection: String,
+    pub documents: usize,
+    pub chunks: usize,
+    /// Records in the collection afterwards
+    pub count: usize,
+}
+
+/// Content type of a document, from the request or its file extension
+pub fn document_type(doc: &IngestDocument) -> Result<&'static str, ShimmyError> {
+    let declared = doc
+        .content_type
+        .as_deref()
+        .map(|t| t.split(';').next().unwrap_or(t).trim().to_ascii_lowercase());
+    let extension = std::path::Path::new(&doc.name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match (declared.as_deref(), extension.as_deref()) {
+        (Some("application/pdf"), _) | (None, Some("pdf")) => {
+            Err(ShimmyError::UnsupportedOperation {
+                operation: format!(
+                    "PDF ingestion ('{}'): this build has no PDF text extraction; send the extracted text instead",
+                    doc.name
+                ),
+            })
+        }
+        (Some("text/markdown"), _) | (None, Some("md" | "markdown")) => Ok("text/markdown"),
+        (Some("text/plain"), _) | (None, _) => Ok("text/plain"),
+        (Some(other), _) => Err(InvalidParameter::new(
+            "content_type",
+            format!("'{}' is not supported for '{}'", other, doc.name),
+        )
+        .into()),
+    }
+}
+
+/// Check the whole request before anything is loaded or written
+fn validate_ingest(req: &IngestRequest) -> Result<Chunking, ShimmyError> {
+    if req.documents.is_empty() {
+        return Err(InvalidParameter::new("documents", "must not be empty").into());
+    }
+    let chunking = req.chunking.unwrap_or(DEFAULT_CHUNKING);
+    chunking.validate()?;
+    let mut names = std::collections::HashSet::new();
+    for doc in &req.documents {
+        if doc.name.trim().is_empty() {
+            return Err(InvalidParameter::new("documents", "every document needs a name").into());
+        }
+        if !names.insert(doc.name.as_str()) {
+            return Err(InvalidParameter::new(
+                "documents",
+                format!("duplicate document name '{}'", doc.name),
+            )
+            .into());
+        }
+        document_type(doc)?;
+    }
+    Ok(chunking)
+}
+
+/// Chunk, embed and store every document, reporting after each batch
+async fn run_ingest(
+    state: &AppState,
+    req: &IngestRequest,
+    chunking: Chunking,
+    mut on_progress: impl FnMut(IngestProgress),
+) -> Result<IngestResponse, ShimmyError> {
+    let loaded = crate::embeddings::load_model(state, &req.embedding_model).await?;
+    let store = &state.vector_store;
+    if req.create.unwrap_or(true) {
+        match store.create(&req.collection, None) {
+            Ok(()) | Err(crate::vector_store::VectorStoreError::CollectionExists { .. }) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let chunked: Vec<(&IngestDocument, Vec<String>)> = req
+        .documents
+        .iter()
+        .map(|doc| {
+            let chunks = crate::embeddings::chunk_text(loaded.as_ref(), &doc.content, chunking);
+            (doc, chunks)
+        })
+        .collect();
+    let chunks_total = chunked.iter().map(|(_, chunks)| chunks.len()).sum();
+    let mut chunks_done = 0;
+    // Also fails early when the collection is missing
+    let mut count = store.add(&req.collection, Vec::new())?;
+
+    for (done, (doc, chunks)) in chunked.into_iter().enumerate() {
+        let content_type = document_type(doc)?;
+        for (batch_no, batch) in chunks.chunks(INGEST_BATCH).enumerate() {
+            let vectors = crate::embeddings::embed_loaded(loaded.as_ref(), batch).await?;
+            let records = batch
+                .iter()
+                .zip(vectors)
+                .enumerate()
+                .map(|(i, (text, vector))| {
+                    let chunk = batch_no * INGEST_BATCH + i;
+                    let mut metadata = doc.metadata.clone().unwrap_or_default();
+                    metadata.insert("source".into(), doc.name.clone().into());
+                    metadata.insert("chunk".into(), chunk.into());
+                    metadata.insert("content_type".into(), content_type.into());
+                    VectorRecord {
+                        id: format!("{}#{}", doc.name, chunk),
+                        vector,
+                        text: Some(text.clone()),
+                        metadata: metadata.into(),
+                    }
+                })
+                .collect();
+            count = store.add(&req.collection, records)?;
+            chunks_done += batch.len();
+            let last_batch = (batch_no + 1) * INGEST_BATCH >= chunks.len();
+            on_progress(IngestProgress {
+                document: doc.name.clone(),
+                documents_done: done + usize::from(last_batch),
+                documents_total: req.documents.len(),
+                chunks_done,
+                chunks_total,
+            });
+        }
+    }
+
+    Ok(IngestResponse {
+        collection: req.collection.clone(),
+        documents: req.documents.len(),
+        chunks: chunks_done,
+        count,
+    })
+}
+
+pub async fn ingest(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<IngestRequest>,
+) -> Response {
+    let chunking = match validate_ingest(&req) {
+        Ok(chunking) => chunking,
+        Err(e) => return e.into_response(),
+    };
+    if !req.stream.unwrap_or(false) {
+        return match run_ingest(&state, &req, chunking, |_| {}).await {
+            Ok(summary) => Json(summary).into_response(),
+            Err(e) => e.into_response(),
+        };
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Event>();
+    tokio::spawn(async move {
+        let tx_progress = tx.clone();
+        let result = run_ingest(&state, &req, chunking, move |progress| {
+            if let Ok(event) = Event::default().event("progress").json_data(&progress) {
+                let _ = tx_progress.send(event);
+            }
+        })
+        .await;
+        let event = match result {
+            Ok(summary) => Event::default().event("done").json_data(&summary),
+            Err(e) => {
+                tracing::warn!("RAG ingestion into '{}' failed: {}", req.collection, e);
+                Event::default()
+                    .event("error")
+                    .json_data(serde_json::json!({
+                        "error": { "code": e.code(), "message": e.to_string() }
+                    }))
+            }
+        };
+        if let Ok(event) = event {
+            let _ = tx.send(event);
+        }
+    });
+    let stream = futures_util::StreamExt::map(
+        tokio_stream::wrappers::UnboundedReceiverStream::new(rx),
+        Ok::<Event, std::convert::Infallible>,
+    );
+    crate::api::sse_response(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(n: usize, text: Option<&str>) -> Source {
+        Source {
+            n,
+            chunk: VectorMatch {
+                id: format!("chunk-{n}"),
+                score: 0.9,
+                text: text.map(str::to_string),
+                metadata: serde_json::json!({ "file": "notes.md" }),
+            },
+        }
+    }
+
+    #[test]
+    fn test_build_user_message() {
+        let sources = [source(1, Some(" Shimmy is small. ")), source(2, None)];
+        assert_eq!(
+            build_user_message("How big is shimmy?", &sources),
+            "Sources:\n[1] Shimmy is small.\n\n[2] (no text)\n\nQuestion: How big is shimmy?"
+        );
+    }
+
+    #[test]
+    fn test_source_serialization_is_flat() {
+        let json = serde_json::to_value(source(1, Some("x"))).unwrap();
+        assert_eq!(json["n"], 1);
+        assert_eq!(json["id"], "chunk-1");
+        assert_eq!(json["metadata"]["file"], "notes.md");
+    }
+
+    #[tokio::test]
+    async fn test_query_validation() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::Registry;
+        use axum::http::StatusCode;
+
+        let engine = Box::new(InferenceEngineAdapter::new());
+        let state = Arc::new(AppState::new(engine, Registry::default()));
+        let cases = [
+            (
+                r#"{"collection": "c", "model": "m", "embedding_model": "e", "query": " "}"#,
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                r#"{"collection": "c", "model": "m", "embedding_model": "e", "query": "q", "top_k": 0}"#,
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                r#"{"collection": "c", "model": "m", "embedding_model": "e", "query": "q"}"#,
+                StatusCode::NOT_FOUND,
+            ),
+        ];
+        for (body, status) in cases {
+            let req: RagQueryRequest = serde_json::from_str(body).unwrap();
+            assert_eq!(
+                query(State(state.clone()), Json(req)).await.status(),
+                status,
+                "{body}"
+            );
+        }
+    }
+
+    fn document(name: &str, content_type: Option<&str>) -> IngestDocument {
+        IngestDocument {
+            name: name.to_string(),
+            content: "text".to_string(),
+            content_type: content_type.map(str::to_string),
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_document_type() {
+        assert_eq!(
+            document_type(&document("notes.md", None)).unwrap(),
+            "text/markdown"
+        );
+        assert_eq!(
+            document_type(&document("notes", None)).unwrap(),
+            "text/plain"
+        );
+        assert_eq!(
+            document_type(&document("notes", Some("text/markdown; charset=utf-8"))).unwrap(),
+            "text/markdown"
+        );
+        assert_eq!(
+            document_type(&document("paper.pdf", None))
+                .unwrap_err()
+                .code(),
+            "NOT_IMPLEMENTED"
+        );
+        assert_eq!(
+            document_type(&document("paper", Some("application/pdf")))
+                .unwrap_err()
+                .code(),
+            "NOT_IMPLEMENTED"
+        );
+        assert!(document_type(&document("page", Some("text/html"))).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ingest_validation() {
+        use crate::engine::adapter::InferenceEngineAdapter;
+        use crate::model_registry::Registry;
+        use axum::http::StatusCode;
+
+        let engine = Box::new(InferenceEngineAdapter::new());
+        let state = Arc::new(AppState::new(engine, Registry::default()));
+        let cases = [
+            (r#"{"documents": []}"#, StatusCode::BAD_REQUEST),
+            (
+                r#"{"documents": [{"name": "a", "content": "x"}, {"name": "a", "content": "y"}]}"#,
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                r#"{"documents": [{"name": "a", "content": "x"}], "chunking": {"tokens": 8, "overlap": 8}}"#,
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                r#"{"documents": [{"name": "a", "content": "x"}, {"name": "b.pdf", "content": "%PDF"}]}"#,
+                StatusCode::NOT_IMPLEMENTED,
+            ),
+            (
+                r#"{"documents": [{"name": "a", "content": "x"}]}"#,
+                StatusCode::NOT_FOUND,
+            ),
+        ];
+        for (documents, status) in cases {
+            let mut body: serde_json::Value = serde_json::from_str(documents).unwrap();
+            body["collection"] = "docs".into();
+            body["embedding_model"] = "missing".into();
+            let req: IngestRequest = serde_json::from_value(body).unwrap();
+            assert_eq!(
+                ingest(State(state.clone()), Json(req)).await.status(),
+                status,
+                "{documents}"
+            );
+        }
+    }
+}
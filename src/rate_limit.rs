@@ -0,0 +1,381 @@
+//! Server-wide and per-client rate limits that need no API keys or license.
+//!
+//! One runaway script can swamp a shared home-lab server long before anyone
+//! hands out API keys. These limits cap what all clients together, and each
+//! client address on its own, may ask of the server:
+//!
+//! - `SHIMMY_RATE_LIMIT_RPM` / `SHIMMY_RATE_LIMIT_TPM`: requests and tokens
+//!   per minute for the whole server
+//! - `SHIMMY_RATE_LIMIT_IP_RPM` / `SHIMMY_RATE_LIMIT_IP_TPM`: the same for
+//!   each client IP address
+//!
+//! Unset or `0` leaves a limit off. Each limit is a bucket that refills
+//! continuously, so a client can burst up to a minute's allowance and then
+//! gets more as it refills. Tokens are the prompt and generated tokens of
+//! text generation, counted when a generation ends: the request that
+//! overdraws the allowance still finishes, and the next one waits until the
+//! bucket has refilled past the debt. A request over a limit gets `429
+//! RATE_LIMITED` with `Retry-After`. `/health` and `/readyz` are never
+//! limited, and API keys' own limits apply on top of these.
+
+use crate::error::ShimmyError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+pub const RPM_ENV: &str = "SHIMMY_RATE_LIMIT_RPM";
+pub const TPM_ENV: &str = "SHIMMY_RATE_LIMIT_TPM";
+pub const IP_RPM_ENV: &str = "SHIMMY_RATE_LIMIT_IP_RPM";
+pub const IP_TPM_ENV: &str = "SHIMMY_RATE_LIMIT_IP_TPM";
+
+/// Past this many client addresses, ones with a full bucket are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+tokio::task_local! {
+    static CURRENT: Arc<Charge>;
+}
+
+/// Allowances per minute; `None` is unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u64>,
+}
+
+impl Limits {
+    fn from_env(rpm_env: &str, tpm_env: &str) -> Self {
+        fn read<T: std::str::FromStr + PartialEq + Default>(env: &str) -> Option<T> {
+            let value = std::env::var(env).ok()?;
+            match value.trim().parse::<T>() {
+                Ok(n) if n == T::default() => None,
+                Ok(n) => Some(n),
+                Err(_) => {
+                    tracing::warn!("Ignoring {}={:?}: not a whole number", env, value);
+                    None
+                }
+            }
+        }
+        Self {
+            requests_per_minute: read(rpm_env),
+            tokens_per_minute: read(tpm_env),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.requests_per_minute.is_none() && self.tokens_per_minute.is_none()
+    }
+}
+
+impl std::fmt::Display for Limits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(rpm) = self.requests_per_minute {
+            parts.push(format!("{} requests/min", rpm));
+        }
+        if let Some(tpm) = self.tokens_per_minute {
+            parts.push(format!("{} tokens/min", tpm));
+        }
+        if parts.is_empty() {
+            f.write_str("unlimited")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+/// Request and token allowances for one scope
+#[derive(Debug)]
+pub struct Bucket {
+    /// Who the bucket limits, for error messages
+    scope: String,
+    limits: Limits,
+    level: Mutex<Level>,
+}
+
+#[derive(Debug)]
+struct Level {
+    requests: f64,
+    /// Negative after a generation overdraws the allowance
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    pub fn new(scope: &str, limits: Limits, now: Instant) -> Self {
+        Self {
+            scope: scope.to_string(),
+            limits,
+            level: Mutex::new(Level {
+                requests: limits.requests_per_minute.unwrap_or(0) as f64,
+                tokens: limits.tokens_per_minute.unwrap_or(0) as f64,
+                refilled: now,
+            }),
+        }
+    }
+
+    fn lock(&self, now: Instant) -> std::sync::MutexGuard<'_, Level> {
+        let mut level = self.level.lock().unwrap_or_else(|e| e.into_inner());
+        let minutes = now.saturating_duration_since(level.refilled).as_secs_f64() / 60.0;
+        if let Some(rpm) = self.limits.requests_per_minute {
+            level.requests = (level.requests + minutes * rpm as f64).min(rpm as f64);
+        }
+        if let Some(tpm) = self.limits.tokens_per_minute {
+            level.tokens = (level.tokens + minutes * tpm as f64).min(tpm as f64);
+        }
+        level.refilled = level.refilled.max(now);
+        level
+    }
+
+    /// Take one request, failing with `RATE_LIMITED` when either allowance
+    /// is used up
+    pub fn admit(&self, now: Instant) -> Result<(), ShimmyError> {
+        let mut level = self.lock(now);
+        if let Some(tpm) = self.limits.tokens_per_minute {
+            if level.tokens < 1.0 {
+                return Err(self.limited("tokens/minute", level.tokens, tpm as f64));
+            }
+        }
+        if let Some(rpm) = self.limits.requests_per_minute {
+            if level.requests < 1.0 {
+                return Err(self.limited("requests/minute", level.requests, rpm as f64));
+            }
+            level.requests -= 1.0;
+        }
+        Ok(())
+    }
+
+    /// Count tokens a request used against the allowance
+    pub fn record_tokens(&self, tokens: u64, now: Instant) {
+        if self.limits.tokens_per_minute.is_some() {
+            self.lock(now).tokens -= tokens as f64;
+        }
+    }
+
+    /// Whether the bucket has refilled completely, i.e. its client has been
+    /// idle long enough to forget
+    fn is_full(&self, now: Instant) -> bool {
+        let level = self.lock(now);
+        self.limits
+            .requests_per_minute
+            .is_none_or(|rpm| level.requests >= rpm as f64)
+            && self
+                .limits
+                .tokens_per_minute
+                .is_none_or(|tpm| level.tokens >= tpm as f64)
+    }
+
+    fn limited(&self, limit: &str, level: f64, per_minute: f64) -> ShimmyError {
+        let wait = ((1.0 - level) * 60.0 / per_minute).ceil().max(1.0) as u64;
+        ShimmyError::ServerRateLimited {
+            scope: self.scope.clone(),
+            limit: limit.to_string(),
+            retry_after_secs: wait,
+        }
+    }
+}
+
+/// The buckets a request was admitted against, charged for the tokens it uses
+#[derive(Debug, Default)]
+pub struct Charge {
+    buckets: Vec<Arc<Bucket>>,
+}
+
+impl Charge {
+    pub fn record_tokens(&self, tokens: u64) {
+        let now = Instant::now();
+        for bucket in &self.buckets {
+            bucket.record_tokens(tokens, now);
+        }
+    }
+}
+
+/// Limits from the environment; empty leaves the server unlimited
+#[derive(Debug, Default)]
+pub struct RateLimits {
+    global: RwLock<Option<Arc<Bucket>>>,
+    per_client: RwLock<Limits>,
+    clients: Mutex<HashMap<IpAddr, Arc<Bucket>>>,
+}
+
+fn global_bucket(global: Limits) -> Option<Arc<Bucket>> {
+    (!global.is_unlimited()).then(|| Arc::new(Bucket::new("all clients", global, Instant::now())))
+}
+
+impl RateLimits {
+    pub fn new(global: Limits, per_client: Limits) -> Self {
+        Self {
+            global: RwLock::new(global_bucket(global)),
+            per_client: RwLock::new(per_client),
+            clients: Mutex::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            Limits::from_env(RPM_ENV, TPM_ENV),
+            Limits::from_env(IP_RPM_ENV, IP_TPM_ENV),
+        )
+    }
+
+    fn global(&self) -> Option<Arc<Bucket>> {
+        self.global
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn per_client(&self) -> Limits {
+        *self.per_client.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.global().is_none() && self.per_client().is_unlimited()
+    }
+
+    /// The limits in force: for all clients together, and for each client
+    pub fn limits(&self) -> (Limits, Limits) {
+        let global = self.global().map(|bucket| bucket.limits);
+        (global.unwrap_or_default(), self.per_client())
+    }
+
+    /// Replace the limits at runtime. Every bucket starts over full under
+    /// its new allowance
+    pub fn set(&self, global: Limits, per_client: Limits) {
+        *self.global.write().unwrap_or_else(|e| e.into_inner()) = global_bucket(global);
+        *self.per_client.write().unwrap_or_else(|e| e.into_inner()) = per_client;
+        self.clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// The configured limits, for the startup banner
+    pub fn summary(&self) -> String {
+        let (global, per_client) = self.limits();
+        format!("{} total, {} per client", global, per_client)
+    }
+
+    /// Take one request from `client`'s allowance and the server's, failing
+    /// with `RATE_LIMITED` when either is used up
+    pub fn admit(&self, client: Option<IpAddr>, now: Instant) -> Result<Charge, ShimmyError> {
+        let mut buckets = Vec::new();
+        let per_client = self.per_client();
+        if let (false, Some(ip)) = (per_client.is_unlimited(), client) {
+            let bucket = self.client(ip, per_client, now);
+            bucket.admit(now)?;
+            buckets.push(bucket);
+        }
+        if let Some(global) = self.global() {
+            global.admit(now)?;
+            buckets.push(global);
+        }
+        Ok(Charge { buckets })
+    }
+
+    fn client(&self, ip: IpAddr, limits: Limits, now: Instant) -> Arc<Bucket> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(&ip) {
+            clients.retain(|_, bucket| !bucket.is_full(now));
+        }
+        let bucket = clients
+            .entry(ip)
+            .or_insert_with(|| Arc::new(Bucket::new(&format!("client {}", ip), limits, now)));
+        Arc::clone(bucket)
+    }
+}
+
+/// Run `f` charging `charge` for the tokens it generates
+pub async fn scope<F: std::future::Future>(charge: Arc<Charge>, f: F) -> F::Output {
+    CURRENT.scope(charge, f).await
+}
+
+/// What the current request is charged against, if any limit applies
+pub fn current() -> Option<Arc<Charge>> {
+    CURRENT.try_with(Arc::clone).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limits(rpm: Option<u32>, tpm: Option<u64>) -> Limits {
+        Limits {
+            requests_per_minute: rpm,
+            tokens_per_minute: tpm,
+        }
+    }
+
+    #[test]
+    fn test_per_client_and_global_buckets() {
+        let limits = RateLimits::new(limits(Some(3), None), limits(Some(2), None));
+        let now = Instant::now();
+        let alice: IpAddr = "10.0.0.2".parse().unwrap();
+        let bob: IpAddr = "10.0.0.3".parse().unwrap();
+
+        assert!(limits.admit(Some(alice), now).is_ok());
+        assert!(limits.admit(Some(alice), now).is_ok());
+        match limits.admit(Some(alice), now) {
+            Err(ShimmyError::ServerRateLimited {
+                scope,
+                limit,
+                retry_after_secs,
+            }) => {
+                assert_eq!(scope, "client 10.0.0.2");
+                assert_eq!(limit, "requests/minute");
+                assert_eq!(retry_after_secs, 30);
+            }
+            other => panic!("expected a per-client limit, got {:?}", other),
+        }
+
+        // Bob has his own allowance, but the server's runs out first
+        assert!(limits.admit(Some(bob), now).is_ok());
+        let Err(ShimmyError::ServerRateLimited { scope, .. }) = limits.admit(Some(bob), now) else {
+            panic!("expected the global limit");
+        };
+        assert_eq!(scope, "all clients");
+
+        let later = now + Duration::from_secs(30);
+        assert!(limits.admit(Some(alice), later).is_ok());
+
+        // New limits take effect with full buckets
+        let one_rpm = Limits {
+            requests_per_minute: Some(1),
+            tokens_per_minute: None,
+        };
+        limits.set(Limits::default(), one_rpm);
+        assert_eq!(limits.limits(), (Limits::default(), one_rpm));
+        assert!(limits.admit(Some(alice), later).is_ok());
+        assert!(limits.admit(Some(bob), later).is_ok());
+        assert!(limits.admit(Some(alice), later).is_err());
+        assert!(RateLimits::default().is_empty());
+        assert!(RateLimits::default()
+            .admit(None, now)
+            .unwrap()
+            .buckets
+            .is_empty());
+    }
+
+    #[test]
+    fn test_tokens_per_minute_allows_overdraw_then_waits() {
+        let now = Instant::now();
+        let bucket = Bucket::new("all clients", limits(None, Some(600)), now);
+        assert!(bucket.admit(now).is_ok());
+        bucket.record_tokens(900, now);
+        let Err(ShimmyError::ServerRateLimited {
+            limit,
+            retry_after_secs,
+            ..
+        }) = bucket.admit(now)
+        else {
+            panic!("expected the token allowance to be used up");
+        };
+        assert_eq!(limit, "tokens/minute");
+        // 301 tokens short at 10 tokens a second
+        assert_eq!(retry_after_secs, 31);
+        assert!(bucket.admit(now + Duration::from_secs(31)).is_ok());
+    }
+}
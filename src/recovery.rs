@@ -0,0 +1,282 @@
+//! Startup recovery after an unclean shutdown.
+//!
+//! A power cut or `kill -9` mid-write can leave a state file empty or cut off,
+//! and without this shimmy would refuse to start with a serde error. Before
+//! migrations run, [`recover_on_startup`] checks what a crash can damage:
+//!
+//! - JSON state files (license cache, usage and telemetry stats, config, the
+//!   version manifest) that are empty or don't parse are moved aside to
+//!   `<file>.corrupt`; their owner starts fresh, as on a first run
+//! - vector collections ending in a half-written line are truncated back to
+//!   the last complete record, so the next append starts on a clean line
+//! - temp files from interrupted writes (`.tmp*`) and downloads (`*.partial`)
+//!   older than [`STALE_AFTER`] are deleted; younger ones may belong to
+//!   another instance sharing the data directory
+//!
+//! Every repair is logged. State is written through [`write_atomic`] (temp
+//! file, fsync, rename) so a reader only ever sees the old or the new file.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Temp files younger than this are left alone
+pub const STALE_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// A repair made during recovery
+#[derive(Debug, Clone, PartialEq)]
+pub enum Repair {
+    Quarantined { path: PathBuf, to: PathBuf },
+    Truncated { path: PathBuf, removed_bytes: u64 },
+    RemovedTemp { path: PathBuf },
+}
+
+impl std::fmt::Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Repair::Quarantined { path, to } => write!(
+                f,
+                "{} was unreadable (partial write?); moved to {}",
+                path.display(),
+                to.display()
+            ),
+            Repair::Truncated {
+                path,
+                removed_bytes,
+            } => write!(
+                f,
+                "{} ended in a partial record; dropped the last {} bytes",
+                path.display(),
+                removed_bytes
+            ),
+            Repair::RemovedTemp { path } => {
+                write!(f, "removed leftover temp file {}", path.display())
+            }
+        }
+    }
+}
+
+/// Replace `path` with `data` via a temp file in the same directory
+pub fn write_atomic(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir)?;
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(data)?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Move a JSON file aside if it exists but doesn't parse
+pub fn check_json_file(path: &Path) -> Option<Repair> {
+    let data = std::fs::read(path).ok()?;
+    if serde_json::from_slice::<serde_json::Value>(&data).is_ok() {
+        return None;
+    }
+    let to = path.with_extension(match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}.corrupt", ext),
+        None => "corrupt".to_string(),
+    });
+    std::fs::rename(path, &to).ok()?;
+    Some(Repair::Quarantined {
+        path: path.to_path_buf(),
+        to,
+    })
+}
+
+/// Cut a JSON-lines file back to its last complete line
+pub fn repair_jsonl(path: &Path) -> std::io::Result<Option<Repair>> {
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)?;
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(None);
+    }
+    let mut last = [0u8; 1];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    if last[0] == b'\n' {
+        return Ok(None);
+    }
+
+    // Scan back for the end of the last complete line
+    let mut keep = 0;
+    let mut pos = len;
+    let mut buf = vec![0u8; 64 * 1024];
+    while pos > 0 {
+        let start = pos.saturating_sub(buf.len() as u64);
+        let chunk = &mut buf[..(pos - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        if let Some(i) = chunk.iter().rposition(|&b| b == b'\n') {
+            keep = start + i as u64 + 1;
+            break;
+        }
+        pos = start;
+    }
+    file.set_len(keep)?;
+    file.sync_all()?;
+    Ok(Some(Repair::Truncated {
+        path: path.to_path_buf(),
+        removed_bytes: len - keep,
+    }))
+}
+
+/// Delete `.tmp*` and `*.partial` files in `dir` older than `older_than`
+pub fn remove_stale_temps(dir: &Path, older_than: Duration) -> Vec<Repair> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let now = SystemTime::now();
+    entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with(".tmp") || name.ends_with(".partial")
+        })
+        .filter(|entry| {
+            entry.metadata().is_ok_and(|meta| {
+                meta.is_file()
+                    && meta
+                        .modified()
+                        .ok()
+                        .and_then(|m| now.duration_since(m).ok())
+                        .is_some_and(|age| age >= older_than)
+            })
+        })
+        .filter(|entry| std::fs::remove_file(entry.path()).is_ok())
+        .map(|entry| Repair::RemovedTemp { path: entry.path() })
+        .collect()
+}
+
+/// Check JSON `state_files`, JSON-lines files in `jsonl_dirs`, and temp files
+/// next to either
+pub fn recover(
+    state_files: &[PathBuf],
+    jsonl_dirs: &[PathBuf],
+    older_than: Duration,
+) -> Vec<Repair> {
+    let mut repairs = Vec::new();
+    let mut dirs: Vec<PathBuf> = jsonl_dirs.to_vec();
+    for path in state_files {
+        repairs.extend(check_json_file(path));
+        if let Some(parent) = path.parent() {
+            dirs.push(parent.to_path_buf());
+        }
+    }
+    for dir in jsonl_dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension().is_some_and(|e| e == "jsonl") {
+                match repair_jsonl(&path) {
+                    Ok(repair) => repairs.extend(repair),
+                    Err(e) => tracing::warn!("Could not check {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+    dirs.sort();
+    dirs.dedup();
+    for dir in &dirs {
+        repairs.extend(remove_stale_temps(dir, older_than));
+    }
+    repairs
+}
+
+/// Repair what an unclean shutdown may have left behind; never fails startup
+pub fn recover_on_startup() {
+    let mut state_files: Vec<PathBuf> = crate::migrations::state_files()
+        .into_iter()
+        .map(|f| f.path)
+        .collect();
+    state_files.push(crate::migrations::manifest_path());
+    let jsonl_dirs = [crate::util::paths::data_dir().join("vectors")];
+
+    for repair in recover(&state_files, &jsonl_dirs, STALE_AFTER) {
+        tracing::warn!("Startup recovery: {}", repair);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_json_is_quarantined() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("usage_stats.json");
+        let bad = dir.path().join("license_cache.json");
+        let empty = dir.path().join("config.json");
+        write_atomic(&good, br#"{"requests_today": 3}"#).unwrap();
+        std::fs::write(&bad, br#"{"key": "ab"#).unwrap();
+        std::fs::write(&empty, b"").unwrap();
+
+        let repairs = recover(
+            &[
+                good.clone(),
+                bad.clone(),
+                empty.clone(),
+                dir.path().join("missing.json"),
+            ],
+            &[],
+            STALE_AFTER,
+        );
+        assert_eq!(repairs.len(), 2);
+        assert!(good.exists());
+        assert!(!bad.exists());
+        assert_eq!(
+            std::fs::read(dir.path().join("license_cache.json.corrupt")).unwrap(),
+            br#"{"key": "ab"#
+        );
+        assert!(dir.path().join("config.json.corrupt").exists());
+    }
+
+    #[test]
+    fn test_jsonl_is_cut_back_to_the_last_full_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("docs.jsonl");
+        std::fs::write(&path, "{\"id\":\"a\"}\n{\"id\":\"b\"}\n{\"id\":").unwrap();
+
+        let repair = repair_jsonl(&path).unwrap().unwrap();
+        assert_eq!(
+            repair,
+            Repair::Truncated {
+                path: path.clone(),
+                removed_bytes: 6
+            }
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"id\":\"a\"}\n{\"id\":\"b\"}\n"
+        );
+        assert!(repair_jsonl(&path).unwrap().is_none());
+
+        // A single partial line leaves an empty collection file
+        std::fs::write(&path, "{\"id\"").unwrap();
+        repair_jsonl(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_only_stale_temp_files_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".tmpA1b2C3"), b"half").unwrap();
+        std::fs::write(dir.path().join("model.partial"), b"half").unwrap();
+        std::fs::write(dir.path().join("stats.json"), b"{}").unwrap();
+
+        assert!(remove_stale_temps(dir.path(), STALE_AFTER).is_empty());
+        let removed = remove_stale_temps(dir.path(), Duration::ZERO);
+        assert_eq!(removed.len(), 2);
+        assert!(dir.path().join("stats.json").exists());
+    }
+}
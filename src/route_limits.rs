@@ -0,0 +1,212 @@
+//! Per-route request concurrency and queue length.
+//!
+//! Chat, code completion and vision requests each get their own pool of
+//! slots, so a burst of image requests can't starve text generation, and
+//! editor completions don't wait behind long chat jobs. A
+//! request that finds every slot busy waits in the route's queue; once the
+//! queue is full, further requests are refused with `503 SERVER_BUSY` instead
+//! of piling up behind it. A slot is held until the response body has been
+//! sent, which covers streaming responses too.
+//!
+//! What is fair depends on the machine, so nothing is limited by default:
+//!
+//! - `SHIMMY_MAX_CONCURRENT_CHAT` / `SHIMMY_MAX_CONCURRENT_CODE` /
+//!   `SHIMMY_MAX_CONCURRENT_VISION`: slots per route (unset or `0` for no
+//!   limit)
+//! - `SHIMMY_MAX_QUEUE_CHAT` / `SHIMMY_MAX_QUEUE_CODE` /
+//!   `SHIMMY_MAX_QUEUE_VISION`: requests allowed to wait for a slot (unset
+//!   for no limit, `0` to refuse as soon as all slots are busy)
+
+use crate::error::ShimmyError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+pub const MAX_CONCURRENT_CHAT_ENV: &str = "SHIMMY_MAX_CONCURRENT_CHAT";
+pub const MAX_CONCURRENT_CODE_ENV: &str = "SHIMMY_MAX_CONCURRENT_CODE";
+pub const MAX_CONCURRENT_VISION_ENV: &str = "SHIMMY_MAX_CONCURRENT_VISION";
+pub const MAX_QUEUE_CHAT_ENV: &str = "SHIMMY_MAX_QUEUE_CHAT";
+pub const MAX_QUEUE_CODE_ENV: &str = "SHIMMY_MAX_QUEUE_CODE";
+pub const MAX_QUEUE_VISION_ENV: &str = "SHIMMY_MAX_QUEUE_VISION";
+
+/// Routes that run text generation
+const CHAT_ROUTES: &[&str] = &[
+    "/api/chat",
+    "/api/generate",
+    "/api/generate/raw",
+    "/v1/chat/completions",
+    "/v1/completions",
+    "/v1/messages",
+];
+
+/// Routes that run editor code completion
+const CODE_ROUTES: &[&str] = &["/v1/code/completions"];
+
+/// Routes that run vision inference
+const VISION_ROUTES: &[&str] = &["/api/vision", "/api/vision/batch"];
+
+/// Slots and waiting room for one group of routes
+pub struct RouteLimit {
+    name: &'static str,
+    slots: Option<Arc<Semaphore>>,
+    /// `usize::MAX` for no limit, so it can change at runtime
+    max_queue: AtomicUsize,
+    waiting: AtomicUsize,
+}
+
+/// Counts a request as waiting until it gets a slot or gives up
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl RouteLimit {
+    pub fn new(name: &'static str, concurrency: Option<usize>, max_queue: Option<usize>) -> Self {
+        Self {
+            name,
+            slots: concurrency
+                .filter(|&n| n > 0)
+                .map(|n| Arc::new(Semaphore::new(n))),
+            max_queue: AtomicUsize::new(max_queue.unwrap_or(usize::MAX)),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Requests allowed to wait for a slot; `None` for no limit
+    pub fn max_queue(&self) -> Option<usize> {
+        Some(self.max_queue.load(Ordering::SeqCst)).filter(|&n| n != usize::MAX)
+    }
+
+    /// Change the queue length. Requests already waiting keep their place
+    pub fn set_max_queue(&self, max_queue: Option<usize>) {
+        self.max_queue
+            .store(max_queue.unwrap_or(usize::MAX), Ordering::SeqCst);
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Requests currently waiting for a slot
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+
+    fn from_env(name: &'static str, concurrency_env: &str, queue_env: &str) -> Self {
+        Self::new(name, env_usize(concurrency_env), env_usize(queue_env))
+    }
+
+    /// Take a slot, waiting for one if the queue has room; `None` when the
+    /// route is unlimited
+    pub async fn enter(&self) -> Result<Option<OwnedSemaphorePermit>, ShimmyError> {
+        let Some(slots) = &self.slots else {
+            return Ok(None);
+        };
+        if let Ok(permit) = slots.clone().try_acquire_owned() {
+            return Ok(Some(permit));
+        }
+        let ahead = self.waiting.fetch_add(1, Ordering::SeqCst);
+        let _waiting = Waiting(&self.waiting);
+        if self.max_queue().is_some_and(|max| ahead >= max) {
+            return Err(ShimmyError::ServerBusy {
+                route: self.name.to_string(),
+            });
+        }
+        let permit = slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("route semaphore is never closed");
+        Ok(Some(permit))
+    }
+}
+
+/// Limits for every route group
+pub struct RouteLimits {
+    pub chat: RouteLimit,
+    pub code: RouteLimit,
+    pub vision: RouteLimit,
+}
+
+impl RouteLimits {
+    pub fn from_env() -> Self {
+        Self {
+            chat: RouteLimit::from_env("chat", MAX_CONCURRENT_CHAT_ENV, MAX_QUEUE_CHAT_ENV),
+            code: RouteLimit::from_env("code", MAX_CONCURRENT_CODE_ENV, MAX_QUEUE_CODE_ENV),
+            vision: RouteLimit::from_env("vision", MAX_CONCURRENT_VISION_ENV, MAX_QUEUE_VISION_ENV),
+        }
+    }
+
+    /// The limit covering a matched route pattern, if any
+    pub fn for_route(&self, route: &str) -> Option<&RouteLimit> {
+        if CHAT_ROUTES.contains(&route) {
+            Some(&self.chat)
+        } else if CODE_ROUTES.contains(&route) {
+            Some(&self.code)
+        } else if VISION_ROUTES.contains(&route) {
+            Some(&self.vision)
+        } else {
+            None
+        }
+    }
+}
+
+fn env_usize(name: &str) -> Option<usize> {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unlimited_route_hands_out_no_permit() {
+        let limit = RouteLimit::new("chat", None, Some(0));
+        assert!(limit.enter().await.unwrap().is_none());
+        let limit = RouteLimit::new("chat", Some(0), None);
+        assert!(limit.enter().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_is_refused() {
+        let limit = Arc::new(RouteLimit::new("vision", Some(1), Some(1)));
+        let first = limit.enter().await.unwrap();
+
+        let queued = {
+            let limit = limit.clone();
+            tokio::spawn(async move { limit.enter().await.map(|p| p.is_some()) })
+        };
+        while limit.waiting.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        let err = limit.enter().await.unwrap_err();
+        assert!(matches!(err, ShimmyError::ServerBusy { ref route } if route == "vision"));
+        assert_eq!(limit.waiting.load(Ordering::SeqCst), 1);
+
+        drop(first);
+        assert!(queued.await.unwrap().unwrap());
+        assert_eq!(limit.waiting.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_routes_map_to_their_group() {
+        let limits = RouteLimits {
+            chat: RouteLimit::new("chat", Some(2), None),
+            code: RouteLimit::new("code", Some(1), Some(0)),
+            vision: RouteLimit::new("vision", Some(1), None),
+        };
+        assert_eq!(
+            limits.for_route("/v1/chat/completions").unwrap().name,
+            "chat"
+        );
+        assert_eq!(
+            limits.for_route("/v1/code/completions").unwrap().name,
+            "code"
+        );
+        assert_eq!(limits.for_route("/api/vision").unwrap().name, "vision");
+        assert!(limits.for_route("/v1/models").is_none());
+    }
+}
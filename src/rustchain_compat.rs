@@ -32,6 +32,12 @@ pub async fn rustchain_generate(
         top_p: None,
         top_k: None,
         stream: Some(false),
+        max_time_ms: None,
+        token_ids: None,
+        logprobs: None,
+        grammar: None,
+        keep_alive: None,
+        adapter: None,
     };
 
     // For now, return a placeholder response since we don't have the full server context
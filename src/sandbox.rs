@@ -0,0 +1,164 @@
+//! Optional Landlock sandbox for `serve` on Linux.
+//!
+//! Model files and the tooling that loads them come from the internet, so
+//! `serve --sandbox` (or `SHIMMY_SANDBOX=1`) locks the server process down
+//! before it starts:
+//!
+//! - filesystem writes are limited to shimmy's own directories (data dir,
+//!   local data dir, model store, `<config_dir>/shimmy`), the temp directory,
+//!   `/dev` and any extra paths in `SHIMMY_SANDBOX_ALLOW_WRITE`; reads are not
+//!   restricted, so models anywhere on disk still load
+//! - outbound TCP is refused except to the ports in
+//!   `SHIMMY_SANDBOX_ALLOW_CONNECT` (e.g. `443` for license validation and
+//!   vision URL fetches); accepting connections is unaffected
+//!
+//! Landlock restricts the calling thread and everything it spawns afterwards,
+//! so [`apply`] must run before the async runtime starts its workers. The
+//! network rules need kernel 6.7+ (Landlock ABI 4); on older kernels only the
+//! filesystem rules apply and startup says so. A kernel without Landlock, or a
+//! build without the `sandbox` feature, refuses to start rather than run
+//! unprotected.
+
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+
+pub const SANDBOX_ENV: &str = "SHIMMY_SANDBOX";
+pub const ALLOW_CONNECT_ENV: &str = "SHIMMY_SANDBOX_ALLOW_CONNECT";
+pub const ALLOW_WRITE_ENV: &str = "SHIMMY_SANDBOX_ALLOW_WRITE";
+
+/// What the sandboxed process may still do
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxPolicy {
+    pub writable: Vec<PathBuf>,
+    pub connect_ports: Vec<u16>,
+}
+
+/// How much of the policy the kernel enforces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(all(target_os = "linux", feature = "sandbox")), allow(dead_code))]
+pub enum SandboxStatus {
+    Full,
+    /// The kernel's Landlock is older than ABI 4: writes are restricted but
+    /// outbound TCP is not
+    Partial,
+}
+
+impl std::fmt::Display for SandboxStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SandboxStatus::Full => write!(f, "filesystem writes and outbound TCP restricted"),
+            SandboxStatus::Partial => write!(
+                f,
+                "filesystem writes restricted; outbound TCP NOT restricted (needs Linux 6.7+)"
+            ),
+        }
+    }
+}
+
+/// Whether the sandbox was asked for by flag or environment
+pub fn requested(flag: bool) -> bool {
+    flag || std::env::var(SANDBOX_ENV).is_ok_and(|v| v == "1")
+}
+
+/// Comma-separated TCP ports
+pub fn parse_ports(spec: &str) -> Result<Vec<u16>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.parse::<u16>() {
+            Ok(port) if port > 0 => Ok(port),
+            _ => bail!("invalid {} port `{}`", ALLOW_CONNECT_ENV, p),
+        })
+        .collect()
+}
+
+impl SandboxPolicy {
+    pub fn from_env() -> Result<Self> {
+        let mut writable = vec![
+            crate::util::paths::data_dir(),
+            crate::util::paths::local_data_dir(),
+            crate::model_store::ModelStore::open_default()
+                .root()
+                .to_path_buf(),
+            std::env::temp_dir(),
+            PathBuf::from("/dev"),
+        ];
+        if let Some(config_dir) = dirs::config_dir() {
+            writable.push(config_dir.join(crate::branding::APP_NAME));
+        }
+        if let Some(extra) = std::env::var_os(ALLOW_WRITE_ENV) {
+            writable.extend(std::env::split_paths(&extra));
+        }
+        let connect_ports = match std::env::var(ALLOW_CONNECT_ENV) {
+            Ok(spec) => parse_ports(&spec)?,
+            Err(_) => Vec::new(),
+        };
+        Ok(Self {
+            writable,
+            connect_ports,
+        })
+    }
+}
+
+/// Restrict the current thread and everything it starts from now on
+#[cfg(all(target_os = "linux", feature = "sandbox"))]
+pub fn apply(policy: &SandboxPolicy) -> Result<SandboxStatus> {
+    use landlock::{
+        Access, AccessFs, AccessNet, NetPort, PathBeneath, PathFd, Ruleset, RulesetAttr,
+        RulesetCreatedAttr, RulesetStatus, ABI,
+    };
+
+    let abi = ABI::V4;
+    let mut ruleset = Ruleset::default()
+        .handle_access(AccessFs::from_write(abi))?
+        .handle_access(AccessNet::ConnectTcp)?
+        .create()?;
+    for path in &policy.writable {
+        // Our own directories may not exist yet on a first run
+        let _ = std::fs::create_dir_all(path);
+        match PathFd::new(path) {
+            Ok(fd) => {
+                ruleset = ruleset.add_rule(PathBeneath::new(fd, AccessFs::from_all(abi)))?;
+            }
+            Err(e) => eprintln!("⚠️  Sandbox: skipping {}: {}", path.display(), e),
+        }
+    }
+    for port in &policy.connect_ports {
+        ruleset = ruleset.add_rule(NetPort::new(*port, AccessNet::ConnectTcp))?;
+    }
+
+    match ruleset.restrict_self()?.ruleset {
+        RulesetStatus::FullyEnforced => Ok(SandboxStatus::Full),
+        RulesetStatus::PartiallyEnforced => Ok(SandboxStatus::Partial),
+        RulesetStatus::NotEnforced => {
+            bail!("--sandbox requested but this kernel does not support Landlock")
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "sandbox")))]
+pub fn apply(_policy: &SandboxPolicy) -> Result<SandboxStatus> {
+    bail!("--sandbox needs a Linux build with `--features sandbox`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ports() {
+        assert_eq!(parse_ports("443, 8080,").unwrap(), vec![443, 8080]);
+        assert!(parse_ports("").unwrap().is_empty());
+        for bad in ["0", "https", "70000"] {
+            let err = parse_ports(bad).unwrap_err().to_string();
+            assert!(err.contains(ALLOW_CONNECT_ENV), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_policy_covers_shimmy_directories() {
+        let policy = SandboxPolicy::from_env().unwrap();
+        assert!(policy.writable.contains(&crate::util::paths::data_dir()));
+        assert!(policy.writable.contains(&std::env::temp_dir()));
+    }
+}
@@ -1,15 +1,21 @@
-use crate::{anthropic_compat, api, openai_compat, util::diag::diag_handler, AppState};
-use axum::extract::Request;
+use crate::{
+    admin_config, anthropic_compat, api, code_completion, embeddings, error::ShimmyError,
+    ollama_compat, openai_compat, rag, util::diag::diag_handler, vector_store, AppState,
+};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, MatchedPath, Request};
 use axum::{
     extract::State,
     http::{HeaderValue, Method},
     middleware::{self, Next},
-    response::Response,
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
+use futures_util::StreamExt;
 use serde_json::{json, Value};
 use std::{net::SocketAddr, sync::Arc};
+use tracing::Instrument;
 
 /// CORS middleware for better client compatibility
 async fn cors_layer(req: Request, next: Next) -> Response {
@@ -24,7 +30,9 @@ async fn cors_layer(req: Request, next: Next) -> Response {
     );
     headers.insert(
         "Access-Control-Allow-Headers",
-        HeaderValue::from_static("Content-Type, Authorization"),
+        HeaderValue::from_static(
+            "Content-Type, Authorization, X-Api-Key, X-Request-Deadline-Ms, Idempotency-Key",
+        ),
     );
     headers.insert("Access-Control-Max-Age", HeaderValue::from_static("86400"));
 
@@ -36,6 +44,235 @@ async fn cors_layer(req: Request, next: Next) -> Response {
     response
 }
 
+/// Count requests per route pattern for opt-in telemetry (no paths or bodies)
+async fn telemetry_layer(req: Request, next: Next) -> Response {
+    if let Some(route) = req.extensions().get::<MatchedPath>() {
+        crate::telemetry::Telemetry::global().record_request(route.as_str());
+    }
+    next.run(req).await
+}
+
+/// Wrap each request in an `http.request` span, exported over OTLP when
+/// configured
+async fn span_layer(req: Request, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |route| route.as_str())
+        .to_string();
+    let span = tracing::info_span!(
+        "http.request",
+        otel.name = %format!("{} {}", req.method(), route),
+        http.method = %req.method(),
+        http.route = %route,
+        http.status_code = tracing::field::Empty,
+    );
+    let response = next.run(req).instrument(span.clone()).await;
+    span.record("http.status_code", response.status().as_u16());
+    response
+}
+
+/// Request counts and latency per route pattern for `/metrics`
+async fn metrics_layer(mut req: Request, next: Next) -> Response {
+    let Some(route) = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|route| route.as_str().to_string())
+    else {
+        return next.run(req).await;
+    };
+    let method = req.method().clone();
+    let started = std::time::Instant::now();
+    req.extensions_mut()
+        .insert(crate::prometheus::Received(started));
+    let response = next.run(req).await;
+    crate::prometheus::ServerMetrics::global().record_request(
+        method.as_str(),
+        &route,
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+    response
+}
+
+/// Write one line per request to `access.log` when file logging is on
+///
+/// Timed until the response head is ready, so a stream counts only up to its
+/// first byte.
+async fn access_log_layer(req: Request, next: Next) -> Response {
+    let Some(log) = crate::log_files::access_log() else {
+        return next.run(req).await;
+    };
+    let started = std::time::Instant::now();
+    let at = chrono::Local::now();
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let version = format!("{:?}", req.version());
+    let response = next.run(req).await;
+    let line = crate::log_files::access_line(
+        peer,
+        at,
+        (method.as_str(), &path, &version),
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+    if let Err(e) = log.append(line.as_bytes()) {
+        tracing::warn!("Could not write {}: {}", log.path().display(), e);
+    }
+    response
+}
+
+/// Routes that change server state, refused under `serve --read-only`
+const MUTATING_ROUTES: &[(Method, &str)] = &[
+    (Method::PUT, "/api/admin/config"),
+    (Method::POST, "/api/models/register"),
+    (Method::POST, "/api/models/:name/adapters"),
+    (Method::POST, "/api/models/:name/load"),
+    (Method::POST, "/api/models/:name/unload"),
+    (Method::POST, "/api/vectors"),
+    (Method::DELETE, "/api/vectors/:name"),
+    (Method::POST, "/api/vectors/:name/add"),
+    (Method::POST, "/api/rag/ingest"),
+    (Method::POST, "/api/system/power"),
+];
+
+fn is_mutating(method: &Method, route: &str) -> bool {
+    MUTATING_ROUTES
+        .iter()
+        .any(|(m, r)| m == method && *r == route)
+}
+
+/// Answer mutating routes with 403 when the server is read-only
+async fn read_only_layer(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    if state.read_only {
+        if let Some(route) = req.extensions().get::<MatchedPath>() {
+            if is_mutating(req.method(), route.as_str()) {
+                return ShimmyError::ReadOnly {
+                    operation: format!("{} {}", req.method(), route.as_str()),
+                }
+                .into_response();
+            }
+        }
+    }
+    next.run(req).await
+}
+
+/// Refuse clients that `SHIMMY_IP_ACL` keeps off the requested route
+async fn ip_acl_layer(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    if !state.ip_acl.is_empty() {
+        let path = req.uri().path();
+        let admin = req
+            .extensions()
+            .get::<MatchedPath>()
+            .is_some_and(|route| is_mutating(req.method(), route.as_str()));
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip());
+        if !peer.is_some_and(|ip| state.ip_acl.allows(ip, path, admin)) {
+            return ShimmyError::AccessDenied {
+                addr: peer.map_or_else(|| "an unknown address".to_string(), |ip| ip.to_string()),
+            }
+            .into_response();
+        }
+    }
+    next.run(req).await
+}
+
+/// Require a key from `SHIMMY_API_KEYS_FILE` and hold it to its limits
+async fn api_key_layer(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let open = req.method() == Method::OPTIONS
+        || req
+            .extensions()
+            .get::<MatchedPath>()
+            .is_some_and(|route| crate::api_keys::is_open_route(route.as_str()));
+    if state.api_keys.is_empty() || open {
+        return next.run(req).await;
+    }
+    let Some(key) = state.api_keys.authenticate(req.headers()) else {
+        return ShimmyError::Unauthorized.into_response();
+    };
+    if let Err(e) = key.admit(std::time::Instant::now(), chrono::Utc::now()) {
+        return e.into_response();
+    }
+    crate::api_keys::scope(key, next.run(req)).await
+}
+
+/// Hold every client, and the server as a whole, to `SHIMMY_RATE_LIMIT_*`
+async fn rate_limit_layer(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let open = req.method() == Method::OPTIONS
+        || req
+            .extensions()
+            .get::<MatchedPath>()
+            .is_some_and(|route| crate::api_keys::is_open_route(route.as_str()));
+    if state.rate_limits.is_empty() || open {
+        return next.run(req).await;
+    }
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    match state.rate_limits.admit(peer, std::time::Instant::now()) {
+        Ok(charge) => crate::rate_limit::scope(Arc::new(charge), next.run(req)).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Run the request under its `x-request-deadline-ms`, if it sent one
+async fn deadline_layer(req: Request, next: Next) -> Response {
+    match crate::deadline::from_headers(req.headers(), std::time::Instant::now()) {
+        Ok(deadline) => crate::deadline::scope(deadline, next.run(req)).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Hold a chat/vision slot until the response body has been sent
+async fn concurrency_layer(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(limit) = req
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|route| state.route_limits.for_route(route.as_str()))
+    else {
+        return next.run(req).await;
+    };
+    if let Err(e) = crate::deadline::admit() {
+        return e.into_response();
+    }
+    let entered = crate::deadline::within(
+        "queueing",
+        limit
+            .enter()
+            .instrument(tracing::info_span!("queue.wait", queue = limit.name())),
+    )
+    .await;
+    let permit = match entered.and_then(|entered| entered) {
+        Ok(Some(permit)) => permit,
+        Ok(None) => return next.run(req).await,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = crate::deadline::admit() {
+        return e.into_response();
+    }
+    let (parts, body) = next.run(req).await.into_parts();
+    let body = Body::from_stream(body.into_data_stream().map(move |chunk| {
+        let _slot = &permit;
+        chunk
+    }));
+    Response::from_parts(parts, body)
+}
+
 /// Enhanced health check endpoint for production use
 async fn health_check(State(state): State<Arc<AppState>>) -> Json<Value> {
     let models = state.registry.list_all_available();
@@ -46,6 +283,7 @@ async fn health_check(State(state): State<Arc<AppState>>) -> Json<Value> {
         "status": "ok",
         "service": "shimmy",
         "version": env!("CARGO_PKG_VERSION"),
+        "read_only": state.read_only,
         "models": {
             "total": models.len(),
             "discovered": discovered,
@@ -53,12 +291,14 @@ async fn health_check(State(state): State<Arc<AppState>>) -> Json<Value> {
         },
         "endpoints": {
             "health": "/health",
+            "ready": "/readyz",
             "models": "/v1/models",
             "chat": "/v1/chat/completions",
             "generate": "/api/generate"
         },
         "compatibility": {
             "openai": true,
+            "ollama": true,
             "cors": true
         },
         "timestamp": chrono::Utc::now().to_rfc3339(),
@@ -69,7 +309,68 @@ async fn health_check(State(state): State<Arc<AppState>>) -> Json<Value> {
     }))
 }
 
-/// Metrics endpoint for monitoring and performance tracking
+/// Readiness probe: 503 while swap thrashing is detected
+async fn readiness_check() -> (axum::http::StatusCode, Json<Value>) {
+    let monitor = crate::util::memory::MemoryPressureMonitor::global();
+    if monitor.is_degraded() {
+        (
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "status": "degraded",
+                "reason": "memory_pressure",
+                "paused": monitor.should_pause()
+            })),
+        )
+    } else {
+        (
+            axum::http::StatusCode::OK,
+            Json(json!({ "status": "ready" })),
+        )
+    }
+}
+
+/// `/metrics`: the Prometheus text format for scrapers, JSON otherwise
+async fn metrics(State(state): State<Arc<AppState>>, headers: axum::http::HeaderMap) -> Response {
+    if !crate::prometheus::wants_text(&headers) {
+        return metrics_endpoint(State(state)).await.into_response();
+    }
+    let running = state.engine.running_models();
+    let snapshot = crate::prometheus::Snapshot {
+        queue_depth: vec![
+            (
+                state.route_limits.chat.name(),
+                state.route_limits.chat.waiting(),
+            ),
+            (
+                state.route_limits.code.name(),
+                state.route_limits.code.waiting(),
+            ),
+            (
+                state.route_limits.vision.name(),
+                state.route_limits.vision.waiting(),
+            ),
+            ("load", state.load_queue.waiting()),
+        ],
+        loaded_models: running.len(),
+        loaded_model_bytes: running
+            .iter()
+            .filter_map(|m| m.memory.map(|u| u.total_bytes()))
+            .sum(),
+        memory: crate::util::memory::sample_memory(),
+        reserved_bytes: crate::util::memory::MemoryAdmission::global().reserved_bytes(),
+        code_slo: Some(crate::code_completion::slo()),
+    };
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            crate::prometheus::CONTENT_TYPE,
+        )],
+        crate::prometheus::ServerMetrics::global().render(&snapshot),
+    )
+        .into_response()
+}
+
+/// JSON summary for monitoring and performance tracking
 async fn metrics_endpoint(State(state): State<Arc<AppState>>) -> Json<Value> {
     let models = state.registry.list_all_available();
     let discovered_models = state.registry.discovered_models.clone();
@@ -80,15 +381,8 @@ async fn metrics_endpoint(State(state): State<Arc<AppState>>) -> Json<Value> {
         .map(|m| m.size_bytes / (1024 * 1024))
         .sum();
 
-    let memory_info = sys_info::mem_info().unwrap_or(sys_info::MemInfo {
-        total: 0,
-        free: 0,
-        avail: 0,
-        buffers: 0,
-        cached: 0,
-        swap_total: 0,
-        swap_free: 0,
-    });
+    let memory = crate::util::memory::sample_memory().unwrap_or_default();
+    let to_mb = |bytes: u64| bytes / (1024 * 1024);
 
     // GPU detection for metrics endpoint
     let gpu_detected = detect_gpu();
@@ -108,9 +402,9 @@ async fn metrics_endpoint(State(state): State<Arc<AppState>>) -> Json<Value> {
             }
         },
         "system": {
-            "memory_total_mb": memory_info.total / 1024,
-            "memory_free_mb": memory_info.free / 1024,
-            "memory_available_mb": memory_info.avail / 1024
+            "memory_total_mb": to_mb(memory.total_bytes),
+            "memory_free_mb": to_mb(memory.free_bytes),
+            "memory_available_mb": to_mb(memory.available_bytes)
         },
         "features": {
             "llama": cfg!(feature = "llama"),
@@ -118,29 +412,102 @@ async fn metrics_endpoint(State(state): State<Arc<AppState>>) -> Json<Value> {
         },
         "endpoints": [
             "/health",
+            "/readyz",
             "/metrics",
             "/v1/chat/completions",
+            "/v1/completions",
+            "/v1/code/completions",
             "/v1/models",
+            "/v1/embeddings",
             "/api/generate",
-            "/api/models"
+            "/api/generate/raw",
+            "/api/chat",
+            "/api/tags",
+            "/api/models",
+            "/api/ps",
+            "/api/events",
+            "/api/system",
+            "/api/connect"
         ],
         "timestamp": chrono::Utc::now().to_rfc3339()
     }))
 }
 
-pub async fn run(addr: SocketAddr, state: Arc<AppState>) -> anyhow::Result<()> {
+/// Serve the API on `addr`, over HTTPS when `tls` is given
+pub async fn run(
+    addr: SocketAddr,
+    state: Arc<AppState>,
+    tls: Option<crate::tls::TlsFiles>,
+) -> anyhow::Result<()> {
+    let tls_config = match &tls {
+        Some(files) => Some(files.load().await?),
+        None => None,
+    };
     let listener = tokio::net::TcpListener::bind(addr).await?;
+
+    let sample_secs = std::env::var("SHIMMY_MEMORY_SAMPLE_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(5);
+    crate::util::memory::MemoryPressureMonitor::global()
+        .spawn(std::time::Duration::from_secs(sample_secs));
+    crate::telemetry::Telemetry::global().spawn_flusher(std::time::Duration::from_secs(60));
+    crate::load_progress::spawn_terminal_reporter();
+    state.model_cache.spawn_reaper();
+    #[cfg(feature = "vision")]
+    if let Some(license_manager) = &state.vision_license_manager {
+        license_manager.spawn_usage_flusher();
+    }
+    #[cfg(feature = "vision")]
+    let shutdown_state = state.clone();
+
     #[allow(unused_mut)]
     let mut app = Router::new()
         .route("/health", get(health_check))
-        .route("/metrics", get(metrics_endpoint))
+        .route("/readyz", get(readiness_check))
+        .route("/metrics", get(metrics))
         .route("/diag", get(diag_handler))
-        .route("/api/generate", post(api::generate))
+        .route("/api/generate", post(ollama_compat::generate_any))
+        .route("/api/generate/raw", post(api::generate_raw))
         .route("/api/models", get(api::list_models))
         .route("/api/models/discover", post(api::discover_models))
+        .route("/api/models/register", post(api::register_model))
         .route("/api/models/:name/load", post(api::load_model))
         .route("/api/models/:name/unload", post(api::unload_model))
         .route("/api/models/:name/status", get(api::model_status))
+        .route(
+            "/api/models/:name/adapters",
+            get(api::list_adapters).post(api::register_adapter),
+        )
+        .route(
+            "/api/models/:name/apply-template",
+            post(api::apply_template),
+        )
+        .route("/api/ps", get(api::running_models))
+        .route("/api/events", get(api::events))
+        .route("/api/system", get(api::system_info))
+        .route("/api/system/power", post(api::set_power_mode))
+        .route("/api/connect", get(api::connect_info))
+        .route(
+            "/api/admin/config",
+            get(admin_config::get_config).put(admin_config::put_config),
+        )
+        .route(
+            "/api/vectors",
+            get(vector_store::list_collections).post(vector_store::create_collection),
+        )
+        .route(
+            "/api/vectors/:name",
+            axum::routing::delete(vector_store::delete_collection),
+        )
+        .route("/api/vectors/:name/add", post(vector_store::add_vectors))
+        .route(
+            "/api/vectors/:name/query",
+            post(vector_store::query_vectors),
+        )
+        .route("/api/rag/ingest", post(rag::ingest))
+        .route("/api/rag/query", post(rag::query))
         .route("/api/tools", get(api::list_tools))
         .route("/api/tools/:name/execute", post(api::execute_tool))
         .route("/api/workflows/execute", post(api::execute_workflow))
@@ -149,26 +516,145 @@ pub async fn run(addr: SocketAddr, state: Arc<AppState>) -> anyhow::Result<()> {
             "/v1/chat/completions",
             post(openai_compat::chat_completions),
         )
+        .route("/v1/completions", post(openai_compat::completions))
+        .route("/v1/code/completions", post(code_completion::completions))
         .route("/v1/models", get(openai_compat::models))
+        .route("/v1/embeddings", post(embeddings::embeddings))
         // Anthropic Claude API compatibility
-        .route("/v1/messages", post(anthropic_compat::messages));
+        .route("/v1/messages", post(anthropic_compat::messages))
+        // Ollama API compatibility; /api/generate is shared above
+        .route("/api/chat", post(ollama_compat::chat))
+        .route("/api/tags", get(ollama_compat::tags))
+        .route("/api/show", post(ollama_compat::show))
+        .route("/api/version", get(ollama_compat::version));
 
     #[cfg(feature = "vision")]
     {
-        app = app.route("/api/vision", post(api::vision));
+        app = app
+            .route("/api/vision", post(api::vision))
+            .route("/api/vision/batch", post(api::vision_batch))
+            .route("/api/license/status", get(api::license_status));
     }
 
-    let app = app.layer(middleware::from_fn(cors_layer)).with_state(state);
-    axum::serve(listener, app).await?;
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            read_only_layer,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            concurrency_layer,
+        ))
+        .layer(middleware::from_fn(deadline_layer))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit_layer,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), api_key_layer))
+        .layer(middleware::from_fn_with_state(state.clone(), ip_acl_layer))
+        .layer(middleware::from_fn(telemetry_layer))
+        .layer(middleware::from_fn(metrics_layer))
+        .layer(middleware::from_fn(cors_layer))
+        .layer(middleware::from_fn(access_log_layer))
+        .layer(middleware::from_fn(span_layer))
+        // For `/api/connect` answering requests without a `Host` header
+        .layer(axum::Extension(crate::connect::Listener {
+            addr: listener.local_addr()?,
+            tls: tls.is_some(),
+        }))
+        .with_state(state);
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let (stop_tx, stop_rx) = tokio::sync::watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("Shutting down; finishing in-flight requests");
+        let _ = stop_tx.send(true);
+    });
+    match tls.zip(tls_config) {
+        Some((files, config)) => {
+            files.spawn_reloader(config.clone());
+            let handle = axum_server::Handle::new();
+            let shutdown = handle.clone();
+            let stopped = stopped(stop_rx);
+            tokio::spawn(async move {
+                stopped.await;
+                shutdown.graceful_shutdown(Some(SHUTDOWN_GRACE));
+            });
+            axum_server::from_tcp_rustls(listener.into_std()?, config)
+                .handle(handle)
+                .serve(app)
+                .await?
+        }
+        None => {
+            let server =
+                axum::serve(listener, app).with_graceful_shutdown(stopped(stop_rx.clone()));
+            // Open streams would otherwise hold the shutdown up indefinitely
+            let grace = async {
+                stopped(stop_rx).await;
+                tokio::time::sleep(SHUTDOWN_GRACE).await;
+            };
+            tokio::select! {
+                result = server => result?,
+                () = grace => tracing::warn!(
+                    "Requests still running after {:?}; shutting down anyway",
+                    SHUTDOWN_GRACE
+                ),
+            }
+        }
+    }
+
+    #[cfg(feature = "vision")]
+    if let Some(license_manager) = &shutdown_state.vision_license_manager {
+        match license_manager.flush_usage_export().await {
+            Ok(0) => {}
+            Ok(sent) => tracing::info!("Exported {} queued usage events", sent),
+            Err(e) => tracing::warn!("Usage export flush on shutdown failed: {}", e),
+        }
+    }
     Ok(())
 }
 
+/// How long in-flight requests get to finish once shutdown starts
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
+/// Resolves once shutdown has started
+async fn stopped(mut stop: tokio::sync::watch::Receiver<bool>) {
+    if stop.wait_for(|stopping| *stopping).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
 /// GPU detection for metrics endpoint
 fn detect_gpu() -> bool {
     detect_nvidia() || detect_amd() || detect_intel()
 }
 
-fn get_gpu_vendor() -> Option<String> {
+pub(crate) fn get_gpu_vendor() -> Option<String> {
     if detect_nvidia() {
         Some("nvidia".to_string())
     } else if detect_amd() {
@@ -239,6 +725,240 @@ mod tests {
         assert_eq!(state.registry.list().len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_readiness_ready_when_not_degraded() {
+        let (status, Json(body)) = readiness_check().await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(body["status"], "ready");
+    }
+
+    #[tokio::test]
+    async fn test_read_only_refuses_mutating_routes() {
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let app = |read_only: bool| {
+            let engine = Box::new(crate::engine::adapter::InferenceEngineAdapter::new());
+            let mut state = AppState::new(engine, Registry::default());
+            state.read_only = read_only;
+            let state = Arc::new(state);
+            Router::new()
+                .route(
+                    "/api/vectors",
+                    get(|| async { "list" }).post(|| async { "created" }),
+                )
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    read_only_layer,
+                ))
+                .with_state(state)
+        };
+        let call = |read_only: bool, method: Method| async move {
+            let req = Request::builder()
+                .method(method)
+                .uri("/api/vectors")
+                .body(Body::empty())
+                .unwrap();
+            app(read_only).oneshot(req).await.unwrap().status()
+        };
+
+        assert_eq!(call(true, Method::POST).await, StatusCode::FORBIDDEN);
+        assert_eq!(call(true, Method::GET).await, StatusCode::OK);
+        assert_eq!(call(false, Method::POST).await, StatusCode::OK);
+        assert!(is_mutating(&Method::DELETE, "/api/vectors/:name"));
+        assert!(!is_mutating(&Method::POST, "/api/generate"));
+    }
+
+    #[tokio::test]
+    async fn test_ip_acl_checks_the_peer_address() {
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let engine = Box::new(crate::engine::adapter::InferenceEngineAdapter::new());
+        let mut state = AppState::new(engine, Registry::default());
+        state.ip_acl = crate::ip_acl::IpAcl::parse("admin allow loopback; * allow lan").unwrap();
+        let state = Arc::new(state);
+        let app = Router::new()
+            .route("/api/vectors", post(|| async { "created" }))
+            .route("/api/generate", post(|| async { "generated" }))
+            .layer(middleware::from_fn_with_state(state.clone(), ip_acl_layer))
+            .with_state(state);
+        let call = |peer: Option<&str>, uri: &str| {
+            let mut req = Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            if let Some(peer) = peer {
+                let addr: SocketAddr = peer.parse().unwrap();
+                req.extensions_mut().insert(ConnectInfo(addr));
+            }
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+
+        let lan = Some("192.168.1.20:50000");
+        assert_eq!(call(lan, "/api/generate").await, StatusCode::OK);
+        assert_eq!(call(lan, "/api/vectors").await, StatusCode::FORBIDDEN);
+        let local = Some("127.0.0.1:50000");
+        assert_eq!(call(local, "/api/vectors").await, StatusCode::OK);
+        let outside = Some("203.0.113.9:50000");
+        assert_eq!(call(outside, "/api/generate").await, StatusCode::FORBIDDEN);
+        assert_eq!(call(None, "/api/generate").await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_api_keys_are_required_and_rate_limited() {
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let engine = Box::new(crate::engine::adapter::InferenceEngineAdapter::new());
+        let mut state = AppState::new(engine, Registry::default());
+        state.api_keys = crate::api_keys::ApiKeys::parse(
+            r#"{"keys": [{"name": "alice", "key": "sk-alice", "requests_per_minute": 1}]}"#,
+        )
+        .unwrap();
+        let state = Arc::new(state);
+        let app = Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .route(
+                "/api/generate",
+                post(|| async {
+                    crate::api_keys::current()
+                        .map(|key| key.name.clone())
+                        .unwrap_or_default()
+                }),
+            )
+            .layer(middleware::from_fn_with_state(state.clone(), api_key_layer))
+            .with_state(state);
+        let call = |uri: &str, key: Option<&str>| {
+            let method = if uri == "/health" {
+                Method::GET
+            } else {
+                Method::POST
+            };
+            let mut req = Request::builder().method(method).uri(uri);
+            if let Some(key) = key {
+                req = req.header("authorization", format!("Bearer {}", key));
+            }
+            app.clone().oneshot(req.body(Body::empty()).unwrap())
+        };
+
+        assert_eq!(
+            call("/health", None).await.unwrap().status(),
+            StatusCode::OK
+        );
+        let missing = call("/api/generate", None).await.unwrap();
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        let wrong = call("/api/generate", Some("sk-bob")).await.unwrap();
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+
+        let first = call("/api/generate", Some("sk-alice")).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(first.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"alice");
+
+        let limited = call("/api/generate", Some("sk-alice")).await.unwrap();
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()["retry-after"], "60");
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_apply_per_client_address() {
+        use crate::rate_limit::{Limits, RateLimits};
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let engine = Box::new(crate::engine::adapter::InferenceEngineAdapter::new());
+        let mut state = AppState::new(engine, Registry::default());
+        let per_client = Limits {
+            requests_per_minute: Some(1),
+            tokens_per_minute: None,
+        };
+        state.rate_limits = RateLimits::new(Limits::default(), per_client);
+        let state = Arc::new(state);
+        let app = Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .route(
+                "/api/generate",
+                post(|| async { crate::rate_limit::current().is_some().to_string() }),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit_layer,
+            ))
+            .with_state(state);
+        let call = |peer: &str, uri: &str| {
+            let method = if uri == "/health" {
+                Method::GET
+            } else {
+                Method::POST
+            };
+            let mut req = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let addr: SocketAddr = peer.parse().unwrap();
+            req.extensions_mut().insert(ConnectInfo(addr));
+            app.clone().oneshot(req)
+        };
+
+        let first = call("192.168.1.20:50000", "/api/generate").await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(first.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"true");
+
+        let limited = call("192.168.1.20:50001", "/api/generate").await.unwrap();
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()["retry-after"], "60");
+        let other = call("192.168.1.21:50000", "/api/generate").await.unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+        let health = call("192.168.1.20:50000", "/health").await.unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_chat_slot_is_held_until_the_body_is_sent() {
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let engine = Box::new(crate::engine::adapter::InferenceEngineAdapter::new());
+        let mut state = AppState::new(engine, Registry::default());
+        state.route_limits.chat = crate::route_limits::RouteLimit::new("chat", Some(1), Some(0));
+        let state = Arc::new(state);
+        let app = Router::new()
+            .route("/api/generate", post(|| async { "generated" }))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                concurrency_layer,
+            ))
+            .with_state(state);
+        let call = || {
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri("/api/generate")
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(req)
+        };
+
+        let first = call().await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let busy = call().await.unwrap();
+        assert_eq!(busy.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = axum::body::to_bytes(first.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"generated");
+        assert_eq!(call().await.unwrap().status(), StatusCode::OK);
+    }
+
     #[test]
     fn test_socket_addr_parsing() {
         let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
@@ -293,7 +1013,10 @@ mod tests {
         let state = Arc::new(crate::AppState::new(engine, registry));
 
         // Test that run function exercises TcpListener::bind line (line 6)
-        let result = timeout(Duration::from_millis(100), async { run(addr, state).await }).await;
+        let result = timeout(Duration::from_millis(100), async {
+            run(addr, state, None).await
+        })
+        .await;
 
         // Should timeout quickly since server would run indefinitely
         // but this exercises the bind() call on line 6
@@ -333,7 +1056,7 @@ mod tests {
         let state = Arc::new(crate::AppState::new(engine, registry));
 
         // Create a future that will exercise the run function
-        let run_future = run(addr, state);
+        let run_future = run(addr, state, None);
 
         // Set a very short timeout to ensure we exercise the setup but don't actually serve
         let result = timeout(Duration::from_millis(50), run_future).await;
@@ -358,7 +1081,7 @@ mod tests {
         let state = Arc::new(crate::AppState::new(engine, registry));
 
         // Spawn the server in a background task
-        let server_handle = tokio::spawn(async move { run(addr, state).await });
+        let server_handle = tokio::spawn(async move { run(addr, state, None).await });
 
         // Give it a tiny amount of time to start
         tokio::time::sleep(Duration::from_millis(10)).await;
@@ -386,7 +1109,7 @@ mod tests {
         let state = Arc::new(crate::AppState::new(engine, registry));
 
         // Start the function and let it bind
-        let run_task = tokio::spawn(run(addr, state));
+        let run_task = tokio::spawn(run(addr, state, None));
 
         // Let it run long enough to execute all setup lines
         tokio::time::sleep(Duration::from_millis(20)).await;
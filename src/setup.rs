@@ -0,0 +1,573 @@
+//! First-run setup wizard (`shimmy setup`).
+//!
+//! Detects RAM/VRAM, recommends a starter model (and the vision model when the
+//! machine can hold it), optionally downloads them, writes `shimmy.toml`, and
+//! prints connection snippets for common clients.
+//!
+//! `shimmy.toml` is a flat `key = "value"` file. On startup [`apply_config_file`]
+//! exports its values as the matching `SHIMMY_*` environment variables unless
+//! they are already set, so the environment always wins.
+
+use crate::model_store::{LayerKind, ModelStore};
+use crate::util::memory::{self, MemoryStatus};
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+
+/// File name of the setup-generated config
+pub const CONFIG_FILE: &str = "shimmy.toml";
+
+/// Default address clients are pointed at
+const DEFAULT_BIND: &str = "127.0.0.1:11435";
+
+/// `shimmy.toml` keys and the environment variables they populate
+const CONFIG_ENV: &[(&str, &str)] = &[
+    ("access_log", "SHIMMY_ACCESS_LOG"),
+    ("admin_token", "SHIMMY_ADMIN_TOKEN"),
+    ("api_keys_file", "SHIMMY_API_KEYS_FILE"),
+    ("base_gguf", "SHIMMY_BASE_GGUF"),
+    ("bind", "SHIMMY_BIND_ADDRESS"),
+    ("ca_bundle", "SHIMMY_CA_BUNDLE"),
+    ("code_slo_ms", "SHIMMY_CODE_SLO_MS"),
+    ("cpu_affinity", "SHIMMY_CPU_AFFINITY"),
+    ("vision_model_dir", "SHIMMY_VISION_MODEL_DIR"),
+    ("data_dir", "SHIMMY_DATA_DIR"),
+    ("deadline_min_ms", "SHIMMY_DEADLINE_MIN_MS"),
+    ("grpc_bind", "SHIMMY_GRPC_BIND"),
+    ("ip_acl", "SHIMMY_IP_ACL"),
+    ("isolate_inference", "SHIMMY_ISOLATE_INFERENCE"),
+    ("keygen_pins", "SHIMMY_KEYGEN_PINS"),
+    ("log_dir", "SHIMMY_LOG_DIR"),
+    ("log_keep", "SHIMMY_LOG_KEEP"),
+    ("log_max_size_mb", "SHIMMY_LOG_MAX_SIZE_MB"),
+    ("log_rotate", "SHIMMY_LOG_ROTATE"),
+    ("max_concurrent_chat", "SHIMMY_MAX_CONCURRENT_CHAT"),
+    ("max_concurrent_code", "SHIMMY_MAX_CONCURRENT_CODE"),
+    ("max_concurrent_vision", "SHIMMY_MAX_CONCURRENT_VISION"),
+    ("max_queue_chat", "SHIMMY_MAX_QUEUE_CHAT"),
+    ("max_queue_code", "SHIMMY_MAX_QUEUE_CODE"),
+    ("max_queue_vision", "SHIMMY_MAX_QUEUE_VISION"),
+    ("model_memory_mb", "SHIMMY_MODEL_MEMORY_MB"),
+    ("model_schedule", "SHIMMY_MODEL_SCHEDULE"),
+    ("model_store", "SHIMMY_MODEL_STORE"),
+    ("model_ttl", "SHIMMY_MODEL_TTL"),
+    ("no_proxy", "SHIMMY_NO_PROXY"),
+    ("ollama_generate", "SHIMMY_OLLAMA_GENERATE"),
+    ("otlp_endpoint", "SHIMMY_OTLP_ENDPOINT"),
+    ("power_mode", "SHIMMY_POWER_MODE"),
+    ("proxy", "SHIMMY_PROXY"),
+    ("rate_limit_ip_rpm", "SHIMMY_RATE_LIMIT_IP_RPM"),
+    ("rate_limit_ip_tpm", "SHIMMY_RATE_LIMIT_IP_TPM"),
+    ("rate_limit_rpm", "SHIMMY_RATE_LIMIT_RPM"),
+    ("rate_limit_tpm", "SHIMMY_RATE_LIMIT_TPM"),
+    ("sandbox", "SHIMMY_SANDBOX"),
+    ("sandbox_allow_connect", "SHIMMY_SANDBOX_ALLOW_CONNECT"),
+    ("sandbox_allow_write", "SHIMMY_SANDBOX_ALLOW_WRITE"),
+    ("state_store", "SHIMMY_STATE_STORE"),
+    ("state_store_path", "SHIMMY_STATE_STORE_PATH"),
+    ("telemetry", "SHIMMY_TELEMETRY"),
+    ("tls_cert", "SHIMMY_TLS_CERT"),
+    ("tls_key", "SHIMMY_TLS_KEY"),
+    (
+        "vision_batch_concurrency",
+        "SHIMMY_VISION_BATCH_CONCURRENCY",
+    ),
+    ("vision_max_frames", "SHIMMY_VISION_MAX_FRAMES"),
+    ("vision_max_images", "SHIMMY_VISION_MAX_IMAGES"),
+    ("vision_mode_models", "SHIMMY_VISION_MODE_MODELS"),
+    ("vision_modes_file", "SHIMMY_VISION_MODES_FILE"),
+    ("vision_ocr_backend", "SHIMMY_VISION_OCR_BACKEND"),
+    ("vision_safety", "SHIMMY_VISION_SAFETY"),
+    ("vision_safety_model", "SHIMMY_VISION_SAFETY_MODEL"),
+];
+
+/// A downloadable starter model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StarterModel {
+    pub name: &'static str,
+    pub file_name: &'static str,
+    pub url: &'static str,
+    /// Approximate download size
+    pub size_bytes: u64,
+}
+
+/// Starter models, smallest first
+pub const STARTER_MODELS: &[StarterModel] = &[
+    StarterModel {
+        name: "Qwen2.5 0.5B Instruct (Q4_K_M)",
+        file_name: "qwen2.5-0.5b-instruct-q4_k_m.gguf",
+        url: "https://huggingface.co/Qwen/Qwen2.5-0.5B-Instruct-GGUF/resolve/main/qwen2.5-0.5b-instruct-q4_k_m.gguf",
+        size_bytes: 491_000_000,
+    },
+    StarterModel {
+        name: "Phi-3 Mini 4K Instruct (Q4)",
+        file_name: "Phi-3-mini-4k-instruct-q4.gguf",
+        url: "https://huggingface.co/microsoft/Phi-3-mini-4k-instruct-gguf/resolve/main/Phi-3-mini-4k-instruct-q4.gguf",
+        size_bytes: 2_390_000_000,
+    },
+    StarterModel {
+        name: "Llama 3.1 8B Instruct (Q4_K_M)",
+        file_name: "Meta-Llama-3.1-8B-Instruct-Q4_K_M.gguf",
+        url: "https://huggingface.co/bartowski/Meta-Llama-3.1-8B-Instruct-GGUF/resolve/main/Meta-Llama-3.1-8B-Instruct-Q4_K_M.gguf",
+        size_bytes: 4_920_000_000,
+    },
+];
+
+/// Approximate size of the MiniCPM-V model plus projector
+pub const VISION_MODEL_BYTES: u64 = 5_720_000_000;
+
+/// Detected hardware
+#[derive(Debug, Clone, PartialEq)]
+pub struct HardwareProfile {
+    pub total_ram_bytes: u64,
+    pub available_ram_bytes: u64,
+    pub gpu_vendor: Option<String>,
+    pub vram_bytes: Option<u64>,
+}
+
+impl HardwareProfile {
+    pub fn detect() -> Self {
+        Self {
+            total_ram_bytes: memory::get_total_memory(),
+            available_ram_bytes: memory::get_available_memory(),
+            gpu_vendor: crate::server::get_gpu_vendor(),
+            vram_bytes: memory::detect_vram_bytes(),
+        }
+    }
+
+    /// How a model of `size_bytes` fits, using VRAM when it is larger than free RAM
+    pub fn fit(&self, size_bytes: u64) -> MemoryStatus {
+        let required = memory::estimate_memory_requirements(size_bytes).estimated_runtime_gb;
+        let vram = self.vram_bytes.unwrap_or(0);
+        MemoryStatus::classify(
+            required,
+            gb(self.total_ram_bytes.max(vram)),
+            gb(self.available_ram_bytes.max(vram)),
+        )
+    }
+}
+
+fn gb(bytes: u64) -> f64 {
+    // Same divisor as `estimate_memory_requirements`
+    bytes as f64 / 1_024_000_000.0
+}
+
+/// Largest starter model that fits, falling back to the smallest one
+pub fn recommend_starter(hw: &HardwareProfile) -> &'static StarterModel {
+    STARTER_MODELS
+        .iter()
+        .rev()
+        .find(|m| hw.fit(m.size_bytes) == MemoryStatus::Sufficient)
+        .unwrap_or(&STARTER_MODELS[0])
+}
+
+/// Whether the vision model fits on its own
+pub fn recommend_vision(hw: &HardwareProfile) -> bool {
+    hw.fit(VISION_MODEL_BYTES) == MemoryStatus::Sufficient
+}
+
+/// Settings written to `shimmy.toml`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SetupConfig {
+    pub base_gguf: Option<PathBuf>,
+    pub bind: Option<String>,
+    pub vision_model_dir: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+}
+
+impl SetupConfig {
+    pub fn to_toml(&self) -> String {
+        let mut out =
+            String::from("# Generated by `shimmy setup`; environment variables take precedence\n");
+        let entries = [
+            (
+                "base_gguf",
+                self.base_gguf.as_ref().map(|p| p.display().to_string()),
+            ),
+            ("bind", self.bind.clone()),
+            (
+                "vision_model_dir",
+                self.vision_model_dir
+                    .as_ref()
+                    .map(|p| p.display().to_string()),
+            ),
+            (
+                "data_dir",
+                self.data_dir.as_ref().map(|p| p.display().to_string()),
+            ),
+        ];
+        for (key, value) in entries {
+            if let Some(value) = value {
+                out.push_str(&format!("{} = \"{}\"\n", key, escape(&value)));
+            }
+        }
+        out
+    }
+
+    pub fn from_toml(text: &str) -> Self {
+        let values = parse_flat_toml(text);
+        let get = |key: &str| {
+            values
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+        };
+        Self {
+            base_gguf: get("base_gguf").map(PathBuf::from),
+            bind: get("bind"),
+            vision_model_dir: get("vision_model_dir").map(PathBuf::from),
+            data_dir: get("data_dir").map(PathBuf::from),
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Parse top-level `key = "value"` lines; comments, tables and other value
+/// types are ignored
+fn parse_flat_toml(text: &str) -> Vec<(String, String)> {
+    let mut values = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            // Only the top-level table is read
+            break;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.is_empty() || key.starts_with('#') {
+            continue;
+        }
+        let Some(quoted) = value.trim().strip_prefix('"') else {
+            continue;
+        };
+
+        let mut parsed = String::new();
+        let mut chars = quoted.chars();
+        let mut closed = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => parsed.push('\n'),
+                    Some('t') => parsed.push('\t'),
+                    Some(other) => parsed.push(other),
+                    None => break,
+                },
+                '"' => {
+                    closed = true;
+                    break;
+                }
+                c => parsed.push(c),
+            }
+        }
+        if closed {
+            values.push((key.to_string(), parsed));
+        }
+    }
+    values
+}
+
+/// `./shimmy.toml` if present, otherwise `<config_dir>/shimmy/shimmy.toml`
+pub fn config_path() -> PathBuf {
+    let local = PathBuf::from(CONFIG_FILE);
+    if local.exists() {
+        return local;
+    }
+    dirs::config_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(crate::branding::APP_NAME)
+        .join(CONFIG_FILE)
+}
+
+/// Set `key` in `text`, replacing its top-level line or adding one before the
+/// first table
+fn set_flat_toml(text: &str, key: &str, value: &str) -> String {
+    let entry = format!("{} = \"{}\"", key, escape(value));
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let top_level = lines
+        .iter()
+        .position(|line| line.trim().starts_with('['))
+        .unwrap_or(lines.len());
+    let existing = lines[..top_level]
+        .iter()
+        .position(|line| line.split_once('=').is_some_and(|(k, _)| k.trim() == key));
+    match existing {
+        Some(i) => lines[i] = entry,
+        None => lines.insert(top_level, entry),
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Store `key = "value"` in `shimmy.toml`, keeping its other settings
+pub fn set_config_value(key: &str, value: &str) -> anyhow::Result<PathBuf> {
+    let path = config_path();
+    let text = std::fs::read_to_string(&path).unwrap_or_default();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, set_flat_toml(&text, key, value))?;
+    Ok(path)
+}
+
+/// Export `shimmy.toml` values as `SHIMMY_*` variables that aren't already set
+pub fn apply_config_file() {
+    let path = config_path();
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return;
+    };
+    let values = parse_flat_toml(&text);
+    for (key, env) in CONFIG_ENV {
+        let Some((_, value)) = values.iter().find(|(k, _)| k == key) else {
+            continue;
+        };
+        if std::env::var_os(env).is_none() && !value.trim().is_empty() {
+            tracing::debug!("{} = {} (from {})", env, value, path.display());
+            std::env::set_var(env, value);
+        }
+    }
+}
+
+/// Client configuration snippets for a server at `bind`
+pub fn connection_snippets(bind: &str) -> String {
+    let base = format!("http://{}/v1", bind);
+    format!(
+        r#"curl:
+  curl {base}/chat/completions -H "Content-Type: application/json" \
+    -d '{{"model": "<model>", "messages": [{{"role": "user", "content": "Hello"}}]}}'
+
+OpenAI Python SDK:
+  from openai import OpenAI
+  client = OpenAI(base_url="{base}", api_key="shimmy")
+
+Tools that read OpenAI environment variables:
+  export OPENAI_BASE_URL={base}
+  export OPENAI_API_KEY=shimmy"#
+    )
+}
+
+/// Options for `shimmy setup`
+#[derive(Debug, Clone, Default)]
+pub struct SetupOptions {
+    /// Accept every prompt
+    pub yes: bool,
+    /// Recommend only; never download
+    pub no_download: bool,
+    /// Where to write the config (default: [`config_path`])
+    pub output: Option<PathBuf>,
+}
+
+fn confirm(question: &str, opts: &SetupOptions) -> std::io::Result<bool> {
+    if opts.yes {
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Download `url` into the model store as `name`, printing progress
+async fn download_to_store(store: &ModelStore, name: &str, url: &str) -> anyhow::Result<PathBuf> {
+    let mut last_percent = 0;
+    let mut progress = |written: u64, total: Option<u64>| {
+        if let Some(total) = total.filter(|t| *t > 0) {
+            let percent = written * 100 / total;
+            if percent >= last_percent + 5 {
+                last_percent = percent;
+                eprint!(
+                    "\r   {}% ({:.1} / {:.1} GB)",
+                    percent,
+                    gb(written),
+                    gb(total)
+                );
+            }
+        }
+    };
+    let layer = store
+        .download(url, LayerKind::Model, None, &mut progress)
+        .await?;
+    eprintln!();
+    let path = store.blob_path(&layer);
+    store.write_manifest(name, vec![layer])?;
+    Ok(path)
+}
+
+/// Run the interactive setup wizard
+pub async fn run(opts: SetupOptions) -> anyhow::Result<()> {
+    println!("🔍 Detecting hardware...");
+    let hw = HardwareProfile::detect();
+    println!(
+        "   RAM:  {:.1} GB total, {:.1} GB available",
+        gb(hw.total_ram_bytes),
+        gb(hw.available_ram_bytes)
+    );
+    match (&hw.gpu_vendor, hw.vram_bytes) {
+        (Some(vendor), Some(vram)) => println!("   GPU:  {} ({:.1} GB VRAM)", vendor, gb(vram)),
+        (Some(vendor), None) => println!("   GPU:  {}", vendor),
+        (None, _) => println!("   GPU:  none detected (CPU inference)"),
+    }
+
+    let path = opts.output.clone().unwrap_or_else(config_path);
+    let mut config = if path.exists() {
+        SetupConfig::from_toml(&std::fs::read_to_string(&path)?)
+    } else {
+        SetupConfig::default()
+    };
+
+    let starter = recommend_starter(&hw);
+    let starter_fit = hw.fit(starter.size_bytes);
+    println!();
+    println!(
+        "💡 Recommended model: {} (~{:.1} GB download)",
+        starter.name,
+        gb(starter.size_bytes)
+    );
+    if starter_fit != MemoryStatus::Sufficient {
+        println!(
+            "   ⚠️  Even this model is a tight fit ({:?}); expect slow responses",
+            starter_fit
+        );
+    }
+
+    let store = ModelStore::open_default();
+    let store_name = starter.file_name.trim_end_matches(".gguf");
+    // Starter models downloaded before the store existed
+    let legacy_path = crate::util::paths::local_data_dir()
+        .join("models")
+        .join(starter.file_name);
+    let stored_path = store
+        .manifest(store_name)?
+        .and_then(|m| m.layer(LayerKind::Model).map(|l| store.blob_path(l)))
+        .filter(|p| p.exists());
+    if let Some(model_path) = stored_path.or_else(|| legacy_path.exists().then_some(legacy_path)) {
+        println!("   ✅ Already downloaded: {}", model_path.display());
+        config.base_gguf = Some(model_path);
+    } else if opts.no_download {
+        println!("   Download: {}", starter.url);
+    } else if confirm("   Download it now?", &opts)? {
+        println!("⬇️  Downloading to {}", store.root().display());
+        let model_path = download_to_store(&store, store_name, starter.url).await?;
+        println!("   ✅ Done ({})", store_name);
+        config.base_gguf = Some(model_path);
+    }
+
+    println!();
+    if recommend_vision(&hw) {
+        println!(
+            "💡 Vision: MiniCPM-V fits on this machine (~{:.1} GB download)",
+            gb(VISION_MODEL_BYTES)
+        );
+        #[cfg(feature = "vision")]
+        if !opts.no_download && confirm("   Download the vision model now?", &opts)? {
+            println!("⬇️  Downloading vision model...");
+            let path = crate::vision::prefetch_builtin_model().await?;
+            println!("   ✅ Done");
+            config.vision_model_dir = path.parent().map(std::path::Path::to_path_buf);
+        }
+        #[cfg(not(feature = "vision"))]
+        println!("   Requires a build with `--features vision`");
+    } else {
+        println!("ℹ️  Vision: skipped, MiniCPM-V needs more memory than is available");
+    }
+
+    let bind = config
+        .bind
+        .get_or_insert_with(|| DEFAULT_BIND.to_string())
+        .clone();
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, config.to_toml())?;
+    println!();
+    println!("📝 Wrote {}", path.display());
+
+    println!();
+    println!(
+        "🚀 Start the server with: {} serve --bind {}",
+        crate::branding::APP_NAME,
+        bind
+    );
+    println!();
+    println!("{}", connection_snippets(&bind));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(ram_gb: u64, vram_gb: Option<u64>) -> HardwareProfile {
+        let gib = 1024 * 1024 * 1024;
+        HardwareProfile {
+            total_ram_bytes: ram_gb * gib,
+            available_ram_bytes: ram_gb * gib,
+            gpu_vendor: vram_gb.map(|_| "nvidia".to_string()),
+            vram_bytes: vram_gb.map(|v| v * gib),
+        }
+    }
+
+    #[test]
+    fn test_recommend_starter_scales_with_memory() {
+        assert_eq!(recommend_starter(&profile(1, None)), &STARTER_MODELS[0]);
+        assert_eq!(recommend_starter(&profile(5, None)), &STARTER_MODELS[1]);
+        assert_eq!(recommend_starter(&profile(32, None)), &STARTER_MODELS[2]);
+        // VRAM counts when it exceeds free RAM
+        assert_eq!(recommend_starter(&profile(2, Some(24))), &STARTER_MODELS[2]);
+        assert!(!recommend_vision(&profile(5, None)));
+        assert!(recommend_vision(&profile(16, None)));
+    }
+
+    #[test]
+    fn test_config_toml_round_trip() {
+        let config = SetupConfig {
+            base_gguf: Some(PathBuf::from("C:\\models\\phi3 \"q4\".gguf")),
+            bind: Some("127.0.0.1:11435".into()),
+            vision_model_dir: None,
+            data_dir: Some(PathBuf::from("/srv/shimmy")),
+        };
+        let text = config.to_toml();
+        assert!(!text.contains("vision_model_dir"));
+        assert_eq!(SetupConfig::from_toml(&text), config);
+    }
+
+    #[test]
+    fn test_parse_flat_toml_ignores_unsupported_lines() {
+        let values = parse_flat_toml(
+            "# comment\nbind = \"0.0.0.0:8080\" # trailing\nport = 8080\n\n[vision]\nbind = \"x\"\n",
+        );
+        assert_eq!(
+            values,
+            vec![("bind".to_string(), "0.0.0.0:8080".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_set_flat_toml_replaces_or_adds_top_level_key() {
+        let text = "# settings\nbind = \"0.0.0.0:8080\"\ntelemetry = \"off\"\n\n[vision]\ntelemetry = \"x\"\n";
+        let updated = set_flat_toml(text, "telemetry", "local");
+        assert_eq!(
+            updated,
+            "# settings\nbind = \"0.0.0.0:8080\"\ntelemetry = \"local\"\n\n[vision]\ntelemetry = \"x\"\n"
+        );
+
+        let added = set_flat_toml("bind = \"0.0.0.0:8080\"\n", "telemetry", "upload");
+        assert_eq!(
+            parse_flat_toml(&added),
+            vec![
+                ("bind".to_string(), "0.0.0.0:8080".to_string()),
+                ("telemetry".to_string(), "upload".to_string()),
+            ]
+        );
+        assert_eq!(
+            set_flat_toml("", "telemetry", "off"),
+            "telemetry = \"off\"\n"
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Pluggable storage for state that outlives a request.
+//!
+//! The vision license cache and usage counters are read and written through
+//! a [`StateStore`], so a deployment can pick how durable they are.
+//! `SHIMMY_STATE_STORE` (or `state_store` in `shimmy.toml`) selects it:
+//!
+//! - `file` (default): one file per entry under the data directory, written
+//!   atomically. Existing `vision/*.json` files keep working.
+//! - `sqlite`: one SQLite database, `SHIMMY_STATE_STORE_PATH` (default
+//!   `state.db` in the data directory). Needs the `sqlite` feature.
+//! - `memory`: nothing is persisted; for tests and throwaway instances.
+//!
+//! Entries are opaque bytes addressed by a namespace and a key; callers
+//! serialize with [`load_json`] and [`save_json`].
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+pub const STATE_STORE_ENV: &str = "SHIMMY_STATE_STORE";
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+pub const STATE_STORE_PATH_ENV: &str = "SHIMMY_STATE_STORE_PATH";
+
+/// Key-value storage for persistent state
+pub trait StateStore: Send + Sync + std::fmt::Debug {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>>;
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<()>;
+    fn delete(&self, namespace: &str, key: &str) -> Result<()>;
+}
+
+/// Read and deserialize an entry
+pub fn load_json<T: DeserializeOwned>(
+    store: &dyn StateStore,
+    namespace: &str,
+    key: &str,
+) -> Result<Option<T>> {
+    match store.get(namespace, key)? {
+        Some(data) => serde_json::from_slice(&data)
+            .map(Some)
+            .with_context(|| format!("{}/{} is not valid JSON", namespace, key)),
+        None => Ok(None),
+    }
+}
+
+/// Serialize and write an entry
+pub fn save_json<T: Serialize>(
+    store: &dyn StateStore,
+    namespace: &str,
+    key: &str,
+    value: &T,
+) -> Result<()> {
+    store.put(namespace, key, &serde_json::to_vec_pretty(value)?)
+}
+
+/// The store configured by `SHIMMY_STATE_STORE`, opened once per process
+pub fn global() -> Arc<dyn StateStore> {
+    static GLOBAL: OnceLock<Arc<dyn StateStore>> = OnceLock::new();
+    GLOBAL
+        .get_or_init(|| {
+            from_env().unwrap_or_else(|e| {
+                tracing::error!("State store unavailable, keeping state in memory: {:#}", e);
+                Arc::new(MemoryStore::default())
+            })
+        })
+        .clone()
+}
+
+/// Open the store `SHIMMY_STATE_STORE` names
+pub fn from_env() -> Result<Arc<dyn StateStore>> {
+    let kind = std::env::var(STATE_STORE_ENV).unwrap_or_default();
+    let data_dir = crate::util::paths::data_dir();
+    match kind.trim().to_ascii_lowercase().as_str() {
+        "" | "file" => Ok(Arc::new(FileStore::new(data_dir))),
+        "memory" => Ok(Arc::new(MemoryStore::default())),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let path = std::env::var(STATE_STORE_PATH_ENV)
+                .ok()
+                .filter(|p| !p.trim().is_empty())
+                .map(PathBuf::from)
+                .unwrap_or_else(|| data_dir.join("state.db"));
+            Ok(Arc::new(SqliteStore::open(&path)?))
+        }
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => anyhow::bail!(
+            "{}=sqlite needs a build with the `sqlite` feature",
+            STATE_STORE_ENV
+        ),
+        other => anyhow::bail!(
+            "Unrecognized {}={:?} (expected file, sqlite or memory)",
+            STATE_STORE_ENV,
+            other
+        ),
+    }
+}
+
+/// `<root>/<namespace>/<key>`, replaced atomically on every write
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    root: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, namespace: &str, key: &str) -> Result<PathBuf> {
+        let safe = |part: &str| {
+            !part.is_empty() && part != "." && part != ".." && !part.contains(['/', '\\'])
+        };
+        anyhow::ensure!(
+            safe(namespace) && safe(key),
+            "invalid state entry name {}/{}",
+            namespace,
+            key
+        );
+        Ok(self.root.join(namespace).join(key))
+    }
+}
+
+impl StateStore for FileStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(self.path(namespace, key)?) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        crate::recovery::write_atomic(&self.path(namespace, key)?, value)
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<()> {
+        match std::fs::remove_file(self.path(namespace, key)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Process-local store; everything is lost on exit
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<(String, String), Vec<u8>>>,
+}
+
+impl MemoryStore {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(String, String), Vec<u8>>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl StateStore for MemoryStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .lock()
+            .get(&(namespace.to_string(), key.to_string()))
+            .cloned())
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        self.lock()
+            .insert((namespace.to_string(), key.to_string()), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<()> {
+        self.lock()
+            .remove(&(namespace.to_string(), key.to_string()));
+        Ok(())
+    }
+}
+
+/// All entries in one SQLite database in WAL mode, so a crash mid-write
+/// never leaves a torn entry and several entries can be read consistently
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub struct SqliteStore {
+    conn: Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    pub fn open(path: &std::path::Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = rusqlite::Connection::open(path)
+            .with_context(|| format!("opening state database {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS state (
+                namespace TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value BLOB NOT NULL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (namespace, key)
+            )",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl StateStore for SqliteStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        use rusqlite::OptionalExtension;
+
+        Ok(self
+            .lock()
+            .query_row(
+                "SELECT value FROM state WHERE namespace = ?1 AND key = ?2",
+                rusqlite::params![namespace, key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        self.lock().execute(
+            "INSERT INTO state (namespace, key, value, updated_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (namespace, key) DO UPDATE SET value = ?3, updated_at = ?4",
+            rusqlite::params![namespace, key, value, chrono::Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<()> {
+        self.lock().execute(
+            "DELETE FROM state WHERE namespace = ?1 AND key = ?2",
+            rusqlite::params![namespace, key],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(store: &dyn StateStore) {
+        assert_eq!(store.get("vision", "usage_stats.json").unwrap(), None);
+        save_json(store, "vision", "usage_stats.json", &vec![1, 2, 3]).unwrap();
+        save_json(store, "vision", "usage_stats.json", &vec![4]).unwrap();
+        let loaded: Option<Vec<u32>> = load_json(store, "vision", "usage_stats.json").unwrap();
+        assert_eq!(loaded, Some(vec![4]));
+        assert_eq!(store.get("other", "usage_stats.json").unwrap(), None);
+
+        store.delete("vision", "usage_stats.json").unwrap();
+        store.delete("vision", "usage_stats.json").unwrap();
+        assert_eq!(store.get("vision", "usage_stats.json").unwrap(), None);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        round_trip(&MemoryStore::default());
+    }
+
+    #[test]
+    fn test_file_store_keeps_existing_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path());
+        round_trip(&store);
+
+        std::fs::create_dir_all(dir.path().join("vision")).unwrap();
+        std::fs::write(dir.path().join("vision/license_cache.json"), b"{}").unwrap();
+        assert_eq!(
+            store.get("vision", "license_cache.json").unwrap(),
+            Some(b"{}".to_vec())
+        );
+        assert!(store.put("vision", "../escape", b"x").is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        round_trip(&SqliteStore::open(&path).unwrap());
+
+        SqliteStore::open(&path)
+            .unwrap()
+            .put("vision", "license_cache.json", b"{}")
+            .unwrap();
+        let reopened = SqliteStore::open(&path).unwrap();
+        assert_eq!(
+            reopened.get("vision", "license_cache.json").unwrap(),
+            Some(b"{}".to_vec())
+        );
+    }
+}
@@ -0,0 +1,390 @@
+//! Opt-in, anonymous usage counters (`shimmy stats`).
+//!
+//! Off unless enabled with `shimmy stats --enable` or `SHIMMY_TELEMETRY`. When
+//! on, Shimmy counts requests per route pattern (e.g. `/api/models/:name/load`,
+//! never the actual path, body, prompt or output) and model loads per backend,
+//! alongside OS/arch and version. Counts are kept in
+//! `<data_dir>/telemetry/stats.json`; nothing leaves the machine unless upload
+//! is separately enabled, in which case the same aggregate is sent once a day.
+//!
+//! Counts are buffered in memory and merged into the file every minute and on
+//! exit. The file is replaced with an atomic rename, so a crash loses at most
+//! the last interval and never leaves a half-written file.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// What happens to usage counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TelemetryMode {
+    /// Nothing is counted (default)
+    Off,
+    /// Counts are written to the local stats file only
+    Local,
+    /// Local file plus a daily upload of the aggregate
+    Upload,
+}
+
+impl TelemetryMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "off" | "0" | "false" => Some(Self::Off),
+            "local" | "on" | "1" | "true" => Some(Self::Local),
+            "upload" => Some(Self::Upload),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Local => "local",
+            Self::Upload => "upload",
+        }
+    }
+
+    /// `SHIMMY_TELEMETRY` (also set from `telemetry` in `shimmy.toml`), else
+    /// off
+    pub fn current() -> Self {
+        std::env::var(TELEMETRY_ENV)
+            .ok()
+            .and_then(|v| Self::parse(&v))
+            .unwrap_or(Self::Off)
+    }
+}
+
+/// Consent variable, also set from `telemetry` in `shimmy.toml`
+pub const TELEMETRY_ENV: &str = "SHIMMY_TELEMETRY";
+
+/// Store the consent setting as `telemetry` in `shimmy.toml`
+pub fn set_mode(mode: TelemetryMode) -> anyhow::Result<PathBuf> {
+    crate::setup::set_config_value("telemetry", mode.as_str())
+}
+
+/// Location of the local stats file
+pub fn stats_path() -> PathBuf {
+    crate::util::paths::data_dir()
+        .join("telemetry")
+        .join("stats.json")
+}
+
+/// Request and model-load counters
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageCounts {
+    #[serde(default)]
+    pub requests: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub backends: BTreeMap<String, u64>,
+}
+
+impl UsageCounts {
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty() && self.backends.is_empty()
+    }
+
+    pub fn merge(&mut self, other: &UsageCounts) {
+        for (route, n) in &other.requests {
+            *self.requests.entry(route.clone()).or_default() += n;
+        }
+        for (backend, n) in &other.backends {
+            *self.backends.entry(backend.clone()).or_default() += n;
+        }
+    }
+}
+
+/// Contents of the stats file; this is also exactly what an upload sends
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageStats {
+    pub since: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+    #[serde(flatten)]
+    pub counts: UsageCounts,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_upload: Option<DateTime<Utc>>,
+}
+
+impl UsageStats {
+    fn new() -> Self {
+        let now = Utc::now();
+        Self {
+            since: now,
+            updated: now,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            counts: UsageCounts::default(),
+            last_upload: None,
+        }
+    }
+
+    /// Load the stats file. A missing file starts fresh; an unreadable one is
+    /// moved aside to `stats.json.corrupt` rather than blocking new counts.
+    pub fn load(path: &Path) -> Self {
+        let Ok(data) = std::fs::read_to_string(path) else {
+            return Self::new();
+        };
+        match serde_json::from_str(&data) {
+            Ok(stats) => stats,
+            Err(e) => {
+                tracing::warn!("Ignoring corrupt telemetry stats {}: {}", path.display(), e);
+                let _ = std::fs::rename(path, path.with_extension("json.corrupt"));
+                Self::new()
+            }
+        }
+    }
+
+    /// Write via a temp file and rename so readers never see a partial file
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        crate::recovery::write_atomic(path, &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Process-wide counter buffer
+pub struct Telemetry {
+    mode: TelemetryMode,
+    path: PathBuf,
+    pending: Mutex<UsageCounts>,
+    started: AtomicBool,
+}
+
+impl Telemetry {
+    pub fn new(mode: TelemetryMode, path: PathBuf) -> Self {
+        Self {
+            mode,
+            path,
+            pending: Mutex::new(UsageCounts::default()),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    pub fn global() -> &'static Telemetry {
+        static GLOBAL: OnceLock<Telemetry> = OnceLock::new();
+        GLOBAL.get_or_init(|| Telemetry::new(TelemetryMode::current(), stats_path()))
+    }
+
+    fn bump(&self, f: impl FnOnce(&mut UsageCounts)) {
+        if self.mode == TelemetryMode::Off {
+            return;
+        }
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut pending);
+    }
+
+    /// Count a request against its route pattern
+    pub fn record_request(&self, route: &str) {
+        self.bump(|c| *c.requests.entry(route.to_string()).or_default() += 1);
+    }
+
+    /// Count a model load on `backend`
+    pub fn record_backend(&self, backend: &str) {
+        self.bump(|c| *c.backends.entry(backend.to_string()).or_default() += 1);
+    }
+
+    /// Merge buffered counts into the stats file
+    pub fn flush(&self) -> anyhow::Result<UsageStats> {
+        let pending = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            std::mem::take(&mut *pending)
+        };
+        let mut stats = UsageStats::load(&self.path);
+        if pending.is_empty() {
+            return Ok(stats);
+        }
+
+        stats.counts.merge(&pending);
+        stats.updated = Utc::now();
+        stats.version = env!("CARGO_PKG_VERSION").to_string();
+        if let Err(e) = stats.save(&self.path) {
+            // Put the counts back so the next flush retries them
+            self.pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .merge(&pending);
+            return Err(e);
+        }
+        Ok(stats)
+    }
+
+    /// Flush on `interval` (and upload daily when enabled); no-op when off
+    pub fn spawn_flusher(&'static self, interval: std::time::Duration) {
+        if self.mode == TelemetryMode::Off || self.started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let stats = match tokio::task::spawn_blocking(|| self.flush()).await {
+                    Ok(Ok(stats)) => stats,
+                    Ok(Err(e)) => {
+                        tracing::debug!("Telemetry flush failed: {}", e);
+                        continue;
+                    }
+                    Err(_) => continue,
+                };
+                if self.mode == TelemetryMode::Upload && upload_due(&stats, Utc::now()) {
+                    self.upload(stats).await;
+                }
+            }
+        });
+    }
+
+    async fn upload(&self, mut stats: UsageStats) {
+        let url = std::env::var("SHIMMY_TELEMETRY_URL")
+            .unwrap_or_else(|_| crate::branding::TELEMETRY_URL.to_string());
+        let client = match crate::util::http::client_builder()
+            .timeout(std::time::Duration::from_secs(5))
+            .build()
+        {
+            Ok(client) => client,
+            Err(_) => return,
+        };
+
+        match client.post(&url).json(&stats).send().await {
+            Ok(resp) if resp.status().is_success() => {
+                stats.last_upload = Some(Utc::now());
+                if let Err(e) = stats.save(&self.path) {
+                    tracing::debug!("Failed to record telemetry upload: {}", e);
+                }
+            }
+            Ok(resp) => tracing::debug!("Telemetry upload failed with status: {}", resp.status()),
+            // Silent failure - never affects serving
+            Err(e) => tracing::debug!("Telemetry upload failed: {}", e),
+        }
+    }
+}
+
+fn upload_due(stats: &UsageStats, now: DateTime<Utc>) -> bool {
+    !stats.counts.is_empty()
+        && !matches!(stats.last_upload, Some(last) if now - last < chrono::Duration::hours(24))
+}
+
+/// Print the local stats for `shimmy stats`
+pub fn print_stats(json: bool) -> anyhow::Result<()> {
+    let path = stats_path();
+    let stats = path.exists().then(|| UsageStats::load(&path));
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    let mode = TelemetryMode::current();
+    match mode {
+        TelemetryMode::Off => println!("📊 Telemetry: off"),
+        TelemetryMode::Local => println!("📊 Telemetry: local only ({})", path.display()),
+        TelemetryMode::Upload => println!(
+            "📊 Telemetry: local ({}) with daily anonymous upload",
+            path.display()
+        ),
+    }
+
+    let Some(stats) = stats else {
+        if mode == TelemetryMode::Off {
+            println!("   Enable local-only counting with: shimmy stats --enable");
+        } else {
+            println!("   No usage recorded yet");
+        }
+        return Ok(());
+    };
+
+    println!(
+        "   Since {} · shimmy {} on {}/{}",
+        stats.since.format("%Y-%m-%d"),
+        stats.version,
+        stats.os,
+        stats.arch
+    );
+    if let Some(last) = stats.last_upload {
+        println!("   Last upload: {}", last.format("%Y-%m-%d %H:%M UTC"));
+    }
+
+    println!();
+    println!("   Requests by endpoint:");
+    for (route, n) in &stats.counts.requests {
+        println!("     {:<32} {:>8}", route, n);
+    }
+    println!("   Model loads by backend:");
+    for (backend, n) in &stats.counts.backends {
+        println!("     {:<32} {:>8}", backend, n);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_off_mode_records_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.json");
+        let telemetry = Telemetry::new(TelemetryMode::Off, path.clone());
+        telemetry.record_request("/v1/chat/completions");
+        telemetry.flush().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_flush_merges_into_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.json");
+
+        let first = Telemetry::new(TelemetryMode::Local, path.clone());
+        first.record_request("/v1/chat/completions");
+        first.record_backend("llama");
+        first.flush().unwrap();
+
+        // A later process adds to the same file
+        let second = Telemetry::new(TelemetryMode::Local, path.clone());
+        second.record_request("/v1/chat/completions");
+        second.record_request("/api/models/:name/load");
+        let stats = second.flush().unwrap();
+
+        assert_eq!(stats.counts.requests["/v1/chat/completions"], 2);
+        assert_eq!(stats.counts.requests["/api/models/:name/load"], 1);
+        assert_eq!(stats.counts.backends["llama"], 1);
+        assert_eq!(UsageStats::load(&path), stats);
+    }
+
+    #[test]
+    fn test_corrupt_stats_file_is_set_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.json");
+        std::fs::write(&path, "{\"requests\": {").unwrap();
+
+        let stats = UsageStats::load(&path);
+        assert!(stats.counts.is_empty());
+        assert!(dir.path().join("stats.json.corrupt").exists());
+    }
+
+    #[test]
+    fn test_upload_due_once_a_day() {
+        let mut stats = UsageStats::new();
+        let now = Utc::now();
+        assert!(!upload_due(&stats, now));
+
+        stats.counts.requests.insert("/health".into(), 1);
+        assert!(upload_due(&stats, now));
+        stats.last_upload = Some(now - chrono::Duration::hours(2));
+        assert!(!upload_due(&stats, now));
+        stats.last_upload = Some(now - chrono::Duration::hours(25));
+        assert!(upload_due(&stats, now));
+    }
+
+    #[test]
+    fn test_mode_parse() {
+        assert_eq!(TelemetryMode::parse("LOCAL"), Some(TelemetryMode::Local));
+        assert_eq!(TelemetryMode::parse("upload"), Some(TelemetryMode::Upload));
+        assert_eq!(TelemetryMode::parse("0"), Some(TelemetryMode::Off));
+        assert_eq!(TelemetryMode::parse("maybe"), None);
+    }
+}
@@ -78,6 +78,64 @@ impl TemplateFamily {
     }
 }
 
+/// Fill-in-the-middle prompt format of a code model: the code before and
+/// after the cursor wrapped in the sentinel tokens the model was trained on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FimFormat {
+    CodeLlama,
+    DeepseekCoder,
+    StarCoder,
+}
+
+impl FimFormat {
+    /// The registry's `template` when it names a FIM format, else a guess
+    /// from the model name; `None` for models without FIM training
+    pub fn detect(template: Option<&str>, model: &str) -> Option<Self> {
+        template
+            .and_then(Self::from_name)
+            .or_else(|| Self::from_name(model))
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase().replace('_', "-");
+        if name.contains("codellama") || name.contains("code-llama") {
+            Some(FimFormat::CodeLlama)
+        } else if name.contains("deepseek-coder") {
+            Some(FimFormat::DeepseekCoder)
+        } else if name.contains("starcoder") {
+            Some(FimFormat::StarCoder)
+        } else {
+            None
+        }
+    }
+
+    /// Prompt asking the model for the code between `prefix` and `suffix`
+    pub fn render(&self, prefix: &str, suffix: &str) -> String {
+        match self {
+            FimFormat::CodeLlama => format!("<PRE> {} <SUF>{} <MID>", prefix, suffix),
+            FimFormat::DeepseekCoder => format!(
+                "<｜fim▁begin｜>{}<｜fim▁hole｜>{}<｜fim▁end｜>",
+                prefix, suffix
+            ),
+            FimFormat::StarCoder => {
+                format!("<fim_prefix>{}<fim_suffix>{}<fim_middle>", prefix, suffix)
+            }
+        }
+    }
+
+    /// Tokens that end the middle
+    pub fn stop_tokens(&self) -> Vec<String> {
+        match self {
+            FimFormat::CodeLlama => vec!["<EOT>".to_string()],
+            FimFormat::DeepseekCoder => {
+                vec!["<｜end▁of▁sentence｜>".to_string(), "<|EOT|>".to_string()]
+            }
+            FimFormat::StarCoder => vec!["<|endoftext|>".to_string(), "<file_sep>".to_string()],
+        }
+    }
+}
+
 // Template generation functions for deployment platforms
 
 /// Generate Docker deployment template
@@ -285,6 +343,40 @@ mod tests {
         assert!(result.contains("<|eot_id|>"));
     }
 
+    #[test]
+    fn test_fim_formats() {
+        assert_eq!(
+            FimFormat::detect(None, "CodeLlama-7b-hf.Q4_K_M"),
+            Some(FimFormat::CodeLlama)
+        );
+        assert_eq!(
+            FimFormat::detect(None, "deepseek_coder_1.3b_base"),
+            Some(FimFormat::DeepseekCoder)
+        );
+        assert_eq!(
+            FimFormat::detect(Some("starcoder"), "my-model"),
+            Some(FimFormat::StarCoder)
+        );
+        assert_eq!(
+            FimFormat::detect(Some("chatml"), "starcoder2-3b"),
+            Some(FimFormat::StarCoder)
+        );
+        assert_eq!(FimFormat::detect(None, "phi3-mini"), None);
+
+        assert_eq!(
+            FimFormat::CodeLlama.render("def f(x):", "\n    return y"),
+            "<PRE> def f(x): <SUF>\n    return y <MID>"
+        );
+        assert_eq!(
+            FimFormat::StarCoder.render("a", "b"),
+            "<fim_prefix>a<fim_suffix>b<fim_middle>"
+        );
+        assert_eq!(
+            FimFormat::DeepseekCoder.render("a", "b"),
+            "<｜fim▁begin｜>a<｜fim▁hole｜>b<｜fim▁end｜>"
+        );
+    }
+
     #[test]
     fn test_openchat_render() {
         let template = TemplateFamily::OpenChat;
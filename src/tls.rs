@@ -0,0 +1,128 @@
+//! HTTPS without a reverse proxy.
+//!
+//! `serve --tls-cert cert.pem --tls-key key.pem` (or `SHIMMY_TLS_CERT` and
+//! `SHIMMY_TLS_KEY`, `tls_cert` / `tls_key` in `shimmy.toml`) terminates TLS
+//! in the server itself. The files are checked for changes every few seconds
+//! and reloaded in place, so a renewed certificate (certbot, cert-manager) is
+//! picked up without a restart. A pair that fails to load is logged and the
+//! current certificate stays in use.
+
+use anyhow::{bail, Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+pub const TLS_CERT_ENV: &str = "SHIMMY_TLS_CERT";
+pub const TLS_KEY_ENV: &str = "SHIMMY_TLS_KEY";
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// PEM certificate chain and private key to serve HTTPS with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsFiles {
+    /// The flags, else the environment; `None` serves plain HTTP
+    pub fn resolve(cert: Option<&str>, key: Option<&str>) -> Result<Option<Self>> {
+        let env = |name| std::env::var(name).ok();
+        Self::from_parts(
+            cert.map(str::to_string).or_else(|| env(TLS_CERT_ENV)),
+            key.map(str::to_string).or_else(|| env(TLS_KEY_ENV)),
+        )
+    }
+
+    fn from_parts(cert: Option<String>, key: Option<String>) -> Result<Option<Self>> {
+        let non_empty = |v: Option<String>| v.filter(|v| !v.trim().is_empty());
+        match (non_empty(cert), non_empty(key)) {
+            (Some(cert), Some(key)) => Ok(Some(Self {
+                cert: PathBuf::from(cert.trim()),
+                key: PathBuf::from(key.trim()),
+            })),
+            (None, None) => Ok(None),
+            (Some(_), None) => bail!("--tls-cert needs --tls-key (or {})", TLS_KEY_ENV),
+            (None, Some(_)) => bail!("--tls-key needs --tls-cert (or {})", TLS_CERT_ENV),
+        }
+    }
+
+    /// Load the pair, failing on unreadable or mismatched files
+    pub async fn load(&self) -> Result<RustlsConfig> {
+        RustlsConfig::from_pem_file(&self.cert, &self.key)
+            .await
+            .with_context(|| {
+                format!(
+                    "loading TLS certificate {} and key {}",
+                    self.cert.display(),
+                    self.key.display()
+                )
+            })
+    }
+
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let mtime = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        Some((mtime(&self.cert)?, mtime(&self.key)?))
+    }
+
+    /// Reload `config` whenever either file changes on disk
+    pub fn spawn_reloader(self, config: RustlsConfig) {
+        tokio::spawn(async move {
+            let mut seen = self.modified();
+            let mut tick = tokio::time::interval(RELOAD_INTERVAL);
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                let current = self.modified();
+                if current.is_none() || current == seen {
+                    continue;
+                }
+                // Remember the change either way: a failed load is retried
+                // once the other half of a renewal lands
+                seen = current;
+                match config.reload_from_pem_file(&self.cert, &self.key).await {
+                    Ok(()) => tracing::info!("Reloaded TLS certificate {}", self.cert.display()),
+                    Err(e) => tracing::warn!(
+                        "Keeping the current TLS certificate; reloading {} failed: {}",
+                        self.cert.display(),
+                        e
+                    ),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(cert: Option<&str>, key: Option<&str>) -> Result<Option<TlsFiles>> {
+        TlsFiles::from_parts(cert.map(str::to_string), key.map(str::to_string))
+    }
+
+    #[test]
+    fn test_cert_and_key_go_together() {
+        assert_eq!(parts(None, None).unwrap(), None);
+        assert_eq!(parts(Some(" "), Some("")).unwrap(), None);
+        assert_eq!(
+            parts(Some("cert.pem"), Some("key.pem")).unwrap(),
+            Some(TlsFiles {
+                cert: PathBuf::from("cert.pem"),
+                key: PathBuf::from("key.pem"),
+            })
+        );
+        assert!(parts(Some("cert.pem"), None).is_err());
+        assert!(parts(None, Some("key.pem")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_reports_missing_files() {
+        let files = TlsFiles {
+            cert: PathBuf::from("/nonexistent/cert.pem"),
+            key: PathBuf::from("/nonexistent/key.pem"),
+        };
+        let err = files.load().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("/nonexistent/cert.pem"));
+    }
+}
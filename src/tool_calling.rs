@@ -0,0 +1,402 @@
+//! OpenAI-style function calling for `/v1/chat/completions`.
+//!
+//! Local models have no native function-calling API, so the request's
+//! `tools` are described in a system prompt that asks the model to answer
+//! with `<tool_call>{"name": ..., "arguments": {...}}</tool_call>` blocks (the
+//! format Qwen and Hermes models are trained on). The reply is parsed back
+//! into `tool_calls`; output without a call for a known function is returned
+//! as plain content.
+//!
+//! Earlier turns are rendered in the same format: assistant `tool_calls`
+//! become `<tool_call>` blocks and `tool` messages become user turns wrapped
+//! in `<tool_response>`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A `tools[]` entry of a chat completion request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tool {
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: FunctionDefinition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON Schema of the arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+}
+
+/// `tool_choice`: `"none"`, `"auto"`, `"required"` or a specific function
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ToolChoice {
+    Mode(String),
+    Function { function: FunctionName },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FunctionName {
+    pub name: String,
+}
+
+/// A function call in a request or response message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments object
+    pub arguments: String,
+}
+
+/// Part of a function call in a stream delta: the first part of a call has
+/// its id and name, the rest carry fragments of its arguments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallDelta {
+    /// Position of the call in the reply's list
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    pub function: FunctionCallDelta,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionCallDelta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,
+}
+
+/// Characters of arguments per stream delta
+const ARGUMENT_FRAGMENT_CHARS: usize = 32;
+
+impl ToolCall {
+    /// The call as the deltas OpenAI streams for it at position `index`: id,
+    /// type and name with empty arguments, then the arguments in fragments
+    pub fn into_deltas(self, index: usize) -> Vec<ToolCallDelta> {
+        let mut deltas = vec![ToolCallDelta {
+            index,
+            id: Some(self.id),
+            kind: Some(self.kind),
+            function: FunctionCallDelta {
+                name: Some(self.function.name),
+                arguments: Some(String::new()),
+            },
+        }];
+        let chars: Vec<char> = self.function.arguments.chars().collect();
+        deltas.extend(
+            chars
+                .chunks(ARGUMENT_FRAGMENT_CHARS)
+                .map(|fragment| ToolCallDelta {
+                    index,
+                    id: None,
+                    kind: None,
+                    function: FunctionCallDelta {
+                        name: None,
+                        arguments: Some(fragment.iter().collect()),
+                    },
+                }),
+        );
+        deltas
+    }
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+/// The functions offered for one request
+#[derive(Debug, Clone)]
+pub struct ToolPlan {
+    pub tools: Vec<Tool>,
+    /// Whether the model must call one (`"required"` or a named function)
+    pub required: bool,
+}
+
+impl ToolPlan {
+    /// Resolve `tools` and `tool_choice`; `None` when no function is offered
+    pub fn new(tools: &[Tool], choice: Option<&ToolChoice>) -> Result<Option<Self>, String> {
+        let (tools, required) = match choice {
+            None => (tools.to_vec(), false),
+            Some(ToolChoice::Mode(mode)) => match mode.as_str() {
+                "none" => return Ok(None),
+                "auto" => (tools.to_vec(), false),
+                "required" => (tools.to_vec(), true),
+                other => {
+                    return Err(format!(
+                        "Invalid tool_choice '{}' (expected none, auto, required or a function)",
+                        other
+                    ))
+                }
+            },
+            Some(ToolChoice::Function { function }) => {
+                let tool = tools
+                    .iter()
+                    .find(|t| t.function.name == function.name)
+                    .ok_or_else(|| {
+                        format!("tool_choice names unknown function '{}'", function.name)
+                    })?;
+                (vec![tool.clone()], true)
+            }
+        };
+        if tools.is_empty() {
+            return if required {
+                Err("tool_choice requires tools".to_string())
+            } else {
+                Ok(None)
+            };
+        }
+        Ok(Some(Self { tools, required }))
+    }
+
+    /// System prompt describing the functions and the call format
+    pub fn system_prompt(&self) -> String {
+        let mut prompt = String::from(
+            "You can call the following functions. Their signatures are given as JSON within <tools></tools>:\n<tools>\n",
+        );
+        for tool in &self.tools {
+            prompt.push_str(&serde_json::to_string(&tool.function).unwrap_or_default());
+            prompt.push('\n');
+        }
+        prompt.push_str(
+            "</tools>\n\nTo call a function, reply with one <tool_call></tool_call> block per call holding a JSON object with the function name and arguments:\n<tool_call>\n{\"name\": <function-name>, \"arguments\": <args-json-object>}\n</tool_call>\n",
+        );
+        prompt.push_str(if self.required {
+            "You must call a function in this reply."
+        } else {
+            "If no function is needed, answer normally."
+        });
+        prompt
+    }
+
+    /// Split model output into remaining content and calls to offered functions
+    pub fn extract(&self, output: &str) -> (String, Vec<ToolCall>) {
+        let (content, candidates) = match tagged_calls(output) {
+            Some(found) => found,
+            None => (String::new(), bare_calls(output)),
+        };
+        let calls: Vec<ToolCall> = candidates
+            .into_iter()
+            .filter(|(name, _)| self.tools.iter().any(|t| &t.function.name == name))
+            .map(|(name, arguments)| ToolCall {
+                id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+                kind: function_type(),
+                function: FunctionCall { name, arguments },
+            })
+            .collect();
+        if calls.is_empty() {
+            (output.to_string(), calls)
+        } else {
+            (content, calls)
+        }
+    }
+}
+
+/// `<tool_call>` blocks, with the text around them; the closing tag may be
+/// missing when a stop token ended the output
+fn tagged_calls(output: &str) -> Option<(String, Vec<(String, String)>)> {
+    const OPEN: &str = "<tool_call>";
+    const CLOSE: &str = "</tool_call>";
+
+    let mut rest = output;
+    let mut content = String::new();
+    let mut calls = Vec::new();
+    while let Some(start) = rest.find(OPEN) {
+        content.push_str(&rest[..start]);
+        let body = &rest[start + OPEN.len()..];
+        let (inner, after) = match body.find(CLOSE) {
+            Some(end) => (&body[..end], &body[end + CLOSE.len()..]),
+            None => (body, ""),
+        };
+        if let Ok(value) = serde_json::from_str::<Value>(strip_fence(inner)) {
+            calls.extend(call_from_value(&value));
+        }
+        rest = after;
+    }
+    if calls.is_empty() {
+        return None;
+    }
+    content.push_str(rest);
+    Some((content.trim().to_string(), calls))
+}
+
+/// A reply that is nothing but a call object (or an array of them), as
+/// Llama 3.1 and most instruct models produce
+fn bare_calls(output: &str) -> Vec<(String, String)> {
+    match serde_json::from_str::<Value>(strip_fence(output)) {
+        Ok(Value::Array(items)) => items.iter().filter_map(call_from_value).collect(),
+        Ok(value) => call_from_value(&value).into_iter().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn strip_fence(text: &str) -> &str {
+    let text = text.trim();
+    match text.strip_prefix("```") {
+        Some(fenced) => fenced
+            .trim_start_matches("json")
+            .trim_end_matches("```")
+            .trim(),
+        None => text,
+    }
+}
+
+/// `{"name": ..., "arguments": {...}}` (or `parameters`) to a name and
+/// JSON-encoded arguments
+fn call_from_value(value: &Value) -> Option<(String, String)> {
+    let name = value.get("name")?.as_str()?.to_string();
+    let arguments = match value.get("arguments").or_else(|| value.get("parameters")) {
+        Some(Value::String(encoded)) => encoded.clone(),
+        Some(args) => args.to_string(),
+        None => "{}".to_string(),
+    };
+    Some((name, arguments))
+}
+
+/// An earlier assistant call, in the format the model is asked to use
+pub fn render_call(call: &FunctionCall) -> String {
+    let arguments = serde_json::from_str::<Value>(&call.arguments)
+        .unwrap_or_else(|_| Value::String(call.arguments.clone()));
+    format!(
+        "<tool_call>\n{{\"name\": {}, \"arguments\": {}}}\n</tool_call>",
+        Value::String(call.name.clone()),
+        arguments
+    )
+}
+
+/// A function result, sent back to the model as a user turn
+pub fn render_response(content: &str) -> String {
+    format!("<tool_response>\n{}\n</tool_response>", content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(choice: Option<ToolChoice>) -> Result<Option<ToolPlan>, String> {
+        let tools: Vec<Tool> = serde_json::from_value(serde_json::json!([
+            {"type": "function", "function": {"name": "get_weather", "parameters": {"type": "object"}}},
+            {"type": "function", "function": {"name": "search"}}
+        ]))
+        .unwrap();
+        ToolPlan::new(&tools, choice.as_ref())
+    }
+
+    #[test]
+    fn test_tool_choice_resolution() {
+        let auto = plan(None).unwrap().unwrap();
+        assert_eq!(auto.tools.len(), 2);
+        assert!(!auto.required);
+        assert!(plan(Some(ToolChoice::Mode("none".into())))
+            .unwrap()
+            .is_none());
+        assert!(plan(Some(ToolChoice::Mode("sometimes".into()))).is_err());
+
+        let choice: ToolChoice = serde_json::from_value(
+            serde_json::json!({"type": "function", "function": {"name": "search"}}),
+        )
+        .unwrap();
+        let named = plan(Some(choice)).unwrap().unwrap();
+        assert_eq!(named.tools.len(), 1);
+        assert!(named.required);
+        assert!(named.system_prompt().contains("You must call a function"));
+
+        let unknown = ToolChoice::Function {
+            function: FunctionName {
+                name: "delete_all".into(),
+            },
+        };
+        assert!(plan(Some(unknown)).is_err());
+        assert!(ToolPlan::new(&[], Some(&ToolChoice::Mode("required".into()))).is_err());
+        assert!(ToolPlan::new(&[], None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_extract_tool_calls() {
+        let plan = plan(None).unwrap().unwrap();
+
+        let (content, calls) = plan.extract(
+            "Let me check.\n<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\"}}\n</tool_call>",
+        );
+        assert_eq!(content, "Let me check.");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Paris"}"#);
+        assert!(calls[0].id.starts_with("call_"));
+
+        // Unterminated block, and a bare Llama-style object
+        let (_, calls) = plan.extract("<tool_call>{\"name\": \"search\", \"arguments\": {}}");
+        assert_eq!(calls.len(), 1);
+        let (content, calls) =
+            plan.extract("```json\n{\"name\": \"search\", \"parameters\": {\"q\": \"rust\"}}\n```");
+        assert_eq!(content, "");
+        assert_eq!(calls[0].function.arguments, r#"{"q":"rust"}"#);
+
+        // Plain answers and calls to functions that weren't offered stay content
+        let (content, calls) = plan.extract("It is sunny.");
+        assert_eq!(content, "It is sunny.");
+        assert!(calls.is_empty());
+        let output = "<tool_call>{\"name\": \"rm_rf\", \"arguments\": {}}</tool_call>";
+        assert_eq!(plan.extract(output), (output.to_string(), vec![]));
+    }
+
+    #[test]
+    fn test_stream_deltas_fragment_arguments() {
+        let arguments = format!(r#"{{"query":"{}"}}"#, "é".repeat(40));
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: "search".to_string(),
+                arguments: arguments.clone(),
+            },
+        };
+        let deltas = call.into_deltas(2);
+        assert_eq!(deltas.len(), 3);
+        assert!(deltas.iter().all(|d| d.index == 2));
+        let first = serde_json::to_value(&deltas[0]).unwrap();
+        assert_eq!(
+            first,
+            serde_json::json!({"index": 2, "id": "call_1", "type": "function",
+                "function": {"name": "search", "arguments": ""}})
+        );
+        let rest = serde_json::to_value(&deltas[1]).unwrap();
+        assert!(rest.get("id").is_none() && rest["function"].get("name").is_none());
+        let joined: String = deltas
+            .iter()
+            .filter_map(|d| d.function.arguments.as_deref())
+            .collect();
+        assert_eq!(joined, arguments);
+    }
+
+    #[test]
+    fn test_render_history() {
+        let call = FunctionCall {
+            name: "search".to_string(),
+            arguments: r#"{"q":"rust"}"#.to_string(),
+        };
+        assert_eq!(
+            render_call(&call),
+            "<tool_call>\n{\"name\": \"search\", \"arguments\": {\"q\":\"rust\"}}\n</tool_call>"
+        );
+        assert_eq!(
+            render_response("42"),
+            "<tool_response>\n42\n</tool_response>"
+        );
+    }
+}
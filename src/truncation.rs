@@ -0,0 +1,152 @@
+//! Fitting chat history into the model's context window.
+//!
+//! When a conversation outgrows the context, the oldest turns are dropped
+//! first; system messages and the latest message always stay. Whatever was
+//! dropped is reported back so clients can trim their own history instead of
+//! silently losing context.
+
+use crate::api::ChatMessage;
+use serde::{Deserialize, Serialize};
+
+/// What was left out of a prompt to make it fit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Truncation {
+    /// Indices into the request's `messages` that were not sent to the model
+    pub dropped_messages: Vec<usize>,
+    /// Prompt tokens removed by dropping them
+    pub dropped_tokens: usize,
+    /// Prompt tokens actually sent
+    pub prompt_tokens: usize,
+    /// Tokens available to the prompt: context length minus `max_tokens`
+    pub budget_tokens: usize,
+}
+
+/// Rough token count for backends without a tokenizer (~4 bytes per token)
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+/// Drop the oldest droppable messages until the rendered prompt fits `budget`
+///
+/// `render` turns a message list into the final prompt and `count` tokenizes
+/// it. Returns the prompt that will be sent, plus a report when anything was
+/// dropped. If even system messages and the last message overflow, they are
+/// sent as-is and the backend reports the overflow.
+pub fn fit_messages(
+    messages: &[ChatMessage],
+    budget: usize,
+    render: impl Fn(&[ChatMessage]) -> String,
+    count: impl Fn(&str) -> usize,
+) -> (String, Option<Truncation>) {
+    let prompt = render(messages);
+    let original_tokens = count(&prompt);
+    if original_tokens <= budget {
+        return (prompt, None);
+    }
+
+    let last = messages.len().saturating_sub(1);
+    let mut kept: Vec<usize> = (0..messages.len()).collect();
+    let mut dropped = Vec::new();
+    let (mut prompt, mut tokens) = (prompt, original_tokens);
+
+    while tokens > budget {
+        let Some(pos) = kept
+            .iter()
+            .position(|&i| i != last && messages[i].role != "system")
+        else {
+            break;
+        };
+        dropped.push(kept.remove(pos));
+        let remaining: Vec<ChatMessage> = kept.iter().map(|&i| messages[i].clone()).collect();
+        prompt = render(&remaining);
+        tokens = count(&prompt);
+    }
+
+    if dropped.is_empty() {
+        return (prompt, None);
+    }
+    tracing::debug!(
+        "Dropped {} messages ({} tokens) to fit a {}-token prompt budget",
+        dropped.len(),
+        original_tokens.saturating_sub(tokens),
+        budget
+    );
+    let truncation = Truncation {
+        dropped_messages: dropped,
+        dropped_tokens: original_tokens.saturating_sub(tokens),
+        prompt_tokens: tokens,
+        budget_tokens: budget,
+    };
+    (prompt, Some(truncation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    fn render(messages: &[ChatMessage]) -> String {
+        messages
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn words(text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+
+    #[test]
+    fn test_fitting_prompt_is_untouched() {
+        let messages = vec![msg("system", "be brief"), msg("user", "hi")];
+        let (prompt, truncation) = fit_messages(&messages, 10, render, words);
+        assert_eq!(prompt, "be brief hi");
+        assert!(truncation.is_none());
+    }
+
+    #[test]
+    fn test_drops_oldest_turns_but_keeps_system_and_last() {
+        let messages = vec![
+            msg("system", "be brief"),
+            msg("user", "one two three"),
+            msg("assistant", "four five"),
+            msg("user", "six"),
+            msg("assistant", "seven"),
+            msg("user", "eight nine"),
+        ];
+        let (prompt, truncation) = fit_messages(&messages, 6, render, words);
+        assert_eq!(prompt, "be brief six seven eight nine");
+
+        let truncation = truncation.unwrap();
+        assert_eq!(truncation.dropped_messages, vec![1, 2]);
+        assert_eq!(truncation.dropped_tokens, 5);
+        assert_eq!(truncation.prompt_tokens, 6);
+        assert_eq!(truncation.budget_tokens, 6);
+    }
+
+    #[test]
+    fn test_unfittable_prompt_keeps_system_and_last() {
+        let messages = vec![
+            msg("system", "a very long system prompt"),
+            msg("user", "old"),
+            msg("user", "latest question here"),
+        ];
+        let (prompt, truncation) = fit_messages(&messages, 3, render, words);
+        assert_eq!(prompt, "a very long system prompt latest question here");
+        assert_eq!(truncation.unwrap().dropped_messages, vec![1]);
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+}
@@ -0,0 +1,385 @@
+//! Self-update (`shimmy upgrade`) from GitHub releases.
+//!
+//! Picks the newest release on the requested channel, downloads the artifact
+//! for this platform, checks it against the release's `SHA256SUMS` (and the
+//! Ed25519 signature over that file when a release key is embedded at build
+//! time via `SHIMMY_RELEASE_PUBKEY`), then swaps the running binary in place.
+//!
+//! Installs managed by Homebrew, winget or cargo are left to their package
+//! manager unless `--force` is given.
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+const SIGNATURE_ASSET: &str = "SHA256SUMS.sig";
+
+/// Hex-encoded Ed25519 public key for release signatures
+const RELEASE_PUBKEY: Option<&str> = option_env!("SHIMMY_RELEASE_PUBKEY");
+
+/// Which releases `shimmy upgrade` considers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Stable,
+    Prerelease,
+}
+
+impl std::str::FromStr for Channel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "stable" => Ok(Channel::Stable),
+            "prerelease" | "beta" => Ok(Channel::Prerelease),
+            other => anyhow::bail!("unknown channel '{other}' (expected stable or prerelease)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|a| a.name == name)
+    }
+}
+
+/// Release artifact name for this platform, as published by the release workflow
+pub fn platform_asset_name() -> Option<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => Some("shimmy-linux-x86_64"),
+        ("linux", "aarch64") => Some("shimmy-linux-aarch64"),
+        ("windows", "x86_64") => Some("shimmy-windows-x86_64.exe"),
+        ("macos", "x86_64") => Some("shimmy-macos-intel"),
+        ("macos", "aarch64") => Some("shimmy-macos-arm64"),
+        _ => None,
+    }
+}
+
+/// Compare `1.9.0`-style versions (leading `v` optional). A prerelease sorts
+/// before its release; prerelease identifiers compare numerically when both are numbers.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn split(v: &str) -> (Vec<u64>, Option<&str>) {
+        let v = v.trim_start_matches('v');
+        let v = v.split('+').next().unwrap_or(v);
+        let (core, pre) = match v.split_once('-') {
+            Some((core, pre)) => (core, Some(pre)),
+            None => (v, None),
+        };
+        let nums = core.split('.').map(|n| n.parse().unwrap_or(0)).collect();
+        (nums, pre)
+    }
+
+    let (a_core, a_pre) = split(a);
+    let (b_core, b_pre) = split(b);
+    for i in 0..a_core.len().max(b_core.len()) {
+        let ord = a_core.get(i).unwrap_or(&0).cmp(b_core.get(i).unwrap_or(&0));
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+
+    match (a_pre, b_pre) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => {
+            for (x, y) in a.split('.').zip(b.split('.')) {
+                let ord = match (x.parse::<u64>(), y.parse::<u64>()) {
+                    (Ok(x), Ok(y)) => x.cmp(&y),
+                    _ => x.cmp(y),
+                };
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            a.split('.').count().cmp(&b.split('.').count())
+        }
+    }
+}
+
+/// Newest non-draft release on `channel`
+pub fn select_release(releases: &[Release], channel: Channel) -> Option<&Release> {
+    releases
+        .iter()
+        .filter(|r| !r.draft && (channel == Channel::Prerelease || !r.prerelease))
+        .max_by(|a, b| compare_versions(&a.tag_name, &b.tag_name))
+}
+
+/// Find the expected digest for `asset` in `sha256sum` output
+pub fn expected_checksum(sums: &str, asset: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (digest, name) = line.trim().split_once(char::is_whitespace)?;
+        // `sha256sum` marks binary-mode entries with a leading '*'
+        let name = name.trim().trim_start_matches('*');
+        (name == asset).then(|| digest.to_lowercase())
+    })
+}
+
+/// Verify the hex Ed25519 signature over `SHA256SUMS`
+pub fn verify_signature(sums: &[u8], signature_hex: &str, pubkey_hex: &str) -> anyhow::Result<()> {
+    let key: [u8; 32] = hex::decode(pubkey_hex.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("release public key must be 32 bytes"))?;
+    let signature: [u8; 64] = hex::decode(signature_hex.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("release signature must be 64 bytes"))?;
+    VerifyingKey::from_bytes(&key)?
+        .verify(sums, &Signature::from_bytes(&signature))
+        .map_err(|_| anyhow::anyhow!("SHA256SUMS signature does not match the release key"))
+}
+
+/// Package manager that owns the binary at `exe`, if any
+pub fn package_manager(exe: &Path) -> Option<(&'static str, &'static str)> {
+    let path = exe.to_string_lossy().replace('\\', "/").to_lowercase();
+    if path.contains("/cellar/") || path.contains("/homebrew/") || path.contains("/linuxbrew/") {
+        Some(("Homebrew", "brew upgrade shimmy"))
+    } else if path.contains("/winget/") {
+        Some(("winget", "winget upgrade shimmy"))
+    } else if path.contains("/.cargo/bin/") {
+        Some(("cargo", "cargo install shimmy --force"))
+    } else {
+        None
+    }
+}
+
+/// Options for `shimmy upgrade`
+#[derive(Debug, Clone)]
+pub struct UpgradeOptions {
+    pub channel: Channel,
+    /// Report whether an update exists without installing it
+    pub check: bool,
+    /// Upgrade even when a package manager owns the binary
+    pub force: bool,
+}
+
+async fn fetch_releases(client: &reqwest::Client) -> anyhow::Result<Vec<Release>> {
+    Ok(client
+        .get(crate::branding::RELEASES_URL)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+async fn fetch_bytes(client: &reqwest::Client, url: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?
+        .to_vec())
+}
+
+/// Write `bytes` next to `exe` and swap it in with a rename
+fn replace_binary(exe: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let dir = exe
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("cannot determine install directory"))?;
+    let mut staged = tempfile::NamedTempFile::new_in(dir)?;
+    std::io::Write::write_all(&mut staged, bytes)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(exe)?.permissions().mode();
+        staged
+            .as_file()
+            .set_permissions(std::fs::Permissions::from_mode(mode | 0o755))?;
+    }
+
+    // Windows can't overwrite a running executable, but it can rename it
+    #[cfg(windows)]
+    {
+        let old = exe.with_extension("exe.old");
+        let _ = std::fs::remove_file(&old);
+        std::fs::rename(exe, &old)?;
+    }
+
+    staged.persist(exe).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Check for and install a newer release
+pub async fn run(opts: UpgradeOptions) -> anyhow::Result<()> {
+    let exe: PathBuf = std::env::current_exe()?.canonicalize()?;
+    if let Some((manager, command)) = package_manager(&exe) {
+        if !opts.force && !opts.check {
+            println!("📦 shimmy was installed with {manager}; upgrade it with:");
+            println!("   {command}");
+            println!("   (or pass --force to replace {} directly)", exe.display());
+            return Ok(());
+        }
+    }
+
+    let Some(asset_name) = platform_asset_name() else {
+        anyhow::bail!(
+            "no prebuilt release for {}/{}; build from source instead",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+    };
+
+    let client = crate::util::http::client_builder()
+        .user_agent(concat!("shimmy/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let releases = fetch_releases(&client).await?;
+    let Some(release) = select_release(&releases, opts.channel) else {
+        anyhow::bail!("no releases found on the {:?} channel", opts.channel);
+    };
+
+    if compare_versions(&release.tag_name, CURRENT_VERSION) != Ordering::Greater {
+        println!("✅ shimmy {CURRENT_VERSION} is up to date");
+        return Ok(());
+    }
+    println!(
+        "⬆️  {} is available (current: {CURRENT_VERSION})",
+        release.tag_name
+    );
+    if opts.check {
+        return Ok(());
+    }
+
+    let asset = release
+        .asset(asset_name)
+        .ok_or_else(|| anyhow::anyhow!("{} has no {asset_name} artifact", release.tag_name))?;
+    let sums_asset = release.asset(CHECKSUMS_ASSET).ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} has no {CHECKSUMS_ASSET}; refusing to install unverified",
+            release.tag_name
+        )
+    })?;
+    let sums = fetch_bytes(&client, &sums_asset.browser_download_url).await?;
+
+    match RELEASE_PUBKEY.filter(|k| !k.trim().is_empty()) {
+        Some(pubkey) => {
+            let sig_asset = release.asset(SIGNATURE_ASSET).ok_or_else(|| {
+                anyhow::anyhow!("{} is not signed; refusing to install", release.tag_name)
+            })?;
+            let signature = fetch_bytes(&client, &sig_asset.browser_download_url).await?;
+            verify_signature(&sums, &String::from_utf8_lossy(&signature), pubkey)?;
+            println!("🔏 Release signature verified");
+        }
+        None => tracing::warn!("No release key embedded in this build; verifying checksum only"),
+    }
+
+    let expected = expected_checksum(&String::from_utf8_lossy(&sums), asset_name)
+        .ok_or_else(|| anyhow::anyhow!("{CHECKSUMS_ASSET} has no entry for {asset_name}"))?;
+
+    println!("⬇️  Downloading {asset_name}...");
+    let binary = fetch_bytes(&client, &asset.browser_download_url).await?;
+    let actual = hex::encode(Sha256::digest(&binary));
+    if actual != expected {
+        anyhow::bail!("checksum mismatch for {asset_name}: expected {expected}, got {actual}");
+    }
+
+    replace_binary(&exe, &binary)?;
+    println!("✅ Upgraded {} to {}", exe.display(), release.tag_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn release(tag: &str, prerelease: bool) -> Release {
+        Release {
+            tag_name: tag.to_string(),
+            prerelease,
+            draft: false,
+            assets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("v1.10.0", "1.9.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.9.0", "v1.9.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.9.0-beta.2", "1.9.0"), Ordering::Less);
+        assert_eq!(
+            compare_versions("1.9.0-beta.10", "1.9.0-beta.2"),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_select_release_respects_channel() {
+        let releases = vec![
+            release("v1.9.0", false),
+            release("v1.10.0-beta.1", true),
+            release("v1.8.2", false),
+        ];
+        assert_eq!(
+            select_release(&releases, Channel::Stable).unwrap().tag_name,
+            "v1.9.0"
+        );
+        assert_eq!(
+            select_release(&releases, Channel::Prerelease)
+                .unwrap()
+                .tag_name,
+            "v1.10.0-beta.1"
+        );
+    }
+
+    #[test]
+    fn test_expected_checksum_parses_sha256sum_output() {
+        let sums = "abc123  shimmy-linux-x86_64\nDEF456 *shimmy-linux-aarch64\n";
+        assert_eq!(
+            expected_checksum(sums, "shimmy-linux-aarch64").as_deref(),
+            Some("def456")
+        );
+        assert!(expected_checksum(sums, "shimmy-macos-arm64").is_none());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let pubkey = hex::encode(key.verifying_key().to_bytes());
+        let sums = b"abc123  shimmy-linux-aarch64\n";
+        let signature = hex::encode(key.sign(sums).to_bytes());
+
+        assert!(verify_signature(sums, &signature, &pubkey).is_ok());
+        assert!(verify_signature(b"tampered", &signature, &pubkey).is_err());
+    }
+
+    #[test]
+    fn test_package_manager_detection() {
+        assert_eq!(
+            package_manager(Path::new("/opt/homebrew/Cellar/shimmy/1.9.0/bin/shimmy"))
+                .map(|(m, _)| m),
+            Some("Homebrew")
+        );
+        assert_eq!(
+            package_manager(Path::new(
+                r"C:\Users\me\AppData\Local\Microsoft\WinGet\Packages\shimmy\shimmy.exe"
+            ))
+            .map(|(m, _)| m),
+            Some("winget")
+        );
+        assert!(package_manager(Path::new("/usr/local/bin/shimmy")).is_none());
+    }
+}
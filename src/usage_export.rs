@@ -0,0 +1,416 @@
+//! Upstream export of vision usage events for metered billing.
+//!
+//! `VisionLicenseManager::record_usage` always keeps local counters; when an
+//! exporter is configured each request is also queued here and pushed upstream
+//! in batches, either to Keygen (`increment-usage` on the license) or to a
+//! generic webhook (e.g. a worker that forwards to Stripe usage records).
+//!
+//! Configuration:
+//! - `SHIMMY_USAGE_EXPORT=keygen|webhook` enables export
+//! - `SHIMMY_USAGE_WEBHOOK_URL` target for webhook mode
+//! - `SHIMMY_USAGE_WEBHOOK_TOKEN` optional bearer token for webhook mode
+//! - `SHIMMY_USAGE_BATCH_SIZE` events per flush (default 10)
+//! - `SHIMMY_USAGE_FLUSH_SECS` seconds between flushes of smaller batches
+//!   (default 60)
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+const DEFAULT_BATCH_SIZE: usize = 10;
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+/// Upper bound on queued events so an unreachable upstream can't grow memory forever
+const MAX_PENDING_EVENTS: usize = 10_000;
+
+/// Where usage events are sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsageExportTarget {
+    Keygen,
+    Webhook { url: String, token: Option<String> },
+}
+
+/// Exporter configuration
+#[derive(Debug, Clone)]
+pub struct UsageExportConfig {
+    pub target: UsageExportTarget,
+    pub batch_size: usize,
+    pub max_retries: u32,
+    /// Events queued for this long are sent even if the batch isn't full
+    pub flush_interval: Duration,
+}
+
+impl UsageExportConfig {
+    /// Build config from environment; `None` when export is disabled
+    pub fn from_env() -> Option<Self> {
+        let mode = std::env::var("SHIMMY_USAGE_EXPORT").ok()?;
+        let target = match mode.trim().to_lowercase().as_str() {
+            "keygen" => UsageExportTarget::Keygen,
+            "webhook" => {
+                let Some(url) = std::env::var("SHIMMY_USAGE_WEBHOOK_URL")
+                    .ok()
+                    .filter(|u| !u.trim().is_empty())
+                else {
+                    tracing::warn!(
+                        "SHIMMY_USAGE_EXPORT=webhook but SHIMMY_USAGE_WEBHOOK_URL is not set; usage export disabled"
+                    );
+                    return None;
+                };
+                UsageExportTarget::Webhook {
+                    url,
+                    token: std::env::var("SHIMMY_USAGE_WEBHOOK_TOKEN").ok(),
+                }
+            }
+            "" | "off" | "none" | "0" | "false" => return None,
+            other => {
+                tracing::warn!(
+                    "Unknown SHIMMY_USAGE_EXPORT value '{}'; usage export disabled",
+                    other
+                );
+                return None;
+            }
+        };
+
+        let batch_size = std::env::var("SHIMMY_USAGE_BATCH_SIZE")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_BATCH_SIZE);
+        let flush_interval = std::env::var("SHIMMY_USAGE_FLUSH_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|n| *n > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_FLUSH_INTERVAL);
+
+        Some(Self {
+            target,
+            batch_size,
+            max_retries: DEFAULT_MAX_RETRIES,
+            flush_interval,
+        })
+    }
+}
+
+/// A single billable vision request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageEvent {
+    pub license_key: String,
+    pub feature: String,
+    pub quantity: u32,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+/// A usage event as a webhook receives it: the license is identified by the
+/// SHA-256 of its key, never by the key itself
+#[derive(Debug, Serialize)]
+struct WebhookEvent<'a> {
+    license_sha256: String,
+    feature: &'a str,
+    quantity: u32,
+    timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+impl<'a> From<&'a UsageEvent> for WebhookEvent<'a> {
+    fn from(event: &'a UsageEvent) -> Self {
+        Self {
+            license_sha256: license_sha256(&event.license_key),
+            feature: &event.feature,
+            quantity: event.quantity,
+            timestamp: event.timestamp,
+        }
+    }
+}
+
+/// Hex SHA-256 of a license key
+pub fn license_sha256(key: &str) -> String {
+    use sha2::{Digest, Sha256};
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Batching, retrying usage exporter
+#[derive(Debug, Clone)]
+pub struct UsageExporter {
+    config: UsageExportConfig,
+    pending: Arc<Mutex<Vec<UsageEvent>>>,
+    flush_lock: Arc<Mutex<()>>,
+    client: reqwest::Client,
+}
+
+impl UsageExporter {
+    /// Fails when the HTTP client can't be built with the configured proxy
+    /// and CA bundle
+    pub fn new(config: UsageExportConfig) -> anyhow::Result<Self> {
+        let client = crate::util::http::client_builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(Self {
+            config,
+            pending: Arc::new(Mutex::new(Vec::new())),
+            flush_lock: Arc::new(Mutex::new(())),
+            client,
+        })
+    }
+
+    /// Create an exporter from environment, if enabled
+    pub fn from_env() -> Option<Self> {
+        let config = UsageExportConfig::from_env()?;
+        match Self::new(config) {
+            Ok(exporter) => Some(exporter),
+            Err(e) => {
+                tracing::error!("Usage export disabled: HTTP client setup failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Queue an event; kicks off a background flush once a batch is full
+    pub async fn record(&self, event: UsageEvent) {
+        let should_flush = {
+            let mut pending = self.pending.lock().await;
+            pending.push(event);
+            trim_pending(&mut pending);
+            pending.len() >= self.config.batch_size
+        };
+
+        if should_flush {
+            let exporter = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = exporter.flush().await {
+                    tracing::warn!("Usage export flush failed: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Flush on `flush_interval` so batches that never fill up still go out
+    pub fn spawn_flusher(&self) {
+        let exporter = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(exporter.config.flush_interval);
+            // The first tick completes immediately
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = exporter.flush().await {
+                    tracing::warn!("Usage export flush failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Number of events waiting to be sent
+    #[cfg(test)]
+    async fn pending_len(&self) -> usize {
+        self.pending.lock().await.len()
+    }
+
+    /// Send all queued events; events that weren't delivered are re-queued
+    /// for the next flush
+    pub async fn flush(&self) -> anyhow::Result<usize> {
+        let _guard = self.flush_lock.lock().await;
+        let mut batch: Vec<UsageEvent> = std::mem::take(&mut *self.pending.lock().await);
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let total = batch.len();
+        let mut attempt = 0;
+        loop {
+            match self.send_batch(&batch).await {
+                Ok(()) => return Ok(total),
+                Err(failure) if attempt < self.config.max_retries => {
+                    attempt += 1;
+                    let backoff = Duration::from_millis(500 * 2u64.pow(attempt - 1));
+                    tracing::debug!(
+                        "Usage export attempt {} failed: {}; retrying in {:?}",
+                        attempt,
+                        failure.error,
+                        backoff
+                    );
+                    // Only retry what wasn't delivered, so nothing is billed twice
+                    batch = failure.unsent;
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(failure) => {
+                    let mut pending = self.pending.lock().await;
+                    let newer = std::mem::take(&mut *pending);
+                    *pending = failure.unsent;
+                    pending.extend(newer);
+                    trim_pending(&mut pending);
+                    return Err(failure.error);
+                }
+            }
+        }
+    }
+
+    async fn send_batch(&self, batch: &[UsageEvent]) -> Result<(), SendFailure> {
+        match &self.config.target {
+            UsageExportTarget::Keygen => self.send_keygen(batch).await,
+            UsageExportTarget::Webhook { url, token } => {
+                let events: Vec<WebhookEvent> = batch.iter().map(WebhookEvent::from).collect();
+                let mut request = self
+                    .client
+                    .post(url)
+                    .json(&serde_json::json!({ "events": events }));
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                let result = request.send().await.and_then(|r| r.error_for_status());
+                result.map(drop).map_err(|e| SendFailure {
+                    error: e.into(),
+                    unsent: batch.to_vec(),
+                })
+            }
+        }
+    }
+
+    /// Keygen metered usage: one `increment-usage` call per license. A failed
+    /// call leaves only that license's events unsent.
+    async fn send_keygen(&self, batch: &[UsageEvent]) -> Result<(), SendFailure> {
+        let Some(token) = crate::vision_license::KeygenConfig::load().resolve_product_token()
+        else {
+            return Err(SendFailure {
+                error: anyhow::anyhow!("Keygen product token not configured"),
+                unsent: batch.to_vec(),
+            });
+        };
+
+        let mut failed = HashSet::new();
+        let mut last_error = None;
+        for (license_key, increment) in group_by_license(batch) {
+            let url = format!(
+                "https://api.keygen.sh/v1/accounts/{}/licenses/{}/actions/increment-usage",
+                crate::vision_license::KEYGEN_ACCOUNT_ID,
+                license_key
+            );
+            let result = self
+                .client
+                .post(&url)
+                .bearer_auth(&token)
+                .header("Content-Type", "application/vnd.api+json")
+                .header("Accept", "application/vnd.api+json")
+                .json(&serde_json::json!({ "meta": { "increment": increment } }))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            if let Err(e) = result {
+                failed.insert(license_key);
+                last_error = Some(e);
+            }
+        }
+
+        match last_error {
+            None => Ok(()),
+            Some(e) => Err(SendFailure {
+                error: e.into(),
+                unsent: events_of(batch, &failed),
+            }),
+        }
+    }
+}
+
+/// A send that failed, with the events it didn't deliver
+struct SendFailure {
+    error: anyhow::Error,
+    unsent: Vec<UsageEvent>,
+}
+
+/// Sum quantities per license key
+pub fn group_by_license(batch: &[UsageEvent]) -> HashMap<String, u64> {
+    let mut totals = HashMap::new();
+    for event in batch {
+        *totals.entry(event.license_key.clone()).or_insert(0) += event.quantity as u64;
+    }
+    totals
+}
+
+/// Events of the given licenses, in order
+fn events_of(batch: &[UsageEvent], license_keys: &HashSet<String>) -> Vec<UsageEvent> {
+    batch
+        .iter()
+        .filter(|event| license_keys.contains(&event.license_key))
+        .cloned()
+        .collect()
+}
+
+fn trim_pending(pending: &mut Vec<UsageEvent>) {
+    if pending.len() > MAX_PENDING_EVENTS {
+        let overflow = pending.len() - MAX_PENDING_EVENTS;
+        tracing::warn!(
+            "Usage export queue full; dropping {} oldest events",
+            overflow
+        );
+        pending.drain(..overflow);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(key: &str) -> UsageEvent {
+        UsageEvent {
+            license_key: key.to_string(),
+            feature: "VISION_ANALYSIS".to_string(),
+            quantity: 1,
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_group_by_license_sums_quantities() {
+        let totals = group_by_license(&[event("a"), event("b"), event("a")]);
+        assert_eq!(totals.get("a"), Some(&2));
+        assert_eq!(totals.get("b"), Some(&1));
+    }
+
+    #[test]
+    fn test_webhook_events_hash_the_license_key() {
+        let key = "ABCD-1234-SECRET";
+        let json = serde_json::to_string(&WebhookEvent::from(&event(key))).unwrap();
+        assert!(!json.contains(key));
+        assert!(json.contains(&license_sha256(key)));
+        assert_eq!(license_sha256(key).len(), 64);
+    }
+
+    #[test]
+    fn test_only_failed_licenses_are_unsent() {
+        let batch = [event("a"), event("b"), event("a"), event("c")];
+        let failed = HashSet::from(["a".to_string()]);
+        let unsent = events_of(&batch, &failed);
+        assert_eq!(unsent.len(), 2);
+        assert!(unsent.iter().all(|e| e.license_key == "a"));
+    }
+
+    #[test]
+    fn test_trim_pending_drops_oldest() {
+        let mut pending: Vec<UsageEvent> = (0..MAX_PENDING_EVENTS + 5)
+            .map(|i| event(&i.to_string()))
+            .collect();
+        trim_pending(&mut pending);
+        assert_eq!(pending.len(), MAX_PENDING_EVENTS);
+        assert_eq!(pending[0].license_key, "5");
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_requeues_events() {
+        let exporter = UsageExporter::new(UsageExportConfig {
+            target: UsageExportTarget::Webhook {
+                url: "http://127.0.0.1:9/usage".to_string(),
+                token: None,
+            },
+            batch_size: 100,
+            max_retries: 0,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+        })
+        .unwrap();
+        exporter.record(event("a")).await;
+        exporter.record(event("b")).await;
+
+        assert!(exporter.flush().await.is_err());
+        assert_eq!(exporter.pending_len().await, 2);
+    }
+}
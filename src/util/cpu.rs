@@ -0,0 +1,321 @@
+//! CPU topology and inference thread placement on hybrid CPUs.
+//!
+//! Intel hybrid parts (P-cores + E-cores) and big.LITTLE ARM chips mix fast
+//! and slow cores. llama.cpp splits every step evenly across its threads, so a
+//! thread the scheduler parks on a slow core holds the whole step back. On
+//! Linux, shimmy detects the performance cores at startup and restricts the
+//! process to them before any thread starts; llama.cpp's threads inherit
+//! that, and the default thread count becomes the number of physical
+//! performance cores.
+//!
+//! `SHIMMY_CPU_AFFINITY` controls it:
+//!
+//! - `performance` (default): pin to performance cores on hybrid CPUs; uniform
+//!   CPUs are left alone
+//! - `all`: leave placement to the OS scheduler
+//! - a CPU list such as `0-7,16`: pin to exactly those CPUs
+//!
+//! Topology comes from sysfs: `/sys/devices/cpu_core/cpus` on Intel hybrid,
+//! otherwise each CPU's `cpu_capacity` (ARM). Other platforms keep the OS
+//! default.
+
+use anyhow::{bail, Result};
+use std::path::Path;
+
+pub const AFFINITY_ENV: &str = "SHIMMY_CPU_AFFINITY";
+
+/// Cores below this share of the fastest core's capacity count as efficiency
+/// cores; keeps the mid cores of three-tier ARM designs in the fast set
+const PERFORMANCE_CAPACITY_RATIO: f64 = 0.75;
+
+/// Where inference threads may run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AffinityMode {
+    Performance,
+    All,
+    Cpus(Vec<usize>),
+}
+
+impl AffinityMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim() {
+            "" | "performance" => Ok(AffinityMode::Performance),
+            "all" => Ok(AffinityMode::All),
+            list => match parse_cpu_list(list) {
+                Some(cpus) if !cpus.is_empty() => Ok(AffinityMode::Cpus(cpus)),
+                _ => bail!(
+                    "invalid {} `{}` (expected performance, all, or a CPU list like 0-7,16)",
+                    AFFINITY_ENV,
+                    list
+                ),
+            },
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Self::parse(&std::env::var(AFFINITY_ENV).unwrap_or_default())
+    }
+}
+
+/// Which logical CPUs are fast and which are slow
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoreTopology {
+    /// Logical CPUs on the fastest cores
+    pub performance: Vec<usize>,
+    /// Logical CPUs on slower cores; empty on uniform CPUs
+    pub efficiency: Vec<usize>,
+    /// Physical cores behind `performance` (SMT siblings counted once)
+    pub performance_cores: usize,
+}
+
+impl CoreTopology {
+    pub fn is_hybrid(&self) -> bool {
+        !self.performance.is_empty() && !self.efficiency.is_empty()
+    }
+
+    /// Topology of this machine, where the platform exposes it
+    pub fn detect() -> Option<Self> {
+        if cfg!(target_os = "linux") {
+            Self::from_sysfs(Path::new("/sys"))
+        } else {
+            None
+        }
+    }
+
+    /// Read the topology from a sysfs tree rooted at `sys`
+    pub fn from_sysfs(sys: &Path) -> Option<Self> {
+        let cpu_dir = sys.join("devices/system/cpu");
+        let online = read_cpu_list(&cpu_dir.join("online"))?;
+
+        // Intel hybrid: the kernel registers a PMU per core type
+        let p_cores = read_cpu_list(&sys.join("devices/cpu_core/cpus"));
+        let e_cores = read_cpu_list(&sys.join("devices/cpu_atom/cpus"));
+
+        let (performance, efficiency) = if let Some(p_cores) = p_cores {
+            (p_cores, e_cores.unwrap_or_default())
+        } else {
+            let capacities: Vec<(usize, u64)> = online
+                .iter()
+                .filter_map(|&cpu| {
+                    let path = cpu_dir.join(format!("cpu{}/cpu_capacity", cpu));
+                    let capacity = std::fs::read_to_string(path).ok()?;
+                    Some((cpu, capacity.trim().parse().ok()?))
+                })
+                .collect();
+            let max = capacities.iter().map(|&(_, c)| c).max().unwrap_or(0);
+            if capacities.len() != online.len() || max == 0 {
+                (online.clone(), Vec::new())
+            } else {
+                let threshold = max as f64 * PERFORMANCE_CAPACITY_RATIO;
+                let (fast, slow): (Vec<_>, Vec<_>) = capacities
+                    .into_iter()
+                    .partition(|&(_, c)| c as f64 >= threshold);
+                (
+                    fast.into_iter().map(|(cpu, _)| cpu).collect(),
+                    slow.into_iter().map(|(cpu, _)| cpu).collect(),
+                )
+            }
+        };
+
+        let mut siblings: Vec<String> = performance
+            .iter()
+            .map(|cpu| {
+                let path = cpu_dir.join(format!("cpu{}/topology/thread_siblings_list", cpu));
+                std::fs::read_to_string(path)
+                    .map(|s| s.trim().to_string())
+                    .unwrap_or_else(|_| cpu.to_string())
+            })
+            .collect();
+        siblings.sort();
+        siblings.dedup();
+
+        Some(Self {
+            performance_cores: siblings.len(),
+            performance,
+            efficiency,
+        })
+    }
+}
+
+fn read_cpu_list(path: &Path) -> Option<Vec<usize>> {
+    parse_cpu_list(std::fs::read_to_string(path).ok()?.trim())
+}
+
+/// Parse the kernel's CPU list format, e.g. `0-3,8,10-11`
+pub fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+                if start > end {
+                    return None;
+                }
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(part.parse().ok()?),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Some(cpus)
+}
+
+/// The inverse of [`parse_cpu_list`], collapsing runs into ranges
+pub fn format_cpu_list(cpus: &[usize]) -> String {
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < cpus.len() {
+        let start = cpus[i];
+        while i + 1 < cpus.len() && cpus[i + 1] == cpus[i] + 1 {
+            i += 1;
+        }
+        parts.push(if cpus[i] == start {
+            start.to_string()
+        } else {
+            format!("{}-{}", start, cpus[i])
+        });
+        i += 1;
+    }
+    parts.join(",")
+}
+
+/// Default inference thread count under the configured affinity, if it
+/// narrows the process to a known set of cores
+pub fn inference_threads() -> Option<usize> {
+    match AffinityMode::from_env().ok()? {
+        AffinityMode::All => None,
+        AffinityMode::Cpus(cpus) => Some(cpus.len()),
+        AffinityMode::Performance => CoreTopology::detect()
+            .filter(CoreTopology::is_hybrid)
+            .map(|t| t.performance_cores.max(1)),
+    }
+}
+
+/// Apply `SHIMMY_CPU_AFFINITY` to the calling thread and every thread it
+/// starts afterwards; returns the CPUs it pinned to, if any
+pub fn apply_on_startup() -> Result<Option<Vec<usize>>> {
+    let cpus = match AffinityMode::from_env()? {
+        AffinityMode::All => return Ok(None),
+        AffinityMode::Cpus(cpus) => cpus,
+        AffinityMode::Performance => match CoreTopology::detect() {
+            Some(topology) if topology.is_hybrid() => topology.performance,
+            _ => return Ok(None),
+        },
+    };
+    set_affinity(&cpus)?;
+    Ok(Some(cpus))
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpus: &[usize]) -> Result<()> {
+    // SAFETY: cpu_set_t is plain data, and CPU_SET is only called with indexes
+    // checked against CPU_SETSIZE
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                bail!("CPU {} is out of range", cpu);
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            bail!(
+                "could not pin to CPUs {}: {}",
+                format_cpu_list(cpus),
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cpus: &[usize]) -> Result<()> {
+    bail!("pinning to a CPU list is only supported on Linux")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_cpu_lists_and_modes() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n".trim()),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list("3-1"), None);
+        assert_eq!(parse_cpu_list("a"), None);
+        assert_eq!(format_cpu_list(&[0, 1, 2, 3, 8, 10, 11]), "0-3,8,10-11");
+
+        assert_eq!(AffinityMode::parse("").unwrap(), AffinityMode::Performance);
+        assert_eq!(AffinityMode::parse("all").unwrap(), AffinityMode::All);
+        assert_eq!(
+            AffinityMode::parse("0-1,4").unwrap(),
+            AffinityMode::Cpus(vec![0, 1, 4])
+        );
+        assert!(AffinityMode::parse("fast").is_err());
+    }
+
+    #[test]
+    fn test_intel_hybrid_topology() {
+        // 6 P-cores with hyperthreading (CPUs 0-11) and 8 E-cores (12-19)
+        let sys = tempfile::tempdir().unwrap();
+        write(sys.path(), "devices/system/cpu/online", "0-19\n");
+        write(sys.path(), "devices/cpu_core/cpus", "0-11\n");
+        write(sys.path(), "devices/cpu_atom/cpus", "12-19\n");
+        for cpu in 0..12 {
+            let pair = cpu / 2 * 2;
+            write(
+                sys.path(),
+                &format!(
+                    "devices/system/cpu/cpu{}/topology/thread_siblings_list",
+                    cpu
+                ),
+                &format!("{}-{}\n", pair, pair + 1),
+            );
+        }
+
+        let topology = CoreTopology::from_sysfs(sys.path()).unwrap();
+        assert!(topology.is_hybrid());
+        assert_eq!(topology.performance, (0..12).collect::<Vec<_>>());
+        assert_eq!(topology.efficiency, (12..20).collect::<Vec<_>>());
+        assert_eq!(topology.performance_cores, 6);
+    }
+
+    #[test]
+    fn test_arm_capacity_topology() {
+        // One prime core, three big cores, four little cores
+        let sys = tempfile::tempdir().unwrap();
+        write(sys.path(), "devices/system/cpu/online", "0-7");
+        for (cpu, capacity) in [1024, 870, 870, 870, 325, 325, 325, 325].iter().enumerate() {
+            write(
+                sys.path(),
+                &format!("devices/system/cpu/cpu{}/cpu_capacity", cpu),
+                &capacity.to_string(),
+            );
+        }
+
+        let topology = CoreTopology::from_sysfs(sys.path()).unwrap();
+        assert_eq!(topology.performance, vec![0, 1, 2, 3]);
+        assert_eq!(topology.efficiency, vec![4, 5, 6, 7]);
+        assert_eq!(topology.performance_cores, 4);
+
+        // Uniform cores are not hybrid
+        for cpu in 0..8 {
+            write(
+                sys.path(),
+                &format!("devices/system/cpu/cpu{}/cpu_capacity", cpu),
+                "1024",
+            );
+        }
+        assert!(!CoreTopology::from_sysfs(sys.path()).unwrap().is_hybrid());
+    }
+}
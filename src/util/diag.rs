@@ -1,6 +1,5 @@
 use axum::Json;
 use serde::Serialize;
-use sysinfo::System;
 
 #[derive(Serialize)]
 pub struct Diag {
@@ -10,14 +9,11 @@ pub struct Diag {
 }
 
 pub async fn diag_handler() -> Json<Diag> {
-    let mut sys = System::new_all();
-    sys.refresh_all();
-    // Some sysinfo methods changed across versions; keep it minimal & portable.
     let os = std::env::consts::OS.to_string();
     let cores = std::thread::available_parallelism()
         .map(|n| n.get())
         .unwrap_or(0);
-    let mem_total_mb = sys.total_memory() / 1024; // KiB -> MiB
+    let mem_total_mb = crate::util::memory::get_total_memory() / (1024 * 1024);
     Json(Diag {
         os,
         cores,
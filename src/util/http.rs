@@ -0,0 +1,293 @@
+/// Outbound HTTP clients
+///
+/// Every client shimmy uses for outbound requests (license validation, image
+/// URL fetches, model and update downloads, telemetry, webhooks) starts from
+/// [`client_builder`], so proxy settings apply everywhere.
+///
+/// Resolution order, per URL scheme:
+/// 1. `SHIMMY_PROXY` (or `proxy` in `shimmy.toml`) for both http and https
+/// 2. `HTTPS_PROXY` / `HTTP_PROXY`, upper or lower case
+/// 3. `ALL_PROXY`, upper or lower case
+///
+/// Hosts listed in `SHIMMY_NO_PROXY` (or `no_proxy`), falling back to
+/// `NO_PROXY`, are reached directly. With none of these set, reqwest's own
+/// platform proxy detection is left in place.
+///
+/// `SHIMMY_CA_BUNDLE` (or `ca_bundle`) names a PEM file of extra root
+/// certificates, e.g. a corporate TLS-inspecting proxy's CA. They are trusted
+/// alongside the built-in roots; verification itself is never turned off.
+use reqwest::{Certificate, ClientBuilder, NoProxy, Proxy};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+pub const PROXY_ENV: &str = "SHIMMY_PROXY";
+pub const NO_PROXY_ENV: &str = "SHIMMY_NO_PROXY";
+pub const CA_BUNDLE_ENV: &str = "SHIMMY_CA_BUNDLE";
+
+/// Proxies for outbound requests
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxyConfig {
+    pub http: Option<String>,
+    pub https: Option<String>,
+    /// Comma-separated hosts, domains and CIDR ranges to reach directly
+    pub no_proxy: Option<String>,
+}
+
+impl ProxyConfig {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    fn from_lookup(get: impl Fn(&str) -> Option<String>) -> Self {
+        let first = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| get(name).filter(|v| !v.trim().is_empty()))
+        };
+        let explicit = first(&[PROXY_ENV]);
+        let all = first(&["ALL_PROXY", "all_proxy"]);
+        Self {
+            https: explicit
+                .clone()
+                .or_else(|| first(&["HTTPS_PROXY", "https_proxy"]))
+                .or_else(|| all.clone()),
+            http: explicit
+                .or_else(|| first(&["HTTP_PROXY", "http_proxy"]))
+                .or(all),
+            no_proxy: first(&[NO_PROXY_ENV, "NO_PROXY", "no_proxy"]),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.http.is_none() && self.https.is_none()
+    }
+
+    /// The proxies to install, failing on a malformed proxy URL
+    pub fn proxies(&self) -> Result<Vec<Proxy>, reqwest::Error> {
+        let no_proxy = || self.no_proxy.as_deref().and_then(NoProxy::from_string);
+        let mut proxies = Vec::new();
+        if let Some(url) = &self.https {
+            proxies.push(Proxy::https(url.trim())?.no_proxy(no_proxy()));
+        }
+        if let Some(url) = &self.http {
+            proxies.push(Proxy::http(url.trim())?.no_proxy(no_proxy()));
+        }
+        Ok(proxies)
+    }
+}
+
+/// Every certificate in a PEM bundle
+pub fn parse_ca_bundle(pem: &[u8]) -> anyhow::Result<Vec<Certificate>> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    let text = std::str::from_utf8(pem)?;
+    let mut certs = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(BEGIN) {
+        let Some(len) = rest[start..].find(END) else {
+            anyhow::bail!("unterminated certificate");
+        };
+        let end = start + len + END.len();
+        certs.push(Certificate::from_pem(&rest.as_bytes()[start..end])?);
+        rest = &rest[end..];
+    }
+    if certs.is_empty() {
+        anyhow::bail!("no PEM certificates found");
+    }
+    Ok(certs)
+}
+
+/// Certificates from `SHIMMY_CA_BUNDLE`, read once
+fn extra_roots() -> &'static [Certificate] {
+    static ROOTS: OnceLock<Vec<Certificate>> = OnceLock::new();
+    ROOTS.get_or_init(|| {
+        let Some(path) = std::env::var_os(CA_BUNDLE_ENV).filter(|p| !p.is_empty()) else {
+            return Vec::new();
+        };
+        let path = std::path::PathBuf::from(path);
+        match std::fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|pem| parse_ca_bundle(&pem))
+        {
+            Ok(certs) => {
+                tracing::info!(
+                    "Trusting {} extra root certificate(s) from {}",
+                    certs.len(),
+                    path.display()
+                );
+                certs
+            }
+            Err(e) => {
+                tracing::warn!("Ignoring {} ({}): {}", CA_BUNDLE_ENV, path.display(), e);
+                Vec::new()
+            }
+        }
+    })
+}
+
+/// A `reqwest` client builder with the configured proxies and extra root
+/// certificates installed
+pub fn client_builder() -> ClientBuilder {
+    let builder = extra_roots().iter().cloned().fold(
+        reqwest::Client::builder(),
+        ClientBuilder::add_root_certificate,
+    );
+    let config = ProxyConfig::from_env();
+    if config.is_empty() {
+        return builder;
+    }
+    match config.proxies() {
+        Ok(proxies) => proxies.into_iter().fold(builder, ClientBuilder::proxy),
+        Err(e) => {
+            // The URL may hold credentials, so only the error is logged
+            tracing::warn!("Ignoring invalid proxy setting: {}", e);
+            builder
+        }
+    }
+}
+
+/// SHA-256 fingerprints a server certificate must match
+///
+/// Fingerprints are of the whole DER certificate, as printed by
+/// `openssl x509 -noout -fingerprint -sha256`; colons and a `sha256:` prefix
+/// are optional. Pinning needs a client built with `tls_info(true)`.
+#[cfg_attr(not(feature = "vision"), allow(dead_code))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CertPins(Vec<[u8; 32]>);
+
+#[cfg_attr(not(feature = "vision"), allow(dead_code))]
+impl CertPins {
+    /// Comma-separated fingerprints
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut pins = Vec::new();
+        for pin in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let hex_digits: String = pin
+                .trim_start_matches("sha256:")
+                .chars()
+                .filter(|c| *c != ':')
+                .collect();
+            let bytes = hex::decode(&hex_digits)
+                .ok()
+                .and_then(|b| <[u8; 32]>::try_from(b).ok())
+                .ok_or_else(|| anyhow::anyhow!("`{}` is not a SHA-256 fingerprint", pin))?;
+            pins.push(bytes);
+        }
+        Ok(Self(pins))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether a DER certificate matches one of the pins
+    pub fn matches(&self, der: &[u8]) -> bool {
+        let digest: [u8; 32] = Sha256::digest(der).into();
+        self.0.contains(&digest)
+    }
+
+    /// Fail unless the response came over a connection presenting a pinned
+    /// certificate
+    pub fn check(&self, response: &reqwest::Response) -> anyhow::Result<()> {
+        let der = response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .and_then(|info| info.peer_certificate())
+            .ok_or_else(|| anyhow::anyhow!("no TLS certificate to check the pin against"))?;
+        if !self.matches(der) {
+            anyhow::bail!(
+                "certificate for {} (sha256 {}) matches none of the pinned fingerprints",
+                response.url().host_str().unwrap_or("?"),
+                hex::encode(Sha256::digest(der))
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> ProxyConfig {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        ProxyConfig::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_standard_variables() {
+        let c = config(&[
+            ("https_proxy", "http://proxy:3128"),
+            ("NO_PROXY", "localhost,.corp"),
+        ]);
+        assert_eq!(c.https.as_deref(), Some("http://proxy:3128"));
+        assert_eq!(c.http, None);
+        assert_eq!(c.no_proxy.as_deref(), Some("localhost,.corp"));
+        assert!(config(&[]).is_empty());
+        assert!(config(&[("HTTPS_PROXY", " ")]).is_empty());
+    }
+
+    #[test]
+    fn test_explicit_proxy_wins() {
+        let c = config(&[
+            (PROXY_ENV, "http://explicit:8080"),
+            ("HTTPS_PROXY", "http://env:3128"),
+            (NO_PROXY_ENV, "10.0.0.0/8"),
+            ("NO_PROXY", "localhost"),
+        ]);
+        assert_eq!(c.https.as_deref(), Some("http://explicit:8080"));
+        assert_eq!(c.http.as_deref(), Some("http://explicit:8080"));
+        assert_eq!(c.no_proxy.as_deref(), Some("10.0.0.0/8"));
+    }
+
+    #[test]
+    fn test_all_proxy_fills_gaps() {
+        let c = config(&[
+            ("ALL_PROXY", "http://all:3128"),
+            ("HTTP_PROXY", "http://plain:3128"),
+        ]);
+        assert_eq!(c.https.as_deref(), Some("http://all:3128"));
+        assert_eq!(c.http.as_deref(), Some("http://plain:3128"));
+    }
+
+    #[test]
+    fn test_cert_pins() {
+        let der = b"not really a certificate";
+        let hex_digest = hex::encode(Sha256::digest(der));
+        let colons = hex_digest
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| std::str::from_utf8(pair).unwrap().to_uppercase())
+            .collect::<Vec<_>>()
+            .join(":");
+
+        for spec in [
+            hex_digest.clone(),
+            colons,
+            format!("sha256:{}, {}", "00".repeat(32), hex_digest),
+        ] {
+            let pins = CertPins::parse(&spec).unwrap();
+            assert!(pins.matches(der), "{spec}");
+            assert!(!pins.matches(b"another certificate"));
+        }
+        assert!(CertPins::parse("").unwrap().is_empty());
+        assert!(CertPins::parse("abcd").is_err());
+        assert!(CertPins::parse(&"zz".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_ca_bundle_needs_certificates() {
+        assert!(parse_ca_bundle(b"").is_err());
+        assert!(parse_ca_bundle(b"-----BEGIN CERTIFICATE-----\nAAAA").is_err());
+    }
+
+    #[test]
+    fn test_proxies() {
+        let c = config(&[("HTTPS_PROXY", "http://proxy:3128")]);
+        assert_eq!(c.proxies().unwrap().len(), 1);
+        assert!(config(&[(PROXY_ENV, "http://[broken")]).proxies().is_err());
+    }
+}
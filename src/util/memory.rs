@@ -1,30 +1,26 @@
-/// Memory management utilities for Issue #108
-///
-/// Provides memory estimation and warnings to help users understand
-/// system requirements for large language models.
-use sysinfo::System;
+//! Memory management utilities for Issue #108
+//!
+//! Provides memory estimation and warnings to help users understand
+//! system requirements for large language models.
+//!
+//! Memory is read through `sysinfo`; builds without the `sysinfo` feature
+//! can't tell, so readings come back as 0 (or `None`) and the memory guards
+//! let everything through.
 
-/// Get total system memory in bytes
-#[allow(dead_code)] // Placeholder utility for future use
+/// Get total system memory in bytes (0 when unknown)
 pub fn get_total_memory() -> u64 {
-    let mut system = System::new_all();
-    system.refresh_memory();
-    system.total_memory()
+    sample_memory().map_or(0, |s| s.total_bytes)
 }
 
-/// Get available system memory in bytes
-#[allow(dead_code)] // Placeholder utility for future use
+/// Get available system memory in bytes (0 when unknown)
 pub fn get_available_memory() -> u64 {
-    let mut system = System::new_all();
-    system.refresh_memory();
-    system.available_memory()
+    sample_memory().map_or(0, |s| s.available_bytes)
 }
 
 /// Estimate memory requirements for a model file
 ///
 /// This provides a rough estimate based on file size and typical
 /// memory overhead for quantized models.
-#[allow(dead_code)] // Placeholder utility for future use
 pub fn estimate_memory_requirements(model_file_size: u64) -> MemoryEstimate {
     let file_size_gb = model_file_size as f64 / 1_024_000_000.0;
 
@@ -45,8 +41,7 @@ pub fn estimate_memory_requirements(model_file_size: u64) -> MemoryEstimate {
 }
 
 /// Memory requirement estimate
-#[derive(Debug)]
-#[allow(dead_code)] // Placeholder utility for future use
+#[derive(Debug, Clone)]
 pub struct MemoryEstimate {
     pub file_size_gb: f64,
     pub estimated_runtime_gb: f64,
@@ -59,13 +54,7 @@ pub fn check_memory_availability(required_gb: f64) -> MemoryAvailability {
     let total_gb = get_total_memory() as f64 / 1_024_000_000.0;
     let available_gb = get_available_memory() as f64 / 1_024_000_000.0;
 
-    let status = if available_gb >= required_gb {
-        MemoryStatus::Sufficient
-    } else if total_gb >= required_gb {
-        MemoryStatus::Tight
-    } else {
-        MemoryStatus::Insufficient
-    };
+    let status = MemoryStatus::classify(required_gb, total_gb, available_gb);
 
     MemoryAvailability {
         total_gb,
@@ -85,14 +74,28 @@ pub struct MemoryAvailability {
     pub status: MemoryStatus,
 }
 
-#[derive(Debug, PartialEq)]
-#[allow(dead_code)] // Placeholder utility for future use
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MemoryStatus {
-    Sufficient,   // Available memory > required
+    #[serde(rename = "fits")]
+    Sufficient, // Available memory > required
     Tight,        // Total memory >= required but available < required
     Insufficient, // Total memory < required
 }
 
+impl MemoryStatus {
+    /// Classify a requirement against total and currently available memory
+    pub fn classify(required_gb: f64, total_gb: f64, available_gb: f64) -> Self {
+        if available_gb >= required_gb {
+            MemoryStatus::Sufficient
+        } else if total_gb >= required_gb {
+            MemoryStatus::Tight
+        } else {
+            MemoryStatus::Insufficient
+        }
+    }
+}
+
 impl MemoryAvailability {
     /// Get user-friendly recommendations based on memory status
     #[allow(dead_code)] // Placeholder utility for future use
@@ -128,6 +131,332 @@ impl MemoryAvailability {
     }
 }
 
+/// Estimate peak memory for one vision job, in bytes
+///
+/// Counts the fully decoded source image (RGBA), the resized working copy the
+/// backend receives, and the per-job model delta (KV cache, image embeddings).
+#[allow(dead_code)] // Only used by vision builds
+pub fn estimate_vision_job_bytes(
+    source_width: u32,
+    source_height: u32,
+    max_working_pixels: u64,
+    model_delta_bytes: u64,
+) -> u64 {
+    const BYTES_PER_PIXEL: u64 = 4;
+    let source_pixels = source_width as u64 * source_height as u64;
+    let working_pixels = source_pixels.min(max_working_pixels);
+    source_pixels * BYTES_PER_PIXEL + working_pixels * BYTES_PER_PIXEL + model_delta_bytes
+}
+
+/// Request-scoped admission control against available system memory
+///
+/// Jobs reserve their estimated footprint before running; reservations are
+/// released when the returned [`MemoryReservation`] is dropped. A job that
+/// does not fit waits for running jobs to finish, up to `queue_timeout`.
+#[derive(Debug)]
+#[allow(dead_code)] // Only used by vision builds
+pub struct MemoryAdmission {
+    reserved: std::sync::Mutex<u64>,
+    released: tokio::sync::Notify,
+}
+
+/// Outstanding reservation; releases its bytes on drop
+#[derive(Debug)]
+#[allow(dead_code)] // Only used by vision builds
+pub struct MemoryReservation {
+    admission: &'static MemoryAdmission,
+    bytes: u64,
+}
+
+/// Why a job was not admitted
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)] // Only used by vision builds
+pub struct AdmissionRejected {
+    pub required_bytes: u64,
+    pub available_bytes: u64,
+}
+
+impl Default for MemoryAdmission {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(dead_code)] // Only used by vision builds
+impl MemoryAdmission {
+    pub const fn new() -> Self {
+        Self {
+            reserved: std::sync::Mutex::new(0),
+            released: tokio::sync::Notify::const_new(),
+        }
+    }
+
+    /// Process-wide admission controller
+    pub fn global() -> &'static MemoryAdmission {
+        static GLOBAL: MemoryAdmission = MemoryAdmission::new();
+        &GLOBAL
+    }
+
+    /// Bytes currently reserved by running jobs
+    pub fn reserved_bytes(&self) -> u64 {
+        *self.reserved.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Reserve `bytes` if they fit in `available` minus outstanding reservations
+    pub fn try_reserve(
+        &'static self,
+        bytes: u64,
+        available: u64,
+    ) -> Result<MemoryReservation, AdmissionRejected> {
+        let mut reserved = self.reserved.lock().unwrap_or_else(|e| e.into_inner());
+        let free = available.saturating_sub(*reserved);
+        if bytes > free {
+            return Err(AdmissionRejected {
+                required_bytes: bytes,
+                available_bytes: free,
+            });
+        }
+        *reserved += bytes;
+        Ok(MemoryReservation {
+            admission: self,
+            bytes,
+        })
+    }
+
+    /// Reserve `bytes`, queuing behind running jobs for up to `queue_timeout`
+    pub async fn acquire(
+        &'static self,
+        bytes: u64,
+        queue_timeout: std::time::Duration,
+    ) -> Result<MemoryReservation, AdmissionRejected> {
+        let deadline = tokio::time::Instant::now() + queue_timeout;
+        loop {
+            // Register interest before checking so a release in between isn't missed
+            let released = self.released.notified();
+            // Nothing to guard against when the build can't read memory
+            let available = sample_memory().map_or(u64::MAX, |s| s.available_bytes);
+            let err = match self.try_reserve(bytes, available) {
+                Ok(reservation) => return Ok(reservation),
+                Err(err) => err,
+            };
+
+            // Nothing to wait for: the job can't fit even with no other jobs running
+            if self.reserved_bytes() == 0 {
+                return Err(err);
+            }
+            if tokio::time::timeout_at(deadline, released).await.is_err() {
+                return Err(err);
+            }
+        }
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        let mut reserved = self
+            .admission
+            .reserved
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *reserved = reserved.saturating_sub(self.bytes);
+        drop(reserved);
+        self.admission.released.notify_waiters();
+    }
+}
+
+/// Point-in-time memory and swap reading, in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemorySample {
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+    pub available_bytes: u64,
+    pub swap_total_bytes: u64,
+    pub swap_used_bytes: u64,
+}
+
+/// Sample current memory and swap usage; `None` without the `sysinfo` feature
+pub fn sample_memory() -> Option<MemorySample> {
+    #[cfg(feature = "sysinfo")]
+    {
+        let mut system = sysinfo::System::new();
+        system.refresh_memory();
+        Some(MemorySample {
+            total_bytes: system.total_memory(),
+            free_bytes: system.free_memory(),
+            available_bytes: system.available_memory(),
+            swap_total_bytes: system.total_swap(),
+            swap_used_bytes: system.used_swap(),
+        })
+    }
+    #[cfg(not(feature = "sysinfo"))]
+    {
+        None
+    }
+}
+
+/// Available memory below this fraction of total counts as pressure
+const PRESSURE_AVAILABLE_RATIO: f64 = 0.10;
+/// Swap growth between samples that counts as active swapping
+const SWAP_GROWTH_BYTES: u64 = 64 * 1024 * 1024;
+/// Consecutive thrashing samples before reporting degraded
+const DEGRADE_AFTER_SAMPLES: u32 = 2;
+/// Consecutive healthy samples before clearing degraded
+const RECOVER_AFTER_SAMPLES: u32 = 3;
+
+/// Detects swap thrashing from consecutive samples
+///
+/// A sample is thrashing when available memory is under 10% of total and swap
+/// usage grew by at least 64 MB since the previous sample. Hysteresis keeps
+/// one noisy sample from flapping readiness.
+#[derive(Debug, Default)]
+pub struct ThrashingDetector {
+    previous: Option<MemorySample>,
+    thrashing_streak: u32,
+    healthy_streak: u32,
+    degraded: bool,
+}
+
+impl ThrashingDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a sample; returns whether the system is currently degraded
+    pub fn observe(&mut self, sample: MemorySample) -> bool {
+        let thrashing = match self.previous {
+            Some(prev) => Self::is_thrashing_sample(prev, sample),
+            None => false,
+        };
+        self.previous = Some(sample);
+
+        if thrashing {
+            self.thrashing_streak += 1;
+            self.healthy_streak = 0;
+            if self.thrashing_streak >= DEGRADE_AFTER_SAMPLES {
+                self.degraded = true;
+            }
+        } else {
+            self.healthy_streak += 1;
+            self.thrashing_streak = 0;
+            if self.healthy_streak >= RECOVER_AFTER_SAMPLES {
+                self.degraded = false;
+            }
+        }
+        self.degraded
+    }
+
+    fn is_thrashing_sample(prev: MemorySample, cur: MemorySample) -> bool {
+        if cur.total_bytes == 0 || cur.swap_total_bytes == 0 {
+            return false;
+        }
+        let available_ratio = cur.available_bytes as f64 / cur.total_bytes as f64;
+        let swap_growth = cur.swap_used_bytes.saturating_sub(prev.swap_used_bytes);
+        available_ratio < PRESSURE_AVAILABLE_RATIO && swap_growth >= SWAP_GROWTH_BYTES
+    }
+}
+
+/// Background memory-pressure monitor backing `/readyz`
+#[derive(Debug)]
+pub struct MemoryPressureMonitor {
+    degraded: std::sync::atomic::AtomicBool,
+    started: std::sync::atomic::AtomicBool,
+}
+
+impl MemoryPressureMonitor {
+    const fn new() -> Self {
+        Self {
+            degraded: std::sync::atomic::AtomicBool::new(false),
+            started: std::sync::atomic::AtomicBool::new(false),
+        }
+    }
+
+    /// Process-wide monitor
+    pub fn global() -> &'static MemoryPressureMonitor {
+        static GLOBAL: MemoryPressureMonitor = MemoryPressureMonitor::new();
+        &GLOBAL
+    }
+
+    /// Whether swap thrashing is currently detected
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Whether new work should be held back while degraded
+    /// (`SHIMMY_PAUSE_ON_THRASHING=1`)
+    pub fn should_pause(&self) -> bool {
+        self.is_degraded()
+            && std::env::var("SHIMMY_PAUSE_ON_THRASHING")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false)
+    }
+
+    /// Start sampling on a background task; later calls are no-ops
+    pub fn spawn(&'static self, interval: std::time::Duration) {
+        if self.started.swap(true, std::sync::atomic::Ordering::SeqCst) {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut detector = ThrashingDetector::new();
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let sample = match tokio::task::spawn_blocking(sample_memory).await {
+                    Ok(Some(sample)) => sample,
+                    _ => continue,
+                };
+                let degraded = detector.observe(sample);
+                let was_degraded = self
+                    .degraded
+                    .swap(degraded, std::sync::atomic::Ordering::Relaxed);
+
+                if degraded && !was_degraded {
+                    Self::warn_thrashing(sample);
+                } else if !degraded && was_degraded {
+                    tracing::info!("Memory pressure subsided; readiness restored");
+                }
+            }
+        });
+    }
+
+    fn warn_thrashing(sample: MemorySample) {
+        let to_gb = |bytes: u64| bytes as f64 / 1_024_000_000.0;
+        let availability = MemoryAvailability {
+            total_gb: to_gb(sample.total_bytes),
+            available_gb: to_gb(sample.available_bytes),
+            required_gb: 0.0,
+            status: MemoryStatus::Tight,
+        };
+        tracing::warn!(
+            available_gb = availability.available_gb,
+            swap_used_gb = to_gb(sample.swap_used_bytes),
+            "Swap thrashing detected; marking /readyz degraded. {}",
+            availability.get_recommendations().join(" ")
+        );
+    }
+}
+
+/// Total VRAM of the largest GPU, in bytes
+///
+/// Only NVIDIA is probed (via `nvidia-smi`); other vendors return `None`.
+pub fn detect_vram_bytes() -> Option<u64> {
+    let output = std::process::Command::new("nvidia-smi")
+        .args(["--query-gpu=memory.total", "--format=csv,noheader,nounits"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    parse_nvidia_smi_memory(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse `nvidia-smi` memory.total output (MiB per line) into the largest value in bytes
+fn parse_nvidia_smi_memory(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().parse::<u64>().ok())
+        .max()
+        .map(|mib| mib * 1024 * 1024)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -184,4 +513,90 @@ mod tests {
         assert!(recommendations.iter().any(|r| r.contains("smaller model")));
         assert!(recommendations.iter().any(|r| r.contains("Add more RAM")));
     }
+
+    #[test]
+    fn test_vision_job_estimate_caps_working_copy() {
+        let small = estimate_vision_job_bytes(100, 100, 1_000_000, 0);
+        assert_eq!(small, 100 * 100 * 4 * 2);
+
+        let big = estimate_vision_job_bytes(4000, 3000, 1_000_000, 512);
+        assert_eq!(big, 12_000_000 * 4 + 1_000_000 * 4 + 512);
+    }
+
+    #[test]
+    fn test_admission_reserves_and_releases() {
+        static ADMISSION: MemoryAdmission = MemoryAdmission::new();
+
+        let first = ADMISSION.try_reserve(600, 1000).unwrap();
+        let rejected = ADMISSION.try_reserve(600, 1000).unwrap_err();
+        assert_eq!(rejected.required_bytes, 600);
+        assert_eq!(rejected.available_bytes, 400);
+        assert_eq!(ADMISSION.reserved_bytes(), 600);
+
+        drop(first);
+        assert_eq!(ADMISSION.reserved_bytes(), 0);
+        assert!(ADMISSION.try_reserve(600, 1000).is_ok());
+    }
+
+    fn sample(available_mb: u64, swap_used_mb: u64) -> MemorySample {
+        MemorySample {
+            total_bytes: 16_000 * 1024 * 1024,
+            free_bytes: available_mb * 1024 * 1024,
+            available_bytes: available_mb * 1024 * 1024,
+            swap_total_bytes: 8_000 * 1024 * 1024,
+            swap_used_bytes: swap_used_mb * 1024 * 1024,
+        }
+    }
+
+    #[test]
+    fn test_thrashing_detector_needs_consecutive_samples() {
+        let mut detector = ThrashingDetector::new();
+        assert!(!detector.observe(sample(500, 1000)));
+        // One growing-swap sample under pressure isn't enough
+        assert!(!detector.observe(sample(500, 1200)));
+        assert!(detector.observe(sample(500, 1400)));
+
+        // Stays degraded until several healthy samples in a row
+        assert!(detector.observe(sample(8000, 1400)));
+        assert!(detector.observe(sample(8000, 1400)));
+        assert!(!detector.observe(sample(8000, 1400)));
+    }
+
+    #[test]
+    fn test_swap_growth_without_pressure_is_not_thrashing() {
+        let mut detector = ThrashingDetector::new();
+        for swap in [1000, 2000, 3000, 4000] {
+            assert!(!detector.observe(sample(8000, swap)));
+        }
+    }
+
+    #[test]
+    fn test_memory_status_verdicts() {
+        assert_eq!(
+            MemoryStatus::classify(4.0, 16.0, 8.0),
+            MemoryStatus::Sufficient
+        );
+        assert_eq!(MemoryStatus::classify(12.0, 16.0, 8.0), MemoryStatus::Tight);
+        assert_eq!(
+            MemoryStatus::classify(32.0, 16.0, 8.0),
+            MemoryStatus::Insufficient
+        );
+
+        let json = serde_json::to_value([
+            MemoryStatus::Sufficient,
+            MemoryStatus::Tight,
+            MemoryStatus::Insufficient,
+        ])
+        .unwrap();
+        assert_eq!(json, serde_json::json!(["fits", "tight", "insufficient"]));
+    }
+
+    #[test]
+    fn test_parse_nvidia_smi_memory_takes_largest_gpu() {
+        assert_eq!(
+            parse_nvidia_smi_memory("8192\n24576\n"),
+            Some(24576 * 1024 * 1024)
+        );
+        assert_eq!(parse_nvidia_smi_memory("N/A\n"), None);
+    }
 }
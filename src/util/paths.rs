@@ -0,0 +1,82 @@
+/// State directory resolution
+///
+/// Everything Shimmy persists (vision license cache, usage stats, downloaded
+/// vision models) lives under a single data directory so containerized and
+/// multi-user deployments can relocate it to a mounted volume.
+///
+/// Resolution order:
+/// 1. `SHIMMY_DATA_DIR` environment variable, or `data_dir` in `shimmy.toml`
+///    (see [`crate::setup`])
+/// 2. The default baked into OEM builds (`SHIMMY_OEM_DATA_DIR`, see
+///    [`crate::branding`])
+/// 3. The platform data directory joined with `shimmy`
+/// 4. The system temp directory joined with `shimmy` (no home directory,
+///    e.g. service accounts or minimal containers)
+///
+/// The override is read once, after `shimmy.toml` has been applied, so every
+/// caller in a process agrees on where state lives.
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Data directory variable, also set from `data_dir` in `shimmy.toml`
+pub const DATA_DIR_ENV: &str = "SHIMMY_DATA_DIR";
+
+/// Data directory named by a `SHIMMY_DATA_DIR` value; blank means unset
+fn configured_data_dir(value: Option<String>) -> Option<PathBuf> {
+    value.filter(|d| !d.trim().is_empty()).map(PathBuf::from)
+}
+
+/// Explicitly configured data directory, if any
+pub fn data_dir_override() -> Option<PathBuf> {
+    static OVERRIDE: OnceLock<Option<PathBuf>> = OnceLock::new();
+    OVERRIDE
+        .get_or_init(|| configured_data_dir(std::env::var(DATA_DIR_ENV).ok()))
+        .clone()
+}
+
+/// Data directory set at build time, if any
+fn build_data_dir() -> Option<PathBuf> {
+    crate::branding::DEFAULT_DATA_DIR.map(PathBuf::from)
+}
+
+/// Root directory for persistent Shimmy state
+pub fn data_dir() -> PathBuf {
+    data_dir_override()
+        .or_else(build_data_dir)
+        .unwrap_or_else(|| {
+            dirs::data_dir()
+                .unwrap_or_else(std::env::temp_dir)
+                .join(crate::branding::APP_NAME)
+        })
+}
+
+/// Root directory for large machine-local files (downloaded models)
+///
+/// Same overrides as [`data_dir`]; without one it prefers the non-roaming
+/// local data directory.
+pub fn local_data_dir() -> PathBuf {
+    data_dir_override()
+        .or_else(build_data_dir)
+        .unwrap_or_else(|| {
+            dirs::data_local_dir()
+                .or_else(dirs::cache_dir)
+                .or_else(dirs::data_dir)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(crate::branding::APP_NAME)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shimmy_data_dir_overrides_default() {
+        assert_eq!(
+            configured_data_dir(Some("/tmp/shimmy-data-test".to_string())),
+            Some(PathBuf::from("/tmp/shimmy-data-test"))
+        );
+        assert_eq!(configured_data_dir(Some("  ".to_string())), None);
+        assert_eq!(configured_data_dir(None), None);
+    }
+}
@@ -0,0 +1,109 @@
+/// Minimal ZIP archive writer
+///
+/// Entries are stored uncompressed: bundles written this way are small text
+/// files, and every unzip tool (and GitHub's attachment upload) accepts the
+/// stored method without pulling in a compression dependency.
+use std::io::Write;
+
+/// CRC-32 (IEEE) as required by the ZIP format
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Write `entries` (name, contents) as a ZIP archive
+pub fn write_stored<W: Write>(out: &mut W, entries: &[(String, Vec<u8>)]) -> std::io::Result<()> {
+    let too_big = |what| std::io::Error::new(std::io::ErrorKind::InvalidInput, what);
+    let mut offset = 0u32;
+    let mut central = Vec::new();
+    for (name, data) in entries {
+        let name = name.as_bytes();
+        let size = u32::try_from(data.len()).map_err(|_| too_big("entry larger than 4 GiB"))?;
+        let name_len = u16::try_from(name.len()).map_err(|_| too_big("entry name too long"))?;
+        let crc = crc32(data);
+
+        let mut local = Vec::with_capacity(30 + name.len());
+        local.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        local.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        local.extend_from_slice(&0x0800u16.to_le_bytes()); // UTF-8 names
+        local.extend_from_slice(&0u16.to_le_bytes()); // stored
+        local.extend_from_slice(&0u32.to_le_bytes()); // DOS time and date
+        local.extend_from_slice(&crc.to_le_bytes());
+        local.extend_from_slice(&size.to_le_bytes());
+        local.extend_from_slice(&size.to_le_bytes());
+        local.extend_from_slice(&name_len.to_le_bytes());
+        local.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        local.extend_from_slice(name);
+        out.write_all(&local)?;
+        out.write_all(data)?;
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central.extend_from_slice(&local[4..30]);
+        central.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        central.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        central.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        central.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name);
+
+        offset = (local.len() + data.len())
+            .try_into()
+            .ok()
+            .and_then(|len: u32| offset.checked_add(len))
+            .ok_or_else(|| too_big("archive larger than 4 GiB"))?;
+    }
+    let count = u16::try_from(entries.len()).map_err(|_| too_big("too many entries"))?;
+    let central_len = u32::try_from(central.len()).map_err(|_| too_big("central directory"))?;
+    out.write_all(&central)?;
+
+    let mut end = Vec::with_capacity(22);
+    end.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes()); // this disk
+    end.extend_from_slice(&0u16.to_le_bytes()); // disk with central directory
+    end.extend_from_slice(&count.to_le_bytes());
+    end.extend_from_slice(&count.to_le_bytes());
+    end.extend_from_slice(&central_len.to_le_bytes());
+    end.extend_from_slice(&offset.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    out.write_all(&end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_archive_layout() {
+        let entries = vec![
+            ("a.txt".to_string(), b"hello".to_vec()),
+            ("b.json".to_string(), b"{}".to_vec()),
+        ];
+        let mut zip = Vec::new();
+        write_stored(&mut zip, &entries).unwrap();
+
+        assert_eq!(&zip[..4], b"PK\x03\x04");
+        assert_eq!(&zip[30..35], b"a.txt");
+        assert_eq!(&zip[35..40], b"hello");
+        // End of central directory: two entries, central directory right after the data
+        let end = &zip[zip.len() - 22..];
+        assert_eq!(&end[..4], b"PK\x05\x06");
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+        let central_offset = u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize;
+        assert_eq!(central_offset, 30 + 5 + 5 + 30 + 6 + 2);
+        assert_eq!(&zip[central_offset..central_offset + 4], b"PK\x01\x02");
+    }
+}
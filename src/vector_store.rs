@@ -0,0 +1,612 @@
+//! Tiny built-in vector store for RAG prototypes.
+//!
+//! Collections hold vectors with optional text and metadata, searched by
+//! brute-force cosine similarity — fine for the tens of thousands of chunks a
+//! prototype indexes, and no separate vector DB to run. Each collection is a
+//! flat JSON-lines file under `<data_dir>/vectors/`, appended to on every add
+//! and read back lazily on first use; a later record with the same id
+//! replaces the earlier one.
+//!
+//! Items and queries may carry `text` plus a `model` instead of a vector, in
+//! which case they are embedded with that model first.
+
+use crate::error::ShimmyError;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_TOP_K: usize = 5;
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum VectorStoreError {
+    #[error("Collection not found: {name}")]
+    CollectionNotFound { name: String },
+
+    #[error("Collection already exists: {name}")]
+    CollectionExists { name: String },
+
+    #[error("Invalid collection name '{name}': use 1-64 letters, digits, '-' or '_'")]
+    InvalidName { name: String },
+
+    #[error("Vector has {got} dimensions, collection expects {expected}")]
+    DimensionMismatch { expected: usize, got: usize },
+
+    #[error("Vector store I/O failed")]
+    Io(#[from] std::io::Error),
+}
+
+impl VectorStoreError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            VectorStoreError::CollectionNotFound { .. } => "COLLECTION_NOT_FOUND",
+            VectorStoreError::CollectionExists { .. } => "COLLECTION_EXISTS",
+            VectorStoreError::InvalidName { .. } => "INVALID_REQUEST",
+            VectorStoreError::DimensionMismatch { .. } => "DIMENSION_MISMATCH",
+            VectorStoreError::Io(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            VectorStoreError::CollectionNotFound { .. } => StatusCode::NOT_FOUND,
+            VectorStoreError::CollectionExists { .. } => StatusCode::CONFLICT,
+            VectorStoreError::InvalidName { .. } | VectorStoreError::DimensionMismatch { .. } => {
+                StatusCode::BAD_REQUEST
+            }
+            VectorStoreError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// One stored vector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VectorRecord {
+    pub id: String,
+    pub vector: Vec<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub metadata: serde_json::Value,
+}
+
+/// A line of a collection file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Record(VectorRecord),
+    Header { dimensions: usize },
+}
+
+#[derive(Debug, Default)]
+struct Collection {
+    dimensions: Option<usize>,
+    records: Vec<VectorRecord>,
+    index: HashMap<String, usize>,
+}
+
+impl Collection {
+    fn upsert(&mut self, record: VectorRecord) {
+        match self.index.get(&record.id) {
+            Some(&i) => self.records[i] = record,
+            None => {
+                self.index.insert(record.id.clone(), self.records.len());
+                self.records.push(record);
+            }
+        }
+    }
+}
+
+/// Summary of a collection for listings
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CollectionInfo {
+    pub name: String,
+    pub dimensions: Option<usize>,
+    pub count: usize,
+}
+
+/// A query hit
+#[derive(Debug, Clone, Serialize)]
+pub struct VectorMatch {
+    pub id: String,
+    pub score: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    pub metadata: serde_json::Value,
+}
+
+/// Cosine similarity; 0 when either vector is all zeros
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+pub struct VectorStore {
+    dir: PathBuf,
+    collections: Mutex<HashMap<String, Collection>>,
+}
+
+impl VectorStore {
+    /// Store rooted at `dir`; nothing is read until a collection is used
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            collections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.jsonl"))
+    }
+
+    fn check_name(name: &str) -> Result<(), VectorStoreError> {
+        let valid = !name.is_empty()
+            && name.len() <= MAX_NAME_LEN
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if valid {
+            Ok(())
+        } else {
+            Err(VectorStoreError::InvalidName {
+                name: name.to_string(),
+            })
+        }
+    }
+
+    /// Read a collection file; a truncated last line (crash mid-append) is skipped
+    fn read_collection(&self, name: &str) -> Result<Option<Collection>, VectorStoreError> {
+        let file = match std::fs::File::open(self.path(name)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut collection = Collection::default();
+        for (n, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Line>(&line) {
+                Ok(Line::Header { dimensions }) => collection.dimensions = Some(dimensions),
+                Ok(Line::Record(record)) => {
+                    collection.dimensions.get_or_insert(record.vector.len());
+                    collection.upsert(record);
+                }
+                Err(e) => tracing::warn!(
+                    "Skipping unreadable line {} of vector collection '{}': {}",
+                    n + 1,
+                    name,
+                    e
+                ),
+            }
+        }
+        Ok(Some(collection))
+    }
+
+    /// Run `f` on a collection, loading it from disk on first use
+    fn with_collection<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut Collection) -> Result<T, VectorStoreError>,
+    ) -> Result<T, VectorStoreError> {
+        Self::check_name(name)?;
+        let mut collections = self.collections.lock().unwrap_or_else(|e| e.into_inner());
+        if !collections.contains_key(name) {
+            let collection = self.read_collection(name)?.ok_or_else(|| {
+                VectorStoreError::CollectionNotFound {
+                    name: name.to_string(),
+                }
+            })?;
+            collections.insert(name.to_string(), collection);
+        }
+        f(collections
+            .get_mut(name)
+            .expect("collection was just loaded"))
+    }
+
+    fn append(&self, name: &str, lines: &[Line]) -> Result<(), VectorStoreError> {
+        let mut out = String::new();
+        for line in lines {
+            out.push_str(&serde_json::to_string(line).map_err(std::io::Error::other)?);
+            out.push('\n');
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(self.path(name))?;
+        file.write_all(out.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn create(&self, name: &str, dimensions: Option<usize>) -> Result<(), VectorStoreError> {
+        Self::check_name(name)?;
+        std::fs::create_dir_all(&self.dir)?;
+        let mut file = match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.path(name))
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(VectorStoreError::CollectionExists {
+                    name: name.to_string(),
+                })
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(dimensions) = dimensions {
+            let header = serde_json::to_string(&Line::Header { dimensions })
+                .map_err(std::io::Error::other)?;
+            writeln!(file, "{header}")?;
+        }
+        let collection = Collection {
+            dimensions,
+            ..Default::default()
+        };
+        self.collections
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), collection);
+        Ok(())
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), VectorStoreError> {
+        Self::check_name(name)?;
+        self.collections
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(name);
+        match std::fs::remove_file(self.path(name)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(VectorStoreError::CollectionNotFound {
+                    name: name.to_string(),
+                })
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<CollectionInfo>, VectorStoreError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.strip_suffix(".jsonl").map(str::to_string)
+            })
+            .filter(|name| Self::check_name(name).is_ok())
+            .collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| {
+                self.with_collection(&name, |c| {
+                    Ok(CollectionInfo {
+                        name: name.clone(),
+                        dimensions: c.dimensions,
+                        count: c.records.len(),
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Insert or replace records; returns the collection size afterwards
+    pub fn add(&self, name: &str, records: Vec<VectorRecord>) -> Result<usize, VectorStoreError> {
+        self.with_collection(name, |collection| {
+            let expected = collection
+                .dimensions
+                .or_else(|| records.first().map(|r| r.vector.len()));
+            if let Some(expected) = expected {
+                if let Some(bad) = records.iter().find(|r| r.vector.len() != expected) {
+                    return Err(VectorStoreError::DimensionMismatch {
+                        expected,
+                        got: bad.vector.len(),
+                    });
+                }
+            }
+            let lines: Vec<Line> = records.iter().cloned().map(Line::Record).collect();
+            self.append(name, &lines)?;
+            collection.dimensions = expected;
+            for record in records {
+                collection.upsert(record);
+            }
+            Ok(collection.records.len())
+        })
+    }
+
+    /// The `top_k` records most similar to `vector`, best first
+    pub fn query(
+        &self,
+        name: &str,
+        vector: &[f32],
+        top_k: usize,
+    ) -> Result<Vec<VectorMatch>, VectorStoreError> {
+        self.with_collection(name, |collection| {
+            if let Some(expected) = collection.dimensions {
+                if vector.len() != expected {
+                    return Err(VectorStoreError::DimensionMismatch {
+                        expected,
+                        got: vector.len(),
+                    });
+                }
+            }
+            let mut scored: Vec<(f32, &VectorRecord)> = collection
+                .records
+                .iter()
+                .map(|r| (cosine_similarity(vector, &r.vector), r))
+                .collect();
+            scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+            Ok(scored
+                .into_iter()
+                .take(top_k)
+                .map(|(score, r)| VectorMatch {
+                    id: r.id.clone(),
+                    score,
+                    text: r.text.clone(),
+                    metadata: r.metadata.clone(),
+                })
+                .collect())
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCollectionRequest {
+    pub name: String,
+    #[serde(default)]
+    pub dimensions: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddItem {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub vector: Option<Vec<f32>>,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddRequest {
+    /// Embedding model for items given as text
+    #[serde(default)]
+    pub model: Option<String>,
+    pub items: Vec<AddItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryRequest {
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub vector: Option<Vec<f32>>,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub top_k: Option<usize>,
+}
+
+fn missing_vector(param: &'static str) -> ShimmyError {
+    crate::engine::InvalidParameter::new(
+        param,
+        "give a `vector`, or `text` together with an embedding `model`",
+    )
+    .into()
+}
+
+pub async fn list_collections(State(state): State<Arc<AppState>>) -> Response {
+    match state.vector_store.list() {
+        Ok(collections) => Json(serde_json::json!({ "collections": collections })).into_response(),
+        Err(e) => ShimmyError::from(e).into_response(),
+    }
+}
+
+pub async fn create_collection(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateCollectionRequest>,
+) -> Response {
+    match state.vector_store.create(&req.name, req.dimensions) {
+        Ok(()) => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({ "name": req.name, "dimensions": req.dimensions })),
+        )
+            .into_response(),
+        Err(e) => ShimmyError::from(e).into_response(),
+    }
+}
+
+pub async fn delete_collection(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response {
+    match state.vector_store.delete(&name) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => ShimmyError::from(e).into_response(),
+    }
+}
+
+pub async fn add_vectors(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(req): Json<AddRequest>,
+) -> Response {
+    // Embed every text-only item in one batch
+    let to_embed: Vec<String> = req
+        .items
+        .iter()
+        .filter(|item| item.vector.is_none())
+        .map(|item| item.text.clone())
+        .collect::<Option<_>>()
+        .unwrap_or_default();
+    let missing = req
+        .items
+        .iter()
+        .filter(|item| item.vector.is_none())
+        .count();
+    if missing > to_embed.len() {
+        return missing_vector("items").into_response();
+    }
+    let mut embedded = if to_embed.is_empty() {
+        Vec::new()
+    } else {
+        let Some(model) = req.model.as_deref() else {
+            return missing_vector("items").into_response();
+        };
+        match crate::embeddings::embed_texts(&state, model, &to_embed).await {
+            Ok(vectors) => vectors,
+            Err(e) => return e.into_response(),
+        }
+    }
+    .into_iter();
+
+    let records = req
+        .items
+        .into_iter()
+        .map(|item| VectorRecord {
+            id: item.id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            vector: item
+                .vector
+                .unwrap_or_else(|| embedded.next().unwrap_or_default()),
+            text: item.text,
+            metadata: item.metadata,
+        })
+        .collect::<Vec<_>>();
+    let ids: Vec<String> = records.iter().map(|r| r.id.clone()).collect();
+
+    match state.vector_store.add(&name, records) {
+        Ok(count) => Json(serde_json::json!({ "ids": ids, "count": count })).into_response(),
+        Err(e) => ShimmyError::from(e).into_response(),
+    }
+}
+
+pub async fn query_vectors(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(req): Json<QueryRequest>,
+) -> Response {
+    let vector = match (req.vector, req.text, req.model.as_deref()) {
+        (Some(vector), _, _) => vector,
+        (None, Some(text), Some(model)) => {
+            match crate::embeddings::embed_texts(&state, model, &[text]).await {
+                Ok(mut vectors) => vectors.pop().unwrap_or_default(),
+                Err(e) => return e.into_response(),
+            }
+        }
+        _ => return missing_vector("vector").into_response(),
+    };
+    let top_k = req.top_k.unwrap_or(DEFAULT_TOP_K);
+    match state.vector_store.query(&name, &vector, top_k) {
+        Ok(matches) => Json(serde_json::json!({ "matches": matches })).into_response(),
+        Err(e) => ShimmyError::from(e).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, vector: &[f32]) -> VectorRecord {
+        VectorRecord {
+            id: id.to_string(),
+            vector: vector.to_vec(),
+            text: Some(format!("text of {id}")),
+            metadata: serde_json::Value::Null,
+        }
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_add_query_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = VectorStore::new(dir.path().to_path_buf());
+        store.create("docs", None).unwrap();
+        store
+            .add(
+                "docs",
+                vec![
+                    record("a", &[1.0, 0.0]),
+                    record("b", &[0.0, 1.0]),
+                    record("c", &[0.7, 0.7]),
+                ],
+            )
+            .unwrap();
+        // Same id replaces the earlier record
+        let count = store.add("docs", vec![record("b", &[0.0, -1.0])]).unwrap();
+        assert_eq!(count, 3);
+
+        let ids = |matches: Vec<VectorMatch>| matches.into_iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(store.query("docs", &[1.0, 0.1], 2).unwrap()),
+            vec!["a", "c"]
+        );
+
+        // A fresh store reads the same state back from the flat file
+        let reopened = VectorStore::new(dir.path().to_path_buf());
+        let hits = reopened.query("docs", &[0.0, -1.0], 1).unwrap();
+        assert_eq!(hits[0].id, "b");
+        assert_eq!(hits[0].text.as_deref(), Some("text of b"));
+        assert_eq!(
+            reopened.list().unwrap(),
+            vec![CollectionInfo {
+                name: "docs".to_string(),
+                dimensions: Some(2),
+                count: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = VectorStore::new(dir.path().to_path_buf());
+        store.create("fixed", Some(3)).unwrap();
+
+        let err = store
+            .add("fixed", vec![record("a", &[1.0, 0.0])])
+            .unwrap_err();
+        assert_eq!(err.code(), "DIMENSION_MISMATCH");
+        let err = store.create("fixed", None).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::CONFLICT);
+        let err = store.query("missing", &[1.0], 1).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::NOT_FOUND);
+        let err = store.create("../escape", None).unwrap_err();
+        assert_eq!(err.code(), "INVALID_REQUEST");
+
+        store.delete("fixed").unwrap();
+        assert!(store.list().unwrap().is_empty());
+    }
+}
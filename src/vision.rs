@@ -3,6 +3,8 @@
 //! Feature-gated vision capabilities for image and web analysis.
 //! Mirrors Seer functionality with structured JSON output.
 
+#[cfg(feature = "vision")]
+use crate::error::ShimmyError;
 #[cfg(feature = "vision")]
 use base64::{engine::general_purpose, Engine as _};
 #[cfg(feature = "vision")]
@@ -20,6 +22,10 @@ use tracing::info;
 #[cfg(feature = "vision")]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VisionResponse {
+    /// Version of this shape, see [`SCHEMA_VERSION`]; responses cached before
+    /// versioning deserialize as version 1
+    #[serde(default = "first_schema_version")]
+    pub schema_version: u32,
     pub image_path: Option<String>,
     pub url: Option<String>,
     pub mode: String,
@@ -28,8 +34,75 @@ pub struct VisionResponse {
     pub visual: Visual,
     pub interaction: Interaction,
     pub dom_map: Option<Vec<DomElement>>,
+    /// Objects and UI elements located by `mode: "detect"`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub detections: Vec<Detection>,
+    /// Values of the fields a `custom:<name>` mode defines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<serde_json::Map<String, serde_json::Value>>,
+    /// One analysis per frame sampled from a `video` request, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<Keyframe>,
     pub meta: Meta,
     pub raw_model_output: Option<String>,
+    /// Set when the license expires soon (see `SHIMMY_LICENSE_WARNING_DAYS`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license_warning: Option<crate::vision_license::LicenseWarning>,
+}
+
+/// Version of the [`VisionResponse`] shape, bumped whenever a field is
+/// removed, renamed or changes meaning. Adding a field doesn't bump it.
+#[cfg(feature = "vision")]
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Versions a client may ask for, oldest first
+#[cfg(feature = "vision")]
+pub const SUPPORTED_SCHEMA_VERSIONS: &[u32] = &[1];
+
+/// Request header listing the response schema versions a client understands
+#[cfg(feature = "vision")]
+pub const ACCEPT_SCHEMA_VERSION_HEADER: &str = "accept-schema-version";
+
+#[cfg(feature = "vision")]
+fn first_schema_version() -> u32 {
+    1
+}
+
+/// The newest supported version among the comma-separated ones in an
+/// `Accept-Schema-Version` header, e.g. `1` or `2, 1`
+#[cfg(feature = "vision")]
+pub fn negotiate_schema_version(accept: &str) -> crate::error::Result<u32> {
+    let requested = accept
+        .split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            v.parse::<u32>().map_err(|_| ShimmyError::InvalidRequest {
+                reason: format!(
+                    "{}: `{}` is not a version number",
+                    ACCEPT_SCHEMA_VERSION_HEADER, v
+                ),
+            })
+        })
+        .collect::<crate::error::Result<Vec<u32>>>()?;
+    requested
+        .iter()
+        .copied()
+        .filter(|v| SUPPORTED_SCHEMA_VERSIONS.contains(v))
+        .max()
+        .ok_or_else(|| unsupported_schema_version(accept.trim()))
+}
+
+#[cfg(feature = "vision")]
+fn unsupported_schema_version(requested: &str) -> ShimmyError {
+    let supported: Vec<String> = SUPPORTED_SCHEMA_VERSIONS
+        .iter()
+        .map(u32::to_string)
+        .collect();
+    ShimmyError::UnsupportedSchemaVersion {
+        requested: requested.to_string(),
+        supported: supported.join(", "),
+    }
 }
 
 /// Text block from OCR
@@ -38,6 +111,9 @@ pub struct VisionResponse {
 pub struct TextBlock {
     pub text: String,
     pub confidence: Option<f32>,
+    /// Where the text is, when the backend reports it (tesseract OCR)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Rect>,
 }
 
 /// Layout analysis
@@ -101,6 +177,17 @@ pub struct DomElement {
     pub colors: Option<std::collections::HashMap<String, String>>,
 }
 
+/// Object or UI element located in detect mode
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Detection {
+    /// What was found, e.g. `button: Sign in` or `dog`
+    pub label: String,
+    pub confidence: Option<f32>,
+    /// In the space `coordinates` asks for, like `dom_map` positions
+    pub position: Rect,
+}
+
 /// Rectangle for positioning
 #[cfg(feature = "vision")]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -111,6 +198,93 @@ pub struct Rect {
     pub height: f32,
 }
 
+/// Coordinate space for `Rect` values in responses
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Coordinates {
+    /// Fractions of the image size (0..1)
+    #[default]
+    Normalized,
+    /// Pixels of the original (not preprocessed) image
+    Pixels,
+}
+
+/// Analysis of one frame of a `video` request
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub index: usize,
+    /// Where the frame is in the video
+    pub timestamp_ms: u64,
+    pub text_blocks: Vec<TextBlock>,
+    pub layout: Layout,
+    pub visual: Visual,
+    pub interaction: Interaction,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub detections: Vec<Detection>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<serde_json::Map<String, serde_json::Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parse_warnings: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_model_output: Option<String>,
+}
+
+#[cfg(feature = "vision")]
+impl Keyframe {
+    #[cfg_attr(not(feature = "vision-video"), allow(dead_code))]
+    pub fn new(index: usize, timestamp_ms: u64, response: VisionResponse) -> Self {
+        Self {
+            index,
+            timestamp_ms,
+            text_blocks: response.text_blocks,
+            layout: response.layout,
+            visual: response.visual,
+            interaction: response.interaction,
+            detections: response.detections,
+            fields: response.fields,
+            parse_warnings: response.meta.parse_warnings,
+            raw_model_output: response.raw_model_output,
+        }
+    }
+}
+
+/// Partial results of a streaming vision request, as they are produced
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone)]
+pub enum VisionProgress {
+    /// Model output, in generation order
+    Token(String),
+    /// A frame of a `video` request, finished before the rest of the video
+    #[cfg_attr(not(feature = "vision-video"), allow(dead_code))]
+    Keyframe(Box<Keyframe>),
+}
+
+/// Receiver of [`VisionProgress`] events
+#[cfg(feature = "vision")]
+pub type ProgressSink = std::sync::Arc<dyn Fn(VisionProgress) + Send + Sync>;
+
+/// Model output callback forwarding tokens to `sink`
+#[cfg(feature = "vision")]
+fn token_sink(sink: &ProgressSink) -> Box<dyn FnMut(String) + Send> {
+    let sink = sink.clone();
+    Box::new(move |text| sink(VisionProgress::Token(text)))
+}
+
+/// How a `video` request was sampled, reported in `meta.video`
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoInfo {
+    pub fps: f32,
+    pub frames: usize,
+    /// `max_frames` was reached before the end of the video
+    pub truncated: bool,
+    /// Detected format, size and quality of the first frame
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame: Option<crate::vision_quality::ImageDiagnostics>,
+}
+
 /// Metadata
 #[cfg(feature = "vision")]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -119,6 +293,21 @@ pub struct Meta {
     pub backend: String,
     pub duration_ms: u64,
     pub parse_warnings: Option<Vec<String>>,
+    /// Set when `SHIMMY_VISION_SAFETY` screened the image first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety: Option<crate::vision_safety::SafetyCheck>,
+    /// Served from the URL cache without running the model again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Detected format, size and quality of the input image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<crate::vision_quality::ImageDiagnostics>,
+    /// The same for each of a request's `images`, in order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<crate::vision_quality::ImageDiagnostics>>,
+    /// How a `video` request's frames were sampled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video: Option<VideoInfo>,
 }
 
 /// Vision request for HTTP API
@@ -127,6 +316,17 @@ pub struct Meta {
 pub struct VisionRequest {
     pub image_base64: Option<String>,
     pub url: Option<String>,
+    /// Several images analyzed together, e.g. to compare them or read the
+    /// pages of a document; replaces `image_base64` and `url`
+    #[serde(default)]
+    pub images: Vec<ImageInput>,
+    /// A video whose sampled frames are each analyzed; replaces the other
+    /// inputs and answers with a `timeline`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video: Option<VideoInput>,
+    /// Extra headers sent when fetching `url` (e.g. a bearer token for a
+    /// private bucket); names must be allowed by `SHIMMY_VISION_URL_HEADERS`
+    pub url_headers: Option<std::collections::HashMap<String, String>>,
     pub mode: String,
     pub model: Option<String>,
     #[allow(dead_code)]
@@ -139,8 +339,43 @@ pub struct VisionRequest {
     /// Viewport dimensions for screenshot
     pub viewport_width: Option<u32>,
     pub viewport_height: Option<u32>,
+    /// Stream model output as SSE `token` events, ending with `done` or `error`
+    pub stream: Option<bool>,
+    /// Space for `dom_map` positions (default `normalized`)
+    pub coordinates: Option<Coordinates>,
+    /// Answer `202 Accepted` and POST the result here when done
+    pub callback_url: Option<String>,
+    /// [`VisionResponse`] shape to answer with (default [`SCHEMA_VERSION`]);
+    /// set from the `Accept-Schema-Version` header when one is sent
+    #[serde(default)]
+    pub schema_version: Option<u32>,
 }
 
+/// One entry of [`VisionRequest::images`]: exactly one of `image_base64` or `url`
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageInput {
+    pub image_base64: Option<String>,
+    pub url: Option<String>,
+}
+
+/// [`VisionRequest::video`]: exactly one of `video_base64` or `url`, and how
+/// to sample it, see [`crate::vision_video`]
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VideoInput {
+    pub video_base64: Option<String>,
+    pub url: Option<String>,
+    /// Frames analyzed per second of video (default 1)
+    pub fps: Option<f32>,
+    /// Stop after this many frames (default and cap `SHIMMY_VISION_MAX_FRAMES`)
+    pub max_frames: Option<usize>,
+}
+
+/// Upper bound on [`VisionRequest::images`] (default 8)
+#[cfg(feature = "vision")]
+pub const MAX_IMAGES_ENV: &str = "SHIMMY_VISION_MAX_IMAGES";
+
 /// Image preprocessing configuration
 #[cfg(feature = "vision")]
 pub struct PreprocessConfig {
@@ -189,24 +424,31 @@ pub struct PreprocessedImage {
     pub bytes: Vec<u8>,
     pub width: u32,
     pub height: u32,
+    /// Size of the image before downscaling
+    pub original_width: u32,
+    pub original_height: u32,
+    /// Format detected from the input bytes
+    pub format: Option<String>,
+    /// Measured on the downscaled image the model sees
+    pub quality: crate::vision_quality::Quality,
 }
 
 /// Stub implementation - returns feature disabled error
 #[cfg(not(feature = "vision"))]
-pub fn handle_vision_request(
-    _req: serde_json::Value,
-) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
-    Err("Vision feature not enabled".into())
+pub fn handle_vision_request(_req: serde_json::Value) -> crate::error::Result<serde_json::Value> {
+    Err(crate::error::ShimmyError::BackendNotAvailable {
+        backend: "vision".to_string(),
+    })
 }
 
 /// Real implementation placeholder
 #[cfg(feature = "vision")]
 #[allow(dead_code)]
-pub fn handle_vision_request(
-    _req: VisionRequest,
-) -> Result<VisionResponse, Box<dyn std::error::Error>> {
+pub fn handle_vision_request(_req: VisionRequest) -> crate::error::Result<VisionResponse> {
     // TODO: Implement actual vision processing
-    Err("Vision processing not yet implemented".into())
+    Err(ShimmyError::NotImplemented {
+        feature: "synchronous vision processing".to_string(),
+    })
 }
 
 /// Process vision request with actual model inference
@@ -216,59 +458,123 @@ pub async fn process_vision_request(
     model_name: &str,
     license_manager: &crate::vision_license::VisionLicenseManager,
     state: &crate::AppState,
-) -> Result<VisionResponse, Box<dyn std::error::Error>> {
+) -> crate::error::Result<VisionResponse> {
+    process_vision_request_streaming(req, model_name, license_manager, state, None).await
+}
+
+/// [`process_vision_request`], passing partial results to `on_progress` as
+/// they are produced
+#[cfg(feature = "vision")]
+pub async fn process_vision_request_streaming(
+    req: VisionRequest,
+    model_name: &str,
+    license_manager: &crate::vision_license::VisionLicenseManager,
+    state: &crate::AppState,
+    on_progress: Option<ProgressSink>,
+) -> crate::error::Result<VisionResponse> {
     let start_time = Instant::now();
 
+    let schema_version = req.schema_version.unwrap_or(SCHEMA_VERSION);
+    if !SUPPORTED_SCHEMA_VERSIONS.contains(&schema_version) {
+        return Err(unsupported_schema_version(&schema_version.to_string()));
+    }
+    let custom_mode = crate::vision_modes::global().resolve(&req.mode)?;
+
     let trace = std::env::var("SHIMMY_VISION_TRACE").is_ok();
 
-    // Check license first
-    license_manager
-        .check_vision_access(req.license.as_deref())
+    // Check license first, holding the request's place under the usage cap
+    let usage = crate::deadline::within(
+        "license check",
+        license_manager.reserve_usage(req.license.as_deref()),
+    )
+    .await??;
+
+    if let Some(video) = &req.video {
+        let mut response = process_video(
+            &req,
+            video,
+            model_name,
+            state,
+            on_progress.as_ref(),
+            start_time,
+        )
         .await?;
+        response.schema_version = schema_version;
+        usage.commit().await?;
+        response.license_warning = license_manager.license_warning().await;
+        return Ok(response);
+    }
 
-    // Record usage
-    license_manager.record_usage().await?;
+    // URL cache key and content hash of a fetched image
+    let mut cache_entry: Option<(String, String)> = None;
 
     // Load image data
-    let (raw_image_data, captured_dom) = if let Some(base64) = &req.image_base64 {
+    let (raw_images, captured_dom) = if !req.images.is_empty() {
+        (load_images(&req).await?, None)
+    } else if let Some(base64) = &req.image_base64 {
         // Decode base64 image
-        let data = general_purpose::STANDARD
-            .decode(base64)
-            .map_err(|e| format!("Failed to decode base64 image: {}", e))?;
-        (data, None)
+        let data =
+            general_purpose::STANDARD
+                .decode(base64)
+                .map_err(|e| ShimmyError::InvalidRequest {
+                    reason: format!("Failed to decode base64 image: {}", e),
+                })?;
+        (vec![data], None)
     } else if let Some(url) = &req.url {
+        let headers = request_url_headers(&req)?;
         // Enable screenshot for web mode or when explicitly requested
         let should_screenshot = req.screenshot.unwrap_or(false) || req.mode == "web";
+        if should_screenshot && headers.is_some() {
+            return Err(ShimmyError::InvalidRequest {
+                reason: "url_headers only apply to image downloads; they can't be used with web mode or screenshot capture".to_string(),
+            });
+        }
         if should_screenshot {
             // Try to capture screenshot and extract DOM
             let viewport_width = req.viewport_width.unwrap_or(1280);
             let viewport_height = req.viewport_height.unwrap_or(720);
-            match capture_screenshot_and_dom(url, viewport_width, viewport_height).await {
-                Ok((screenshot_data, dom_elements)) => (screenshot_data, Some(dom_elements)),
+            let capture = crate::deadline::within(
+                "screenshot capture",
+                capture_screenshot_and_dom(url, viewport_width, viewport_height),
+            )
+            .await?;
+            match capture {
+                Ok((screenshot_data, dom_elements)) => (vec![screenshot_data], Some(dom_elements)),
                 Err(e) => {
                     tracing::warn!(
                         "Screenshot capture failed: {}. Falling back to URL fetch.",
                         e
                     );
                     // Fall back to fetching URL as image
-                    let data = fetch_image_from_url(url).await?;
-                    (data, None)
+                    let data =
+                        crate::deadline::within("image fetch", fetch_image_from_url(url, None))
+                            .await?
+                            .map_err(fetch_error)?;
+                    (vec![data], None)
                 }
             }
         } else {
-            // Fetch image from URL
-            let data = fetch_image_from_url(url).await?;
-            (data, None)
+            // Fetch image from URL, revalidating a cached copy
+            let (key, image) =
+                crate::deadline::within("image fetch", fetch_image_cached(url, headers.as_ref()))
+                    .await?
+                    .map_err(fetch_error)?;
+            let data = image.bytes.as_ref().clone();
+            cache_entry = Some((key, image.content_hash));
+            (vec![data], None)
         }
     } else {
-        return Err("Either image_base64 or url must be provided".into());
+        return Err(ShimmyError::InvalidRequest {
+            reason: "Either image_base64 or url must be provided".to_string(),
+        });
     };
 
     if trace {
         info!(
             target: "vision",
             stage = "input",
-            bytes = raw_image_data.len(),
+            images = raw_images.len(),
+            bytes = raw_images.iter().map(Vec::len).sum::<usize>(),
             has_base64 = req.image_base64.is_some(),
             has_url = req.url.is_some(),
             mode = %req.mode,
@@ -276,6 +582,32 @@ pub async fn process_vision_request(
         );
     }
 
+    // Identical content analyzed the same way before: skip inference
+    let cache_options = serde_json::json!({
+        "model": model_name,
+        "mode": req.mode,
+        // A redefined custom mode must not be answered from the old prompt
+        "custom_mode": custom_mode,
+        "raw": req.raw,
+        "coordinates": req.coordinates,
+        "schema_version": schema_version,
+        "safety": format!("{:?}", crate::vision_safety::SafetyMode::from_env()),
+    })
+    .to_string();
+    if let Some((key, content_hash)) = &cache_entry {
+        let cache = crate::cache::url_cache::UrlCache::global();
+        if let Some(mut response) = cache.result(key, content_hash, &cache_options) {
+            if trace {
+                info!(target: "vision", stage = "cache", "vision result served from URL cache");
+            }
+            response.meta.cached = true;
+            response.schema_version = schema_version;
+            usage.commit().await?;
+            response.license_warning = license_manager.license_warning().await;
+            return Ok(response);
+        }
+    }
+
     // Preprocess image to a safe size/format for the vision backend
     // Web mode uses smaller defaults to reduce tile count for MiniCPM-V
     let preprocess_cfg = preprocess_config_for_mode(Some(req.mode.as_str()));
@@ -285,63 +617,66 @@ pub async fn process_vision_request(
         preprocess_cfg.max_long_edge,
         preprocess_cfg.max_pixels
     );
-    tracing::error!("About to preprocess image: {} bytes", raw_image_data.len());
-    let preprocessed = preprocess_image(&raw_image_data, &preprocess_cfg)
-        .map_err(|e| format!("Failed to preprocess image: {}", e))?;
+    // Held until the response is built so concurrent large jobs can't OOM the process
+    let _memory_reservation = crate::deadline::within(
+        "memory admission",
+        admit_vision_job(&raw_images, &preprocess_cfg),
+    )
+    .await??;
+
+    crate::deadline::check("image preprocessing")?;
+    let input_bytes: usize = raw_images.iter().map(Vec::len).sum();
+    tracing::error!("About to preprocess image: {} bytes", input_bytes);
+    let preprocessed = tracing::info_span!("vision.preprocess", input_bytes).in_scope(|| {
+        raw_images
+            .iter()
+            .map(|data| preprocess_image(data, &preprocess_cfg))
+            .collect::<crate::error::Result<Vec<_>>>()
+    })?;
+    let images: Vec<&[u8]> = preprocessed.iter().map(|p| p.bytes.as_slice()).collect();
 
     if trace {
-        info!(
-            target: "vision",
-            stage = "preprocess",
-            width = preprocessed.width,
-            height = preprocessed.height,
-            encoded_bytes = preprocessed.bytes.len(),
-            "vision image preprocessed"
-        );
+        for image in &preprocessed {
+            info!(
+                target: "vision",
+                stage = "preprocess",
+                width = image.width,
+                height = image.height,
+                encoded_bytes = image.bytes.len(),
+                sharpness = image.quality.sharpness,
+                contrast = image.quality.contrast,
+                "vision image preprocessed"
+            );
+        }
     }
 
     // Determine model to use (use provided model_name)
     let vision_model = model_name.to_string();
-    let vision_model_id = normalize_vision_model_id(&vision_model);
-
-    // Shimmy-native vision bootstrap: no Ollama dependency.
-    let (model_spec, resolved_model_name) = if is_builtin_minicpm_v(&vision_model_id) {
-        let auto_download = std::env::var("SHIMMY_VISION_AUTO_DOWNLOAD")
-            .ok()
-            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
-            .unwrap_or(true);
-
-        let (model_path, _projector_path) = ensure_minicpm_v_files(auto_download).await?;
-
-        (
-            crate::engine::ModelSpec {
-                name: "minicpm-v".to_string(),
-                base_path: model_path,
-                lora_path: None,
-                template: Some("chatml".to_string()),
-                ctx_len: 32768,
-                n_threads: None,
-            },
-            "minicpm-v".to_string(),
+    let vision_model_id = crate::model_names::normalize(&vision_model);
+
+    #[cfg(feature = "vision-tesseract")]
+    if use_tesseract(&req.mode, &vision_model_id, state).await? {
+        let mut response =
+            crate::vision_tesseract::analyze(&req, &preprocessed, start_time).await?;
+        response.meta.safety = screen_images(&images, None, state).await?;
+        response.schema_version = schema_version;
+        return finish_response(
+            response,
+            &req,
+            &preprocessed,
+            cache_entry.as_ref(),
+            &cache_options,
+            (license_manager, usage),
         )
-    } else {
-        let spec = state
-            .registry
-            .to_spec(&vision_model_id)
-            .ok_or_else(|| {
-                format!(
-                    "Vision model '{}' not found.\n\nTo use the built-in MiniCPM-V download, set SHIMMY_VISION_MODEL=minicpm-v.",
-                    vision_model_id
-                )
-            })?;
-        (spec, vision_model_id.clone())
-    };
+        .await;
+    }
+
+    let (model_spec, resolved_model_name) = resolve_vision_model(&vision_model_id, state).await?;
 
     let loaded_model = state
-        .engine
-        .load(&model_spec)
+        .load_model(&model_spec)
         .await
-        .map_err(|e| format!("Failed to load vision model: {}", e))?;
+        .map_err(|e| ShimmyError::from_load(&model_spec.base_path, e))?;
 
     if trace {
         info!(
@@ -352,13 +687,65 @@ pub async fn process_vision_request(
         );
     }
 
-    // Prepare vision prompt based on mode
-    let prompt = prepare_vision_prompt(
-        &req.mode,
-        preprocessed.width,
-        preprocessed.height,
+    let safety = screen_images(
+        &images,
+        Some((loaded_model.as_ref(), resolved_model_name.as_str())),
+        state,
+    )
+    .await?;
+    if trace {
+        if let Some(check) = &safety {
+            info!(
+                target: "vision",
+                stage = "safety",
+                verdict = ?check.verdict,
+                model = %check.model,
+                duration_ms = check.duration_ms,
+                "vision safety check completed"
+            );
+        }
+    }
+
+    let mut response = run_inference(
+        &req,
+        &preprocessed,
+        (loaded_model.as_ref(), resolved_model_name.as_str()),
         &vision_model,
-    );
+        captured_dom,
+        on_progress.as_ref().map(token_sink),
+        start_time,
+    )
+    .await?;
+
+    response.meta.safety = safety;
+    response.schema_version = schema_version;
+    finish_response(
+        response,
+        &req,
+        &preprocessed,
+        cache_entry.as_ref(),
+        &cache_options,
+        (license_manager, usage),
+    )
+    .await
+}
+
+/// Prompt the model with the request's images and parse its answer
+#[cfg(feature = "vision")]
+async fn run_inference(
+    req: &VisionRequest,
+    preprocessed: &[PreprocessedImage],
+    (model, model_name): (&dyn crate::engine::LoadedModel, &str),
+    prompt_model: &str,
+    captured_dom: Option<Vec<DomElement>>,
+    on_token: Option<Box<dyn FnMut(String) + Send>>,
+    start_time: Instant,
+) -> crate::error::Result<VisionResponse> {
+    let trace = std::env::var("SHIMMY_VISION_TRACE").is_ok();
+
+    // Prepare vision prompt based on mode
+    let sizes: Vec<(u32, u32)> = preprocessed.iter().map(|p| (p.width, p.height)).collect();
+    let prompt = prepare_images_prompt(&req.mode, &sizes, prompt_model);
 
     if trace {
         info!(
@@ -377,14 +764,31 @@ pub async fn process_vision_request(
         top_k: 40,
         repeat_penalty: 1.0,
         seed: None,
-        stream: false,
+        stream: on_token.is_some(),
         stop_tokens: vec!["</s>".to_string(), "<|im_end|>".to_string()],
+        add_bos: None,
+        repetition: None,
+        max_time_ms: None,
+        // Keep the model from wrapping its JSON in prose or code fences
+        grammar: Some(crate::engine::grammar::JSON_OBJECT.to_string()),
+        top_logprobs: 0,
+        cancel: None,
+        deadline: crate::deadline::current(),
+        api_key: crate::api_keys::current(),
+        rate_limit: crate::rate_limit::current(),
+        adapter: None,
     };
 
     // Run inference with timeout to avoid hanging
-    let generate_future =
-        loaded_model.generate_vision(&preprocessed.bytes, &prompt, gen_options, None);
-    let timeout_ms = req.timeout_ms.unwrap_or(60_000);
+    let images: Vec<&[u8]> = preprocessed.iter().map(|p| p.bytes.as_slice()).collect();
+    let generate_future = model.generate_vision(&images, &prompt, gen_options, on_token);
+    let mut timeout_ms = req.timeout_ms.unwrap_or(60_000);
+    // Stop at the request deadline if that comes first
+    let remaining = crate::deadline::remaining();
+    let deadline_first = remaining.is_some_and(|left| (left.as_millis() as u64) < timeout_ms);
+    if let Some(left) = remaining.filter(|_| deadline_first) {
+        timeout_ms = left.as_millis() as u64;
+    }
     if trace {
         info!(
             target: "vision",
@@ -399,8 +803,15 @@ pub async fn process_vision_request(
     )
     .await
     {
-        Ok(result) => result.map_err(|e| format!("Vision inference failed: {}", e))?,
-        Err(_) => return Err(format!("Vision inference timed out after {} ms", timeout_ms).into()),
+        Ok(result) => result.map_err(|e| ShimmyError::InferenceFailed {
+            reason: e.to_string(),
+        })?,
+        Err(_) if deadline_first => {
+            return Err(ShimmyError::DeadlineExceeded {
+                stage: "vision inference".to_string(),
+            })
+        }
+        Err(_) => return Err(ShimmyError::InferenceTimeout { timeout_ms }),
     };
 
     if trace {
@@ -415,8 +826,8 @@ pub async fn process_vision_request(
     // Parse model output into structured response
     let response = parse_vision_output(
         &raw_output,
-        &req,
-        resolved_model_name.as_str(),
+        req,
+        model_name,
         start_time.elapsed().as_millis() as u64,
         captured_dom,
     )?;
@@ -434,16 +845,701 @@ pub async fn process_vision_request(
     Ok(response)
 }
 
+/// Analyze frames sampled from the request's video, see [`crate::vision_video`]
+#[cfg(feature = "vision-video")]
+async fn process_video(
+    req: &VisionRequest,
+    video: &VideoInput,
+    model_name: &str,
+    state: &crate::AppState,
+    on_progress: Option<&ProgressSink>,
+    start_time: Instant,
+) -> crate::error::Result<VisionResponse> {
+    use crate::vision_video::{extract_frames, Sampling};
+
+    let invalid = |reason: &str| ShimmyError::InvalidRequest {
+        reason: reason.to_string(),
+    };
+    if req.image_base64.is_some() || req.url.is_some() || !req.images.is_empty() {
+        return Err(invalid(
+            "video can't be combined with image_base64, url or images",
+        ));
+    }
+    if req.mode == "web" || req.screenshot.unwrap_or(false) {
+        return Err(invalid(
+            "video can't be used with web mode or screenshot capture",
+        ));
+    }
+    let sampling = Sampling::new(video.fps, video.max_frames)?;
+    let data =
+        match (&video.video_base64, &video.url) {
+            (Some(base64), None) => general_purpose::STANDARD.decode(base64).map_err(|e| {
+                ShimmyError::InvalidRequest {
+                    reason: format!("Failed to decode base64 video: {}", e),
+                }
+            })?,
+            (None, Some(url)) => {
+                let headers = request_url_headers(req)?;
+                crate::deadline::within("video fetch", fetch_image_from_url(url, headers.as_ref()))
+                    .await?
+                    .map_err(fetch_error)?
+            }
+            _ => return Err(invalid("video needs exactly one of video_base64 or url")),
+        };
+
+    let timeout = std::time::Duration::from_millis(req.timeout_ms.unwrap_or(60_000));
+    let (frames, truncated) =
+        crate::deadline::within("frame extraction", extract_frames(&data, sampling, timeout))
+            .await??;
+    drop(data);
+
+    let vision_model_id = crate::model_names::normalize(model_name);
+    #[cfg(feature = "vision-tesseract")]
+    let tesseract = use_tesseract(&req.mode, &vision_model_id, state).await?;
+    #[cfg(not(feature = "vision-tesseract"))]
+    let tesseract = false;
+    let loaded = if tesseract {
+        None
+    } else {
+        let (model_spec, resolved_model_name) =
+            resolve_vision_model(&vision_model_id, state).await?;
+        let loaded_model = state
+            .load_model(&model_spec)
+            .await
+            .map_err(|e| ShimmyError::from_load(&model_spec.base_path, e))?;
+        Some((loaded_model, resolved_model_name))
+    };
+
+    // One frame at a time, so memory use doesn't grow with the video's length
+    let preprocess_cfg = preprocess_config_for_mode(Some(req.mode.as_str()));
+    let mut timeline = Vec::with_capacity(frames.len());
+    let mut safety: Option<crate::vision_safety::SafetyCheck> = None;
+    let mut first_frame = None;
+    for (i, frame) in frames.iter().enumerate() {
+        let _memory_reservation = crate::deadline::within(
+            "memory admission",
+            admit_vision_job(std::slice::from_ref(&frame.png), &preprocess_cfg),
+        )
+        .await??;
+        crate::deadline::check("image preprocessing")?;
+        let preprocessed = preprocess_image(&frame.png, &preprocess_cfg)?;
+        let images = [preprocessed.bytes.as_slice()];
+        let frame_start = Instant::now();
+
+        let (mut response, check) = match &loaded {
+            Some((model, name)) => {
+                let model = (model.as_ref(), name.as_str());
+                let check = screen_images(&images, Some(model), state).await?;
+                let response = run_inference(
+                    req,
+                    std::slice::from_ref(&preprocessed),
+                    model,
+                    model_name,
+                    None,
+                    on_progress.map(token_sink),
+                    frame_start,
+                )
+                .await?;
+                (response, check)
+            }
+            #[cfg(feature = "vision-tesseract")]
+            None => {
+                let check = screen_images(&images, None, state).await?;
+                let response = crate::vision_tesseract::analyze(
+                    req,
+                    std::slice::from_ref(&preprocessed),
+                    frame_start,
+                )
+                .await?;
+                (response, check)
+            }
+            #[cfg(not(feature = "vision-tesseract"))]
+            None => unreachable!("tesseract is only chosen in builds that have it"),
+        };
+        apply_coordinates(
+            &mut response,
+            req.coordinates.unwrap_or_default(),
+            &preprocessed,
+        );
+        safety = match (safety, check) {
+            (Some(a), Some(b)) => Some(a.merge(b)),
+            (a, b) => a.or(b),
+        };
+        if i == 0 {
+            first_frame = Some(crate::vision_quality::ImageDiagnostics::new(&preprocessed));
+        }
+        let keyframe = Keyframe::new(i, frame.timestamp_ms, response);
+        if let Some(sink) = on_progress {
+            sink(VisionProgress::Keyframe(Box::new(keyframe.clone())));
+        }
+        timeline.push(keyframe);
+    }
+
+    let (model, backend) = match &loaded {
+        Some((_, name)) => (name.clone(), "llama.cpp".to_string()),
+        None => ("tesseract".to_string(), "tesseract".to_string()),
+    };
+    Ok(VisionResponse {
+        schema_version: SCHEMA_VERSION,
+        image_path: None,
+        url: video.url.clone(),
+        mode: req.mode.clone(),
+        text_blocks: Vec::new(),
+        layout: Layout {
+            theme: None,
+            regions: vec![],
+            key_ui_elements: vec![],
+        },
+        visual: Visual {
+            background: None,
+            accent_colors: vec![],
+            contrast: None,
+            description: None,
+        },
+        interaction: Interaction { description: None },
+        dom_map: None,
+        detections: Vec::new(),
+        fields: None,
+        meta: Meta {
+            model,
+            backend,
+            duration_ms: start_time.elapsed().as_millis() as u64,
+            parse_warnings: None,
+            safety,
+            cached: false,
+            image: None,
+            images: None,
+            video: Some(VideoInfo {
+                fps: sampling.fps,
+                frames: timeline.len(),
+                truncated,
+                frame: first_frame,
+            }),
+        },
+        timeline,
+        raw_model_output: None,
+        license_warning: None,
+    })
+}
+
+#[cfg(all(feature = "vision", not(feature = "vision-video")))]
+async fn process_video(
+    _req: &VisionRequest,
+    _video: &VideoInput,
+    _model_name: &str,
+    _state: &crate::AppState,
+    _on_progress: Option<&ProgressSink>,
+    _start_time: Instant,
+) -> crate::error::Result<VisionResponse> {
+    Err(ShimmyError::InvalidRequest {
+        reason: "video input needs a build with the `vision-video` feature (and ffmpeg)"
+            .to_string(),
+    })
+}
+
+/// Place positions, describe the inputs, cache the result and count the request
+#[cfg(feature = "vision")]
+async fn finish_response(
+    mut response: VisionResponse,
+    req: &VisionRequest,
+    preprocessed: &[PreprocessedImage],
+    cache_entry: Option<&(String, String)>,
+    cache_options: &str,
+    (license_manager, usage): (
+        &crate::vision_license::VisionLicenseManager,
+        crate::vision_license::UsageReservation<'_>,
+    ),
+) -> crate::error::Result<VisionResponse> {
+    apply_coordinates(
+        &mut response,
+        req.coordinates.unwrap_or_default(),
+        &preprocessed[0],
+    );
+    let mut diagnostics = preprocessed
+        .iter()
+        .map(crate::vision_quality::ImageDiagnostics::new);
+    if req.images.is_empty() {
+        response.meta.image = diagnostics.next();
+    } else {
+        response.meta.images = Some(diagnostics.collect());
+    }
+    if let Some((key, content_hash)) = cache_entry {
+        crate::cache::url_cache::UrlCache::global().store_result(
+            key,
+            content_hash,
+            cache_options,
+            &response,
+        );
+    }
+    usage.commit().await?;
+    response.license_warning = license_manager.license_warning().await;
+    Ok(response)
+}
+
+/// Whether `mode` runs through tesseract instead of a vision model, see
+/// [`crate::vision_tesseract`]
+#[cfg(feature = "vision-tesseract")]
+async fn use_tesseract(
+    mode: &str,
+    vision_model_id: &str,
+    state: &crate::AppState,
+) -> crate::error::Result<bool> {
+    use crate::vision_tesseract::OcrBackend;
+
+    if mode != "ocr" {
+        return Ok(false);
+    }
+    match OcrBackend::from_env() {
+        OcrBackend::Model => Ok(false),
+        OcrBackend::Tesseract => Ok(true),
+        OcrBackend::Auto => match resolve_vision_model(vision_model_id, state).await {
+            Ok(_) => Ok(false),
+            Err(ShimmyError::VisionModelUnavailable { reason }) => {
+                tracing::info!("No vision model for OCR, using tesseract: {}", reason);
+                Ok(true)
+            }
+            Err(e) => Err(e),
+        },
+    }
+}
+
+/// Vision model per analysis mode, e.g. `ocr=qwen2-vl-7b,brief=moondream2`
+#[cfg(feature = "vision")]
+pub const MODE_MODELS_ENV: &str = "SHIMMY_VISION_MODE_MODELS";
+
+/// The model a `mode=model` table names for `mode`
+#[cfg(feature = "vision")]
+fn mode_model(table: &str, mode: &str) -> Option<String> {
+    table
+        .split(',')
+        .filter_map(|entry| entry.split_once('='))
+        .find(|(m, _)| m.trim().eq_ignore_ascii_case(mode))
+        .map(|(_, model)| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+}
+
+/// The vision model for a request that names none: the entry for its mode in
+/// `SHIMMY_VISION_MODE_MODELS` when that model is available, else `default`
+#[cfg(feature = "vision")]
+pub fn select_vision_model(mode: &str, default: &str, state: &crate::AppState) -> String {
+    let Some(model) = std::env::var(MODE_MODELS_ENV)
+        .ok()
+        .and_then(|table| mode_model(&table, mode))
+    else {
+        return default.to_string();
+    };
+    let id = crate::model_names::normalize(&model);
+    if is_builtin_minicpm_v(&id) || state.registry.to_spec(&id).is_some() {
+        model
+    } else {
+        tracing::warn!(
+            "{}: model '{}' for mode '{}' not found, using '{}'",
+            MODE_MODELS_ENV,
+            model,
+            mode,
+            default
+        );
+        default.to_string()
+    }
+}
+
+/// Registry spec for a vision model, downloading the built-in MiniCPM-V if needed
+#[cfg(feature = "vision")]
+async fn resolve_vision_model(
+    vision_model_id: &str,
+    state: &crate::AppState,
+) -> crate::error::Result<(crate::engine::ModelSpec, String)> {
+    // Shimmy-native vision bootstrap: no Ollama dependency.
+    if is_builtin_minicpm_v(vision_model_id) {
+        let auto_download = std::env::var("SHIMMY_VISION_AUTO_DOWNLOAD")
+            .ok()
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(true);
+
+        let (model_path, projector_path) = ensure_minicpm_v_files(auto_download).await?;
+
+        Ok((
+            crate::engine::ModelSpec {
+                name: "minicpm-v".to_string(),
+                base_path: model_path,
+                lora_path: None,
+                template: Some("chatml".to_string()),
+                ctx_len: 32768,
+                n_threads: None,
+                n_gpu_layers: None,
+                mmproj_path: Some(projector_path),
+            },
+            "minicpm-v".to_string(),
+        ))
+    } else {
+        let spec = state
+            .registry
+            .to_spec(vision_model_id)
+            .ok_or_else(|| ShimmyError::VisionModelUnavailable {
+                reason: format!(
+                    "Vision model '{}' not found.\n\nTo use the built-in MiniCPM-V download, set SHIMMY_VISION_MODEL=minicpm-v.",
+                    vision_model_id
+                ),
+            })?;
+        Ok((spec, vision_model_id.to_string()))
+    }
+}
+
+/// Screen the images when `SHIMMY_VISION_SAFETY` is on
+#[cfg(feature = "vision")]
+async fn screen_images(
+    images: &[&[u8]],
+    vision_model: Option<(&dyn crate::engine::LoadedModel, &str)>,
+    state: &crate::AppState,
+) -> crate::error::Result<Option<crate::vision_safety::SafetyCheck>> {
+    let Some(mode) = crate::vision_safety::SafetyMode::from_env() else {
+        return Ok(None);
+    };
+    crate::deadline::within(
+        "safety screening",
+        screen_image(images, vision_model, mode, state),
+    )
+    .await?
+    .map(Some)
+}
+
+/// Run the images past the safety classifier: `SHIMMY_VISION_SAFETY_MODEL`,
+/// else the already loaded vision model. The least safe verdict wins.
+#[cfg(feature = "vision")]
+async fn screen_image(
+    images: &[&[u8]],
+    vision_model: Option<(&dyn crate::engine::LoadedModel, &str)>,
+    mode: crate::vision_safety::SafetyMode,
+    state: &crate::AppState,
+) -> crate::error::Result<crate::vision_safety::SafetyCheck> {
+    use crate::vision_safety::{screen, SAFETY_MODEL_ENV};
+
+    let classifier = match std::env::var(SAFETY_MODEL_ENV) {
+        Ok(name) if !name.trim().is_empty() => {
+            let (spec, name) =
+                resolve_vision_model(&crate::model_names::normalize(name.trim()), state).await?;
+            let model = state
+                .load_model(&spec)
+                .await
+                .map_err(|e| ShimmyError::from_load(&spec.base_path, e))?;
+            Some((model, name))
+        }
+        _ => None,
+    };
+    let (model, name) = match (&classifier, vision_model) {
+        (Some((model, name)), _) => (model.as_ref(), name.as_str()),
+        (None, Some(vision_model)) => vision_model,
+        (None, None) => {
+            return Err(ShimmyError::VisionModelUnavailable {
+                reason: format!(
+                "Safety screening needs a vision model; set {} to screen images read by tesseract",
+                SAFETY_MODEL_ENV
+            ),
+            })
+        }
+    };
+
+    let mut combined: Option<crate::vision_safety::SafetyCheck> = None;
+    for image in images {
+        let check = screen(image, model, name, mode).await?;
+        combined = Some(match combined {
+            Some(previous) => previous.merge(check),
+            None => check,
+        });
+    }
+    combined.ok_or_else(|| ShimmyError::InvalidRequest {
+        reason: "No image to screen".to_string(),
+    })
+}
+
+/// Load every entry of `images`, in order
+#[cfg(feature = "vision")]
+async fn load_images(req: &VisionRequest) -> crate::error::Result<Vec<Vec<u8>>> {
+    let invalid = |reason: &str| ShimmyError::InvalidRequest {
+        reason: reason.to_string(),
+    };
+    if req.image_base64.is_some() || req.url.is_some() {
+        return Err(invalid(
+            "images can't be combined with image_base64 or url; list every image in images",
+        ));
+    }
+    if req.mode == "web" || req.screenshot.unwrap_or(false) {
+        return Err(invalid(
+            "images can't be used with web mode or screenshot capture",
+        ));
+    }
+    let max_images = std::env::var(MAX_IMAGES_ENV)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(8);
+    if req.images.len() > max_images {
+        return Err(ShimmyError::InvalidRequest {
+            reason: format!(
+                "{} images were sent but at most {} are allowed ({})",
+                req.images.len(),
+                max_images,
+                MAX_IMAGES_ENV
+            ),
+        });
+    }
+
+    let headers = request_url_headers(req)?;
+    let mut images = Vec::with_capacity(req.images.len());
+    for (i, input) in req.images.iter().enumerate() {
+        images.push(load_image_input(i, input, headers.as_ref()).await?);
+    }
+    Ok(images)
+}
+
+/// Decode or fetch entry `i` of an image list
+#[cfg(feature = "vision")]
+async fn load_image_input(
+    i: usize,
+    input: &ImageInput,
+    headers: Option<&reqwest::header::HeaderMap>,
+) -> crate::error::Result<Vec<u8>> {
+    match (&input.image_base64, &input.url) {
+        (Some(base64), None) => {
+            general_purpose::STANDARD
+                .decode(base64)
+                .map_err(|e| ShimmyError::InvalidRequest {
+                    reason: format!("Failed to decode base64 image {}: {}", i, e),
+                })
+        }
+        (None, Some(url)) => {
+            let (_, image) =
+                crate::deadline::within("image fetch", fetch_image_cached(url, headers))
+                    .await?
+                    .map_err(fetch_error)?;
+            Ok(image.bytes.as_ref().clone())
+        }
+        _ => Err(ShimmyError::InvalidRequest {
+            reason: format!("images[{}] needs exactly one of image_base64 or url", i),
+        }),
+    }
+}
+
+/// Embedding vectors for `inputs`, in order and scaled to unit length: each
+/// image is loaded and preprocessed as for analysis, then encoded by the
+/// model's vision projector without generating text. The request counts once
+/// against the license.
+#[cfg(feature = "vision")]
+pub async fn embed_images(
+    inputs: &[ImageInput],
+    loaded: &dyn crate::engine::LoadedModel,
+    license: Option<&str>,
+    license_manager: &crate::vision_license::VisionLicenseManager,
+) -> crate::error::Result<Vec<Vec<f32>>> {
+    let usage =
+        crate::deadline::within("license check", license_manager.reserve_usage(license)).await??;
+
+    let cfg = preprocess_config_for_mode(None);
+    let mut preprocessed = Vec::with_capacity(inputs.len());
+    for (i, input) in inputs.iter().enumerate() {
+        let data = load_image_input(i, input, None).await?;
+        // Only the preprocessed copy is kept, so one reservation at a time
+        let _memory_reservation = crate::deadline::within(
+            "memory admission",
+            admit_vision_job(std::slice::from_ref(&data), &cfg),
+        )
+        .await??;
+        crate::deadline::check("image preprocessing")?;
+        preprocessed.push(preprocess_image(&data, &cfg)?.bytes);
+    }
+    let images: Vec<&[u8]> = preprocessed.iter().map(Vec::as_slice).collect();
+
+    let mut vectors = crate::deadline::within("image embedding", loaded.embed_images(&images))
+        .await?
+        .map_err(|e| ShimmyError::InferenceFailed {
+            reason: e.to_string(),
+        })?;
+    vectors
+        .iter_mut()
+        .for_each(|v| crate::embeddings::normalize(v));
+    usage.commit().await?;
+    Ok(vectors)
+}
+
+/// `url_headers` of the request, checked against `SHIMMY_VISION_URL_HEADERS`
+#[cfg(feature = "vision")]
+fn request_url_headers(
+    req: &VisionRequest,
+) -> crate::error::Result<Option<reqwest::header::HeaderMap>> {
+    match &req.url_headers {
+        Some(headers) if !headers.is_empty() => {
+            let allowed =
+                std::env::var(URL_HEADERS_ENV).unwrap_or_else(|_| DEFAULT_URL_HEADERS.to_string());
+            Ok(Some(url_fetch_headers(headers, &allowed)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Categorize an image fetch failure
+#[cfg(feature = "vision")]
+fn fetch_error(e: anyhow::Error) -> ShimmyError {
+    let timed_out = e
+        .downcast_ref::<reqwest::Error>()
+        .map(|re| re.is_timeout())
+        .unwrap_or(false)
+        || e.to_string().to_lowercase().contains("timed out");
+    ShimmyError::ImageFetchFailed {
+        reason: e.to_string(),
+        timed_out,
+    }
+}
+
+/// Reserve memory for a vision job before decoding, queuing or rejecting it
+/// when parallel jobs would exceed available memory. New jobs are also
+/// rejected while swap thrashing is detected and `SHIMMY_PAUSE_ON_THRASHING=1`.
+///
+/// Disabled with `SHIMMY_VISION_MEMORY_GUARD=0`. `SHIMMY_VISION_JOB_OVERHEAD_MB`
+/// sets the per-job model delta (default 512) and
+/// `SHIMMY_VISION_QUEUE_TIMEOUT_MS` how long to wait for running jobs (default 30000).
+#[cfg(feature = "vision")]
+async fn admit_vision_job(
+    raw_images: &[Vec<u8>],
+    cfg: &PreprocessConfig,
+) -> crate::error::Result<Option<crate::util::memory::MemoryReservation>> {
+    use crate::util::memory::{estimate_vision_job_bytes, MemoryAdmission};
+
+    fn env_u64(key: &str) -> Option<u64> {
+        std::env::var(key).ok().and_then(|v| v.parse::<u64>().ok())
+    }
+
+    if std::env::var("SHIMMY_VISION_MEMORY_GUARD")
+        .map(|v| v == "0" || v.eq_ignore_ascii_case("false"))
+        .unwrap_or(false)
+    {
+        return Ok(None);
+    }
+
+    let overhead_bytes = env_u64("SHIMMY_VISION_JOB_OVERHEAD_MB").unwrap_or(512) * 1024 * 1024;
+    // Header-only reads; undecodable input is reported by preprocessing instead
+    let required = overhead_bytes
+        + raw_images
+            .iter()
+            .map(|data| {
+                let (width, height) = image::io::Reader::new(std::io::Cursor::new(data))
+                    .with_guessed_format()
+                    .ok()
+                    .and_then(|r| r.into_dimensions().ok())
+                    .unwrap_or((0, 0));
+                estimate_vision_job_bytes(width, height, cfg.max_pixels, 0)
+            })
+            .sum::<u64>();
+    let queue_timeout_ms = env_u64("SHIMMY_VISION_QUEUE_TIMEOUT_MS").unwrap_or(30_000);
+    let queue_timeout = std::time::Duration::from_millis(queue_timeout_ms);
+
+    if crate::util::memory::MemoryPressureMonitor::global().should_pause() {
+        return Err(ShimmyError::InsufficientMemory {
+            required_mb: required / (1024 * 1024),
+            available_mb: crate::util::memory::get_available_memory() / (1024 * 1024),
+        });
+    }
+
+    MemoryAdmission::global()
+        .acquire(required, queue_timeout)
+        .await
+        .map(Some)
+        .map_err(|rejected| ShimmyError::InsufficientMemory {
+            required_mb: rejected.required_bytes / (1024 * 1024),
+            available_mb: rejected.available_bytes / (1024 * 1024),
+        })
+}
+
 /// Fetch image data from URL
 #[cfg(feature = "vision")]
-async fn fetch_image_from_url(url: &str) -> Result<Vec<u8>, anyhow::Error> {
-    let parsed = validate_remote_url(url).await?;
+async fn fetch_image_from_url(
+    url: &str,
+    headers: Option<&reqwest::header::HeaderMap>,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let fetched = fetch_image_conditional(url, headers, None).await?;
+    Ok(fetched.bytes.unwrap_or_default())
+}
 
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(20))
-        .build()?;
+/// Fetch a URL image through the URL cache, revalidating a cached copy with
+/// its ETag / Last-Modified instead of downloading it again
+#[cfg(feature = "vision")]
+async fn fetch_image_cached(
+    url: &str,
+    headers: Option<&reqwest::header::HeaderMap>,
+) -> Result<(String, crate::cache::url_cache::CachedImage), anyhow::Error> {
+    use crate::cache::url_cache::{url_key, UrlCache};
+
+    let cache = UrlCache::global();
+    let key = url_key(url, headers);
+    let cached = cache.image(&key);
+    let fetched = fetch_image_conditional(url, headers, cached.as_ref()).await?;
+    let image = match (fetched.bytes, cached) {
+        (None, Some(cached)) => {
+            tracing::debug!("Vision URL not modified, using cached image: {}", url);
+            cached
+        }
+        (bytes, _) => cache.store_image(
+            &key,
+            bytes.unwrap_or_default(),
+            fetched.etag,
+            fetched.last_modified,
+        ),
+    };
+    Ok((key, image))
+}
+
+/// A fetched image body with its cache validators; `bytes` is `None` when
+/// the server answered `304 Not Modified`
+#[cfg(feature = "vision")]
+struct FetchedImage {
+    bytes: Option<Vec<u8>>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+#[cfg(feature = "vision")]
+async fn fetch_image_conditional(
+    url: &str,
+    headers: Option<&reqwest::header::HeaderMap>,
+    cached: Option<&crate::cache::url_cache::CachedImage>,
+) -> Result<FetchedImage, anyhow::Error> {
+    use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+
+    let parsed = validate_remote_url(url).await?;
 
-    let mut response = client.get(parsed).send().await?.error_for_status()?;
+    let mut client =
+        crate::util::http::client_builder().timeout(std::time::Duration::from_secs(20));
+    if headers.is_some() {
+        client = client.redirect(same_host_redirects(&parsed));
+    }
+    let mut request = client.build()?.get(parsed);
+    if let Some(headers) = headers {
+        request = request.headers(headers.clone());
+    }
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request.send().await?;
+    if cached.is_some() && response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(FetchedImage {
+            bytes: None,
+            etag: None,
+            last_modified: None,
+        });
+    }
+    let mut response = response.error_for_status()?;
+    let header = |name: reqwest::header::HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
 
     let max_bytes = std::env::var("SHIMMY_VISION_MAX_FETCH_BYTES")
         .ok()
@@ -471,11 +1567,82 @@ async fn fetch_image_from_url(url: &str) -> Result<Vec<u8>, anyhow::Error> {
         out.extend_from_slice(&chunk);
     }
 
-    Ok(out)
+    Ok(FetchedImage {
+        bytes: Some(out),
+        etag,
+        last_modified,
+    })
 }
 
-/// Capture screenshot and extract DOM from URL
+/// Header names `url_headers` may set (comma-separated, case-insensitive)
+#[cfg(feature = "vision")]
+const URL_HEADERS_ENV: &str = "SHIMMY_VISION_URL_HEADERS";
+
+#[cfg(feature = "vision")]
+const DEFAULT_URL_HEADERS: &str = "authorization";
+
+/// Validate `url_headers` against the `allowed` names and build them for the fetch
+#[cfg(feature = "vision")]
+fn url_fetch_headers(
+    headers: &std::collections::HashMap<String, String>,
+    allowed: &str,
+) -> crate::error::Result<reqwest::header::HeaderMap> {
+    let invalid = |reason: String| ShimmyError::InvalidRequest { reason };
+    let allowed: Vec<String> = allowed
+        .split(',')
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    let mut map = reqwest::header::HeaderMap::new();
+    for (name, value) in headers {
+        let header =
+            reqwest::header::HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| {
+                invalid(format!(
+                    "url_headers: `{}` is not a valid header name",
+                    name
+                ))
+            })?;
+        if !allowed.iter().any(|a| a == header.as_str()) {
+            return Err(invalid(format!(
+                "url_headers: `{}` is not allowed; add it to {} to send it",
+                name, URL_HEADERS_ENV
+            )));
+        }
+        let mut value = reqwest::header::HeaderValue::from_str(value).map_err(|_| {
+            invalid(format!(
+                "url_headers: the value for `{}` is not a valid header value",
+                name
+            ))
+        })?;
+        value.set_sensitive(true);
+        map.insert(header, value);
+    }
+    Ok(map)
+}
+
+/// Follow redirects only within the original host so `url_headers` never
+/// reach another server
 #[cfg(feature = "vision")]
+fn same_host_redirects(origin: &reqwest::Url) -> reqwest::redirect::Policy {
+    let host = origin.host_str().map(str::to_string);
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= 10 {
+            attempt.error("too many redirects")
+        } else if attempt.url().host_str() == host.as_deref() {
+            attempt.follow()
+        } else {
+            let target = attempt.url().host_str().unwrap_or("").to_string();
+            attempt.error(format!(
+                "redirected to another host ({}); url_headers are not forwarded",
+                target
+            ))
+        }
+    })
+}
+
+/// Capture screenshot and extract DOM from URL
+#[cfg(feature = "vision-web")]
 async fn capture_screenshot_and_dom(
     url: &str,
     viewport_width: u32,
@@ -554,6 +1721,17 @@ async fn capture_screenshot_and_dom(
     Ok((screenshot_data, dom_elements))
 }
 
+/// Builds without `vision-web` have no browser; callers fall back to
+/// fetching the URL as an image
+#[cfg(all(feature = "vision", not(feature = "vision-web")))]
+async fn capture_screenshot_and_dom(
+    _url: &str,
+    _viewport_width: u32,
+    _viewport_height: u32,
+) -> Result<(Vec<u8>, Vec<DomElement>), anyhow::Error> {
+    anyhow::bail!("screenshot capture needs a build with the `vision-web` feature")
+}
+
 #[cfg(feature = "vision")]
 async fn validate_remote_url(input: &str) -> Result<reqwest::Url, anyhow::Error> {
     let url = reqwest::Url::parse(input)
@@ -627,7 +1805,7 @@ fn is_private_or_local_ip(ip: std::net::IpAddr) -> bool {
 }
 
 /// Extract interactive DOM elements from the page
-#[cfg(feature = "vision")]
+#[cfg(feature = "vision-web")]
 async fn extract_dom_elements(
     page: &chromiumoxide::Page,
 ) -> Result<Vec<DomElement>, anyhow::Error> {
@@ -736,8 +1914,14 @@ async fn extract_dom_elements(
 pub fn preprocess_image(
     data: &[u8],
     cfg: &PreprocessConfig,
-) -> Result<PreprocessedImage, Box<dyn std::error::Error>> {
-    let img = image::load_from_memory(data)?;
+) -> crate::error::Result<PreprocessedImage> {
+    fn preprocess_err(e: impl std::fmt::Display) -> ShimmyError {
+        ShimmyError::ImagePreprocessFailed {
+            reason: e.to_string(),
+        }
+    }
+
+    let img = image::load_from_memory(data).map_err(preprocess_err)?;
     let rgb = img.to_rgb8();
     let (w, h) = rgb.dimensions();
 
@@ -782,39 +1966,129 @@ pub fn preprocess_image(
 
     // Final guard against unexpected oversize inputs.
     if target_pixels > cfg.max_pixels {
-        return Err(format!("image too large after resize ({}x{})", target_w, target_h).into());
+        return Err(preprocess_err(format!(
+            "image too large after resize ({}x{})",
+            target_w, target_h
+        )));
     }
 
     let mut encoded = Vec::new();
     // Lossless encoding improves OCR on low-contrast UI text compared to JPEG artifacts.
     let encoder = PngEncoder::new(&mut encoded);
-    encoder.write_image(resized_rgb.as_raw(), target_w, target_h, ColorType::Rgb8)?;
+    encoder
+        .write_image(resized_rgb.as_raw(), target_w, target_h, ColorType::Rgb8)
+        .map_err(preprocess_err)?;
 
     Ok(PreprocessedImage {
         bytes: encoded,
         width: target_w,
         height: target_h,
+        original_width: w,
+        original_height: h,
+        format: crate::vision_quality::detect_format(data),
+        quality: crate::vision_quality::Quality::measure(&resized_rgb),
     })
 }
 
+/// Put `dom_map`, `detections` and `text_blocks` positions into the requested
+/// coordinate space.
+///
+/// Captured DOM boxes and the prompt both use normalized 0..1 values. A box
+/// with any value above 1 is taken to be in pixels of the preprocessed image
+/// the model saw and is normalized by that size first, so both spaces are
+/// derived from the same normalized box.
+#[cfg(feature = "vision")]
+pub fn apply_coordinates(
+    response: &mut VisionResponse,
+    coordinates: Coordinates,
+    image: &PreprocessedImage,
+) {
+    let dom_rects = response
+        .dom_map
+        .iter_mut()
+        .flatten()
+        .map(|e| &mut e.position);
+    let detection_rects = response.detections.iter_mut().map(|d| &mut d.position);
+    let text_rects = response
+        .text_blocks
+        .iter_mut()
+        .filter_map(|b| b.position.as_mut());
+    for rect in dom_rects.chain(detection_rects).chain(text_rects) {
+        if [rect.x, rect.y, rect.width, rect.height]
+            .iter()
+            .any(|&v| v > 1.0)
+        {
+            let (w, h) = (image.width.max(1) as f32, image.height.max(1) as f32);
+            *rect = Rect {
+                x: rect.x / w,
+                y: rect.y / h,
+                width: rect.width / w,
+                height: rect.height / h,
+            };
+        }
+        if coordinates == Coordinates::Pixels {
+            let (w, h) = (image.original_width as f32, image.original_height as f32);
+            *rect = Rect {
+                x: (rect.x * w).round(),
+                y: (rect.y * h).round(),
+                width: (rect.width * w).round(),
+                height: (rect.height * h).round(),
+            };
+        }
+    }
+}
+
 /// Prepare vision prompt based on analysis mode
 #[cfg(feature = "vision")]
+#[allow(dead_code)] // Single-image form for library users; requests use prepare_images_prompt
 pub fn prepare_vision_prompt(mode: &str, width: u32, height: u32, model_name: &str) -> String {
-    let base_instruction = format!(
-        "Analyze the provided image ({}x{} px). Return ONE valid JSON object only (no markdown). Use null for unknowns and [] for empty lists.",
-        width, height
-    );
+    prepare_images_prompt(mode, &[(width, height)], model_name)
+}
+
+/// Prepare a vision prompt for one or more images of the given sizes, in the
+/// order they are passed to the backend
+#[cfg(feature = "vision")]
+pub fn prepare_images_prompt(mode: &str, sizes: &[(u32, u32)], model_name: &str) -> String {
+    let output = "Return ONE valid JSON object only (no markdown). Use null for unknowns and [] for empty lists.";
+    let base_instruction = match sizes {
+        [(width, height)] => format!(
+            "Analyze the provided image ({}x{} px). {}",
+            width, height, output
+        ),
+        _ => {
+            let list: Vec<String> = sizes
+                .iter()
+                .enumerate()
+                .map(|(i, (w, h))| format!("{}: {}x{} px", i + 1, w, h))
+                .collect();
+            format!(
+                "Analyze the {} provided images together, in order ({}). Compare them or treat them as consecutive pages as the content suggests, and say which image each finding comes from. {}",
+                sizes.len(),
+                list.join(", "),
+                output
+            )
+        }
+    };
 
     // Keep this short: long prompts increase token count and can trigger mtmd "memory slot" failures.
-    let schema_hint = "Keys: text_blocks([{text,confidence}]), layout({theme,regions,key_ui_elements}), visual({background,accent_colors,contrast,description}), interaction({description}), dom_map(list or null).";
-
-    let analysis_task = match mode {
-        "ocr" => "OCR: extract all visible on-screen text exactly as written. Do not add labels or prefixes (no 'A:', 'Q:', 'User:', 'Assistant:', bullet markers). Do not paraphrase, summarize, or correct spelling. Preserve punctuation and casing.",
-        "layout" => "Layout: identify major regions and key UI elements.",
-        "brief" => "Brief: concise visual description.",
-        "web" => "Web screenshot: include dom_map with approximate normalized boxes (x,y,width,height in 0..1) and describe interactions.",
-        "full" => "Full: fill text_blocks, layout, visual (accent_colors as #RRGGBB when possible), and interaction.",
-        _ => "Full: fill text_blocks, layout, visual (accent_colors as #RRGGBB when possible), and interaction.",
+    let custom = crate::vision_modes::global().resolve(mode).ok().flatten();
+    let schema_hint = match (custom, mode) {
+        (Some(custom), _) => custom.schema_hint(),
+        (None, "detect") => "Keys: detections([{label,confidence,x,y,width,height}]), text_blocks([{text,confidence}]).".to_string(),
+        (None, _) => "Keys: text_blocks([{text,confidence}]), layout({theme,regions,key_ui_elements}), visual({background,accent_colors,contrast,description}), interaction({description}), dom_map(list or null).".to_string(),
+    };
+
+    let analysis_task = match custom {
+        Some(custom) => custom.prompt.trim(),
+        None => match mode {
+            "ocr" => "OCR: extract all visible on-screen text exactly as written. Do not add labels or prefixes (no 'A:', 'Q:', 'User:', 'Assistant:', bullet markers). Do not paraphrase, summarize, or correct spelling. Preserve punctuation and casing.",
+            "layout" => "Layout: identify major regions and key UI elements.",
+            "brief" => "Brief: concise visual description.",
+            "web" => "Web screenshot: include dom_map with approximate normalized boxes (x,y,width,height in 0..1) and describe interactions.",
+            "detect" => "Detect: list every distinct object and UI element (buttons, inputs, links, icons, images) in detections, with a short label, confidence 0..1 and a tight normalized box (x,y = top-left corner, width,height; all in 0..1).",
+            "full" => "Full: fill text_blocks, layout, visual (accent_colors as #RRGGBB when possible), and interaction.",
+            _ => "Full: fill text_blocks, layout, visual (accent_colors as #RRGGBB when possible), and interaction.",
+        },
     };
 
     // Image is provided separately to the backend; keep prompt small to avoid Windows argv limits.
@@ -860,6 +2134,8 @@ mod tests {
         };
 
         let out = preprocess_image(&png_bytes, &cfg).expect("preprocess");
+        assert_eq!(out.format.as_deref(), Some("png"));
+        assert_eq!((out.original_width, out.original_height), (2000, 1000));
         assert!(out.width.max(out.height) <= cfg.max_long_edge);
         assert!((out.width as u64) * (out.height as u64) <= cfg.max_pixels);
         // PNG magic bytes: 89 50 4E 47 0D 0A 1A 0A
@@ -868,6 +2144,99 @@ mod tests {
         assert_eq!(&out.bytes[..sig.len()], &sig);
     }
 
+    #[test]
+    fn url_fetch_headers_enforces_allow_list() {
+        let headers = std::collections::HashMap::from([(
+            "Authorization".to_string(),
+            "Bearer secret".to_string(),
+        )]);
+        let map = url_fetch_headers(&headers, "authorization, x-api-key").unwrap();
+        assert_eq!(map["authorization"], "Bearer secret");
+        assert!(map["authorization"].is_sensitive());
+
+        let err = url_fetch_headers(&headers, "x-api-key").unwrap_err();
+        assert!(err.to_string().contains("`Authorization` is not allowed"));
+
+        let bad_value = std::collections::HashMap::from([(
+            "authorization".to_string(),
+            "line\nbreak".to_string(),
+        )]);
+        assert!(matches!(
+            url_fetch_headers(&bad_value, "authorization"),
+            Err(ShimmyError::InvalidRequest { .. })
+        ));
+    }
+
+    #[test]
+    fn apply_coordinates_scales_from_a_shared_normalized_box() {
+        let element = |x: f32, y: f32, width: f32, height: f32| DomElement {
+            tag: "button".to_string(),
+            id: None,
+            class: None,
+            text: None,
+            position: Rect {
+                x,
+                y,
+                width,
+                height,
+            },
+            attributes: std::collections::HashMap::new(),
+            colors: None,
+        };
+        let mut response = parse_vision_output(
+            "plain text",
+            &serde_json::from_value(serde_json::json!({"mode": "web"})).unwrap(),
+            "test",
+            0,
+            Some(vec![
+                element(0.5, 0.25, 0.1, 0.1),
+                // Pixels of the 1000x500 preprocessed image
+                element(500.0, 125.0, 100.0, 50.0),
+            ]),
+        )
+        .unwrap();
+        // Downscaled by half from the original
+        let image = PreprocessedImage {
+            bytes: Vec::new(),
+            width: 1000,
+            height: 500,
+            original_width: 2000,
+            original_height: 1000,
+            format: None,
+            quality: crate::vision_quality::Quality {
+                sharpness: 1.0,
+                contrast: 1.0,
+            },
+        };
+
+        let mut normalized = response.clone();
+        apply_coordinates(&mut normalized, Coordinates::Normalized, &image);
+        let boxes: Vec<_> = normalized
+            .dom_map
+            .unwrap()
+            .into_iter()
+            .map(|e| e.position)
+            .collect();
+        assert_eq!((boxes[0].x, boxes[0].y, boxes[0].width), (0.5, 0.25, 0.1));
+        assert_eq!((boxes[1].x, boxes[1].y, boxes[1].height), (0.5, 0.25, 0.1));
+
+        apply_coordinates(&mut response, Coordinates::Pixels, &image);
+        for rect in response.dom_map.unwrap().into_iter().map(|e| e.position) {
+            assert_eq!((rect.x, rect.y), (1000.0, 250.0));
+            assert_eq!(rect.height, 100.0);
+        }
+    }
+
+    #[test]
+    fn mode_model_reads_the_table() {
+        let table = "ocr = qwen2-vl-7b, Brief=moondream2,web=";
+        assert_eq!(mode_model(table, "ocr").as_deref(), Some("qwen2-vl-7b"));
+        assert_eq!(mode_model(table, "brief").as_deref(), Some("moondream2"));
+        assert_eq!(mode_model(table, "web"), None);
+        assert_eq!(mode_model(table, "full"), None);
+        assert_eq!(mode_model("", "ocr"), None);
+    }
+
     #[test]
     fn prepare_vision_prompt_is_compact_and_json_only() {
         let p = prepare_vision_prompt("full", 640, 480, "minicpm-v");
@@ -876,6 +2245,110 @@ mod tests {
         assert!(p.contains("text_blocks"));
         assert!(p.contains("dom_map"));
     }
+
+    #[test]
+    fn detect_mode_parses_detections_and_places_them() {
+        let p = prepare_vision_prompt("detect", 640, 480, "minicpm-v");
+        assert!(p.contains("detections"));
+        assert!(!p.contains("dom_map"));
+
+        let req: VisionRequest =
+            serde_json::from_value(serde_json::json!({"mode": "detect"})).unwrap();
+        let output = r#"{"detections": [
+            {"label": "button: Sign in", "confidence": 0.9, "x": 0.5, "y": 0.25, "width": 0.1, "height": 0.1},
+            {"label": "logo", "bbox": [100, 50, 200, 100]},
+            {"label": "no box"},
+            {"label": "", "x": 0, "y": 0, "width": 1, "height": 1}
+        ]}"#;
+        let mut response = parse_vision_output(output, &req, "test", 0, None).unwrap();
+        let labels: Vec<&str> = response
+            .detections
+            .iter()
+            .map(|d| d.label.as_str())
+            .collect();
+        assert_eq!(labels, vec!["button: Sign in", "logo"]);
+        assert_eq!(response.detections[0].confidence, Some(0.9));
+        assert_eq!(response.detections[1].confidence, None);
+
+        let image = PreprocessedImage {
+            bytes: Vec::new(),
+            width: 1000,
+            height: 500,
+            original_width: 2000,
+            original_height: 1000,
+            format: None,
+            quality: crate::vision_quality::Quality {
+                sharpness: 1.0,
+                contrast: 1.0,
+            },
+        };
+        apply_coordinates(&mut response, Coordinates::Pixels, &image);
+        let boxes: Vec<_> = response
+            .detections
+            .iter()
+            .map(|d| {
+                let r = &d.position;
+                (r.x, r.y, r.width, r.height)
+            })
+            .collect();
+        assert_eq!(
+            boxes,
+            vec![(1000.0, 250.0, 200.0, 100.0), (200.0, 100.0, 400.0, 200.0)]
+        );
+    }
+
+    #[test]
+    fn token_sink_forwards_model_output_as_progress() {
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let collected = seen.clone();
+        let sink: ProgressSink = std::sync::Arc::new(move |progress| {
+            if let VisionProgress::Token(text) = progress {
+                collected.lock().unwrap().push(text);
+            }
+        });
+        let mut on_token = token_sink(&sink);
+        on_token("{\"text_".to_string());
+        on_token("blocks\"".to_string());
+        assert_eq!(*seen.lock().unwrap(), vec!["{\"text_", "blocks\""]);
+    }
+
+    #[test]
+    fn prepare_images_prompt_lists_images_in_order() {
+        let single = prepare_images_prompt("full", &[(640, 480)], "minicpm-v");
+        assert_eq!(single, prepare_vision_prompt("full", 640, 480, "minicpm-v"));
+
+        let p = prepare_images_prompt("ocr", &[(640, 480), (800, 600)], "minicpm-v");
+        assert!(p.contains("2 provided images"));
+        assert!(p.contains("1: 640x480 px, 2: 800x600 px"));
+        assert!(p.contains("valid JSON"));
+    }
+
+    #[test]
+    fn negotiate_schema_version_picks_newest_supported() {
+        assert_eq!(negotiate_schema_version("1").unwrap(), 1);
+        assert_eq!(negotiate_schema_version(" 7, 1 ").unwrap(), 1);
+
+        let err = negotiate_schema_version("7").unwrap_err();
+        assert_eq!(err.code(), "UNSUPPORTED_SCHEMA_VERSION");
+        assert!(err.to_string().contains("supported versions: 1"));
+        let err = negotiate_schema_version("v1").unwrap_err();
+        assert_eq!(err.code(), "INVALID_REQUEST");
+    }
+
+    #[test]
+    fn vision_request_accepts_images() {
+        let req: VisionRequest = serde_json::from_value(serde_json::json!({
+            "mode": "full",
+            "images": [{"image_base64": "aGk="}, {"url": "https://example.com/b.png"}],
+        }))
+        .unwrap();
+        assert_eq!(req.images.len(), 2);
+        assert_eq!(req.images[0].image_base64.as_deref(), Some("aGk="));
+        assert_eq!(
+            req.images[1].url.as_deref(),
+            Some("https://example.com/b.png")
+        );
+    }
 }
 
 /// Parse model output into structured vision response
@@ -886,7 +2359,7 @@ pub fn parse_vision_output(
     model_name: &str,
     duration_ms: u64,
     captured_dom: Option<Vec<DomElement>>,
-) -> Result<VisionResponse, Box<dyn std::error::Error>> {
+) -> crate::error::Result<VisionResponse> {
     let (json_candidate, warnings) = extract_json_candidate(raw_output);
 
     if let Some(json_str) = json_candidate {
@@ -909,12 +2382,14 @@ pub fn parse_vision_output(
 
     // Final fallback: create basic response from raw text
     Ok(VisionResponse {
+        schema_version: SCHEMA_VERSION,
         image_path: None,
         url: req.url.clone(),
         mode: req.mode.clone(),
         text_blocks: vec![TextBlock {
             text: raw_output.trim().to_string(),
             confidence: Some(0.5),
+            position: None,
         }],
         layout: Layout {
             theme: None,
@@ -929,13 +2404,22 @@ pub fn parse_vision_output(
         },
         interaction: Interaction { description: None },
         dom_map: captured_dom,
+        detections: Vec::new(),
+        fields: None,
+        timeline: Vec::new(),
         meta: Meta {
             model: model_name.to_string(),
             backend: "llama.cpp".to_string(),
             duration_ms,
             parse_warnings: Some(vec!["Could not parse structured output".to_string()]),
+            safety: None,
+            cached: false,
+            image: None,
+            images: None,
+            video: None,
         },
         raw_model_output: Some(raw_output.to_string()),
+        license_warning: None,
     })
 }
 
@@ -1024,7 +2508,7 @@ pub fn parse_structured_output(
     raw_output: &str,
     parse_warnings: Option<Vec<String>>,
     captured_dom: Option<Vec<DomElement>>,
-) -> Result<VisionResponse, Box<dyn std::error::Error>> {
+) -> crate::error::Result<VisionResponse> {
     // Extract text blocks
     let mut text_blocks = parsed
         .get("text_blocks")
@@ -1038,6 +2522,7 @@ pub fn parse_structured_output(
                             .get("confidence")
                             .and_then(|c| c.as_f64())
                             .map(|c| c as f32),
+                        position: None,
                     })
                 })
                 .collect::<Vec<_>>()
@@ -1208,7 +2693,20 @@ pub fn parse_structured_output(
             .collect::<Vec<_>>()
     });
 
+    let detections = parsed
+        .get("detections")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(parse_detection).collect())
+        .unwrap_or_default();
+
+    let fields = crate::vision_modes::global()
+        .resolve(&req.mode)
+        .ok()
+        .flatten()
+        .map(|mode| mode.extract(parsed));
+
     Ok(VisionResponse {
+        schema_version: SCHEMA_VERSION,
         image_path: None,
         url: req.url.clone(),
         mode: req.mode.clone(),
@@ -1217,35 +2715,82 @@ pub fn parse_structured_output(
         visual,
         interaction,
         dom_map: captured_dom.or(dom_map),
+        detections,
+        fields,
+        timeline: Vec::new(),
         meta: Meta {
             model: model_name.to_string(),
             backend: "llama.cpp".to_string(),
             duration_ms,
             parse_warnings,
+            safety: None,
+            cached: false,
+            image: None,
+            images: None,
+            video: None,
         },
         raw_model_output: if req.raw.unwrap_or(false) {
             Some(raw_output.to_string())
         } else {
             None
         },
+        license_warning: None,
     })
 }
 
+/// One `detections` entry. Models place the box in `x`/`y`/`width`/`height`
+/// keys, in a `position`/`box`/`bbox` object with those keys, or in a
+/// `box`/`bbox` array of `[x, y, width, height]`; entries without a usable
+/// box or label are dropped.
 #[cfg(feature = "vision")]
-fn normalize_vision_model_id(input: &str) -> String {
-    let s = input.trim();
-
-    // Back-compat: older default used an Ollama registry URL-like string.
-    if let Some(stripped) = s.strip_prefix("registry.ollama.ai/library/") {
-        let candidate = stripped
-            .trim_end_matches("/latest")
-            .trim_end_matches(":latest")
-            .trim_matches('/');
-        return candidate.to_string();
+fn parse_detection(item: &serde_json::Value) -> Option<Detection> {
+    let label = item
+        .get("label")
+        .or_else(|| item.get("name"))?
+        .as_str()?
+        .trim();
+    if label.is_empty() {
+        return None;
     }
-
-    // Also accept minicpm-v:latest and normalize to minicpm-v.
-    s.trim_end_matches(":latest").to_string()
+    let rect_from = |v: &serde_json::Value| -> Option<Rect> {
+        let num = |key: &str| v.get(key)?.as_f64().map(|n| n as f32);
+        match v.as_array() {
+            Some(values) if values.len() == 4 => {
+                let n: Vec<f32> = values
+                    .iter()
+                    .map(|n| n.as_f64().map(|n| n as f32))
+                    .collect::<Option<_>>()?;
+                Some(Rect {
+                    x: n[0],
+                    y: n[1],
+                    width: n[2],
+                    height: n[3],
+                })
+            }
+            Some(_) => None,
+            None => Some(Rect {
+                x: num("x")?,
+                y: num("y")?,
+                width: num("width")?,
+                height: num("height")?,
+            }),
+        }
+    };
+    let position = ["position", "box", "bbox"]
+        .iter()
+        .find_map(|key| item.get(*key).and_then(rect_from))
+        .or_else(|| rect_from(item))?;
+    if position.width < 0.0 || position.height < 0.0 {
+        return None;
+    }
+    Some(Detection {
+        label: label.to_string(),
+        confidence: item
+            .get("confidence")
+            .and_then(|c| c.as_f64())
+            .map(|c| c as f32),
+        position,
+    })
 }
 
 #[cfg(feature = "vision")]
@@ -1262,11 +2807,16 @@ fn vision_model_dir() -> std::path::PathBuf {
         }
     }
 
-    let base = dirs::data_local_dir()
-        .or_else(dirs::cache_dir)
-        .or_else(dirs::data_dir)
-        .unwrap_or_else(|| std::path::PathBuf::from("."));
-    base.join("shimmy").join("vision").join("models")
+    crate::util::paths::local_data_dir()
+        .join("vision")
+        .join("models")
+}
+
+/// Download (or verify) the built-in MiniCPM-V files ahead of the first request
+#[cfg(feature = "vision")]
+pub async fn prefetch_builtin_model() -> crate::error::Result<std::path::PathBuf> {
+    let (model_path, _projector_path) = ensure_minicpm_v_files(true).await?;
+    Ok(model_path)
 }
 
 #[cfg(feature = "vision")]
@@ -1278,7 +2828,7 @@ fn minicpm_bootstrap_mutex() -> &'static tokio::sync::Mutex<()> {
 #[cfg(feature = "vision")]
 async fn ensure_minicpm_v_files(
     auto_download: bool,
-) -> Result<(std::path::PathBuf, std::path::PathBuf), Box<dyn std::error::Error>> {
+) -> crate::error::Result<(std::path::PathBuf, std::path::PathBuf)> {
     const MODEL_URL: &str =
         "https://huggingface.co/openbmb/MiniCPM-V-2_6-gguf/resolve/main/ggml-model-Q4_K_M.gguf";
     const MODEL_SHA256_HEX: &str =
@@ -1295,12 +2845,13 @@ async fn ensure_minicpm_v_files(
     let proj_path = dir.join("mmproj-model-f16.gguf");
 
     if !auto_download && (!model_path.exists() || !proj_path.exists()) {
-        return Err(format!(
-            "MiniCPM-V model files are missing.\n\nExpected:\n  - {}\n  - {}\n\nSet SHIMMY_VISION_AUTO_DOWNLOAD=1 to let Shimmy download them automatically.",
-            model_path.display(),
-            proj_path.display()
-        )
-        .into());
+        return Err(ShimmyError::VisionModelUnavailable {
+            reason: format!(
+                "MiniCPM-V model files are missing.\n\nExpected:\n  - {}\n  - {}\n\nSet SHIMMY_VISION_AUTO_DOWNLOAD=1 to let Shimmy download them automatically.",
+                model_path.display(),
+                proj_path.display()
+            ),
+        });
     }
 
     // Prevent duplicate concurrent downloads within the same process.
@@ -1317,7 +2868,13 @@ async fn ensure_download_and_verify(
     dest: &std::path::Path,
     url: &str,
     expected_sha256_hex: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> crate::error::Result<()> {
+    fn download_err(e: impl std::fmt::Display) -> ShimmyError {
+        ShimmyError::ModelDownloadFailed {
+            reason: e.to_string(),
+        }
+    }
+
     if dest.exists() {
         if verify_file_sha256(dest, expected_sha256_hex).await.is_ok() {
             return Ok(());
@@ -1337,19 +2894,24 @@ async fn ensure_download_and_verify(
         .and_then(|v| v.parse::<u64>().ok())
         .unwrap_or(60 * 60);
 
-    let client = reqwest::Client::builder()
+    let client = crate::util::http::client_builder()
         .timeout(std::time::Duration::from_secs(timeout_secs))
-        .build()?;
-    let mut resp = client.get(url).send().await?;
+        .build()
+        .map_err(download_err)?;
+    let mut resp = client.get(url).send().await.map_err(download_err)?;
     if !resp.status().is_success() {
-        return Err(format!("Failed to download {} (HTTP {})", url, resp.status()).into());
+        return Err(download_err(format!(
+            "Failed to download {} (HTTP {})",
+            url,
+            resp.status()
+        )));
     }
 
     let mut file = tokio::fs::File::create(&tmp).await?;
     let mut hasher = Sha256::new();
 
     use tokio::io::AsyncWriteExt;
-    while let Some(chunk) = resp.chunk().await? {
+    while let Some(chunk) = resp.chunk().await.map_err(download_err)? {
         hasher.update(&chunk);
         file.write_all(&chunk).await?;
     }
@@ -1359,13 +2921,12 @@ async fn ensure_download_and_verify(
     let actual = format!("{:x}", hasher.finalize());
     if actual != expected_lower {
         let _ = tokio::fs::remove_file(&tmp).await;
-        return Err(format!(
+        return Err(download_err(format!(
             "SHA256 mismatch for {}. Expected {}, got {}",
             dest.display(),
             expected_sha256_hex,
             actual
-        )
-        .into());
+        )));
     }
 
     tokio::fs::rename(&tmp, dest).await?;
@@ -1376,7 +2937,7 @@ async fn ensure_download_and_verify(
 async fn verify_file_sha256(
     path: &std::path::Path,
     expected_sha256_hex: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> crate::error::Result<()> {
     let mut file = tokio::fs::File::open(path).await?;
     let mut hasher = Sha256::new();
     let mut buf = vec![0u8; 8 * 1024 * 1024];
@@ -1393,13 +2954,14 @@ async fn verify_file_sha256(
     let expected_lower = expected_sha256_hex.to_lowercase();
     let actual = format!("{:x}", hasher.finalize());
     if actual != expected_lower {
-        return Err(format!(
-            "SHA256 mismatch for {}. Expected {}, got {}",
-            path.display(),
-            expected_sha256_hex,
-            actual
-        )
-        .into());
+        return Err(ShimmyError::ModelVerificationFailed {
+            details: format!(
+                "SHA256 mismatch for {}. Expected {}, got {}",
+                path.display(),
+                expected_sha256_hex,
+                actual
+            ),
+        });
     }
 
     Ok(())
@@ -23,11 +23,7 @@ pub trait VisionProvider: Send + Sync {
         model_name: String,
         state: &'a crate::AppState,
     ) -> std::pin::Pin<
-        Box<
-            dyn std::future::Future<Output = Result<VisionResponse, Box<dyn std::error::Error>>>
-                + Send
-                + 'a,
-        >,
+        Box<dyn std::future::Future<Output = crate::error::Result<VisionResponse>> + Send + 'a>,
     >;
 }
 
@@ -43,11 +39,7 @@ impl VisionProvider for PrivateVisionProvider {
         model_name: String,
         state: &'a crate::AppState,
     ) -> std::pin::Pin<
-        Box<
-            dyn std::future::Future<Output = Result<VisionResponse, Box<dyn std::error::Error>>>
-                + Send
-                + 'a,
-        >,
+        Box<dyn std::future::Future<Output = crate::error::Result<VisionResponse>> + Send + 'a>,
     > {
         Box::pin(async move {
             // TODO: Call the private shimmy-vision crate for licensed vision processing
@@ -71,14 +63,12 @@ impl VisionProvider for StubVisionProvider {
         #[allow(unused_variables)] _model_name: String,
         #[allow(unused_variables)] _state: &'a crate::AppState,
     ) -> std::pin::Pin<
-        Box<
-            dyn std::future::Future<Output = Result<VisionResponse, Box<dyn std::error::Error>>>
-                + Send
-                + 'a,
-        >,
+        Box<dyn std::future::Future<Output = crate::error::Result<VisionResponse>> + Send + 'a>,
     > {
-        Box::pin(
-            async move { Err("Vision feature not enabled. This is a licensed feature.".into()) },
-        )
+        Box::pin(async move {
+            Err(crate::error::ShimmyError::BackendNotAvailable {
+                backend: "vision (a licensed feature)".to_string(),
+            })
+        })
     }
 }
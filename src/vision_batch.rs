@@ -0,0 +1,275 @@
+//! Many images analyzed with one request.
+//!
+//! `POST /api/vision/batch` takes a list of images and the options they
+//! share (`mode`, `model`, ...). Each image is analyzed on its own, exactly
+//! as if it had been sent to `/api/vision`, with up to
+//! `SHIMMY_VISION_BATCH_CONCURRENCY` of them in flight at a time. This suits
+//! offline jobs such as analyzing a night's worth of screenshots.
+//!
+//! A failing image doesn't fail the batch: its entry in `results` carries
+//! the error the single request would have answered with. The license and
+//! the mode are checked once, up front, since they'd fail every image alike.
+
+use crate::error::ShimmyError;
+use crate::vision::{Coordinates, ImageInput, VisionRequest, VisionResponse};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Most images of one batch analyzed at a time (default 2)
+pub const BATCH_CONCURRENCY_ENV: &str = "SHIMMY_VISION_BATCH_CONCURRENCY";
+
+const DEFAULT_CONCURRENCY: usize = 2;
+
+/// Body of `POST /api/vision/batch`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisionBatchRequest {
+    /// Images analyzed one by one; each has one of `image_base64` or `url`
+    pub images: Vec<ImageInput>,
+    pub mode: String,
+    pub model: Option<String>,
+    /// Applies to each image
+    pub timeout_ms: Option<u64>,
+    pub url_headers: Option<HashMap<String, String>>,
+    pub license: Option<String>,
+    pub coordinates: Option<Coordinates>,
+    /// Images in flight at a time, at most `SHIMMY_VISION_BATCH_CONCURRENCY`
+    pub concurrency: Option<usize>,
+    #[serde(default)]
+    pub schema_version: Option<u32>,
+}
+
+impl VisionBatchRequest {
+    /// The `/api/vision` request for one of the images
+    pub fn item(&self, image: &ImageInput) -> VisionRequest {
+        VisionRequest {
+            image_base64: image.image_base64.clone(),
+            url: image.url.clone(),
+            images: Vec::new(),
+            video: None,
+            url_headers: self.url_headers.clone(),
+            mode: self.mode.clone(),
+            model: self.model.clone(),
+            timeout_ms: self.timeout_ms,
+            raw: None,
+            license: self.license.clone(),
+            screenshot: None,
+            viewport_width: None,
+            viewport_height: None,
+            stream: None,
+            coordinates: self.coordinates,
+            callback_url: None,
+            schema_version: self.schema_version,
+        }
+    }
+
+    /// Images in flight at a time: the requested number within the
+    /// configured limit
+    pub fn concurrency(&self) -> usize {
+        let limit = std::env::var(BATCH_CONCURRENCY_ENV)
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        self.concurrency.unwrap_or(limit).clamp(1, limit)
+    }
+}
+
+/// The outcome for one image: `result`, or `error_status` and `error`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    /// Position of the image in the request
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<VisionResponse>,
+    /// HTTP status the single request would have failed with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_status: Option<u16>,
+    /// Error body the single request would have returned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<serde_json::Value>,
+}
+
+impl BatchItem {
+    pub fn from_result(index: usize, result: crate::error::Result<VisionResponse>) -> Self {
+        match result {
+            Ok(response) => Self {
+                index,
+                result: Some(response),
+                error_status: None,
+                error: None,
+            },
+            Err(e) => {
+                let (status, mut body) = e.response_body();
+                Self {
+                    index,
+                    result: None,
+                    error_status: Some(status.as_u16()),
+                    error: body.get_mut("error").map(serde_json::Value::take),
+                }
+            }
+        }
+    }
+}
+
+/// Aggregate timing of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchMeta {
+    pub model: String,
+    pub images: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub concurrency: usize,
+    /// Wall-clock time of the whole batch
+    pub duration_ms: u64,
+    /// Mean analysis time of the images that succeeded
+    pub avg_duration_ms: Option<u64>,
+}
+
+/// Body of the answer, with `results` in request order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisionBatchResponse {
+    pub results: Vec<BatchItem>,
+    pub meta: BatchMeta,
+}
+
+impl VisionBatchResponse {
+    pub fn new(
+        model: &str,
+        concurrency: usize,
+        results: Vec<BatchItem>,
+        duration: Duration,
+    ) -> Self {
+        let durations: Vec<u64> = results
+            .iter()
+            .filter_map(|item| item.result.as_ref())
+            .map(|response| response.meta.duration_ms)
+            .collect();
+        let succeeded = durations.len();
+        let avg_duration_ms =
+            (succeeded > 0).then(|| durations.iter().sum::<u64>() / succeeded as u64);
+        Self {
+            meta: BatchMeta {
+                model: model.to_string(),
+                images: results.len(),
+                succeeded,
+                failed: results.len() - succeeded,
+                concurrency,
+                duration_ms: duration.as_millis() as u64,
+                avg_duration_ms,
+            },
+            results,
+        }
+    }
+}
+
+/// Analyze every image of `req` with `model_name`
+pub async fn process_batch(
+    req: VisionBatchRequest,
+    model_name: &str,
+    license_manager: &crate::vision_license::VisionLicenseManager,
+    state: &crate::AppState,
+) -> crate::error::Result<VisionBatchResponse> {
+    let start_time = Instant::now();
+    if req.images.is_empty() {
+        return Err(ShimmyError::InvalidRequest {
+            reason: "images must list at least one image".to_string(),
+        });
+    }
+    crate::vision_modes::global().resolve(&req.mode)?;
+    crate::deadline::within(
+        "license check",
+        license_manager.check_vision_access(req.license.as_deref()),
+    )
+    .await??;
+
+    let concurrency = req.concurrency();
+    tracing::info!(
+        images = req.images.len(),
+        concurrency,
+        model = model_name,
+        "Vision batch started"
+    );
+    let items: Vec<VisionRequest> = req.images.iter().map(|image| req.item(image)).collect();
+    let results: Vec<BatchItem> = stream::iter(items.into_iter().enumerate())
+        .map(|(index, item)| async move {
+            let result =
+                crate::vision::process_vision_request(item, model_name, license_manager, state)
+                    .await;
+            BatchItem::from_result(index, result)
+        })
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    let response = VisionBatchResponse::new(model_name, concurrency, results, start_time.elapsed());
+    tracing::info!(
+        succeeded = response.meta.succeeded,
+        failed = response.meta.failed,
+        duration_ms = response.meta.duration_ms,
+        "Vision batch finished"
+    );
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    fn batch(concurrency: Option<usize>) -> VisionBatchRequest {
+        serde_json::from_value(serde_json::json!({
+            "images": [{"url": "https://example.com/a.png"}, {"image_base64": "AAAA"}],
+            "mode": "ocr",
+            "timeout_ms": 5000,
+            "concurrency": concurrency,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    #[serial]
+    fn test_concurrency_is_capped_by_env() {
+        std::env::remove_var(BATCH_CONCURRENCY_ENV);
+        assert_eq!(batch(None).concurrency(), DEFAULT_CONCURRENCY);
+        assert_eq!(batch(Some(1)).concurrency(), 1);
+        assert_eq!(batch(Some(0)).concurrency(), 1);
+        assert_eq!(batch(Some(16)).concurrency(), DEFAULT_CONCURRENCY);
+
+        std::env::set_var(BATCH_CONCURRENCY_ENV, "8");
+        assert_eq!(batch(None).concurrency(), 8);
+        assert_eq!(batch(Some(16)).concurrency(), 8);
+        std::env::remove_var(BATCH_CONCURRENCY_ENV);
+    }
+
+    #[test]
+    fn test_items_share_options_and_errors_are_kept() {
+        let req = batch(None);
+        let item = req.item(&req.images[1]);
+        assert_eq!(item.image_base64.as_deref(), Some("AAAA"));
+        assert!(item.url.is_none());
+        assert_eq!(item.mode, "ocr");
+        assert_eq!(item.timeout_ms, Some(5000));
+
+        let results = vec![
+            BatchItem::from_result(
+                0,
+                Err(ShimmyError::InvalidRequest {
+                    reason: "bad image".to_string(),
+                }),
+            ),
+            BatchItem::from_result(1, Err(ShimmyError::ModelNotFound { name: "x".into() })),
+        ];
+        let response =
+            VisionBatchResponse::new("minicpm-v", 2, results, Duration::from_millis(1500));
+        assert_eq!(response.meta.images, 2);
+        assert_eq!(response.meta.failed, 2);
+        assert_eq!(response.meta.avg_duration_ms, None);
+        assert_eq!(response.meta.duration_ms, 1500);
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["results"][0]["error_status"], 400);
+        assert!(json["results"][0].get("result").is_none());
+        assert_eq!(json["results"][1]["index"], 1);
+    }
+}
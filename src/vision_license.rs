@@ -23,25 +23,36 @@ use serde::{Deserialize, Serialize};
 
 /// Hard-coded Keygen Account ID (SECURITY: Do not move to environment variable)
 /// This is a public identifier, safe to embed in source code.
+/// OEM builds replace it at compile time with `SHIMMY_OEM_KEYGEN_ACCOUNT_ID`.
 #[cfg(feature = "vision")]
-pub const KEYGEN_ACCOUNT_ID: &str = "6270bf9c-23ad-4483-9296-3a6d9178514a";
+pub const KEYGEN_ACCOUNT_ID: &str = crate::branding::or_default(
+    option_env!("SHIMMY_OEM_KEYGEN_ACCOUNT_ID"),
+    "6270bf9c-23ad-4483-9296-3a6d9178514a",
+);
 
 /// Hard-coded Keygen Ed25519 Public Key (SECURITY: Do not move to environment variable)
 /// Used to verify API response signatures, preventing MITM and replay attacks.
 /// Format: Hex-encoded 32-byte Ed25519 public key
+/// OEM builds replace it at compile time with `SHIMMY_OEM_KEYGEN_PUBLIC_KEY`.
 #[cfg(feature = "vision")]
-pub const KEYGEN_PUBLIC_KEY: &str =
-    "42f313585a72a41513208800f730944f1a3b74a8acfff539f96ce244d029fa5d";
+pub const KEYGEN_PUBLIC_KEY: &str = crate::branding::or_default(
+    option_env!("SHIMMY_OEM_KEYGEN_PUBLIC_KEY"),
+    "42f313585a72a41513208800f730944f1a3b74a8acfff539f96ce244d029fa5d",
+);
 
 /// Shimmy version for User-Agent header (helps Keygen detect cracks)
 #[cfg(feature = "vision")]
 const SHIMMY_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+#[cfg(feature = "vision")]
+use crate::error::ShimmyError;
 #[cfg(feature = "vision")]
 use std::collections::HashMap;
 #[cfg(feature = "vision")]
 use std::path::PathBuf;
 #[cfg(feature = "vision")]
+use std::sync::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "vision")]
 use std::sync::Arc;
 #[cfg(feature = "vision")]
 use tokio::sync::RwLock;
@@ -75,27 +86,191 @@ pub struct UsageStats {
     pub last_reset: chrono::DateTime<chrono::Utc>,
 }
 
+/// A request's place under the monthly cap, from the license check until the
+/// request is recorded with [`commit`](Self::commit). Dropping it instead
+/// (the request failed or timed out) gives the place back.
+#[cfg(feature = "vision")]
+#[must_use = "dropping a reservation releases it without recording usage"]
+pub struct UsageReservation<'a> {
+    manager: &'a VisionLicenseManager,
+}
+
+#[cfg(feature = "vision")]
+impl UsageReservation<'_> {
+    /// Record the request for metering and release the reservation
+    pub async fn commit(self) -> crate::error::Result<()> {
+        self.manager.record_usage().await
+    }
+}
+
+#[cfg(feature = "vision")]
+impl Drop for UsageReservation<'_> {
+    fn drop(&mut self) {
+        self.manager.reserved.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Days before expiry that vision responses start carrying a renewal warning
+#[cfg(feature = "vision")]
+pub const LICENSE_WARNING_DAYS_ENV: &str = "SHIMMY_LICENSE_WARNING_DAYS";
+
+#[cfg(feature = "vision")]
+const DEFAULT_LICENSE_WARNING_DAYS: i64 = 14;
+
+/// SHA-256 fingerprints the Keygen API certificate must match (optional)
+#[cfg(feature = "vision")]
+pub const KEYGEN_PINS_ENV: &str = "SHIMMY_KEYGEN_PINS";
+
+/// Checked for the pinned certificate before the license key is sent
+#[cfg(feature = "vision")]
+const KEYGEN_PING_URL: &str = "https://api.keygen.sh/v1/ping";
+
+/// Renewal reminder for a license that expires soon
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LicenseWarning {
+    pub expires_at: String,
+    pub days_remaining: i64,
+    pub message: String,
+}
+
+/// Warning for a license expiring at `expires_at`, if that is within `within_days` of `now`
+#[cfg(feature = "vision")]
+pub fn expiry_warning(
+    expires_at: chrono::DateTime<chrono::Utc>,
+    now: chrono::DateTime<chrono::Utc>,
+    within_days: i64,
+) -> Option<LicenseWarning> {
+    let remaining = expires_at - now;
+    if remaining > chrono::Duration::days(within_days) {
+        return None;
+    }
+    let date = expires_at.format("%Y-%m-%d");
+    let message = if remaining <= chrono::Duration::zero() {
+        format!(
+            "Your Shimmy Vision license expired on {}; vision requests will be refused within 24 hours. Renew it to keep access.",
+            date
+        )
+    } else {
+        let days = remaining.num_days();
+        format!(
+            "Your Shimmy Vision license expires in {} day{} ({}). Renew it to avoid interruption.",
+            days,
+            if days == 1 { "" } else { "s" },
+            date
+        )
+    };
+    Some(LicenseWarning {
+        expires_at: expires_at.to_rfc3339(),
+        days_remaining: remaining.num_days().max(0),
+        message,
+    })
+}
+
+#[cfg(feature = "vision")]
+fn license_warning_days() -> i64 {
+    std::env::var(LICENSE_WARNING_DAYS_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|&days: &i64| days >= 0)
+        .unwrap_or(DEFAULT_LICENSE_WARNING_DAYS)
+}
+
+/// `GET /api/license/status` body
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, Serialize)]
+pub struct LicenseStatus {
+    /// Whether a key was found (request env, `shimmy license set`)
+    pub configured: bool,
+    /// The key with all but its last characters masked
+    pub key: Option<String>,
+    pub valid: bool,
+    /// Why vision requests would be refused, as `{"code", "message"}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<serde_json::Value>,
+    pub expires_at: Option<String>,
+    pub requests_today: u32,
+    pub requests_this_month: u32,
+    pub monthly_cap: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license_warning: Option<LicenseWarning>,
+}
+
+/// Keygen settings read from `vision.json` in the app's config directory
+#[cfg(feature = "vision")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeygenConfig {
+    #[serde(default)]
+    pub product_token: Option<String>,
+}
+
+#[cfg(feature = "vision")]
+impl KeygenConfig {
+    /// Path of the vision config file
+    pub fn path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(crate::branding::APP_NAME)
+            .join("vision.json")
+    }
+
+    /// Load the config file, returning defaults when it is missing or unreadable
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Resolve the Keygen product token.
+    ///
+    /// Order: runtime env override (developers/CI), then the config file. The
+    /// token is an admin credential, so it is never compiled into the binary.
+    pub fn resolve_product_token(&self) -> Option<String> {
+        let non_empty = |token: &String| !token.trim().is_empty();
+        std::env::var("KEYGEN_API_KEY")
+            .ok()
+            .filter(non_empty)
+            .or_else(|| std::env::var("KEYGEN_PRODUCT_TOKEN").ok().filter(non_empty))
+            .or_else(|| self.product_token.clone().filter(non_empty))
+    }
+}
+
+/// [`crate::state_store`] entries of the license manager; with the file
+/// store these are `vision/license_cache.json` and `vision/usage_stats.json`
+/// in the data directory, as before stores were pluggable
+#[cfg(feature = "vision")]
+const STATE_NAMESPACE: &str = "vision";
+#[cfg(feature = "vision")]
+const LICENSE_KEY: &str = "license_cache.json";
+#[cfg(feature = "vision")]
+const USAGE_KEY: &str = "usage_stats.json";
+
 /// Vision licensing manager
 #[cfg(feature = "vision")]
 #[derive(Debug, Clone)]
 pub struct VisionLicenseManager {
     cache: Arc<RwLock<Option<CachedLicense>>>,
     usage: Arc<RwLock<UsageStats>>,
-    cache_path: PathBuf,
-    usage_path: PathBuf,
+    /// Requests that passed the license check and are still running
+    reserved: Arc<AtomicU32>,
+    /// Where the license cache and usage counters persist
+    store: Arc<dyn crate::state_store::StateStore>,
+    /// Optional upstream export for metered billing
+    exporter: Option<crate::usage_export::UsageExporter>,
+    /// Day the expiry reminder was last logged
+    reminded_on: Arc<std::sync::Mutex<Option<chrono::NaiveDate>>>,
 }
 
 #[cfg(feature = "vision")]
 impl VisionLicenseManager {
-    /// Create a new license manager
+    /// Create a new license manager over the configured state store
     pub fn new() -> Self {
-        let cache_dir = dirs::data_dir()
-            .unwrap_or_else(|| PathBuf::from("."))
-            .join("shimmy")
-            .join("vision");
-
-        std::fs::create_dir_all(&cache_dir).ok();
+        Self::with_store(crate::state_store::global())
+    }
 
+    /// Create a license manager that keeps its state in `store`
+    pub fn with_store(store: Arc<dyn crate::state_store::StateStore>) -> Self {
         Self {
             cache: Arc::new(RwLock::new(None)),
             usage: Arc::new(RwLock::new(UsageStats {
@@ -103,24 +278,28 @@ impl VisionLicenseManager {
                 requests_this_month: 0,
                 last_reset: chrono::Utc::now(),
             })),
-            cache_path: cache_dir.join("license_cache.json"),
-            usage_path: cache_dir.join("usage_stats.json"),
+            reserved: Arc::new(AtomicU32::new(0)),
+            store,
+            exporter: crate::usage_export::UsageExporter::from_env(),
+            reminded_on: Arc::new(std::sync::Mutex::new(None)),
         }
     }
 
     /// Load cached license and usage data
-    pub async fn load_cache(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn load_cache(&self) -> crate::error::Result<()> {
+        use crate::state_store::load_json;
+
         // Load cached license
-        if self.cache_path.exists() {
-            let data = tokio::fs::read_to_string(&self.cache_path).await?;
-            let cached: CachedLicense = serde_json::from_str(&data)?;
+        if let Some(cached) = load_json::<CachedLicense>(&*self.store, STATE_NAMESPACE, LICENSE_KEY)
+            .map_err(state_store_failed)?
+        {
             *self.cache.write().await = Some(cached);
         }
 
         // Load usage stats
-        if self.usage_path.exists() {
-            let data = tokio::fs::read_to_string(&self.usage_path).await?;
-            let usage: UsageStats = serde_json::from_str(&data)?;
+        if let Some(usage) = load_json::<UsageStats>(&*self.store, STATE_NAMESPACE, USAGE_KEY)
+            .map_err(state_store_failed)?
+        {
             *self.usage.write().await = usage;
         }
 
@@ -131,7 +310,7 @@ impl VisionLicenseManager {
     pub async fn validate_license(
         &self,
         license_key: &str,
-    ) -> Result<LicenseValidation, Box<dyn std::error::Error>> {
+    ) -> crate::error::Result<LicenseValidation> {
         // Check cache first
         if let Some(cached) = self.cache.read().await.as_ref() {
             if cached.key == license_key {
@@ -162,9 +341,9 @@ impl VisionLicenseManager {
                 .map(|dt| dt.with_timezone(&chrono::Utc)),
         };
 
-        // Save to disk
-        let data = serde_json::to_string_pretty(&cached)?;
-        tokio::fs::write(&self.cache_path, &data).await?;
+        // Persist
+        crate::state_store::save_json(&*self.store, STATE_NAMESPACE, LICENSE_KEY, &cached)
+            .map_err(state_store_failed)?;
 
         *self.cache.write().await = Some(cached);
 
@@ -176,14 +355,24 @@ impl VisionLicenseManager {
         &self,
         license_key: Option<&str>,
     ) -> Result<(), VisionLicenseError> {
+        self.reserve_usage(license_key).await.map(drop)
+    }
+
+    /// Check vision access and hold a place under the monthly cap for one
+    /// request. Requests in flight count against the cap, so concurrent
+    /// requests can't all pass the check before any of them is recorded.
+    pub async fn reserve_usage(
+        &self,
+        license_key: Option<&str>,
+    ) -> Result<UsageReservation<'_>, VisionLicenseError> {
         let Some(key) = license_key else {
             return Err(VisionLicenseError::MissingLicense);
         };
 
-        let validation = self
-            .validate_license(key)
-            .await
-            .map_err(|e| VisionLicenseError::ValidationFailed(e.to_string()))?;
+        let validation = self.validate_license(key).await.map_err(|e| match e {
+            ShimmyError::VisionLicense(license_err) => license_err,
+            other => VisionLicenseError::ValidationFailed(other.to_string()),
+        })?;
 
         if !validation.valid {
             return Err(VisionLicenseError::InvalidLicense);
@@ -194,21 +383,74 @@ impl VisionLicenseManager {
             return Err(VisionLicenseError::FeatureNotEnabled);
         }
 
-        // Check usage limits
-        let usage = self.usage.read().await;
+        // Check usage limits; the write lock keeps reservations one at a time
+        let usage = self.usage.write().await;
+        let reserved = self.reserved.load(Ordering::SeqCst);
         if let Some(monthly_cap) = validation.entitlements.get("monthly_cap") {
             if let Some(cap) = monthly_cap.as_u64() {
-                if usage.requests_this_month >= cap as u32 {
+                if (usage.requests_this_month + reserved) as u64 >= cap {
                     return Err(VisionLicenseError::UsageLimitExceeded);
                 }
             }
         }
+        self.reserved.fetch_add(1, Ordering::SeqCst);
 
-        Ok(())
+        Ok(UsageReservation { manager: self })
     }
 
-    /// Record a vision request for metering
-    pub async fn record_usage(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Renewal warning for the cached license, logging it at most once a day
+    pub async fn license_warning(&self) -> Option<LicenseWarning> {
+        let expires_at = self.cache.read().await.as_ref()?.expires_at?;
+        let now = chrono::Utc::now();
+        let warning = expiry_warning(expires_at, now, license_warning_days())?;
+
+        let today = now.date_naive();
+        let mut reminded_on = self
+            .reminded_on
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *reminded_on != Some(today) {
+            *reminded_on = Some(today);
+            tracing::warn!("{}", warning.message);
+        }
+        Some(warning)
+    }
+
+    /// License state for `key`: validity, expiry, usage and any renewal warning
+    pub async fn status(&self, key: Option<&str>) -> LicenseStatus {
+        let access = self.check_vision_access(key).await;
+        let cached = self
+            .cache
+            .read()
+            .await
+            .clone()
+            .filter(|cached| Some(cached.key.as_str()) == key);
+        let usage = self.usage.read().await.clone();
+        LicenseStatus {
+            configured: key.is_some(),
+            key: key.map(crate::license_store::mask),
+            valid: access.is_ok(),
+            error: access.err().map(|e| e.to_json_error()["error"].clone()),
+            expires_at: cached
+                .as_ref()
+                .and_then(|c| c.validation.expires_at.clone()),
+            requests_today: usage.requests_today,
+            requests_this_month: usage.requests_this_month,
+            monthly_cap: cached
+                .as_ref()
+                .and_then(|c| c.validation.entitlements.get("monthly_cap"))
+                .and_then(|cap| cap.as_u64()),
+            license_warning: match cached {
+                Some(_) => self.license_warning().await,
+                None => None,
+            },
+        }
+    }
+
+    /// Record a vision request for metering. Callers count a request once it
+    /// has produced a response, so requests that fail or time out don't use
+    /// up quota.
+    pub async fn record_usage(&self) -> crate::error::Result<()> {
         let mut usage = self.usage.write().await;
         let now = chrono::Utc::now();
 
@@ -224,28 +466,70 @@ impl VisionLicenseManager {
         usage.requests_today += 1;
         usage.requests_this_month += 1;
 
-        // Save to disk
-        let data = serde_json::to_string_pretty(&*usage)?;
-        tokio::fs::write(&self.usage_path, &data).await?;
+        // Persist
+        crate::state_store::save_json(&*self.store, STATE_NAMESPACE, USAGE_KEY, &*usage).map_err(
+            |e| ShimmyError::UsageRecordingFailed {
+                reason: e.to_string(),
+            },
+        )?;
+        drop(usage);
+
+        // Queue for upstream metered billing, keyed by the validated license
+        if let Some(exporter) = &self.exporter {
+            let license_key = self.cache.read().await.as_ref().map(|c| c.key.clone());
+            if let Some(license_key) = license_key {
+                exporter
+                    .record(crate::usage_export::UsageEvent {
+                        license_key,
+                        feature: "VISION_ANALYSIS".to_string(),
+                        quantity: 1,
+                        timestamp: now,
+                    })
+                    .await;
+            }
+        }
 
         Ok(())
     }
 
+    /// Start the periodic usage export flush, if export is enabled
+    pub fn spawn_usage_flusher(&self) {
+        if let Some(exporter) = &self.exporter {
+            exporter.spawn_flusher();
+        }
+    }
+
+    /// Push any queued usage events upstream (e.g. on shutdown)
+    pub async fn flush_usage_export(&self) -> anyhow::Result<usize> {
+        match &self.exporter {
+            Some(exporter) => exporter.flush().await,
+            None => Ok(0),
+        }
+    }
+
     /// Call Keygen API to validate license
     ///
     /// ## Security Features
     /// - Hard-coded account ID (prevents key-swapping)
     /// - Ed25519 signature verification (prevents MITM/replay)
+    /// - Optional certificate pinning (`SHIMMY_KEYGEN_PINS`)
     /// - Custom User-Agent (enables crack detection)
     async fn call_keygen_validate(
         &self,
         license_key: &str,
-    ) -> Result<LicenseValidation, Box<dyn std::error::Error>> {
+    ) -> crate::error::Result<LicenseValidation> {
         // SECURITY: Account ID is hard-coded to prevent key-swapping attacks
-        // API key (product token) can remain in env as it's server-side only
-        let api_key = std::env::var("KEYGEN_API_KEY")
-            .or_else(|_| std::env::var("KEYGEN_PRODUCT_TOKEN"))
-            .map_err(|_| "KEYGEN_API_KEY or KEYGEN_PRODUCT_TOKEN environment variable not set")?;
+        // Product token is server-side only: read from the config file, with
+        // env vars as an override for development.
+        let api_key = KeygenConfig::load()
+            .resolve_product_token()
+            .ok_or_else(|| {
+                validation_failed(format!(
+                    "Keygen product token not configured (set product_token in {}, or set the \
+                 KEYGEN_PRODUCT_TOKEN environment variable)",
+                    KeygenConfig::path().display()
+                ))
+            })?;
 
         // Build client with custom User-Agent for crack detection
         let user_agent = format!(
@@ -254,7 +538,25 @@ impl VisionLicenseManager {
             std::env::consts::OS,
             std::env::consts::ARCH
         );
-        let client = reqwest::Client::builder().user_agent(&user_agent).build()?;
+        let pins =
+            crate::util::http::CertPins::parse(&std::env::var(KEYGEN_PINS_ENV).unwrap_or_default())
+                .map_err(|e| validation_failed(format!("{}: {}", KEYGEN_PINS_ENV, e)))?;
+        let client = crate::util::http::client_builder()
+            .user_agent(&user_agent)
+            .tls_info(!pins.is_empty())
+            .build()
+            .map_err(validation_failed)?;
+
+        // SECURITY: With pins set, prove the connection reaches Keygen before
+        // the license key goes over it
+        if !pins.is_empty() {
+            let ping = client
+                .get(KEYGEN_PING_URL)
+                .send()
+                .await
+                .map_err(validation_failed)?;
+            pins.check(&ping).map_err(validation_failed)?;
+        }
 
         // Include entitlements and policy in response for full license context
         let url = format!(
@@ -332,10 +634,17 @@ impl VisionLicenseManager {
             .header("Accept", "application/vnd.api+json")
             .json(&request_body)
             .send()
-            .await?;
+            .await
+            .map_err(validation_failed)?;
+        if !pins.is_empty() {
+            pins.check(&response).map_err(validation_failed)?;
+        }
 
         if !response.status().is_success() {
-            return Err(format!("Keygen API error: {}", response.status()).into());
+            return Err(validation_failed(format!(
+                "Keygen API error: {}",
+                response.status()
+            )));
         }
 
         // SECURITY: Extract headers needed for signature verification
@@ -353,7 +662,7 @@ impl VisionLicenseManager {
             .map(|s| s.to_string());
 
         // Get response body as text for signature verification
-        let response_body = response.text().await?;
+        let response_body = response.text().await.map_err(validation_failed)?;
 
         // SECURITY: Verify response signature to prevent MITM attacks
         // Only verify if we have both signature and date headers
@@ -374,7 +683,8 @@ impl VisionLicenseManager {
         }
 
         // Parse the verified response
-        let validate_response: ValidateResponse = serde_json::from_str(&response_body)?;
+        let validate_response: ValidateResponse =
+            serde_json::from_str(&response_body).map_err(validation_failed)?;
 
         // Extract entitlements and usage info
         let mut entitlements = HashMap::new();
@@ -445,7 +755,7 @@ impl VisionLicenseManager {
         sig_header: &str,
         date_header: &str,
         response_body: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> crate::error::Result<()> {
         use base64::{engine::general_purpose::STANDARD, Engine};
         use sha2::{Digest, Sha256};
 
@@ -459,7 +769,9 @@ impl VisionLicenseManager {
                     .strip_prefix("signature=\"")
                     .and_then(|s| s.strip_suffix('"'))
             })
-            .ok_or("Invalid signature header format: missing signature field")?;
+            .ok_or_else(|| {
+                validation_failed("Invalid signature header format: missing signature field")
+            })?;
 
         // Parse algorithm to ensure it's ed25519
         let algorithm = sig_header
@@ -472,11 +784,10 @@ impl VisionLicenseManager {
             });
 
         if algorithm != Some("ed25519") {
-            return Err(format!(
+            return Err(validation_failed(format!(
                 "Unsupported signature algorithm: {:?} (expected ed25519)",
                 algorithm
-            )
-            .into());
+            )));
         }
 
         // Compute SHA-256 digest of response body
@@ -497,24 +808,24 @@ impl VisionLicenseManager {
         );
 
         // Decode the public key from hex
-        let public_key_bytes =
-            hex::decode(KEYGEN_PUBLIC_KEY).map_err(|e| format!("Invalid public key hex: {}", e))?;
+        let public_key_bytes = hex::decode(KEYGEN_PUBLIC_KEY)
+            .map_err(|e| validation_failed(format!("Invalid public key hex: {}", e)))?;
 
         let public_key_array: [u8; 32] = public_key_bytes
             .try_into()
-            .map_err(|_| "Public key must be exactly 32 bytes")?;
+            .map_err(|_| validation_failed("Public key must be exactly 32 bytes"))?;
 
         let verifying_key = VerifyingKey::from_bytes(&public_key_array)
-            .map_err(|e| format!("Invalid Ed25519 public key: {}", e))?;
+            .map_err(|e| validation_failed(format!("Invalid Ed25519 public key: {}", e)))?;
 
         // Decode signature from base64
         let sig_bytes = STANDARD
             .decode(sig_base64)
-            .map_err(|e| format!("Invalid signature base64: {}", e))?;
+            .map_err(|e| validation_failed(format!("Invalid signature base64: {}", e)))?;
 
         let sig_array: [u8; 64] = sig_bytes
             .try_into()
-            .map_err(|_| "Signature must be exactly 64 bytes")?;
+            .map_err(|_| validation_failed("Signature must be exactly 64 bytes"))?;
 
         let signature = Signature::from_bytes(&sig_array);
 
@@ -522,11 +833,11 @@ impl VisionLicenseManager {
         verifying_key
             .verify(signing_string.as_bytes(), &signature)
             .map_err(|e| {
-                format!(
+                validation_failed(format!(
                     "SECURITY WARNING: Response signature verification failed! \
                      Possible MITM attack detected. Error: {}",
                     e
-                )
+                ))
             })?;
 
         tracing::debug!("Keygen response signature verified successfully");
@@ -538,12 +849,17 @@ impl VisionLicenseManager {
     /// Per Keygen docs: "If the signature is valid, but the response date is
     /// older than 5 minutes, we recommend rejecting the response"
     /// See: https://keygen.sh/docs/api/signatures/#response-signatures
-    pub fn check_response_freshness(date_header: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn check_response_freshness(date_header: &str) -> crate::error::Result<()> {
         use chrono::{DateTime, Utc};
 
         // Parse the HTTP date format: "Wed, 09 Jun 2021 16:08:15 GMT"
         let response_time = DateTime::parse_from_rfc2822(date_header)
-            .map_err(|e| format!("Invalid date header format: {} ({})", date_header, e))?
+            .map_err(|e| {
+                validation_failed(format!(
+                    "Invalid date header format: {} ({})",
+                    date_header, e
+                ))
+            })?
             .with_timezone(&Utc);
 
         let now = Utc::now();
@@ -552,23 +868,21 @@ impl VisionLicenseManager {
         // Reject responses older than 5 minutes (replay attack protection)
         const MAX_AGE_SECONDS: i64 = 5 * 60;
         if age.num_seconds() > MAX_AGE_SECONDS {
-            return Err(format!(
+            return Err(validation_failed(format!(
                 "SECURITY WARNING: Response is too old ({} seconds). \
                  Possible replay attack detected. Response date: {}",
                 age.num_seconds(),
                 date_header
-            )
-            .into());
+            )));
         }
 
         // Also reject responses from the future (clock manipulation)
         if age.num_seconds() < -60 {
-            return Err(format!(
+            return Err(validation_failed(format!(
                 "SECURITY WARNING: Response date is in the future. \
                  Possible clock tampering detected. Response date: {}",
                 date_header
-            )
-            .into());
+            )));
         }
 
         Ok(())
@@ -614,6 +928,20 @@ impl Default for VisionLicenseManager {
     }
 }
 
+/// A Keygen call or response check that failed
+#[cfg(feature = "vision")]
+fn validation_failed(reason: impl std::fmt::Display) -> ShimmyError {
+    VisionLicenseError::ValidationFailed(reason.to_string()).into()
+}
+
+/// Reading or writing the license cache failed
+#[cfg(feature = "vision")]
+fn state_store_failed(err: anyhow::Error) -> ShimmyError {
+    ShimmyError::StateStoreFailed {
+        reason: err.to_string(),
+    }
+}
+
 /// License-related errors
 #[cfg(feature = "vision")]
 #[derive(Debug, thiserror::Error)]
@@ -649,17 +977,22 @@ impl VisionLicenseError {
         }
     }
 
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            VisionLicenseError::MissingLicense => "MISSING_LICENSE",
+            VisionLicenseError::ValidationFailed(_) => "VALIDATION_ERROR",
+            VisionLicenseError::InvalidLicense => "INVALID_LICENSE",
+            VisionLicenseError::FeatureNotEnabled => "FEATURE_DISABLED",
+            VisionLicenseError::UsageLimitExceeded => "USAGE_LIMIT_EXCEEDED",
+        }
+    }
+
     /// Convert to JSON error response
     pub fn to_json_error(&self) -> serde_json::Value {
         serde_json::json!({
             "error": {
-                "code": match self {
-                    VisionLicenseError::MissingLicense => "MISSING_LICENSE",
-                    VisionLicenseError::ValidationFailed(_) => "VALIDATION_ERROR",
-                    VisionLicenseError::InvalidLicense => "INVALID_LICENSE",
-                    VisionLicenseError::FeatureNotEnabled => "FEATURE_DISABLED",
-                    VisionLicenseError::UsageLimitExceeded => "USAGE_LIMIT_EXCEEDED",
-                },
+                "code": self.code(),
                 "message": self.to_string()
             }
         })
@@ -0,0 +1,210 @@
+//! Custom vision modes defined in configuration.
+//!
+//! The built-in modes (`ocr`, `layout`, `full`, ...) cover generic screen
+//! analysis. Extracting something specific, like the totals of an invoice,
+//! needs a prompt of its own. `SHIMMY_VISION_MODES_FILE` (or
+//! `vision_modes_file` in `shimmy.toml`) names a JSON file of modes:
+//!
+//! ```json
+//! {
+//!   "invoice": {
+//!     "prompt": "Invoice: read the vendor, invoice date, line items and total due.",
+//!     "fields": {
+//!       "vendor": "string",
+//!       "date": "YYYY-MM-DD",
+//!       "line_items": "[{description,amount}]",
+//!       "total": "number"
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! A request selects one with `mode: "custom:invoice"`. `prompt` replaces the
+//! built-in task description, and `fields` become the schema hint
+//! (`Keys: date(YYYY-MM-DD), line_items(...)`). The model's value for each
+//! field is returned under `fields` in the response, `null` when missing.
+//! `text_blocks` and the other usual keys are still filled in when the model
+//! includes them.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub const MODES_FILE_ENV: &str = "SHIMMY_VISION_MODES_FILE";
+
+/// Prefix of `mode` values naming a custom mode
+pub const CUSTOM_PREFIX: &str = "custom:";
+
+/// One custom mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomMode {
+    /// Task description given to the model
+    pub prompt: String,
+    /// Keys the model should return, each with a short hint of its shape
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+}
+
+impl CustomMode {
+    /// The fields as a schema hint for the prompt
+    pub fn schema_hint(&self) -> String {
+        let keys: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(name, hint)| match hint.trim() {
+                "" => name.clone(),
+                hint => format!("{}({})", name, hint),
+            })
+            .collect();
+        if keys.is_empty() {
+            "Keys: text_blocks([{text,confidence}]).".to_string()
+        } else {
+            format!("Keys: {}.", keys.join(", "))
+        }
+    }
+
+    /// The defined fields of the model's answer, `null` for those it left out
+    pub fn extract(
+        &self,
+        parsed: &serde_json::Value,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        self.fields
+            .keys()
+            .map(|name| {
+                let value = parsed.get(name).cloned().unwrap_or_default();
+                (name.clone(), value)
+            })
+            .collect()
+    }
+}
+
+/// Custom modes by name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VisionModes {
+    modes: BTreeMap<String, CustomMode>,
+}
+
+impl VisionModes {
+    pub fn parse(json: &str) -> Result<Self> {
+        let modes: BTreeMap<String, CustomMode> = serde_json::from_str(json)?;
+        for (name, mode) in &modes {
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                bail!(
+                    "mode name `{}` may only use letters, digits, `-` and `_`",
+                    name
+                );
+            }
+            if mode.prompt.trim().is_empty() {
+                bail!("mode `{}` needs a prompt", name);
+            }
+        }
+        Ok(Self { modes })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("reading {} {}", MODES_FILE_ENV, path.display()))?;
+        Self::parse(&json).with_context(|| format!("invalid {} {}", MODES_FILE_ENV, path.display()))
+    }
+
+    /// Modes from `SHIMMY_VISION_MODES_FILE`, or none when it is unset
+    pub fn from_env() -> Result<Self> {
+        match std::env::var_os(MODES_FILE_ENV).filter(|p| !p.is_empty()) {
+            Some(path) => Self::load(Path::new(&path)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The definition a `custom:<name>` mode refers to; `None` for built-in
+    /// modes, an error for an undefined custom one
+    pub fn resolve(&self, mode: &str) -> Result<Option<&CustomMode>, crate::error::ShimmyError> {
+        let Some(name) = mode.strip_prefix(CUSTOM_PREFIX) else {
+            return Ok(None);
+        };
+        self.modes.get(name).map(Some).ok_or_else(|| {
+            let defined: Vec<&str> = self.modes.keys().map(String::as_str).collect();
+            crate::error::ShimmyError::InvalidRequest {
+                reason: if defined.is_empty() {
+                    format!(
+                        "Unknown mode '{}': no custom modes are defined ({})",
+                        mode, MODES_FILE_ENV
+                    )
+                } else {
+                    format!(
+                        "Unknown mode '{}' (custom modes: {})",
+                        mode,
+                        defined.join(", ")
+                    )
+                },
+            }
+        })
+    }
+}
+
+static GLOBAL: OnceLock<VisionModes> = OnceLock::new();
+
+/// Load `SHIMMY_VISION_MODES_FILE` at startup so a bad file stops the server
+pub fn init() -> Result<()> {
+    let modes = VisionModes::from_env()?;
+    let _ = GLOBAL.set(modes);
+    Ok(())
+}
+
+/// The configured modes, loaded on first use when [`init`] wasn't called
+pub fn global() -> &'static VisionModes {
+    GLOBAL.get_or_init(|| {
+        VisionModes::from_env().unwrap_or_else(|e| {
+            tracing::error!("Custom vision modes unavailable: {:#}", e);
+            VisionModes::default()
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: &str = r#"{
+        "invoice": {
+            "prompt": "Invoice: read the vendor and total due.",
+            "fields": {"vendor": "string", "total": "number", "notes": ""}
+        }
+    }"#;
+
+    #[test]
+    fn test_resolve_and_extract_custom_mode() {
+        let modes = VisionModes::parse(MODES).unwrap();
+        assert_eq!(modes.resolve("ocr").unwrap(), None);
+        let invoice = modes.resolve("custom:invoice").unwrap().unwrap();
+        assert_eq!(
+            invoice.schema_hint(),
+            "Keys: notes, total(number), vendor(string)."
+        );
+
+        let parsed = serde_json::json!({"vendor": "ACME", "total": 12.5, "extra": 1});
+        let fields = invoice.extract(&parsed);
+        assert_eq!(
+            serde_json::Value::Object(fields),
+            serde_json::json!({"vendor": "ACME", "total": 12.5, "notes": null})
+        );
+
+        let err = modes.resolve("custom:receipt").unwrap_err();
+        assert_eq!(err.code(), "INVALID_REQUEST");
+        assert!(err.to_string().contains("custom modes: invoice"));
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_definitions() {
+        assert!(VisionModes::parse(r#"{"in voice": {"prompt": "x"}}"#).is_err());
+        assert!(VisionModes::parse(r#"{"invoice": {"prompt": " "}}"#).is_err());
+        assert!(VisionModes::parse(r#"{"invoice": {"prompt": "x", "model": "y"}}"#).is_err());
+        assert_eq!(VisionModes::parse("{}").unwrap(), VisionModes::default());
+    }
+}
@@ -0,0 +1,176 @@
+//! Input diagnostics for vision requests.
+//!
+//! Poor OCR is often the photo's fault rather than the model's. Each vision
+//! response reports in `meta.image` the format the input was detected as, its
+//! original size, how far it was downscaled, and sharpness and contrast scores
+//! measured on the image the model saw. Scores low enough to make text hard to
+//! read add a warning, so callers know to re-capture the image.
+
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+
+/// Variance of the Laplacian at which `sharpness` is 0.5
+const SHARPNESS_MIDPOINT: f64 = 100.0;
+/// Standard deviation of luma at which `contrast` reaches 1
+const FULL_CONTRAST_STD: f64 = 64.0;
+/// Scores below these are likely to hurt OCR
+const MIN_SHARPNESS: f32 = 0.35;
+const MIN_CONTRAST: f32 = 0.25;
+
+/// Sharpness and contrast of an image, each from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Quality {
+    /// From the variance of the Laplacian; low for blurred or out-of-focus images
+    pub sharpness: f32,
+    /// From the spread of brightness; low for washed-out or underexposed images
+    pub contrast: f32,
+}
+
+impl Quality {
+    pub fn measure(rgb: &RgbImage) -> Self {
+        let luma = image::imageops::grayscale(rgb);
+        let (w, h) = luma.dimensions();
+        let at = |x: u32, y: u32| luma.get_pixel(x, y)[0] as f64;
+
+        let brightness = variance(luma.pixels().map(|p| p[0] as f64));
+        let laplacian = variance((1..h.saturating_sub(1)).flat_map(|y| {
+            (1..w.saturating_sub(1)).map(move |x| {
+                4.0 * at(x, y) - at(x - 1, y) - at(x + 1, y) - at(x, y - 1) - at(x, y + 1)
+            })
+        }));
+        Self {
+            sharpness: (laplacian / (laplacian + SHARPNESS_MIDPOINT)) as f32,
+            contrast: (brightness.sqrt() / FULL_CONTRAST_STD).min(1.0) as f32,
+        }
+    }
+
+    /// The lower of the two scores, since either alone can make text unreadable
+    pub fn score(&self) -> f32 {
+        self.sharpness.min(self.contrast)
+    }
+
+    /// Why the image is likely too degraded for reliable OCR, if it is
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.sharpness < MIN_SHARPNESS {
+            warnings.push(format!(
+                "image looks blurred (sharpness {:.2}); small text may be unreadable, \
+                 consider re-capturing it in focus",
+                self.sharpness
+            ));
+        }
+        if self.contrast < MIN_CONTRAST {
+            warnings.push(format!(
+                "image has low contrast ({:.2}); text may not stand out from the background, \
+                 consider re-capturing it with better lighting",
+                self.contrast
+            ));
+        }
+        warnings
+    }
+}
+
+fn variance(values: impl Iterator<Item = f64>) -> f64 {
+    let (mut n, mut sum, mut sum_sq) = (0.0, 0.0, 0.0);
+    for v in values {
+        n += 1.0;
+        sum += v;
+        sum_sq += v * v;
+    }
+    if n == 0.0 {
+        return 0.0;
+    }
+    let mean = sum / n;
+    (sum_sq / n - mean * mean).max(0.0)
+}
+
+/// Format the image's contents identify it as, e.g. `png` or `jpeg`
+pub fn detect_format(data: &[u8]) -> Option<String> {
+    image::guess_format(data)
+        .ok()
+        .map(|format| format!("{:?}", format).to_lowercase())
+}
+
+/// `meta.image` of a vision response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageDiagnostics {
+    /// Detected from the bytes, not the URL or content type
+    pub format: Option<String>,
+    pub original_width: u32,
+    pub original_height: u32,
+    /// How many times smaller each side was made before the model saw it; 1
+    /// when the image was used at its original size
+    pub downscale_factor: f32,
+    pub sharpness: f32,
+    pub contrast: f32,
+    /// [`Quality::score`]
+    pub quality_score: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl ImageDiagnostics {
+    pub fn new(image: &crate::vision::PreprocessedImage) -> Self {
+        let long_edge = |w: u32, h: u32| w.max(h).max(1) as f32;
+        Self {
+            format: image.format.clone(),
+            original_width: image.original_width,
+            original_height: image.original_height,
+            downscale_factor: long_edge(image.original_width, image.original_height)
+                / long_edge(image.width, image.height),
+            sharpness: image.quality.sharpness,
+            contrast: image.quality.contrast,
+            quality_score: image.quality.score(),
+            warnings: image.quality.warnings(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripes(dark: u8, light: u8) -> RgbImage {
+        RgbImage::from_fn(64, 64, |x, _| {
+            let v = if (x / 2) % 2 == 0 { dark } else { light };
+            image::Rgb([v, v, v])
+        })
+    }
+
+    #[test]
+    fn test_sharp_text_like_image_passes() {
+        let quality = Quality::measure(&stripes(0, 255));
+        assert!(quality.sharpness > 0.9, "{:?}", quality);
+        assert_eq!(quality.contrast, 1.0);
+        assert!(quality.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_degraded_images_warn() {
+        let blurred = image::imageops::blur(&stripes(0, 255), 4.0);
+        let quality = Quality::measure(&blurred);
+        assert!(quality.sharpness < MIN_SHARPNESS, "{:?}", quality);
+        assert!(quality.warnings()[0].contains("blurred"));
+
+        let faded = Quality::measure(&stripes(120, 135));
+        assert!(faded.contrast < MIN_CONTRAST);
+        assert!(faded.warnings().iter().any(|w| w.contains("low contrast")));
+
+        let blank = Quality::measure(&RgbImage::new(8, 8));
+        assert_eq!(
+            (blank.sharpness, blank.contrast, blank.score()),
+            (0.0, 0.0, 0.0)
+        );
+        assert_eq!(blank.warnings().len(), 2);
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(RgbImage::new(2, 2))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        assert_eq!(detect_format(&png).as_deref(), Some("png"));
+        assert_eq!(detect_format(b"not an image"), None);
+    }
+}
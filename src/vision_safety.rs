@@ -0,0 +1,204 @@
+//! Image safety pre-screen for vision requests.
+//!
+//! Deployments that accept user-generated images can run each image past a
+//! small local vision model before the main analysis. The classifier is asked
+//! for a one-word SAFE/UNSAFE answer and its verdict is reported in
+//! `meta.safety` of the vision response.
+//!
+//! `SHIMMY_VISION_SAFETY` (or `vision_safety` in `shimmy.toml`) enables it:
+//!
+//! - `flag`: screen every image and report the verdict, but analyze it anyway
+//! - `block`: refuse images that are not classified safe with `IMAGE_BLOCKED`
+//!
+//! `SHIMMY_VISION_SAFETY_MODEL` names the registry model used as classifier;
+//! without it the request's own vision model screens the image. A classifier
+//! that fails or times out yields an `unknown` verdict, which `block` treats
+//! as unsafe.
+
+use crate::engine::{GenOptions, LoadedModel};
+use crate::error::ShimmyError;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+pub const SAFETY_ENV: &str = "SHIMMY_VISION_SAFETY";
+pub const SAFETY_MODEL_ENV: &str = "SHIMMY_VISION_SAFETY_MODEL";
+
+/// Upper bound for one classification
+const SAFETY_TIMEOUT: Duration = Duration::from_secs(30);
+
+const SAFETY_PROMPT: &str = "<|im_start|>user\nDoes this image contain nudity, sexual content, gore or graphic violence? Answer with exactly one word: UNSAFE if it does, SAFE if it does not.<|im_end|>\n<|im_start|>assistant\n";
+
+/// What to do with the classifier's verdict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SafetyMode {
+    Flag,
+    Block,
+}
+
+impl SafetyMode {
+    pub fn parse(value: &str) -> Option<Option<Self>> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "0" | "off" | "false" => Some(None),
+            "flag" => Some(Some(SafetyMode::Flag)),
+            "block" | "1" | "on" | "true" => Some(Some(SafetyMode::Block)),
+            _ => None,
+        }
+    }
+
+    /// The configured mode, or `None` when screening is off
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var(SAFETY_ENV).ok()?;
+        Self::parse(&value).unwrap_or_else(|| {
+            tracing::warn!(
+                "Unrecognized {}={:?} (expected off, flag or block); blocking",
+                SAFETY_ENV,
+                value
+            );
+            Some(SafetyMode::Block)
+        })
+    }
+}
+
+/// Classifier answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Safe,
+    Unsafe,
+    /// The classifier failed, timed out or gave an answer that wasn't a verdict
+    Unknown,
+}
+
+/// Safety check result reported in the vision response's `meta.safety`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafetyCheck {
+    pub verdict: Verdict,
+    /// Model that classified the image
+    pub model: String,
+    pub duration_ms: u64,
+}
+
+impl SafetyCheck {
+    /// One check for several images: the least safe verdict and the total time
+    pub fn merge(self, other: SafetyCheck) -> SafetyCheck {
+        let rank = |verdict| match verdict {
+            Verdict::Safe => 0,
+            Verdict::Unknown => 1,
+            Verdict::Unsafe => 2,
+        };
+        SafetyCheck {
+            verdict: if rank(other.verdict) > rank(self.verdict) {
+                other.verdict
+            } else {
+                self.verdict
+            },
+            model: self.model,
+            duration_ms: self.duration_ms + other.duration_ms,
+        }
+    }
+}
+
+/// Read the verdict from the classifier's first word
+pub fn parse_verdict(output: &str) -> Verdict {
+    let word = output
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find(|w| !w.is_empty())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match word.as_str() {
+        "unsafe" | "nsfw" => Verdict::Unsafe,
+        "safe" | "sfw" => Verdict::Safe,
+        _ => Verdict::Unknown,
+    }
+}
+
+/// Classify `image` with `model`; in [`SafetyMode::Block`] anything but a
+/// safe verdict becomes [`ShimmyError::ImageBlocked`]
+pub async fn screen(
+    image: &[u8],
+    model: &dyn LoadedModel,
+    model_name: &str,
+    mode: SafetyMode,
+) -> Result<SafetyCheck, ShimmyError> {
+    let start = Instant::now();
+    let opts = GenOptions {
+        max_tokens: 4,
+        temperature: 0.0,
+        stream: false,
+        stop_tokens: vec!["</s>".to_string(), "<|im_end|>".to_string()],
+        ..Default::default()
+    };
+
+    let verdict = match tokio::time::timeout(
+        SAFETY_TIMEOUT,
+        model.generate_vision(&[image], SAFETY_PROMPT, opts, None),
+    )
+    .await
+    {
+        Ok(Ok(output)) => parse_verdict(&output),
+        Ok(Err(e)) => {
+            tracing::warn!("Image safety check with {} failed: {}", model_name, e);
+            Verdict::Unknown
+        }
+        Err(_) => {
+            tracing::warn!(
+                "Image safety check with {} timed out after {}s",
+                model_name,
+                SAFETY_TIMEOUT.as_secs()
+            );
+            Verdict::Unknown
+        }
+    };
+
+    if mode == SafetyMode::Block && verdict != Verdict::Safe {
+        return Err(ShimmyError::ImageBlocked {
+            reason: match verdict {
+                Verdict::Unsafe => format!("classified unsafe by {}", model_name),
+                _ => format!("{} could not classify it", model_name),
+            },
+        });
+    }
+    if verdict == Verdict::Unsafe {
+        tracing::warn!("Image flagged unsafe by {}", model_name);
+    }
+
+    Ok(SafetyCheck {
+        verdict,
+        model: model_name.to_string(),
+        duration_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_verdict_and_mode() {
+        assert_eq!(parse_verdict("SAFE"), Verdict::Safe);
+        assert_eq!(parse_verdict(" Unsafe."), Verdict::Unsafe);
+        assert_eq!(parse_verdict("**NSFW**"), Verdict::Unsafe);
+        assert_eq!(parse_verdict("The image shows"), Verdict::Unknown);
+        assert_eq!(parse_verdict(""), Verdict::Unknown);
+
+        assert_eq!(SafetyMode::parse("off"), Some(None));
+        assert_eq!(SafetyMode::parse("Flag"), Some(Some(SafetyMode::Flag)));
+        assert_eq!(SafetyMode::parse("block"), Some(Some(SafetyMode::Block)));
+        assert_eq!(SafetyMode::parse("strict"), None);
+    }
+
+    #[test]
+    fn test_merge_keeps_least_safe_verdict() {
+        let check = |verdict, duration_ms| SafetyCheck {
+            verdict,
+            model: "classifier".to_string(),
+            duration_ms,
+        };
+        let merged = check(Verdict::Safe, 10)
+            .merge(check(Verdict::Unknown, 20))
+            .merge(check(Verdict::Safe, 5));
+        assert_eq!((merged.verdict, merged.duration_ms), (Verdict::Unknown, 35));
+        let merged = check(Verdict::Unsafe, 1).merge(check(Verdict::Unknown, 1));
+        assert_eq!(merged.verdict, Verdict::Unsafe);
+    }
+}
@@ -0,0 +1,280 @@
+//! Local Tesseract OCR backend for `mode: "ocr"` vision requests.
+//!
+//! Tesseract is much faster than a vision model on plain text and needs no
+//! model download. `SHIMMY_VISION_OCR_BACKEND` (or `vision_ocr_backend` in
+//! `shimmy.toml`) picks who answers OCR requests:
+//!
+//! - `model` (default): the vision model, as for every other mode
+//! - `tesseract`: always tesseract
+//! - `auto`: tesseract only when no vision model is available
+//!
+//! Tesseract runs as the `tesseract` executable (`SHIMMY_TESSERACT_PATH`
+//! overrides it) with the languages in `SHIMMY_TESSERACT_LANG` (default
+//! `eng`). Its TSV output gives each text block a real confidence and a
+//! bounding box, returned in the usual [`VisionResponse`] shape.
+
+use crate::error::ShimmyError;
+use crate::vision::{
+    Interaction, Layout, Meta, PreprocessedImage, Rect, TextBlock, VisionRequest, VisionResponse,
+    Visual, SCHEMA_VERSION,
+};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+pub const OCR_BACKEND_ENV: &str = "SHIMMY_VISION_OCR_BACKEND";
+pub const TESSERACT_PATH_ENV: &str = "SHIMMY_TESSERACT_PATH";
+pub const TESSERACT_LANG_ENV: &str = "SHIMMY_TESSERACT_LANG";
+
+/// Who answers `mode: "ocr"` requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OcrBackend {
+    Model,
+    Tesseract,
+    /// Tesseract when no vision model is available
+    Auto,
+}
+
+impl OcrBackend {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "model" => Some(OcrBackend::Model),
+            "tesseract" => Some(OcrBackend::Tesseract),
+            "auto" => Some(OcrBackend::Auto),
+            _ => None,
+        }
+    }
+
+    /// The configured backend, `model` when unset or unrecognized
+    pub fn from_env() -> Self {
+        let Ok(value) = std::env::var(OCR_BACKEND_ENV) else {
+            return OcrBackend::Model;
+        };
+        Self::parse(&value).unwrap_or_else(|| {
+            tracing::warn!(
+                "Unrecognized {}={:?} (expected model, tesseract or auto); using the vision model",
+                OCR_BACKEND_ENV,
+                value
+            );
+            OcrBackend::Model
+        })
+    }
+}
+
+/// Read the text of every image with tesseract. Boxes are given for the
+/// first image only, which is the one `coordinates` refer to.
+pub async fn analyze(
+    req: &VisionRequest,
+    images: &[PreprocessedImage],
+    start_time: Instant,
+) -> Result<VisionResponse, ShimmyError> {
+    let timeout_ms = req.timeout_ms.unwrap_or(60_000);
+    let mut text_blocks = Vec::new();
+    let mut raw_output = String::new();
+    for (i, image) in images.iter().enumerate() {
+        let tsv = tokio::time::timeout(Duration::from_millis(timeout_ms), run(&image.bytes))
+            .await
+            .map_err(|_| ShimmyError::InferenceTimeout { timeout_ms })??;
+        let mut blocks = parse_tsv(&tsv, image.width, image.height);
+        if i > 0 {
+            blocks.iter_mut().for_each(|block| block.position = None);
+        }
+        text_blocks.extend(blocks);
+        raw_output.push_str(&tsv);
+    }
+
+    Ok(VisionResponse {
+        schema_version: SCHEMA_VERSION,
+        image_path: None,
+        url: req.url.clone(),
+        mode: req.mode.clone(),
+        text_blocks,
+        layout: Layout {
+            theme: None,
+            regions: vec![],
+            key_ui_elements: vec![],
+        },
+        visual: Visual {
+            background: None,
+            accent_colors: vec![],
+            contrast: None,
+            description: None,
+        },
+        interaction: Interaction { description: None },
+        dom_map: None,
+        detections: Vec::new(),
+        fields: None,
+        timeline: Vec::new(),
+        meta: Meta {
+            model: "tesseract".to_string(),
+            backend: "tesseract".to_string(),
+            duration_ms: start_time.elapsed().as_millis() as u64,
+            parse_warnings: None,
+            safety: None,
+            cached: false,
+            image: None,
+            images: None,
+            video: None,
+        },
+        raw_model_output: req.raw.unwrap_or(false).then_some(raw_output),
+        license_warning: None,
+    })
+}
+
+/// Run tesseract on one encoded image and return its TSV output
+async fn run(image: &[u8]) -> Result<String, ShimmyError> {
+    let program = std::env::var(TESSERACT_PATH_ENV).unwrap_or_else(|_| "tesseract".to_string());
+    let lang = std::env::var(TESSERACT_LANG_ENV).unwrap_or_else(|_| "eng".to_string());
+    let failed = |reason: String| ShimmyError::InferenceFailed { reason };
+
+    let mut child = tokio::process::Command::new(&program)
+        .args(["stdin", "stdout", "-l", lang.as_str(), "tsv"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| ShimmyError::VisionModelUnavailable {
+            reason: format!(
+                "Could not run tesseract ({}): {}. Install it or set {} to its path.",
+                program, e, TESSERACT_PATH_ENV
+            ),
+        })?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let image = image.to_vec();
+    let writer = tokio::spawn(async move {
+        let written = stdin.write_all(&image).await;
+        drop(stdin);
+        written
+    });
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| failed(format!("tesseract failed: {}", e)))?;
+    writer
+        .await
+        .map_err(|e| failed(format!("tesseract input failed: {}", e)))?
+        .map_err(|e| failed(format!("tesseract input failed: {}", e)))?;
+    if !output.status.success() {
+        return Err(failed(format!(
+            "tesseract exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Group the words of tesseract's TSV output into one text block per
+/// tesseract block, with lines joined by newlines, the mean word confidence
+/// and the union of the word boxes normalized by the image size
+pub fn parse_tsv(tsv: &str, width: u32, height: u32) -> Vec<TextBlock> {
+    struct Block {
+        lines: BTreeMap<(u32, u32), Vec<String>>,
+        confidence: f32,
+        words: u32,
+        bounds: (u32, u32, u32, u32),
+    }
+
+    let mut blocks: BTreeMap<(u32, u32), Block> = BTreeMap::new();
+    for line in tsv.lines().skip(1) {
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() < 12 || cols[0] != "5" {
+            continue;
+        }
+        let text = cols[11].trim();
+        let num = |i: usize| cols[i].trim().parse::<u32>().ok();
+        let confidence = cols[10].trim().parse::<f32>().unwrap_or(-1.0);
+        let (Some(page), Some(block), Some(par), Some(line_no)) = (num(1), num(2), num(3), num(4))
+        else {
+            continue;
+        };
+        let (Some(left), Some(top), Some(w), Some(h)) = (num(6), num(7), num(8), num(9)) else {
+            continue;
+        };
+        if text.is_empty() || confidence < 0.0 {
+            continue;
+        }
+
+        let entry = blocks.entry((page, block)).or_insert(Block {
+            lines: BTreeMap::new(),
+            confidence: 0.0,
+            words: 0,
+            bounds: (left, top, left + w, top + h),
+        });
+        entry
+            .lines
+            .entry((par, line_no))
+            .or_default()
+            .push(text.to_string());
+        entry.confidence += confidence;
+        entry.words += 1;
+        let b = &mut entry.bounds;
+        *b = (
+            b.0.min(left),
+            b.1.min(top),
+            b.2.max(left + w),
+            b.3.max(top + h),
+        );
+    }
+
+    let (w, h) = (width.max(1) as f32, height.max(1) as f32);
+    blocks
+        .into_values()
+        .map(|block| {
+            let (x0, y0, x1, y1) = block.bounds;
+            let lines: Vec<String> = block.lines.into_values().map(|l| l.join(" ")).collect();
+            TextBlock {
+                text: lines.join("\n"),
+                confidence: Some(block.confidence / block.words as f32 / 100.0),
+                position: Some(Rect {
+                    x: x0 as f32 / w,
+                    y: y0 as f32 / h,
+                    width: (x1 - x0) as f32 / w,
+                    height: (y1 - y0) as f32 / h,
+                }),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TSV: &str = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t200\t100\t-1\t
+2\t1\t1\t0\t0\t0\t10\t10\t80\t30\t-1\t
+5\t1\t1\t1\t1\t1\t10\t10\t40\t10\t96.5\tHello
+5\t1\t1\t1\t1\t2\t60\t10\t30\t10\t91.5\tworld
+5\t1\t1\t1\t2\t1\t10\t30\t50\t10\t88\tSecond
+5\t1\t2\t1\t1\t1\t100\t60\t20\t10\t-1\t
+5\t1\t2\t1\t1\t2\t100\t60\t50\t20\t70\tSign-in
+";
+
+    #[test]
+    fn test_parse_tsv_groups_words_into_blocks() {
+        let blocks = parse_tsv(TSV, 200, 100);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].text, "Hello world\nSecond");
+        assert!((blocks[0].confidence.unwrap() - 0.92).abs() < 1e-4);
+        let rect = blocks[0].position.as_ref().unwrap();
+        assert_eq!(
+            (rect.x, rect.y, rect.width, rect.height),
+            (0.05, 0.1, 0.4, 0.3)
+        );
+
+        assert_eq!(blocks[1].text, "Sign-in");
+        assert_eq!(blocks[1].confidence, Some(0.7));
+        assert!(parse_tsv("level\tpage_num\n", 10, 10).is_empty());
+    }
+
+    #[test]
+    fn test_backend_parse() {
+        assert_eq!(OcrBackend::parse("Tesseract"), Some(OcrBackend::Tesseract));
+        assert_eq!(OcrBackend::parse("auto"), Some(OcrBackend::Auto));
+        assert_eq!(OcrBackend::parse(""), Some(OcrBackend::Model));
+        assert_eq!(OcrBackend::parse("paddle"), None);
+    }
+}
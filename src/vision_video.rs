@@ -0,0 +1,174 @@
+//! Keyframe extraction for `video` vision requests.
+//!
+//! A request with `video` (base64 or a URL) has frames sampled from it at
+//! `fps` frames per second (default 1), up to `max_frames`. Each frame is
+//! analyzed like a single image with the request's mode, and the response
+//! lists the results as a `timeline` of timestamped frames. That suits QA of
+//! screen recordings (what did the screen say at 0:42?) and summarizing
+//! short camera clips.
+//!
+//! Frames are decoded by the `ffmpeg` executable (`SHIMMY_FFMPEG_PATH`
+//! overrides it), so any container and codec it reads works.
+//! `SHIMMY_VISION_MAX_FRAMES` (default 32) caps `max_frames`; a video longer
+//! than `max_frames / fps` seconds is analyzed up to that point and reported
+//! as `truncated` in `meta.video`. The whole video counts as one request
+//! against the license.
+
+use crate::error::ShimmyError;
+use std::time::Duration;
+
+pub const FFMPEG_PATH_ENV: &str = "SHIMMY_FFMPEG_PATH";
+pub const MAX_FRAMES_ENV: &str = "SHIMMY_VISION_MAX_FRAMES";
+
+const DEFAULT_FPS: f32 = 1.0;
+const DEFAULT_MAX_FRAMES: usize = 32;
+/// Faster sampling than this is rarely useful and multiplies inference time
+const MAX_FPS: f32 = 30.0;
+
+/// Which frames to take from a video
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    pub fps: f32,
+    pub max_frames: usize,
+}
+
+impl Sampling {
+    /// The request's `fps` and `max_frames`, checked against the limits
+    pub fn new(fps: Option<f32>, max_frames: Option<usize>) -> Result<Self, ShimmyError> {
+        let cap = std::env::var(MAX_FRAMES_ENV)
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(DEFAULT_MAX_FRAMES);
+        let invalid = |reason: String| ShimmyError::InvalidRequest { reason };
+
+        let fps = fps.unwrap_or(DEFAULT_FPS);
+        if !(fps > 0.0 && fps <= MAX_FPS) {
+            return Err(invalid(format!(
+                "video.fps must be above 0 and at most {}",
+                MAX_FPS
+            )));
+        }
+        let max_frames = max_frames.unwrap_or(cap);
+        if max_frames == 0 || max_frames > cap {
+            return Err(invalid(format!(
+                "video.max_frames must be between 1 and {} ({})",
+                cap, MAX_FRAMES_ENV
+            )));
+        }
+        Ok(Self { fps, max_frames })
+    }
+
+    /// Position of the `index`th sampled frame
+    pub fn timestamp_ms(&self, index: usize) -> u64 {
+        (index as f64 * 1000.0 / self.fps as f64).round() as u64
+    }
+}
+
+/// One decoded frame, PNG-encoded
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub timestamp_ms: u64,
+    pub png: Vec<u8>,
+}
+
+/// Sample frames from an encoded video. The flag is set when `max_frames`
+/// was reached before the video ended.
+pub async fn extract_frames(
+    video: &[u8],
+    sampling: Sampling,
+    timeout: Duration,
+) -> Result<(Vec<Frame>, bool), ShimmyError> {
+    let program = std::env::var(FFMPEG_PATH_ENV).unwrap_or_else(|_| "ffmpeg".to_string());
+    let failed = |reason: String| ShimmyError::ImagePreprocessFailed { reason };
+
+    // ffmpeg needs a seekable input for containers that index at the end
+    let dir = tempfile::tempdir().map_err(|e| failed(format!("no temp directory: {}", e)))?;
+    let input = dir.path().join("input");
+    tokio::fs::write(&input, video)
+        .await
+        .map_err(|e| failed(format!("could not stage the video: {}", e)))?;
+
+    // One frame past the limit tells whether the video went on
+    let output = tokio::process::Command::new(&program)
+        .args(["-hide_banner", "-loglevel", "error", "-nostdin", "-i"])
+        .arg(&input)
+        .args(["-vf", &format!("fps={}", sampling.fps)])
+        .args(["-frames:v", &(sampling.max_frames + 1).to_string()])
+        .arg(dir.path().join("frame_%05d.png"))
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(timeout, output)
+        .await
+        .map_err(|_| ShimmyError::InferenceTimeout {
+            timeout_ms: timeout.as_millis() as u64,
+        })?
+        .map_err(|e| ShimmyError::VisionModelUnavailable {
+            reason: format!(
+                "Could not run ffmpeg ({}): {}. Install it or set {} to its path.",
+                program, e, FFMPEG_PATH_ENV
+            ),
+        })?;
+    if !output.status.success() {
+        return Err(failed(format!(
+            "ffmpeg could not decode the video: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let mut names: Vec<_> = std::fs::read_dir(dir.path())
+        .map_err(|e| failed(format!("could not read the frames: {}", e)))?
+        .filter_map(|entry| entry.ok().map(|e| e.file_name()))
+        .filter(|name| name.to_string_lossy().starts_with("frame_"))
+        .collect();
+    names.sort();
+    let truncated = names.len() > sampling.max_frames;
+    names.truncate(sampling.max_frames);
+
+    let mut frames = Vec::with_capacity(names.len());
+    for (i, name) in names.iter().enumerate() {
+        let png = tokio::fs::read(dir.path().join(name))
+            .await
+            .map_err(|e| failed(format!("could not read frame {}: {}", i, e)))?;
+        frames.push(Frame {
+            timestamp_ms: sampling.timestamp_ms(i),
+            png,
+        });
+    }
+    if frames.is_empty() {
+        return Err(failed("the video has no frames to analyze".to_string()));
+    }
+    Ok((frames, truncated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_limits_and_timestamps() {
+        std::env::remove_var(MAX_FRAMES_ENV);
+        let default = Sampling::new(None, None).unwrap();
+        assert_eq!(
+            default,
+            Sampling {
+                fps: 1.0,
+                max_frames: DEFAULT_MAX_FRAMES
+            }
+        );
+        assert_eq!(default.timestamp_ms(3), 3000);
+
+        let fast = Sampling::new(Some(4.0), Some(10)).unwrap();
+        assert_eq!(fast.timestamp_ms(5), 1250);
+        let slow = Sampling::new(Some(0.2), None).unwrap();
+        assert_eq!(slow.timestamp_ms(2), 10_000);
+
+        assert!(Sampling::new(Some(0.0), None).is_err());
+        assert!(Sampling::new(Some(f32::NAN), None).is_err());
+        assert!(Sampling::new(Some(60.0), None).is_err());
+        assert!(Sampling::new(None, Some(0)).is_err());
+        assert!(Sampling::new(None, Some(DEFAULT_MAX_FRAMES + 1)).is_err());
+    }
+}
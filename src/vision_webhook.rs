@@ -0,0 +1,281 @@
+//! Asynchronous vision jobs delivered by webhook.
+//!
+//! A vision request with `callback_url` is answered right away with
+//! `202 Accepted` and a job id; the analysis runs in the background and its
+//! outcome is POSTed to the callback. This suits job-queue integrations that
+//! can't hold a connection open for a slow CPU inference.
+//!
+//! Deliveries are signed with `SHIMMY_VISION_WEBHOOK_SECRET`, which must be
+//! set for `callback_url` to be accepted. The receiver recomputes
+//! `HMAC-SHA256(secret, "<X-Shimmy-Timestamp>.<body>")` and compares it with
+//! the hex digest in `X-Shimmy-Signature: sha256=<digest>`. Failed deliveries
+//! (network errors and non-2xx answers) are retried a few times with backoff.
+
+use crate::error::ShimmyError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+pub const WEBHOOK_SECRET_ENV: &str = "SHIMMY_VISION_WEBHOOK_SECRET";
+
+pub const SIGNATURE_HEADER: &str = "x-shimmy-signature";
+pub const TIMESTAMP_HEADER: &str = "x-shimmy-timestamp";
+pub const JOB_ID_HEADER: &str = "x-shimmy-job-id";
+
+/// Delays before each retry of a failed delivery
+const RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+    Duration::from_secs(30),
+];
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Body of the `202 Accepted` answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobAccepted {
+    pub job_id: String,
+    pub status: JobStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Accepted,
+    Completed,
+    Failed,
+}
+
+/// What the callback receives
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub job_id: String,
+    pub status: JobStatus,
+    /// The vision response, when the job completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<crate::vision::VisionResponse>,
+    /// HTTP status the synchronous request would have failed with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_status: Option<u16>,
+    /// Error body the synchronous request would have returned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<serde_json::Value>,
+}
+
+impl WebhookPayload {
+    pub fn from_result(
+        job_id: String,
+        result: crate::error::Result<crate::vision::VisionResponse>,
+    ) -> Self {
+        match result {
+            Ok(response) => Self {
+                job_id,
+                status: JobStatus::Completed,
+                result: Some(response),
+                error_status: None,
+                error: None,
+            },
+            Err(e) => {
+                let (status, mut body) = e.response_body();
+                Self {
+                    job_id,
+                    status: JobStatus::Failed,
+                    result: None,
+                    error_status: Some(status.as_u16()),
+                    error: body.get_mut("error").map(serde_json::Value::take),
+                }
+            }
+        }
+    }
+}
+
+/// Check `callback_url` and the signing secret before the job is accepted
+pub fn validate_callback(url: &str, secret: Option<&str>) -> Result<reqwest::Url, ShimmyError> {
+    let invalid = |reason: String| ShimmyError::InvalidRequest { reason };
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| invalid(format!("callback_url: not a valid URL ({})", e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(invalid(
+            "callback_url: must be an http or https URL".to_string(),
+        ));
+    }
+    if secret.is_none_or(|s| s.trim().is_empty()) {
+        return Err(invalid(format!(
+            "callback_url needs {} to be set so deliveries can be signed",
+            WEBHOOK_SECRET_ENV
+        )));
+    }
+    Ok(parsed)
+}
+
+/// HMAC-SHA256 (RFC 2104) of `message` under `key`
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// `X-Shimmy-Signature` value for a delivery made at `timestamp`
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(body);
+    format!(
+        "sha256={}",
+        hex::encode(hmac_sha256(secret.as_bytes(), &message))
+    )
+}
+
+/// POST `payload` to `url`, retrying failed attempts
+pub async fn deliver(url: reqwest::Url, secret: String, payload: WebhookPayload) {
+    let body = match serde_json::to_vec(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!(
+                "Vision job {}: could not encode webhook: {}",
+                payload.job_id,
+                e
+            );
+            return;
+        }
+    };
+    let client = match crate::util::http::client_builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!(
+                "Vision job {}: webhook client failed: {}",
+                payload.job_id,
+                e
+            );
+            return;
+        }
+    };
+
+    let attempts = RETRY_DELAYS.len() + 1;
+    for attempt in 1..=attempts {
+        let timestamp = chrono::Utc::now().timestamp();
+        let result = client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature(&secret, timestamp, &body))
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(JOB_ID_HEADER, &payload.job_id)
+            .body(body.clone())
+            .send()
+            .await;
+        let failure = match result {
+            Ok(response) if response.status().is_success() => {
+                tracing::info!(
+                    "Vision job {}: delivered to {} (attempt {})",
+                    payload.job_id,
+                    url,
+                    attempt
+                );
+                return;
+            }
+            Ok(response) => format!("callback answered {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        match RETRY_DELAYS.get(attempt - 1) {
+            Some(delay) => {
+                tracing::warn!(
+                    "Vision job {}: delivery attempt {} failed ({}); retrying in {}s",
+                    payload.job_id,
+                    attempt,
+                    failure,
+                    delay.as_secs()
+                );
+                tokio::time::sleep(*delay).await;
+            }
+            None => tracing::error!(
+                "Vision job {}: giving up after {} delivery attempts: {}",
+                payload.job_id,
+                attempts,
+                failure
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256_rfc4231() {
+        // RFC 4231 test cases 2 and 6 (short key, key longer than a block)
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex::encode(hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_signature_covers_timestamp_and_body() {
+        let sig = signature("secret", 1_700_000_000, b"{}");
+        assert!(sig.starts_with("sha256="));
+        assert_eq!(sig, signature("secret", 1_700_000_000, b"{}"));
+        assert_ne!(sig, signature("secret", 1_700_000_001, b"{}"));
+        assert_ne!(sig, signature("other", 1_700_000_000, b"{}"));
+    }
+
+    #[test]
+    fn test_validate_callback() {
+        assert!(validate_callback("https://jobs.example.com/done", Some("s")).is_ok());
+        for (url, secret) in [
+            ("https://jobs.example.com/done", None),
+            ("https://jobs.example.com/done", Some(" ")),
+            ("ftp://jobs.example.com/done", Some("s")),
+            ("not a url", Some("s")),
+        ] {
+            assert!(
+                matches!(
+                    validate_callback(url, secret),
+                    Err(ShimmyError::InvalidRequest { .. })
+                ),
+                "{url} / {secret:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_failed_payload_carries_error_body() {
+        let payload = WebhookPayload::from_result(
+            "job-1".to_string(),
+            Err(ShimmyError::InvalidRequest {
+                reason: "bad image".to_string(),
+            }),
+        );
+        assert_eq!(payload.status, JobStatus::Failed);
+        assert_eq!(payload.error_status, Some(400));
+        let json = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["status"], "failed");
+        assert!(json.get("result").is_none());
+        assert!(json["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("bad image"));
+    }
+}
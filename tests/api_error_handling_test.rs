@@ -69,10 +69,20 @@ async fn test_chat_completions_model_not_found_response() {
             content: "Hello".to_string(),
         }],
         stream: Some(false),
+        stream_options: None,
         temperature: None,
         max_tokens: None,
         top_p: None,
         stop: None,
+        max_time_ms: None,
+        tools: None,
+        tool_choice: None,
+        response_format: None,
+        grammar: None,
+        keep_alive: None,
+        adapter: None,
+        logprobs: None,
+        top_logprobs: None,
     };
 
     // Exercise the handler - should return 404 with JSON error
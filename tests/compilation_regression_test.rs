@@ -149,6 +149,8 @@ fn test_model_registry_thread_safety() {
                     template: Some("chatml".to_string()),
                     ctx_len: Some(2048),
                     n_threads: None,
+                    n_gpu_layers: None,
+                    mmproj_path: None,
                 };
 
                 let mut reg = registry.lock().unwrap();
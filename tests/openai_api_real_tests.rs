@@ -22,6 +22,8 @@ fn setup_test_state_with_models() -> Arc<AppState> {
         template: Some("chatml".into()),
         ctx_len: Some(4096),
         n_threads: None,
+        n_gpu_layers: None,
+        mmproj_path: None,
     });
 
     registry.register(ModelEntry {
@@ -31,6 +33,8 @@ fn setup_test_state_with_models() -> Arc<AppState> {
         template: Some("llama3".into()),
         ctx_len: Some(8192),
         n_threads: None,
+        n_gpu_layers: None,
+        mmproj_path: None,
     });
 
     registry.register(ModelEntry {
@@ -40,6 +44,8 @@ fn setup_test_state_with_models() -> Arc<AppState> {
         template: Some("chatml".into()),
         ctx_len: Some(2048),
         n_threads: None,
+        n_gpu_layers: None,
+        mmproj_path: None,
     });
 
     let engine = Box::new(InferenceEngineAdapter::new());
@@ -97,10 +103,20 @@ async fn test_chat_completions_error_handling_real() {
             content: "Hello".to_string(),
         }],
         stream: Some(false),
+        stream_options: None,
         temperature: Some(0.7),
         max_tokens: Some(50),
         top_p: None,
         stop: None,
+        max_time_ms: None,
+        tools: None,
+        tool_choice: None,
+        response_format: None,
+        grammar: None,
+        keep_alive: None,
+        adapter: None,
+        logprobs: None,
+        top_logprobs: None,
     };
 
     let response = openai_compat::chat_completions(State(state), Json(request)).await;
@@ -135,10 +151,20 @@ fn test_chat_completions_model_loading_failure() {
             content: "Hello, this should fail to load the model".to_string(),
         }],
         stream: Some(false),
+        stream_options: None,
         temperature: Some(0.7),
         max_tokens: Some(100),
         top_p: Some(0.9),
         stop: None,
+        max_time_ms: None,
+        tools: None,
+        tool_choice: None,
+        response_format: None,
+        grammar: None,
+        keep_alive: None,
+        adapter: None,
+        logprobs: None,
+        top_logprobs: None,
     };
 
     // Verify request structure for model loading scenarios
@@ -169,10 +195,20 @@ fn test_system_message_handling() {
             },
         ],
         stream: Some(false),
+        stream_options: None,
         temperature: Some(0.5),
         max_tokens: Some(50),
         top_p: Some(0.8),
         stop: None,
+        max_time_ms: None,
+        tools: None,
+        tool_choice: None,
+        response_format: None,
+        grammar: None,
+        keep_alive: None,
+        adapter: None,
+        logprobs: None,
+        top_logprobs: None,
     };
 
     // Verify the request structure is correct for multi-message scenarios
@@ -197,10 +233,20 @@ fn test_streaming_request_processing() {
             content: "Count from 1 to 5".to_string(),
         }],
         stream: Some(true),
+        stream_options: None,
         temperature: Some(0.3),
         max_tokens: Some(50),
         top_p: None,
         stop: None,
+        max_time_ms: None,
+        tools: None,
+        tool_choice: None,
+        response_format: None,
+        grammar: None,
+        keep_alive: None,
+        adapter: None,
+        logprobs: None,
+        top_logprobs: None,
     };
 
     // Verify streaming request structure
@@ -307,10 +353,20 @@ fn test_generation_options_parsing() {
             content: "Test".to_string(),
         }],
         stream: Some(true),
+        stream_options: None,
         temperature: Some(0.8),
         max_tokens: Some(150),
         top_p: Some(0.95),
         stop: None,
+        max_time_ms: None,
+        tools: None,
+        tool_choice: None,
+        response_format: None,
+        grammar: None,
+        keep_alive: None,
+        adapter: None,
+        logprobs: None,
+        top_logprobs: None,
     };
 
     // Verify the request structure matches what Open WebUI/AnythingLLM send
@@ -329,10 +385,20 @@ fn test_generation_options_parsing() {
             content: "Test".to_string(),
         }],
         stream: None,
+        stream_options: None,
         temperature: None,
         max_tokens: None,
         top_p: None,
         stop: None,
+        max_time_ms: None,
+        tools: None,
+        tool_choice: None,
+        response_format: None,
+        grammar: None,
+        keep_alive: None,
+        adapter: None,
+        logprobs: None,
+        top_logprobs: None,
     };
 
     assert!(minimal_request.stream.is_none());
@@ -343,7 +409,7 @@ fn test_generation_options_parsing() {
 
 #[test]
 fn test_openai_response_serialization() {
-    use shimmy::openai_compat::{ChatCompletionResponse, Choice, Usage};
+    use shimmy::openai_compat::{ChatCompletionResponse, Choice, ResponseMessage, Usage};
 
     // Test that our responses serialize to valid OpenAI format
     let response = ChatCompletionResponse {
@@ -353,17 +419,22 @@ fn test_openai_response_serialization() {
         model: "test-model".to_string(),
         choices: vec![Choice {
             index: 0,
-            message: ChatMessage {
+            message: ResponseMessage {
                 role: "assistant".to_string(),
                 content: "Hello! How can I help you today?".to_string(),
+                tool_calls: None,
+                refusal: None,
             },
+            logprobs: None,
             finish_reason: Some("stop".to_string()),
         }],
         usage: Usage {
             prompt_tokens: 12,
             completion_tokens: 8,
             total_tokens: 20,
+            prompt_tokens_details: None,
         },
+        truncation: None,
     };
 
     // Serialize to JSON
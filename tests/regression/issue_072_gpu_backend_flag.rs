@@ -27,6 +27,8 @@ mod issue_072_tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         // Verify model spec can be created with GPU features enabled
@@ -72,6 +74,8 @@ mod issue_072_tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(4),
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         // Verify model spec can be created even if GPU not available
@@ -27,6 +27,8 @@ mod issue_101_tests {
             template: None,
             ctx_len: 2048,
             n_threads: None, // Should auto-detect optimal thread count
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         assert!(auto_spec.n_threads.is_none()); // Verifies auto mode
@@ -39,6 +41,8 @@ mod issue_101_tests {
             template: None,
             ctx_len: 2048,
             n_threads: Some(8), // User-specified thread count
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         assert_eq!(manual_spec.n_threads, Some(8));
@@ -63,6 +67,12 @@ mod issue_101_tests {
             temperature: Some(0.7),
             top_p: Some(0.9),
             top_k: None,
+            max_time_ms: None,
+            token_ids: None,
+            logprobs: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
         };
 
         // Verify streaming flag is set correctly
@@ -114,6 +124,8 @@ mod issue_101_tests {
             template: None,
             ctx_len: 2048,
             n_threads: None, // Auto threading
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         // Test 2: Streaming request with threading config
@@ -127,6 +139,12 @@ mod issue_101_tests {
             temperature: Some(0.7),
             top_p: Some(0.9),
             top_k: None,
+            max_time_ms: None,
+            token_ids: None,
+            logprobs: None,
+            grammar: None,
+            keep_alive: None,
+            adapter: None,
         };
 
         // Verify all components work together
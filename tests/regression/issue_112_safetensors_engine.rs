@@ -23,6 +23,8 @@ mod issue_112_tests {
             template: None,
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         // Verify extension detection works
@@ -45,6 +47,8 @@ mod issue_112_tests {
             template: None,
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         assert_eq!(
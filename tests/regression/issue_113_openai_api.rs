@@ -69,6 +69,8 @@ mod issue_113_tests {
             object: "model".to_string(),
             created: 1640995200,
             owned_by: "shimmy".to_string(),
+            estimated_runtime_gb: None,
+            memory_fit: None,
         };
 
         let response = ModelsResponse {
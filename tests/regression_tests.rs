@@ -25,6 +25,8 @@ mod regression_tests {
             template: Some("chatml".to_string()),
             ctx_len: Some(2048),
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         registry.register(test_model.clone());
@@ -115,17 +117,22 @@ mod regression_tests {
             model: "test-model".to_string(),
             choices: vec![Choice {
                 index: 0,
-                message: crate::api::ChatMessage {
+                message: ResponseMessage {
                     role: "assistant".to_string(),
                     content: "Hello!".to_string(),
+                    tool_calls: None,
+                    refusal: None,
                 },
+                logprobs: None,
                 finish_reason: Some("stop".to_string()),
             }],
             usage: Usage {
                 prompt_tokens: 5,
                 completion_tokens: 2,
                 total_tokens: 7,
+                prompt_tokens_details: None,
             },
+            truncation: None,
         };
 
         let json = serde_json::to_string(&response).unwrap();
@@ -203,12 +210,16 @@ mod regression_tests {
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    estimated_runtime_gb: None,
+                    memory_fit: None,
                 },
                 openai_compat::ListModel {
                     id: "llama-7b".to_string(),
                     object: "model".to_string(),
                     created: 1234567890,
                     owned_by: "shimmy".to_string(),
+                    estimated_runtime_gb: None,
+                    memory_fit: None,
                 },
             ],
         };
@@ -291,6 +302,8 @@ mod regression_tests {
             template: None,
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         // This should select SafeTensors engine, not HuggingFace
@@ -306,6 +319,8 @@ mod regression_tests {
             template: None,
             ctx_len: 2048,
             n_threads: None,
+            n_gpu_layers: None,
+            mmproj_path: None,
         };
 
         assert!(complex_safetensors.base_path.extension().unwrap() == "safetensors");
@@ -344,6 +359,8 @@ mod regression_tests {
             object: "model".to_string(),
             created: 1640995200,
             owned_by: "shimmy".to_string(),
+            estimated_runtime_gb: None,
+            memory_fit: None,
         };
 
         let response = ModelsResponse {
@@ -8,9 +8,14 @@
 //! - HTTP 400: Invalid base64
 //! - HTTP 402: Missing license
 //! - HTTP 403: Invalid license key
+//! - HTTP 400: `url_headers` outside the allow-list or with web mode
 //! - HTTP 422: Unprocessable image format
 //! - HTTP 504: Timeout scenario (mock)
 //! - HTTP 200: Valid request returns VisionResponse schema
+//! - Streaming (`"stream": true`) against a mock backend: token events then
+//!   `done`, and license, usage and backend failures as a terminal `error` event
+//! - Usage metering: only requests that produce a response count against the
+//!   license; timeouts, backend and license failures don't
 //!
 //! Run with: cargo test --test vision_api_integration --features vision
 
@@ -27,7 +32,10 @@ mod vision_tests {
     use serial_test::serial;
     use shimmy::{
         api,
-        engine::adapter::InferenceEngineAdapter,
+        auto_discovery::DiscoveredModel,
+        engine::{
+            adapter::InferenceEngineAdapter, GenOptions, InferenceEngine, LoadedModel, ModelSpec,
+        },
         model_registry::Registry,
         vision_license::{CachedLicense, LicenseValidation, VisionLicenseManager},
         AppState,
@@ -465,12 +473,14 @@ mod vision_tests {
         use shimmy::vision::{Interaction, Layout, Meta, TextBlock, VisionResponse, Visual};
 
         let response = VisionResponse {
+            schema_version: 1,
             image_path: Some("test.png".to_string()),
             url: None,
             mode: "screenshot".to_string(),
             text_blocks: vec![TextBlock {
                 text: "Test text".to_string(),
                 confidence: Some(0.95),
+                position: None,
             }],
             layout: Layout {
                 theme: Some("light".to_string()),
@@ -487,13 +497,22 @@ mod vision_tests {
                 description: Some("Test interaction".to_string()),
             },
             dom_map: None,
+            detections: Vec::new(),
+            fields: None,
+            timeline: Vec::new(),
             meta: Meta {
                 model: "test-model".to_string(),
                 backend: "llama.cpp".to_string(),
                 duration_ms: 1500,
                 parse_warnings: None,
+                safety: None,
+                cached: false,
+                image: None,
+                images: None,
+                video: None,
             },
             raw_model_output: Some("Raw output".to_string()),
+            license_warning: None,
         };
 
         // Test serialization
@@ -560,6 +579,403 @@ mod vision_tests {
         // The response should indicate proper processing attempt
         assert!(response.status().is_client_error() || response.status().is_server_error());
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_url_headers_outside_allow_list_return_400() {
+        for (headers, mode) in [
+            (json!({"Cookie": "session=abc"}), "screenshot"),
+            (json!({"Authorization": "Bearer token"}), "web"),
+        ] {
+            let app = create_test_router_with_license().await;
+            let request_body = json!({
+                "license": "test-license-key",
+                "url": "https://example.com/private/shot.png",
+                "url_headers": headers,
+                "mode": mode
+            });
+            let request = Request::builder()
+                .method("POST")
+                .uri("/api/vision")
+                .header("content-type", "application/json")
+                .body(Body::from(request_body.to_string()))
+                .unwrap();
+
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body_json: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+            assert_eq!(body_json["error"]["code"], "INVALID_REQUEST");
+            assert!(body_json["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("url_headers"));
+        }
+    }
+
+    /// How the mock backend answers a vision request
+    #[derive(Clone, Copy)]
+    enum MockBehavior {
+        /// Stream the canned answer
+        Answer,
+        /// Fail after the first token
+        Fail,
+        /// Answer with text that isn't JSON
+        Unstructured,
+        /// Never finish
+        Hang,
+    }
+
+    /// Mock backend: loads instantly and answers as its [`MockBehavior`] says
+    struct MockVisionEngine {
+        behavior: MockBehavior,
+    }
+
+    struct MockVisionModel {
+        behavior: MockBehavior,
+    }
+
+    const MOCK_OUTPUT: &[&str] = &[
+        r#"{"text_blocks": [{"text": "Sign in", "confidence": 0.9}], "#,
+        r#""layout": {"theme": "light", "regions": [], "key_ui_elements": []}, "#,
+        r#""visual": {"background": "white", "accent_colors": []}, "#,
+        r#""interaction": {"description": "login form"}}"#,
+    ];
+
+    #[async_trait::async_trait]
+    impl InferenceEngine for MockVisionEngine {
+        async fn load(&self, _spec: &ModelSpec) -> anyhow::Result<Box<dyn LoadedModel>> {
+            Ok(Box::new(MockVisionModel {
+                behavior: self.behavior,
+            }))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl LoadedModel for MockVisionModel {
+        async fn generate(
+            &self,
+            _prompt: &str,
+            _opts: GenOptions,
+            _on_token: Option<Box<dyn FnMut(String) + Send>>,
+        ) -> anyhow::Result<String> {
+            Ok(String::new())
+        }
+
+        async fn generate_vision(
+            &self,
+            _images: &[&[u8]],
+            _prompt: &str,
+            _opts: GenOptions,
+            mut on_token: Option<Box<dyn FnMut(String) + Send>>,
+        ) -> anyhow::Result<String> {
+            match self.behavior {
+                MockBehavior::Answer | MockBehavior::Fail => {}
+                MockBehavior::Unstructured => return Ok("A login form".to_string()),
+                MockBehavior::Hang => std::future::pending::<()>().await,
+            }
+            if let Some(cb) = on_token.as_mut() {
+                cb(MOCK_OUTPUT[0].to_string());
+            }
+            if let MockBehavior::Fail = self.behavior {
+                anyhow::bail!("backend crashed mid-generation");
+            }
+            for piece in &MOCK_OUTPUT[1..] {
+                if let Some(cb) = on_token.as_mut() {
+                    cb(piece.to_string());
+                }
+            }
+            Ok(MOCK_OUTPUT.concat())
+        }
+    }
+
+    /// State over the mock backend with a seeded license capped at `monthly_cap`
+    async fn create_mock_state(behavior: MockBehavior, monthly_cap: u64) -> Arc<AppState> {
+        let mut registry = Registry::default();
+        registry.discovered_models.insert(
+            "mock-vision".to_string(),
+            DiscoveredModel {
+                name: "mock-vision".to_string(),
+                path: "/nonexistent/mock-vision.gguf".into(),
+                lora_path: None,
+                mmproj_path: None,
+                size_bytes: 0,
+                model_type: "vision".to_string(),
+                parameter_count: None,
+                quantization: None,
+            },
+        );
+        let mut state = AppState::new(Box::new(MockVisionEngine { behavior }), registry);
+
+        let manager = VisionLicenseManager::new();
+        manager
+            .set_cached_license(Some(CachedLicense {
+                key: "test-license-key".to_string(),
+                validation: LicenseValidation {
+                    valid: true,
+                    entitlements: HashMap::from([
+                        ("VISION_ANALYSIS".to_string(), json!(true)),
+                        ("monthly_cap".to_string(), json!(monthly_cap)),
+                    ]),
+                    expires_at: None,
+                    meta: HashMap::new(),
+                },
+                cached_at: Utc::now(),
+                expires_at: Some(Utc::now() + chrono::Duration::days(30)),
+            }))
+            .await;
+        state.vision_license_manager = Some(manager);
+        Arc::new(state)
+    }
+
+    async fn create_mock_router(behavior: MockBehavior, monthly_cap: u64) -> Router {
+        Router::new()
+            .route("/api/vision", post(api::vision))
+            .with_state(create_mock_state(behavior, monthly_cap).await)
+    }
+
+    /// Requests counted against the license this month
+    async fn requests_this_month(state: &AppState) -> u32 {
+        let manager = state.vision_license_manager.as_ref().unwrap();
+        manager.get_usage_stats().await.requests_this_month
+    }
+
+    /// POST a non-streaming vision request to the mock backend
+    async fn post_mock_vision(
+        state: &Arc<AppState>,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let app = Router::new()
+            .route("/api/vision", post(api::vision))
+            .with_state(Arc::clone(state));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/vision")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    /// POST a streaming vision request and split the reply into (event, data) pairs
+    async fn stream_events(
+        app: Router,
+        body: serde_json::Value,
+    ) -> Vec<(String, serde_json::Value)> {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/vision")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/event-stream"));
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        let mut events = Vec::new();
+        for block in text.split("\n\n").filter(|b| !b.trim().is_empty()) {
+            let mut name = "message".to_string();
+            let mut data = None;
+            for line in block.lines() {
+                if let Some(event) = line.strip_prefix("event: ") {
+                    name = event.to_string();
+                } else if let Some(json) = line.strip_prefix("data: ") {
+                    data = Some(serde_json::from_str(json).unwrap());
+                }
+            }
+            if let Some(data) = data {
+                events.push((name, data));
+            }
+        }
+        events
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_streaming_sends_tokens_then_done() {
+        let app = create_mock_router(MockBehavior::Answer, 1_000_000).await;
+        let events = stream_events(
+            app,
+            json!({
+                "license": "test-license-key",
+                "image_base64": create_valid_base64_image(),
+                "mode": "screenshot",
+                "model": "mock-vision",
+                "stream": true
+            }),
+        )
+        .await;
+
+        let (last, tokens) = events.split_last().unwrap();
+        assert_eq!(tokens.len(), MOCK_OUTPUT.len());
+        for ((event, data), piece) in tokens.iter().zip(MOCK_OUTPUT) {
+            assert_eq!(event, "token");
+            assert_eq!(data["text"], *piece);
+        }
+        assert_eq!(last.0, "done");
+        assert_eq!(last.1["text_blocks"][0]["text"], "Sign in");
+        assert_eq!(last.1["meta"]["model"], "mock-vision");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_streaming_license_failure_ends_with_error_event() {
+        let app = create_mock_router(MockBehavior::Answer, 1_000_000).await;
+        let events = stream_events(
+            app,
+            json!({
+                "image_base64": create_valid_base64_image(),
+                "mode": "screenshot",
+                "model": "mock-vision",
+                "stream": true
+            }),
+        )
+        .await;
+
+        assert_eq!(events.len(), 1);
+        let (event, data) = &events[0];
+        assert_eq!(event, "error");
+        assert_eq!(data["status"], 402);
+        assert_eq!(data["error"]["code"], "MISSING_LICENSE");
+        assert_eq!(data["error"]["message"], "No license key provided");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_streaming_usage_limit_ends_with_error_event() {
+        let app = create_mock_router(MockBehavior::Answer, 0).await;
+        let events = stream_events(
+            app,
+            json!({
+                "license": "test-license-key",
+                "image_base64": create_valid_base64_image(),
+                "mode": "screenshot",
+                "model": "mock-vision",
+                "stream": true
+            }),
+        )
+        .await;
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, "error");
+        assert_eq!(events[0].1["status"], 402);
+        assert_eq!(events[0].1["error"]["code"], "USAGE_LIMIT_EXCEEDED");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_streaming_backend_failure_after_tokens() {
+        let app = create_mock_router(MockBehavior::Fail, 1_000_000).await;
+        let events = stream_events(
+            app,
+            json!({
+                "license": "test-license-key",
+                "image_base64": create_valid_base64_image(),
+                "mode": "screenshot",
+                "model": "mock-vision",
+                "stream": true
+            }),
+        )
+        .await;
+
+        let names: Vec<&str> = events.iter().map(|(e, _)| e.as_str()).collect();
+        assert_eq!(names, vec!["token", "error"]);
+        assert_eq!(events[1].1["status"], 502);
+        assert_eq!(events[1].1["error"]["code"], "INFERENCE_FAILED");
+    }
+
+    fn mock_request() -> serde_json::Value {
+        json!({
+            "license": "test-license-key",
+            "image_base64": create_valid_base64_image(),
+            "mode": "screenshot",
+            "model": "mock-vision",
+            "timeout_ms": 200
+        })
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_successful_request_records_usage() {
+        let state = create_mock_state(MockBehavior::Answer, 1_000_000).await;
+        let (status, body) = post_mock_vision(&state, mock_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["schema_version"], 1);
+        assert_eq!(requests_this_month(&state).await, 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_unsupported_schema_version_returns_406() {
+        let app = create_mock_router(MockBehavior::Answer, 1_000_000).await;
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/vision")
+            .header("content-type", "application/json")
+            .header("accept-schema-version", "99")
+            .body(Body::from(mock_request().to_string()))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"]["code"], "UNSUPPORTED_SCHEMA_VERSION");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_timeout_does_not_record_usage() {
+        let state = create_mock_state(MockBehavior::Hang, 1_000_000).await;
+        let (status, body) = post_mock_vision(&state, mock_request()).await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT, "{}", body);
+        assert_eq!(requests_this_month(&state).await, 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_backend_failure_does_not_record_usage() {
+        let state = create_mock_state(MockBehavior::Fail, 1_000_000).await;
+        let (status, body) = post_mock_vision(&state, mock_request()).await;
+        assert_eq!(body["error"]["code"], "INFERENCE_FAILED", "{}", status);
+        assert_eq!(requests_this_month(&state).await, 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_license_failure_does_not_record_usage() {
+        let state = create_mock_state(MockBehavior::Answer, 1_000_000).await;
+        let mut request = mock_request();
+        request["license"] = json!(null);
+        let (status, _) = post_mock_vision(&state, request).await;
+        assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+        assert_eq!(requests_this_month(&state).await, 0);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_parse_fallback_records_usage_once() {
+        let state = create_mock_state(MockBehavior::Unstructured, 1_000_000).await;
+        let (status, body) = post_mock_vision(&state, mock_request()).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(body["meta"]["parse_warnings"].is_array(), "{}", body);
+        assert_eq!(requests_this_month(&state).await, 1);
+    }
 }
 
 // Stubs for when vision feature is disabled
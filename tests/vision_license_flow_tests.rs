@@ -158,6 +158,65 @@ mod vision_license_tests {
         env::remove_var("KEYGEN_API_KEY");
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn test_in_flight_requests_count_against_the_cap() {
+        env::set_var("KEYGEN_API_KEY", "test-api-key");
+
+        let (manager, _temp_dir) = create_test_manager().await;
+
+        let cached_license = CachedLicense {
+            key: "limited-license".to_string(),
+            validation: LicenseValidation {
+                valid: true,
+                entitlements: {
+                    let mut map = HashMap::new();
+                    map.insert("VISION_ANALYSIS".to_string(), json!(true));
+                    map.insert("monthly_cap".to_string(), json!(10));
+                    map
+                },
+                expires_at: None,
+                meta: HashMap::new(),
+            },
+            cached_at: Utc::now(),
+            expires_at: None,
+        };
+        manager.set_cached_license(Some(cached_license)).await;
+        manager
+            .set_usage_stats(UsageStats {
+                requests_today: 0,
+                requests_this_month: 9, // One request left
+                last_reset: Utc::now(),
+            })
+            .await;
+
+        // The first request holds the last place until it finishes
+        let first = manager
+            .reserve_usage(Some("limited-license"))
+            .await
+            .unwrap();
+        let second = manager.reserve_usage(Some("limited-license")).await;
+        assert!(matches!(
+            second,
+            Err(VisionLicenseError::UsageLimitExceeded)
+        ));
+
+        // A failed request gives its place back
+        drop(first);
+        let retry = manager
+            .reserve_usage(Some("limited-license"))
+            .await
+            .unwrap();
+
+        // A recorded one keeps it
+        retry.commit().await.unwrap();
+        assert_eq!(manager.get_usage_stats().await.requests_this_month, 10);
+        let after = manager.reserve_usage(Some("limited-license")).await;
+        assert!(matches!(after, Err(VisionLicenseError::UsageLimitExceeded)));
+
+        env::remove_var("KEYGEN_API_KEY");
+    }
+
     #[tokio::test]
     #[serial]
     async fn test_usage_tracking_increments_correctly() {
@@ -5,6 +5,8 @@
 //! - verify_response_signature() with various signature scenarios
 //! - VisionLicenseError status code and JSON serialization
 //! - VisionLicenseManager functionality with mocking
+//! - Expiry warnings and the license status report
+//! - Usage persistence through a pluggable state store
 
 #[cfg(feature = "vision")]
 mod vision_license_tests {
@@ -19,9 +21,7 @@ mod vision_license_tests {
 
     impl VisionLicenseManagerTestWrapper {
         /// Test wrapper for check_response_freshness
-        pub fn check_response_freshness(
-            date_header: &str,
-        ) -> Result<(), Box<dyn std::error::Error>> {
+        pub fn check_response_freshness(date_header: &str) -> shimmy::error::Result<()> {
             VisionLicenseManager::check_response_freshness(date_header)
         }
 
@@ -30,7 +30,7 @@ mod vision_license_tests {
             sig_header: &str,
             date_header: &str,
             response_body: &str,
-        ) -> Result<(), Box<dyn std::error::Error>> {
+        ) -> shimmy::error::Result<()> {
             VisionLicenseManager::verify_response_signature(sig_header, date_header, response_body)
         }
     }
@@ -459,6 +459,24 @@ mod vision_license_tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_usage_persists_in_state_store() {
+        use shimmy::state_store::{MemoryStore, StateStore};
+        use std::sync::Arc;
+
+        let store: Arc<dyn StateStore> = Arc::new(MemoryStore::default());
+        let manager = VisionLicenseManager::with_store(store.clone());
+        manager.record_usage().await.unwrap();
+        manager.record_usage().await.unwrap();
+        assert!(store.get("vision", "usage_stats.json").unwrap().is_some());
+
+        let restarted = VisionLicenseManager::with_store(store);
+        assert_eq!(restarted.get_usage_stats().await.requests_this_month, 0);
+        restarted.load_cache().await.unwrap();
+        let usage = restarted.get_usage_stats().await;
+        assert_eq!((usage.requests_today, usage.requests_this_month), (2, 2));
+    }
+
     #[test]
     fn test_license_validation_serialization() {
         // Test LicenseValidation struct serialization/deserialization
@@ -847,6 +865,88 @@ mod vision_license_tests {
             "Public key must be hard-coded to prevent MITM key substitution"
         );
     }
+
+    /// Product token resolution: env override wins, config file is the fallback
+    #[test]
+    #[serial]
+    fn test_keygen_config_product_token_resolution() {
+        std::env::remove_var("KEYGEN_API_KEY");
+        std::env::remove_var("KEYGEN_PRODUCT_TOKEN");
+
+        let config = KeygenConfig {
+            product_token: Some("prod-from-config".to_string()),
+        };
+        assert_eq!(
+            config.resolve_product_token().as_deref(),
+            Some("prod-from-config")
+        );
+
+        std::env::set_var("KEYGEN_PRODUCT_TOKEN", "prod-from-env");
+        assert_eq!(
+            config.resolve_product_token().as_deref(),
+            Some("prod-from-env")
+        );
+        std::env::remove_var("KEYGEN_PRODUCT_TOKEN");
+
+        let blank = KeygenConfig {
+            product_token: Some("   ".to_string()),
+        };
+        assert!(blank.resolve_product_token().is_none());
+    }
+
+    #[test]
+    fn test_expiry_warning_window() {
+        let now = Utc::now();
+        assert!(expiry_warning(now + Duration::days(30), now, 14).is_none());
+
+        let warning =
+            expiry_warning(now + Duration::days(5) + Duration::hours(1), now, 14).unwrap();
+        assert_eq!(warning.days_remaining, 5);
+        assert!(warning.message.contains("expires in 5 days"));
+
+        let warning = expiry_warning(now + Duration::hours(30), now, 14).unwrap();
+        assert!(warning.message.contains("expires in 1 day "));
+
+        let warning = expiry_warning(now - Duration::hours(2), now, 14).unwrap();
+        assert_eq!(warning.days_remaining, 0);
+        assert!(warning.message.contains("expired"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_status_reports_expiring_license() {
+        let manager = VisionLicenseManager::new();
+        let expires = Utc::now() + Duration::days(3) + Duration::hours(1);
+        manager
+            .set_cached_license(Some(CachedLicense {
+                key: "status-test-key-1234".to_string(),
+                validation: LicenseValidation {
+                    valid: true,
+                    entitlements: HashMap::from([
+                        ("VISION_ANALYSIS".to_string(), serde_json::json!(true)),
+                        ("monthly_cap".to_string(), serde_json::json!(500)),
+                    ]),
+                    expires_at: Some(expires.to_rfc3339()),
+                    meta: HashMap::new(),
+                },
+                cached_at: Utc::now(),
+                expires_at: Some(expires),
+            }))
+            .await;
+
+        let status = manager.status(Some("status-test-key-1234")).await;
+        assert!(status.configured && status.valid);
+        assert!(status.error.is_none());
+        assert_eq!(status.monthly_cap, Some(500));
+        assert!(!status.key.unwrap().contains("status-test"));
+        assert_eq!(status.license_warning.unwrap().days_remaining, 3);
+        assert_eq!(manager.license_warning().await.unwrap().days_remaining, 3);
+
+        let status = manager.status(None).await;
+        assert!(!status.configured && !status.valid);
+        assert_eq!(status.error.unwrap()["code"], "MISSING_LICENSE");
+        assert!(status.license_warning.is_none());
+    }
 }
 
 // Tests for when vision feature is disabled
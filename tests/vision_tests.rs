@@ -408,6 +408,13 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            stream: None,
+            url_headers: None,
+            images: Vec::new(),
+            video: None,
+            coordinates: None,
+            callback_url: None,
+            schema_version: None,
         };
 
         let result = shimmy::vision::parse_structured_output(
@@ -471,6 +478,13 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            stream: None,
+            url_headers: None,
+            images: Vec::new(),
+            video: None,
+            coordinates: None,
+            callback_url: None,
+            schema_version: None,
         };
 
         let result = shimmy::vision::parse_structured_output(
@@ -510,6 +524,13 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            stream: None,
+            url_headers: None,
+            images: Vec::new(),
+            video: None,
+            coordinates: None,
+            callback_url: None,
+            schema_version: None,
         };
 
         let result = shimmy::vision::parse_structured_output(
@@ -558,6 +579,13 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            stream: None,
+            url_headers: None,
+            images: Vec::new(),
+            video: None,
+            coordinates: None,
+            callback_url: None,
+            schema_version: None,
         };
 
         let result =
@@ -584,6 +612,13 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            stream: None,
+            url_headers: None,
+            images: Vec::new(),
+            video: None,
+            coordinates: None,
+            callback_url: None,
+            schema_version: None,
         };
 
         let result =
@@ -617,6 +652,13 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            stream: None,
+            url_headers: None,
+            images: Vec::new(),
+            video: None,
+            coordinates: None,
+            callback_url: None,
+            schema_version: None,
         };
 
         // This would be tested in the actual process_vision_request function
@@ -639,6 +681,13 @@ mod vision_tests {
             screenshot: Some(false),
             viewport_width: Some(1920),
             viewport_height: Some(1080),
+            stream: None,
+            url_headers: None,
+            images: Vec::new(),
+            video: None,
+            coordinates: None,
+            callback_url: None,
+            schema_version: None,
         };
 
         assert_eq!(req.image_base64, Some(base64_image));
@@ -733,12 +782,14 @@ mod vision_tests {
     #[test]
     fn test_vision_response_structure() {
         let response = VisionResponse {
+            schema_version: 1,
             image_path: None,
             url: Some("https://example.com".to_string()),
             mode: "web".to_string(),
             text_blocks: vec![TextBlock {
                 text: "Header text".to_string(),
                 confidence: Some(0.95),
+                position: None,
             }],
             layout: Layout {
                 theme: Some("light".to_string()),
@@ -765,13 +816,22 @@ mod vision_tests {
                 description: Some("Click buttons to navigate".to_string()),
             },
             dom_map: None,
+            detections: Vec::new(),
+            fields: None,
+            timeline: Vec::new(),
             meta: Meta {
                 model: "test-model".to_string(),
                 backend: "llama.cpp".to_string(),
                 duration_ms: 1500,
                 parse_warnings: None,
+                safety: None,
+                cached: false,
+                image: None,
+                images: None,
+                video: None,
             },
             raw_model_output: None,
+            license_warning: None,
         };
 
         assert_eq!(response.mode, "web");
@@ -792,7 +852,7 @@ mod vision_tests {
             assert!(result.is_err());
             assert_eq!(
                 result.unwrap_err().to_string(),
-                "Vision feature not enabled"
+                "Backend not available: vision"
             );
         }
     }
@@ -886,6 +946,13 @@ mod vision_tests {
             screenshot: None,
             viewport_width: None,
             viewport_height: None,
+            stream: None,
+            url_headers: None,
+            images: Vec::new(),
+            video: None,
+            coordinates: None,
+            callback_url: None,
+            schema_version: None,
         };
 
         let result = shimmy::vision::parse_structured_output(